@@ -1,4 +1,4 @@
-use agent_mem_db::{AgentMemDB, AgentMemDBDisk, DiskOptions, Episode};
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, DiskOptions, Episode, QueryOptions};
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use rand::Rng;
 use std::path::PathBuf;
@@ -43,6 +43,15 @@ pub fn bench_query(c: &mut Criterion) {
                         }
                     })
                 });
+                let name = format!("query_shared_{}d_{}eps_topk{}", dim, n, top_k);
+                c.bench_function(&name, |b| {
+                    b.iter(|| {
+                        for q in &queries {
+                            let opts = QueryOptions::new(-1.0, top_k);
+                            let _ = db.query_similar_shared(q, opts).unwrap();
+                        }
+                    })
+                });
             }
         }
     }
@@ -168,6 +177,35 @@ pub fn bench_disk_open_replay_vs_checkpoint(c: &mut Criterion) {
     let _ = std::fs::remove_dir_all(&dir_checkpoint);
 }
 
+pub fn bench_disk_store_group_commit(c: &mut Criterion) {
+    let dim = 64;
+    let episodes = make_episodes(200, dim);
+    let dir: PathBuf = std::env::temp_dir().join("agent_mem_bench_group_commit");
+
+    let mut g = c.benchmark_group("disk_store");
+    for (name, opts) in [
+        ("store_200_fsync_each", DiskOptions::exact(dim)),
+        (
+            "store_200_group_commit_64",
+            DiskOptions::exact(dim).group_commit(10, 64),
+        ),
+    ] {
+        g.bench_function(name, |b| {
+            b.iter(|| {
+                let _ = std::fs::remove_dir_all(&dir);
+                let mut db = AgentMemDBDisk::open_with_options(&dir, opts.clone()).unwrap();
+                for ep in &episodes {
+                    db.store_episode(ep.clone()).unwrap();
+                }
+                db.flush().unwrap();
+            })
+        });
+    }
+    g.finish();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
 pub fn bench_load(c: &mut Criterion) {
     let dim = 768;
     let n = 10_000;
@@ -207,6 +245,7 @@ criterion_group!(
     bench_exact_query,
     bench_scale_insert,
     bench_scale_query,
-    bench_disk_open_replay_vs_checkpoint
+    bench_disk_open_replay_vs_checkpoint,
+    bench_disk_store_group_commit
 );
 criterion_main!(benches);
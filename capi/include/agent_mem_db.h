@@ -27,6 +27,9 @@ size_t agent_mem_db_prune_older_than(AgentMemDBHandle h, int64_t timestamp_cutof
 size_t agent_mem_db_prune_keep_newest(AgentMemDBHandle h, size_t n);
 size_t agent_mem_db_prune_keep_highest_reward(AgentMemDBHandle h, size_t n);
 
+/* Nonzero: fill unset timestamps with the current Unix ms on store. */
+void agent_mem_db_set_auto_timestamp(AgentMemDBHandle h, int enabled);
+
 char* agent_mem_db_last_error(void);
 void agent_mem_db_free_string(char* s);
 
@@ -49,6 +52,8 @@ int agent_mem_db_disk_prune_older_than(AgentMemDBDiskHandle h, int64_t timestamp
 int agent_mem_db_disk_prune_keep_newest(AgentMemDBDiskHandle h, size_t n);
 int agent_mem_db_disk_prune_keep_highest_reward(AgentMemDBDiskHandle h, size_t n);
 
+void agent_mem_db_disk_set_auto_timestamp(AgentMemDBDiskHandle h, int enabled);
+
 #ifdef __cplusplus
 }
 #endif
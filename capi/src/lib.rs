@@ -195,7 +195,8 @@ pub extern "C" fn agent_mem_db_prune_older_than(
     let db = unsafe { &*h };
     db.lock()
         .unwrap()
-        .prune_older_than(timestamp_cutoff_ms as i64) as size_t
+        .prune_older_than(timestamp_cutoff_ms as i64)
+        .unwrap_or(0) as size_t
 }
 
 /// Prune to keep only n most recent episodes. Returns number removed.
@@ -205,7 +206,7 @@ pub extern "C" fn agent_mem_db_prune_keep_newest(h: *mut Mutex<AgentMemDB>, n: s
         return 0;
     }
     let db = unsafe { &*h };
-    db.lock().unwrap().prune_keep_newest(n) as size_t
+    db.lock().unwrap().prune_keep_newest(n).unwrap_or(0) as size_t
 }
 
 /// Prune to keep only n highest-reward episodes. Returns number removed.
@@ -218,7 +219,17 @@ pub extern "C" fn agent_mem_db_prune_keep_highest_reward(
         return 0;
     }
     let db = unsafe { &*h };
-    db.lock().unwrap().prune_keep_highest_reward(n) as size_t
+    db.lock().unwrap().prune_keep_highest_reward(n).unwrap_or(0) as size_t
+}
+
+/// Fill unset timestamps with the current Unix ms on store when `enabled` is nonzero.
+#[no_mangle]
+pub extern "C" fn agent_mem_db_set_auto_timestamp(h: *mut Mutex<AgentMemDB>, enabled: c_int) {
+    if h.is_null() {
+        return;
+    }
+    let db = unsafe { &*h };
+    db.lock().unwrap().set_auto_timestamp(enabled != 0);
 }
 
 // --- AgentMemDBDisk ---
@@ -430,3 +441,16 @@ pub extern "C" fn agent_mem_db_disk_prune_keep_highest_reward(
         }
     }
 }
+
+/// Fill unset timestamps with the current Unix ms on store when `enabled` is nonzero.
+#[no_mangle]
+pub extern "C" fn agent_mem_db_disk_set_auto_timestamp(
+    h: *mut Mutex<AgentMemDBDisk>,
+    enabled: c_int,
+) {
+    if h.is_null() {
+        return;
+    }
+    let db = unsafe { &*h };
+    db.lock().unwrap().set_auto_timestamp(enabled != 0);
+}
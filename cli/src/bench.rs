@@ -0,0 +1,212 @@
+//! `agent-mem bench`: insert throughput, query latency and recall on synthetic data, for sizing
+//! a deployment without writing a criterion harness.
+
+use agent_mem_db::{AgentMemDB, Episode, HnswParams, QueryOptions};
+use anyhow::{bail, Result};
+use clap::Args;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Embedding dimension.
+    #[arg(long, default_value_t = 768)]
+    dim: usize,
+    /// Episodes to insert.
+    #[arg(long, default_value_t = 10_000)]
+    n: usize,
+    #[arg(long, default_value = "hnsw", value_parser = ["exact", "hnsw"])]
+    index: String,
+    /// Queries to time.
+    #[arg(long, default_value_t = 1_000)]
+    queries: usize,
+    #[arg(long, default_value_t = 10)]
+    top_k: usize,
+    /// Queries checked against an exact index for recall (exact search is O(n) per query).
+    #[arg(long, default_value_t = 100)]
+    recall_queries: usize,
+    /// HNSW max neighbours per node.
+    #[arg(long)]
+    m: Option<usize>,
+    /// HNSW candidate list size while inserting.
+    #[arg(long)]
+    ef_construction: Option<usize>,
+    /// HNSW candidate list size while searching.
+    #[arg(long)]
+    ef_search: Option<usize>,
+    /// Seed for the synthetic data, so runs are comparable.
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+    /// Print the report as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct Report {
+    index: String,
+    dim: usize,
+    n: usize,
+    top_k: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hnsw_params: Option<HnswParams>,
+    insert_secs: f64,
+    inserts_per_sec: f64,
+    queries: usize,
+    queries_per_sec: f64,
+    query_p50_us: f64,
+    query_p95_us: f64,
+    query_p99_us: f64,
+    query_max_us: f64,
+    /// Mean fraction of the exact top-k found; 1.0 for the exact index.
+    recall_at_k: f64,
+    recall_queries: usize,
+}
+
+pub fn run(out: &mut impl Write, args: BenchArgs) -> Result<()> {
+    if args.dim == 0 || args.n == 0 || args.queries == 0 || args.top_k == 0 {
+        bail!("--dim, --n, --queries and --top-k must be > 0");
+    }
+    let hnsw_params = (args.index == "hnsw").then(|| {
+        let defaults = HnswParams::default();
+        HnswParams {
+            m: args.m.unwrap_or(defaults.m),
+            ef_construction: args.ef_construction.unwrap_or(defaults.ef_construction),
+            ef_search: args.ef_search.unwrap_or(defaults.ef_search),
+        }
+    });
+    if hnsw_params.is_some_and(|p| p.m < 2) {
+        bail!("--m must be at least 2");
+    }
+
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let embedding = |rng: &mut StdRng| -> Vec<f32> { (0..args.dim).map(|_| rng.gen()).collect() };
+    let episodes: Vec<Episode> = (0..args.n)
+        .map(|i| {
+            let reward = rng.gen_range(-1.0..=1.0);
+            Episode::new(format!("bench-{i}"), embedding(&mut rng), reward)
+        })
+        .collect();
+    let queries: Vec<Vec<f32>> = (0..args.queries).map(|_| embedding(&mut rng)).collect();
+
+    let mut db = match hnsw_params {
+        Some(params) => AgentMemDB::new_hnsw(args.dim, args.n, params),
+        None => AgentMemDB::new_exact(args.dim),
+    };
+    let start = Instant::now();
+    for ep in episodes.iter().cloned() {
+        db.store_episode(ep)?;
+    }
+    let insert = start.elapsed();
+
+    let opts = || QueryOptions::new(f32::NEG_INFINITY, args.top_k);
+    let mut latencies = Vec::with_capacity(queries.len());
+    let start = Instant::now();
+    for q in &queries {
+        let t = Instant::now();
+        db.query_similar_with_options(q, opts())?;
+        latencies.push(t.elapsed());
+    }
+    let query_total = start.elapsed();
+    latencies.sort();
+
+    let recall_queries = args.recall_queries.min(queries.len());
+    let recall_at_k = if hnsw_params.is_none() || recall_queries == 0 {
+        1.0
+    } else {
+        let mut exact = AgentMemDB::new_exact(args.dim);
+        exact.store_episodes(episodes)?;
+        let mut total = 0.0;
+        for q in &queries[..recall_queries] {
+            let truth: HashSet<_> = exact
+                .query_similar_with_options(q, opts())?
+                .into_iter()
+                .map(|ep| ep.id)
+                .collect();
+            let found = db
+                .query_similar_with_options(q, opts())?
+                .into_iter()
+                .filter(|ep| truth.contains(&ep.id))
+                .count();
+            total += found as f64 / truth.len().max(1) as f64;
+        }
+        total / recall_queries as f64
+    };
+
+    let report = Report {
+        index: args.index,
+        dim: args.dim,
+        n: args.n,
+        top_k: args.top_k,
+        hnsw_params,
+        insert_secs: insert.as_secs_f64(),
+        inserts_per_sec: args.n as f64 / insert.as_secs_f64(),
+        queries: queries.len(),
+        queries_per_sec: queries.len() as f64 / query_total.as_secs_f64(),
+        query_p50_us: micros(percentile(&latencies, 0.50)),
+        query_p95_us: micros(percentile(&latencies, 0.95)),
+        query_p99_us: micros(percentile(&latencies, 0.99)),
+        query_max_us: micros(latencies[latencies.len() - 1]),
+        recall_at_k,
+        recall_queries: if hnsw_params.is_some() {
+            recall_queries
+        } else {
+            0
+        },
+    };
+    if args.json {
+        serde_json::to_writer_pretty(&mut *out, &report)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    write!(
+        out,
+        "{} index, {} x {}-dim episodes",
+        report.index, report.n, report.dim
+    )?;
+    if let Some(p) = report.hnsw_params {
+        write!(
+            out,
+            " (m={}, ef_construction={}, ef_search={})",
+            p.m, p.ef_construction, p.ef_search
+        )?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "insert   {:.3} s  ({:.0} episodes/s)",
+        report.insert_secs, report.inserts_per_sec
+    )?;
+    writeln!(
+        out,
+        "query    p50 {:.1} µs  p95 {:.1} µs  p99 {:.1} µs  max {:.1} µs  ({:.0} queries/s, top-{})",
+        report.query_p50_us,
+        report.query_p95_us,
+        report.query_p99_us,
+        report.query_max_us,
+        report.queries_per_sec,
+        report.top_k
+    )?;
+    if report.recall_queries > 0 {
+        writeln!(
+            out,
+            "recall@{} {:.3}  (vs exact, {} queries)",
+            report.top_k, report.recall_at_k, report.recall_queries
+        )?;
+    }
+    Ok(())
+}
+
+/// Nearest-rank percentile of sorted latencies.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn micros(d: Duration) -> f64 {
+    d.as_secs_f64() * 1e6
+}
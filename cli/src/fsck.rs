@@ -0,0 +1,653 @@
+//! `agent-mem fsck`: check a disk directory against the layout `AgentMemDBDisk` writes
+//! (`meta.json`, the `episodes.jsonl` log and the exact-index checkpoint) without opening it,
+//! and optionally repair a log whose tail was left corrupt by an interrupted write. With
+//! `--verify` a directory that passes is then opened and checked by `AgentMemDBDisk::verify`.
+
+use agent_mem_db::{AgentMemDBDisk, Episode, LogRecord, VerifyReport, FORMAT_VERSION};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use uuid::Uuid;
+
+const META_FILE: &str = "meta.json";
+const EPISODES_LOG: &str = "episodes.jsonl";
+const EXACT_CHECKPOINT_FILE: &str = "exact_checkpoint.json";
+/// Bytes cut from the log by `--repair` are appended here rather than discarded.
+const CORRUPT_FILE: &str = "episodes.jsonl.corrupt";
+
+/// The fields of `meta.json` that fsck checks; others are ignored.
+#[derive(Deserialize)]
+struct Meta {
+    #[serde(default)]
+    format_version: u32,
+    dim: usize,
+    index_type: String,
+    max_elements: usize,
+    #[serde(default)]
+    checkpoint_line_count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct Checkpoint {
+    #[serde(default)]
+    format_version: u32,
+    episodes: Vec<Episode>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Opening the directory fails or returns wrong data.
+    Error,
+    /// Opening works, but something is off (e.g. a checkpoint that will be ignored).
+    Warning,
+}
+
+#[derive(Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Stable identifier, e.g. `invalid_json`, `dimension_mismatch`, `duplicate_id`.
+    pub kind: &'static str,
+    /// 1-based line in the log, for log issues.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckpointStatus {
+    /// No checkpoint file.
+    None,
+    /// Matches the log; opening loads it instead of replaying.
+    Valid,
+    /// Written for a different log length; opening ignores it and replays.
+    Stale,
+    /// Would be loaded on open but is unreadable or disagrees with the log.
+    Invalid,
+}
+
+/// What `--repair` changed.
+#[derive(Serialize)]
+pub struct Repair {
+    /// Non-empty log lines cut from the end.
+    pub removed_lines: usize,
+    pub truncated_bytes: u64,
+    /// Where the cut bytes were saved.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saved_to: Option<String>,
+    /// A newline was appended to an unterminated (but valid) last line.
+    pub newline_added: bool,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub path: String,
+    /// No errors (warnings are allowed).
+    pub ok: bool,
+    pub dim: Option<usize>,
+    pub index_type: Option<String>,
+    /// Non-empty lines in the log.
+    pub log_lines: usize,
+    /// Distinct episodes in valid log lines.
+    pub episodes: usize,
+    pub checkpoint: CheckpointStatus,
+    pub issues: Vec<Issue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair: Option<Repair>,
+    /// What `--verify` found in the opened DB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify: Option<VerifyReport>,
+}
+
+/// A `store_episodes` batch whose commit marker hasn't been seen yet.
+struct Batch {
+    id: Uuid,
+    count: usize,
+    /// Line and offset of the begin marker.
+    line: usize,
+    offset: u64,
+    /// Non-empty lines so far, including the begin marker.
+    lines: usize,
+    /// (line, id, version)
+    episodes: Vec<(usize, Uuid, u64)>,
+    /// An unreadable line, harmless only if nothing follows it.
+    torn: Option<(usize, String)>,
+}
+
+fn uncommitted(b: &Batch, outcome: &str) -> Issue {
+    warning(
+        "uncommitted_batch",
+        Some(b.line),
+        format!(
+            "batch {} of {} episodes was never committed (interrupted write); {outcome}",
+            b.id, b.count
+        ),
+    )
+}
+
+/// Record an episode line. A repeated id is fine when its version went up (`update_episode`).
+fn add_id(
+    ids: &mut HashMap<Uuid, u64>,
+    (line, id, version): (usize, Uuid, u64),
+    issues: &mut Vec<Issue>,
+) {
+    match ids.insert(id, version) {
+        Some(earlier) if earlier >= version => issues.push(error(
+            "duplicate_id",
+            Some(line),
+            format!("episode {id} version {version} already appears earlier in the log"),
+        )),
+        _ => {}
+    }
+}
+
+/// Where the log's trailing corruption (or uncommitted batch) starts.
+struct Tail {
+    offset: u64,
+    lines: usize,
+}
+
+struct LogScan {
+    lines: usize,
+    episodes: usize,
+    tail: Option<Tail>,
+    /// The last line is valid but has no newline, so the next append would corrupt it.
+    unterminated: bool,
+}
+
+/// Check the directory at `path`, repairing trailing log corruption first when `repair` is set,
+/// then (with `verify`) opening it and checking the loaded state.
+pub fn fsck(path: &Path, repair: bool, verify: bool) -> Result<Report> {
+    if !path.is_dir() {
+        bail!("{} is not a disk directory", path.display());
+    }
+    let (mut report, mut scan) = check(path)?;
+    if repair && (scan.tail.is_some() || scan.unterminated) {
+        let done = apply_repair(path, &scan)?;
+        (report, scan) = check(path)?;
+        report.repair = Some(done);
+    }
+    if verify {
+        verify_opened(path, &mut report, &scan)?;
+    }
+    Ok(report)
+}
+
+/// Open the directory and record `AgentMemDBDisk::verify` problems as errors. Skipped when
+/// the files already have errors or an uncommitted tail, which opening would cut.
+fn verify_opened(path: &Path, report: &mut Report, scan: &LogScan) -> Result<()> {
+    if !report.ok || scan.tail.is_some() {
+        report.issues.push(warning(
+            "verify_skipped",
+            None,
+            "not opened for --verify until the log is repaired".to_string(),
+        ));
+        return Ok(());
+    }
+    let db = AgentMemDBDisk::open_existing(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let verified = db.verify()?;
+    for problem in &verified.problems {
+        report
+            .issues
+            .push(error("inconsistent_state", None, problem.clone()));
+    }
+    report.ok &= verified.is_ok();
+    report.verify = Some(verified);
+    Ok(())
+}
+
+fn check(path: &Path) -> Result<(Report, LogScan)> {
+    let mut issues = Vec::new();
+    let meta = read_meta(path, &mut issues);
+    let scan = scan_log(
+        &path.join(EPISODES_LOG),
+        meta.as_ref().map(|m| m.dim),
+        &mut issues,
+    )?;
+    let checkpoint = match meta {
+        Some(ref meta) => check_checkpoint(path, meta, &scan, &mut issues),
+        None => CheckpointStatus::None,
+    };
+    let report = Report {
+        path: path.display().to_string(),
+        ok: !issues.iter().any(|i| i.severity == Severity::Error),
+        dim: meta.as_ref().map(|m| m.dim),
+        index_type: meta.map(|m| m.index_type),
+        log_lines: scan.lines,
+        episodes: scan.episodes,
+        checkpoint,
+        issues,
+        repair: None,
+        verify: None,
+    };
+    Ok((report, scan))
+}
+
+fn error(kind: &'static str, line: Option<usize>, message: String) -> Issue {
+    Issue {
+        severity: Severity::Error,
+        kind,
+        line,
+        message,
+    }
+}
+
+fn warning(kind: &'static str, line: Option<usize>, message: String) -> Issue {
+    Issue {
+        severity: Severity::Warning,
+        kind,
+        line,
+        message,
+    }
+}
+
+/// Parse and validate `meta.json`; `None` (with an issue recorded) when it can't be used.
+fn read_meta(path: &Path, issues: &mut Vec<Issue>) -> Option<Meta> {
+    let data = match fs::read_to_string(path.join(META_FILE)) {
+        Ok(data) => data,
+        Err(e) => {
+            issues.push(error("missing_meta", None, format!("{META_FILE}: {e}")));
+            return None;
+        }
+    };
+    let meta: Meta = match serde_json::from_str(&data) {
+        Ok(meta) => meta,
+        Err(e) => {
+            issues.push(error("invalid_meta", None, format!("{META_FILE}: {e}")));
+            return None;
+        }
+    };
+    if meta.format_version > FORMAT_VERSION {
+        issues.push(error(
+            "unsupported_version",
+            None,
+            format!(
+                "{META_FILE}: format version {} is newer than this build reads ({FORMAT_VERSION})",
+                meta.format_version
+            ),
+        ));
+        return None;
+    }
+    if meta.dim == 0 {
+        issues.push(error(
+            "invalid_meta",
+            None,
+            format!("{META_FILE}: dim must be > 0"),
+        ));
+        return None;
+    }
+    match meta.index_type.as_str() {
+        "exact" => {}
+        "hnsw" if meta.max_elements > 0 => {}
+        "hnsw" => issues.push(error(
+            "invalid_meta",
+            None,
+            format!("{META_FILE}: max_elements must be > 0 for hnsw"),
+        )),
+        other => issues.push(warning(
+            "invalid_meta",
+            None,
+            format!("{META_FILE}: unknown index_type {other:?} (opened as hnsw)"),
+        )),
+    }
+    Some(meta)
+}
+
+/// Replay the log the way `AgentMemDBDisk` does, recording every line it would reject.
+fn scan_log(log_path: &Path, dim: Option<usize>, issues: &mut Vec<Issue>) -> Result<LogScan> {
+    let data = match fs::read(log_path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            issues.push(warning(
+                "missing_log",
+                None,
+                format!("{EPISODES_LOG} not found; opening creates an empty one"),
+            ));
+            Vec::new()
+        }
+        Err(e) => return Err(e).with_context(|| format!("reading {}", log_path.display())),
+    };
+    let mut ids = HashMap::new();
+    let mut lines = 0;
+    let mut tail: Option<Tail> = None;
+    let mut batch: Option<Batch> = None;
+    let mut unterminated = false;
+    let mut last_line = 0;
+    let mut offset = 0u64;
+    for (i, raw) in data.split_inclusive(|b| *b == b'\n').enumerate() {
+        let line_no = i + 1;
+        let start = offset;
+        offset += raw.len() as u64;
+        let terminated = raw.ends_with(b"\n");
+        let text = match std::str::from_utf8(raw) {
+            Ok(text) => text.trim(),
+            Err(e) => {
+                lines += 1;
+                issues.push(error("invalid_utf8", Some(line_no), e.to_string()));
+                tail.get_or_insert(Tail {
+                    offset: start,
+                    lines: 0,
+                })
+                .lines += 1;
+                continue;
+            }
+        };
+        if text.is_empty() {
+            continue;
+        }
+        lines += 1;
+        if let Some(ref mut b) = batch {
+            b.lines += 1;
+            // An unreadable line inside a batch is only harmless as the batch's torn end.
+            if let Some((torn_line, message)) = b.torn.take() {
+                issues.push(error("invalid_json", Some(torn_line), message));
+                batch = None;
+            }
+        }
+        let record = match LogRecord::parse(text.as_bytes()) {
+            Ok(record) => record,
+            Err(e) => {
+                if let Some(ref mut b) = batch {
+                    b.torn = Some((line_no, e.to_string()));
+                    continue;
+                }
+                issues.push(if terminated {
+                    error("invalid_json", Some(line_no), e.to_string())
+                } else {
+                    error(
+                        "truncated_line",
+                        Some(line_no),
+                        format!("incomplete last line: {e}"),
+                    )
+                });
+                tail.get_or_insert(Tail {
+                    offset: start,
+                    lines: 0,
+                })
+                .lines += 1;
+                continue;
+            }
+        };
+        let bad = match record {
+            LogRecord::Episode(ep) => match dim {
+                Some(dim) if ep.state_embedding.len() != dim => Some(error(
+                    "dimension_mismatch",
+                    Some(line_no),
+                    format!(
+                        "episode {} has dimension {}, expected {dim}",
+                        ep.id,
+                        ep.state_embedding.len()
+                    ),
+                )),
+                _ => {
+                    match batch {
+                        Some(ref mut b) => b.episodes.push((line_no, ep.id, ep.version)),
+                        None => add_id(&mut ids, (line_no, ep.id, ep.version), issues),
+                    }
+                    None
+                }
+            },
+            LogRecord::BatchBegin { batch: id, count } => {
+                if let Some(b) = batch.take() {
+                    issues.push(uncommitted(&b, "ignored on open"));
+                }
+                batch = Some(Batch {
+                    id,
+                    count,
+                    line: line_no,
+                    offset: start,
+                    lines: 1,
+                    episodes: Vec::new(),
+                    torn: None,
+                });
+                None
+            }
+            LogRecord::BatchCommit { batch: id } => match batch.take() {
+                Some(b) if b.id == id && b.episodes.len() == b.count => {
+                    for ep in b.episodes {
+                        add_id(&mut ids, ep, issues);
+                    }
+                    None
+                }
+                _ => Some(error(
+                    "orphan_commit",
+                    Some(line_no),
+                    format!("commit for unknown or incomplete batch {id}"),
+                )),
+            },
+        };
+        match bad {
+            Some(issue) => {
+                issues.push(issue);
+                tail.get_or_insert(Tail {
+                    offset: start,
+                    lines: 0,
+                })
+                .lines += 1;
+            }
+            // A readable line means the corruption before it isn't trailing.
+            None => {
+                tail = None;
+                unterminated = !terminated;
+                last_line = line_no;
+            }
+        }
+    }
+    if let Some(b) = batch {
+        issues.push(uncommitted(&b, "discarded and cut from the log on open"));
+        tail = Some(Tail {
+            offset: b.offset,
+            lines: b.lines,
+        });
+    }
+    if tail.is_some() {
+        unterminated = false;
+    } else if unterminated {
+        issues.push(warning(
+            "missing_newline",
+            Some(last_line),
+            "last line has no trailing newline; the next append would corrupt it".to_string(),
+        ));
+    }
+    Ok(LogScan {
+        lines,
+        episodes: ids.len(),
+        tail,
+        unterminated,
+    })
+}
+
+/// Compare the exact-index checkpoint with the log, as `open` would.
+fn check_checkpoint(
+    path: &Path,
+    meta: &Meta,
+    scan: &LogScan,
+    issues: &mut Vec<Issue>,
+) -> CheckpointStatus {
+    let checkpoint_path = path.join(EXACT_CHECKPOINT_FILE);
+    if !checkpoint_path.exists() {
+        return CheckpointStatus::None;
+    }
+    if meta.index_type != "exact" {
+        issues.push(warning(
+            "stale_checkpoint",
+            None,
+            format!(
+                "{EXACT_CHECKPOINT_FILE} is ignored by {} indexes",
+                meta.index_type
+            ),
+        ));
+        return CheckpointStatus::Stale;
+    }
+    if meta.checkpoint_line_count != Some(scan.lines) {
+        let covers = meta
+            .checkpoint_line_count
+            .map_or_else(|| "no recorded".to_string(), |n| n.to_string());
+        issues.push(warning(
+            "stale_checkpoint",
+            None,
+            format!(
+                "checkpoint covers {covers} log lines but the log has {}; it is ignored and the log replayed",
+                scan.lines
+            ),
+        ));
+        return CheckpointStatus::Stale;
+    }
+    let checkpoint: Checkpoint = match fs::read_to_string(&checkpoint_path)
+        .map_err(|e| e.to_string())
+        .and_then(|data| serde_json::from_str(&data).map_err(|e| e.to_string()))
+    {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            issues.push(error(
+                "invalid_checkpoint",
+                None,
+                format!("{EXACT_CHECKPOINT_FILE}: {e}"),
+            ));
+            return CheckpointStatus::Invalid;
+        }
+    };
+    if checkpoint.format_version > FORMAT_VERSION {
+        issues.push(error(
+            "unsupported_version",
+            None,
+            format!(
+                "{EXACT_CHECKPOINT_FILE}: format version {} is newer than this build reads ({FORMAT_VERSION})",
+                checkpoint.format_version
+            ),
+        ));
+        return CheckpointStatus::Invalid;
+    }
+    if let Some(ep) = checkpoint
+        .episodes
+        .iter()
+        .find(|ep| ep.state_embedding.len() != meta.dim)
+    {
+        issues.push(error(
+            "invalid_checkpoint",
+            None,
+            format!(
+                "checkpoint episode {} has dimension {}, expected {}",
+                ep.id,
+                ep.state_embedding.len(),
+                meta.dim
+            ),
+        ));
+        return CheckpointStatus::Invalid;
+    }
+    if checkpoint.episodes.len() != scan.episodes {
+        issues.push(error(
+            "checkpoint_mismatch",
+            None,
+            format!(
+                "checkpoint holds {} episodes but the log has {}",
+                checkpoint.episodes.len(),
+                scan.episodes
+            ),
+        ));
+        return CheckpointStatus::Invalid;
+    }
+    CheckpointStatus::Valid
+}
+
+/// Cut the corrupt tail from the log (saving it to `episodes.jsonl.corrupt`), or terminate an
+/// unterminated last line.
+fn apply_repair(path: &Path, scan: &LogScan) -> Result<Repair> {
+    let log_path = path.join(EPISODES_LOG);
+    let mut log = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&log_path)
+        .with_context(|| format!("opening {}", log_path.display()))?;
+    let Some(ref tail) = scan.tail else {
+        log.write_all(b"\n")
+            .and_then(|()| log.sync_all())
+            .with_context(|| format!("writing {}", log_path.display()))?;
+        return Ok(Repair {
+            removed_lines: 0,
+            truncated_bytes: 0,
+            saved_to: None,
+            newline_added: true,
+        });
+    };
+    let data = fs::read(&log_path).with_context(|| format!("reading {}", log_path.display()))?;
+    let cut = &data[tail.offset as usize..];
+    let saved_path = path.join(CORRUPT_FILE);
+    let mut saved = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&saved_path)
+        .with_context(|| format!("opening {}", saved_path.display()))?;
+    saved
+        .write_all(cut)
+        .and_then(|()| saved.sync_all())
+        .with_context(|| format!("writing {}", saved_path.display()))?;
+    log.set_len(tail.offset)
+        .and_then(|()| log.sync_all())
+        .with_context(|| format!("truncating {}", log_path.display()))?;
+    Ok(Repair {
+        removed_lines: tail.lines,
+        truncated_bytes: cut.len() as u64,
+        saved_to: Some(saved_path.display().to_string()),
+        newline_added: false,
+    })
+}
+
+/// Human-readable form of the report.
+pub fn print(out: &mut impl Write, report: &Report) -> Result<()> {
+    writeln!(out, "{:<11} {}", "path", report.path)?;
+    match (report.dim, &report.index_type) {
+        (Some(dim), Some(index)) => writeln!(out, "{:<11} {dim} ({index})", "dim")?,
+        _ => writeln!(out, "{:<11} -", "dim")?,
+    }
+    writeln!(out, "{:<11} {}", "log_lines", report.log_lines)?;
+    writeln!(out, "{:<11} {}", "episodes", report.episodes)?;
+    let checkpoint = serde_json::to_value(report.checkpoint)?;
+    writeln!(
+        out,
+        "{:<11} {}",
+        "checkpoint",
+        checkpoint.as_str().unwrap_or("-")
+    )?;
+    if let Some(ref repair) = report.repair {
+        if repair.newline_added {
+            writeln!(out, "repaired: added a newline to the last line")?;
+        } else {
+            writeln!(
+                out,
+                "repaired: removed {} trailing line(s) ({} bytes), saved to {}",
+                repair.removed_lines,
+                repair.truncated_bytes,
+                repair.saved_to.as_deref().unwrap_or("-")
+            )?;
+        }
+    }
+    for issue in &report.issues {
+        let severity = match issue.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match issue.line {
+            Some(line) => write!(out, "{severity}: line {line}: ")?,
+            None => write!(out, "{severity}: ")?,
+        }
+        writeln!(out, "{}: {}", issue.kind, issue.message)?;
+    }
+    let errors = report
+        .issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    let warnings = report.issues.len() - errors;
+    if report.ok {
+        writeln!(out, "ok ({warnings} warning(s))")?;
+    } else {
+        writeln!(out, "{errors} error(s), {warnings} warning(s)")?;
+    }
+    Ok(())
+}
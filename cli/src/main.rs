@@ -0,0 +1,528 @@
+//! `agent-mem`: inspect, query, prune and convert Agent Memory DB save files (`*.json`, from
+//! `AgentMemDB::save_to_file`) and disk directories (`AgentMemDBDisk`).
+
+mod bench;
+mod fsck;
+mod shell;
+mod store;
+
+use agent_mem_db::{DbDiff, Episode, QueryOptions};
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use std::collections::HashSet;
+use std::io::{BufRead, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use store::{Format, Prune, Store};
+
+#[derive(Parser)]
+#[command(
+    name = "agent-mem",
+    version,
+    about = "Inspect, query and convert Agent Memory DB save files and disk directories",
+    after_help = "PATH is a save file (*.json) or a disk directory (episodes.jsonl + meta.json).\n\
+                  Exit status: 0 on success, 1 on failure, 2 when fsck finds errors or diff \
+                  finds differences."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Episode count, dimension, reward and time ranges.
+    Stats {
+        path: PathBuf,
+        /// Print a JSON object instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// One line per episode, oldest first.
+    List {
+        path: PathBuf,
+        #[command(flatten)]
+        filter: Filter,
+        /// Stop after this many episodes.
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Print JSON lines instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print one episode as JSON.
+    Get { path: PathBuf, id: uuid::Uuid },
+    /// Similarity query with an embedding read from a file.
+    Query {
+        path: PathBuf,
+        /// JSON array of floats; `-` reads stdin.
+        #[arg(long)]
+        embedding: PathBuf,
+        #[arg(long, default_value_t = 5)]
+        top_k: usize,
+        #[arg(long, default_value_t = f32::NEG_INFINITY, allow_negative_numbers = true)]
+        min_reward: f32,
+        #[command(flatten)]
+        filter: Filter,
+        /// Print JSON lines instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Remove episodes in place (save files are rewritten).
+    Prune {
+        path: PathBuf,
+        #[command(flatten)]
+        rule: PruneRule,
+    },
+    /// Write every episode as JSON lines.
+    Export {
+        path: PathBuf,
+        /// Output file; stdout when omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Store episodes from JSON lines, creating PATH if needed.
+    Import {
+        path: PathBuf,
+        /// JSON lines file; `-` reads stdin.
+        input: PathBuf,
+        /// Dimension for a new PATH.
+        #[arg(long)]
+        dim: Option<usize>,
+        /// Index for a new disk directory: `exact` (with checkpoint) or `hnsw`.
+        #[arg(long, default_value = "exact", value_parser = ["exact", "hnsw"])]
+        index: String,
+    },
+    /// Copy a save file into a new disk directory, or a disk directory into a new save file.
+    Convert {
+        from: PathBuf,
+        to: PathBuf,
+        /// Index for a new disk directory: `exact` (with checkpoint) or `hnsw`.
+        #[arg(long, default_value = "exact", value_parser = ["exact", "hnsw"])]
+        index: String,
+    },
+    /// Episodes only in LEFT, only in RIGHT, and changed (matched by id); formats may differ.
+    Diff {
+        left: PathBuf,
+        right: PathBuf,
+        /// Print a JSON object instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Interactive session: list, query, filter and delete episodes.
+    Shell {
+        path: PathBuf,
+        #[command(flatten)]
+        embedder: shell::EmbedderArgs,
+    },
+    /// Insert throughput, query latency and recall on synthetic data.
+    Bench(bench::BenchArgs),
+    /// Compact a disk directory's log, rewrite its checkpoint and remove orphan files.
+    Vacuum {
+        path: PathBuf,
+        /// Print the report as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check a disk directory: meta.json, every log line, and the checkpoint.
+    Fsck {
+        path: PathBuf,
+        /// Truncate unreadable lines at the end of the log (saved to episodes.jsonl.corrupt).
+        #[arg(long)]
+        repair: bool,
+        /// Also open the directory and check the loaded index and checkpoint against the log.
+        #[arg(long)]
+        verify: bool,
+        /// Print the report as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Episode filters shared by `list`, `query` and the shell's `filter`.
+#[derive(Args, Clone, Default)]
+struct Filter {
+    #[arg(long)]
+    task_prefix: Option<String>,
+    /// Match episodes with any of these tags (comma-separated).
+    #[arg(long, value_delimiter = ',')]
+    tags_any: Vec<String>,
+    #[arg(long)]
+    user_id: Option<String>,
+    #[arg(long)]
+    source: Option<String>,
+}
+
+impl Filter {
+    fn apply(self, mut opts: QueryOptions) -> QueryOptions {
+        if let Some(prefix) = self.task_prefix {
+            opts = opts.task_id_prefix(prefix);
+        }
+        if !self.tags_any.is_empty() {
+            opts = opts.tags_any(self.tags_any);
+        }
+        if let Some(user_id) = self.user_id {
+            opts = opts.user_id(user_id);
+        }
+        if let Some(source) = self.source {
+            opts = opts.source(source);
+        }
+        opts
+    }
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct PruneRule {
+    /// Remove episodes with a timestamp before this Unix time (ms).
+    #[arg(long)]
+    older_than_ms: Option<i64>,
+    /// Keep only the n most recent episodes.
+    #[arg(long)]
+    keep_newest: Option<usize>,
+    /// Keep only the n highest-reward episodes.
+    #[arg(long)]
+    keep_highest_reward: Option<usize>,
+}
+
+impl PruneRule {
+    fn prune(&self) -> Prune {
+        match (
+            self.older_than_ms,
+            self.keep_newest,
+            self.keep_highest_reward,
+        ) {
+            (Some(ts), _, _) => Prune::OlderThan(ts),
+            (_, Some(n), _) => Prune::KeepNewest(n),
+            (_, _, Some(n)) => Prune::KeepHighestReward(n),
+            // clap requires exactly one.
+            (None, None, None) => unreachable!(),
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli.command) {
+        eprintln!("agent-mem: {e:#}");
+        std::process::exit(1);
+    }
+}
+
+fn run(command: Command) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    match command {
+        Command::Stats { path, json } => stats(&mut out, &path, &Store::open(&path)?, json)?,
+        Command::List {
+            path,
+            filter,
+            limit,
+            json,
+        } => {
+            let store = Store::open(&path)?;
+            let opts = filter.apply(QueryOptions::new(f32::NEG_INFINITY, usize::MAX));
+            let matching = store.episodes().into_iter().filter(|ep| opts.matches(ep));
+            for ep in matching.take(limit.unwrap_or(usize::MAX)) {
+                print_summary(&mut out, ep, None, json)?;
+            }
+        }
+        Command::Get { path, id } => {
+            let store = Store::open(&path)?;
+            let Some(ep) = store.episodes().into_iter().find(|ep| ep.id == id) else {
+                bail!("no episode {id} in {}", path.display());
+            };
+            serde_json::to_writer_pretty(&mut out, ep)?;
+            writeln!(out)?;
+        }
+        Command::Query {
+            path,
+            embedding,
+            top_k,
+            min_reward,
+            filter,
+            json,
+        } => {
+            let store = Store::open(&path)?;
+            let embedding = read_embedding(&embedding)?;
+            let results = store.query(
+                &embedding,
+                filter.apply(QueryOptions::new(min_reward, top_k)),
+            )?;
+            for ep in &results {
+                print_summary(
+                    &mut out,
+                    ep,
+                    Some(l2(&embedding, &ep.state_embedding)),
+                    json,
+                )?;
+            }
+        }
+        Command::Prune { path, rule } => {
+            let mut store = Store::open(&path)?;
+            let removed = store.prune(rule.prune())?;
+            store.save()?;
+            writeln!(out, "removed {removed}, {} remaining", store.len())?;
+        }
+        Command::Export { path, output } => {
+            let store = Store::open(&path)?;
+            match output {
+                Some(output) => {
+                    let file = std::fs::File::create(&output)
+                        .with_context(|| format!("creating {}", output.display()))?;
+                    export(&mut BufWriter::new(file), &store)?;
+                }
+                None => export(&mut out, &store)?,
+            }
+        }
+        Command::Import {
+            path,
+            input,
+            dim,
+            index,
+        } => {
+            let episodes = read_jsonl(&input)?;
+            let dim = dim.or_else(|| episodes.first().map(|ep| ep.state_embedding.len()));
+            let mut store = Store::open_or_create(&path, dim, &index)?;
+            let count = episodes.len();
+            store.store_episodes(episodes)?;
+            store.save()?;
+            writeln!(out, "imported {count}, {} total", store.len())?;
+        }
+        Command::Convert { from, to, index } => {
+            if to.exists() {
+                bail!("{} already exists", to.display());
+            }
+            let source = Store::open(&from)?;
+            if Format::of(&to) == source.format() {
+                bail!(
+                    "{} and {} are the same format",
+                    from.display(),
+                    to.display()
+                );
+            }
+            let mut target = Store::open_or_create(&to, Some(source.dim()), &index)?;
+            let episodes: Vec<Episode> = source.episodes().into_iter().cloned().collect();
+            target.store_episodes(episodes)?;
+            target.save()?;
+            writeln!(out, "converted {} episodes", target.len())?;
+        }
+        Command::Diff { left, right, json } => {
+            let (l, r) = (Store::open(&left)?, Store::open(&right)?);
+            let diff = DbDiff::between(l.episodes(), r.episodes());
+            print_diff(&mut out, &diff, json)?;
+            if !diff.is_empty() {
+                out.flush()?;
+                std::process::exit(2);
+            }
+        }
+        Command::Shell { path, embedder } => {
+            let stdin = std::io::stdin();
+            let interactive = stdin.is_terminal();
+            shell::run(&path, embedder, stdin.lock(), &mut out, interactive)?;
+        }
+        Command::Bench(args) => bench::run(&mut out, args)?,
+        Command::Vacuum { path, json } => {
+            let Store::Disk(mut db) = Store::open(&path)? else {
+                bail!("{} is not a disk directory", path.display());
+            };
+            let report = db.vacuum()?;
+            if json {
+                serde_json::to_writer_pretty(&mut out, &report)?;
+                writeln!(out)?;
+            } else {
+                writeln!(
+                    out,
+                    "removed {} records, {} -> {} bytes",
+                    report.records_removed, report.bytes_before, report.bytes_after
+                )?;
+            }
+        }
+        Command::Fsck {
+            path,
+            repair,
+            verify,
+            json,
+        } => {
+            let report = fsck::fsck(&path, repair, verify)?;
+            if json {
+                serde_json::to_writer_pretty(&mut out, &report)?;
+                writeln!(out)?;
+            } else {
+                fsck::print(&mut out, &report)?;
+            }
+            if !report.ok {
+                out.flush()?;
+                std::process::exit(2);
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn stats(out: &mut impl Write, path: &Path, store: &Store, json: bool) -> Result<()> {
+    let episodes = store.episodes();
+    let rewards: Vec<f32> = episodes.iter().map(|ep| ep.reward).collect();
+    let timestamps: Vec<i64> = episodes.iter().filter_map(|ep| ep.timestamp).collect();
+    let distinct = |f: fn(&Episode) -> Option<&str>| {
+        episodes
+            .iter()
+            .filter_map(|ep| f(ep))
+            .collect::<HashSet<_>>()
+            .len()
+    };
+    let stats = serde_json::json!({
+        "path": path.display().to_string(),
+        "format": match store.format() {
+            Format::File => "file",
+            Format::Disk => "disk",
+        },
+        "dim": store.dim(),
+        "episodes": episodes.len(),
+        "tasks": distinct(|ep| Some(ep.task_id.as_str())),
+        "users": distinct(|ep| ep.user_id.as_deref()),
+        "sources": distinct(|ep| ep.source.as_deref()),
+        "reward_min": rewards.iter().copied().reduce(f32::min),
+        "reward_mean": (!rewards.is_empty()).then(|| rewards.iter().sum::<f32>() / rewards.len() as f32),
+        "reward_max": rewards.iter().copied().reduce(f32::max),
+        "with_timestamp": timestamps.len(),
+        "oldest_ms": timestamps.iter().min(),
+        "newest_ms": timestamps.iter().max(),
+    });
+    if json {
+        serde_json::to_writer_pretty(&mut *out, &stats)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    for (key, value) in stats.as_object().into_iter().flatten() {
+        match value {
+            serde_json::Value::String(s) => writeln!(out, "{key:<15} {s}")?,
+            serde_json::Value::Null => writeln!(out, "{key:<15} -")?,
+            v => writeln!(out, "{key:<15} {v}")?,
+        }
+    }
+    Ok(())
+}
+
+fn print_diff(out: &mut impl Write, diff: &DbDiff, json: bool) -> Result<()> {
+    if json {
+        let brief = |ep: &Episode| serde_json::json!({"id": ep.id, "task_id": ep.task_id});
+        let report = serde_json::json!({
+            "identical": diff.is_empty(),
+            "only_in_left": diff.only_in_left.iter().map(brief).collect::<Vec<_>>(),
+            "only_in_right": diff.only_in_right.iter().map(brief).collect::<Vec<_>>(),
+            "changed": diff.changed.iter().map(|c| serde_json::json!({
+                "id": c.left.id,
+                "task_id": c.left.task_id,
+                "fields": c.fields,
+            })).collect::<Vec<_>>(),
+        });
+        serde_json::to_writer_pretty(&mut *out, &report)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    for ep in &diff.only_in_left {
+        writeln!(out, "- {}  {}", ep.id, ep.task_id)?;
+    }
+    for ep in &diff.only_in_right {
+        writeln!(out, "+ {}  {}", ep.id, ep.task_id)?;
+    }
+    for c in &diff.changed {
+        writeln!(
+            out,
+            "~ {}  {}  ({})",
+            c.left.id,
+            c.left.task_id,
+            c.fields.join(", ")
+        )?;
+    }
+    writeln!(
+        out,
+        "{} only in left, {} only in right, {} changed",
+        diff.only_in_left.len(),
+        diff.only_in_right.len(),
+        diff.changed.len()
+    )?;
+    Ok(())
+}
+
+fn print_summary(
+    out: &mut impl Write,
+    ep: &Episode,
+    distance: Option<f32>,
+    json: bool,
+) -> Result<()> {
+    if json {
+        let mut line = serde_json::json!({
+            "id": ep.id,
+            "task_id": ep.task_id,
+            "reward": ep.reward,
+            "timestamp": ep.timestamp,
+            "tags": ep.tags,
+            "source": ep.source,
+            "user_id": ep.user_id,
+        });
+        if let Some(d) = distance {
+            line["distance"] = d.into();
+        }
+        writeln!(out, "{line}")?;
+        return Ok(());
+    }
+    write!(out, "{}  {}  reward={}", ep.id, ep.task_id, ep.reward)?;
+    if let Some(d) = distance {
+        write!(out, "  distance={d:.4}")?;
+    }
+    if let Some(ts) = ep.timestamp {
+        write!(out, "  ts={ts}")?;
+    }
+    if let Some(ref tags) = ep.tags {
+        write!(out, "  tags={}", tags.join(","))?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn export(out: &mut impl Write, store: &Store) -> Result<()> {
+    for ep in store.episodes() {
+        serde_json::to_writer(&mut *out, ep)?;
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Read `path`, or stdin for `-`.
+fn read_input(path: &Path) -> Result<Box<dyn BufRead>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(std::io::stdin().lock()));
+    }
+    let file = std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    Ok(Box::new(std::io::BufReader::new(file)))
+}
+
+fn read_embedding(path: &Path) -> Result<Vec<f32>> {
+    serde_json::from_reader(read_input(path)?)
+        .with_context(|| format!("{}: expected a JSON array of numbers", path.display()))
+}
+
+fn read_jsonl(path: &Path) -> Result<Vec<Episode>> {
+    let mut episodes = Vec::new();
+    for (i, line) in read_input(path)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let ep = serde_json::from_str(&line)
+            .with_context(|| format!("{} line {}", path.display(), i + 1))?;
+        episodes.push(ep);
+    }
+    Ok(episodes)
+}
+
+fn l2(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
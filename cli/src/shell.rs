@@ -0,0 +1,297 @@
+//! `agent-mem shell`: an interactive session over one save file or disk directory, for looking
+//! through (and cleaning up) an agent's memory while developing it.
+
+use crate::store::Store;
+use crate::{l2, print_summary, read_embedding, Filter};
+use agent_mem_db::QueryOptions;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const HELP: &str = "\
+commands:
+  stats                      episode count, dimension, reward and time ranges
+  list [n]                   episodes matching the filter, oldest first
+  get <id>                   one episode as JSON
+  query <file> [k]           similarity query with an embedding from a JSON file
+  search <text>              similarity query, embedding <text> with --embedding-url
+  top-k <n>                  results per query (default 5)
+  filter                     show the filter applied to list, query and search
+  filter <key> <value>       set task_prefix, tags (comma-separated), user, source or min_reward
+  filter clear               remove all filters
+  delete <id>...             delete episodes (save files are rewritten)
+  help                       this list
+  quit                       leave the shell (also Ctrl-D)";
+
+/// An OpenAI-compatible embeddings API, configured like the server's `[embedding]` section.
+#[derive(Args)]
+pub struct EmbedderArgs {
+    /// Embeddings API base URL (`POST <url>/embeddings`), enabling `search <text>`.
+    #[arg(long, env = "AGENT_MEM_EMBEDDING_URL")]
+    embedding_url: Option<String>,
+    #[arg(
+        long,
+        env = "AGENT_MEM_EMBEDDING_MODEL",
+        default_value = "text-embedding-3-small"
+    )]
+    embedding_model: String,
+    /// Sent as `Authorization: Bearer`.
+    #[arg(long, env = "AGENT_MEM_EMBEDDING_API_KEY", hide_env_values = true)]
+    embedding_api_key: Option<String>,
+}
+
+struct Embedder {
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+    client: reqwest::blocking::Client,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+impl Embedder {
+    /// `None` when no embedding URL is configured.
+    fn new(args: EmbedderArgs) -> Result<Option<Self>> {
+        let Some(url) = args.embedding_url else {
+            return Ok(None);
+        };
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(Some(Self {
+            endpoint: format!("{}/embeddings", url.trim_end_matches('/')),
+            model: args.embedding_model,
+            api_key: args.embedding_api_key,
+            client,
+        }))
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let mut request = self.client.post(&self.endpoint).json(&serde_json::json!({
+            "model": self.model,
+            "input": [text],
+        }));
+        if let Some(ref key) = self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request
+            .send()
+            .with_context(|| format!("calling {}", self.endpoint))?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!(
+                "{} returned {status}: {}",
+                self.endpoint,
+                response.text().unwrap_or_default()
+            );
+        }
+        let body: EmbeddingsResponse = response
+            .json()
+            .with_context(|| format!("parsing the response from {}", self.endpoint))?;
+        body.data
+            .into_iter()
+            .next()
+            .map(|d| d.embedding)
+            .ok_or_else(|| anyhow!("{} returned no embedding", self.endpoint))
+    }
+}
+
+struct Session<'a> {
+    path: &'a Path,
+    store: Store,
+    filter: Filter,
+    min_reward: f32,
+    top_k: usize,
+    embedder: Option<Embedder>,
+}
+
+/// Run commands read from `input` until `quit` or end of input. Prompts when `interactive`.
+pub fn run(
+    path: &Path,
+    embedder: EmbedderArgs,
+    input: impl BufRead,
+    out: &mut impl Write,
+    interactive: bool,
+) -> Result<()> {
+    let mut session = Session {
+        path,
+        store: Store::open(path)?,
+        filter: Filter::default(),
+        min_reward: f32::NEG_INFINITY,
+        top_k: 5,
+        embedder: Embedder::new(embedder)?,
+    };
+    if interactive {
+        writeln!(
+            out,
+            "{}: {} episodes, dimension {}. Type `help` for commands.",
+            path.display(),
+            session.store.len(),
+            session.store.dim()
+        )?;
+    }
+    let mut lines = input.lines();
+    loop {
+        if interactive {
+            write!(out, "agent-mem> ")?;
+            out.flush()?;
+        }
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match session.exec(out, line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => writeln!(out, "error: {e:#}")?,
+        }
+        out.flush()?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+impl Session<'_> {
+    fn opts(&self, top_k: usize) -> QueryOptions {
+        self.filter
+            .clone()
+            .apply(QueryOptions::new(self.min_reward, top_k))
+    }
+
+    /// Run one command; `false` to leave the shell.
+    fn exec(&mut self, out: &mut impl Write, line: &str) -> Result<bool> {
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        match (command, args.as_slice()) {
+            ("quit" | "exit", _) => return Ok(false),
+            ("help" | "?", _) => writeln!(out, "{HELP}")?,
+            ("stats", []) => crate::stats(out, self.path, &self.store, false)?,
+            ("list", [] | [_]) => {
+                let limit = match args.first() {
+                    Some(n) => n.parse().context("list [n]: n must be a number")?,
+                    None => usize::MAX,
+                };
+                let opts = self.opts(usize::MAX);
+                let episodes = self.store.episodes();
+                let matching = episodes.into_iter().filter(|ep| opts.matches(ep));
+                for ep in matching.take(limit) {
+                    print_summary(out, ep, None, false)?;
+                }
+            }
+            ("get", [id]) => {
+                let id: uuid::Uuid = id.parse().context("get <id>: not a UUID")?;
+                let episodes = self.store.episodes();
+                let Some(ep) = episodes.into_iter().find(|ep| ep.id == id) else {
+                    bail!("no episode {id}");
+                };
+                serde_json::to_writer_pretty(&mut *out, ep)?;
+                writeln!(out)?;
+            }
+            ("query", [file] | [file, _]) => {
+                let top_k = match args.get(1) {
+                    Some(k) => k.parse().context("query <file> [k]: k must be a number")?,
+                    None => self.top_k,
+                };
+                let embedding = read_embedding(Path::new(file))?;
+                self.query(out, &embedding, top_k)?;
+            }
+            ("search", _) if !rest.is_empty() => {
+                let Some(ref embedder) = self.embedder else {
+                    bail!("search needs --embedding-url (or AGENT_MEM_EMBEDDING_URL)");
+                };
+                let embedding = embedder.embed(rest)?;
+                self.query(out, &embedding, self.top_k)?;
+            }
+            ("top-k", [k]) => {
+                self.top_k = k.parse().context("top-k <n>: n must be a number")?;
+                writeln!(out, "top-k {}", self.top_k)?;
+            }
+            ("filter", []) => self.show_filter(out)?,
+            ("filter", ["clear"]) => {
+                self.filter = Filter::default();
+                self.min_reward = f32::NEG_INFINITY;
+                self.show_filter(out)?;
+            }
+            ("filter", [key, value]) => {
+                let value = value.to_string();
+                match *key {
+                    "task_prefix" => self.filter.task_prefix = Some(value),
+                    "tags" => self.filter.tags_any = value.split(',').map(str::to_string).collect(),
+                    "user" => self.filter.user_id = Some(value),
+                    "source" => self.filter.source = Some(value),
+                    "min_reward" => {
+                        self.min_reward = value.parse().context("min_reward must be a number")?
+                    }
+                    other => bail!("unknown filter {other:?}; see `help`"),
+                }
+                self.show_filter(out)?;
+            }
+            ("delete", [_, ..]) => {
+                let ids = args
+                    .iter()
+                    .map(|id| id.parse())
+                    .collect::<Result<HashSet<uuid::Uuid>, _>>()
+                    .context("delete <id>...: not a UUID")?;
+                let removed = self.store.delete_where(|ep| ids.contains(&ep.id))?;
+                self.store.save()?;
+                writeln!(out, "deleted {removed}, {} remaining", self.store.len())?;
+            }
+            _ => bail!("unknown command or wrong arguments: {line:?}; see `help`"),
+        }
+        Ok(true)
+    }
+
+    fn query(&self, out: &mut impl Write, embedding: &[f32], top_k: usize) -> Result<()> {
+        let results = self.store.query(embedding, self.opts(top_k))?;
+        if results.is_empty() {
+            writeln!(out, "no matches")?;
+        }
+        for ep in &results {
+            print_summary(out, ep, Some(l2(embedding, &ep.state_embedding)), false)?;
+        }
+        Ok(())
+    }
+
+    fn show_filter(&self, out: &mut impl Write) -> Result<()> {
+        let f = &self.filter;
+        let mut parts = Vec::new();
+        if let Some(ref p) = f.task_prefix {
+            parts.push(format!("task_prefix={p}"));
+        }
+        if !f.tags_any.is_empty() {
+            parts.push(format!("tags={}", f.tags_any.join(",")));
+        }
+        if let Some(ref u) = f.user_id {
+            parts.push(format!("user={u}"));
+        }
+        if let Some(ref s) = f.source {
+            parts.push(format!("source={s}"));
+        }
+        if self.min_reward.is_finite() {
+            parts.push(format!("min_reward={}", self.min_reward));
+        }
+        if parts.is_empty() {
+            writeln!(out, "filter: none")?;
+        } else {
+            writeln!(out, "filter: {}", parts.join(" "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+//! A save file (`AgentMemDB::save_to_file` JSON) or a disk directory (`AgentMemDBDisk`),
+//! opened behind one interface.
+
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, QueryOptions};
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+pub enum Store {
+    /// Loaded into memory; changes are written back by `save`.
+    File { path: PathBuf, db: AgentMemDB },
+    /// Changes are durable as soon as they are made.
+    Disk(AgentMemDBDisk),
+}
+
+/// Which format a path is in (or should be created in).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    File,
+    Disk,
+}
+
+impl Format {
+    /// Directories (and paths without a `.json` extension) are disk DBs.
+    pub fn of(path: &Path) -> Format {
+        if path.is_dir() || path.extension().and_then(|e| e.to_str()) != Some("json") {
+            Format::Disk
+        } else {
+            Format::File
+        }
+    }
+}
+
+impl Store {
+    /// Open an existing save file or disk directory.
+    pub fn open(path: &Path) -> Result<Store> {
+        if !path.exists() {
+            bail!("{} does not exist", path.display());
+        }
+        match Format::of(path) {
+            Format::File => {
+                let db = AgentMemDB::load_from_file_exact(path)
+                    .with_context(|| format!("loading {}", path.display()))?;
+                Ok(Store::File {
+                    path: path.to_path_buf(),
+                    db,
+                })
+            }
+            Format::Disk => {
+                let db = AgentMemDBDisk::open_existing(path)
+                    .with_context(|| format!("opening {}", path.display()))?;
+                Ok(Store::Disk(db))
+            }
+        }
+    }
+
+    /// Open `path`, creating an empty store of dimension `dim` if it doesn't exist.
+    pub fn open_or_create(path: &Path, dim: Option<usize>, index: &str) -> Result<Store> {
+        if path.exists() {
+            let store = Store::open(path)?;
+            if let Some(dim) = dim.filter(|d| *d != store.dim()) {
+                bail!(
+                    "{} has dimension {}, not {dim}",
+                    path.display(),
+                    store.dim()
+                );
+            }
+            return Ok(store);
+        }
+        let Some(dim) = dim else {
+            bail!("{} does not exist; pass --dim to create it", path.display());
+        };
+        match Format::of(path) {
+            Format::File => Ok(Store::File {
+                path: path.to_path_buf(),
+                db: AgentMemDB::new_exact(dim),
+            }),
+            Format::Disk => {
+                let opts = match index {
+                    "hnsw" => DiskOptions::hnsw(dim, 20_000),
+                    _ => DiskOptions::exact_with_checkpoint(dim),
+                };
+                let db = AgentMemDBDisk::open_with_options(path, opts)
+                    .with_context(|| format!("creating {}", path.display()))?;
+                Ok(Store::Disk(db))
+            }
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        match self {
+            Store::File { .. } => Format::File,
+            Store::Disk(_) => Format::Disk,
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        match self {
+            Store::File { db, .. } => db.dim(),
+            Store::Disk(db) => db.dim(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Store::File { db, .. } => db.len(),
+            Store::Disk(db) => db.len(),
+        }
+    }
+
+    /// Every episode, oldest first (untimestamped first), ties broken by id.
+    pub fn episodes(&self) -> Vec<&Episode> {
+        let mut episodes: Vec<&Episode> = match self {
+            Store::File { db, .. } => db.iter().collect(),
+            Store::Disk(db) => db.iter().collect(),
+        };
+        episodes.sort_by_key(|ep| (ep.timestamp, ep.id));
+        episodes
+    }
+
+    pub fn query(&self, embedding: &[f32], opts: QueryOptions) -> Result<Vec<Episode>> {
+        Ok(match self {
+            Store::File { db, .. } => db.query_similar_with_options(embedding, opts)?,
+            Store::Disk(db) => db.query_similar_with_options(embedding, opts)?,
+        })
+    }
+
+    pub fn store_episodes(&mut self, episodes: Vec<Episode>) -> Result<()> {
+        match self {
+            Store::File { db, .. } => db.store_episodes(episodes)?,
+            Store::Disk(db) => db.store_episodes(episodes)?,
+        }
+        Ok(())
+    }
+
+    /// Apply a prune and return the number of episodes removed.
+    pub fn prune(&mut self, prune: Prune) -> Result<usize> {
+        Ok(match (self, prune) {
+            (Store::File { db, .. }, Prune::OlderThan(ts)) => db.prune_older_than(ts)?,
+            (Store::File { db, .. }, Prune::KeepNewest(n)) => db.prune_keep_newest(n)?,
+            (Store::File { db, .. }, Prune::KeepHighestReward(n)) => {
+                db.prune_keep_highest_reward(n)?
+            }
+            (Store::Disk(db), Prune::OlderThan(ts)) => db.prune_older_than(ts)?,
+            (Store::Disk(db), Prune::KeepNewest(n)) => db.prune_keep_newest(n)?,
+            (Store::Disk(db), Prune::KeepHighestReward(n)) => db.prune_keep_highest_reward(n)?,
+        })
+    }
+
+    /// Remove every episode matching `predicate` and return the number removed.
+    pub fn delete_where(&mut self, predicate: impl Fn(&Episode) -> bool) -> Result<usize> {
+        Ok(match self {
+            Store::File { db, .. } => db.delete_where(predicate),
+            Store::Disk(db) => db.delete_where(predicate)?,
+        })
+    }
+
+    /// Persist changes: rewrite the save file, or checkpoint the disk DB.
+    pub fn save(&mut self) -> Result<(), AgentMemError> {
+        match self {
+            Store::File { path, db } => db.save_to_file(path),
+            Store::Disk(db) => db.checkpoint(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Prune {
+    OlderThan(i64),
+    KeepNewest(usize),
+    KeepHighestReward(usize),
+}
@@ -0,0 +1,517 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, DiskOptions, Episode};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn agent_mem(args: &[&str]) -> (bool, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_agent-mem"))
+        .args(args)
+        .output()
+        .unwrap();
+    let text = String::from_utf8_lossy(if out.status.success() {
+        &out.stdout
+    } else {
+        &out.stderr
+    })
+    .into_owned();
+    (out.status.success(), text)
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("agent_mem_cli_{name}"));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn save_file(path: &Path) {
+    let mut db = AgentMemDB::new_exact(2);
+    for i in 0..5 {
+        let ep = Episode::with_timestamp(
+            format!("task-{i}"),
+            vec![i as f32, 0.0],
+            i as f32 / 4.0,
+            1_000 + i,
+        );
+        db.store_episode(ep).unwrap();
+    }
+    db.save_to_file(path).unwrap();
+}
+
+fn s(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
+
+#[test]
+fn test_cli_stats_list_get() {
+    let dir = scratch("stats");
+    let file = dir.join("db.json");
+    save_file(&file);
+
+    let (ok, stats) = agent_mem(&["stats", s(&file), "--json"]);
+    assert!(ok, "{stats}");
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    assert_eq!(stats["episodes"], 5);
+    assert_eq!(stats["dim"], 2);
+    assert_eq!(stats["oldest_ms"], 1000);
+
+    let (ok, list) = agent_mem(&["list", s(&file), "--json", "--limit", "2"]);
+    assert!(ok, "{list}");
+    let lines: Vec<serde_json::Value> = list
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["task_id"], "task-0");
+
+    let id = lines[1]["id"].as_str().unwrap();
+    let (ok, ep) = agent_mem(&["get", s(&file), id]);
+    assert!(ok, "{ep}");
+    assert!(ep.contains("task-1"));
+
+    let (ok, err) = agent_mem(&["stats", s(&dir.join("missing.json"))]);
+    assert!(!ok);
+    assert!(err.contains("does not exist"));
+}
+
+#[test]
+fn test_cli_query_and_prune() {
+    let dir = scratch("query");
+    let file = dir.join("db.json");
+    save_file(&file);
+    let embedding = dir.join("q.json");
+    fs::write(&embedding, "[4.0, 0.0]").unwrap();
+
+    let (ok, out) = agent_mem(&[
+        "query",
+        s(&file),
+        "--embedding",
+        s(&embedding),
+        "--top-k",
+        "2",
+        "--json",
+    ]);
+    assert!(ok, "{out}");
+    let first: serde_json::Value = serde_json::from_str(out.lines().next().unwrap()).unwrap();
+    assert_eq!(first["task_id"], "task-4");
+    assert_eq!(out.lines().count(), 2);
+
+    let (ok, out) = agent_mem(&["prune", s(&file), "--keep-newest", "3"]);
+    assert!(ok, "{out}");
+    assert!(out.contains("removed 2"));
+    assert_eq!(AgentMemDB::load_from_file(&file).unwrap().len(), 3);
+
+    let (ok, _) = agent_mem(&["prune", s(&file)]);
+    assert!(!ok, "a prune rule is required");
+}
+
+#[test]
+fn test_cli_convert_export_import() {
+    let dir = scratch("convert");
+    let file = dir.join("db.json");
+    save_file(&file);
+    let disk = dir.join("disk");
+
+    let (ok, out) = agent_mem(&["convert", s(&file), s(&disk)]);
+    assert!(ok, "{out}");
+    assert_eq!(AgentMemDBDisk::open_existing(&disk).unwrap().len(), 5);
+    let (ok, _) = agent_mem(&["convert", s(&file), s(&disk)]);
+    assert!(!ok, "existing target is refused");
+
+    let jsonl = dir.join("episodes.jsonl");
+    let (ok, out) = agent_mem(&["export", s(&disk), "-o", s(&jsonl)]);
+    assert!(ok, "{out}");
+    assert_eq!(fs::read_to_string(&jsonl).unwrap().lines().count(), 5);
+
+    let copy = dir.join("copy.json");
+    let (ok, out) = agent_mem(&["import", s(&copy), s(&jsonl)]);
+    assert!(ok, "{out}");
+    assert!(out.contains("imported 5"));
+    let db = AgentMemDB::load_from_file(&copy).unwrap();
+    assert_eq!(db.dim(), 2);
+    assert_eq!(db.len(), 5);
+}
+
+fn agent_mem_status(args: &[&str]) -> (Option<i32>, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_agent-mem"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        out.status.code(),
+        String::from_utf8_lossy(&out.stdout).into_owned(),
+    )
+}
+
+#[test]
+fn test_cli_fsck_and_repair() {
+    let dir = scratch("fsck");
+    let disk = dir.join("disk");
+    {
+        let mut db =
+            AgentMemDBDisk::open_with_options(&disk, DiskOptions::exact_with_checkpoint(2))
+                .unwrap();
+        for i in 0..3 {
+            db.store_episode(Episode::new(format!("task-{i}"), vec![i as f32, 1.0], 0.5))
+                .unwrap();
+        }
+        db.checkpoint().unwrap();
+    }
+
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--json"]);
+    assert_eq!(code, Some(0), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["ok"], true);
+    assert_eq!(report["episodes"], 3);
+    assert_eq!(report["checkpoint"], "valid");
+
+    // An interrupted append leaves a partial line at the end of the log.
+    let log = disk.join("episodes.jsonl");
+    let mut f = fs::OpenOptions::new().append(true).open(&log).unwrap();
+    std::io::Write::write_all(&mut f, b"{\"id\":\"5b1c").unwrap();
+    drop(f);
+    assert!(AgentMemDBDisk::open_existing(&disk).is_err());
+
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--json"]);
+    assert_eq!(code, Some(2), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["ok"], false);
+    assert_eq!(report["checkpoint"], "stale");
+    assert_eq!(report["issues"][0]["kind"], "truncated_line");
+    assert_eq!(report["issues"][0]["line"], 4);
+
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--repair", "--json"]);
+    assert_eq!(code, Some(0), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["repair"]["removed_lines"], 1);
+    assert_eq!(report["checkpoint"], "valid");
+    assert!(disk.join("episodes.jsonl.corrupt").exists());
+    assert_eq!(AgentMemDBDisk::open_existing(&disk).unwrap().len(), 3);
+
+    // Corruption before a readable line can't be truncated away.
+    let text = fs::read_to_string(&log).unwrap();
+    let mut lines: Vec<&str> = text.lines().collect();
+    let bad = lines[1].replacen("[1.0,1.0]", "[1.0]", 1);
+    lines[1] = &bad;
+    fs::write(&log, lines.join("\n") + "\n").unwrap();
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--repair", "--json"]);
+    assert_eq!(code, Some(2), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["issues"][0]["kind"], "dimension_mismatch");
+    assert!(report.get("repair").is_none());
+    assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 3);
+}
+
+#[test]
+fn test_cli_bench() {
+    let args = [
+        "bench",
+        "--dim",
+        "8",
+        "--n",
+        "300",
+        "--queries",
+        "50",
+        "--top-k",
+        "50",
+        "--ef-search",
+        "200",
+        "--seed",
+        "7",
+        "--json",
+    ];
+    let (ok, out) = agent_mem(&args);
+    assert!(ok, "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["index"], "hnsw");
+    assert_eq!(report["n"], 300);
+    assert_eq!(report["recall_queries"], 50);
+    assert_eq!(report["hnsw_params"]["ef_search"], 200);
+    let recall = report["recall_at_k"].as_f64().unwrap();
+    // The HNSW graph isn't deterministic and its recall is low (see "Known issue" in
+    // BENCHMARKS.md): 0.31-0.72 over 200 builds of this data. Random picks would score
+    // 50/300, so the floor still shows the index finds near neighbours.
+    assert!(recall >= 0.25, "recall {recall}");
+    assert!(report["query_p50_us"].as_f64().unwrap() <= report["query_p99_us"].as_f64().unwrap());
+
+    let (ok, out) = agent_mem(&[
+        "bench", "--index", "exact", "--dim", "4", "--n", "50", "--json",
+    ]);
+    assert!(ok, "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["recall_at_k"], 1.0);
+    assert!(report.get("hnsw_params").is_none());
+}
+
+/// Answer one `POST /embeddings` with a fixed embedding.
+fn embedding_server(embedding: &'static str) -> String {
+    use std::io::{BufRead, BufReader, Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = v.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        let response = format!(r#"{{"data":[{{"embedding":{embedding},"index":0}}]}}"#);
+        write!(
+            reader.get_mut(),
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{response}",
+            response.len()
+        )
+        .unwrap();
+    });
+    url
+}
+
+#[test]
+fn test_cli_shell() {
+    use std::io::Write;
+    let dir = scratch("shell");
+    let file = dir.join("db.json");
+    save_file(&file);
+    let embedding = dir.join("q.json");
+    fs::write(&embedding, "[3.0, 0.0]").unwrap();
+    let id = AgentMemDB::load_from_file(&file)
+        .unwrap()
+        .iter()
+        .find(|ep| ep.task_id == "task-3")
+        .unwrap()
+        .id;
+
+    let script = format!(
+        "stats\n\
+         filter min_reward 0.5\n\
+         list\n\
+         query {} 1\n\
+         filter clear\n\
+         search anything\n\
+         delete {id}\n\
+         bogus\n\
+         quit\n\
+         stats\n",
+        embedding.display()
+    );
+    let mut child = Command::new(env!("CARGO_BIN_EXE_agent-mem"))
+        .args(["shell", s(&file), "--embedding-url"])
+        .arg(embedding_server("[4.0, 0.0]"))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    let out = String::from_utf8_lossy(&out.stdout);
+
+    assert_eq!(out.matches("episodes        5").count(), 1, "{out}");
+    assert!(out.contains("filter: min_reward=0.5"), "{out}");
+    let listed: Vec<&str> = out.lines().filter(|l| l.contains("  task-")).collect();
+    // list: task-2..=task-4; query k=1: task-3; search: task-4 first.
+    assert!(
+        listed[0].contains("task-2") && listed[2].contains("task-4"),
+        "{out}"
+    );
+    assert!(
+        listed[3].contains("task-3") && listed[3].contains("distance=0.0000"),
+        "{out}"
+    );
+    assert!(listed[4].contains("task-4"), "{out}");
+    assert!(out.contains("deleted 1, 4 remaining"), "{out}");
+    assert!(out.contains("error: unknown command"), "{out}");
+    assert_eq!(AgentMemDB::load_from_file(&file).unwrap().len(), 4);
+}
+
+#[test]
+fn test_cli_diff() {
+    let dir = scratch("diff");
+    let file = dir.join("db.json");
+    save_file(&file);
+    let disk = dir.join("disk");
+    assert!(agent_mem(&["convert", s(&file), s(&disk)]).0);
+
+    let (code, out) = agent_mem_status(&["diff", s(&file), s(&disk)]);
+    assert_eq!(code, Some(0), "{out}");
+    assert!(out.contains("0 only in left, 0 only in right, 0 changed"));
+
+    assert!(agent_mem(&["prune", s(&disk), "--keep-newest", "4"]).0);
+    let mut db = AgentMemDB::load_from_file(&file).unwrap();
+    let mut ep = db.iter().find(|ep| ep.task_id == "task-4").unwrap().clone();
+    db.delete_where(|e| e.id == ep.id);
+    ep.reward = -1.0;
+    db.store_episode(ep.clone()).unwrap();
+    db.save_to_file(&file).unwrap();
+
+    let (code, out) = agent_mem_status(&["diff", s(&file), s(&disk), "--json"]);
+    assert_eq!(code, Some(2), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["identical"], false);
+    assert_eq!(report["only_in_left"][0]["task_id"], "task-0");
+    assert_eq!(report["only_in_right"].as_array().unwrap().len(), 0);
+    assert_eq!(report["changed"][0]["id"], ep.id.to_string());
+    assert_eq!(report["changed"][0]["fields"][0], "reward");
+}
+
+#[test]
+fn test_cli_fsck_uncommitted_batch() {
+    let dir = scratch("fsck_batch");
+    let disk = dir.join("disk");
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&disk, DiskOptions::exact(2)).unwrap();
+        let batch = (0..3)
+            .map(|i| Episode::new(format!("task-{i}"), vec![i as f32, 0.0], 0.5))
+            .collect();
+        db.store_episodes(batch).unwrap();
+    }
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--json"]);
+    assert_eq!(code, Some(0), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["episodes"], 3);
+    assert_eq!(report["issues"].as_array().unwrap().len(), 0, "{out}");
+
+    // Crash after the begin marker and one episode of a second batch.
+    let log = disk.join("episodes.jsonl");
+    let text = fs::read_to_string(&log).unwrap();
+    let partial: Vec<&str> = text.lines().take(2).collect();
+    fs::write(&log, format!("{text}{}\n{{\"id\":", partial.join("\n"))).unwrap();
+
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--json"]);
+    assert_eq!(
+        code,
+        Some(0),
+        "an uncommitted batch is discarded on open: {out}"
+    );
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["episodes"], 3);
+    assert_eq!(report["issues"][0]["kind"], "uncommitted_batch");
+    assert_eq!(report["issues"][0]["line"], 6);
+
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--repair", "--json"]);
+    assert_eq!(code, Some(0), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["repair"]["removed_lines"], 3);
+    assert_eq!(report["issues"].as_array().unwrap().len(), 0, "{out}");
+    assert_eq!(fs::read_to_string(&log).unwrap(), text);
+}
+
+#[test]
+fn test_cli_fsck_updated_episode() {
+    let dir = scratch("fsck_update");
+    let disk = dir.join("disk");
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&disk, DiskOptions::exact(2)).unwrap();
+        let mut ep = Episode::new("task", vec![0.0, 1.0], 0.5);
+        db.store_episode(ep.clone()).unwrap();
+        ep.reward = 0.9;
+        db.update_episode(ep, 0).unwrap();
+    }
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--json"]);
+    assert_eq!(code, Some(0), "a newer version is not a duplicate: {out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["log_lines"], 2);
+    assert_eq!(report["episodes"], 1);
+
+    // The same version twice is.
+    let log = disk.join("episodes.jsonl");
+    let text = fs::read_to_string(&log).unwrap();
+    let last = text.lines().last().unwrap().to_string();
+    fs::write(&log, format!("{text}{last}\n")).unwrap();
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--json"]);
+    assert_eq!(code, Some(2), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["issues"][0]["kind"], "duplicate_id");
+    assert_eq!(report["issues"][0]["line"], 3);
+}
+
+#[test]
+fn test_cli_vacuum() {
+    let dir = scratch("vacuum");
+    let disk = dir.join("disk");
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&disk, DiskOptions::exact(2)).unwrap();
+        let mut ep = Episode::new("task", vec![0.0, 1.0], 0.5);
+        db.store_episode(ep.clone()).unwrap();
+        ep.reward = 0.9;
+        db.update_episode(ep, 0).unwrap();
+    }
+    let (ok, out) = agent_mem(&["vacuum", s(&disk), "--json"]);
+    assert!(ok, "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["records_removed"], 1);
+    // The log is down to the latest version, with a fresh checkpoint (the CLI enables them).
+    let log = fs::read_to_string(disk.join("episodes.jsonl")).unwrap();
+    assert_eq!(log.lines().count(), 1);
+    assert!(disk.join("exact_checkpoint.json").exists());
+
+    let file = dir.join("db.json");
+    save_file(&file);
+    let (ok, out) = agent_mem(&["vacuum", s(&file)]);
+    assert!(!ok);
+    assert!(out.contains("not a disk directory"), "{out}");
+}
+
+#[test]
+fn test_cli_fsck_verify() {
+    let dir = scratch("fsck_verify");
+    let disk = dir.join("disk");
+    {
+        let mut db =
+            AgentMemDBDisk::open_with_options(&disk, DiskOptions::exact_with_checkpoint(2))
+                .unwrap();
+        db.store_episode(Episode::new("task", vec![0.0, 1.0], 0.5))
+            .unwrap();
+        db.checkpoint().unwrap();
+    }
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--verify", "--json"]);
+    assert_eq!(code, Some(0), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["verify"]["episodes"], 1);
+    assert_eq!(report["verify"]["problems"], serde_json::json!([]));
+
+    // A torn tail is reported, and the directory isn't opened (which would cut it).
+    let log = disk.join("episodes.jsonl");
+    let text = fs::read_to_string(&log).unwrap();
+    fs::write(&log, format!("{text}{{\"id\":")).unwrap();
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--verify", "--json"]);
+    assert_eq!(code, Some(2), "{out}");
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert!(report.get("verify").is_none());
+    assert!(out.contains("verify_skipped"));
+    assert!(fs::read_to_string(&log).unwrap().ends_with("{\"id\":"));
+}
+
+#[test]
+fn test_cli_fsck_unsupported_version() {
+    let dir = scratch("fsck_version");
+    let disk = dir.join("disk");
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&disk, DiskOptions::exact(2)).unwrap();
+        db.store_episode(Episode::new("task", vec![0.0, 1.0], 0.5))
+            .unwrap();
+    }
+    let meta_path = disk.join("meta.json");
+    let mut meta: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&meta_path).unwrap()).unwrap();
+    meta["format_version"] = (agent_mem_db::FORMAT_VERSION + 1).into();
+    fs::write(&meta_path, meta.to_string()).unwrap();
+
+    let (code, out) = agent_mem_status(&["fsck", s(&disk), "--json"]);
+    assert_eq!(code, Some(2), "{out}");
+    assert!(out.contains("unsupported_version"), "{out}");
+}
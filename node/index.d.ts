@@ -11,6 +11,14 @@ export declare class AgentMemDb {
   storeEpisode(episode: Episode): void
   /** Query for similar episodes. embedding: number[], min_reward, top_k. Optional opts for filters. */
   querySimilar(embedding: Array<number>, minReward: number, topK: number, opts?: QueryOptionsJs | undefined | null): Array<Episode>
+  /** Embed storeText/queryText input with an OpenAI-compatible API (e.g. Ollama at
+   * http://localhost:11434/v1). dim must match the DB's. Requests block the calling thread.
+   */
+  useOpenAiEmbeddings(baseUrl: string, model: string, dim: number, apiKey?: string | undefined | null): void
+  /** Embed text and store it as an episode. Returns the episode id. */
+  storeText(taskId: string, text: string, reward: number): string
+  /** Like querySimilar, but embeds text first. */
+  queryText(text: string, minReward: number, topK: number, opts?: QueryOptionsJs | undefined | null): Array<Episode>
   /** Save to JSON file. */
   saveToFile(path: string): void
   /** Load from JSON file. */
@@ -21,6 +29,10 @@ export declare class AgentMemDb {
   pruneKeepNewest(n: number): number
   /** Prune to keep only the n episodes with highest reward. */
   pruneKeepHighestReward(n: number): number
+  /** Remove every episode of the given session. */
+  pruneSession(sessionId: string): number
+  /** Fill unset timestamps with the current Unix ms on store. */
+  setAutoTimestamp(enabled: boolean): void
 }
 export type AgentMemDB = AgentMemDb
 
@@ -28,12 +40,26 @@ export type AgentMemDB = AgentMemDb
 export declare class AgentMemDbDisk {
   /** Open or create a disk-backed DB at the given directory. Uses HNSW by default. */
   static open(path: string, dim: number): AgentMemDbDisk
+  /**
+   * Open or create a disk-backed DB without blocking the event loop: opening replays the
+   * log (or loads the checkpoint), which takes seconds for large DBs, so it runs on a worker
+   * thread and the promise resolves with the DB.
+   */
+  static openAsync(path: string, options: DiskOpenOptions): Promise<AgentMemDbDisk>
   /** Open with exact index and checkpoint enabled for fast restart. Call checkpoint() after stores. */
   static openExactWithCheckpoint(path: string, dim: number): AgentMemDbDisk
   /** Store an episode. */
   storeEpisode(episode: Episode): void
   /** Query for similar episodes. */
   querySimilar(embedding: Array<number>, minReward: number, topK: number, opts?: QueryOptionsJs | undefined | null): Array<Episode>
+  /** Embed storeText/queryText input with an OpenAI-compatible API (e.g. Ollama at
+   * http://localhost:11434/v1). dim must match the DB's. Requests block the calling thread.
+   */
+  useOpenAiEmbeddings(baseUrl: string, model: string, dim: number, apiKey?: string | undefined | null): void
+  /** Embed text and store it as an episode. Returns the episode id. */
+  storeText(taskId: string, text: string, reward: number): string
+  /** Like querySimilar, but embeds text first. */
+  queryText(text: string, minReward: number, topK: number, opts?: QueryOptionsJs | undefined | null): Array<Episode>
   /** Persist checkpoint for fast restart (ExactIndex only). No-op for HNSW. */
   checkpoint(): void
   /** Prune episodes with timestamp older than cutoff (Unix ms). */
@@ -42,11 +68,30 @@ export declare class AgentMemDbDisk {
   pruneKeepNewest(n: number): number
   /** Prune to keep only the n episodes with highest reward. */
   pruneKeepHighestReward(n: number): number
+  /** Remove every episode of the given session. Compacts the log. */
+  pruneSession(sessionId: string): number
+  /** Fill unset timestamps with the current Unix ms on store. */
+  setAutoTimestamp(enabled: boolean): void
 }
 export type AgentMemDBDisk = AgentMemDbDisk
 
 /** Create a new Episode. id is auto-generated. */
-export declare function createEpisode(taskId: string, stateEmbedding: Array<number>, reward: number, metadata?: any | undefined | null, timestamp?: number | undefined | null, tags?: Array<string> | undefined | null, source?: string | undefined | null, userId?: string | undefined | null): Episode
+export declare function createEpisode(taskId: string, stateEmbedding: Array<number>, reward: number, metadata?: any | undefined | null, timestamp?: number | undefined | null, tags?: Array<string> | undefined | null, source?: string | undefined | null, userId?: string | undefined | null, sessionId?: string | undefined | null): Episode
+
+/** Options for `AgentMemDBDisk.openAsync`. */
+export interface DiskOpenOptions {
+  dim: number
+  /** "hnsw" (default) or "exact". */
+  indexType?: string
+  /** HNSW capacity; defaults to 20000. */
+  maxElements?: number
+  /** Enable checkpoints for fast restart (exact index only). */
+  useCheckpoint?: boolean
+  /** Only "l2" (the default) is supported. */
+  metric?: string
+  /** Fill unset timestamps with the current Unix ms on store. */
+  autoTimestamp?: boolean
+}
 
 /** Episode for agent memory. Pass to storeEpisode. */
 export interface Episode {
@@ -59,6 +104,10 @@ export interface Episode {
   tags?: Array<string>
   source?: string
   userId?: string
+  sessionId?: string
+  /** E.g. tokens or dollars. */
+  cost?: number
+  durationMs?: number
 }
 
 /** Query options for similarity search. */
@@ -67,9 +116,20 @@ export interface QueryOptionsJs {
   topK: number
   tagsAny?: Array<string>
   tagsAll?: Array<string>
+  /** Hierarchical tags: matches a tag equal to or under any prefix, e.g. `tool/browser`. */
+  tagPrefixAny?: Array<string>
   taskIdPrefix?: string
   timeAfter?: number
   timeBefore?: number
   source?: string
   userId?: string
+  sessionId?: string
+  /** Episodes without a cost are excluded. */
+  maxCost?: number
+  /** Episodes without a duration are excluded. */
+  maxDurationMs?: number
+  /** At most this many results per taskId. */
+  maxPerTask?: number
+  /** Defaults to true; false returns episodes with an empty `stateEmbedding`. */
+  includeEmbeddings?: boolean
 }
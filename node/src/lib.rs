@@ -2,11 +2,12 @@
 
 use agent_mem_db::{
     AgentMemDB as RustAgentMemDB, AgentMemDBDisk as RustAgentMemDBDisk, DiskOptions,
-    Episode as RustEpisode, QueryOptions,
+    Episode as RustEpisode, OpenAiEmbeddings, QueryOptions,
 };
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
 use std::path::Path;
+use std::sync::Arc;
 
 fn f64_to_f32(v: Vec<f64>) -> Vec<f32> {
     v.into_iter().map(|x| x as f32).collect()
@@ -28,6 +29,10 @@ pub struct Episode {
     pub tags: Option<Vec<String>>,
     pub source: Option<String>,
     pub user_id: Option<String>,
+    pub session_id: Option<String>,
+    /// E.g. tokens or dollars.
+    pub cost: Option<f64>,
+    pub duration_ms: Option<i64>,
 }
 
 impl From<RustEpisode> for Episode {
@@ -47,6 +52,9 @@ impl From<RustEpisode> for Episode {
             tags: ep.tags,
             source: ep.source,
             user_id: ep.user_id,
+            session_id: ep.session_id,
+            cost: ep.cost.map(f64::from),
+            duration_ms: ep.duration_ms,
         }
     }
 }
@@ -60,6 +68,9 @@ impl From<Episode> for RustEpisode {
         rust.tags = ep.tags;
         rust.source = ep.source;
         rust.user_id = ep.user_id;
+        rust.session_id = ep.session_id;
+        rust.cost = ep.cost.map(|c| c as f32);
+        rust.duration_ms = ep.duration_ms;
         rust
     }
 }
@@ -71,11 +82,58 @@ pub struct QueryOptionsJs {
     pub top_k: u32,
     pub tags_any: Option<Vec<String>>,
     pub tags_all: Option<Vec<String>>,
+    /// Hierarchical tags: matches a tag equal to or under any prefix, e.g. `tool/browser`.
+    pub tag_prefix_any: Option<Vec<String>>,
     pub task_id_prefix: Option<String>,
     pub time_after: Option<i64>,
     pub time_before: Option<i64>,
     pub source: Option<String>,
     pub user_id: Option<String>,
+    pub session_id: Option<String>,
+    /// Episodes without a cost are excluded.
+    pub max_cost: Option<f64>,
+    /// Episodes without a duration are excluded.
+    pub max_duration_ms: Option<i64>,
+    /// At most this many results per taskId.
+    pub max_per_task: Option<u32>,
+    /// Defaults to true; false returns episodes with an empty `stateEmbedding`.
+    pub include_embeddings: Option<bool>,
+}
+
+/// `opts` when given (it carries its own min_reward and top_k), else no filters.
+fn query_options(min_reward: f64, top_k: u32, opts: Option<QueryOptionsJs>) -> QueryOptions {
+    opts.map(|o| {
+        let mut q = QueryOptions::new(o.min_reward as f32, o.top_k as usize);
+        q.tags_any = o.tags_any;
+        q.tags_all = o.tags_all;
+        q.tag_prefix_any = o.tag_prefix_any;
+        q.task_id_prefix = o.task_id_prefix;
+        q.time_after = o.time_after;
+        q.time_before = o.time_before;
+        q.source = o.source;
+        q.user_id = o.user_id;
+        q.session_id = o.session_id;
+        q.max_cost = o.max_cost.map(|c| c as f32);
+        q.max_duration_ms = o.max_duration_ms;
+        q.max_per_task = o.max_per_task.map(|n| (n as usize).max(1));
+        q.include_embeddings = o.include_embeddings.unwrap_or(true);
+        q
+    })
+    .unwrap_or_else(|| QueryOptions::new(min_reward as f32, top_k as usize))
+}
+
+fn openai_embedder(
+    base_url: String,
+    model: String,
+    dim: u32,
+    api_key: Option<String>,
+) -> Result<Arc<OpenAiEmbeddings>> {
+    let mut embedder = OpenAiEmbeddings::new(&base_url, model, dim as usize)
+        .map_err(|e| Error::from_reason(e.to_string()))?;
+    if let Some(key) = api_key {
+        embedder = embedder.with_api_key(key);
+    }
+    Ok(Arc::new(embedder))
 }
 
 /// In-memory agent memory DB with HNSW vector search.
@@ -136,19 +194,7 @@ impl AgentMemDB {
             .inner
             .lock()
             .map_err(|e| Error::from_reason(format!("lock: {e}")))?;
-        let query_opts = opts
-            .map(|o| {
-                let mut q = QueryOptions::new(o.min_reward as f32, o.top_k as usize);
-                q.tags_any = o.tags_any;
-                q.tags_all = o.tags_all;
-                q.task_id_prefix = o.task_id_prefix;
-                q.time_after = o.time_after;
-                q.time_before = o.time_before;
-                q.source = o.source;
-                q.user_id = o.user_id;
-                q
-            })
-            .unwrap_or_else(|| QueryOptions::new(min_reward as f32, top_k as usize));
+        let query_opts = query_options(min_reward, top_k, opts);
         let emb_f32 = f64_to_f32(embedding);
         let results = db
             .query_similar_with_options(&emb_f32, query_opts)
@@ -156,6 +202,54 @@ impl AgentMemDB {
         Ok(results.into_iter().map(Episode::from).collect())
     }
 
+    /// Embed storeText/queryText input with an OpenAI-compatible API (e.g. Ollama at
+    /// http://localhost:11434/v1). dim must match the DB's. Requests block the calling thread.
+    #[napi]
+    pub fn use_open_ai_embeddings(
+        &self,
+        base_url: String,
+        model: String,
+        dim: u32,
+        api_key: Option<String>,
+    ) -> Result<()> {
+        let embedder = openai_embedder(base_url, model, dim, api_key)?;
+        self.inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .set_embedder(embedder)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Embed text and store it as an episode. Returns the episode id.
+    #[napi]
+    pub fn store_text(&self, task_id: String, text: String, reward: f64) -> Result<String> {
+        self.inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .store_text(task_id, &text, reward as f32)
+            .map(|id| id.to_string())
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Like querySimilar, but embeds text first.
+    #[napi]
+    pub fn query_text(
+        &self,
+        text: String,
+        min_reward: f64,
+        top_k: u32,
+        opts: Option<QueryOptionsJs>,
+    ) -> Result<Vec<Episode>> {
+        let db = self
+            .inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?;
+        let results = db
+            .query_text_with_options(&text, query_options(min_reward, top_k, opts))
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok(results.into_iter().map(Episode::from).collect())
+    }
+
     /// Save to JSON file.
     #[napi]
     pub fn save_to_file(&self, path: String) -> Result<()> {
@@ -179,29 +273,121 @@ impl AgentMemDB {
 
     /// Prune episodes with timestamp older than cutoff (Unix ms). Episodes without timestamp are kept.
     #[napi]
-    pub fn prune_older_than(&self, timestamp_cutoff_ms: i64) -> u32 {
+    pub fn prune_older_than(&self, timestamp_cutoff_ms: i64) -> Result<u32> {
         self.inner
             .lock()
-            .map(|mut db| db.prune_older_than(timestamp_cutoff_ms) as u32)
-            .unwrap_or(0)
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .prune_older_than(timestamp_cutoff_ms)
+            .map(|n| n as u32)
+            .map_err(|e| Error::from_reason(e.to_string()))
     }
 
     /// Prune to keep only the n most recent episodes (by timestamp).
     #[napi]
-    pub fn prune_keep_newest(&self, n: u32) -> u32 {
+    pub fn prune_keep_newest(&self, n: u32) -> Result<u32> {
         self.inner
             .lock()
-            .map(|mut db| db.prune_keep_newest(n as usize) as u32)
-            .unwrap_or(0)
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .prune_keep_newest(n as usize)
+            .map(|n| n as u32)
+            .map_err(|e| Error::from_reason(e.to_string()))
     }
 
     /// Prune to keep only the n episodes with highest reward.
     #[napi]
-    pub fn prune_keep_highest_reward(&self, n: u32) -> u32 {
+    pub fn prune_keep_highest_reward(&self, n: u32) -> Result<u32> {
         self.inner
             .lock()
-            .map(|mut db| db.prune_keep_highest_reward(n as usize) as u32)
-            .unwrap_or(0)
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .prune_keep_highest_reward(n as usize)
+            .map(|n| n as u32)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Remove every episode of the given session.
+    #[napi]
+    pub fn prune_session(&self, session_id: String) -> Result<u32> {
+        self.inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .prune_session(&session_id)
+            .map(|n| n as u32)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Fill unset timestamps with the current Unix ms on store.
+    #[napi]
+    pub fn set_auto_timestamp(&self, enabled: bool) -> Result<()> {
+        self.inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .set_auto_timestamp(enabled);
+        Ok(())
+    }
+}
+
+/// Options for `AgentMemDBDisk.openAsync`.
+#[napi(object)]
+pub struct DiskOpenOptions {
+    pub dim: u32,
+    /// "hnsw" (default) or "exact".
+    pub index_type: Option<String>,
+    /// HNSW capacity; defaults to 20000.
+    pub max_elements: Option<u32>,
+    /// Enable checkpoints for fast restart (exact index only).
+    pub use_checkpoint: Option<bool>,
+    /// Only "l2" (the default) is supported.
+    pub metric: Option<String>,
+    /// Fill unset timestamps with the current Unix ms on store.
+    pub auto_timestamp: Option<bool>,
+}
+
+impl DiskOpenOptions {
+    fn to_disk_options(&self) -> Result<DiskOptions> {
+        let dim = self.dim as usize;
+        let mut opts = match self.index_type.as_deref().unwrap_or("hnsw") {
+            "hnsw" => DiskOptions::hnsw(dim, self.max_elements.unwrap_or(20_000) as usize),
+            "exact" => DiskOptions::exact(dim),
+            other => {
+                return Err(Error::from_reason(format!(
+                    "indexType must be \"hnsw\" or \"exact\", got {other:?}"
+                )))
+            }
+        };
+        opts.use_checkpoint = self.use_checkpoint.unwrap_or(false);
+        match self.metric.as_deref() {
+            None | Some("l2") => Ok(opts),
+            Some(other) => Err(Error::from_reason(format!(
+                "metric must be \"l2\", got {other:?}"
+            ))),
+        }
+    }
+}
+
+/// Opens a disk DB on the libuv thread pool, so log replay doesn't block the event loop.
+pub struct OpenDiskTask {
+    path: String,
+    options: DiskOpenOptions,
+}
+
+impl Task for OpenDiskTask {
+    type Output = RustAgentMemDBDisk;
+    type JsValue = AgentMemDBDisk;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let mut db = RustAgentMemDBDisk::open_with_options(
+            Path::new(&self.path),
+            self.options.to_disk_options()?,
+        )
+        .map_err(|e| Error::from_reason(e.to_string()))?;
+        db.set_auto_timestamp(self.options.auto_timestamp.unwrap_or(false));
+        Ok(db)
+    }
+
+    fn resolve(&mut self, _env: Env, db: Self::Output) -> Result<Self::JsValue> {
+        Ok(AgentMemDBDisk {
+            inner: std::sync::Mutex::new(db),
+        })
     }
 }
 
@@ -223,6 +409,14 @@ impl AgentMemDBDisk {
         })
     }
 
+    /// Open or create a disk-backed DB without blocking the event loop: opening replays the
+    /// log (or loads the checkpoint), which takes seconds for large DBs, so it runs on a worker
+    /// thread and the promise resolves with the DB.
+    #[napi(ts_return_type = "Promise<AgentMemDbDisk>")]
+    pub fn open_async(path: String, options: DiskOpenOptions) -> AsyncTask<OpenDiskTask> {
+        AsyncTask::new(OpenDiskTask { path, options })
+    }
+
     /// Open with exact index and checkpoint enabled for fast restart. Call checkpoint() after stores.
     #[napi(factory)]
     pub fn open_exact_with_checkpoint(path: String, dim: u32) -> Result<Self> {
@@ -260,19 +454,7 @@ impl AgentMemDBDisk {
             .inner
             .lock()
             .map_err(|e| Error::from_reason(format!("lock: {e}")))?;
-        let query_opts = opts
-            .map(|o| {
-                let mut q = QueryOptions::new(o.min_reward as f32, o.top_k as usize);
-                q.tags_any = o.tags_any;
-                q.tags_all = o.tags_all;
-                q.task_id_prefix = o.task_id_prefix;
-                q.time_after = o.time_after;
-                q.time_before = o.time_before;
-                q.source = o.source;
-                q.user_id = o.user_id;
-                q
-            })
-            .unwrap_or_else(|| QueryOptions::new(min_reward as f32, top_k as usize));
+        let query_opts = query_options(min_reward, top_k, opts);
         let emb_f32 = f64_to_f32(embedding);
         let results = db
             .query_similar_with_options(&emb_f32, query_opts)
@@ -280,6 +462,54 @@ impl AgentMemDBDisk {
         Ok(results.into_iter().map(Episode::from).collect())
     }
 
+    /// Embed storeText/queryText input with an OpenAI-compatible API (e.g. Ollama at
+    /// http://localhost:11434/v1). dim must match the DB's. Requests block the calling thread.
+    #[napi]
+    pub fn use_open_ai_embeddings(
+        &self,
+        base_url: String,
+        model: String,
+        dim: u32,
+        api_key: Option<String>,
+    ) -> Result<()> {
+        let embedder = openai_embedder(base_url, model, dim, api_key)?;
+        self.inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .set_embedder(embedder)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Embed text and store it as an episode. Returns the episode id.
+    #[napi]
+    pub fn store_text(&self, task_id: String, text: String, reward: f64) -> Result<String> {
+        self.inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .store_text(task_id, &text, reward as f32)
+            .map(|id| id.to_string())
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Like querySimilar, but embeds text first.
+    #[napi]
+    pub fn query_text(
+        &self,
+        text: String,
+        min_reward: f64,
+        top_k: u32,
+        opts: Option<QueryOptionsJs>,
+    ) -> Result<Vec<Episode>> {
+        let db = self
+            .inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?;
+        let results = db
+            .query_text_with_options(&text, query_options(min_reward, top_k, opts))
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok(results.into_iter().map(Episode::from).collect())
+    }
+
     /// Persist checkpoint for fast restart (ExactIndex only). No-op for HNSW.
     #[napi]
     pub fn checkpoint(&self) -> Result<()> {
@@ -322,6 +552,27 @@ impl AgentMemDBDisk {
             .map(|r| r as u32)
             .map_err(|e| Error::from_reason(e.to_string()))
     }
+
+    /// Remove every episode of the given session. Compacts the log.
+    #[napi]
+    pub fn prune_session(&self, session_id: String) -> Result<u32> {
+        self.inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .prune_session(&session_id)
+            .map(|r| r as u32)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Fill unset timestamps with the current Unix ms on store.
+    #[napi]
+    pub fn set_auto_timestamp(&self, enabled: bool) -> Result<()> {
+        self.inner
+            .lock()
+            .map_err(|e| Error::from_reason(format!("lock: {e}")))?
+            .set_auto_timestamp(enabled);
+        Ok(())
+    }
 }
 
 /// Create a new Episode. id is auto-generated.
@@ -335,6 +586,7 @@ pub fn create_episode(
     tags: Option<Vec<String>>,
     source: Option<String>,
     user_id: Option<String>,
+    session_id: Option<String>,
 ) -> Episode {
     let mut rust = RustEpisode::new(
         task_id.clone(),
@@ -346,6 +598,7 @@ pub fn create_episode(
     rust.tags = tags.clone();
     rust.source = source.clone();
     rust.user_id = user_id.clone();
+    rust.session_id = session_id.clone();
     Episode {
         id: rust.id.to_string(),
         task_id,
@@ -360,5 +613,8 @@ pub fn create_episode(
         tags,
         source,
         user_id,
+        session_id,
+        cost: None,
+        duration_ms: None,
     }
 }
@@ -58,6 +58,18 @@ assert(removed3 === 1, `prune_keep_highest_reward: expected 1 removed, got ${rem
 const afterReward = db5.querySimilar(Array(dim).fill(0.1), 0.0, 5);
 assert(afterReward.length === 2, `prune_keep_highest_reward: expected 2, got ${afterReward.length}`);
 
+// Session scoping
+const db6 = new AgentMemDb(dim);
+db6.storeEpisode(createEpisode('a', Array(dim).fill(0.1), 0.9, null, null, null, null, null, 's1'));
+db6.storeEpisode(createEpisode('b', Array(dim).fill(0.1), 0.9, null, null, null, null, null, 's2'));
+db6.storeEpisode(createEpisode('c', Array(dim).fill(0.1), 0.9, null, null, null, null, null, 's1'));
+const inSession = db6.querySimilar(Array(dim).fill(0.1), 0.0, 5, { minReward: 0.0, topK: 5, sessionId: 's1' });
+assert(inSession.length === 2 && inSession.every((e) => e.sessionId === 's1'), 'session filter should return s1 only');
+const removed4 = db6.pruneSession('s1');
+assert(removed4 === 2, `prune_session: expected 2 removed, got ${removed4}`);
+const afterSession = db6.querySimilar(Array(dim).fill(0.1), 0.0, 5);
+assert(afterSession.length === 1 && afterSession[0].taskId === 'b', 'prune_session should keep only b');
+
 // AgentMemDBDisk with checkpoint
 const path2 = require('path').join(require('os').tmpdir(), `agent_mem_db_disk_test_${Date.now()}`);
 const diskDb = AgentMemDbDisk.openExactWithCheckpoint(path2, dim);
@@ -69,4 +81,23 @@ const diskHits = diskDb2.querySimilar(Array(dim).fill(0.1), 0.5, 5);
 assert(diskHits.length === 2, `disk checkpoint: expected 2 hits, got ${diskHits.length}`);
 require('fs').rmSync(path2, { recursive: true, force: true });
 
-console.log('All Node tests passed.');
+// openAsync: log replay runs off the event loop
+(async () => {
+  const path3 = require('path').join(require('os').tmpdir(), `agent_mem_db_disk_async_test_${Date.now()}`);
+  const seeded = AgentMemDbDisk.openExactWithCheckpoint(path3, dim);
+  seeded.storeEpisode(createEpisode('t1', Array(dim).fill(0.1), 0.7));
+  const opened = await AgentMemDbDisk.openAsync(path3, { dim, indexType: 'exact', useCheckpoint: true });
+  const asyncHits = opened.querySimilar(Array(dim).fill(0.1), 0.0, 5);
+  assert(asyncHits.length === 1 && asyncHits[0].taskId === 't1', 'openAsync should replay the log');
+  const hnsw = await AgentMemDbDisk.openAsync(require('path').join(path3, 'hnsw'), { dim, maxElements: 100 });
+  hnsw.storeEpisode(createEpisode('t2', Array(dim).fill(0.2), 0.5));
+  assert(hnsw.querySimilar(Array(dim).fill(0.2), 0.0, 1)[0].taskId === 't2', 'openAsync hnsw');
+  const rejected = await AgentMemDbDisk.openAsync(path3, { dim, metric: 'cosine' }).then(() => false, () => true);
+  assert(rejected, 'openAsync should reject an unsupported metric');
+  require('fs').rmSync(path3, { recursive: true, force: true });
+
+  console.log('All Node tests passed.');
+})().catch((e) => {
+  console.error(e);
+  process.exit(1);
+});
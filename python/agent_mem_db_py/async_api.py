@@ -43,11 +43,14 @@ class AgentMemDBAsync:
         *,
         tags_any: Optional[List[str]] = None,
         tags_all: Optional[List[str]] = None,
+        tag_prefix_any: Optional[List[str]] = None,
         task_id_prefix: Optional[str] = None,
         time_after: Optional[int] = None,
         time_before: Optional[int] = None,
         source: Optional[str] = None,
         user_id: Optional[str] = None,
+        session_id: Optional[str] = None,
+        include_embeddings: bool = True,
     ) -> List["Episode"]:
         """Query similar episodes without blocking the event loop."""
         return await _to_thread(
@@ -57,11 +60,14 @@ class AgentMemDBAsync:
             top_k,
             tags_any=tags_any,
             tags_all=tags_all,
+            tag_prefix_any=tag_prefix_any,
             task_id_prefix=task_id_prefix,
             time_after=time_after,
             time_before=time_before,
             source=source,
             user_id=user_id,
+            session_id=session_id,
+            include_embeddings=include_embeddings,
         )
 
     async def save_to_file_async(self, path: str) -> None:
@@ -82,6 +88,10 @@ class AgentMemDBAsync:
         """Prune to keep only n highest-reward episodes without blocking the event loop."""
         return await _to_thread(self._db.prune_keep_highest_reward, n)
 
+    async def prune_session_async(self, session_id: str) -> int:
+        """Remove every episode of a session without blocking the event loop."""
+        return await _to_thread(self._db.prune_session, session_id)
+
     @staticmethod
     async def load_from_file_async(path: str) -> "AgentMemDB":
         """Load DB from file without blocking the event loop."""
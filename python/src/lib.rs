@@ -1,6 +1,7 @@
 use agent_mem_db::{
-    AgentMemDB as RustAgentMemDB, AgentMemDBDisk as RustAgentMemDBDisk, DiskOptions,
-    Episode as RustEpisode, QueryOptions,
+    AccessSummary, AgentMemDB as RustAgentMemDB, AgentMemDBDisk as RustAgentMemDBDisk,
+    AgentMemError, CostSummary, DiskOptions, EmbeddingProvider, Episode as RustEpisode, HnswParams,
+    QueryOptions,
 };
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
@@ -8,6 +9,7 @@ use pyo3::types::PyAny;
 use pyo3::types::PyType;
 use serde_json::Value as JsonValue;
 use std::path::Path;
+use std::sync::Arc;
 
 #[pyclass]
 pub struct Episode {
@@ -29,12 +31,18 @@ pub struct Episode {
     pub source: Option<String>,
     #[pyo3(get, set)]
     pub user_id: Option<String>,
+    #[pyo3(get, set)]
+    pub session_id: Option<String>,
+    #[pyo3(get, set)]
+    pub cost: Option<f32>,
+    #[pyo3(get, set)]
+    pub duration_ms: Option<i64>,
 }
 
 #[pymethods]
 impl Episode {
     #[new]
-    #[pyo3(signature = (task_id, state_embedding, reward, metadata=None, timestamp=None, tags=None, source=None, user_id=None))]
+    #[pyo3(signature = (task_id, state_embedding, reward, metadata=None, timestamp=None, tags=None, source=None, user_id=None, session_id=None, cost=None, duration_ms=None))]
     fn new(
         task_id: String,
         state_embedding: Vec<f32>,
@@ -44,6 +52,9 @@ impl Episode {
         tags: Option<Vec<String>>,
         source: Option<String>,
         user_id: Option<String>,
+        session_id: Option<String>,
+        cost: Option<f32>,
+        duration_ms: Option<i64>,
     ) -> Self {
         let rust_ep = RustEpisode::new(task_id.clone(), state_embedding.clone(), reward);
         let mut ep = Episode {
@@ -56,11 +67,17 @@ impl Episode {
             tags: None,
             source: None,
             user_id: None,
+            session_id: None,
+            cost: None,
+            duration_ms: None,
         };
         ep.timestamp = timestamp;
         ep.tags = tags;
         ep.source = source;
         ep.user_id = user_id;
+        ep.session_id = session_id;
+        ep.cost = cost;
+        ep.duration_ms = duration_ms;
         ep
     }
 }
@@ -98,6 +115,9 @@ fn rust_episode_to_py(py: Python, ep: &agent_mem_db::Episode) -> PyResult<Episod
         tags: ep.tags.clone(),
         source: ep.source.clone(),
         user_id: ep.user_id.clone(),
+        session_id: ep.session_id.clone(),
+        cost: ep.cost,
+        duration_ms: ep.duration_ms,
     })
 }
 
@@ -109,6 +129,143 @@ fn results_to_py(py: Python, results: Vec<agent_mem_db::Episode>) -> PyResult<Ve
     Ok(out)
 }
 
+fn py_episode_to_rust(py: Python, episode: &Episode) -> PyResult<RustEpisode> {
+    let mut rust_ep = RustEpisode::new(
+        episode.task_id.clone(),
+        episode.state_embedding.clone(),
+        episode.reward,
+    );
+    if let Some(meta) = &episode.metadata {
+        rust_ep.metadata = pyobj_to_json(py, meta.as_ref(py))?;
+    }
+    rust_ep.timestamp = episode.timestamp;
+    rust_ep.tags = episode.tags.clone();
+    rust_ep.source = episode.source.clone();
+    rust_ep.user_id = episode.user_id.clone();
+    rust_ep.session_id = episode.session_id.clone();
+    rust_ep.cost = episode.cost;
+    rust_ep.duration_ms = episode.duration_ms;
+    Ok(rust_ep)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn query_options(
+    min_reward: f32,
+    top_k: usize,
+    tags_any: Option<Vec<String>>,
+    tags_all: Option<Vec<String>>,
+    tag_prefix_any: Option<Vec<String>>,
+    task_id_prefix: Option<String>,
+    time_after: Option<i64>,
+    time_before: Option<i64>,
+    source: Option<String>,
+    user_id: Option<String>,
+    session_id: Option<String>,
+) -> QueryOptions {
+    let mut opts = QueryOptions::new(min_reward, top_k);
+    opts.tags_any = tags_any;
+    opts.tags_all = tags_all;
+    opts.tag_prefix_any = tag_prefix_any;
+    opts.task_id_prefix = task_id_prefix;
+    opts.time_after = time_after;
+    opts.time_before = time_before;
+    opts.source = source;
+    opts.user_id = user_id;
+    opts.session_id = session_id;
+    opts
+}
+
+/// Options matching every episode that passes the given filters (any reward), for
+/// delete_where and count. `None` when no filter is set.
+#[allow(clippy::too_many_arguments)]
+fn filter_options(
+    tags_any: Option<Vec<String>>,
+    tags_all: Option<Vec<String>>,
+    tag_prefix_any: Option<Vec<String>>,
+    task_id_prefix: Option<String>,
+    time_after: Option<i64>,
+    time_before: Option<i64>,
+    source: Option<String>,
+    user_id: Option<String>,
+    session_id: Option<String>,
+) -> Option<QueryOptions> {
+    let opts = query_options(
+        f32::NEG_INFINITY,
+        0,
+        tags_any.filter(|t| !t.is_empty()),
+        tags_all.filter(|t| !t.is_empty()),
+        tag_prefix_any.filter(|p| !p.is_empty()),
+        task_id_prefix,
+        time_after,
+        time_before,
+        source,
+        user_id,
+        session_id,
+    );
+    let any_set = opts.tags_any.is_some()
+        || opts.tags_all.is_some()
+        || opts.tag_prefix_any.is_some()
+        || opts.task_id_prefix.is_some()
+        || opts.time_after.is_some()
+        || opts.time_before.is_some()
+        || opts.source.is_some()
+        || opts.user_id.is_some()
+        || opts.session_id.is_some();
+    any_set.then_some(opts)
+}
+
+fn no_filter_error() -> PyErr {
+    PyValueError::new_err(
+        "delete_where needs at least one filter (tags_any, tags_all, tag_prefix_any, task_id_prefix, time_after, time_before, source, user_id, session_id)",
+    )
+}
+
+/// Episode count, dim, retrievals, and cost and duration totals, as a dict.
+fn stats_to_py(
+    py: Python,
+    backend: &str,
+    dim: usize,
+    episodes: usize,
+    access: AccessSummary,
+    cost: CostSummary,
+) -> PyResult<PyObject> {
+    json_to_pyobject(
+        py,
+        &serde_json::json!({
+            "backend": backend,
+            "dim": dim,
+            "episodes": episodes,
+            "total_retrievals": access.total_retrievals,
+            "never_retrieved": access.never_retrieved,
+            "total_cost": cost.total_cost,
+            "mean_cost": cost.mean_cost,
+            "total_duration_ms": cost.total_duration_ms,
+            "mean_duration_ms": cost.mean_duration_ms,
+        }),
+    )
+}
+
+/// A Python callable `embed(text: str) -> list[float]` used as the DB's embedding provider.
+struct PyEmbedder {
+    embed: PyObject,
+    dim: usize,
+}
+
+impl EmbeddingProvider for PyEmbedder {
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, AgentMemError> {
+        Python::with_gil(|py| {
+            self.embed
+                .call1(py, (text,))
+                .and_then(|v| v.extract::<Vec<f32>>(py))
+        })
+        .map_err(|e| AgentMemError::Embedding(e.to_string()))
+    }
+}
+
 #[pyclass]
 pub struct AgentMemDB {
     db: RustAgentMemDB,
@@ -116,11 +273,23 @@ pub struct AgentMemDB {
 
 #[pymethods]
 impl AgentMemDB {
+    /// `auto_timestamp=True` sets `timestamp` to the current Unix ms on episodes stored
+    /// without one.
     #[new]
-    fn new(dim: usize) -> Self {
-        AgentMemDB {
-            db: RustAgentMemDB::new(dim),
-        }
+    #[pyo3(signature = (dim, auto_timestamp=false))]
+    fn new(dim: usize, auto_timestamp: bool) -> Self {
+        let mut db = RustAgentMemDB::new(dim);
+        db.set_auto_timestamp(auto_timestamp);
+        AgentMemDB { db }
+    }
+
+    /// Fill unset timestamps with the current Unix ms on store.
+    fn set_auto_timestamp(&mut self, enabled: bool) {
+        self.db.set_auto_timestamp(enabled);
+    }
+
+    fn auto_timestamp(&self) -> bool {
+        self.db.auto_timestamp()
     }
 
     #[classmethod]
@@ -139,25 +308,56 @@ impl AgentMemDB {
     }
 
     fn store_episode(&mut self, py: Python, episode: &Episode) -> PyResult<()> {
-        let mut rust_ep = RustEpisode::new(
-            episode.task_id.clone(),
-            episode.state_embedding.clone(),
-            episode.reward,
-        );
-        if let Some(meta) = &episode.metadata {
-            let meta_val = pyobj_to_json(py, meta.as_ref(py))?;
-            rust_ep.metadata = meta_val;
-        }
-        rust_ep.timestamp = episode.timestamp;
-        rust_ep.tags = episode.tags.clone();
-        rust_ep.source = episode.source.clone();
-        rust_ep.user_id = episode.user_id.clone();
+        let rust_ep = py_episode_to_rust(py, episode)?;
         self.db
             .store_episode(rust_ep)
             .map_err(|e| PyValueError::new_err(format!("{e}")))
     }
 
-    #[pyo3(signature = (state_embedding, min_reward, top_k, tags_any=None, tags_all=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None))]
+    /// Attach an embedding function `embed(text) -> list[float]` of output size `dim` (must
+    /// match the DB's) for store_text and query_text.
+    fn set_embedder(&mut self, embed: PyObject, dim: usize) -> PyResult<()> {
+        self.db
+            .set_embedder(Arc::new(PyEmbedder { embed, dim }))
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
+    }
+
+    /// Embed text with the attached embedder and store it as an episode. Returns the episode id.
+    #[pyo3(signature = (task_id, text, reward, metadata=None, timestamp=None, tags=None, source=None, user_id=None, session_id=None))]
+    fn store_text(
+        &mut self,
+        py: Python,
+        task_id: String,
+        text: &str,
+        reward: f32,
+        metadata: Option<PyObject>,
+        timestamp: Option<i64>,
+        tags: Option<Vec<String>>,
+        source: Option<String>,
+        user_id: Option<String>,
+        session_id: Option<String>,
+    ) -> PyResult<String> {
+        let episode = Episode::new(
+            task_id,
+            Vec::new(),
+            reward,
+            metadata,
+            timestamp,
+            tags,
+            source,
+            user_id,
+            session_id,
+            None,
+            None,
+        );
+        let rust_ep = py_episode_to_rust(py, &episode)?;
+        self.db
+            .store_episode_with_text(rust_ep, text)
+            .map(|id| id.to_string())
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
+    }
+
+    #[pyo3(signature = (state_embedding, min_reward, top_k, tags_any=None, tags_all=None, tag_prefix_any=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None, session_id=None, include_embeddings=true))]
     fn query_similar(
         &self,
         py: Python,
@@ -166,34 +366,29 @@ impl AgentMemDB {
         top_k: usize,
         tags_any: Option<Vec<String>>,
         tags_all: Option<Vec<String>>,
+        tag_prefix_any: Option<Vec<String>>,
         task_id_prefix: Option<String>,
         time_after: Option<i64>,
         time_before: Option<i64>,
         source: Option<String>,
         user_id: Option<String>,
+        session_id: Option<String>,
+        include_embeddings: bool,
     ) -> PyResult<Vec<Episode>> {
-        let mut opts = QueryOptions::new(min_reward, top_k);
-        if let Some(tags) = tags_any {
-            opts.tags_any = Some(tags);
-        }
-        if let Some(tags) = tags_all {
-            opts.tags_all = Some(tags);
-        }
-        if let Some(prefix) = task_id_prefix {
-            opts.task_id_prefix = Some(prefix);
-        }
-        if let Some(ts) = time_after {
-            opts.time_after = Some(ts);
-        }
-        if let Some(ts) = time_before {
-            opts.time_before = Some(ts);
-        }
-        if let Some(s) = source {
-            opts.source = Some(s);
-        }
-        if let Some(u) = user_id {
-            opts.user_id = Some(u);
-        }
+        let opts = query_options(
+            min_reward,
+            top_k,
+            tags_any,
+            tags_all,
+            tag_prefix_any,
+            task_id_prefix,
+            time_after,
+            time_before,
+            source,
+            user_id,
+            session_id,
+        )
+        .include_embeddings(include_embeddings);
         let results = self
             .db
             .query_similar_with_options(&state_embedding, opts)
@@ -201,6 +396,46 @@ impl AgentMemDB {
         results_to_py(py, results)
     }
 
+    /// Like query_similar, but embeds `text` with the attached embedder.
+    #[pyo3(signature = (text, min_reward, top_k, tags_any=None, tags_all=None, tag_prefix_any=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None, session_id=None, include_embeddings=true))]
+    fn query_text(
+        &self,
+        py: Python,
+        text: &str,
+        min_reward: f32,
+        top_k: usize,
+        tags_any: Option<Vec<String>>,
+        tags_all: Option<Vec<String>>,
+        tag_prefix_any: Option<Vec<String>>,
+        task_id_prefix: Option<String>,
+        time_after: Option<i64>,
+        time_before: Option<i64>,
+        source: Option<String>,
+        user_id: Option<String>,
+        session_id: Option<String>,
+        include_embeddings: bool,
+    ) -> PyResult<Vec<Episode>> {
+        let opts = query_options(
+            min_reward,
+            top_k,
+            tags_any,
+            tags_all,
+            tag_prefix_any,
+            task_id_prefix,
+            time_after,
+            time_before,
+            source,
+            user_id,
+            session_id,
+        )
+        .include_embeddings(include_embeddings);
+        let results = self
+            .db
+            .query_text_with_options(text, opts)
+            .map_err(|e| PyValueError::new_err(format!("{e}")))?;
+        results_to_py(py, results)
+    }
+
     fn save_to_file(&self, path: &str) -> PyResult<()> {
         self.db
             .save_to_file(Path::new(path))
@@ -215,18 +450,106 @@ impl AgentMemDB {
     }
 
     /// Prune episodes with timestamp older than cutoff (Unix ms). Episodes without timestamp are kept.
-    fn prune_older_than(&mut self, timestamp_cutoff_ms: i64) -> usize {
-        self.db.prune_older_than(timestamp_cutoff_ms)
+    fn prune_older_than(&mut self, timestamp_cutoff_ms: i64) -> PyResult<usize> {
+        self.db
+            .prune_older_than(timestamp_cutoff_ms)
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
     }
 
     /// Prune to keep only the n most recent episodes (by timestamp). Episodes without timestamp are treated as oldest.
-    fn prune_keep_newest(&mut self, n: usize) -> usize {
-        self.db.prune_keep_newest(n)
+    fn prune_keep_newest(&mut self, n: usize) -> PyResult<usize> {
+        self.db
+            .prune_keep_newest(n)
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
     }
 
     /// Prune to keep only the n episodes with highest reward.
-    fn prune_keep_highest_reward(&mut self, n: usize) -> usize {
-        self.db.prune_keep_highest_reward(n)
+    fn prune_keep_highest_reward(&mut self, n: usize) -> PyResult<usize> {
+        self.db
+            .prune_keep_highest_reward(n)
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
+    }
+
+    /// Remove every episode of the given session.
+    fn prune_session(&mut self, session_id: &str) -> PyResult<usize> {
+        self.db
+            .prune_session(session_id)
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
+    }
+
+    /// Remove every episode matching the keyword filters (at least one is required), e.g.
+    /// `delete_where(user_id="u1")` to erase a user's memories. Soft-deleted episodes go too.
+    /// Returns episodes removed.
+    #[pyo3(signature = (*, tags_any=None, tags_all=None, tag_prefix_any=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None, session_id=None))]
+    fn delete_where(
+        &mut self,
+        tags_any: Option<Vec<String>>,
+        tags_all: Option<Vec<String>>,
+        tag_prefix_any: Option<Vec<String>>,
+        task_id_prefix: Option<String>,
+        time_after: Option<i64>,
+        time_before: Option<i64>,
+        source: Option<String>,
+        user_id: Option<String>,
+        session_id: Option<String>,
+    ) -> PyResult<usize> {
+        let opts = filter_options(
+            tags_any,
+            tags_all,
+            tag_prefix_any,
+            task_id_prefix,
+            time_after,
+            time_before,
+            source,
+            user_id,
+            session_id,
+        )
+        .ok_or_else(no_filter_error)?
+        .include_deleted(true);
+        Ok(self.db.delete_where(|ep| opts.matches(ep)))
+    }
+
+    /// Number of episodes matching the keyword filters (all episodes when none is given).
+    #[pyo3(signature = (*, tags_any=None, tags_all=None, tag_prefix_any=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None, session_id=None))]
+    fn count(
+        &self,
+        tags_any: Option<Vec<String>>,
+        tags_all: Option<Vec<String>>,
+        tag_prefix_any: Option<Vec<String>>,
+        task_id_prefix: Option<String>,
+        time_after: Option<i64>,
+        time_before: Option<i64>,
+        source: Option<String>,
+        user_id: Option<String>,
+        session_id: Option<String>,
+    ) -> usize {
+        match filter_options(
+            tags_any,
+            tags_all,
+            tag_prefix_any,
+            task_id_prefix,
+            time_after,
+            time_before,
+            source,
+            user_id,
+            session_id,
+        ) {
+            Some(opts) => self.db.iter().filter(|ep| opts.matches(ep)).count(),
+            None => self.db.len(),
+        }
+    }
+
+    /// Dict of `backend`, `dim`, `episodes`, `total_retrievals`, `never_retrieved`,
+    /// `total_cost`, `mean_cost`, `total_duration_ms` and `mean_duration_ms`.
+    fn stats(&self, py: Python) -> PyResult<PyObject> {
+        stats_to_py(
+            py,
+            "memory",
+            self.db.dim(),
+            self.db.len(),
+            self.db.access_summary(),
+            self.db.cost_summary(),
+        )
     }
 }
 
@@ -257,6 +580,92 @@ impl AgentMemDBDisk {
         Ok(AgentMemDBDisk { db })
     }
 
+    /// Open or create with the full set of disk options. `index_type` is "hnsw" (sized for
+    /// `max_elements`, graph built with `hnsw_m` / `ef_construction` / `ef_search`) or "exact".
+    /// `use_checkpoint` enables checkpoints (exact only); `auto_checkpoint_every_n` /
+    /// `auto_checkpoint_every_ms` make them automatic. `sync` is "always" (fsync each write)
+    /// or "group" (one fsync per `group_commit_max_records` writes or `group_commit_window_ms`;
+    /// call flush() at quiet points). Only the "l2" metric is supported. `auto_timestamp`
+    /// fills unset timestamps with the current Unix ms on store.
+    #[classmethod]
+    #[pyo3(signature = (path, dim, index_type="hnsw", max_elements=20_000, hnsw_m=None, ef_construction=None, ef_search=None, use_checkpoint=false, auto_checkpoint_every_n=None, auto_checkpoint_every_ms=None, sync="always", group_commit_window_ms=10, group_commit_max_records=64, metric="l2", auto_timestamp=false))]
+    fn open_with_options(
+        _cls: &PyType,
+        path: &str,
+        dim: usize,
+        index_type: &str,
+        max_elements: usize,
+        hnsw_m: Option<usize>,
+        ef_construction: Option<usize>,
+        ef_search: Option<usize>,
+        use_checkpoint: bool,
+        auto_checkpoint_every_n: Option<usize>,
+        auto_checkpoint_every_ms: Option<u64>,
+        sync: &str,
+        group_commit_window_ms: u64,
+        group_commit_max_records: usize,
+        metric: &str,
+        auto_timestamp: bool,
+    ) -> PyResult<Self> {
+        let mut opts = match index_type {
+            "hnsw" => {
+                let defaults = HnswParams::default();
+                DiskOptions::hnsw(dim, max_elements).with_hnsw_params(HnswParams {
+                    m: hnsw_m.unwrap_or(defaults.m),
+                    ef_construction: ef_construction.unwrap_or(defaults.ef_construction),
+                    ef_search: ef_search.unwrap_or(defaults.ef_search),
+                })
+            }
+            "exact" => DiskOptions::exact(dim),
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "index_type must be \"hnsw\" or \"exact\", got {other:?}"
+                )))
+            }
+        };
+        opts.use_checkpoint = use_checkpoint;
+        if auto_checkpoint_every_n.is_some() || auto_checkpoint_every_ms.is_some() {
+            opts = opts.auto_checkpoint(
+                auto_checkpoint_every_n.unwrap_or(0),
+                auto_checkpoint_every_ms.unwrap_or(0),
+            );
+        }
+        match sync {
+            "always" => {}
+            "group" => opts = opts.group_commit(group_commit_window_ms, group_commit_max_records),
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "sync must be \"always\" or \"group\", got {other:?}"
+                )))
+            }
+        }
+        if metric != "l2" {
+            return Err(PyValueError::new_err(format!(
+                "metric must be \"l2\", got {metric:?}"
+            )));
+        }
+        let mut db = RustAgentMemDBDisk::open_with_options(Path::new(path), opts)
+            .map_err(|e| PyValueError::new_err(format!("{e}")))?;
+        db.set_auto_timestamp(auto_timestamp);
+        Ok(AgentMemDBDisk { db })
+    }
+
+    /// Fill unset timestamps with the current Unix ms on store.
+    fn set_auto_timestamp(&mut self, enabled: bool) {
+        self.db.set_auto_timestamp(enabled);
+    }
+
+    fn auto_timestamp(&self) -> bool {
+        self.db.auto_timestamp()
+    }
+
+    /// Write single writes buffered by sync="group". No-op otherwise.
+    fn flush(&mut self) -> PyResult<()> {
+        self.db
+            .flush()
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
+    }
+
     /// Persist checkpoint for fast restart (ExactIndex only). No-op for HNSW.
     fn checkpoint(&mut self) -> PyResult<()> {
         self.db
@@ -265,25 +674,56 @@ impl AgentMemDBDisk {
     }
 
     fn store_episode(&mut self, py: Python, episode: &Episode) -> PyResult<()> {
-        let mut rust_ep = RustEpisode::new(
-            episode.task_id.clone(),
-            episode.state_embedding.clone(),
-            episode.reward,
-        );
-        if let Some(meta) = &episode.metadata {
-            let meta_val = pyobj_to_json(py, meta.as_ref(py))?;
-            rust_ep.metadata = meta_val;
-        }
-        rust_ep.timestamp = episode.timestamp;
-        rust_ep.tags = episode.tags.clone();
-        rust_ep.source = episode.source.clone();
-        rust_ep.user_id = episode.user_id.clone();
+        let rust_ep = py_episode_to_rust(py, episode)?;
         self.db
             .store_episode(rust_ep)
             .map_err(|e| PyValueError::new_err(format!("{e}")))
     }
 
-    #[pyo3(signature = (state_embedding, min_reward, top_k, tags_any=None, tags_all=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None))]
+    /// Attach an embedding function `embed(text) -> list[float]` of output size `dim` (must
+    /// match the DB's) for store_text and query_text.
+    fn set_embedder(&mut self, embed: PyObject, dim: usize) -> PyResult<()> {
+        self.db
+            .set_embedder(Arc::new(PyEmbedder { embed, dim }))
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
+    }
+
+    /// Embed text with the attached embedder and store it as an episode. Returns the episode id.
+    #[pyo3(signature = (task_id, text, reward, metadata=None, timestamp=None, tags=None, source=None, user_id=None, session_id=None))]
+    fn store_text(
+        &mut self,
+        py: Python,
+        task_id: String,
+        text: &str,
+        reward: f32,
+        metadata: Option<PyObject>,
+        timestamp: Option<i64>,
+        tags: Option<Vec<String>>,
+        source: Option<String>,
+        user_id: Option<String>,
+        session_id: Option<String>,
+    ) -> PyResult<String> {
+        let episode = Episode::new(
+            task_id,
+            Vec::new(),
+            reward,
+            metadata,
+            timestamp,
+            tags,
+            source,
+            user_id,
+            session_id,
+            None,
+            None,
+        );
+        let rust_ep = py_episode_to_rust(py, &episode)?;
+        self.db
+            .store_episode_with_text(rust_ep, text)
+            .map(|id| id.to_string())
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
+    }
+
+    #[pyo3(signature = (state_embedding, min_reward, top_k, tags_any=None, tags_all=None, tag_prefix_any=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None, session_id=None, include_embeddings=true))]
     fn query_similar(
         &self,
         py: Python,
@@ -292,34 +732,29 @@ impl AgentMemDBDisk {
         top_k: usize,
         tags_any: Option<Vec<String>>,
         tags_all: Option<Vec<String>>,
+        tag_prefix_any: Option<Vec<String>>,
         task_id_prefix: Option<String>,
         time_after: Option<i64>,
         time_before: Option<i64>,
         source: Option<String>,
         user_id: Option<String>,
+        session_id: Option<String>,
+        include_embeddings: bool,
     ) -> PyResult<Vec<Episode>> {
-        let mut opts = QueryOptions::new(min_reward, top_k);
-        if let Some(tags) = tags_any {
-            opts.tags_any = Some(tags);
-        }
-        if let Some(tags) = tags_all {
-            opts.tags_all = Some(tags);
-        }
-        if let Some(prefix) = task_id_prefix {
-            opts.task_id_prefix = Some(prefix);
-        }
-        if let Some(ts) = time_after {
-            opts.time_after = Some(ts);
-        }
-        if let Some(ts) = time_before {
-            opts.time_before = Some(ts);
-        }
-        if let Some(s) = source {
-            opts.source = Some(s);
-        }
-        if let Some(u) = user_id {
-            opts.user_id = Some(u);
-        }
+        let opts = query_options(
+            min_reward,
+            top_k,
+            tags_any,
+            tags_all,
+            tag_prefix_any,
+            task_id_prefix,
+            time_after,
+            time_before,
+            source,
+            user_id,
+            session_id,
+        )
+        .include_embeddings(include_embeddings);
         let results = self
             .db
             .query_similar_with_options(&state_embedding, opts)
@@ -327,6 +762,46 @@ impl AgentMemDBDisk {
         results_to_py(py, results)
     }
 
+    /// Like query_similar, but embeds `text` with the attached embedder.
+    #[pyo3(signature = (text, min_reward, top_k, tags_any=None, tags_all=None, tag_prefix_any=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None, session_id=None, include_embeddings=true))]
+    fn query_text(
+        &self,
+        py: Python,
+        text: &str,
+        min_reward: f32,
+        top_k: usize,
+        tags_any: Option<Vec<String>>,
+        tags_all: Option<Vec<String>>,
+        tag_prefix_any: Option<Vec<String>>,
+        task_id_prefix: Option<String>,
+        time_after: Option<i64>,
+        time_before: Option<i64>,
+        source: Option<String>,
+        user_id: Option<String>,
+        session_id: Option<String>,
+        include_embeddings: bool,
+    ) -> PyResult<Vec<Episode>> {
+        let opts = query_options(
+            min_reward,
+            top_k,
+            tags_any,
+            tags_all,
+            tag_prefix_any,
+            task_id_prefix,
+            time_after,
+            time_before,
+            source,
+            user_id,
+            session_id,
+        )
+        .include_embeddings(include_embeddings);
+        let results = self
+            .db
+            .query_text_with_options(text, opts)
+            .map_err(|e| PyValueError::new_err(format!("{e}")))?;
+        results_to_py(py, results)
+    }
+
     /// Prune episodes with timestamp older than cutoff (Unix ms). Episodes without timestamp are kept. Compacts the log.
     fn prune_older_than(&mut self, timestamp_cutoff_ms: i64) -> PyResult<usize> {
         self.db
@@ -347,6 +822,90 @@ impl AgentMemDBDisk {
             .prune_keep_highest_reward(n)
             .map_err(|e| PyValueError::new_err(format!("{e}")))
     }
+
+    /// Remove every episode of the given session. Compacts the log.
+    fn prune_session(&mut self, session_id: &str) -> PyResult<usize> {
+        self.db
+            .prune_session(session_id)
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
+    }
+
+    /// Remove every episode matching the keyword filters (at least one is required), e.g.
+    /// `delete_where(user_id="u1")` to erase a user's memories. Soft-deleted episodes go too.
+    /// Compacts the log. Returns episodes removed.
+    #[pyo3(signature = (*, tags_any=None, tags_all=None, tag_prefix_any=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None, session_id=None))]
+    fn delete_where(
+        &mut self,
+        tags_any: Option<Vec<String>>,
+        tags_all: Option<Vec<String>>,
+        tag_prefix_any: Option<Vec<String>>,
+        task_id_prefix: Option<String>,
+        time_after: Option<i64>,
+        time_before: Option<i64>,
+        source: Option<String>,
+        user_id: Option<String>,
+        session_id: Option<String>,
+    ) -> PyResult<usize> {
+        let opts = filter_options(
+            tags_any,
+            tags_all,
+            tag_prefix_any,
+            task_id_prefix,
+            time_after,
+            time_before,
+            source,
+            user_id,
+            session_id,
+        )
+        .ok_or_else(no_filter_error)?
+        .include_deleted(true);
+        self.db
+            .delete_where(|ep| opts.matches(ep))
+            .map_err(|e| PyValueError::new_err(format!("{e}")))
+    }
+
+    /// Number of episodes matching the keyword filters (all episodes when none is given).
+    #[pyo3(signature = (*, tags_any=None, tags_all=None, tag_prefix_any=None, task_id_prefix=None, time_after=None, time_before=None, source=None, user_id=None, session_id=None))]
+    fn count(
+        &self,
+        tags_any: Option<Vec<String>>,
+        tags_all: Option<Vec<String>>,
+        tag_prefix_any: Option<Vec<String>>,
+        task_id_prefix: Option<String>,
+        time_after: Option<i64>,
+        time_before: Option<i64>,
+        source: Option<String>,
+        user_id: Option<String>,
+        session_id: Option<String>,
+    ) -> usize {
+        match filter_options(
+            tags_any,
+            tags_all,
+            tag_prefix_any,
+            task_id_prefix,
+            time_after,
+            time_before,
+            source,
+            user_id,
+            session_id,
+        ) {
+            Some(opts) => self.db.iter().filter(|ep| opts.matches(ep)).count(),
+            None => self.db.len(),
+        }
+    }
+
+    /// Dict of `backend`, `dim`, `episodes`, `total_retrievals`, `never_retrieved`,
+    /// `total_cost`, `mean_cost`, `total_duration_ms` and `mean_duration_ms`.
+    fn stats(&self, py: Python) -> PyResult<PyObject> {
+        stats_to_py(
+            py,
+            "disk",
+            self.db.dim(),
+            self.db.len(),
+            self.db.access_summary(),
+            self.db.cost_summary(),
+        )
+    }
 }
 
 #[pymodule]
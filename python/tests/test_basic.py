@@ -164,6 +164,80 @@ def test_prune_keep_highest_reward():
     assert "low" not in task_ids
 
 
+
+def test_session_filter_and_prune():
+    """Test session scoping: filter queries by session_id, then prune a session."""
+    db = agent_mem_db.AgentMemDB(8)
+    for task_id, session_id in [("a", "s1"), ("b", "s2"), ("c", "s1")]:
+        db.store_episode(
+            agent_mem_db.Episode(
+                task_id=task_id,
+                state_embedding=[0.1] * 8,
+                reward=0.9,
+                session_id=session_id,
+            )
+        )
+    results = db.query_similar([0.1] * 8, min_reward=0.0, top_k=5, session_id="s1")
+    assert sorted(r.task_id for r in results) == ["a", "c"]
+    assert all(r.session_id == "s1" for r in results)
+    assert db.prune_session("s1") == 2
+    results = db.query_similar([0.1] * 8, min_reward=0.0, top_k=5)
+    assert [r.task_id for r in results] == ["b"]
+
+
+def test_tag_prefix_filter():
+    """Hierarchical tags: tag_prefix_any matches a tag and everything under it."""
+    db = agent_mem_db.AgentMemDB(8)
+    for task_id, tag in [("click", "tool/browser/click"), ("plural", "tool/browsers"), ("ls", "tool/shell")]:
+        db.store_episode(
+            agent_mem_db.Episode(
+                task_id=task_id,
+                state_embedding=[0.1] * 8,
+                reward=0.9,
+                tags=[tag],
+            )
+        )
+    results = db.query_similar([0.1] * 8, min_reward=0.0, top_k=5, tag_prefix_any=["tool/browser"])
+    assert [r.task_id for r in results] == ["click"]
+
+def test_delete_where_count_and_stats():
+    """Test filtered delete and count, and stats, on both DB classes."""
+    import shutil
+
+    tmpdir = os.path.join(tempfile.gettempdir(), "agent_mem_db_delete_where_test")
+    shutil.rmtree(tmpdir, ignore_errors=True)
+    try:
+        for db in (
+            agent_mem_db.AgentMemDB.exact(4),
+            agent_mem_db.AgentMemDBDisk.open_with_options(tmpdir, 4, index_type="exact"),
+        ):
+            for task_id, user_id, cost in (("a", "u1", 0.5), ("b", "u1", None), ("c", "u2", 1.5)):
+                db.store_episode(
+                    agent_mem_db.Episode(
+                        task_id=task_id, state_embedding=[0.1] * 4, reward=-1.0, user_id=user_id, cost=cost
+                    )
+                )
+            assert db.count() == 3
+            assert db.count(user_id="u1") == 2
+            assert db.count(user_id="u1", task_id_prefix="b") == 1
+            stats = db.stats()
+            assert stats["episodes"] == 3 and stats["dim"] == 4
+            assert stats["total_cost"] == 2.0 and stats["mean_cost"] == 1.0
+
+            try:
+                db.delete_where()
+                assert False, "delete_where without filters should raise"
+            except ValueError:
+                pass
+            assert db.delete_where(user_id="u1") == 2
+            assert db.delete_where(user_id="u1") == 0
+            assert db.count() == 1
+            assert db.query_similar([0.1] * 4, min_reward=-5.0, top_k=5)[0].task_id == "c"
+        assert db.stats()["backend"] == "disk"
+    finally:
+        shutil.rmtree(tmpdir, ignore_errors=True)
+
+
 def test_save_and_load_roundtrip():
     db = agent_mem_db.AgentMemDB(8)
     ep = agent_mem_db.Episode(
@@ -222,3 +296,64 @@ def test_disk_checkpoint_fast_restart():
 
         if os.path.exists(tmpdir):
             shutil.rmtree(tmpdir, ignore_errors=True)
+
+
+def test_disk_open_with_options():
+    """Test opening a disk DB with explicit options."""
+    import shutil
+
+    tmpdir = os.path.join(tempfile.gettempdir(), "agent_mem_db_open_with_options_test")
+    shutil.rmtree(tmpdir, ignore_errors=True)
+    try:
+        db = agent_mem_db.AgentMemDBDisk.open_with_options(
+            tmpdir,
+            4,
+            index_type="exact",
+            use_checkpoint=True,
+            auto_checkpoint_every_n=1,
+            sync="group",
+            group_commit_max_records=8,
+        )
+        db.store_episode(agent_mem_db.Episode(task_id="t1", state_embedding=[0.1] * 4, reward=0.9))
+        db.flush()
+        del db
+        assert os.path.exists(os.path.join(tmpdir, "exact_checkpoint.json"))
+
+        db2 = agent_mem_db.AgentMemDBDisk.open_with_options(tmpdir, 4, index_type="exact")
+        assert len(db2.query_similar([0.1] * 4, min_reward=0.0, top_k=5)) == 1
+
+        hnsw_dir = os.path.join(tmpdir, "hnsw")
+        db3 = agent_mem_db.AgentMemDBDisk.open_with_options(
+            hnsw_dir, 4, max_elements=100, hnsw_m=8, ef_search=64
+        )
+        db3.store_episode(agent_mem_db.Episode(task_id="t2", state_embedding=[0.2] * 4, reward=0.5))
+        assert db3.query_similar([0.2] * 4, min_reward=0.0, top_k=1)[0].task_id == "t2"
+
+        for bad in ({"index_type": "ivf"}, {"sync": "never"}, {"metric": "cosine"}):
+            try:
+                agent_mem_db.AgentMemDBDisk.open_with_options(hnsw_dir, 4, **bad)
+                assert False, bad
+            except ValueError:
+                pass
+    finally:
+        shutil.rmtree(tmpdir, ignore_errors=True)
+
+
+def test_store_and_query_text():
+    """set_embedder + store_text / query_text."""
+    db = agent_mem_db.AgentMemDB.exact(2)
+
+    def embed(text):
+        return [float(len(text)), float(text.count("a"))]
+
+    try:
+        db.set_embedder(embed, 3)
+        assert False, "dimension mismatch should raise"
+    except ValueError:
+        pass
+    db.set_embedder(embed, 2)
+    ep_id = db.store_text("banana", "banana", 1.0, tags=["fruit"])
+    db.store_text("kiwi", "kiwi", 1.0)
+    results = db.query_text("papaya", min_reward=0.0, top_k=1, tags_any=["fruit"])
+    assert [ep.id for ep in results] == [ep_id]
+    assert results[0].state_embedding == [6.0, 3.0]
@@ -0,0 +1,47 @@
+//! Generates the gRPC service trait from a Rust description of `proto/agent_mem.proto`,
+//! so building the server does not require protoc.
+
+fn main() {
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        tonic_build::manual::Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::pb::{input}"))
+            .output_type(format!("crate::grpc::pb::{output}"))
+            .codec_path("tonic::codec::ProstCodec")
+    };
+    let service = tonic_build::manual::Service::builder()
+        .name("AgentMemory")
+        .package("agent_mem.v1")
+        .method(
+            method(
+                "store_episode",
+                "StoreEpisode",
+                "StoreEpisodeRequest",
+                "StoreEpisodeResponse",
+            )
+            .build(),
+        )
+        .method(
+            method(
+                "store_episodes",
+                "StoreEpisodes",
+                "StoreEpisodesRequest",
+                "StoreEpisodesResponse",
+            )
+            .build(),
+        )
+        .method(method("query", "Query", "QueryRequest", "QueryResponse").build())
+        .method(method("prune", "Prune", "PruneRequest", "PruneResponse").build())
+        .method(
+            method("export", "Export", "ExportRequest", "Episode")
+                .server_streaming()
+                .build(),
+        )
+        .build();
+
+    println!("cargo:rerun-if-changed=build.rs");
+    tonic_build::manual::Builder::new()
+        .build_client(false)
+        .compile(&[service]);
+}
@@ -0,0 +1,258 @@
+//! JSONL audit log file with size/age-based rotation, and `GET /v1/audit` to read back a
+//! tenant's recent entries.
+//!
+//! Rotated files sit next to the live file as `<name>.<UTC timestamp>` (e.g.
+//! `audit.jsonl.20260101T120000123Z`), so they sort oldest-first by name; only the newest
+//! `keep` rotated files are retained.
+
+use crate::config::AuditConfig;
+use crate::{openapi, ApiError, AppState, AuditEntry};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// The live audit file plus its rotation policy.
+pub struct AuditFile {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+    keep: Option<usize>,
+    current: Mutex<Current>,
+}
+
+struct Current {
+    file: File,
+    size: u64,
+    opened: SystemTime,
+}
+
+fn open_append(path: &Path) -> std::io::Result<Current> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let meta = file.metadata()?;
+    Ok(Current {
+        size: meta.len(),
+        opened: meta.created().unwrap_or_else(|_| SystemTime::now()),
+        file,
+    })
+}
+
+impl AuditFile {
+    pub fn open(path: &Path, config: &AuditConfig) -> std::io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes: config.max_bytes,
+            max_age: config.max_age_secs.map(Duration::from_secs),
+            keep: config.keep,
+            current: Mutex::new(open_append(path)?),
+        })
+    }
+
+    /// Append one JSON line, rotating first if the live file is over its size or age limit.
+    pub fn append(&self, line: &str) {
+        let Ok(mut current) = self.current.lock() else {
+            return;
+        };
+        let too_big = self
+            .max_bytes
+            .is_some_and(|max| current.size > 0 && current.size + line.len() as u64 + 1 > max);
+        let too_old = self.max_age.is_some_and(|max| {
+            current.size > 0 && current.opened.elapsed().is_ok_and(|age| age >= max)
+        });
+        if too_big || too_old {
+            match self.rotate() {
+                Ok(fresh) => *current = fresh,
+                Err(e) => tracing::error!(error = %e, "audit log rotation failed"),
+            }
+        }
+        if writeln!(current.file, "{}", line)
+            .and_then(|_| current.file.flush())
+            .is_ok()
+        {
+            current.size += line.len() as u64 + 1;
+        }
+    }
+
+    /// Rename the live file aside, open a fresh one, and drop rotated files beyond `keep`.
+    fn rotate(&self) -> std::io::Result<Current> {
+        let stamp = Utc::now().format("%Y%m%dT%H%M%S%3fZ");
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{stamp}"));
+        std::fs::rename(&self.path, &rotated)?;
+        let fresh = Current {
+            // Age counts from rotation, whatever birth time the filesystem reports.
+            opened: SystemTime::now(),
+            ..open_append(&self.path)?
+        };
+        if let Some(keep) = self.keep {
+            let old = self.rotated_files();
+            for path in old.iter().take(old.len().saturating_sub(keep)) {
+                if let Err(e) = std::fs::remove_file(path) {
+                    tracing::warn!(
+                        path = %path.display(),
+                        error = %e,
+                        "failed to remove rotated audit log"
+                    );
+                }
+            }
+        }
+        tracing::info!(rotated = %Path::new(&rotated).display(), "rotated audit log");
+        Ok(fresh)
+    }
+
+    /// Rotated files, oldest first.
+    fn rotated_files(&self) -> Vec<PathBuf> {
+        let Some(name) = self.path.file_name().and_then(|n| n.to_str()) else {
+            return Vec::new();
+        };
+        let prefix = format!("{name}.");
+        let dir = match self.path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|e| {
+                e.file_name().to_str().is_some_and(|n| {
+                    n.strip_prefix(&prefix).is_some_and(|stamp| {
+                        stamp.ends_with('Z') && stamp.starts_with(char::is_numeric)
+                    })
+                })
+            })
+            .map(|e| e.path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// A tenant's entries at or after `since`, oldest first, at most `limit`. The second
+    /// value is true when more matching entries were left out.
+    pub fn read_since(
+        &self,
+        tenant_id: &str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> (Vec<AuditEntry>, bool) {
+        let since_sys = SystemTime::from(since);
+        let mut files = self.rotated_files();
+        files.push(self.path.clone());
+        let mut entries = Vec::new();
+        for path in files {
+            // Everything in a file last written before `since` is older than `since`.
+            let stale = std::fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .is_ok_and(|m| m < since_sys);
+            if stale {
+                continue;
+            }
+            let Ok(file) = File::open(&path) else {
+                continue;
+            };
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) else {
+                    continue;
+                };
+                if entry.tenant_id != tenant_id {
+                    continue;
+                }
+                let after = DateTime::parse_from_rfc3339(&entry.ts).is_ok_and(|ts| ts >= since);
+                if !after {
+                    continue;
+                }
+                if entries.len() == limit {
+                    return (entries, true);
+                }
+                entries.push(entry);
+            }
+        }
+        (entries, false)
+    }
+}
+
+/// Query for `GET /v1/audit`.
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AuditQuery {
+    /// RFC 3339 timestamp or Unix milliseconds; defaults to 24 hours ago.
+    since: Option<String>,
+    /// Max entries to return (default 100, max 1000).
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuditResponse {
+    entries: Vec<AuditEntry>,
+    /// More entries match; query again with `since` set to the last entry's `ts`.
+    truncated: bool,
+}
+
+fn parse_since(since: Option<&str>) -> Result<DateTime<Utc>, String> {
+    let Some(since) = since else {
+        return Ok(Utc::now() - chrono::Duration::hours(24));
+    };
+    if let Ok(ms) = since.parse::<i64>() {
+        return DateTime::from_timestamp_millis(ms)
+            .ok_or_else(|| format!("since out of range: {ms}"));
+    }
+    DateTime::parse_from_rfc3339(since)
+        .map(|ts| ts.with_timezone(&Utc))
+        .map_err(|e| format!("invalid since {since:?}: {e} (expected RFC 3339 or Unix ms)"))
+}
+
+/// Recent audit entries for the caller's tenant, read from the audit log and its rotated files.
+#[utoipa::path(
+    get,
+    path = "/v1/audit",
+    tag = "admin",
+    params(AuditQuery),
+    responses(
+        (status = 200, body = AuditResponse),
+        (status = 400, description = "Invalid `since`", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Audit log not enabled", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn query(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditResponse>, ApiError> {
+    let Some(audit) = state.audit_log.clone() else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Audit log not enabled (AGENT_MEM_AUDIT_LOG)"})),
+        ));
+    };
+    let since = parse_since(query.since.as_deref()).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": e})),
+        )
+    })?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let (entries, truncated) =
+        tokio::task::spawn_blocking(move || audit.read_since(&tenant_id, since, limit))
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({"error": e.to_string()})),
+                )
+            })?;
+    Ok(Json(AuditResponse { entries, truncated }))
+}
@@ -0,0 +1,442 @@
+//! `POST /v1/admin/backup` and `POST /v1/admin/restore`: tenant snapshots as `.tar.zst`.
+//!
+//! An archive holds `manifest.json` plus either the tenant's disk files (`episodes.jsonl`,
+//! `meta.json`, `exact_checkpoint.json`) or, for in-memory tenants, `db.json` in the
+//! `AgentMemDB::save_to_file` format. Either kind restores into either backend.
+
+use crate::config::BackendKind;
+use crate::{
+    audit_log, new_memory_db, openapi, replication, ApiError, AppState, Tenant, TenantStore,
+};
+use agent_mem_db::{
+    tenant_dir_name, AgentMemDBDisk, DiskOptions, Episode, EpisodeStore, LogRecord,
+};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+/// Disk backend files copied into (and restored from) an archive.
+const DISK_FILES: [&str; 3] = ["episodes.jsonl", "meta.json", "exact_checkpoint.json"];
+const MANIFEST: &str = "manifest.json";
+const MEMORY_DB: &str = "db.json";
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    tenant_id: String,
+    /// `disk` or `memory`.
+    backend: String,
+    dim: usize,
+    episodes: usize,
+    created_at: String,
+}
+
+/// `db.json`: the `AgentMemDB::save_to_file` layout.
+#[derive(Serialize, Deserialize)]
+struct MemoryDb {
+    dim: usize,
+    episodes: Vec<Episode>,
+}
+
+fn internal(e: impl std::fmt::Display) -> ApiError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({"error": e.to_string()})),
+    )
+}
+
+fn bad_request(e: impl std::fmt::Display) -> ApiError {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({"error": e.to_string()})),
+    )
+}
+
+fn pack(files: Vec<(String, Vec<u8>)>) -> std::io::Result<Vec<u8>> {
+    let encoder = zstd::Encoder::new(Vec::new(), 3)?;
+    let mut tar = tar::Builder::new(encoder);
+    for (name, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(chrono::Utc::now().timestamp() as u64);
+        tar.append_data(&mut header, name, data.as_slice())?;
+    }
+    tar.into_inner()?.finish()
+}
+
+/// Read the known entries of an archive; anything else is ignored.
+fn unpack(archive: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let decoder = zstd::Decoder::new(archive).map_err(|e| format!("Not a zstd archive: {e}"))?;
+    let mut tar = tar::Archive::new(decoder);
+    let mut files = Vec::new();
+    for entry in tar.entries().map_err(|e| format!("Bad archive: {e}"))? {
+        let mut entry = entry.map_err(|e| format!("Bad archive: {e}"))?;
+        let name = entry
+            .path()
+            .map_err(|e| format!("Bad archive: {e}"))?
+            .to_string_lossy()
+            .into_owned();
+        let known = name == MANIFEST || name == MEMORY_DB || DISK_FILES.contains(&name.as_str());
+        if !known {
+            continue;
+        }
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Bad archive entry {name}: {e}"))?;
+        files.push((name, data));
+    }
+    Ok(files)
+}
+
+/// Snapshot the caller's tenant as a `.tar.zst` download.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/backup",
+    tag = "admin",
+    responses(
+        (status = 200, description = "tar.zst archive", content_type = "application/zstd"),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn backup(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+) -> Result<Response, ApiError> {
+    let archive = archive(&state, &tenant_id).await?;
+    audit_log(&state, &tenant_id, "backup", None, None, None);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zstd".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", archive_name(&tenant_id)),
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+/// `<tenant>-<UTC timestamp>.tar.zst`.
+pub fn archive_name(tenant_id: &str) -> String {
+    format!(
+        "{}-{}.tar.zst",
+        tenant_dir_name(tenant_id),
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+    )
+}
+
+/// The disk backend files present in `dir`.
+async fn read_disk_files(dir: PathBuf) -> Result<Vec<(String, Vec<u8>)>, ApiError> {
+    tokio::task::spawn_blocking(move || -> std::io::Result<_> {
+        let mut out = Vec::new();
+        for name in DISK_FILES {
+            match std::fs::read(dir.join(name)) {
+                Ok(data) => out.push((name.to_string(), data)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(out)
+    })
+    .await
+    .map_err(internal)?
+    .map_err(internal)
+}
+
+/// Build a tenant's `.tar.zst` archive. A loaded disk tenant is snapshotted under the lock
+/// and copied (with a fresh checkpoint) after releasing it, so stores continue meanwhile; one
+/// that is not loaded (e.g. evicted) is copied from its directory without being reopened.
+pub async fn archive(state: &AppState, tenant_id: &str) -> Result<Vec<u8>, ApiError> {
+    let dir = state
+        .data_dir
+        .as_ref()
+        .map(|d| d.join(tenant_dir_name(tenant_id)));
+    // Hold the lock until the files are read (or a snapshot is taken) so no write lands
+    // mid-copy.
+    let mut tenants = state.tenants.write().await;
+    let mut files = Vec::new();
+    let mut snapshot = None;
+    let (backend, dim, episodes) = match tenants.loaded_mut(tenant_id) {
+        Some(tenant) => {
+            match tenant.backend {
+                TenantStore::Disk(ref mut db) => {
+                    snapshot = Some(db.begin_snapshot().map_err(internal)?);
+                }
+                TenantStore::Memory(ref mut db) => {
+                    db.fold_access_stats();
+                    let snapshot = MemoryDb {
+                        dim: db.dim(),
+                        episodes: db.iter().cloned().collect(),
+                    };
+                    files.push((
+                        MEMORY_DB.to_string(),
+                        serde_json::to_vec(&snapshot).map_err(internal)?,
+                    ));
+                }
+            }
+            let backend = &tenant.backend;
+            (backend.kind(), backend.dim(), backend.len())
+        }
+        None => {
+            let Some(dir) = dir.filter(|d| d.join("meta.json").exists()) else {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    Json(serde_json::json!({"error": "No episodes stored for this tenant yet"})),
+                ));
+            };
+            files.extend(read_disk_files(dir).await?);
+            let dim = file(&files, "meta.json")
+                .and_then(|m| serde_json::from_slice::<serde_json::Value>(m).ok())
+                .and_then(|m| m["dim"].as_u64())
+                .map_or(state.default_dim, |d| d as usize);
+            let episodes = log_episode_count(file(&files, "episodes.jsonl").unwrap_or_default());
+            ("disk", dim, episodes)
+        }
+    };
+    drop(tenants);
+
+    if let Some(snapshot) = snapshot {
+        let data_dir = state
+            .data_dir
+            .as_ref()
+            .ok_or_else(|| internal("disk tenant without data_dir"))?;
+        let staging = data_dir.join(format!(
+            ".backup-{}-{}",
+            tenant_dir_name(tenant_id),
+            uuid::Uuid::new_v4()
+        ));
+        let staged = staging.clone();
+        let written = tokio::task::spawn_blocking(move || snapshot.write_to(&staged))
+            .await
+            .map_err(internal)?
+            .map_err(internal);
+        let read = match written {
+            Ok(()) => read_disk_files(staging.clone()).await,
+            Err(e) => Err(e),
+        };
+        let _ = std::fs::remove_dir_all(&staging);
+        files.extend(read?);
+    }
+
+    let manifest = Manifest {
+        tenant_id: tenant_id.to_string(),
+        backend: backend.to_string(),
+        dim,
+        episodes,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest).map_err(internal)?;
+    files.insert(0, (MANIFEST.to_string(), manifest));
+    tokio::task::spawn_blocking(move || pack(files))
+        .await
+        .map_err(internal)?
+        .map_err(internal)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RestoreResponse {
+    /// Episodes in the restored tenant.
+    episodes: usize,
+    /// Backend the archive was taken from.
+    source_backend: String,
+}
+
+fn file<'a>(files: &'a [(String, Vec<u8>)], name: &str) -> Option<&'a [u8]> {
+    files
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, d)| d.as_slice())
+}
+
+/// Episodes and dimension from an archive of either kind.
+fn archive_episodes(files: &[(String, Vec<u8>)], dim: usize) -> Result<Vec<Episode>, String> {
+    if let Some(db) = file(files, MEMORY_DB) {
+        let db: MemoryDb =
+            serde_json::from_slice(db).map_err(|e| format!("Bad {MEMORY_DB}: {e}"))?;
+        return Ok(db.episodes);
+    }
+    let log = file(files, "episodes.jsonl").unwrap_or_default();
+    // Archives are taken under the tenant lock, so every batch in the log is committed.
+    let episodes: Vec<Episode> = log
+        .split(|b| *b == b'\n')
+        .filter(|l| !l.is_empty())
+        .filter_map(|l| match LogRecord::parse(l) {
+            Ok(LogRecord::Episode(ep)) => Some(Ok(ep)),
+            Ok(_) => None,
+            Err(e) => Some(Err(format!("Bad episodes.jsonl line: {e}"))),
+        })
+        .collect::<Result<_, _>>()?;
+    if let Some(ep) = episodes.iter().find(|e| e.state_embedding.len() != dim) {
+        return Err(format!(
+            "Episode {} has dimension {}, archive dim is {dim}",
+            ep.id,
+            ep.state_embedding.len()
+        ));
+    }
+    Ok(episodes)
+}
+
+/// Episodes in a disk log: updates and soft deletes append a new version of an episode, and
+/// batches add marker lines, so count distinct ids rather than lines.
+fn log_episode_count(log: &[u8]) -> usize {
+    log.split(|b| *b == b'\n')
+        .filter_map(|l| match LogRecord::parse(l) {
+            Ok(LogRecord::Episode(ep)) => Some(ep.id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Build the restored tenant's directory at `staging`, then validate it by opening it.
+fn stage_disk(
+    staging: &Path,
+    tenant_id: &str,
+    dim: usize,
+    files: &[(String, Vec<u8>)],
+) -> Result<(), String> {
+    std::fs::create_dir_all(staging).map_err(|e| e.to_string())?;
+    if file(files, "meta.json").is_some() {
+        // Disk archive: restore the files as-is.
+        for (name, data) in files
+            .iter()
+            .filter(|(n, _)| DISK_FILES.contains(&n.as_str()))
+        {
+            std::fs::write(staging.join(name), data).map_err(|e| e.to_string())?;
+        }
+    } else {
+        let mut db =
+            AgentMemDBDisk::open_with_options(staging, DiskOptions::exact_with_checkpoint(dim))
+                .map_err(|e| e.to_string())?;
+        for ep in archive_episodes(files, dim)? {
+            db.store_episode(ep).map_err(|e| e.to_string())?;
+        }
+        db.checkpoint().map_err(|e| e.to_string())?;
+    }
+    std::fs::write(staging.join(replication::TENANT_ID_FILE), tenant_id)
+        .map_err(|e| e.to_string())?;
+    AgentMemDBDisk::open_with_options(staging, DiskOptions::exact_with_checkpoint(dim))
+        .map(drop)
+        .map_err(|e| format!("Restored data does not open: {e}"))
+}
+
+/// Replace the caller's tenant with the contents of a backup archive (request body).
+#[utoipa::path(
+    post,
+    path = "/v1/admin/restore",
+    tag = "admin",
+    request_body(content = Vec<u8>, description = "tar.zst from /v1/admin/backup", content_type = "application/zstd"),
+    responses(
+        (status = 200, body = RestoreResponse),
+        (status = 400, description = "Invalid archive or dimension mismatch", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn restore(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    body: Bytes,
+) -> Result<Json<RestoreResponse>, ApiError> {
+    state.replication.check_writable()?;
+    let files = tokio::task::spawn_blocking(move || unpack(&body))
+        .await
+        .map_err(internal)?
+        .map_err(bad_request)?;
+    let manifest: Manifest = file(&files, MANIFEST)
+        .ok_or_else(|| bad_request(format!("Archive has no {MANIFEST}")))
+        .and_then(|m| serde_json::from_slice(m).map_err(bad_request))?;
+    if manifest.dim != state.default_dim {
+        return Err(bad_request(format!(
+            "Archive dimension {} does not match server dimension {}",
+            manifest.dim, state.default_dim
+        )));
+    }
+    let dim = manifest.dim;
+
+    let disk = state.tenant_settings.resolve(&tenant_id).backend == BackendKind::Disk;
+    let tenant = match state.data_dir.clone().filter(|_| disk) {
+        Some(data_dir) => {
+            let safe = tenant_dir_name(&tenant_id);
+            let staging = data_dir.join(format!(".restore-{safe}"));
+            let id = tenant_id.clone();
+            let staged = staging.clone();
+            tokio::task::spawn_blocking(move || {
+                let _ = std::fs::remove_dir_all(&staged);
+                let result = stage_disk(&staged, &id, dim, &files);
+                if result.is_err() {
+                    let _ = std::fs::remove_dir_all(&staged);
+                }
+                result
+            })
+            .await
+            .map_err(internal)?
+            .map_err(bad_request)?;
+            let mut tenants = state.tenants.write().await;
+            tenants.remove(&tenant_id);
+            swap_dirs(&staging, &data_dir.join(&safe)).map_err(internal)?;
+            let tenant = crate::open_tenant(&state, &tenant_id)?;
+            tenants.insert(tenant_id.clone(), tenant);
+            tenants
+        }
+        None => {
+            let episodes = tokio::task::spawn_blocking(move || archive_episodes(&files, dim))
+                .await
+                .map_err(internal)?
+                .map_err(bad_request)?;
+            let mut db = new_memory_db(&state.tenant_settings.resolve(&tenant_id), dim);
+            db.store_episodes(episodes).map_err(bad_request)?;
+            db.set_auto_timestamp(state.fills_timestamps());
+            let mut tenant = Tenant::new(TenantStore::Memory(db));
+            tenant.dirty = true;
+            let mut tenants = state.tenants.write().await;
+            tenants.insert(tenant_id.clone(), tenant);
+            tenants
+        }
+    };
+    let episodes = tenant.loaded(&tenant_id).map_or(0, |t| t.backend.len());
+    drop(tenant);
+
+    state.replication.log_rewritten(&tenant_id);
+    audit_log(&state, &tenant_id, "restore", None, Some(episodes), None);
+    Ok(Json(RestoreResponse {
+        episodes,
+        source_backend: manifest.backend,
+    }))
+}
+
+/// Move `staging` into place at `target`, keeping the old directory until the swap succeeds.
+fn swap_dirs(staging: &Path, target: &Path) -> std::io::Result<()> {
+    let mut old = PathBuf::from(target);
+    old.set_extension("restore-old");
+    let _ = std::fs::remove_dir_all(&old);
+    let had_old = target.exists();
+    if had_old {
+        std::fs::rename(target, &old)?;
+    }
+    if let Err(e) = std::fs::rename(staging, target) {
+        if had_old {
+            let _ = std::fs::rename(&old, target);
+        }
+        return Err(e);
+    }
+    if had_old {
+        let _ = std::fs::remove_dir_all(&old);
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! Per-tenant caps on in-flight stores and queries (`[concurrency]`), so one busy tenant
+//! can't queue unbounded work behind the tenant lock. Requests over a cap are shed with
+//! 503 and `Retry-After` instead of waiting.
+
+use crate::config::ConcurrencyConfig;
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Store,
+    Query,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Store => "store",
+            Kind::Query => "query",
+        }
+    }
+}
+
+pub struct Limits {
+    max_stores: Option<usize>,
+    max_queries: Option<usize>,
+    retry_after_secs: u64,
+    in_flight: Mutex<HashMap<(String, Kind), usize>>,
+    /// Requests shed, by kind (store, query).
+    rejected: [AtomicU64; 2],
+}
+
+/// A tenant's slot for one request; released on drop.
+pub struct Permit {
+    limits: Arc<Limits>,
+    key: (String, Kind),
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = self.limits.in_flight.lock() {
+            if let Some(n) = in_flight.get_mut(&self.key) {
+                *n -= 1;
+                if *n == 0 {
+                    in_flight.remove(&self.key);
+                }
+            }
+        }
+    }
+}
+
+/// A request shed because its tenant is at the cap for its kind.
+pub struct Overloaded {
+    kind: Kind,
+    limit: usize,
+    retry_after_secs: u64,
+}
+
+impl Overloaded {
+    fn message(&self) -> String {
+        format!(
+            "Too many {} requests in flight for this tenant (limit {})",
+            self.kind.as_str(),
+            self.limit
+        )
+    }
+
+    /// gRPC form: `UNAVAILABLE`, which clients treat as retryable.
+    pub fn into_status(self) -> tonic::Status {
+        tonic::Status::unavailable(self.message())
+    }
+}
+
+impl IntoResponse for Overloaded {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(RETRY_AFTER, self.retry_after_secs.to_string())],
+            Json(serde_json::json!({
+                "error": self.message(),
+                "limit": self.limit,
+                "retry_after_secs": self.retry_after_secs,
+            })),
+        )
+            .into_response()
+    }
+}
+
+impl Limits {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        Self {
+            max_stores: config.max_stores,
+            max_queries: config.max_queries,
+            retry_after_secs: config.retry_after_secs,
+            in_flight: Mutex::new(HashMap::new()),
+            rejected: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    fn max(&self, kind: Kind) -> Option<usize> {
+        match kind {
+            Kind::Store => self.max_stores,
+            Kind::Query => self.max_queries,
+        }
+    }
+
+    /// Take a slot for `tenant_id`, or `Overloaded` if it is at the cap for `kind`.
+    /// `None` when `kind` is uncapped.
+    pub fn acquire(
+        self: &Arc<Self>,
+        tenant_id: &str,
+        kind: Kind,
+    ) -> Result<Option<Permit>, Overloaded> {
+        let Some(limit) = self.max(kind) else {
+            return Ok(None);
+        };
+        let key = (tenant_id.to_string(), kind);
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let n = in_flight.entry(key.clone()).or_default();
+        if *n >= limit {
+            drop(in_flight);
+            self.rejected[kind as usize].fetch_add(1, Ordering::Relaxed);
+            return Err(Overloaded {
+                kind,
+                limit,
+                retry_after_secs: self.retry_after_secs,
+            });
+        }
+        *n += 1;
+        Ok(Some(Permit {
+            limits: self.clone(),
+            key,
+        }))
+    }
+
+    /// Append the shed-request counters.
+    pub fn render_metrics(&self, out: &mut String) {
+        out.push_str(
+            "# HELP agent_mem_overload_rejections_total Requests shed with 503 by the per-tenant concurrency caps\n\
+             # TYPE agent_mem_overload_rejections_total counter\n",
+        );
+        for kind in [Kind::Store, Kind::Query] {
+            let _ = writeln!(
+                out,
+                "agent_mem_overload_rejections_total{{kind=\"{}\"}} {}",
+                kind.as_str(),
+                self.rejected[kind as usize].load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+async fn limit(state: &AppState, kind: Kind, request: Request, next: Next) -> Response {
+    let tenant_id = request
+        .extensions()
+        .get::<String>()
+        .cloned()
+        .unwrap_or_else(|| "unknown".to_string());
+    match state.concurrency.acquire(&tenant_id, kind) {
+        Ok(_permit) => next.run(request).await,
+        Err(overloaded) => overloaded.into_response(),
+    }
+}
+
+/// Middleware capping in-flight store requests per tenant. Runs after auth.
+pub async fn limit_stores(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    limit(&state, Kind::Store, request, next).await
+}
+
+/// Middleware capping in-flight query requests per tenant. Runs after auth.
+pub async fn limit_queries(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    limit(&state, Kind::Query, request, next).await
+}
@@ -0,0 +1,1225 @@
+//! Server configuration: defaults, then an optional TOML/YAML file, then `AGENT_MEM_*`
+//! environment variables, then CLI flags (later sources win).
+
+use crate::{parse_api_keys, Quotas};
+use agent_mem_db::{EpisodeLimits, PruneRule};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+/// Command-line flags for `agent-mem-server`.
+#[derive(Parser, Debug)]
+#[command(
+    name = "agent-mem-server",
+    version,
+    about = "HTTP server for Agent Memory DB"
+)]
+pub struct Cli {
+    /// Config file (TOML, or YAML when the extension is .yaml/.yml).
+    #[arg(short, long, env = "AGENT_MEM_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Print the resolved configuration (secrets redacted) and exit.
+    #[arg(long)]
+    pub print_config: bool,
+    /// Address to listen on, e.g. 0.0.0.0:8080.
+    #[arg(long)]
+    pub bind: Option<SocketAddr>,
+    /// Address for the gRPC API, e.g. 0.0.0.0:50051 (disabled when unset).
+    #[arg(long)]
+    pub grpc_bind: Option<SocketAddr>,
+    /// Default embedding dimension for new tenants.
+    #[arg(long)]
+    pub dim: Option<usize>,
+    /// Directory for disk-backed tenant storage.
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+    /// Max requests per tenant per rate-limit window.
+    #[arg(long)]
+    pub rate_limit: Option<u64>,
+    /// Path of the JSONL audit log.
+    #[arg(long)]
+    pub audit_log: Option<PathBuf>,
+    /// Log output format: `text` or `json`.
+    #[arg(long)]
+    pub log_format: Option<LogFormat>,
+    /// TLS certificate chain (PEM). Requires --tls-key.
+    #[arg(long)]
+    pub tls_cert: Option<PathBuf>,
+    /// TLS private key (PEM). Requires --tls-cert.
+    #[arg(long)]
+    pub tls_key: Option<PathBuf>,
+    /// Serve MCP over stdin/stdout for the `mcp.tenant` tenant instead of listening on HTTP.
+    #[arg(long)]
+    pub mcp_stdio: bool,
+}
+
+/// Fully resolved server configuration.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: SocketAddr,
+    /// gRPC listen address; `None` disables the gRPC API.
+    pub grpc_bind: Option<SocketAddr>,
+    pub dim: usize,
+    pub data_dir: Option<PathBuf>,
+    pub audit_log: Option<PathBuf>,
+    pub audit: AuditConfig,
+    pub log_format: LogFormat,
+    pub http: HttpConfig,
+    pub cors: CorsConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub concurrency: ConcurrencyConfig,
+    pub quotas: Quotas,
+    /// Size limits on each stored episode.
+    pub limits: EpisodeLimits,
+    /// Set `timestamp` to the current Unix ms on stored episodes that don't have one.
+    pub auto_timestamp: bool,
+    pub eviction: EvictionConfig,
+    pub write_behind: WriteBehindConfig,
+    pub tenant_defaults: TenantSettings,
+    /// Per-tenant overrides of `tenant_defaults`, keyed by tenant id.
+    pub tenants: HashMap<String, TenantSettings>,
+    pub retention: RetentionConfig,
+    pub replication: ReplicationConfig,
+    pub snapshots: SnapshotConfig,
+    pub embedding: EmbeddingConfig,
+    pub mcp: McpConfig,
+    pub openai: OpenAiConfig,
+    pub webhooks: WebhooksConfig,
+    pub usage: UsageConfig,
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            grpc_bind: None,
+            dim: 384,
+            data_dir: None,
+            audit_log: None,
+            audit: AuditConfig::default(),
+            log_format: LogFormat::default(),
+            http: HttpConfig::default(),
+            cors: CorsConfig::default(),
+            auth: AuthConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            concurrency: ConcurrencyConfig::default(),
+            quotas: Quotas::default(),
+            limits: EpisodeLimits::default(),
+            auto_timestamp: false,
+            eviction: EvictionConfig::default(),
+            write_behind: WriteBehindConfig::default(),
+            tenant_defaults: TenantSettings::default(),
+            tenants: HashMap::new(),
+            retention: RetentionConfig::default(),
+            replication: ReplicationConfig::default(),
+            snapshots: SnapshotConfig::default(),
+            embedding: EmbeddingConfig::default(),
+            mcp: McpConfig::default(),
+            openai: OpenAiConfig::default(),
+            webhooks: WebhooksConfig::default(),
+            usage: UsageConfig::default(),
+            tls: None,
+        }
+    }
+}
+
+/// How requests are authenticated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// Require keys if any are configured, otherwise dev mode.
+    #[default]
+    Auto,
+    /// Require a configured API key; startup fails if none are configured.
+    Keys,
+    /// Accept any key with full access (development only).
+    Dev,
+}
+
+impl std::str::FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "keys" => Ok(Self::Keys),
+            "dev" => Ok(Self::Dev),
+            other => Err(format!("unknown auth mode '{other}'")),
+        }
+    }
+}
+
+/// Log line format on stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, including the current span's fields (e.g. `request_id`).
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown log format '{other}' (expected text or json)"
+            )),
+        }
+    }
+}
+
+/// Where a tenant's episodes live.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// RAM only (see `write_behind` for periodic saves).
+    Memory,
+    /// Append-only log under `data_dir`.
+    Disk,
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(Self::Memory),
+            "disk" => Ok(Self::Disk),
+            other => Err(format!(
+                "unknown backend '{other}' (expected memory or disk)"
+            )),
+        }
+    }
+}
+
+/// Vector index used for a tenant's similarity search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexKind {
+    /// Brute force: exact results, O(n) per query.
+    Exact,
+    /// Approximate nearest neighbour graph.
+    Hnsw,
+}
+
+impl std::str::FromStr for IndexKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(Self::Exact),
+            "hnsw" => Ok(Self::Hnsw),
+            other => Err(format!("unknown index '{other}' (expected exact or hnsw)")),
+        }
+    }
+}
+
+/// Backend and index choice for tenants. Unset fields fall through, in order: a tenant's
+/// saved settings, the keys file, `tenants.<id>`, `tenant_defaults`, then the built-in default (disk + exact
+/// with `data_dir`, else memory + HNSW).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantSettings {
+    pub backend: Option<BackendKind>,
+    pub index: Option<IndexKind>,
+    /// HNSW capacity.
+    pub hnsw_max_elements: Option<usize>,
+    /// HNSW max neighbours per node (at least 2).
+    pub hnsw_m: Option<usize>,
+    pub hnsw_ef_construction: Option<usize>,
+    pub hnsw_ef_search: Option<usize>,
+}
+
+impl TenantSettings {
+    /// Fill unset fields from `fallback`.
+    pub fn or(self, fallback: &TenantSettings) -> TenantSettings {
+        TenantSettings {
+            backend: self.backend.or(fallback.backend),
+            index: self.index.or(fallback.index),
+            hnsw_max_elements: self.hnsw_max_elements.or(fallback.hnsw_max_elements),
+            hnsw_m: self.hnsw_m.or(fallback.hnsw_m),
+            hnsw_ef_construction: self.hnsw_ef_construction.or(fallback.hnsw_ef_construction),
+            hnsw_ef_search: self.hnsw_ef_search.or(fallback.hnsw_ef_search),
+        }
+    }
+
+    /// Check the set fields; `has_data_dir` is whether the disk backend is available.
+    pub fn validate(&self, has_data_dir: bool) -> Result<(), String> {
+        if self.backend == Some(BackendKind::Disk) && !has_data_dir {
+            return Err("backend \"disk\" requires data_dir".to_string());
+        }
+        if self.hnsw_m.is_some_and(|m| !(2..=128).contains(&m)) {
+            return Err("hnsw_m must be between 2 and 128".to_string());
+        }
+        for (name, value) in [
+            ("hnsw_max_elements", self.hnsw_max_elements),
+            ("hnsw_ef_construction", self.hnsw_ef_construction),
+            ("hnsw_ef_search", self.hnsw_ef_search),
+        ] {
+            if value == Some(0) {
+                return Err(format!("{name} must be greater than 0"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub mode: AuthMode,
+    /// Legacy single key: full access, tenant_id = key.
+    pub api_key: Option<String>,
+    /// Scoped keys, each `key:tenant[:scope+scope]`.
+    pub api_keys: Vec<String>,
+    /// TOML/JSON file of keys with per-tenant settings, re-read on SIGHUP (see `keys_file`).
+    pub keys_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Max requests per tenant per window; `None` disables rate limiting.
+    pub max_requests: Option<u64>,
+    pub window_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_requests: None,
+            window_secs: 60,
+        }
+    }
+}
+
+/// Per-tenant caps on requests in flight; requests over a cap get 503 with `Retry-After`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// Max store requests in flight per tenant; `None` is unlimited.
+    pub max_stores: Option<usize>,
+    /// Max query requests in flight per tenant; `None` is unlimited.
+    pub max_queries: Option<usize>,
+    /// `Retry-After` sent with the 503.
+    pub retry_after_secs: u64,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_stores: None,
+            max_queries: None,
+            retry_after_secs: 1,
+        }
+    }
+}
+
+/// Per-tenant usage counters for billing, served at `GET /v1/admin/usage`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UsageConfig {
+    /// How often counters are brought up to date and saved.
+    pub interval_secs: u64,
+    /// Operator token that may read every tenant's usage.
+    pub token: Option<String>,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60,
+            token: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvictionConfig {
+    /// Evict disk-backed tenants idle this long.
+    pub idle_secs: Option<u64>,
+    /// Max loaded tenants before least recently used ones are evicted.
+    pub max_loaded: Option<usize>,
+    pub interval_secs: u64,
+}
+
+impl Default for EvictionConfig {
+    fn default() -> Self {
+        Self {
+            idle_secs: None,
+            max_loaded: None,
+            interval_secs: 30,
+        }
+    }
+}
+
+/// Periodic saves of in-memory tenants, restored on boot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WriteBehindConfig {
+    /// Directory for tenant snapshots; `None` disables write-behind.
+    pub dir: Option<PathBuf>,
+    pub interval_secs: u64,
+}
+
+impl Default for WriteBehindConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            interval_secs: 30,
+        }
+    }
+}
+
+/// Retention applied by a background sweep: the default policy below for every loaded
+/// tenant, overridden per tenant by `tenants.<id>` (or the tenant's own `PUT /v1/retention`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Prune episodes older than this many seconds.
+    pub max_age_secs: Option<u64>,
+    /// Keep only this many most recent episodes.
+    pub keep_newest: Option<usize>,
+    /// Default time between runs of a tenant's policy.
+    pub interval_secs: u64,
+    /// Per-tenant policies, keyed by tenant id; replace the default policy entirely.
+    pub tenants: HashMap<String, RetentionPolicy>,
+    /// Move pruned episodes into a per-tenant archive under this directory instead of
+    /// discarding them (see `POST /v1/query/archive`).
+    pub archive_dir: Option<PathBuf>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_secs: None,
+            keep_newest: None,
+            interval_secs: 3600,
+            tenants: HashMap::new(),
+            archive_dir: None,
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// The policy for tenants without their own.
+    pub fn default_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            max_age_secs: self.max_age_secs,
+            keep_newest: self.keep_newest,
+            keep_highest_reward: None,
+            max_idle_secs: None,
+            keep_most_retrieved: None,
+            max_bytes: None,
+            interval_secs: None,
+        }
+    }
+}
+
+/// What a retention run removes, and how often it runs. A policy with no rules removes
+/// nothing (useful to exempt a tenant from the default policy).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionPolicy {
+    /// Prune episodes older than this many seconds ("keep 30 days" = 2592000).
+    pub max_age_secs: Option<u64>,
+    /// Keep only this many most recent episodes.
+    pub keep_newest: Option<usize>,
+    /// Keep only this many highest-reward episodes.
+    pub keep_highest_reward: Option<usize>,
+    /// Prune episodes no query has returned for this many seconds; never-retrieved episodes
+    /// go by their timestamp.
+    pub max_idle_secs: Option<u64>,
+    /// Keep only this many most-retrieved episodes.
+    pub keep_most_retrieved: Option<usize>,
+    /// Prune the oldest episodes until the rest take at most this many bytes of JSON, as the
+    /// `max_bytes` quota counts them.
+    pub max_bytes: Option<u64>,
+    /// Seconds between runs; defaults to `retention.interval_secs`.
+    pub interval_secs: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn has_rules(&self) -> bool {
+        self.max_age_secs.is_some()
+            || self.keep_newest.is_some()
+            || self.keep_highest_reward.is_some()
+            || self.max_idle_secs.is_some()
+            || self.keep_most_retrieved.is_some()
+            || self.max_bytes.is_some()
+    }
+
+    /// The policy's prune rules at `now_ms`, applied in one prune: one index rebuild and log
+    /// compaction. Size goes last, so it only trims what the other rules keep.
+    pub fn rules(&self, now_ms: i64) -> Vec<PruneRule> {
+        let before = |secs: u64| now_ms - (secs as i64) * 1000;
+        let mut rules = Vec::new();
+        rules.extend(self.max_age_secs.map(|s| PruneRule::OlderThan(before(s))));
+        rules.extend(self.keep_newest.map(PruneRule::KeepNewest));
+        rules.extend(self.keep_highest_reward.map(PruneRule::KeepHighestReward));
+        rules.extend(
+            self.max_idle_secs
+                .map(|s| PruneRule::NotRetrievedSince(before(s))),
+        );
+        rules.extend(self.keep_most_retrieved.map(PruneRule::KeepMostRetrieved));
+        rules.extend(self.max_bytes.map(PruneRule::MaxBytes));
+        rules
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keep_newest == Some(0)
+            || self.keep_highest_reward == Some(0)
+            || self.keep_most_retrieved == Some(0)
+            || self.max_bytes == Some(0)
+        {
+            return Err(
+                "keep_newest, keep_highest_reward, keep_most_retrieved and max_bytes must be \
+                 greater than 0"
+                    .to_string(),
+            );
+        }
+        if self.interval_secs == Some(0) {
+            return Err("interval_secs must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Log-shipping replication. A leader serves its logs when `token` is set; a follower
+/// tails `leader` (authenticating with the same `token`) and rejects writes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplicationConfig {
+    /// Shared secret for `/v1/replication` (leader) and for polling the leader (follower).
+    pub token: Option<String>,
+    /// Leader base URL, e.g. `http://leader:8080`; makes this server a follower.
+    pub leader: Option<String>,
+    pub poll_interval_ms: u64,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            token: None,
+            leader: None,
+            poll_interval_ms: 1000,
+        }
+    }
+}
+
+/// Server-side embedding of `text` through an OpenAI-compatible `/embeddings` endpoint;
+/// enabled by `url`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmbeddingConfig {
+    /// API base URL, e.g. `https://api.openai.com/v1` or `http://localhost:11434/v1`.
+    pub url: Option<String>,
+    pub model: String,
+    /// Sent as `Authorization: Bearer`.
+    pub api_key: Option<String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            model: "text-embedding-3-small".to_string(),
+            api_key: None,
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Model Context Protocol server mode.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct McpConfig {
+    /// Tenant used by `--mcp-stdio`; MCP over HTTP uses the API key's tenant.
+    pub tenant: String,
+}
+
+impl Default for McpConfig {
+    fn default() -> Self {
+        Self {
+            tenant: "default".to_string(),
+        }
+    }
+}
+
+/// OpenAI-compatible API surfaces.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenAiConfig {
+    /// Serve `/v1/files` and `/v1/vector_stores` (requires `embedding.url`).
+    pub vector_stores: bool,
+}
+
+/// Webhook delivery, plus webhooks the operator registers per tenant (tenants can also
+/// register their own via `/v1/webhooks`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    /// Retries after a failed delivery (non-2xx or network error) before giving up.
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled on each further retry.
+    pub initial_backoff_ms: u64,
+    pub timeout_ms: u64,
+    /// Fraction of a quota (`max_episodes`, `max_bytes`) at which `quota` events fire.
+    pub quota_warn_ratio: f64,
+    /// Webhooks per tenant, keyed by tenant id.
+    pub tenants: HashMap<String, Vec<Webhook>>,
+    /// Hosts that webhooks registered through the API may target although they are on the
+    /// server's own network (loopback, private or link-local addresses), e.g. an internal
+    /// event relay. Operator webhooks (`tenants`) are never restricted.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff_ms: 1000,
+            timeout_ms: 5000,
+            quota_warn_ratio: 0.9,
+            tenants: HashMap::new(),
+            allowed_hosts: Vec::new(),
+        }
+    }
+}
+
+/// A memory event a webhook can subscribe to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// The tenant's episode count rose past `episode_threshold`.
+    EpisodeCount,
+    /// A prune, delete or retention run removed episodes.
+    Prune,
+    /// Usage rose past `webhooks.quota_warn_ratio` of a quota.
+    Quota,
+}
+
+/// One webhook endpoint.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    /// Assigned when registered through the API.
+    #[serde(default)]
+    pub id: String,
+    pub url: String,
+    /// Events to deliver; empty means all.
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Episode count that triggers `episode_count` events.
+    #[serde(default)]
+    pub episode_threshold: Option<usize>,
+    /// Signs each payload with HMAC-SHA256 (`X-AgentMem-Signature: sha256=<hex>`).
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+impl Webhook {
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            return Err(format!("url must be an http(s) URL, got {:?}", self.url));
+        }
+        if self.episode_threshold == Some(0) {
+            return Err("episode_threshold must be greater than 0".to_string());
+        }
+        if self.events.contains(&WebhookEvent::EpisodeCount) && self.episode_threshold.is_none() {
+            return Err("the episode_count event requires episode_threshold".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// HTTP response compression and request body limits.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// gzip/br responses for clients that send `Accept-Encoding`.
+    pub compression: bool,
+    /// Largest accepted request body (HTTP and gRPC messages); larger requests get 413.
+    pub max_body_bytes: usize,
+    /// Largest accepted `POST /v1/admin/restore` archive.
+    pub max_restore_bytes: usize,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            compression: true,
+            max_body_bytes: 16 * 1024 * 1024,
+            max_restore_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Cross-origin access for browser clients. No origins (the default) means no CORS headers,
+/// so browsers block cross-origin calls.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Allowed origins, e.g. `https://app.example.com`; `*` allows any.
+    pub allowed_origins: Vec<String>,
+    /// `*` allows any.
+    pub allowed_methods: Vec<String>,
+    /// `*` allows any.
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache a preflight response.
+    pub max_age_secs: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: ["GET", "POST", "DELETE"].map(String::from).to_vec(),
+            allowed_headers: ["authorization", "content-type", "x-api-key", "x-request-id"]
+                .map(String::from)
+                .to_vec(),
+            max_age_secs: 600,
+        }
+    }
+}
+
+/// Split a comma-separated env value, dropping empty items.
+fn split_list(v: &str) -> Vec<String> {
+    v.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Periodic tenant snapshots to S3-compatible object storage; enabled by `bucket`.
+/// Credentials come from the standard `AWS_*` environment variables.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub bucket: Option<String>,
+    /// Key prefix; snapshots are stored as `<prefix>/<tenant>/<tenant>-<timestamp>.tar.zst`.
+    pub prefix: String,
+    pub interval_secs: u64,
+    /// Endpoint for S3-compatible stores (MinIO, R2, ...); AWS when unset.
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            bucket: None,
+            prefix: "agent-mem".to_string(),
+            interval_secs: 3600,
+            endpoint: None,
+            region: None,
+        }
+    }
+}
+
+/// Rotation of the audit log file (`audit_log`).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Rotate once the live file would exceed this size.
+    pub max_bytes: Option<u64>,
+    /// Rotate once the live file is this old.
+    pub max_age_secs: Option<u64>,
+    /// Rotated files to retain; all are kept when unset.
+    pub keep: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl Config {
+    /// Resolve configuration from all sources and validate it.
+    pub fn load(cli: &Cli) -> Result<Self, String> {
+        let mut config = match cli.config {
+            Some(ref path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        config.apply_cli(cli)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        let yaml = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("yaml" | "yml")
+        );
+        if yaml {
+            serde_yaml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+        } else {
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+        }
+    }
+
+    fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(v) = env_parse("AGENT_MEM_BIND")? {
+            self.bind = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_GRPC_BIND")? {
+            self.grpc_bind = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_DIM")? {
+            self.dim = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_DATA_DIR")? {
+            self.data_dir = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_AUDIT_LOG")? {
+            self.audit_log = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_AUDIT_MAX_BYTES")? {
+            self.audit.max_bytes = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_AUDIT_MAX_AGE_SECS")? {
+            self.audit.max_age_secs = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_AUDIT_KEEP")? {
+            self.audit.keep = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_LOG_FORMAT")? {
+            self.log_format = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_AUTH_MODE")? {
+            self.auth.mode = v;
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_API_KEY") {
+            self.auth.api_key = Some(v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_API_KEYS") {
+            self.auth.api_keys = split_list(&v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_KEYS_FILE")? {
+            self.auth.keys_file = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_RATE_LIMIT")? {
+            self.rate_limit.max_requests = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_RATE_WINDOW_SECS")? {
+            self.rate_limit.window_secs = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_QUOTA_MAX_EPISODES")? {
+            self.quotas.max_episodes = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_QUOTA_MAX_BYTES")? {
+            self.quotas.max_bytes = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_QUOTA_MAX_DIM")? {
+            self.quotas.max_dim = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_LIMIT_MAX_METADATA_BYTES")? {
+            self.limits.max_metadata_bytes = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_LIMIT_MAX_STEPS")? {
+            self.limits.max_steps = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_LIMIT_MAX_TAGS")? {
+            self.limits.max_tags = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_LIMIT_MAX_TASK_ID_LEN")? {
+            self.limits.max_task_id_len = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_AUTO_TIMESTAMP")? {
+            self.auto_timestamp = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_MAX_INFLIGHT_STORES")? {
+            self.concurrency.max_stores = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_MAX_INFLIGHT_QUERIES")? {
+            self.concurrency.max_queries = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_OVERLOAD_RETRY_AFTER_SECS")? {
+            self.concurrency.retry_after_secs = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_USAGE_INTERVAL_SECS")? {
+            self.usage.interval_secs = v;
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_USAGE_TOKEN") {
+            self.usage.token = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_TENANT_IDLE_SECS")? {
+            self.eviction.idle_secs = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_MAX_LOADED_TENANTS")? {
+            self.eviction.max_loaded = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_EVICT_INTERVAL_SECS")? {
+            self.eviction.interval_secs = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_TENANT_BACKEND")? {
+            self.tenant_defaults.backend = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_TENANT_INDEX")? {
+            self.tenant_defaults.index = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_WRITE_BEHIND_DIR")? {
+            self.write_behind.dir = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_WRITE_BEHIND_INTERVAL_SECS")? {
+            self.write_behind.interval_secs = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_RETENTION_MAX_AGE_SECS")? {
+            self.retention.max_age_secs = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_RETENTION_KEEP_NEWEST")? {
+            self.retention.keep_newest = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_RETENTION_INTERVAL_SECS")? {
+            self.retention.interval_secs = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_ARCHIVE_DIR")? {
+            self.retention.archive_dir = Some(v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_REPLICATION_TOKEN") {
+            self.replication.token = Some(v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_REPLICATION_LEADER") {
+            self.replication.leader = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_REPLICATION_POLL_MS")? {
+            self.replication.poll_interval_ms = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_COMPRESSION")? {
+            self.http.compression = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_MAX_BODY_BYTES")? {
+            self.http.max_body_bytes = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_MAX_RESTORE_BYTES")? {
+            self.http.max_restore_bytes = v;
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_CORS_ORIGINS") {
+            self.cors.allowed_origins = split_list(&v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_CORS_METHODS") {
+            self.cors.allowed_methods = split_list(&v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_CORS_HEADERS") {
+            self.cors.allowed_headers = split_list(&v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_EMBEDDING_URL") {
+            self.embedding.url = Some(v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_EMBEDDING_MODEL") {
+            self.embedding.model = v;
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_EMBEDDING_API_KEY") {
+            self.embedding.api_key = Some(v);
+        }
+        if let Some(v) = env_parse("AGENT_MEM_EMBEDDING_TIMEOUT_SECS")? {
+            self.embedding.timeout_secs = v;
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_MCP_TENANT") {
+            self.mcp.tenant = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_OPENAI_VECTOR_STORES")? {
+            self.openai.vector_stores = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_WEBHOOK_MAX_RETRIES")? {
+            self.webhooks.max_retries = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_WEBHOOK_QUOTA_WARN_RATIO")? {
+            self.webhooks.quota_warn_ratio = v;
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_WEBHOOK_ALLOWED_HOSTS") {
+            self.webhooks.allowed_hosts = split_list(&v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_SNAPSHOT_BUCKET") {
+            self.snapshots.bucket = Some(v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_SNAPSHOT_PREFIX") {
+            self.snapshots.prefix = v;
+        }
+        if let Some(v) = env_parse("AGENT_MEM_SNAPSHOT_INTERVAL_SECS")? {
+            self.snapshots.interval_secs = v;
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_SNAPSHOT_ENDPOINT") {
+            self.snapshots.endpoint = Some(v);
+        }
+        if let Ok(v) = std::env::var("AGENT_MEM_SNAPSHOT_REGION") {
+            self.snapshots.region = Some(v);
+        }
+        let cert = env_parse("AGENT_MEM_TLS_CERT")?;
+        let key = env_parse("AGENT_MEM_TLS_KEY")?;
+        self.set_tls(cert, key)
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) -> Result<(), String> {
+        if let Some(v) = cli.bind {
+            self.bind = v;
+        }
+        if let Some(v) = cli.grpc_bind {
+            self.grpc_bind = Some(v);
+        }
+        if let Some(v) = cli.dim {
+            self.dim = v;
+        }
+        if let Some(ref v) = cli.data_dir {
+            self.data_dir = Some(v.clone());
+        }
+        if let Some(v) = cli.rate_limit {
+            self.rate_limit.max_requests = Some(v);
+        }
+        if let Some(ref v) = cli.audit_log {
+            self.audit_log = Some(v.clone());
+        }
+        if let Some(v) = cli.log_format {
+            self.log_format = v;
+        }
+        self.set_tls(cli.tls_cert.clone(), cli.tls_key.clone())
+    }
+
+    /// Override TLS paths; either half may come from an earlier source.
+    fn set_tls(&mut self, cert: Option<PathBuf>, key: Option<PathBuf>) -> Result<(), String> {
+        if cert.is_none() && key.is_none() {
+            return Ok(());
+        }
+        let (old_cert, old_key) = match self.tls.take() {
+            Some(t) => (Some(t.cert), Some(t.key)),
+            None => (None, None),
+        };
+        match (cert.or(old_cert), key.or(old_key)) {
+            (Some(cert), Some(key)) => {
+                self.tls = Some(TlsConfig { cert, key });
+                Ok(())
+            }
+            _ => Err("TLS requires both a certificate and a key".to_string()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.dim == 0 {
+            return Err("dim must be greater than 0".to_string());
+        }
+        if self.grpc_bind == Some(self.bind) {
+            return Err("grpc_bind must differ from bind".to_string());
+        }
+        if self.rate_limit.window_secs == 0 {
+            return Err("rate_limit.window_secs must be greater than 0".to_string());
+        }
+        if self.concurrency.max_stores == Some(0) || self.concurrency.max_queries == Some(0) {
+            return Err(
+                "concurrency.max_stores and concurrency.max_queries must be greater than 0"
+                    .to_string(),
+            );
+        }
+        if self.usage.interval_secs == 0 {
+            return Err("usage.interval_secs must be greater than 0".to_string());
+        }
+        if self.usage.token.as_deref() == Some("") {
+            return Err("usage.token must not be empty".to_string());
+        }
+        if self.eviction.interval_secs == 0 {
+            return Err("eviction.interval_secs must be greater than 0".to_string());
+        }
+        if self.write_behind.interval_secs == 0 {
+            return Err("write_behind.interval_secs must be greater than 0".to_string());
+        }
+        self.tenant_defaults
+            .validate(self.data_dir.is_some())
+            .map_err(|e| format!("tenant_defaults: {e}"))?;
+        for (id, settings) in &self.tenants {
+            settings
+                .validate(self.data_dir.is_some())
+                .map_err(|e| format!("tenants.{id}: {e}"))?;
+        }
+        if self.retention.interval_secs == 0 {
+            return Err("retention.interval_secs must be greater than 0".to_string());
+        }
+        if self.audit.max_bytes == Some(0) || self.audit.max_age_secs == Some(0) {
+            return Err(
+                "audit.max_bytes and audit.max_age_secs must be greater than 0".to_string(),
+            );
+        }
+        if self.retention.keep_newest == Some(0) {
+            return Err("retention.keep_newest must be greater than 0".to_string());
+        }
+        for (id, policy) in &self.retention.tenants {
+            policy
+                .validate()
+                .map_err(|e| format!("retention.tenants.{id}: {e}"))?;
+        }
+        if let Some(max_dim) = self.quotas.max_dim {
+            if max_dim < self.dim {
+                return Err(format!(
+                    "quotas.max_dim ({max_dim}) is smaller than dim ({})",
+                    self.dim
+                ));
+            }
+        }
+        if !self.auth.api_keys.is_empty() {
+            parse_api_keys(&self.auth.api_keys.join(","))
+                .map_err(|e| format!("auth.api_keys: {e}"))?;
+        }
+        if self.auth.mode == AuthMode::Keys && !self.has_keys() {
+            return Err("auth.mode = \"keys\" but no API keys are configured".to_string());
+        }
+        if let Some(ref leader) = self.replication.leader {
+            if !(leader.starts_with("http://") || leader.starts_with("https://")) {
+                return Err(format!(
+                    "replication.leader must be an http(s) URL, got {leader:?}"
+                ));
+            }
+            if self.replication.token.is_none() {
+                return Err("replication.leader requires replication.token".to_string());
+            }
+        }
+        if self.replication.poll_interval_ms == 0 {
+            return Err("replication.poll_interval_ms must be greater than 0".to_string());
+        }
+        if self.http.max_body_bytes == 0 || self.http.max_restore_bytes == 0 {
+            return Err(
+                "http.max_body_bytes and http.max_restore_bytes must be greater than 0".to_string(),
+            );
+        }
+        for origin in &self.cors.allowed_origins {
+            let valid = origin == "*"
+                || ((origin.starts_with("http://") || origin.starts_with("https://"))
+                    && !origin.ends_with('/')
+                    && axum::http::HeaderValue::from_str(origin).is_ok());
+            if !valid {
+                return Err(format!(
+                    "cors.allowed_origins: {origin:?} is not `*` or an origin like https://app.example.com"
+                ));
+            }
+        }
+        for method in &self.cors.allowed_methods {
+            if method != "*" && method.parse::<axum::http::Method>().is_err() {
+                return Err(format!("cors.allowed_methods: invalid method {method:?}"));
+            }
+        }
+        for header in &self.cors.allowed_headers {
+            if header != "*" && header.parse::<axum::http::HeaderName>().is_err() {
+                return Err(format!("cors.allowed_headers: invalid header {header:?}"));
+            }
+        }
+        if let Some(ref url) = self.embedding.url {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(format!("embedding.url must be an http(s) URL, got {url:?}"));
+            }
+        }
+        if self.embedding.timeout_secs == 0 {
+            return Err("embedding.timeout_secs must be greater than 0".to_string());
+        }
+        if self.mcp.tenant.is_empty() {
+            return Err("mcp.tenant must not be empty".to_string());
+        }
+        if self.openai.vector_stores && self.embedding.url.is_none() {
+            return Err("openai.vector_stores requires embedding.url".to_string());
+        }
+        if self.webhooks.timeout_ms == 0 {
+            return Err("webhooks.timeout_ms must be greater than 0".to_string());
+        }
+        if !(self.webhooks.quota_warn_ratio > 0.0 && self.webhooks.quota_warn_ratio <= 1.0) {
+            return Err("webhooks.quota_warn_ratio must be in (0, 1]".to_string());
+        }
+        for (id, hooks) in &self.webhooks.tenants {
+            for hook in hooks {
+                hook.validate()
+                    .map_err(|e| format!("webhooks.tenants.{id}: {e}"))?;
+            }
+        }
+        if self.snapshots.interval_secs == 0 {
+            return Err("snapshots.interval_secs must be greater than 0".to_string());
+        }
+        if self.snapshots.bucket.as_deref() == Some("") {
+            return Err("snapshots.bucket must not be empty".to_string());
+        }
+        if let Some(ref tls) = self.tls {
+            if cfg!(not(feature = "tls")) {
+                return Err(
+                    "TLS configured but the server was built without the `tls` feature".to_string(),
+                );
+            }
+            for path in [&tls.cert, &tls.key] {
+                if !path.is_file() {
+                    return Err(format!("TLS file not found: {}", path.display()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_keys(&self) -> bool {
+        self.auth.api_key.is_some()
+            || !self.auth.api_keys.is_empty()
+            || self.auth.keys_file.is_some()
+    }
+
+    /// Whether requests must present a configured key.
+    pub fn auth_required(&self) -> bool {
+        match self.auth.mode {
+            AuthMode::Auto => self.has_keys(),
+            AuthMode::Keys => true,
+            AuthMode::Dev => false,
+        }
+    }
+
+    /// TOML rendering with API keys, tokens and webhook secrets redacted, for `--print-config`.
+    pub fn to_redacted_toml(&self) -> String {
+        let mut redacted = self.clone();
+        if redacted.auth.api_key.is_some() {
+            redacted.auth.api_key = Some("<redacted>".to_string());
+        }
+        for spec in redacted.auth.api_keys.iter_mut() {
+            let rest = spec.split_once(':').map(|(_, r)| r).unwrap_or("");
+            *spec = format!("<redacted>:{rest}");
+        }
+        if redacted.replication.token.is_some() {
+            redacted.replication.token = Some("<redacted>".to_string());
+        }
+        if redacted.usage.token.is_some() {
+            redacted.usage.token = Some("<redacted>".to_string());
+        }
+        if redacted.embedding.api_key.is_some() {
+            redacted.embedding.api_key = Some("<redacted>".to_string());
+        }
+        for webhook in redacted.webhooks.tenants.values_mut().flatten() {
+            if webhook.secret.is_some() {
+                webhook.secret = Some("<redacted>".to_string());
+            }
+        }
+        toml::to_string_pretty(&redacted).unwrap_or_else(|e| format!("# failed to render: {e}"))
+    }
+}
+
+/// Parse an env var if set. Set-but-invalid is an error rather than silently ignored.
+fn env_parse<T: std::str::FromStr>(name: &str) -> Result<Option<T>, String> {
+    match std::env::var(name) {
+        Ok(v) => v
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid value for {name}: {v:?}")),
+        Err(_) => Ok(None),
+    }
+}
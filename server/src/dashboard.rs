@@ -0,0 +1,398 @@
+//! `GET /dashboard`: a small web UI with health, usage, a per-tenant breakdown, a recent
+//! query latency sparkline, config, and a query console for debugging retrieval.
+//!
+//! The page itself is unauthenticated (like `/metrics`) and shows only server-wide totals.
+//! The per-tenant table is fetched from `GET /dashboard/tenants` with a key the operator
+//! types in: `usage.token` lists every loaded tenant, an admin key only its own. The page
+//! sends typed keys with each request and never stores them.
+
+use crate::{authenticate, extract_api_key, ApiError, AppState, Scope};
+use axum::{extract::State, http::HeaderMap, response::Html, Json};
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// Tenant rows shown, most recently active first.
+const MAX_TENANT_ROWS: usize = 50;
+
+const SPARK_WIDTH: f64 = 300.0;
+const SPARK_HEIGHT: f64 = 40.0;
+
+/// Client-side tenant table and query console, both sent with the key typed into the page.
+const CONSOLE_JS: &str = r#"
+function humanBytes(bytes) {
+  const units = ['B', 'KiB', 'MiB', 'GiB', 'TiB'];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit += 1;
+  }
+  return unit === 0 ? bytes + ' B' : value.toFixed(1) + ' ' + units[unit];
+}
+
+function ago(secs) {
+  if (secs < 60) return secs + 's ago';
+  if (secs < 3600) return Math.floor(secs / 60) + 'm ago';
+  if (secs < 86400) return Math.floor(secs / 3600) + 'h ago';
+  return Math.floor(secs / 86400) + 'd ago';
+}
+
+document.getElementById('tt').addEventListener('submit', async (event) => {
+  event.preventDefault();
+  const out = document.getElementById('tt-out');
+  const status = document.getElementById('tt-status');
+  out.replaceChildren();
+  let res, data;
+  try {
+    res = await fetch('/dashboard/tenants', {
+      headers: { 'Authorization': 'Bearer ' + document.getElementById('tt-key').value },
+    });
+    data = await res.json().catch(() => ({}));
+  } catch (err) {
+    status.textContent = 'Request failed: ' + err;
+    return;
+  }
+  if (!res.ok) {
+    status.textContent = res.status + ': ' + (data.error || res.statusText);
+    return;
+  }
+  const tenants = data.tenants || [];
+  status.textContent = tenants.length
+    ? (data.more ? data.more + ' more not shown' : '')
+    : 'No tenants loaded.';
+  tenants.forEach((t) => {
+    const row = document.createElement('tr');
+    [t.tenant_id, t.backend, t.episodes, humanBytes(t.stored_bytes), t.stored, t.queries,
+      ago(t.idle_secs)].forEach((value, i) => {
+      const cell = document.createElement('td');
+      if (i >= 2 && i <= 5) cell.className = 'num';
+      cell.textContent = value;
+      row.appendChild(cell);
+    });
+    out.appendChild(row);
+  });
+});
+
+document.getElementById('qc').addEventListener('submit', async (event) => {
+  event.preventDefault();
+  const out = document.getElementById('qc-out');
+  const status = document.getElementById('qc-status');
+  out.replaceChildren();
+  const input = document.getElementById('qc-emb').value.trim();
+  const body = { top_k: Number(document.getElementById('qc-k').value) || 5 };
+  // A JSON array is an embedding; anything else is text for server-side embedding.
+  try {
+    const parsed = JSON.parse(input);
+    if (!Array.isArray(parsed)) throw new Error();
+    body.query_embedding = parsed;
+  } catch (_) {
+    body.text = input;
+  }
+  const tags = document.getElementById('qc-tags').value
+    .split(',').map((t) => t.trim()).filter(Boolean);
+  if (tags.length) body.tags_any = tags;
+  const started = performance.now();
+  let res, data;
+  try {
+    res = await fetch('/v1/query', {
+      method: 'POST',
+      headers: {
+        'Content-Type': 'application/json',
+        'Authorization': 'Bearer ' + document.getElementById('qc-key').value,
+      },
+      body: JSON.stringify(body),
+    });
+    data = await res.json().catch(() => ({}));
+  } catch (err) {
+    status.textContent = 'Request failed: ' + err;
+    return;
+  }
+  const ms = (performance.now() - started).toFixed(1);
+  if (!res.ok) {
+    status.textContent = res.status + ': ' + (data.error || res.statusText);
+    return;
+  }
+  const episodes = data.episodes || [];
+  status.textContent = episodes.length + ' result(s) in ' + ms + ' ms';
+  episodes.forEach((ep, i) => {
+    const row = document.createElement('tr');
+    [i + 1, ep.task_id, ep.reward, (ep.tags || []).join(', '), ep.id].forEach((value) => {
+      const cell = document.createElement('td');
+      cell.textContent = value;
+      row.appendChild(cell);
+    });
+    out.appendChild(row);
+  });
+});
+"#;
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = u;
+    }
+    format!("{value:.1} {unit}")
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// Inline SVG sparkline of recent query latencies, with last/median/max labels.
+fn sparkline(latencies: &[Duration]) -> String {
+    if latencies.is_empty() {
+        return r#"<p class="muted">No queries yet.</p>"#.to_string();
+    }
+    let max = latencies.iter().copied().max().unwrap_or_default();
+    let scale = if max.is_zero() { 1.0 } else { ms(max) };
+    let step = if latencies.len() > 1 {
+        SPARK_WIDTH / (latencies.len() - 1) as f64
+    } else {
+        0.0
+    };
+    let mut points = String::new();
+    for (i, latency) in latencies.iter().enumerate() {
+        let x = i as f64 * step;
+        let y = SPARK_HEIGHT - ms(*latency) / scale * (SPARK_HEIGHT - 2.0) - 1.0;
+        let _ = write!(points, "{x:.1},{y:.1} ");
+    }
+    let mut sorted = latencies.to_vec();
+    sorted.sort();
+    let median = sorted[sorted.len() / 2];
+    let last = latencies[latencies.len() - 1];
+    format!(
+        r#"<svg class="spark" viewBox="0 0 {SPARK_WIDTH} {SPARK_HEIGHT}" preserveAspectRatio="none" role="img" aria-label="Recent query latency"><polyline points="{}" /></svg>
+    <div class="metric"><span>Last / median / max ({} queries)</span><span>{:.2} / {:.2} / {:.2} ms</span></div>"#,
+        points.trim_end(),
+        latencies.len(),
+        ms(last),
+        ms(median),
+        ms(max),
+    )
+}
+
+/// One loaded tenant in the dashboard's tenant table.
+#[derive(Serialize)]
+pub struct TenantRow {
+    tenant_id: String,
+    backend: &'static str,
+    episodes: usize,
+    stored_bytes: u64,
+    /// Episodes stored since server start.
+    stored: u64,
+    /// Queries since server start.
+    queries: u64,
+    /// Seconds since the tenant was last used.
+    idle_secs: u64,
+}
+
+#[derive(Serialize)]
+pub struct TenantRows {
+    /// Most recently active first.
+    tenants: Vec<TenantRow>,
+    /// Tenants past `MAX_TENANT_ROWS` left out.
+    more: usize,
+}
+
+/// `GET /dashboard/tenants`: every loaded tenant for `usage.token`, or the caller's own
+/// tenant for an admin key.
+pub async fn tenants(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<TenantRows>, ApiError> {
+    let key = extract_api_key(&headers);
+    let only = if state.usage.is_operator(key.as_deref()) {
+        None
+    } else {
+        let (tenant_id, scopes) = authenticate(&state, key)?;
+        scopes.require(Scope::Admin)?;
+        Some(tenant_id)
+    };
+    let mut rows: Vec<TenantRow> = {
+        let tenants = state.tenants.read().await;
+        tenants
+            .iter()
+            .filter(|(id, _)| only.as_deref().is_none_or(|only| only == *id))
+            .map(|(id, t)| {
+                let (stored, queries) = state.metrics.tenant_counters(id);
+                TenantRow {
+                    tenant_id: id.to_string(),
+                    backend: t.backend.kind(),
+                    episodes: t.backend.len(),
+                    stored_bytes: t.stored_bytes,
+                    stored,
+                    queries,
+                    idle_secs: tenants
+                        .last_access(id)
+                        .map_or(0, |last| last.elapsed().as_secs()),
+                }
+            })
+            .collect()
+    };
+    rows.sort_by_key(|r| r.idle_secs);
+    let more = rows.len().saturating_sub(MAX_TENANT_ROWS);
+    rows.truncate(MAX_TENANT_ROWS);
+    Ok(Json(TenantRows {
+        tenants: rows,
+        more,
+    }))
+}
+
+pub async fn dashboard(State(state): State<AppState>) -> Html<String> {
+    let requests = state.metrics.requests_total.load(Ordering::Relaxed);
+    let store_episodes = state.metrics.store_episodes_total.load(Ordering::Relaxed);
+    let queries = state.metrics.query_total.load(Ordering::Relaxed);
+
+    let (loaded, total_episodes, total_bytes) = {
+        let tenants = state.tenants.read().await;
+        tenants
+            .iter()
+            .fold((0, 0, 0), |(n, episodes, bytes), (_, t)| {
+                (n + 1, episodes + t.backend.len(), bytes + t.stored_bytes)
+            })
+    };
+    let latency = sparkline(&state.metrics.recent_query_latencies());
+
+    let rate_limit_str = state
+        .rate_limit
+        .as_ref()
+        .and_then(|(_, max, dur)| max.map(|max| format!("{max} req / {}s", dur.as_secs())))
+        .unwrap_or_else(|| "disabled".to_string());
+    let audit_str = if state.audit_log.is_some() {
+        "enabled"
+    } else {
+        "disabled"
+    };
+    let api_key_str = match state.api_keys {
+        Some(ref keys) => format!(
+            "{} configured",
+            keys.len() + state.keys_file.as_ref().map_or(0, |f| f.len())
+        ),
+        None => "not set (dev)".to_string(),
+    };
+    let data_dir = state
+        .data_dir
+        .as_ref()
+        .map(|p| escape_html(&p.display().to_string()))
+        .unwrap_or_else(|| "—".to_string());
+
+    let html = format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>Agent Memory DB — Dashboard</title>
+  <style>
+    :root {{ font-family: system-ui, -apple-system, sans-serif; font-size: 16px; }}
+    body {{ max-width: 880px; margin: 2rem auto; padding: 0 1rem; color: #1a1a1a; }}
+    h1 {{ font-size: 1.5rem; font-weight: 600; margin-bottom: 1.5rem; }}
+    section {{ margin-bottom: 1.5rem; }}
+    h2 {{ font-size: 0.875rem; font-weight: 600; text-transform: uppercase; letter-spacing: 0.05em; color: #666; margin-bottom: 0.5rem; }}
+    .metric {{ display: flex; justify-content: space-between; padding: 0.5rem 0; border-bottom: 1px solid #eee; }}
+    .metric span:last-child {{ font-variant-numeric: tabular-nums; font-weight: 500; }}
+    .status {{ color: #0a0; font-weight: 500; }}
+    .muted {{ color: #888; }}
+    table {{ width: 100%; border-collapse: collapse; font-size: 0.875rem; }}
+    th, td {{ text-align: left; padding: 0.375rem 0.5rem; border-bottom: 1px solid #eee; }}
+    th {{ font-weight: 600; color: #666; }}
+    td.num, th.num {{ text-align: right; font-variant-numeric: tabular-nums; }}
+    .spark {{ width: 100%; height: 48px; }}
+    .spark polyline {{ fill: none; stroke: #0066cc; stroke-width: 1.5; vector-effect: non-scaling-stroke; }}
+    form {{ display: grid; gap: 0.5rem; }}
+    input, textarea, button {{ font: inherit; padding: 0.375rem 0.5rem; }}
+    textarea {{ min-height: 4rem; font-family: ui-monospace, monospace; font-size: 0.875rem; }}
+    button {{ justify-self: start; }}
+    a {{ color: #0066cc; text-decoration: none; }}
+    a:hover {{ text-decoration: underline; }}
+  </style>
+</head>
+<body>
+  <h1>Agent Memory DB</h1>
+
+  <section>
+    <h2>Health</h2>
+    <div class="metric"><span>Status</span><span class="status">ok</span></div>
+    <div class="metric"><span><a href="/readyz">/readyz</a></span><span></span></div>
+    <div class="metric"><span><a href="/metrics">/metrics</a></span><span>Prometheus</span></div>
+  </section>
+
+  <section>
+    <h2>Usage</h2>
+    <div class="metric"><span>API requests</span><span>{requests}</span></div>
+    <div class="metric"><span>Episodes stored</span><span>{store_episodes}</span></div>
+    <div class="metric"><span>Queries</span><span>{queries}</span></div>
+    <div class="metric"><span>Loaded tenants</span><span>{loaded}</span></div>
+    <div class="metric"><span>Episodes in loaded tenants</span><span>{total_episodes} ({total_bytes})</span></div>
+  </section>
+
+  <section>
+    <h2>Query latency</h2>
+    {latency}
+  </section>
+
+  <section>
+    <h2>Tenants</h2>
+    <form id="tt">
+      <input id="tt-key" type="password" placeholder="usage.token (all tenants) or admin API key (its tenant)" autocomplete="off" required>
+      <button type="submit">Load</button>
+    </form>
+    <p id="tt-status" class="muted"></p>
+    <table>
+      <thead><tr><th>Tenant</th><th>Backend</th><th class="num">Episodes</th><th class="num">Storage</th><th class="num">Stored</th><th class="num">Queries</th><th>Last activity</th></tr></thead>
+      <tbody id="tt-out"></tbody>
+    </table>
+    <p class="muted">Loaded tenants only; Stored and Queries count since server start.</p>
+  </section>
+
+  <section>
+    <h2>Query console</h2>
+    <form id="qc">
+      <input id="qc-key" type="password" placeholder="API key" autocomplete="off" required>
+      <textarea id="qc-emb" placeholder="Query embedding as a JSON array ({dim} numbers), or text if server-side embedding is enabled" required></textarea>
+      <input id="qc-k" type="number" min="1" value="5" aria-label="top_k">
+      <input id="qc-tags" placeholder="tags_any (comma-separated, optional)">
+      <button type="submit">Query</button>
+    </form>
+    <p id="qc-status" class="muted"></p>
+    <table>
+      <thead><tr><th>#</th><th>Task</th><th>Reward</th><th>Tags</th><th>ID</th></tr></thead>
+      <tbody id="qc-out"></tbody>
+    </table>
+  </section>
+
+  <section>
+    <h2>Config</h2>
+    <div class="metric"><span>Embedding dim</span><span>{dim}</span></div>
+    <div class="metric"><span>API key</span><span>{api_key_str}</span></div>
+    <div class="metric"><span>Rate limit</span><span>{rate_limit_str}</span></div>
+    <div class="metric"><span>Audit log</span><span>{audit_str}</span></div>
+    <div class="metric"><span>Data dir</span><span>{data_dir}</span></div>
+  </section>
+  <script>{CONSOLE_JS}</script>
+</body>
+</html>"##,
+        total_bytes = human_bytes(total_bytes),
+        dim = state.default_dim,
+    );
+    Html(html)
+}
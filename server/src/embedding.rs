@@ -0,0 +1,175 @@
+//! Server-side text embedding, so clients can send `text` instead of `state_embedding` /
+//! `query_embedding`.
+//!
+//! Calls an OpenAI-compatible `POST <url>/embeddings` (OpenAI, Azure-style gateways, or a
+//! local model behind Ollama, vLLM or text-embeddings-inference). The model's output
+//! dimension must equal the server's `dim`.
+
+use crate::config::EmbeddingConfig;
+use crate::ApiError;
+use axum::{http::StatusCode, Json};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Inputs sent per provider request.
+const MAX_BATCH: usize = 256;
+
+pub struct Embedder {
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+    dim: usize,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: usize,
+}
+
+fn bad_gateway(msg: String) -> ApiError {
+    (
+        StatusCode::BAD_GATEWAY,
+        Json(serde_json::json!({"error": format!("Embedding provider: {msg}")})),
+    )
+}
+
+fn bad_request(msg: &str) -> ApiError {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({"error": msg})),
+    )
+}
+
+impl Embedder {
+    /// `None` when no embedding URL is configured.
+    pub fn new(config: &EmbeddingConfig, dim: usize) -> Result<Option<Self>, String> {
+        let Some(ref url) = config.url else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Some(Self {
+            endpoint: format!("{}/embeddings", url.trim_end_matches('/')),
+            model: config.model.clone(),
+            api_key: config.api_key.clone(),
+            dim,
+            client,
+        }))
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Embed `texts`, in order.
+    #[tracing::instrument(skip_all, fields(model = %self.model, inputs = texts.len()))]
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ApiError> {
+        let mut out = Vec::with_capacity(texts.len());
+        for chunk in texts.chunks(MAX_BATCH) {
+            let mut request = self.client.post(&self.endpoint).json(&serde_json::json!({
+                "model": self.model,
+                "input": chunk,
+            }));
+            if let Some(ref key) = self.api_key {
+                request = request.bearer_auth(key);
+            }
+            let response = request
+                .send()
+                .await
+                .map_err(|e| bad_gateway(e.to_string()))?;
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                let body: String = body.chars().take(500).collect();
+                return Err(bad_gateway(format!("HTTP {status}: {body}")));
+            }
+            let mut parsed: EmbeddingsResponse = response
+                .json()
+                .await
+                .map_err(|e| bad_gateway(format!("invalid response: {e}")))?;
+            if parsed.data.len() != chunk.len() {
+                return Err(bad_gateway(format!(
+                    "returned {} embeddings for {} inputs",
+                    parsed.data.len(),
+                    chunk.len()
+                )));
+            }
+            parsed.data.sort_by_key(|d| d.index);
+            for data in parsed.data {
+                if data.embedding.len() != self.dim {
+                    return Err((
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(serde_json::json!({"error": format!(
+                            "Embedding model {} returned {} dimensions but the server dim is {}",
+                            self.model,
+                            data.embedding.len(),
+                            self.dim
+                        )})),
+                    ));
+                }
+                out.push(data.embedding);
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// The `embedding_model` to tag an episode with: the server's model when it embeds the
+/// episode's `text`, else the one the client sent with its embedding.
+pub fn model_for(
+    embedder: Option<&Embedder>,
+    text: Option<&str>,
+    sent: Option<String>,
+) -> Option<String> {
+    match (embedder, text) {
+        (Some(embedder), Some(_)) => Some(embedder.model().to_string()),
+        _ => sent,
+    }
+}
+
+/// Resolve one embedding per item: given directly, or embedded from its text (all texts in
+/// as few provider calls as possible). Each item must have exactly one of the two.
+pub async fn resolve(
+    embedder: Option<&Embedder>,
+    items: Vec<(Option<Vec<f32>>, Option<String>)>,
+    field: &str,
+) -> Result<Vec<Vec<f32>>, ApiError> {
+    let mut texts = Vec::new();
+    for (embedding, text) in &items {
+        match (embedding, text) {
+            (Some(_), None) => {}
+            (None, Some(text)) => texts.push(text.clone()),
+            (Some(_), Some(_)) => {
+                return Err(bad_request(&format!(
+                    "Send either {field} or text, not both"
+                )))
+            }
+            (None, None) => return Err(bad_request(&format!("Missing {field} (or text)"))),
+        }
+    }
+    let mut embedded = if texts.is_empty() {
+        Vec::new()
+    } else {
+        let Some(embedder) = embedder else {
+            return Err(bad_request(
+                "Server-side embedding is not configured (embedding.url); send embeddings instead of text",
+            ));
+        };
+        embedder.embed(&texts).await?
+    }
+    .into_iter();
+    Ok(items
+        .into_iter()
+        .map(|(embedding, _)| embedding.unwrap_or_else(|| embedded.next().unwrap_or_default()))
+        .collect())
+}
@@ -0,0 +1,77 @@
+//! `GET /v1/events`: Server-Sent Events stream of the tenant's audit events.
+//!
+//! Every `audit_log` call is also published here (whether or not `AGENT_MEM_AUDIT_LOG` is
+//! set), so operators can watch store/query/prune activity live instead of tailing the file.
+
+use crate::{AppState, AuditEntry};
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::{Stream, StreamExt};
+
+/// Events buffered per client before it is reported as lagged.
+const CHANNEL_CAPACITY: usize = 1024;
+
+pub type AuditBus = broadcast::Sender<Arc<AuditEntry>>;
+
+pub fn audit_bus() -> AuditBus {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+/// Filter from the query string, e.g. `?op=store_episode,query`.
+#[derive(Debug, Default, Deserialize)]
+pub struct EventsFilter {
+    /// Comma-separated audit ops to include; all ops when unset.
+    op: Option<String>,
+}
+
+impl EventsFilter {
+    fn matches(&self, op: &str) -> bool {
+        match self.op {
+            Some(ref ops) => ops.split(',').map(str::trim).any(|o| o == op),
+            None => true,
+        }
+    }
+}
+
+/// Stream `audit` events (JSON audit entries) for the caller's tenant. A client that falls
+/// behind gets a `lagged` event whose data is the number of skipped entries.
+#[utoipa::path(
+    get,
+    path = "/v1/events",
+    tag = "admin",
+    params(("op" = Option<String>, Query, description = "Comma-separated audit ops to include")),
+    responses((status = 200, description = "Server-Sent Events stream", content_type = "text/event-stream")),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn events(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Query(filter): Query<EventsFilter>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = BroadcastStream::new(state.audit_events.subscribe())
+        .filter_map(move |msg| match msg {
+            Ok(entry) if entry.tenant_id == tenant_id && filter.matches(&entry.op) => {
+                Event::default().event("audit").json_data(&*entry).ok()
+            }
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                Some(Event::default().event("lagged").data(skipped.to_string()))
+            }
+        })
+        .map(Ok);
+    // End the stream on shutdown so graceful shutdown isn't held open by idle clients.
+    let stream =
+        futures_util::StreamExt::take_until(
+            stream,
+            async move { state.shutdown_requested().await },
+        );
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
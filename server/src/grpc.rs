@@ -0,0 +1,504 @@
+//! gRPC API (tonic), served alongside HTTP when `grpc_bind` is configured.
+//!
+//! Uses the same API keys, scopes, rate limits, concurrency caps, quotas and tenant map as the
+//! HTTP routes.
+//! Message types mirror `proto/agent_mem.proto`; the service trait is generated in build.rs.
+
+use crate::concurrency::{Kind, Overloaded};
+use crate::{
+    audit_log, authenticate, check_rate_limit, existing_tenant_mut, prune_for_tenant,
+    prune_session_for_tenant, query_for_tenant, store_for_tenant, ApiError, AppState, Prune, Scope,
+};
+use agent_mem_db::{Episode, EpisodeStep, QueryOptions};
+use axum::http::StatusCode;
+use std::pin::Pin;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+pub mod pb {
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct EpisodeStep {
+        #[prost(uint32, tag = "1")]
+        pub index: u32,
+        #[prost(string, tag = "2")]
+        pub action: String,
+        #[prost(string, tag = "3")]
+        pub observation: String,
+        #[prost(float, tag = "4")]
+        pub step_reward: f32,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Episode {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub task_id: String,
+        #[prost(float, repeated, tag = "3")]
+        pub state_embedding: Vec<f32>,
+        #[prost(float, tag = "4")]
+        pub reward: f32,
+        #[prost(string, tag = "5")]
+        pub metadata_json: String,
+        #[prost(int64, optional, tag = "6")]
+        pub timestamp: Option<i64>,
+        #[prost(string, repeated, tag = "7")]
+        pub tags: Vec<String>,
+        #[prost(string, optional, tag = "8")]
+        pub source: Option<String>,
+        #[prost(string, optional, tag = "9")]
+        pub user_id: Option<String>,
+        #[prost(message, repeated, tag = "10")]
+        pub steps: Vec<EpisodeStep>,
+        #[prost(string, optional, tag = "11")]
+        pub session_id: Option<String>,
+        #[prost(float, optional, tag = "12")]
+        pub cost: Option<f32>,
+        #[prost(int64, optional, tag = "13")]
+        pub duration_ms: Option<i64>,
+        #[prost(string, optional, tag = "14")]
+        pub embedding_model: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct StoreEpisodeRequest {
+        #[prost(string, tag = "1")]
+        pub task_id: String,
+        #[prost(float, repeated, tag = "2")]
+        pub state_embedding: Vec<f32>,
+        #[prost(float, tag = "3")]
+        pub reward: f32,
+        #[prost(string, tag = "4")]
+        pub metadata_json: String,
+        #[prost(int64, optional, tag = "5")]
+        pub timestamp: Option<i64>,
+        #[prost(string, repeated, tag = "6")]
+        pub tags: Vec<String>,
+        #[prost(string, optional, tag = "7")]
+        pub source: Option<String>,
+        #[prost(string, optional, tag = "8")]
+        pub user_id: Option<String>,
+        #[prost(string, optional, tag = "9")]
+        pub session_id: Option<String>,
+        #[prost(float, optional, tag = "10")]
+        pub cost: Option<f32>,
+        #[prost(int64, optional, tag = "11")]
+        pub duration_ms: Option<i64>,
+        #[prost(string, optional, tag = "12")]
+        pub embedding_model: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct StoreEpisodeResponse {
+        #[prost(string, tag = "1")]
+        pub id: String,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct StoreEpisodesRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub episodes: Vec<StoreEpisodeRequest>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct StoreEpisodesResponse {
+        #[prost(string, repeated, tag = "1")]
+        pub ids: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct QueryRequest {
+        #[prost(float, repeated, tag = "1")]
+        pub query_embedding: Vec<f32>,
+        #[prost(float, tag = "2")]
+        pub min_reward: f32,
+        #[prost(uint32, tag = "3")]
+        pub top_k: u32,
+        #[prost(string, repeated, tag = "4")]
+        pub tags_any: Vec<String>,
+        #[prost(string, repeated, tag = "5")]
+        pub tags_all: Vec<String>,
+        #[prost(string, optional, tag = "6")]
+        pub task_id_prefix: Option<String>,
+        #[prost(int64, optional, tag = "7")]
+        pub time_after: Option<i64>,
+        #[prost(int64, optional, tag = "8")]
+        pub time_before: Option<i64>,
+        #[prost(string, optional, tag = "9")]
+        pub source: Option<String>,
+        #[prost(string, optional, tag = "10")]
+        pub user_id: Option<String>,
+        #[prost(float, optional, tag = "11")]
+        pub max_reward: Option<f32>,
+        #[prost(bool, optional, tag = "12")]
+        pub include_embeddings: Option<bool>,
+        #[prost(string, optional, tag = "13")]
+        pub session_id: Option<String>,
+        #[prost(float, optional, tag = "14")]
+        pub max_cost: Option<f32>,
+        #[prost(int64, optional, tag = "15")]
+        pub max_duration_ms: Option<i64>,
+        #[prost(uint32, optional, tag = "16")]
+        pub max_per_task: Option<u32>,
+        #[prost(string, repeated, tag = "17")]
+        pub tag_prefix_any: Vec<String>,
+        #[prost(string, optional, tag = "18")]
+        pub embedding_model: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct QueryResponse {
+        #[prost(message, repeated, tag = "1")]
+        pub episodes: Vec<Episode>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct PruneRequest {
+        #[prost(oneof = "prune_request::Policy", tags = "1, 2, 3, 4")]
+        pub policy: Option<prune_request::Policy>,
+    }
+
+    pub mod prune_request {
+        #[derive(Clone, PartialEq, ::prost::Oneof)]
+        pub enum Policy {
+            #[prost(int64, tag = "1")]
+            OlderThanMs(i64),
+            #[prost(uint64, tag = "2")]
+            KeepNewest(u64),
+            #[prost(uint64, tag = "3")]
+            KeepHighestReward(u64),
+            #[prost(string, tag = "4")]
+            SessionId(String),
+        }
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct PruneResponse {
+        #[prost(uint64, tag = "1")]
+        pub removed: u64,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct ExportRequest {}
+
+    include!(concat!(env!("OUT_DIR"), "/agent_mem.v1.AgentMemory.rs"));
+}
+
+use pb::agent_memory_server::{AgentMemory, AgentMemoryServer};
+
+/// Map an HTTP-style handler error onto a gRPC status.
+fn to_status((code, body): ApiError) -> Status {
+    let message = body
+        .0
+        .get("error")
+        .and_then(|e| e.as_str())
+        .unwrap_or("error")
+        .to_string();
+    match code {
+        StatusCode::BAD_REQUEST => Status::invalid_argument(message),
+        StatusCode::UNAUTHORIZED => Status::unauthenticated(message),
+        StatusCode::FORBIDDEN => Status::permission_denied(message),
+        StatusCode::NOT_FOUND => Status::not_found(message),
+        StatusCode::PAYLOAD_TOO_LARGE => Status::invalid_argument(message),
+        StatusCode::TOO_MANY_REQUESTS => Status::resource_exhausted(message),
+        _ => Status::internal(message),
+    }
+}
+
+/// Convert a store request; the error is the invalid-argument message.
+fn to_episode(req: pb::StoreEpisodeRequest) -> Result<Episode, String> {
+    let mut ep = Episode::new(&req.task_id, req.state_embedding, req.reward);
+    if !req.metadata_json.is_empty() {
+        ep.metadata =
+            serde_json::from_str(&req.metadata_json).map_err(|e| format!("metadata_json: {e}"))?;
+    }
+    ep.timestamp = req.timestamp;
+    ep.tags = (!req.tags.is_empty()).then_some(req.tags);
+    ep.source = req.source;
+    ep.user_id = req.user_id;
+    ep.session_id = req.session_id;
+    ep.embedding_model = req.embedding_model;
+    ep.cost = req.cost;
+    ep.duration_ms = req.duration_ms;
+    Ok(ep)
+}
+
+fn to_pb(ep: &Episode) -> pb::Episode {
+    pb::Episode {
+        id: ep.id.to_string(),
+        task_id: ep.task_id.clone(),
+        state_embedding: ep.state_embedding.clone(),
+        reward: ep.reward,
+        metadata_json: if ep.metadata.is_null() {
+            String::new()
+        } else {
+            ep.metadata.to_string()
+        },
+        timestamp: ep.timestamp,
+        tags: ep.tags.clone().unwrap_or_default(),
+        source: ep.source.clone(),
+        user_id: ep.user_id.clone(),
+        session_id: ep.session_id.clone(),
+        embedding_model: ep.embedding_model.clone(),
+        cost: ep.cost,
+        duration_ms: ep.duration_ms,
+        steps: ep
+            .steps
+            .iter()
+            .flatten()
+            .map(|s: &EpisodeStep| pb::EpisodeStep {
+                index: s.index,
+                action: s.action.clone(),
+                observation: s.observation.clone(),
+                step_reward: s.step_reward,
+            })
+            .collect(),
+    }
+}
+
+fn to_query_options(req: &pb::QueryRequest) -> QueryOptions {
+    let top_k = if req.top_k == 0 {
+        crate::default_top_k()
+    } else {
+        req.top_k as usize
+    };
+    let mut opts = QueryOptions::new(req.min_reward, top_k);
+    if let Some(max) = req.max_reward {
+        opts = opts.max_reward(max);
+    }
+    if let Some(include) = req.include_embeddings {
+        opts = opts.include_embeddings(include);
+    }
+    if !req.tags_any.is_empty() {
+        opts = opts.tags_any(req.tags_any.clone());
+    }
+    if !req.tags_all.is_empty() {
+        opts = opts.tags_all(req.tags_all.clone());
+    }
+    if !req.tag_prefix_any.is_empty() {
+        opts = opts.tag_prefix_any(req.tag_prefix_any.clone());
+    }
+    if let Some(ref prefix) = req.task_id_prefix {
+        opts = opts.task_id_prefix(prefix.clone());
+    }
+    if let Some(ts) = req.time_after {
+        opts = opts.time_after(ts);
+    }
+    if let Some(ts) = req.time_before {
+        opts = opts.time_before(ts);
+    }
+    if let Some(ref s) = req.source {
+        opts = opts.source(s.clone());
+    }
+    if let Some(ref u) = req.user_id {
+        opts = opts.user_id(u.clone());
+    }
+    if let Some(ref s) = req.session_id {
+        opts = opts.session_id(s.clone());
+    }
+    if let Some(ref model) = req.embedding_model {
+        opts = opts.embedding_model(model.clone());
+    }
+    if let Some(max) = req.max_cost {
+        opts = opts.max_cost(max);
+    }
+    if let Some(max) = req.max_duration_ms {
+        opts = opts.max_duration_ms(max);
+    }
+    if let Some(max) = req.max_per_task {
+        opts = opts.group_by_task(max as usize);
+    }
+    opts
+}
+
+pub struct GrpcService {
+    state: AppState,
+}
+
+impl GrpcService {
+    /// Authenticate from request metadata, apply the rate limit and check `scope`.
+    async fn authorize<T>(&self, request: &Request<T>, scope: Scope) -> Result<String, Status> {
+        let metadata = request.metadata();
+        let key = metadata
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .or_else(|| metadata.get("x-api-key").and_then(|v| v.to_str().ok()))
+            .map(String::from);
+        let (tenant_id, scopes) = authenticate(&self.state, key).map_err(to_status)?;
+        check_rate_limit(&self.state, &tenant_id)
+            .await
+            .map_err(to_status)?;
+        scopes.require(scope).map_err(to_status)?;
+        Ok(tenant_id)
+    }
+}
+
+type ExportStream = Pin<Box<dyn Stream<Item = Result<pb::Episode, Status>> + Send + 'static>>;
+
+#[tonic::async_trait]
+impl AgentMemory for GrpcService {
+    async fn store_episode(
+        &self,
+        request: Request<pb::StoreEpisodeRequest>,
+    ) -> Result<Response<pb::StoreEpisodeResponse>, Status> {
+        let tenant_id = self.authorize(&request, Scope::Write).await?;
+        let _permit = self
+            .state
+            .concurrency
+            .acquire(&tenant_id, Kind::Store)
+            .map_err(Overloaded::into_status)?;
+        let ep = to_episode(request.into_inner()).map_err(Status::invalid_argument)?;
+        let id = ep.id.to_string();
+        let task_id = ep.task_id.clone();
+        store_for_tenant(&self.state, &tenant_id, vec![ep])
+            .await
+            .map_err(to_status)?;
+        audit_log(
+            &self.state,
+            &tenant_id,
+            "store_episode",
+            Some(&task_id),
+            Some(1),
+            None,
+        );
+        Ok(Response::new(pb::StoreEpisodeResponse { id }))
+    }
+
+    async fn store_episodes(
+        &self,
+        request: Request<pb::StoreEpisodesRequest>,
+    ) -> Result<Response<pb::StoreEpisodesResponse>, Status> {
+        let tenant_id = self.authorize(&request, Scope::Write).await?;
+        let _permit = self
+            .state
+            .concurrency
+            .acquire(&tenant_id, Kind::Store)
+            .map_err(Overloaded::into_status)?;
+        let episodes = request
+            .into_inner()
+            .episodes
+            .into_iter()
+            .map(to_episode)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Status::invalid_argument)?;
+        let ids: Vec<String> = episodes.iter().map(|e| e.id.to_string()).collect();
+        store_for_tenant(&self.state, &tenant_id, episodes)
+            .await
+            .map_err(to_status)?;
+        audit_log(
+            &self.state,
+            &tenant_id,
+            "store_episodes",
+            None,
+            Some(ids.len()),
+            None,
+        );
+        Ok(Response::new(pb::StoreEpisodesResponse { ids }))
+    }
+
+    async fn query(
+        &self,
+        request: Request<pb::QueryRequest>,
+    ) -> Result<Response<pb::QueryResponse>, Status> {
+        let tenant_id = self.authorize(&request, Scope::Read).await?;
+        let _permit = self
+            .state
+            .concurrency
+            .acquire(&tenant_id, Kind::Query)
+            .map_err(Overloaded::into_status)?;
+        let req = request.into_inner();
+        let episodes = query_for_tenant(
+            &self.state,
+            &tenant_id,
+            &req.query_embedding,
+            to_query_options(&req),
+        )
+        .await
+        .map_err(to_status)?
+        .episodes;
+        Ok(Response::new(pb::QueryResponse {
+            episodes: episodes.iter().map(to_pb).collect(),
+        }))
+    }
+
+    async fn prune(
+        &self,
+        request: Request<pb::PruneRequest>,
+    ) -> Result<Response<pb::PruneResponse>, Status> {
+        use pb::prune_request::Policy;
+        let tenant_id = self.authorize(&request, Scope::Prune).await?;
+        let prune = match request.into_inner().policy {
+            Some(Policy::OlderThanMs(ts)) => Prune::OlderThan(ts),
+            Some(Policy::KeepNewest(n)) => Prune::KeepNewest(n as usize),
+            Some(Policy::KeepHighestReward(n)) => Prune::KeepHighestReward(n as usize),
+            Some(Policy::SessionId(session)) => {
+                let removed = prune_session_for_tenant(&self.state, &tenant_id, &session)
+                    .await
+                    .map_err(to_status)?;
+                return Ok(Response::new(pb::PruneResponse {
+                    removed: removed as u64,
+                }));
+            }
+            None => return Err(Status::invalid_argument("policy is required")),
+        };
+        let removed = prune_for_tenant(&self.state, &tenant_id, prune)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(pb::PruneResponse {
+            removed: removed as u64,
+        }))
+    }
+
+    type ExportStream = ExportStream;
+
+    async fn export(
+        &self,
+        request: Request<pb::ExportRequest>,
+    ) -> Result<Response<Self::ExportStream>, Status> {
+        let tenant_id = self.authorize(&request, Scope::Read).await?;
+        let episodes: Vec<pb::Episode> = {
+            let mut tenants = self.state.tenants.write().await;
+            let tenant =
+                existing_tenant_mut(&self.state, &mut tenants, &tenant_id).map_err(to_status)?;
+            match tenant.backend {
+                crate::TenantStore::Memory(ref db) => db.iter().map(to_pb).collect(),
+                crate::TenantStore::Disk(ref db) => db.iter().map(to_pb).collect(),
+            }
+        };
+        audit_log(
+            &self.state,
+            &tenant_id,
+            "export",
+            None,
+            Some(episodes.len()),
+            None,
+        );
+        Ok(Response::new(Box::pin(tokio_stream::iter(
+            episodes.into_iter().map(Ok),
+        ))))
+    }
+}
+
+/// Serve the gRPC API until the shutdown signal. `max_message_bytes` caps decoded requests.
+pub async fn serve(addr: std::net::SocketAddr, state: AppState, max_message_bytes: usize) {
+    tracing::info!("gRPC listening on {}", addr);
+    if let Err(e) = tonic::transport::Server::builder()
+        .layer(crate::request_id::set_layer())
+        .layer(
+            tower_http::trace::TraceLayer::new_for_grpc()
+                .make_span_with(crate::request_id::grpc_span),
+        )
+        .layer(crate::request_id::propagate_layer())
+        .add_service(
+            AgentMemoryServer::new(GrpcService {
+                state: state.clone(),
+            })
+            .max_decoding_message_size(max_message_bytes),
+        )
+        .serve_with_shutdown(addr, async move { state.shutdown_requested().await })
+        .await
+    {
+        tracing::error!(error = %e, "gRPC server failed");
+    }
+}
@@ -0,0 +1,172 @@
+//! Kubernetes-style probes. `/livez` only says the process is serving; `/readyz` runs deep
+//! checks (data_dir writable, tenant directories openable, background tasks alive) and
+//! returns 503 when any fails, or once shutdown has begun.
+
+use crate::{replication, AppState};
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+const PROBE_FILE: &str = ".readyz-probe";
+
+/// Long-running background tasks, tracked so readiness fails if one dies (e.g. panics).
+#[derive(Default)]
+pub struct Tasks {
+    running: Mutex<Vec<(&'static str, Arc<AtomicBool>)>>,
+}
+
+/// Clears the task's flag when its future completes or unwinds.
+struct Running(Arc<AtomicBool>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Tasks {
+    /// `tokio::spawn` a task that is expected to run until shutdown.
+    pub fn spawn<F>(&self, name: &'static str, task: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let flag = Arc::new(AtomicBool::new(true));
+        if let Ok(mut running) = self.running.lock() {
+            running.push((name, flag.clone()));
+        }
+        let guard = Running(flag);
+        tokio::spawn(async move {
+            let _guard = guard;
+            task.await
+        })
+    }
+
+    /// Names of tasks that have stopped.
+    fn stopped(&self) -> Vec<&'static str> {
+        self.running
+            .lock()
+            .map(|r| {
+                r.iter()
+                    .filter(|(_, flag)| !flag.load(Ordering::Relaxed))
+                    .map(|(name, _)| *name)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<(), String>) -> Self {
+        Self {
+            name,
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReadyResponse {
+    ready: bool,
+    checks: Vec<Check>,
+}
+
+/// Create, sync and remove a probe file.
+fn check_writable(data_dir: &Path) -> Result<(), String> {
+    let probe = data_dir.join(PROBE_FILE);
+    let result = std::fs::write(&probe, b"ok")
+        .and_then(|_| std::fs::File::open(&probe)?.sync_all())
+        .map_err(|e| format!("{} not writable: {e}", data_dir.display()));
+    let _ = std::fs::remove_file(&probe);
+    result
+}
+
+/// Every tenant directory must have a readable `meta.json` with the server's dim and, if
+/// present, a readable log; otherwise opening that tenant would fail. Loaded tenants are
+/// already open and are not re-checked beyond this.
+fn check_tenants(data_dir: &Path, dim: usize) -> Result<(), String> {
+    let entries =
+        std::fs::read_dir(data_dir).map_err(|e| format!("{}: {e}", data_dir.display()))?;
+    for entry in entries {
+        let dir = entry.map_err(|e| e.to_string())?.path();
+        let meta_path = dir.join("meta.json");
+        if !dir.is_dir() || !meta_path.exists() {
+            continue;
+        }
+        let meta_dim = std::fs::read_to_string(&meta_path)
+            .map_err(|e| e.to_string())
+            .and_then(|m| serde_json::from_str::<serde_json::Value>(&m).map_err(|e| e.to_string()))
+            .and_then(|m| m["dim"].as_u64().ok_or_else(|| "missing dim".to_string()))
+            .map_err(|e| format!("{}: {e}", meta_path.display()))?;
+        if meta_dim as usize != dim {
+            return Err(format!(
+                "{}: dim {meta_dim} does not match server dim {dim}",
+                meta_path.display()
+            ));
+        }
+        let log = dir.join(replication::EPISODES_LOG);
+        if log.exists() {
+            std::fs::File::open(&log).map_err(|e| format!("{}: {e}", log.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Liveness: the process is up and serving HTTP.
+pub async fn livez() -> &'static str {
+    "ok"
+}
+
+/// Readiness: 200 when every check passes, else 503 with the failing checks.
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<ReadyResponse>) {
+    let mut checks = Vec::new();
+    let shutting_down = *state.shutdown.borrow();
+    checks.push(Check::new(
+        "shutdown",
+        if shutting_down {
+            Err("shutting down".to_string())
+        } else {
+            Ok(())
+        },
+    ));
+    if let Some(data_dir) = state.data_dir.clone() {
+        let dim = state.default_dim;
+        let (writable, tenants) = tokio::task::spawn_blocking(move || {
+            (check_writable(&data_dir), check_tenants(&data_dir, dim))
+        })
+        .await
+        .unwrap_or_else(|e| (Err(e.to_string()), Err(e.to_string())));
+        checks.push(Check::new("data_dir", writable));
+        checks.push(Check::new("tenants", tenants));
+    }
+    let stopped = state.tasks.stopped();
+    checks.push(Check::new(
+        "background_tasks",
+        if stopped.is_empty() || shutting_down {
+            Ok(())
+        } else {
+            Err(format!("stopped: {}", stopped.join(", ")))
+        },
+    ));
+
+    let ready = checks.iter().all(|c| c.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(ReadyResponse { ready, checks }))
+}
@@ -0,0 +1,242 @@
+//! Static API key file (`auth.keys_file`, `AGENT_MEM_KEYS_FILE`): each key maps to a tenant
+//! and its scopes, and may carry that tenant's dim, quotas, rate limit and backend. TOML, or
+//! JSON when the extension is `.json`:
+//!
+//! ```toml
+//! [[keys]]
+//! key = "k-acme"
+//! tenant_id = "acme"
+//! scopes = ["read", "write"]   # default: admin
+//! dim = 768
+//! backend = "disk"
+//! rate_limit = 600             # requests per rate_limit.window_secs
+//! quotas = { max_episodes = 100000 }
+//! ```
+//!
+//! The file is re-read on SIGHUP. Keys, quotas and rate limits apply at once; dim and
+//! backend apply to tenants created afterwards. A file that fails to load leaves the
+//! previous one in effect.
+
+use crate::config::{BackendKind, IndexKind, TenantSettings};
+use crate::{ApiKey, AppState, Quotas, Scope, Scopes};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    keys: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    key: String,
+    tenant_id: String,
+    scopes: Option<Vec<String>>,
+    dim: Option<usize>,
+    backend: Option<BackendKind>,
+    index: Option<IndexKind>,
+    rate_limit: Option<u64>,
+    quotas: Option<Quotas>,
+}
+
+impl Entry {
+    fn has_settings(&self) -> bool {
+        self.dim.is_some()
+            || self.backend.is_some()
+            || self.index.is_some()
+            || self.rate_limit.is_some()
+            || self.quotas.is_some()
+    }
+}
+
+/// A tenant's settings from the file; unset fields fall back to the server config.
+#[derive(Clone, Debug, Default)]
+pub struct TenantOverrides {
+    pub dim: Option<usize>,
+    /// Max requests per rate-limit window.
+    pub rate_limit: Option<u64>,
+    pub quotas: Quotas,
+    /// Backend and index, layered into `tenant_settings`.
+    pub settings: TenantSettings,
+}
+
+#[derive(Default)]
+struct Loaded {
+    keys: HashMap<String, ApiKey>,
+    tenants: HashMap<String, TenantOverrides>,
+}
+
+pub struct KeysFile {
+    path: PathBuf,
+    /// Whether `backend = "disk"` is allowed.
+    has_data_dir: bool,
+    loaded: RwLock<Arc<Loaded>>,
+}
+
+impl KeysFile {
+    /// Read `path`; fails on an unreadable or invalid file.
+    pub fn open(path: &Path, has_data_dir: bool) -> Result<Self, String> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            has_data_dir,
+            loaded: RwLock::new(Arc::new(parse(path, has_data_dir)?)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn current(&self) -> Arc<Loaded> {
+        self.loaded
+            .read()
+            .map(|l| l.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+
+    pub fn key(&self, key: &str) -> Option<ApiKey> {
+        self.current().keys.get(key).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.current().keys.len()
+    }
+
+    pub fn tenant(&self, tenant_id: &str) -> Option<TenantOverrides> {
+        self.current().tenants.get(tenant_id).cloned()
+    }
+
+    /// Backend and index settings of every tenant that has any.
+    pub fn tenant_settings(&self) -> HashMap<String, TenantSettings> {
+        self.current()
+            .tenants
+            .iter()
+            .filter(|(_, t)| t.settings != TenantSettings::default())
+            .map(|(id, t)| (id.clone(), t.settings.clone()))
+            .collect()
+    }
+
+    /// Re-read the file, keeping the current keys if it fails. Returns the number of keys.
+    pub fn reload(&self) -> Result<usize, String> {
+        let loaded = parse(&self.path, self.has_data_dir)?;
+        let n = loaded.keys.len();
+        match self.loaded.write() {
+            Ok(mut current) => *current = Arc::new(loaded),
+            Err(e) => *e.into_inner() = Arc::new(loaded),
+        }
+        Ok(n)
+    }
+}
+
+fn parse(path: &Path, has_data_dir: bool) -> Result<Loaded, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let json = path.extension().and_then(|e| e.to_str()) == Some("json");
+    let file: File = if json {
+        serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?
+    } else {
+        toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?
+    };
+
+    let mut loaded = Loaded::default();
+    for entry in file.keys {
+        let key = entry.key.trim();
+        let tenant_id = entry.tenant_id.trim();
+        if key.is_empty() || tenant_id.is_empty() {
+            return Err(format!(
+                "{}: every key needs a non-empty key and tenant_id",
+                path.display()
+            ));
+        }
+        let scopes = match entry.scopes {
+            Some(ref list) => list
+                .iter()
+                .map(|s| Scope::parse(s).ok_or_else(|| format!("unknown scope '{s}'")))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("{}: key for {tenant_id}: {e}", path.display()))?,
+            None => vec![Scope::Admin],
+        };
+        if entry.dim == Some(0) {
+            return Err(format!(
+                "{}: key for {tenant_id}: dim must be greater than 0",
+                path.display()
+            ));
+        }
+        if entry.has_settings() {
+            let overrides = TenantOverrides {
+                dim: entry.dim,
+                rate_limit: entry.rate_limit,
+                quotas: entry.quotas.clone().unwrap_or_default(),
+                settings: TenantSettings {
+                    backend: entry.backend,
+                    index: entry.index,
+                    ..TenantSettings::default()
+                },
+            };
+            overrides
+                .settings
+                .validate(has_data_dir)
+                .map_err(|e| format!("{}: key for {tenant_id}: {e}", path.display()))?;
+            if loaded
+                .tenants
+                .insert(tenant_id.to_string(), overrides)
+                .is_some()
+            {
+                return Err(format!(
+                    "{}: settings for tenant {tenant_id} are given on more than one key",
+                    path.display()
+                ));
+            }
+        }
+        let api_key = ApiKey {
+            tenant_id: tenant_id.to_string(),
+            scopes: Scopes(scopes),
+        };
+        if loaded.keys.insert(key.to_string(), api_key).is_some() {
+            return Err(format!(
+                "{}: duplicate key for tenant {tenant_id}",
+                path.display()
+            ));
+        }
+    }
+    Ok(loaded)
+}
+
+/// Re-read the keys file on every SIGHUP until shutdown.
+pub async fn reload_on_sighup(state: AppState) {
+    let Some(file) = state.keys_file.clone() else {
+        return;
+    };
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let Ok(mut hangup) = signal(SignalKind::hangup()) else {
+            tracing::warn!("cannot install SIGHUP handler; keys file will not be reloaded");
+            return;
+        };
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {}
+                _ = state.shutdown_requested() => return,
+            }
+            match file.reload() {
+                Ok(keys) => {
+                    state.tenant_settings.set_keys_file(file.tenant_settings());
+                    tracing::info!(keys, path = %file.path().display(), "reloaded keys file");
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "keys file reload failed; keeping previous keys")
+                }
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = file;
+        state.shutdown_requested().await;
+    }
+}
@@ -8,128 +8,429 @@
 //!     -d '{"task_id":"t1","state_embedding":[0.1,0.1,0.1,0.1,0.1,0.1,0.1,0.1,0.1,0.1,0.1,0.1,0.1,0.1,0.1,0.1],"reward":0.9}' \
 //!     http://localhost:8080/v1/episodes
 
-use agent_mem_db::{AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, QueryOptions};
+mod audit;
+mod backup;
+mod concurrency;
+mod config;
+mod dashboard;
+mod embedding;
+mod events;
+mod grpc;
+mod health;
+mod keys_file;
+mod mcp;
+mod metrics;
+mod ndjson;
+mod openai;
+mod openapi;
+mod replication;
+mod request_id;
+mod retention;
+mod snapshot;
+mod subscribe;
+mod telemetry;
+mod tenant_settings;
+mod usage;
+mod vectorstore;
+mod webhooks;
+mod write_behind;
+
+use config::{BackendKind, IndexKind};
+use metrics::Metrics;
+
+use agent_mem_db::{
+    tenant_dir_name, AgentMemDB, AgentMemError, DiskArchive, DiskOptions, Episode, EpisodeLimits,
+    EpisodeStore, MetadataRange, MultiTenantMemDB, QueryOptions, QueryResults, TagSubtree,
+    TenantEntry, TenantOptions, TenantStore, WindowStats,
+};
 use axum::{
-    extract::State,
+    extract::{DefaultBodyLimit, State},
     http::{Request, StatusCode},
     middleware::Next,
-    response::{Html, IntoResponse, Response},
-    routing::{get, post},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
     Json, Router,
 };
+use clap::Parser;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
+use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use utoipa::{IntoParams, ToSchema};
 
-/// Per-tenant backend: in-memory or disk-backed.
-enum TenantBackend {
-    InMemory(AgentMemDB),
-    Disk(AgentMemDBDisk),
+/// Approximate stored size of a store: sum of serialized episode sizes (matches the disk log).
+fn compute_stored_bytes(backend: &TenantStore) -> u64 {
+    backend.iter().map(episode_bytes).sum()
 }
 
-impl TenantBackend {
-    fn store_episode(&mut self, ep: Episode) -> Result<(), AgentMemError> {
-        match self {
-            TenantBackend::InMemory(db) => db.store_episode(ep),
-            TenantBackend::Disk(db) => db.store_episode(ep),
+/// Serialized size of an episode, used for storage accounting.
+fn episode_bytes(ep: &Episode) -> u64 {
+    serde_json::to_vec(ep).map(|v| v.len() as u64).unwrap_or(0)
+}
+
+/// A loaded tenant: its backend plus usage tracked for quotas.
+struct Tenant {
+    backend: TenantStore,
+    /// Approximate stored bytes, maintained incrementally on store and recomputed on prune.
+    stored_bytes: u64,
+    /// Changed since the last write-behind save (in-memory tenants).
+    dirty: bool,
+}
+
+impl TenantEntry for Tenant {
+    fn store(&self) -> &TenantStore {
+        &self.backend
+    }
+
+    fn store_mut(&mut self) -> &mut TenantStore {
+        &mut self.backend
+    }
+}
+
+impl Tenant {
+    fn new(backend: TenantStore) -> Self {
+        let stored_bytes = compute_stored_bytes(&backend);
+        Self {
+            backend,
+            stored_bytes,
+            dirty: false,
         }
     }
 
-    fn store_episodes(&mut self, episodes: Vec<Episode>) -> Result<(), AgentMemError> {
-        match self {
-            TenantBackend::InMemory(db) => db.store_episodes(episodes),
-            TenantBackend::Disk(db) => {
-                for ep in episodes {
-                    db.store_episode(ep)?;
-                }
-                Ok(())
+    fn usage(&self) -> webhooks::Usage {
+        webhooks::Usage {
+            episodes: self.backend.len(),
+            bytes: self.stored_bytes,
+        }
+    }
+
+    fn refresh_stored_bytes(&mut self) {
+        self.stored_bytes = compute_stored_bytes(&self.backend);
+    }
+}
+
+/// Loaded tenants, keyed by tenant_id (from API key). The server opens each tenant's store
+/// itself (`open_tenant`, from its resolved settings) and uses the map for access tracking and
+/// eviction.
+type TenantDB = Arc<RwLock<MultiTenantMemDB<Tenant>>>;
+
+/// Per-tenant storage quotas. `None` means unlimited.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+struct Quotas {
+    max_episodes: Option<usize>,
+    max_bytes: Option<u64>,
+    max_dim: Option<usize>,
+}
+
+impl Quotas {
+    /// Check that storing `incoming` keeps the tenant within quota.
+    fn check(&self, tenant: &Tenant, incoming: &[Episode]) -> Result<(), ApiError> {
+        if let Some(max_dim) = self.max_dim {
+            if let Some(ep) = incoming.iter().find(|e| e.state_embedding.len() > max_dim) {
+                return Err(quota_exceeded(
+                    "max_dim",
+                    max_dim as u64,
+                    ep.state_embedding.len() as u64,
+                ));
+            }
+        }
+        if let Some(max) = self.max_episodes {
+            let after = tenant.backend.len() + incoming.len();
+            if after > max {
+                return Err(quota_exceeded("max_episodes", max as u64, after as u64));
             }
         }
+        if let Some(max) = self.max_bytes {
+            let after = tenant.stored_bytes + incoming.iter().map(episode_bytes).sum::<u64>();
+            if after > max {
+                return Err(quota_exceeded("max_bytes", max, after));
+            }
+        }
+        Ok(())
     }
 
-    fn query_similar_with_options(
+    /// Check that replacing an episode of `old_bytes` with `updated` keeps the tenant within
+    /// quota. An update that doesn't grow the tenant is allowed even if it is already over.
+    fn check_update(
         &self,
-        embedding: &[f32],
-        opts: QueryOptions,
-    ) -> Result<Vec<Episode>, AgentMemError> {
-        match self {
-            TenantBackend::InMemory(db) => db.query_similar_with_options(embedding, opts),
-            TenantBackend::Disk(db) => db.query_similar_with_options(embedding, opts),
+        tenant: &Tenant,
+        old_bytes: u64,
+        updated: &Episode,
+    ) -> Result<(), ApiError> {
+        if let Some(max_dim) = self.max_dim {
+            let dim = updated.state_embedding.len();
+            if dim > max_dim {
+                return Err(quota_exceeded("max_dim", max_dim as u64, dim as u64));
+            }
         }
+        if let Some(max) = self.max_bytes {
+            let new_bytes = episode_bytes(updated);
+            let after = tenant.stored_bytes.saturating_sub(old_bytes) + new_bytes;
+            if after > max && new_bytes > old_bytes {
+                return Err(quota_exceeded("max_bytes", max, after));
+            }
+        }
+        Ok(())
     }
+}
 
-    fn prune_older_than(&mut self, ts: i64) -> Result<usize, AgentMemError> {
-        match self {
-            TenantBackend::InMemory(db) => Ok(db.prune_older_than(ts)),
-            TenantBackend::Disk(db) => db.prune_older_than(ts),
-        }
+/// Eviction policy for loaded disk-backed tenants. Evicted tenants are checkpointed,
+/// dropped from memory, and reopened from disk on their next request.
+#[derive(Clone, Debug, Default)]
+struct Eviction {
+    /// Evict tenants not accessed for this long.
+    idle_timeout: Option<Duration>,
+    /// Keep at most this many tenants loaded; least recently used are evicted first.
+    max_loaded: Option<usize>,
+}
+
+impl Eviction {
+    fn enabled(&self) -> bool {
+        self.idle_timeout.is_some() || self.max_loaded.is_some()
     }
+}
 
-    fn prune_keep_newest(&mut self, n: usize) -> Result<usize, AgentMemError> {
-        match self {
-            TenantBackend::InMemory(db) => Ok(db.prune_keep_newest(n)),
-            TenantBackend::Disk(db) => db.prune_keep_newest(n),
-        }
+/// Checkpoint and drop idle or excess disk-backed tenants. Returns the number evicted.
+async fn evict_tenants(state: &AppState) -> usize {
+    let mut tenants = state.tenants.write().await;
+    let evicted = tenants
+        .evict_idle_with(
+            state.eviction.idle_timeout,
+            state.eviction.max_loaded,
+            // Keep the tenant loaded if its state can't be made durable.
+            |id, backend| {
+                checkpoint_backend(&state.metrics, backend).inspect_err(|e| {
+                    tracing::warn!(tenant_id = %id, error = %e, "checkpoint before eviction failed");
+                })
+            },
+        )
+        .len();
+    if evicted > 0 {
+        state
+            .metrics
+            .tenant_evictions_total
+            .fetch_add(evicted as u64, Ordering::Relaxed);
+        tracing::info!(
+            evicted,
+            loaded = tenants.loaded_count(),
+            "evicted idle tenants"
+        );
     }
+    evicted
+}
 
-    fn prune_keep_highest_reward(&mut self, n: usize) -> Result<usize, AgentMemError> {
-        match self {
-            TenantBackend::InMemory(db) => Ok(db.prune_keep_highest_reward(n)),
-            TenantBackend::Disk(db) => db.prune_keep_highest_reward(n),
+/// Persist every loaded tenant before exit: save in-memory tenants through write-behind (the
+/// only place they are restored from) and checkpoint disk-backed tenants so restart skips log
+/// replay.
+async fn flush_tenants(state: &AppState) {
+    if let Some(ref write_behind) = state.write_behind {
+        write_behind.flush(state).await;
+    }
+    state.usage.flush(state).await;
+    let mut tenants = state.tenants.write().await;
+    let mut lost = 0;
+    for (id, tenant) in tenants.iter_mut() {
+        if !tenant.backend.is_disk() {
+            if state.write_behind.is_none() {
+                lost += 1;
+            }
+            continue;
         }
+        if let Err(e) = checkpoint_backend(&state.metrics, &mut tenant.backend) {
+            tracing::error!(tenant_id = %id, error = %e, "failed to persist tenant on shutdown");
+        }
+    }
+    if lost > 0 {
+        tracing::warn!(
+            tenants = lost,
+            "in-memory tenants not persisted (set AGENT_MEM_WRITE_BEHIND_DIR, or use the disk backend, to keep them)"
+        );
     }
+    tracing::info!(tenants = tenants.loaded_count(), "flushed tenants");
+}
 
-    fn save_to_file(&self, path: &std::path::Path) -> Result<(), AgentMemError> {
-        match self {
-            TenantBackend::InMemory(db) => db.save_to_file(path),
-            TenantBackend::Disk(_) => {
-                // Disk backend is already persisted; save is a no-op
-                Ok(())
-            }
+/// Resolves on SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("shutdown signal received, draining in-flight requests");
+}
+
+/// Error response used by handlers.
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+fn quota_exceeded(quota: &str, limit: u64, requested: u64) -> ApiError {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(serde_json::json!({
+            "error": format!("Quota exceeded: {quota} (limit {limit}, would be {requested})"),
+            "quota": quota,
+            "limit": limit,
+        })),
+    )
+}
+
+/// 413 for an episode over one of the `[limits]`.
+fn limit_exceeded(err: AgentMemError) -> ApiError {
+    let body = match &err {
+        AgentMemError::LimitExceeded { limit, max, .. } => serde_json::json!({
+            "error": err.to_string(),
+            "episode_limit": limit,
+            "limit": max,
+        }),
+        _ => serde_json::json!({"error": err.to_string()}),
+    };
+    (StatusCode::PAYLOAD_TOO_LARGE, Json(body))
+}
+
+/// 400 for a rejected store or query. A dimension mismatch also reports the expected and
+/// received dimensions and the tenant's, so a client on the wrong embedding model can tell
+/// from the response alone.
+fn invalid_request(err: AgentMemError, tenant_dim: usize) -> ApiError {
+    let body = match &err {
+        AgentMemError::DimensionMismatch { expected, got } => serde_json::json!({
+            "error": format!("{err} (tenant dim is {tenant_dim}; see GET /v1/config)"),
+            "code": "dimension_mismatch",
+            "expected_dim": expected,
+            "received_dim": got,
+            "tenant_dim": tenant_dim,
+        }),
+        _ => serde_json::json!({"error": err.to_string()}),
+    };
+    (StatusCode::BAD_REQUEST, Json(body))
+}
+
+/// Permission scope attached to an API key. `Admin` implies every other scope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scope {
+    /// Similarity queries.
+    Read,
+    /// Storing episodes.
+    Write,
+    /// Destructive retention operations (prune).
+    Prune,
+    /// Save, load, checkpoint and other operator routes.
+    Admin,
+}
+
+impl Scope {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "read" => Some(Scope::Read),
+            "write" => Some(Scope::Write),
+            "prune" => Some(Scope::Prune),
+            "admin" => Some(Scope::Admin),
+            _ => None,
         }
     }
 
-    fn checkpoint(&mut self) -> Result<(), AgentMemError> {
+    fn as_str(&self) -> &'static str {
         match self {
-            TenantBackend::InMemory(_) => Ok(()),
-            TenantBackend::Disk(db) => db.checkpoint(),
+            Scope::Read => "read",
+            Scope::Write => "write",
+            Scope::Prune => "prune",
+            Scope::Admin => "admin",
         }
     }
 }
 
-/// Per-tenant DB. Key: tenant_id (from API key).
-type TenantDB = Arc<RwLock<HashMap<String, TenantBackend>>>;
+/// Scopes granted to the authenticated key. Inserted into request extensions by auth.
+#[derive(Clone, Debug)]
+struct Scopes(Vec<Scope>);
 
-/// Simple in-memory metrics for observability (Prometheus-style).
-#[derive(Clone)]
-struct Metrics {
-    requests_total: Arc<AtomicU64>,
-    store_episodes_total: Arc<AtomicU64>,
-    query_total: Arc<AtomicU64>,
+impl Scopes {
+    fn all() -> Self {
+        Scopes(vec![Scope::Admin])
+    }
+
+    fn allows(&self, scope: Scope) -> bool {
+        self.0.iter().any(|s| *s == scope || *s == Scope::Admin)
+    }
+
+    /// 403 unless `scope` is granted.
+    fn require(&self, scope: Scope) -> Result<(), ApiError> {
+        if self.allows(scope) {
+            Ok(())
+        } else {
+            Err((
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "error": format!("API key lacks the '{}' scope", scope.as_str())
+                })),
+            ))
+        }
+    }
 }
 
-impl Default for Metrics {
-    fn default() -> Self {
-        Self {
-            requests_total: Arc::new(AtomicU64::new(0)),
-            store_episodes_total: Arc::new(AtomicU64::new(0)),
-            query_total: Arc::new(AtomicU64::new(0)),
+/// A configured API key: the tenant it acts on and what it may do.
+#[derive(Clone, Debug)]
+struct ApiKey {
+    tenant_id: String,
+    scopes: Scopes,
+}
+
+/// Parse `AGENT_MEM_API_KEYS`: comma-separated `key:tenant[:scope+scope...]` entries.
+/// Scopes are `read`, `write`, `prune`, `admin`; omitting them grants `admin`.
+fn parse_api_keys(spec: &str) -> Result<HashMap<String, ApiKey>, String> {
+    let mut keys = HashMap::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let mut parts = entry.splitn(3, ':');
+        let key = parts.next().unwrap_or_default().trim();
+        let tenant_id = parts
+            .next()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .ok_or_else(|| format!("entry '{key}' is missing a tenant (key:tenant[:scopes])"))?;
+        if key.is_empty() {
+            return Err("empty API key".to_string());
         }
+        let scopes = match parts.next() {
+            Some(list) => list
+                .split('+')
+                .map(|s| Scope::parse(s).ok_or_else(|| format!("unknown scope '{s}'")))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => vec![Scope::Admin],
+        };
+        keys.insert(
+            key.to_string(),
+            ApiKey {
+                tenant_id: tenant_id.to_string(),
+                scopes: Scopes(scopes),
+            },
+        );
     }
+    Ok(keys)
 }
 
 /// Per-tenant rate limit: (request_count, window_start)
 type RateLimitStore = Arc<RwLock<HashMap<String, (u64, Instant)>>>;
 
 /// Audit log entry (JSONL).
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 struct AuditEntry {
     ts: String,
     tenant_id: String,
@@ -142,6 +443,8 @@ struct AuditEntry {
     path: Option<String>,
 }
 
+/// Record an audit entry: appended to the audit log file (if configured) and published to
+/// `/v1/events` subscribers.
 fn audit_log(
     state: &AppState,
     tenant_id: &str,
@@ -150,27 +453,24 @@ fn audit_log(
     episode_count: Option<usize>,
     path: Option<&str>,
 ) {
+    if state.audit_log.is_none() && state.audit_events.receiver_count() == 0 {
+        return;
+    }
+    let entry = AuditEntry {
+        ts: chrono::Utc::now().to_rfc3339(),
+        tenant_id: tenant_id.to_string(),
+        op: op.to_string(),
+        task_id: task_id.map(String::from),
+        episode_count,
+        path: path.map(String::from),
+    };
     if let Some(ref audit) = state.audit_log {
-        let entry = AuditEntry {
-            ts: chrono::Utc::now().to_rfc3339(),
-            tenant_id: tenant_id.to_string(),
-            op: op.to_string(),
-            task_id: task_id.map(String::from),
-            episode_count,
-            path: path.map(String::from),
-        };
         let audit = audit.clone();
         let line = serde_json::to_string(&entry).unwrap_or_else(|_| "{}".into());
-        tokio::task::spawn_blocking(move || {
-            use std::io::Write;
-            if let Ok(mut guard) = audit.write() {
-                if let Some(ref mut w) = *guard {
-                    let _ = writeln!(w, "{}", line);
-                    let _ = w.flush();
-                }
-            }
-        });
+        tokio::task::spawn_blocking(move || audit.append(&line));
     }
+    // Err only means no subscribers.
+    let _ = state.audit_events.send(Arc::new(entry));
 }
 
 #[derive(Clone)]
@@ -178,18 +478,118 @@ struct AppState {
     tenants: TenantDB,
     default_dim: usize,
     data_dir: Option<PathBuf>,
-    api_key: Option<String>,
+    /// Configured API keys. `None` means dev mode: any key is accepted with full access.
+    api_keys: Option<Arc<HashMap<String, ApiKey>>>,
+    /// Keys and per-tenant settings from `auth.keys_file`, re-read on SIGHUP.
+    keys_file: Option<Arc<keys_file::KeysFile>>,
     metrics: Metrics,
-    rate_limit: Option<(RateLimitStore, u64, Duration)>,
-    audit_log: Option<Arc<std::sync::RwLock<Option<std::fs::File>>>>,
+    /// Window counters, the default max per window, and the window. Present when a default
+    /// limit is set or the keys file may set per-tenant ones.
+    rate_limit: Option<(RateLimitStore, Option<u64>, Duration)>,
+    /// Per-tenant caps on in-flight stores and queries.
+    concurrency: Arc<concurrency::Limits>,
+    quotas: Quotas,
+    /// Size limits on each stored episode (`[limits]`).
+    limits: EpisodeLimits,
+    /// Fill unset timestamps on store (`auto_timestamp`).
+    auto_timestamp: bool,
+    eviction: Eviction,
+    audit_log: Option<Arc<audit::AuditFile>>,
+    /// Newly stored episodes, for `/v1/subscribe`.
+    episode_events: subscribe::EventBus,
+    /// Audit entries, for `/v1/events`.
+    audit_events: events::AuditBus,
+    /// Flips to `true` once SIGINT/SIGTERM is received.
+    shutdown: tokio::sync::watch::Receiver<bool>,
+    replication: Arc<replication::Replication>,
+    /// Scheduled object-storage snapshots, when `snapshots.bucket` is set.
+    snapshots: Option<Arc<snapshot::Snapshots>>,
+    /// Background tasks checked by `/readyz`.
+    tasks: Arc<health::Tasks>,
+    /// Embeds `text` in store/query requests, when `embedding.url` is set.
+    embedder: Option<Arc<embedding::Embedder>>,
+    /// Open MCP HTTP+SSE sessions.
+    mcp_sessions: mcp::Sessions,
+    /// OpenAI vector store records; `None` unless `openai.vector_stores` is set.
+    openai: Option<Arc<openai::Registry>>,
+    /// Periodic saves of in-memory tenants, when `write_behind.dir` is set.
+    write_behind: Option<Arc<write_behind::WriteBehind>>,
+    /// Per-tenant backend and index choices.
+    tenant_settings: Arc<tenant_settings::Settings>,
+    /// Retention policies and their last runs.
+    retention: Arc<retention::Retention>,
+    /// Per-tenant archives of pruned episodes, when `retention.archive_dir` is set.
+    archive_dir: Option<PathBuf>,
+    /// Event webhooks (delivery settings and operator-configured hooks).
+    webhooks: Arc<webhooks::Webhooks>,
+    /// Per-tenant billing counters.
+    usage: Arc<usage::Usage>,
+}
+
+impl AppState {
+    /// Whether tenant backends fill unset timestamps on store: `auto_timestamp`, except on a
+    /// read-only replica, which stores episodes as the leader logged them.
+    fn fills_timestamps(&self) -> bool {
+        self.auto_timestamp && !self.replication.read_only()
+    }
+
+    /// Dimension the tenant's storage is created with.
+    fn tenant_dim(&self, tenant_id: &str) -> usize {
+        self.keys_file
+            .as_ref()
+            .and_then(|f| f.tenant(tenant_id)?.dim)
+            .unwrap_or(self.default_dim)
+    }
+
+    /// The tenant's quotas: its keys-file entry, field by field, over `[quotas]`.
+    fn quotas(&self, tenant_id: &str) -> Quotas {
+        match self.keys_file.as_ref().and_then(|f| f.tenant(tenant_id)) {
+            Some(t) => Quotas {
+                max_episodes: t.quotas.max_episodes.or(self.quotas.max_episodes),
+                max_bytes: t.quotas.max_bytes.or(self.quotas.max_bytes),
+                max_dim: t.quotas.max_dim.or(self.quotas.max_dim),
+            },
+            None => self.quotas.clone(),
+        }
+    }
+
+    /// Resolves once shutdown has begun.
+    async fn shutdown_requested(&self) {
+        let mut rx = self.shutdown.clone();
+        let _ = rx.wait_for(|stopping| *stopping).await;
+    }
+
+    /// Open the tenant's archive (`archive_dir/<tenant>`) for its backend, if it has none yet,
+    /// so prunes move episodes there. No-op without `retention.archive_dir`.
+    fn attach_archive(
+        &self,
+        tenant_id: &str,
+        backend: &mut TenantStore,
+    ) -> Result<(), AgentMemError> {
+        let Some(dir) = self.archive_dir.as_ref() else {
+            return Ok(());
+        };
+        if backend.archive().is_some() {
+            return Ok(());
+        }
+        let path = dir.join(tenant_dir_name(tenant_id));
+        let archive = DiskArchive::open(path, DiskOptions::exact(backend.dim()))?;
+        backend.set_archive(Some(Arc::new(archive)))
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct StoreEpisodeRequest {
     task_id: String,
-    state_embedding: Vec<f32>,
+    /// Required unless `text` is sent.
+    #[serde(default)]
+    state_embedding: Option<Vec<f32>>,
+    /// Embedded by the server (`embedding.url`) in place of `state_embedding`; not stored.
+    #[serde(default)]
+    text: Option<String>,
     reward: f32,
     #[serde(default)]
+    #[schema(value_type = Object)]
     metadata: serde_json::Value,
     #[serde(default)]
     timestamp: Option<i64>,
@@ -199,36 +599,141 @@ struct StoreEpisodeRequest {
     source: Option<String>,
     #[serde(default)]
     user_id: Option<String>,
+    #[serde(default)]
+    session_id: Option<String>,
+    /// Model that produced `state_embedding`, so queries can stay within one model's vectors.
+    /// With `text`, the server's `embedding.model` is recorded instead.
+    #[serde(default)]
+    embedding_model: Option<String>,
+    /// E.g. tokens or dollars.
+    #[serde(default)]
+    cost: Option<f32>,
+    #[serde(default)]
+    duration_ms: Option<i64>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct StoreEpisodeResponse {
     id: String,
 }
 
-#[derive(Deserialize)]
+/// Replaces every field of the episode; omitted optional fields are cleared.
+#[derive(Deserialize, ToSchema)]
+struct UpdateEpisodeRequest {
+    /// The `version` the episode had when read; the update fails with 409 if it has changed.
+    expected_version: u64,
+    #[serde(flatten)]
+    episode: StoreEpisodeRequest,
+}
+
+#[derive(Serialize, ToSchema)]
+struct UpdateEpisodeResponse {
+    id: String,
+    version: u64,
+}
+
+#[derive(Deserialize, ToSchema)]
 struct StoreEpisodesRequest {
     episodes: Vec<StoreEpisodeRequest>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct StoreEpisodesResponse {
     ids: Vec<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct QuerySimilarRequest {
-    query_embedding: Vec<f32>,
+    /// Required unless `text` is sent.
+    #[serde(default)]
+    query_embedding: Option<Vec<f32>>,
+    /// Embedded by the server (`embedding.url`) in place of `query_embedding`.
+    #[serde(default)]
+    text: Option<String>,
     #[serde(default)]
     min_reward: f32,
+    /// Only episodes with reward <= this, e.g. past failures.
+    #[serde(default)]
+    max_reward: Option<f32>,
     #[serde(default = "default_top_k")]
     top_k: usize,
+    /// Index candidates fetched per result (default 2, or 4 with filters).
+    #[serde(default)]
+    over_fetch: Option<usize>,
+    /// Widen the index search until `top_k` episodes pass the filters.
+    #[serde(default)]
+    adaptive: bool,
+    /// Order ties by timestamp, then id, so identical queries return identical lists.
+    #[serde(default)]
+    deterministic_order: bool,
+    /// At most this many results per task_id, so one repeated task can't crowd out the rest.
+    #[serde(default)]
+    max_per_task: Option<usize>,
+    /// Recency boost λ per second: similarity decays by `exp(-λ · age)`.
+    #[serde(default)]
+    recency_boost: Option<f32>,
+    /// Down-rank results near this embedding, e.g. an approach that just failed.
+    #[serde(default)]
+    avoid_embedding: Option<Vec<f32>>,
+    /// Distance added to an exact match of `avoid_embedding`; less the farther a result is.
+    #[serde(default = "default_avoid_penalty")]
+    avoid_penalty: f32,
+    /// Time budget for the index search; the response is then marked `truncated`.
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    /// Set false to get episodes back with an empty `state_embedding`.
+    #[serde(default = "default_include_embeddings")]
+    include_embeddings: bool,
+    #[serde(flatten)]
+    filter: EpisodeFilter,
+}
+
+impl QuerySimilarRequest {
+    /// Filters plus candidate over-fetch settings; leaves the embedding and text in place.
+    fn options(&mut self) -> QueryOptions {
+        let filter = std::mem::take(&mut self.filter);
+        let mut opts = filter
+            .apply(QueryOptions::new(self.min_reward, self.top_k))
+            .adaptive(self.adaptive)
+            .deterministic_order(self.deterministic_order)
+            .include_embeddings(self.include_embeddings);
+        if let Some(multiplier) = self.over_fetch {
+            opts = opts.over_fetch(multiplier);
+        }
+        if let Some(max) = self.max_reward {
+            opts = opts.max_reward(max);
+        }
+        if let Some(ms) = self.timeout_ms {
+            opts = opts.timeout_ms(ms);
+        }
+        if let Some(max) = self.max_per_task {
+            opts = opts.group_by_task(max);
+        }
+        if let Some(lambda) = self.recency_boost {
+            opts = opts.recency_boost(lambda);
+        }
+        if let Some(avoid) = self.avoid_embedding.take() {
+            opts = opts.avoid_embedding(avoid, self.avoid_penalty);
+        }
+        opts
+    }
+}
+
+/// Episode filters shared by query and bulk delete.
+#[derive(Default, Deserialize, ToSchema)]
+struct EpisodeFilter {
     #[serde(default)]
     tags_any: Option<Vec<String>>,
     #[serde(default)]
     tags_all: Option<Vec<String>>,
+    /// Hierarchical tags: a tag equal to or under any of these, e.g. `tool/browser`.
+    #[serde(default)]
+    tag_prefix_any: Option<Vec<String>>,
     #[serde(default)]
     task_id_prefix: Option<String>,
+    /// Whole-task_id glob, e.g. `deploy-*-prod`.
+    #[serde(default)]
+    task_id_glob: Option<String>,
     #[serde(default)]
     time_after: Option<i64>,
     #[serde(default)]
@@ -237,163 +742,546 @@ struct QuerySimilarRequest {
     source: Option<String>,
     #[serde(default)]
     user_id: Option<String>,
+    #[serde(default)]
+    session_id: Option<String>,
+    /// Only episodes embedded by this model.
+    #[serde(default)]
+    embedding_model: Option<String>,
+    /// `true` for episodes with a trajectory, `false` for those without.
+    #[serde(default)]
+    has_steps: Option<bool>,
+    #[serde(default)]
+    min_steps: Option<usize>,
+    #[serde(default)]
+    max_steps: Option<usize>,
+    /// Episodes without a cost are excluded.
+    #[serde(default)]
+    max_cost: Option<f32>,
+    /// Episodes without a duration are excluded.
+    #[serde(default)]
+    max_duration_ms: Option<i64>,
+    /// Top-level metadata keys every episode must have.
+    #[serde(default)]
+    metadata_has_key: Option<Vec<String>>,
+    /// Numeric metadata ranges, e.g. `[{"key": "cost", "min": 0, "max": 0.05}]`.
+    #[serde(default)]
+    #[schema(value_type = Option<Vec<Object>>)]
+    metadata_range: Option<Vec<MetadataRange>>,
+}
+
+impl EpisodeFilter {
+    fn is_empty(&self) -> bool {
+        self.tags_any.as_ref().is_none_or(Vec::is_empty)
+            && self.tags_all.as_ref().is_none_or(Vec::is_empty)
+            && self.tag_prefix_any.as_ref().is_none_or(Vec::is_empty)
+            && self.task_id_prefix.is_none()
+            && self.task_id_glob.is_none()
+            && self.time_after.is_none()
+            && self.time_before.is_none()
+            && self.source.is_none()
+            && self.user_id.is_none()
+            && self.session_id.is_none()
+            && self.embedding_model.is_none()
+            && self.has_steps.is_none()
+            && self.min_steps.is_none()
+            && self.max_steps.is_none()
+            && self.max_cost.is_none()
+            && self.max_duration_ms.is_none()
+            && self.metadata_has_key.as_ref().is_none_or(Vec::is_empty)
+            && self.metadata_range.as_ref().is_none_or(Vec::is_empty)
+    }
+
+    /// Add the set filters to `opts`; empty tag lists are ignored.
+    fn apply(self, mut opts: QueryOptions) -> QueryOptions {
+        if let Some(tags) = self.tags_any.filter(|t| !t.is_empty()) {
+            opts = opts.tags_any(tags);
+        }
+        if let Some(tags) = self.tags_all.filter(|t| !t.is_empty()) {
+            opts = opts.tags_all(tags);
+        }
+        if let Some(prefixes) = self.tag_prefix_any.filter(|p| !p.is_empty()) {
+            opts = opts.tag_prefix_any(prefixes);
+        }
+        if let Some(prefix) = self.task_id_prefix {
+            opts = opts.task_id_prefix(prefix);
+        }
+        if let Some(glob) = self.task_id_glob {
+            opts = opts.task_id_glob(glob);
+        }
+        if let Some(ts) = self.time_after {
+            opts = opts.time_after(ts);
+        }
+        if let Some(ts) = self.time_before {
+            opts = opts.time_before(ts);
+        }
+        if let Some(s) = self.source {
+            opts = opts.source(s);
+        }
+        if let Some(u) = self.user_id {
+            opts = opts.user_id(u);
+        }
+        if let Some(s) = self.session_id {
+            opts = opts.session_id(s);
+        }
+        if let Some(model) = self.embedding_model {
+            opts = opts.embedding_model(model);
+        }
+        if let Some(has) = self.has_steps {
+            opts = opts.has_steps(has);
+        }
+        if let Some(n) = self.min_steps {
+            opts = opts.min_steps(n);
+        }
+        if let Some(n) = self.max_steps {
+            opts = opts.max_steps(n);
+        }
+        if let Some(max) = self.max_cost {
+            opts = opts.max_cost(max);
+        }
+        if let Some(max) = self.max_duration_ms {
+            opts = opts.max_duration_ms(max);
+        }
+        for key in self.metadata_has_key.unwrap_or_default() {
+            opts = opts.metadata_has_key(key);
+        }
+        for range in self.metadata_range.unwrap_or_default() {
+            opts = opts.metadata_range(range.key, range.min, range.max);
+        }
+        opts
+    }
 }
 
 fn default_top_k() -> usize {
     5
 }
 
-#[derive(Serialize)]
+fn default_include_embeddings() -> bool {
+    true
+}
+
+fn default_avoid_penalty() -> f32 {
+    1.0
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// One query of a fused query, with its weight.
+#[derive(Deserialize, ToSchema)]
+struct WeightedQuery {
+    /// Required unless `text` is sent.
+    #[serde(default)]
+    query_embedding: Option<Vec<f32>>,
+    /// Embedded by the server (`embedding.url`) in place of `query_embedding`.
+    #[serde(default)]
+    text: Option<String>,
+    /// Default 1.
+    #[serde(default = "default_weight")]
+    weight: f32,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct QueryFusedRequest {
+    /// Query embeddings (or texts), e.g. the current state and the goal.
+    queries: Vec<WeightedQuery>,
+    /// Filters and options, applied to every query (`query_embedding` and `text` belong in
+    /// `queries`).
+    #[serde(flatten)]
+    options: QuerySimilarRequest,
+}
+
+#[derive(Serialize, ToSchema)]
+struct FusedResult {
+    #[schema(value_type = openapi::EpisodeSchema)]
+    episode: Episode,
+    /// Weighted reciprocal-rank score; higher is better.
+    score: f32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct QueryFusedResponse {
+    results: Vec<FusedResult>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct QueryContrastiveRequest {
+    /// Rewards above this are successes, at or below it failures.
+    #[serde(default)]
+    reward_threshold: f32,
+    /// Query, `top_k` per list and filters; `min_reward` and `max_reward` are ignored.
+    #[serde(flatten)]
+    options: QuerySimilarRequest,
+}
+
+#[derive(Serialize, ToSchema)]
+struct QueryContrastiveResponse {
+    /// Nearest episodes with reward above the threshold.
+    #[schema(value_type = Vec<openapi::EpisodeSchema>)]
+    high_reward: Vec<Episode>,
+    /// Nearest episodes with reward at or below the threshold.
+    #[schema(value_type = Vec<openapi::EpisodeSchema>)]
+    low_reward: Vec<Episode>,
+}
+
+#[derive(Serialize, ToSchema)]
 struct QuerySimilarResponse {
+    #[schema(value_type = Vec<openapi::EpisodeSchema>)]
     episodes: Vec<Episode>,
+    /// Present (true) when `timeout_ms` ran out and the results are the best found in time.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, ToSchema)]
+struct QueryArchiveResponse {
+    #[schema(value_type = Vec<openapi::EpisodeSchema>)]
+    episodes: Vec<Episode>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ExplainCandidate {
+    id: String,
+    task_id: String,
+    /// L2 distance to the query embedding.
+    distance: f32,
+    reward: f32,
+    timestamp: Option<i64>,
+    tags: Option<Vec<String>>,
+    source: Option<String>,
+    user_id: Option<String>,
+    session_id: Option<String>,
+    /// `returned`, `rejected` (see `rejected_by`) or `cut` (passed the filters but fell
+    /// outside `top_k`).
+    status: &'static str,
+    /// Filter that eliminated the candidate.
+    rejected_by: Option<&'static str>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ExplainTimings {
+    embed_us: u64,
+    search_us: u64,
+    filter_us: u64,
+    total_us: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ExplainResponse {
+    top_k: usize,
+    /// Index candidates fetched per requested result.
+    candidate_multiplier: usize,
+    /// Episodes passing the tags, source and user_id filters, which are applied inside the
+    /// index search; absent when none is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_keys: Option<usize>,
+    candidates_requested: usize,
+    /// Whether `timeout_ms` ran out before the search finished.
+    truncated: bool,
+    /// Candidates in index order (nearest first).
+    candidates: Vec<ExplainCandidate>,
+    /// Candidates eliminated per filter.
+    rejected: BTreeMap<&'static str, usize>,
+    /// Ids of the query's results, in result order.
+    result_ids: Vec<String>,
+    timings: ExplainTimings,
+}
+
+#[derive(Deserialize, ToSchema)]
 struct SaveRequest {
     path: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct SaveResponse {
     ok: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct LoadRequest {
     path: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct LoadResponse {
     ok: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct PruneOlderThanRequest {
     timestamp_cutoff_ms: i64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct PruneResponse {
     removed: usize,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, ToSchema)]
+struct DeleteEpisodesResponse {
+    deleted: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SoftDeleteResponse {
+    id: String,
+    /// Whether the episode is now soft-deleted.
+    deleted: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
 struct PruneKeepNewestRequest {
     n: usize,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct PruneKeepHighestRewardRequest {
     n: usize,
 }
 
+#[derive(Deserialize, ToSchema)]
+struct PruneSessionRequest {
+    session_id: String,
+}
+
 /// Resolve tenant from API key. For Phase 1, API key maps 1:1 to tenant_id.
 fn tenant_from_key(api_key: &str) -> String {
     api_key.to_string()
 }
 
-/// Sanitize tenant_id for use as a path component.
-fn sanitize_tenant_path(tenant_id: &str) -> String {
-    tenant_id
-        .chars()
-        .map(|c| {
-            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
-                c
-            } else {
-                '_'
-            }
-        })
-        .collect()
-}
-
-/// Create a new tenant backend. When data_dir is set, uses AgentMemDBDisk with checkpoint.
+/// Create a new tenant backend with the tenant's resolved settings: disk tenants live under
+/// `data_dir/<tenant>`, memory tenants in RAM.
 fn create_tenant_backend(
     data_dir: Option<&PathBuf>,
     tenant_id: &str,
     dim: usize,
-) -> Result<TenantBackend, AgentMemError> {
-    if let Some(dir) = data_dir {
-        let safe = sanitize_tenant_path(tenant_id);
-        let tenant_path = dir.join(safe);
-        let db = AgentMemDBDisk::open_with_options(
-            tenant_path,
-            DiskOptions::exact_with_checkpoint(dim),
-        )?;
-        Ok(TenantBackend::Disk(db))
-    } else {
-        Ok(TenantBackend::InMemory(AgentMemDB::new(dim)))
-    }
+    settings: &tenant_settings::Resolved,
+) -> Result<TenantStore, AgentMemError> {
+    let options = tenant_options(data_dir.is_some(), dim, settings)?;
+    TenantStore::open(data_dir.map(PathBuf::as_path), tenant_id, &options)
 }
 
-/// Extract API key from Authorization header or X-API-Key.
-fn extract_api_key(headers: &axum::http::HeaderMap) -> Option<String> {
-    if let Some(auth) = headers.get("Authorization") {
-        if let Ok(s) = auth.to_str() {
-            if let Some(key) = s.strip_prefix("Bearer ") {
-                return Some(key.to_string());
-            }
+/// Storage options for a tenant with these settings.
+fn tenant_options(
+    has_data_dir: bool,
+    dim: usize,
+    settings: &tenant_settings::Resolved,
+) -> Result<TenantOptions, AgentMemError> {
+    Ok(match (settings.backend, settings.index) {
+        (BackendKind::Disk, _) if !has_data_dir => {
+            return Err(AgentMemError::io("Open tenant")(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "disk backend requires AGENT_MEM_DATA_DIR",
+            )))
         }
-    }
-    if let Some(key) = headers.get("X-API-Key") {
-        if let Ok(s) = key.to_str() {
-            return Some(s.to_string());
+        (BackendKind::Disk, IndexKind::Exact) => {
+            TenantOptions::disk(DiskOptions::exact_with_checkpoint(dim))
+        }
+        (BackendKind::Disk, IndexKind::Hnsw) => TenantOptions::disk(
+            DiskOptions::hnsw(dim, settings.hnsw_max_elements)
+                .with_hnsw_params(settings.hnsw_params()),
+        ),
+        (BackendKind::Memory, IndexKind::Exact) => TenantOptions::memory_exact(dim),
+        (BackendKind::Memory, IndexKind::Hnsw) => {
+            TenantOptions::memory_hnsw(dim, settings.hnsw_max_elements, settings.hnsw_params())
+        }
+    })
+}
+
+/// Create an empty in-memory database with the tenant's index settings.
+fn new_memory_db(settings: &tenant_settings::Resolved, dim: usize) -> AgentMemDB {
+    match settings.index {
+        IndexKind::Exact => AgentMemDB::new_exact(dim),
+        IndexKind::Hnsw => {
+            AgentMemDB::new_hnsw(dim, settings.hnsw_max_elements, settings.hnsw_params())
         }
     }
-    None
 }
 
-/// Auth middleware: validate API key and insert tenant_id into extensions.
-async fn auth_middleware(
-    State(state): State<AppState>,
-    request: Request<axum::body::Body>,
-    next: Next,
-) -> Result<Response, Response> {
-    let key = extract_api_key(request.headers()).ok_or_else(|| {
+/// Create or reopen a tenant's backend, timing disk opens (checkpoint load + log replay).
+#[tracing::instrument(skip(state))]
+fn open_tenant(state: &AppState, tenant_id: &str) -> Result<Tenant, ApiError> {
+    let settings = state.tenant_settings.resolve(tenant_id);
+    let open = || {
+        create_tenant_backend(
+            state.data_dir.as_ref(),
+            tenant_id,
+            state.tenant_dim(tenant_id),
+            &settings,
+        )
+    };
+    let backend = if settings.backend == BackendKind::Disk {
+        state.metrics.tenant_open_seconds.time(open)
+    } else {
+        open()
+    }
+    .map_err(|e| {
         (
-            StatusCode::UNAUTHORIZED,
-            Json(serde_json::json!({"error": "Missing Authorization: Bearer <key> or X-API-Key"})),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
         )
-            .into_response()
     })?;
+    let mut tenant = Tenant::new(backend);
+    tenant.backend.set_auto_timestamp(state.fills_timestamps());
+    Ok(tenant)
+}
+
+/// Checkpoint a backend, timing disk checkpoints.
+#[tracing::instrument(skip_all, fields(backend = backend.kind()))]
+fn checkpoint_backend(metrics: &Metrics, backend: &mut TenantStore) -> Result<(), AgentMemError> {
+    match backend {
+        TenantStore::Disk(_) => metrics.checkpoint_seconds.time(|| backend.checkpoint()),
+        TenantStore::Memory(_) => backend.checkpoint(),
+    }
+}
 
-    if let Some(ref expected) = state.api_key {
-        if key != *expected {
+/// Look up a tenant that already has data: loaded in memory, or (disk mode) present on disk.
+/// Returns 404 when the tenant has never stored episodes.
+#[tracing::instrument(skip(state, tenants))]
+fn existing_tenant_mut<'a>(
+    state: &AppState,
+    tenants: &'a mut MultiTenantMemDB<Tenant>,
+    tenant_id: &str,
+) -> Result<&'a mut Tenant, ApiError> {
+    if !tenants.is_loaded(tenant_id) {
+        let on_disk = state
+            .data_dir
+            .as_ref()
+            .is_some_and(|dir| TenantStore::exists_on_disk(dir, tenant_id));
+        if !on_disk {
             return Err((
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({"error": "Invalid API key"})),
-            )
-                .into_response());
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "No episodes stored for this tenant yet"})),
+            ));
+        }
+    }
+    tenants.get_or_insert_with(tenant_id, || open_tenant(state, tenant_id))
+}
+
+/// Look up a tenant for writing, creating (or reopening from disk) its backend if not loaded.
+#[tracing::instrument(skip(state, tenants))]
+fn tenant_mut_or_create<'a>(
+    state: &AppState,
+    tenants: &'a mut MultiTenantMemDB<Tenant>,
+    tenant_id: &str,
+) -> Result<&'a mut Tenant, ApiError> {
+    tenants.get_or_insert_with(tenant_id, || open_tenant(state, tenant_id))
+}
+
+/// Extract API key from Authorization header or X-API-Key.
+fn extract_api_key(headers: &axum::http::HeaderMap) -> Option<String> {
+    if let Some(auth) = headers.get("Authorization") {
+        if let Ok(s) = auth.to_str() {
+            if let Some(key) = s.strip_prefix("Bearer ") {
+                return Some(key.to_string());
+            }
+        }
+    }
+    if let Some(key) = headers.get("X-API-Key") {
+        if let Ok(s) = key.to_str() {
+            return Some(s.to_string());
         }
     }
+    None
+}
+
+/// Browsers cannot set headers on WebSocket upgrades, so those may pass `?api_key=` instead.
+fn websocket_query_key(request: &Request<axum::body::Body>) -> Option<String> {
+    let is_upgrade = request
+        .headers()
+        .get(axum::http::header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    if !is_upgrade {
+        return None;
+    }
+    axum::extract::Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .ok()?
+        .0
+        .remove("api_key")
+}
+
+/// Resolve an API key to its tenant and scopes. Shared by the HTTP and gRPC surfaces.
+#[tracing::instrument(skip_all, fields(tenant_id))]
+fn authenticate(state: &AppState, key: Option<String>) -> Result<(String, Scopes), ApiError> {
+    let key = key.ok_or_else(|| {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({"error": "Missing Authorization: Bearer <key> or X-API-Key"})),
+        )
+    })?;
+
+    let (tenant_id, scopes) = match state.api_keys {
+        Some(ref keys) => {
+            let entry = keys
+                .get(&key)
+                .cloned()
+                .or_else(|| state.keys_file.as_ref()?.key(&key))
+                .ok_or_else(|| {
+                    (
+                        StatusCode::UNAUTHORIZED,
+                        Json(serde_json::json!({"error": "Invalid API key"})),
+                    )
+                })?;
+            (entry.tenant_id, entry.scopes)
+        }
+        None => (tenant_from_key(&key), Scopes::all()),
+    };
 
+    tracing::Span::current().record("tenant_id", tenant_id.as_str());
     state.metrics.requests_total.fetch_add(1, Ordering::Relaxed);
-    let tenant_id = tenant_from_key(&key);
+    Ok((tenant_id, scopes))
+}
+
+/// Auth middleware: validate API key and insert tenant_id into extensions.
+async fn auth_middleware(
+    State(state): State<AppState>,
+    request: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, Response> {
+    let key = extract_api_key(request.headers()).or_else(|| websocket_query_key(&request));
+    let (tenant_id, scopes) = authenticate(&state, key).map_err(IntoResponse::into_response)?;
     let mut request = request;
     request.extensions_mut().insert(tenant_id);
+    request.extensions_mut().insert(scopes);
     Ok(next.run(request).await)
 }
 
-/// Rate limit middleware: per-tenant fixed window. Runs after auth (requires tenant_id in extensions).
-async fn rate_limit_middleware(
-    State(state): State<AppState>,
+/// Scope middleware: reject with 403 unless the authenticated key grants `scope`.
+/// Runs after auth (requires `Scopes` in extensions).
+async fn require_scope(
+    State(scope): State<Scope>,
     request: Request<axum::body::Body>,
     next: Next,
 ) -> Result<Response, Response> {
-    let Some(ref config) = state.rate_limit else {
-        return Ok(next.run(request).await);
-    };
-    let (store, max_per_window, window) = config;
-    let tenant_id = request
+    request
         .extensions()
-        .get::<String>()
+        .get::<Scopes>()
         .cloned()
-        .unwrap_or_else(|| "unknown".to_string());
+        .unwrap_or(Scopes(Vec::new()))
+        .require(scope)
+        .map_err(IntoResponse::into_response)?;
+    Ok(next.run(request).await)
+}
+
+/// Per-tenant fixed-window rate limit check. Shared by the HTTP and gRPC surfaces.
+async fn check_rate_limit(state: &AppState, tenant_id: &str) -> Result<(), ApiError> {
+    let Some((ref store, default_max, window)) = state.rate_limit else {
+        return Ok(());
+    };
+    let max_per_window = state
+        .keys_file
+        .as_ref()
+        .and_then(|f| f.tenant(tenant_id)?.rate_limit)
+        .or(default_max);
+    let Some(max_per_window) = max_per_window else {
+        return Ok(());
+    };
 
     let now = Instant::now();
     let mut guard = store.write().await;
-    let (count, window_start) = guard.entry(tenant_id.clone()).or_insert((0, now));
-    if now.duration_since(*window_start) >= *window {
+    let (count, window_start) = guard.entry(tenant_id.to_string()).or_insert((0, now));
+    if now.duration_since(*window_start) >= window {
         *count = 0;
         *window_start = now;
     }
@@ -401,119 +1289,126 @@ async fn rate_limit_middleware(
     let current = *count;
     drop(guard);
 
-    if current > *max_per_window {
+    if current > max_per_window {
         return Err((
             StatusCode::TOO_MANY_REQUESTS,
             Json(serde_json::json!({"error": "Rate limit exceeded"})),
-        )
-            .into_response());
+        ));
     }
-    Ok(next.run(request).await)
+    Ok(())
 }
 
-async fn health() -> &'static str {
-    "ok"
+/// Replace axum's plain-text 413 (body over `DefaultBodyLimit`) with a JSON error naming the
+/// limit and the setting that controls it.
+async fn body_too_large(
+    State((limit, setting)): State<(usize, &'static str)>,
+    request: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(serde_json::json!({
+            "error": format!("Request body exceeds the {limit}-byte limit ({setting})"),
+            "limit": limit,
+        })),
+    )
+        .into_response()
 }
 
-async fn dashboard(State(state): State<AppState>) -> Html<String> {
-    let requests = state.metrics.requests_total.load(Ordering::Relaxed);
-    let store_episodes = state.metrics.store_episodes_total.load(Ordering::Relaxed);
-    let queries = state.metrics.query_total.load(Ordering::Relaxed);
-    let tenants = state.tenants.read().await.len();
-
-    let rate_limit_str = state
-        .rate_limit
-        .as_ref()
-        .map(|(_, max, dur)| format!("{} req / {}s", max, dur.as_secs()))
-        .unwrap_or_else(|| "disabled".to_string());
-    let audit_str = if state.audit_log.is_some() {
-        "enabled"
+/// CORS policy from config. With no allowed origins no CORS headers are sent, so browsers
+/// refuse cross-origin calls.
+fn cors_layer(cors: &config::CorsConfig) -> CorsLayer {
+    use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin};
+    if cors.allowed_origins.is_empty() {
+        return CorsLayer::new();
+    }
+    // Entries were validated at startup.
+    let any = |list: &[String]| list.iter().any(|v| v == "*");
+    let origins = if any(&cors.allowed_origins) {
+        AllowOrigin::any()
     } else {
-        "disabled"
+        AllowOrigin::list(cors.allowed_origins.iter().filter_map(|o| o.parse().ok()))
     };
-    let api_key_str = if state.api_key.is_some() {
-        "set"
+    let methods = if any(&cors.allowed_methods) {
+        AllowMethods::any()
     } else {
-        "not set (dev)"
-    };
-
-    let html = format!(
-        r##"<!DOCTYPE html>
-<html lang="en">
-<head>
-  <meta charset="utf-8">
-  <meta name="viewport" content="width=device-width, initial-scale=1">
-  <title>Agent Memory DB — Dashboard</title>
-  <style>
-    :root {{ font-family: system-ui, -apple-system, sans-serif; font-size: 16px; }}
-    body {{ max-width: 640px; margin: 2rem auto; padding: 0 1rem; color: #1a1a1a; }}
-    h1 {{ font-size: 1.5rem; font-weight: 600; margin-bottom: 1.5rem; }}
-    section {{ margin-bottom: 1.5rem; }}
-    h2 {{ font-size: 0.875rem; font-weight: 600; text-transform: uppercase; letter-spacing: 0.05em; color: #666; margin-bottom: 0.5rem; }}
-    .metric {{ display: flex; justify-content: space-between; padding: 0.5rem 0; border-bottom: 1px solid #eee; }}
-    .metric span:last-child {{ font-variant-numeric: tabular-nums; font-weight: 500; }}
-    .status {{ color: #0a0; font-weight: 500; }}
-    a {{ color: #0066cc; text-decoration: none; }}
-    a:hover {{ text-decoration: underline; }}
-  </style>
-</head>
-<body>
-  <h1>Agent Memory DB</h1>
-
-  <section>
-    <h2>Health</h2>
-    <div class="metric"><span>Status</span><span class="status">ok</span></div>
-    <div class="metric"><span><a href="/health">/health</a></span><span></span></div>
-    <div class="metric"><span><a href="/metrics">/metrics</a></span><span>Prometheus</span></div>
-  </section>
-
-  <section>
-    <h2>Usage</h2>
-    <div class="metric"><span>API requests</span><span>{}</span></div>
-    <div class="metric"><span>Episodes stored</span><span>{}</span></div>
-    <div class="metric"><span>Queries</span><span>{}</span></div>
-    <div class="metric"><span>Active tenants</span><span>{}</span></div>
-  </section>
-
-  <section>
-    <h2>Config</h2>
-    <div class="metric"><span>Embedding dim</span><span>{}</span></div>
-    <div class="metric"><span>API key</span><span>{}</span></div>
-    <div class="metric"><span>Rate limit</span><span>{}</span></div>
-    <div class="metric"><span>Audit log</span><span>{}</span></div>
-    <div class="metric"><span>Data dir</span><span>{}</span></div>
-  </section>
-</body>
-</html>"##,
-        requests,
-        store_episodes,
-        queries,
-        tenants,
-        state.default_dim,
-        api_key_str,
-        rate_limit_str,
-        audit_str,
-        state
-            .data_dir
-            .as_ref()
-            .map(|p| p.display().to_string())
-            .unwrap_or_else(|| "—".to_string())
-    );
-    Html(html)
+        AllowMethods::list(cors.allowed_methods.iter().filter_map(|m| m.parse().ok()))
+    };
+    let headers = if any(&cors.allowed_headers) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(cors.allowed_headers.iter().filter_map(|h| h.parse().ok()))
+    };
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .expose_headers([request_id::HEADER])
+        .max_age(Duration::from_secs(cors.max_age_secs))
+}
+
+/// Rate limit middleware: per-tenant fixed window. Runs after auth (requires tenant_id in extensions).
+async fn rate_limit_middleware(
+    State(state): State<AppState>,
+    request: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, Response> {
+    let tenant_id = request
+        .extensions()
+        .get::<String>()
+        .cloned()
+        .unwrap_or_else(|| "unknown".to_string());
+    check_rate_limit(&state, &tenant_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    Ok(next.run(request).await)
 }
 
 async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
     let requests = state.metrics.requests_total.load(Ordering::Relaxed);
     let store_episodes = state.metrics.store_episodes_total.load(Ordering::Relaxed);
     let queries = state.metrics.query_total.load(Ordering::Relaxed);
-    let tenants = state.tenants.read().await.len();
-    (
-        [(
-            axum::http::header::CONTENT_TYPE,
-            "text/plain; charset=utf-8",
-        )],
-        format!(
-            "# HELP agent_mem_requests_total Total authenticated API requests\n\
+    let evictions = state.metrics.tenant_evictions_total.load(Ordering::Relaxed);
+    let subscribers = state.episode_events.receiver_count();
+
+    let mut by_backend: HashMap<&'static str, usize> = HashMap::new();
+    let mut per_tenant = String::from(
+        "# HELP agent_mem_tenant_episodes Episodes held by each loaded tenant\n\
+         # TYPE agent_mem_tenant_episodes gauge\n",
+    );
+    let mut per_tenant_bytes = String::from(
+        "# HELP agent_mem_tenant_stored_bytes Stored bytes of each loaded tenant\n\
+         # TYPE agent_mem_tenant_stored_bytes gauge\n",
+    );
+    let tenants = {
+        let tenants = state.tenants.read().await;
+        let mut loaded: Vec<(&str, &Tenant)> = tenants.iter().collect();
+        loaded.sort_by_key(|&(id, _)| id);
+        for (id, tenant) in loaded {
+            let label = metrics::escape_label(id);
+            *by_backend.entry(tenant.backend.kind()).or_default() += 1;
+            per_tenant.push_str(&format!(
+                "agent_mem_tenant_episodes{{tenant=\"{label}\"}} {}\n",
+                tenant.backend.len()
+            ));
+            per_tenant_bytes.push_str(&format!(
+                "agent_mem_tenant_stored_bytes{{tenant=\"{label}\"}} {}\n",
+                tenant.stored_bytes
+            ));
+        }
+        tenants.loaded_count()
+    };
+
+    let mut body = format!(
+        "# HELP agent_mem_requests_total Total authenticated API requests\n\
              # TYPE agent_mem_requests_total counter\n\
              agent_mem_requests_total {}\n\
              # HELP agent_mem_store_episodes_total Total episodes stored\n\
@@ -522,54 +1417,325 @@ async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
              # HELP agent_mem_query_total Total similarity queries\n\
              # TYPE agent_mem_query_total counter\n\
              agent_mem_query_total {}\n\
-             # HELP agent_mem_tenants_active Active tenant count\n\
+             # HELP agent_mem_tenants_active Loaded tenant count\n\
              # TYPE agent_mem_tenants_active gauge\n\
-             agent_mem_tenants_active {}\n",
-            requests, store_episodes, queries, tenants
-        ),
+             agent_mem_tenants_active {}\n\
+             # HELP agent_mem_tenant_evictions_total Tenants evicted from memory\n\
+             # TYPE agent_mem_tenant_evictions_total counter\n\
+             agent_mem_tenant_evictions_total {}\n\
+             # HELP agent_mem_subscribers Connected /v1/subscribe WebSockets\n\
+             # TYPE agent_mem_subscribers gauge\n\
+             agent_mem_subscribers {}\n",
+        requests, store_episodes, queries, tenants, evictions, subscribers
+    );
+    body.push_str(
+        "# HELP agent_mem_tenants_loaded Loaded tenants by backend\n\
+         # TYPE agent_mem_tenants_loaded gauge\n",
+    );
+    for kind in ["memory", "disk"] {
+        body.push_str(&format!(
+            "agent_mem_tenants_loaded{{backend=\"{kind}\"}} {}\n",
+            by_backend.get(kind).copied().unwrap_or(0)
+        ));
+    }
+    body.push_str(&per_tenant);
+    body.push_str(&per_tenant_bytes);
+    state.metrics.render_detail(&mut body);
+    state.concurrency.render_metrics(&mut body);
+    if let Some(ref follower) = state.replication.follower {
+        follower.render_metrics(&mut body);
+    }
+    if let Some(ref snapshots) = state.snapshots {
+        snapshots.render_metrics(&mut body);
+    }
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; charset=utf-8",
+        )],
+        body,
     )
 }
 
+/// Store episodes for a tenant (creating it if needed), enforcing size limits and quotas.
+#[tracing::instrument(skip(state, episodes), fields(episodes = episodes.len()))]
+async fn store_for_tenant(
+    state: &AppState,
+    tenant_id: &str,
+    episodes: Vec<Episode>,
+) -> Result<(), ApiError> {
+    state.replication.check_writable()?;
+    episodes
+        .iter()
+        .try_for_each(|ep| state.limits.check(ep))
+        .map_err(limit_exceeded)?;
+    let mut tenants = state.tenants.write().await;
+    let tenant = tenant_mut_or_create(state, &mut tenants, tenant_id)?;
+
+    state.quotas(tenant_id).check(tenant, &episodes)?;
+    let count = episodes.len() as u64;
+    let ids: Vec<uuid::Uuid> = episodes.iter().map(|ep| ep.id).collect();
+    let before = tenant.usage();
+    tenant.dirty = true;
+    if let Err(e) = tenant.backend.store_episodes(episodes) {
+        // A failed batch may have stored a prefix; recount rather than guess.
+        tenant.refresh_stored_bytes();
+        return Err(invalid_request(e, tenant.backend.dim()));
+    }
+    // Count and publish the episodes as stored, with any timestamp `auto_timestamp` filled.
+    let stored = ids.iter().filter_map(|id| tenant.backend.get(*id));
+    tenant.stored_bytes += stored.clone().map(episode_bytes).sum::<u64>();
+    let published =
+        (state.episode_events.receiver_count() > 0).then(|| stored.cloned().collect::<Vec<_>>());
+    state.metrics.record_store(tenant_id, count);
+    state.usage.record_store(tenant_id, count, tenant);
+    state
+        .webhooks
+        .stored(state, tenant_id, before, tenant.usage());
+    if let Some(episodes) = published {
+        subscribe::publish(&state.episode_events, tenant_id, episodes);
+    }
+    Ok(())
+}
+
+/// Similarity query against an existing tenant.
+#[tracing::instrument(skip(state, query_embedding, opts), fields(top_k = opts.top_k))]
+async fn query_for_tenant(
+    state: &AppState,
+    tenant_id: &str,
+    query_embedding: &[f32],
+    opts: QueryOptions,
+) -> Result<QueryResults, ApiError> {
+    let start = Instant::now();
+    let mut tenants = state.tenants.write().await;
+    let db = &existing_tenant_mut(state, &mut tenants, tenant_id)?.backend;
+    let results = db
+        .query_similar_timed(query_embedding, opts)
+        .map_err(|e| invalid_request(e, db.dim()))?;
+    state.metrics.record_query(tenant_id, start.elapsed());
+    state.usage.record_query(tenant_id);
+    audit_log(state, tenant_id, "query", None, None, None);
+    Ok(results)
+}
+
+/// A retention operation on one tenant.
+#[derive(Clone, Copy, Debug)]
+enum Prune {
+    OlderThan(i64),
+    KeepNewest(usize),
+    KeepHighestReward(usize),
+}
+
+impl Prune {
+    fn op(&self) -> &'static str {
+        match self {
+            Prune::OlderThan(_) => "prune_older_than",
+            Prune::KeepNewest(_) => "prune_keep_newest",
+            Prune::KeepHighestReward(_) => "prune_keep_highest_reward",
+        }
+    }
+}
+
+/// Prune an existing tenant; returns the number of episodes removed.
+#[tracing::instrument(skip(state), fields(op = prune.op()))]
+async fn prune_for_tenant(
+    state: &AppState,
+    tenant_id: &str,
+    prune: Prune,
+) -> Result<usize, ApiError> {
+    state.replication.check_writable()?;
+    let mut tenants = state.tenants.write().await;
+    let tenant = existing_tenant_mut(state, &mut tenants, tenant_id)?;
+    let removed = state
+        .attach_archive(tenant_id, &mut tenant.backend)
+        .and_then(|()| match prune {
+            Prune::OlderThan(ts) => tenant.backend.prune_older_than(ts),
+            Prune::KeepNewest(n) => tenant.backend.prune_keep_newest(n),
+            Prune::KeepHighestReward(n) => tenant.backend.prune_keep_highest_reward(n),
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+    tenant.refresh_stored_bytes();
+    if removed > 0 {
+        tenant.dirty = true;
+        state.replication.log_rewritten(tenant_id);
+    }
+    audit_log(state, tenant_id, prune.op(), None, Some(removed), None);
+    let episodes = tenant.backend.len();
+    state
+        .webhooks
+        .pruned(state, tenant_id, prune.op(), removed, episodes);
+    Ok(removed)
+}
+
+/// Delete matching episodes from an existing tenant; returns the number removed.
+#[tracing::instrument(skip(state, predicate))]
+async fn delete_for_tenant(
+    state: &AppState,
+    tenant_id: &str,
+    op: &str,
+    predicate: impl Fn(&Episode) -> bool,
+) -> Result<usize, ApiError> {
+    state.replication.check_writable()?;
+    let mut tenants = state.tenants.write().await;
+    let tenant = existing_tenant_mut(state, &mut tenants, tenant_id)?;
+    let removed = tenant.backend.delete_where(predicate).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
+        )
+    })?;
+    if removed > 0 {
+        tenant.refresh_stored_bytes();
+        tenant.dirty = true;
+        state.replication.log_rewritten(tenant_id);
+    }
+    audit_log(state, tenant_id, op, None, Some(removed), None);
+    let episodes = tenant.backend.len();
+    state
+        .webhooks
+        .pruned(state, tenant_id, op, removed, episodes);
+    Ok(removed)
+}
+
+/// Remove every episode of one session (conversation) from an existing tenant; returns the
+/// number removed.
+async fn prune_session_for_tenant(
+    state: &AppState,
+    tenant_id: &str,
+    session_id: &str,
+) -> Result<usize, ApiError> {
+    delete_for_tenant(state, tenant_id, "prune_session", |ep| {
+        ep.session_id.as_deref() == Some(session_id)
+    })
+    .await
+}
+
+/// Query for `GET /v1/episodes`.
+#[derive(Deserialize, IntoParams)]
+struct ListEpisodesQuery {
+    /// Episodes to skip.
+    #[serde(default)]
+    offset: usize,
+    /// Max episodes returned; all by default.
+    limit: Option<usize>,
+    /// Include soft-deleted episodes.
+    #[serde(default)]
+    include_deleted: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ListEpisodesResponse {
+    /// Oldest first (undated episodes first), ties broken by id.
+    #[schema(value_type = Vec<openapi::EpisodeSchema>)]
+    episodes: Vec<Episode>,
+    /// Episodes listable before `offset` and `limit`.
+    total: usize,
+}
+
+/// List the tenant's episodes, oldest first. Send `Accept: application/x-ndjson` to stream
+/// them one per line (with the total in `X-Total-Count`) instead of one JSON document.
+#[utoipa::path(
+    get,
+    path = "/v1/episodes",
+    tag = "episodes",
+    params(ListEpisodesQuery),
+    responses(
+        (status = 200, body = ListEpisodesResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn list_episodes(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    axum::extract::Query(query): axum::extract::Query<ListEpisodesQuery>,
+    headers: axum::http::HeaderMap,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let (total, episodes) = {
+        let mut tenants = state.tenants.write().await;
+        let db = &existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+        let mut listed: Vec<&Episode> = db
+            .iter()
+            .filter(|ep| query.include_deleted || !ep.deleted)
+            .collect();
+        listed.sort_unstable_by_key(|ep| (ep.timestamp, ep.id));
+        let total = listed.len();
+        let episodes: Vec<Episode> = listed
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        (total, episodes)
+    };
+    audit_log(
+        &state,
+        &tenant_id,
+        "list_episodes",
+        None,
+        Some(episodes.len()),
+        None,
+    );
+    if ndjson::requested(&headers) {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert("x-total-count", total.into());
+        return Ok(ndjson::response(episodes, headers));
+    }
+    Ok(Json(ListEpisodesResponse { episodes, total }).into_response())
+}
+
+/// Store one episode.
+#[utoipa::path(
+    post,
+    path = "/v1/episodes",
+    tag = "episodes",
+    request_body = StoreEpisodeRequest,
+    responses(
+        (status = 200, body = StoreEpisodeResponse),
+        (status = 400, description = "Embedding dimension mismatch: `code` is `dimension_mismatch`, with `expected_dim`, `received_dim` and `tenant_dim`", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `write` scope", body = openapi::ErrorBody),
+        (status = 429, description = "Quota or rate limit exceeded", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
 async fn store_episode(
     State(state): State<AppState>,
     axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
     Json(req): Json<StoreEpisodeRequest>,
 ) -> Result<Json<StoreEpisodeResponse>, (StatusCode, Json<serde_json::Value>)> {
-    let mut ep = Episode::new(&req.task_id, req.state_embedding.clone(), req.reward);
+    let embedding_model = embedding::model_for(
+        state.embedder.as_deref(),
+        req.text.as_deref(),
+        req.embedding_model,
+    );
+    let embedding = embedding::resolve(
+        state.embedder.as_deref(),
+        vec![(req.state_embedding, req.text)],
+        "state_embedding",
+    )
+    .await?
+    .remove(0);
+    let mut ep = Episode::new(&req.task_id, embedding, req.reward);
     ep.metadata = req.metadata;
     ep.timestamp = req.timestamp;
     ep.tags = req.tags;
     ep.source = req.source;
     ep.user_id = req.user_id;
+    ep.session_id = req.session_id;
+    ep.embedding_model = embedding_model;
+    ep.cost = req.cost;
+    ep.duration_ms = req.duration_ms;
     let id = ep.id.to_string();
 
-    let mut tenants = state.tenants.write().await;
-    let db = match tenants.entry(tenant_id.clone()) {
-        std::collections::hash_map::Entry::Occupied(o) => o.into_mut(),
-        std::collections::hash_map::Entry::Vacant(v) => {
-            let backend =
-                create_tenant_backend(state.data_dir.as_ref(), &tenant_id, state.default_dim)
-                    .map_err(|e| {
-                        (
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            Json(serde_json::json!({"error": e.to_string()})),
-                        )
-                    })?;
-            v.insert(backend)
-        }
-    };
-
-    db.store_episode(ep).map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": e.to_string()})),
-        )
-    })?;
-
-    state
-        .metrics
-        .store_episodes_total
-        .fetch_add(1, Ordering::Relaxed);
+    store_for_tenant(&state, &tenant_id, vec![ep]).await?;
     audit_log(
         &state,
         &tenant_id,
@@ -581,53 +1747,61 @@ async fn store_episode(
     Ok(Json(StoreEpisodeResponse { id }))
 }
 
+/// Store a batch of episodes.
+#[utoipa::path(
+    post,
+    path = "/v1/episodes/batch",
+    tag = "episodes",
+    request_body = StoreEpisodesRequest,
+    responses(
+        (status = 200, body = StoreEpisodesResponse),
+        (status = 400, description = "Embedding dimension mismatch: `code` is `dimension_mismatch`, with `expected_dim`, `received_dim` and `tenant_dim`", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `write` scope", body = openapi::ErrorBody),
+        (status = 429, description = "Quota or rate limit exceeded", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
 async fn store_episodes(
     State(state): State<AppState>,
     axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
-    Json(req): Json<StoreEpisodesRequest>,
+    Json(mut req): Json<StoreEpisodesRequest>,
 ) -> Result<Json<StoreEpisodesResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let inputs = req
+        .episodes
+        .iter_mut()
+        .map(|e| {
+            e.embedding_model = embedding::model_for(
+                state.embedder.as_deref(),
+                e.text.as_deref(),
+                e.embedding_model.take(),
+            );
+            (e.state_embedding.take(), e.text.take())
+        })
+        .collect();
+    let embeddings =
+        embedding::resolve(state.embedder.as_deref(), inputs, "state_embedding").await?;
     let episodes: Vec<Episode> = req
         .episodes
         .into_iter()
-        .map(|e| {
-            let mut ep = Episode::new(&e.task_id, e.state_embedding, e.reward);
+        .zip(embeddings)
+        .map(|(e, embedding)| {
+            let mut ep = Episode::new(&e.task_id, embedding, e.reward);
             ep.metadata = e.metadata;
             ep.timestamp = e.timestamp;
             ep.tags = e.tags;
             ep.source = e.source;
             ep.user_id = e.user_id;
+            ep.session_id = e.session_id;
+            ep.embedding_model = e.embedding_model;
+            ep.cost = e.cost;
+            ep.duration_ms = e.duration_ms;
             ep
         })
         .collect();
     let ids: Vec<String> = episodes.iter().map(|e| e.id.to_string()).collect();
 
-    let mut tenants = state.tenants.write().await;
-    let db = match tenants.entry(tenant_id.clone()) {
-        std::collections::hash_map::Entry::Occupied(o) => o.into_mut(),
-        std::collections::hash_map::Entry::Vacant(v) => {
-            let backend =
-                create_tenant_backend(state.data_dir.as_ref(), &tenant_id, state.default_dim)
-                    .map_err(|e| {
-                        (
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            Json(serde_json::json!({"error": e.to_string()})),
-                        )
-                    })?;
-            v.insert(backend)
-        }
-    };
-
-    db.store_episodes(episodes).map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": e.to_string()})),
-        )
-    })?;
-
-    state
-        .metrics
-        .store_episodes_total
-        .fetch_add(ids.len() as u64, Ordering::Relaxed);
+    store_for_tenant(&state, &tenant_id, episodes).await?;
     audit_log(
         &state,
         &tenant_id,
@@ -639,91 +1813,561 @@ async fn store_episodes(
     Ok(Json(StoreEpisodesResponse { ids }))
 }
 
-async fn query_similar(
+fn parse_episode_id(id: &str) -> Result<uuid::Uuid, ApiError> {
+    id.parse().map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": format!("Invalid episode id {id:?}")})),
+        )
+    })
+}
+
+/// Replace an episode, with optimistic concurrency: the update applies only if the episode's
+/// `version` still equals `expected_version`, so concurrent writers can't silently overwrite
+/// each other. On 409 re-read the episode and retry.
+#[utoipa::path(
+    put,
+    path = "/v1/episodes/{id}",
+    tag = "episodes",
+    params(("id" = String, Path, description = "Episode id")),
+    request_body = UpdateEpisodeRequest,
+    responses(
+        (status = 200, body = UpdateEpisodeResponse),
+        (status = 400, description = "Invalid id or embedding", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `write` scope", body = openapi::ErrorBody),
+        (status = 404, description = "No such episode", body = openapi::ErrorBody),
+        (status = 409, description = "Version changed since read; the body has the current `version`", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn update_episode(
     State(state): State<AppState>,
     axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
-    Json(req): Json<QuerySimilarRequest>,
-) -> Result<Json<QuerySimilarResponse>, (StatusCode, Json<serde_json::Value>)> {
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(req): Json<UpdateEpisodeRequest>,
+) -> Result<Json<UpdateEpisodeResponse>, ApiError> {
+    let id = parse_episode_id(&id)?;
+    let e = req.episode;
+    let embedding_model = embedding::model_for(
+        state.embedder.as_deref(),
+        e.text.as_deref(),
+        e.embedding_model,
+    );
+    let embedding = embedding::resolve(
+        state.embedder.as_deref(),
+        vec![(e.state_embedding, e.text)],
+        "state_embedding",
+    )
+    .await?
+    .remove(0);
+    let mut ep = Episode::new(&e.task_id, embedding, e.reward);
+    ep.id = id;
+    ep.metadata = e.metadata;
+    ep.timestamp = e.timestamp;
+    ep.tags = e.tags;
+    ep.source = e.source;
+    ep.user_id = e.user_id;
+    ep.session_id = e.session_id;
+    ep.embedding_model = embedding_model;
+    ep.cost = e.cost;
+    ep.duration_ms = e.duration_ms;
+
+    state.replication.check_writable()?;
+    state.limits.check(&ep).map_err(limit_exceeded)?;
     let mut tenants = state.tenants.write().await;
-    let db = if let Some(backend) = tenants.get_mut(&tenant_id) {
-        backend
-    } else if let Some(ref data_dir) = state.data_dir {
-        let meta_path = data_dir
-            .join(sanitize_tenant_path(&tenant_id))
-            .join("meta.json");
-        if !meta_path.exists() {
-            return Err((
+    let tenant = existing_tenant_mut(&state, &mut tenants, &tenant_id)?;
+    let current = tenant.backend.get(id);
+    // An update doesn't undo a soft delete; that's what restore is for.
+    ep.deleted = current.is_some_and(|c| c.deleted);
+    let old_bytes = current.map(episode_bytes).unwrap_or(0);
+    // A missing episode is a 404 from the backend below, not a quota error.
+    if current.is_some() {
+        state
+            .quotas(&tenant_id)
+            .check_update(tenant, old_bytes, &ep)?;
+    }
+    let version = tenant
+        .backend
+        .update_episode(ep, req.expected_version)
+        .map_err(|err| match err {
+            AgentMemError::NotFound => (
                 StatusCode::NOT_FOUND,
-                Json(serde_json::json!({"error": "No episodes stored for this tenant yet"})),
-            ));
-        }
-        let backend = create_tenant_backend(Some(data_dir), &tenant_id, state.default_dim)
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::json!({"error": e.to_string()})),
-                )
-            })?;
-        tenants.insert(tenant_id.clone(), backend);
-        tenants.get_mut(&tenant_id).unwrap()
-    } else {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(serde_json::json!({"error": "No episodes stored for this tenant yet"})),
-        ));
-    };
+                Json(serde_json::json!({"error": format!("No episode {id}")})),
+            ),
+            AgentMemError::Conflict { actual, .. } => (
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({"error": err.to_string(), "version": actual})),
+            ),
+            err => invalid_request(err, tenant.backend.dim()),
+        })?;
+    tenant.dirty = true;
+    let bytes = tenant.backend.get(id).map_or(0, episode_bytes);
+    tenant.stored_bytes = tenant.stored_bytes.saturating_sub(old_bytes) + bytes;
+    drop(tenants);
+    audit_log(
+        &state,
+        &tenant_id,
+        "update_episode",
+        Some(&e.task_id),
+        Some(1),
+        None,
+    );
+    Ok(Json(UpdateEpisodeResponse {
+        id: id.to_string(),
+        version,
+    }))
+}
 
-    let mut opts = QueryOptions::new(req.min_reward, req.top_k);
-    if let Some(tags) = req.tags_any {
-        if !tags.is_empty() {
-            opts = opts.tags_any(tags);
+/// Similarity search with filters.
+#[utoipa::path(
+    post,
+    path = "/v1/query",
+    tag = "query",
+    request_body = QuerySimilarRequest,
+    responses(
+        (status = 200, description = "Matching episodes; with `Accept: application/x-ndjson`, one episode per line and `X-Query-Truncated: true` when `truncated`", body = QuerySimilarResponse),
+        (status = 400, description = "Embedding dimension mismatch: `code` is `dimension_mismatch`, with `expected_dim`, `received_dim` and `tenant_dim`", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn query_similar(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    headers: axum::http::HeaderMap,
+    Json(mut req): Json<QuerySimilarRequest>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let opts = req.options();
+
+    let query_embedding = embedding::resolve(
+        state.embedder.as_deref(),
+        vec![(req.query_embedding, req.text)],
+        "query_embedding",
+    )
+    .await?
+    .remove(0);
+    let results = query_for_tenant(&state, &tenant_id, &query_embedding, opts).await?;
+    if ndjson::requested(&headers) {
+        let mut headers = axum::http::HeaderMap::new();
+        if results.truncated {
+            headers.insert(
+                "x-query-truncated",
+                axum::http::HeaderValue::from_static("true"),
+            );
         }
+        return Ok(ndjson::response(results.episodes, headers));
     }
-    if let Some(tags) = req.tags_all {
-        if !tags.is_empty() {
-            opts = opts.tags_all(tags);
-        }
-    }
-    if let Some(ref prefix) = req.task_id_prefix {
-        opts = opts.task_id_prefix(prefix.clone());
-    }
-    if let Some(ts) = req.time_after {
-        opts = opts.time_after(ts);
-    }
-    if let Some(ts) = req.time_before {
-        opts = opts.time_before(ts);
-    }
-    if let Some(ref s) = req.source {
-        opts = opts.source(s.clone());
+    Ok(Json(QuerySimilarResponse {
+        episodes: results.episodes,
+        truncated: results.truncated,
+    })
+    .into_response())
+}
+
+/// Search with several query embeddings and fuse the result lists by weighted reciprocal
+/// rank, best first.
+#[utoipa::path(
+    post,
+    path = "/v1/query/fused",
+    tag = "query",
+    request_body = QueryFusedRequest,
+    responses(
+        (status = 200, body = QueryFusedResponse),
+        (status = 400, description = "Weights or embeddings invalid", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn query_fused(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    Json(mut req): Json<QueryFusedRequest>,
+) -> Result<Json<QueryFusedResponse>, (StatusCode, Json<serde_json::Value>)> {
+    if req.options.query_embedding.is_some() || req.options.text.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": "Put query_embedding and text in queries"
+            })),
+        ));
     }
-    if let Some(ref u) = req.user_id {
-        opts = opts.user_id(u.clone());
+    let start = Instant::now();
+    let opts = req.options.options();
+    let weights: Vec<f32> = req.queries.iter().map(|q| q.weight).collect();
+    let queries = embedding::resolve(
+        state.embedder.as_deref(),
+        req.queries
+            .into_iter()
+            .map(|q| (q.query_embedding, q.text))
+            .collect(),
+        "query_embedding",
+    )
+    .await?;
+    let hits = {
+        let mut tenants = state.tenants.write().await;
+        let db = &existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+        db.query_fused(&queries, &weights, opts)
+            .map_err(|e| invalid_request(e, db.dim()))?
+    };
+    state.metrics.record_query(&tenant_id, start.elapsed());
+    state.usage.record_query(&tenant_id);
+    audit_log(&state, &tenant_id, "query_fused", None, None, None);
+    Ok(Json(QueryFusedResponse {
+        results: hits
+            .into_iter()
+            .map(|hit| FusedResult {
+                episode: hit.episode,
+                score: hit.score,
+            })
+            .collect(),
+    }))
+}
+
+/// The nearest successes and the nearest failures for a query, as two lists, e.g. contrast
+/// pairs for a reflection prompt.
+#[utoipa::path(
+    post,
+    path = "/v1/query/contrastive",
+    tag = "query",
+    request_body = QueryContrastiveRequest,
+    responses(
+        (status = 200, body = QueryContrastiveResponse),
+        (status = 400, description = "Embedding dimension mismatch", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn query_contrastive(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    Json(mut req): Json<QueryContrastiveRequest>,
+) -> Result<Json<QueryContrastiveResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let start = Instant::now();
+    let opts = req.options.options();
+    let query_embedding = embedding::resolve(
+        state.embedder.as_deref(),
+        vec![(req.options.query_embedding, req.options.text)],
+        "query_embedding",
+    )
+    .await?
+    .remove(0);
+    let recall = {
+        let mut tenants = state.tenants.write().await;
+        let db = &existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+        db.contrastive_recall_with_options(&query_embedding, req.reward_threshold, opts)
+            .map_err(|e| invalid_request(e, db.dim()))?
+    };
+    state.metrics.record_query(&tenant_id, start.elapsed());
+    state.usage.record_query(&tenant_id);
+    audit_log(&state, &tenant_id, "query_contrastive", None, None, None);
+    Ok(Json(QueryContrastiveResponse {
+        high_reward: recall.high_reward,
+        low_reward: recall.low_reward,
+    }))
+}
+
+/// Search the tenant's archive of pruned episodes (see `retention.archive_dir`). Empty when
+/// no archive is configured.
+#[utoipa::path(
+    post,
+    path = "/v1/query/archive",
+    tag = "query",
+    request_body = QuerySimilarRequest,
+    responses(
+        (status = 200, body = QueryArchiveResponse),
+        (status = 400, description = "Embedding dimension mismatch", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn query_archive(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    Json(mut req): Json<QuerySimilarRequest>,
+) -> Result<Json<QueryArchiveResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let start = Instant::now();
+    let opts = req.options();
+    let query_embedding = embedding::resolve(
+        state.embedder.as_deref(),
+        vec![(req.query_embedding, req.text)],
+        "query_embedding",
+    )
+    .await?
+    .remove(0);
+    let episodes = {
+        let mut tenants = state.tenants.write().await;
+        let db = &mut existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+        let dim = db.dim();
+        state
+            .attach_archive(&tenant_id, db)
+            .and_then(|()| db.query_archive(&query_embedding, opts))
+            .map_err(|e| invalid_request(e, dim))?
+    };
+    state.metrics.record_query(&tenant_id, start.elapsed());
+    state.usage.record_query(&tenant_id);
+    audit_log(&state, &tenant_id, "query_archive", None, None, None);
+    Ok(Json(QueryArchiveResponse { episodes }))
+}
+
+/// Run a query and report how it got its results: the index candidates, which filter
+/// eliminated each, the candidate multiplier and timings.
+#[utoipa::path(
+    post,
+    path = "/v1/query/explain",
+    tag = "query",
+    request_body = QuerySimilarRequest,
+    responses(
+        (status = 200, body = ExplainResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn query_explain(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    Json(mut req): Json<QuerySimilarRequest>,
+) -> Result<Json<ExplainResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let start = Instant::now();
+    let top_k = req.top_k;
+    let opts = req.options();
+    let query_embedding = embedding::resolve(
+        state.embedder.as_deref(),
+        vec![(req.query_embedding, req.text)],
+        "query_embedding",
+    )
+    .await?
+    .remove(0);
+    let embed_time = start.elapsed();
+
+    let explained = {
+        let mut tenants = state.tenants.write().await;
+        let db = &existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+        db.explain_query(&query_embedding, opts)
+            .map_err(|e| invalid_request(e, db.dim()))?
+    };
+    audit_log(&state, &tenant_id, "query_explain", None, None, None);
+
+    let mut rejected = BTreeMap::new();
+    let candidates = explained
+        .candidates
+        .into_iter()
+        .map(|c| {
+            let status = match (c.returned, c.rejected_by) {
+                (true, _) => "returned",
+                (false, Some(filter)) => {
+                    *rejected.entry(filter).or_insert(0) += 1;
+                    "rejected"
+                }
+                (false, None) => "cut",
+            };
+            ExplainCandidate {
+                id: c.episode.id.to_string(),
+                task_id: c.episode.task_id,
+                distance: c.distance,
+                reward: c.episode.reward,
+                timestamp: c.episode.timestamp,
+                tags: c.episode.tags,
+                source: c.episode.source,
+                user_id: c.episode.user_id,
+                session_id: c.episode.session_id,
+                status,
+                rejected_by: c.rejected_by,
+            }
+        })
+        .collect();
+    Ok(Json(ExplainResponse {
+        top_k,
+        candidate_multiplier: explained.candidate_multiplier,
+        allowed_keys: explained.allowed_keys,
+        candidates_requested: explained.candidates_requested,
+        truncated: explained.truncated,
+        candidates,
+        rejected,
+        result_ids: explained
+            .results
+            .iter()
+            .map(|ep| ep.id.to_string())
+            .collect(),
+        timings: ExplainTimings {
+            embed_us: embed_time.as_micros() as u64,
+            search_us: explained.search_time.as_micros() as u64,
+            filter_us: explained.filter_time.as_micros() as u64,
+            total_us: start.elapsed().as_micros() as u64,
+        },
+    }))
+}
+
+/// Delete every episode matching the filters (at least one is required), e.g. all of a
+/// user's episodes for a deletion request.
+#[utoipa::path(
+    post,
+    path = "/v1/episodes/delete",
+    tag = "prune",
+    request_body = EpisodeFilter,
+    responses(
+        (status = 200, body = DeleteEpisodesResponse),
+        (status = 400, description = "No filter given", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn delete_episodes(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    Json(req): Json<EpisodeFilter>,
+) -> Result<Json<DeleteEpisodesResponse>, (StatusCode, Json<serde_json::Value>)> {
+    if req.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": "At least one filter is required (tags_any, tags_all, tag_prefix_any, task_id_prefix, time_after, time_before, source, user_id, session_id, embedding_model)"
+            })),
+        ));
     }
+    // Soft-deleted episodes go too: this is how a user's data is erased.
+    let opts = req.apply(QueryOptions::new(f32::NEG_INFINITY, 0).include_deleted(true));
+    let deleted =
+        delete_for_tenant(&state, &tenant_id, "delete_episodes", |ep| opts.matches(ep)).await?;
+    Ok(Json(DeleteEpisodesResponse { deleted }))
+}
 
-    let episodes = db
-        .query_similar_with_options(&req.query_embedding, opts)
-        .map_err(|e| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": e.to_string()})),
-            )
-        })?;
+/// Soft-delete an episode: queries skip it, but it is kept (and persisted) until restored or
+/// purged, as an undo window.
+#[utoipa::path(
+    post,
+    path = "/v1/episodes/{id}/soft-delete",
+    tag = "prune",
+    params(("id" = String, Path, description = "Episode id")),
+    responses(
+        (status = 200, body = SoftDeleteResponse),
+        (status = 400, description = "Invalid id", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+        (status = 404, description = "No such episode", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn soft_delete_episode(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<SoftDeleteResponse>, ApiError> {
+    set_deleted_for_tenant(&state, &tenant_id, &id, true).await
+}
+
+/// Undo a soft delete.
+#[utoipa::path(
+    post,
+    path = "/v1/episodes/{id}/restore",
+    tag = "prune",
+    params(("id" = String, Path, description = "Episode id")),
+    responses(
+        (status = 200, body = SoftDeleteResponse),
+        (status = 400, description = "Invalid id", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+        (status = 404, description = "No such episode", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn restore_episode(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<SoftDeleteResponse>, ApiError> {
+    set_deleted_for_tenant(&state, &tenant_id, &id, false).await
+}
+
+async fn set_deleted_for_tenant(
+    state: &AppState,
+    tenant_id: &str,
+    id: &str,
+    deleted: bool,
+) -> Result<Json<SoftDeleteResponse>, ApiError> {
+    let id = parse_episode_id(id)?;
+    state.replication.check_writable()?;
+    let mut tenants = state.tenants.write().await;
+    let tenant = existing_tenant_mut(state, &mut tenants, tenant_id)?;
+    let result = if deleted {
+        tenant.backend.soft_delete(id)
+    } else {
+        tenant.backend.restore(id)
+    };
+    result.map_err(|e| match e {
+        AgentMemError::NotFound => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": format!("No episode {id}")})),
+        ),
+        e => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
+        ),
+    })?;
+    tenant.dirty = true;
+    drop(tenants);
+    let op = if deleted { "soft_delete" } else { "restore" };
+    audit_log(state, tenant_id, op, None, Some(1), None);
+    Ok(Json(SoftDeleteResponse {
+        id: id.to_string(),
+        deleted,
+    }))
+}
 
-    state.metrics.query_total.fetch_add(1, Ordering::Relaxed);
-    audit_log(&state, &tenant_id, "query", None, None, None);
-    Ok(Json(QuerySimilarResponse { episodes }))
+/// Permanently remove every soft-deleted episode.
+#[utoipa::path(
+    post,
+    path = "/v1/episodes/purge-deleted",
+    tag = "prune",
+    responses(
+        (status = 200, body = DeleteEpisodesResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn purge_deleted(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+) -> Result<Json<DeleteEpisodesResponse>, ApiError> {
+    let deleted = delete_for_tenant(&state, &tenant_id, "purge_deleted", |ep| ep.deleted).await?;
+    Ok(Json(DeleteEpisodesResponse { deleted }))
 }
 
+/// Save an in-memory tenant to a file (no-op in disk mode).
+#[utoipa::path(
+    post,
+    path = "/v1/save",
+    tag = "admin",
+    request_body = SaveRequest,
+    responses(
+        (status = 200, body = SaveResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
 async fn save(
     State(state): State<AppState>,
     axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
     Json(req): Json<SaveRequest>,
 ) -> Result<Json<SaveResponse>, (StatusCode, Json<serde_json::Value>)> {
-    let tenants = state.tenants.read().await;
-    let db = tenants.get(&tenant_id).ok_or((
-        StatusCode::NOT_FOUND,
-        Json(serde_json::json!({"error": "No episodes stored for this tenant yet"})),
-    ))?;
+    let mut tenants = state.tenants.write().await;
+    let db = &existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
 
     let path = state
         .data_dir
@@ -731,12 +2375,14 @@ async fn save(
         .map(|d| d.join(&req.path))
         .unwrap_or_else(|| PathBuf::from(&req.path));
 
-    db.save_to_file(&path).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": format!("Save failed: {}", e)})),
-        )
-    })?;
+    tracing::info_span!("save_to_file", path = %path.display())
+        .in_scope(|| db.save_to_file(&path))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": format!("Save failed: {}", e)})),
+            )
+        })?;
 
     audit_log(
         &state,
@@ -749,30 +2395,50 @@ async fn save(
     Ok(Json(SaveResponse { ok: true }))
 }
 
+/// Load a tenant from a file (in-memory mode only).
+#[utoipa::path(
+    post,
+    path = "/v1/load",
+    tag = "admin",
+    request_body = LoadRequest,
+    responses(
+        (status = 200, body = LoadResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
 async fn load(
     State(state): State<AppState>,
     axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
     Json(req): Json<LoadRequest>,
 ) -> Result<Json<LoadResponse>, (StatusCode, Json<serde_json::Value>)> {
-    if state.data_dir.is_some() {
+    state.replication.check_writable()?;
+    if state.tenant_settings.resolve(&tenant_id).backend == BackendKind::Disk {
         return Err((
             StatusCode::BAD_REQUEST,
-            Json(
-                serde_json::json!({"error": "Load not supported when using disk-backed storage (AGENT_MEM_DATA_DIR)"}),
-            ),
+            Json(serde_json::json!({"error": "Load not supported for disk-backed tenants"})),
         ));
     }
 
     let path = PathBuf::from(&req.path);
-    let db = AgentMemDB::load_from_file(&path).map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": format!("Load failed: {}", e)})),
-        )
-    })?;
+    let mut db = tracing::info_span!("load_from_file", path = %path.display())
+        .in_scope(|| AgentMemDB::load_from_file(&path))
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": format!("Load failed: {}", e)})),
+            )
+        })?;
+    db.set_auto_timestamp(state.fills_timestamps());
 
-    let mut tenants = state.tenants.write().await;
-    tenants.insert(tenant_id.clone(), TenantBackend::InMemory(db));
+    let mut tenant = Tenant::new(TenantStore::Memory(db));
+    tenant.dirty = true;
+    state
+        .tenants
+        .write()
+        .await
+        .insert(tenant_id.clone(), tenant);
 
     audit_log(
         &state,
@@ -785,106 +2451,129 @@ async fn load(
     Ok(Json(LoadResponse { ok: true }))
 }
 
+/// Remove episodes older than a cutoff.
+#[utoipa::path(
+    post,
+    path = "/v1/prune/older-than",
+    tag = "prune",
+    request_body = PruneOlderThanRequest,
+    responses(
+        (status = 200, body = PruneResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
 async fn prune_older_than(
     State(state): State<AppState>,
     axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
     Json(req): Json<PruneOlderThanRequest>,
 ) -> Result<Json<PruneResponse>, (StatusCode, Json<serde_json::Value>)> {
-    let mut tenants = state.tenants.write().await;
-    let db = tenants.get_mut(&tenant_id).ok_or((
-        StatusCode::NOT_FOUND,
-        Json(serde_json::json!({"error": "No episodes stored for this tenant yet"})),
-    ))?;
-
-    let removed = db.prune_older_than(req.timestamp_cutoff_ms).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": e.to_string()})),
-        )
-    })?;
-    audit_log(
+    let removed = prune_for_tenant(
         &state,
         &tenant_id,
-        "prune_older_than",
-        None,
-        Some(removed),
-        None,
-    );
+        Prune::OlderThan(req.timestamp_cutoff_ms),
+    )
+    .await?;
     Ok(Json(PruneResponse { removed }))
 }
 
+/// Keep only the n most recent episodes.
+#[utoipa::path(
+    post,
+    path = "/v1/prune/keep-newest",
+    tag = "prune",
+    request_body = PruneKeepNewestRequest,
+    responses(
+        (status = 200, body = PruneResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
 async fn prune_keep_newest(
     State(state): State<AppState>,
     axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
     Json(req): Json<PruneKeepNewestRequest>,
 ) -> Result<Json<PruneResponse>, (StatusCode, Json<serde_json::Value>)> {
-    let mut tenants = state.tenants.write().await;
-    let db = tenants.get_mut(&tenant_id).ok_or((
-        StatusCode::NOT_FOUND,
-        Json(serde_json::json!({"error": "No episodes stored for this tenant yet"})),
-    ))?;
-
-    let removed = db.prune_keep_newest(req.n).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": e.to_string()})),
-        )
-    })?;
-    audit_log(
-        &state,
-        &tenant_id,
-        "prune_keep_newest",
-        None,
-        Some(removed),
-        None,
-    );
+    let removed = prune_for_tenant(&state, &tenant_id, Prune::KeepNewest(req.n)).await?;
     Ok(Json(PruneResponse { removed }))
 }
 
+/// Keep only the n highest-reward episodes.
+#[utoipa::path(
+    post,
+    path = "/v1/prune/keep-highest-reward",
+    tag = "prune",
+    request_body = PruneKeepHighestRewardRequest,
+    responses(
+        (status = 200, body = PruneResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
 async fn prune_keep_highest_reward(
     State(state): State<AppState>,
     axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
     Json(req): Json<PruneKeepHighestRewardRequest>,
 ) -> Result<Json<PruneResponse>, (StatusCode, Json<serde_json::Value>)> {
-    let mut tenants = state.tenants.write().await;
-    let db = tenants.get_mut(&tenant_id).ok_or((
-        StatusCode::NOT_FOUND,
-        Json(serde_json::json!({"error": "No episodes stored for this tenant yet"})),
-    ))?;
+    let removed = prune_for_tenant(&state, &tenant_id, Prune::KeepHighestReward(req.n)).await?;
+    Ok(Json(PruneResponse { removed }))
+}
 
-    let removed = db.prune_keep_highest_reward(req.n).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": e.to_string()})),
-        )
-    })?;
-    audit_log(
-        &state,
-        &tenant_id,
-        "prune_keep_highest_reward",
-        None,
-        Some(removed),
-        None,
-    );
+/// Remove every episode of one session, e.g. when a conversation ends.
+#[utoipa::path(
+    post,
+    path = "/v1/prune/session",
+    tag = "prune",
+    request_body = PruneSessionRequest,
+    responses(
+        (status = 200, body = PruneResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn prune_session(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    Json(req): Json<PruneSessionRequest>,
+) -> Result<Json<PruneResponse>, ApiError> {
+    let removed = prune_session_for_tenant(&state, &tenant_id, &req.session_id).await?;
     Ok(Json(PruneResponse { removed }))
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct CheckpointResponse {
     ok: bool,
 }
 
+/// Persist the exact-index checkpoint (disk mode).
+#[utoipa::path(
+    post,
+    path = "/v1/checkpoint",
+    tag = "admin",
+    responses(
+        (status = 200, body = CheckpointResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
 async fn checkpoint(
     State(state): State<AppState>,
     axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
 ) -> Result<Json<CheckpointResponse>, (StatusCode, Json<serde_json::Value>)> {
     let mut tenants = state.tenants.write().await;
-    let db = tenants.get_mut(&tenant_id).ok_or((
-        StatusCode::NOT_FOUND,
-        Json(serde_json::json!({"error": "No episodes stored for this tenant yet"})),
-    ))?;
+    let db = &mut existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
 
-    db.checkpoint().map_err(|e| {
+    checkpoint_backend(&state.metrics, db).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(serde_json::json!({"error": e.to_string()})),
@@ -895,81 +2584,770 @@ async fn checkpoint(
     Ok(Json(CheckpointResponse { ok: true }))
 }
 
-#[tokio::main]
-async fn main() {
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
-    let api_key = std::env::var("AGENT_MEM_API_KEY").ok();
-    let default_dim: usize = std::env::var("AGENT_MEM_DIM")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(384);
-    let data_dir = std::env::var("AGENT_MEM_DATA_DIR").ok().map(PathBuf::from);
-
-    let rate_limit = std::env::var("AGENT_MEM_RATE_LIMIT")
-        .ok()
-        .and_then(|s| s.parse::<u64>().ok())
-        .map(|max_per_window| {
-            let window_secs = std::env::var("AGENT_MEM_RATE_WINDOW_SECS")
-                .ok()
-                .and_then(|s| s.parse::<u64>().ok())
-                .unwrap_or(60);
+#[derive(Serialize, ToSchema)]
+struct VacuumResponse {
+    bytes_before: u64,
+    bytes_after: u64,
+    /// Log lines dropped: superseded versions, batch markers and purged episodes.
+    records_removed: usize,
+}
+
+/// Compact a disk-backed tenant's log, rewrite its checkpoint and remove orphan files.
+#[utoipa::path(
+    post,
+    path = "/v1/vacuum",
+    tag = "admin",
+    responses(
+        (status = 200, body = VacuumResponse),
+        (status = 400, description = "Tenant is not disk-backed", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn vacuum(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+) -> Result<Json<VacuumResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let mut tenants = state.tenants.write().await;
+    let db = &mut existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+
+    let report = db
+        .vacuum()
+        .map_err(|e| {
             (
-                Arc::new(RwLock::new(HashMap::new())),
-                max_per_window,
-                Duration::from_secs(window_secs),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
             )
-        });
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "vacuum applies to disk-backed tenants"})),
+            )
+        })?;
+
+    // The log was rewritten in place; followers must resync it from offset 0.
+    state.replication.log_rewritten(&tenant_id);
+    audit_log(&state, &tenant_id, "vacuum", None, None, None);
+    Ok(Json(VacuumResponse {
+        bytes_before: report.bytes_before,
+        bytes_after: report.bytes_after,
+        records_removed: report.records_removed,
+    }))
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+struct WarmUpRequest {
+    /// Embeddings like the queries to come; omitted, stored embeddings are queried.
+    #[serde(default)]
+    sample_queries: Option<Vec<Vec<f32>>>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct WarmUpResponse {
+    /// Embeddings read.
+    vectors_touched: usize,
+    queries_run: usize,
+    elapsed_ms: u64,
+}
+
+/// Load a tenant if it isn't loaded and warm its caches, so the first queries after a
+/// restart don't pay the cold-start cost. The body is optional.
+#[utoipa::path(
+    post,
+    path = "/v1/warm-up",
+    tag = "admin",
+    request_body = WarmUpRequest,
+    responses(
+        (status = 200, body = WarmUpResponse),
+        (status = 400, description = "Sample query has the wrong dimension", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn warm_up(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    req: Option<Json<WarmUpRequest>>,
+) -> Result<Json<WarmUpResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let Json(req) = req.unwrap_or_default();
+    let mut tenants = state.tenants.write().await;
+    let db = &existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+
+    let report = db
+        .warm_up(req.sample_queries.as_deref())
+        .map_err(|e| invalid_request(e, db.dim()))?;
+
+    Ok(Json(WarmUpResponse {
+        vectors_touched: report.vectors_touched,
+        queries_run: report.queries_run,
+        elapsed_ms: report.elapsed.as_millis() as u64,
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+struct VerifyResponse {
+    /// No problems found.
+    ok: bool,
+    /// Non-empty log lines, batch markers included.
+    log_records: usize,
+    episodes: usize,
+    /// Vectors in the index, including ones superseded by updates.
+    index_len: usize,
+    problems: Vec<String>,
+}
+
+/// Check a disk-backed tenant's log, index mapping and checkpoint for consistency.
+#[utoipa::path(
+    get,
+    path = "/v1/verify",
+    tag = "admin",
+    responses(
+        (status = 200, body = VerifyResponse),
+        (status = 400, description = "Tenant is not disk-backed", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn verify(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+) -> Result<Json<VerifyResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let mut tenants = state.tenants.write().await;
+    let db = &existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+
+    let report = db
+        .verify()
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "verify applies to disk-backed tenants"})),
+            )
+        })?;
+
+    Ok(Json(VerifyResponse {
+        ok: report.is_ok(),
+        log_records: report.log_records,
+        episodes: report.episodes,
+        index_len: report.index_len,
+        problems: report.problems,
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+struct StatsResponse {
+    tenant_id: String,
+    /// `memory` or `disk`.
+    #[schema(value_type = String)]
+    backend: &'static str,
+    dim: usize,
+    episodes: usize,
+    stored_bytes: u64,
+    /// Times queries returned an episode, summed over the stored episodes.
+    total_retrievals: u64,
+    /// Stored episodes no query has returned.
+    never_retrieved: usize,
+    /// `cost` summed over the episodes that have one.
+    total_cost: f64,
+    /// Mean over the episodes with a `cost`; null when none has one.
+    mean_cost: Option<f64>,
+    /// `duration_ms` summed over the episodes that have one.
+    total_duration_ms: i64,
+    /// Mean over the episodes with a `duration_ms`; null when none has one.
+    mean_duration_ms: Option<f64>,
+    quotas: Quotas,
+}
+
+/// Tenant usage and quotas.
+#[utoipa::path(
+    get,
+    path = "/v1/stats",
+    tag = "query",
+    responses(
+        (status = 200, body = StatsResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn stats(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+) -> Result<Json<StatsResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let mut tenants = state.tenants.write().await;
+    let tenant = existing_tenant_mut(&state, &mut tenants, &tenant_id)?;
+    let access = tenant.backend.access_summary();
+    let cost = tenant.backend.cost_summary();
+    Ok(Json(StatsResponse {
+        backend: tenant.backend.kind(),
+        dim: tenant.backend.dim(),
+        episodes: tenant.backend.len(),
+        stored_bytes: tenant.stored_bytes,
+        total_retrievals: access.total_retrievals,
+        never_retrieved: access.never_retrieved,
+        total_cost: cost.total_cost,
+        mean_cost: cost.mean_cost,
+        total_duration_ms: cost.total_duration_ms,
+        mean_duration_ms: cost.mean_duration_ms,
+        quotas: state.quotas(&tenant_id),
+        tenant_id,
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+struct ConfigResponse {
+    tenant_id: String,
+    /// Embedding dimension stores and queries must use: the tenant's, or the server default
+    /// for a tenant with no storage yet.
+    dim: usize,
+    /// Dimension new tenants are created with.
+    default_dim: usize,
+    /// Whether the tenant has storage (loaded or on disk); `dim` is fixed once it does.
+    tenant_exists: bool,
+    /// `memory` or `disk`.
+    #[schema(value_type = String)]
+    backend: &'static str,
+    /// Largest dimension stores may use (`quotas.max_dim`).
+    max_dim: Option<usize>,
+    /// Model the server embeds `text` with, when server-side embedding is on.
+    embedding_model: Option<String>,
+}
+
+/// The embedding dimension this tenant expects and related settings, so clients can check
+/// their embedding model against the server before storing.
+#[utoipa::path(
+    get,
+    path = "/v1/config",
+    tag = "query",
+    responses(
+        (status = 200, body = ConfigResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn get_config(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+) -> Result<Json<ConfigResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let mut tenants = state.tenants.write().await;
+    let existing = match existing_tenant_mut(&state, &mut tenants, &tenant_id) {
+        Ok(tenant) => Some((tenant.backend.dim(), tenant.backend.kind())),
+        Err((StatusCode::NOT_FOUND, _)) => None,
+        Err(e) => return Err(e),
+    };
+    let (dim, backend) = existing.unwrap_or_else(|| {
+        let backend = match state.tenant_settings.resolve(&tenant_id).backend {
+            BackendKind::Memory => "memory",
+            BackendKind::Disk => "disk",
+        };
+        (state.tenant_dim(&tenant_id), backend)
+    });
+    Ok(Json(ConfigResponse {
+        dim,
+        default_dim: state.default_dim,
+        tenant_exists: existing.is_some(),
+        backend,
+        max_dim: state.quotas(&tenant_id).max_dim,
+        embedding_model: state.embedder.as_ref().map(|e| e.model().to_string()),
+        tenant_id,
+    }))
+}
+
+/// Query for `GET /v1/stats/windows`.
+#[derive(Deserialize, IntoParams)]
+struct WindowedStatsQuery {
+    /// Window length in ms.
+    window_ms: i64,
+    /// Time between window starts in ms; defaults to `window_ms` (windows side by side).
+    step_ms: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct WindowedStatsResponse {
+    /// Oldest first; windows start at multiples of `step_ms` and empty ones are included.
+    #[schema(value_type = Vec<Object>)]
+    windows: Vec<WindowStats>,
+}
+
+/// Episode count and mean reward per time window (`start`, `end`, `count`, `mean_reward`),
+/// e.g. to chart whether an agent is improving. Undated episodes are left out.
+#[utoipa::path(
+    get,
+    path = "/v1/stats/windows",
+    tag = "query",
+    params(WindowedStatsQuery),
+    responses(
+        (status = 200, body = WindowedStatsResponse),
+        (status = 400, description = "Non-positive window or step, or too many windows", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn windowed_stats(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    axum::extract::Query(query): axum::extract::Query<WindowedStatsQuery>,
+) -> Result<Json<WindowedStatsResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let mut tenants = state.tenants.write().await;
+    let db = &existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+    let windows = db
+        .windowed_stats(query.window_ms, query.step_ms.unwrap_or(query.window_ms))
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+    Ok(Json(WindowedStatsResponse { windows }))
+}
+
+/// Query for `GET /v1/stats/tags`.
+#[derive(Deserialize, IntoParams)]
+struct TagStatsQuery {
+    /// Subtree to report, e.g. `tool/browser`; defaults to the whole tag hierarchy.
+    #[serde(default)]
+    prefix: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TagStatsResponse {
+    /// Ordered by tag path; ancestors of stored tags (`tool` for `tool/shell`) are included.
+    #[schema(value_type = Vec<Object>)]
+    tags: Vec<TagSubtree>,
+}
+
+/// Episode count and mean reward per subtree of the `/`-separated tag hierarchy (`tag`,
+/// `count`, `mean_reward`). An episode counts once per subtree.
+#[utoipa::path(
+    get,
+    path = "/v1/stats/tags",
+    tag = "query",
+    params(TagStatsQuery),
+    responses(
+        (status = 200, body = TagStatsResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+        (status = 404, description = "Tenant has no episodes", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+async fn tag_stats(
+    State(state): State<AppState>,
+    axum::extract::Extension(tenant_id): axum::extract::Extension<String>,
+    axum::extract::Query(query): axum::extract::Query<TagStatsQuery>,
+) -> Result<Json<TagStatsResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let mut tenants = state.tenants.write().await;
+    let db = &existing_tenant_mut(&state, &mut tenants, &tenant_id)?.backend;
+    Ok(Json(TagStatsResponse {
+        tags: db.tag_subtree_stats(&query.prefix),
+    }))
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = config::Cli::parse();
+    let config = match config::Config::load(&cli) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("agent-mem-server: {e}");
+            std::process::exit(2);
+        }
+    };
+    if cli.print_config {
+        print!("{}", config.to_redacted_toml());
+        return;
+    }
+    let telemetry = telemetry::init(config.log_format);
+
+    let api_keys = if config.auth_required() {
+        let mut keys = parse_api_keys(&config.auth.api_keys.join(","))
+            .expect("api_keys validated in Config::load");
+        if let Some(ref key) = config.auth.api_key {
+            // Legacy single key: full access, tenant_id = key.
+            keys.insert(
+                key.clone(),
+                ApiKey {
+                    tenant_id: tenant_from_key(key),
+                    scopes: Scopes::all(),
+                },
+            );
+        }
+        Some(Arc::new(keys))
+    } else {
+        None
+    };
+
+    let keys_file = match config.auth.keys_file {
+        Some(ref path) => match keys_file::KeysFile::open(path, config.data_dir.is_some()) {
+            Ok(f) => Some(Arc::new(f)),
+            Err(e) => {
+                eprintln!("agent-mem-server: invalid keys file: {e}");
+                std::process::exit(2);
+            }
+        },
+        None => None,
+    };
+
+    let rate_limit = (config.rate_limit.max_requests.is_some() || keys_file.is_some()).then(|| {
+        (
+            Arc::new(RwLock::new(HashMap::new())),
+            config.rate_limit.max_requests,
+            Duration::from_secs(config.rate_limit.window_secs),
+        )
+    });
+
+    let eviction = Eviction {
+        idle_timeout: config.eviction.idle_secs.map(Duration::from_secs),
+        max_loaded: config.eviction.max_loaded,
+    };
+
+    let audit_log = match config.audit_log {
+        Some(ref path) => match audit::AuditFile::open(path, &config.audit) {
+            Ok(f) => Some(Arc::new(f)),
+            Err(e) => {
+                eprintln!(
+                    "agent-mem-server: cannot open audit log {}: {e}",
+                    path.display()
+                );
+                std::process::exit(2);
+            }
+        },
+        None => None,
+    };
+
+    let snapshots = match snapshot::Snapshots::new(&config.snapshots) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("agent-mem-server: invalid snapshot storage configuration: {e}");
+            std::process::exit(2);
+        }
+    };
+
+    let embedder = match embedding::Embedder::new(&config.embedding, config.dim) {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("agent-mem-server: invalid embedding configuration: {e}");
+            std::process::exit(2);
+        }
+    };
+    if let Some(ref e) = embedder {
+        tracing::info!(model = e.model(), "server-side embedding enabled");
+    }
+
+    let openai = if config.openai.vector_stores {
+        match openai::Registry::load(config.data_dir.as_deref()) {
+            Ok(r) => Some(Arc::new(r)),
+            Err(e) => {
+                eprintln!("agent-mem-server: failed to load OpenAI vector stores: {e}");
+                std::process::exit(2);
+            }
+        }
+    } else {
+        None
+    };
+
+    let tenant_settings = match tenant_settings::Settings::load(&config) {
+        Ok(s) => Arc::new(s),
+        Err(e) => {
+            eprintln!("agent-mem-server: failed to load tenant settings: {e}");
+            std::process::exit(2);
+        }
+    };
+    if let Some(ref f) = keys_file {
+        tenant_settings.set_keys_file(f.tenant_settings());
+    }
+
+    let webhooks = match webhooks::Webhooks::new(&config.webhooks) {
+        Ok(w) => Arc::new(w),
+        Err(e) => {
+            eprintln!("agent-mem-server: failed to set up webhooks: {e}");
+            std::process::exit(2);
+        }
+    };
+
+    let usage = match usage::Usage::load(&config) {
+        Ok(u) => Arc::new(u),
+        Err(e) => {
+            eprintln!("agent-mem-server: failed to load usage: {e}");
+            std::process::exit(2);
+        }
+    };
+
+    let tenants = tenant_options(
+        config.data_dir.is_some(),
+        config.dim,
+        &tenant_settings.defaults(),
+    )
+    .and_then(|options| match config.data_dir {
+        Some(ref dir) => MultiTenantMemDB::with_root(dir, options),
+        None => Ok(MultiTenantMemDB::new(options)),
+    });
+    let mut tenants = match tenants {
+        Ok(tenants) => tenants,
+        Err(e) => {
+            eprintln!("agent-mem-server: failed to set up tenants: {e}");
+            std::process::exit(2);
+        }
+    };
+    let write_behind = match config.write_behind.dir {
+        Some(ref dir) => {
+            let write_behind = write_behind::WriteBehind::new(dir.clone()).and_then(|w| {
+                for (id, tenant) in w.restore(&tenant_settings)? {
+                    tenants.insert(id, tenant);
+                }
+                Ok(w)
+            });
+            match write_behind {
+                Ok(w) => {
+                    tracing::info!(tenants = tenants.loaded_count(), dir = %dir.display(), "restored write-behind tenants");
+                    Some(Arc::new(w))
+                }
+                Err(e) => {
+                    eprintln!("agent-mem-server: failed to restore write-behind tenants: {e}");
+                    std::process::exit(2);
+                }
+            }
+        }
+        None => None,
+    };
 
-    let audit_log = std::env::var("AGENT_MEM_AUDIT_LOG").ok().and_then(|path| {
-        std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&path)
-            .ok()
-            .map(|f| Arc::new(std::sync::RwLock::new(Some(f))))
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = shutdown_tx.send(true);
     });
 
     let state = AppState {
-        tenants: Arc::new(RwLock::new(HashMap::new())),
-        default_dim,
-        data_dir,
-        api_key: api_key.clone(),
+        tenants: Arc::new(RwLock::new(tenants)),
+        default_dim: config.dim,
+        data_dir: config.data_dir.clone(),
+        api_keys,
+        keys_file,
         metrics: Metrics::default(),
         rate_limit,
+        concurrency: Arc::new(concurrency::Limits::new(&config.concurrency)),
+        quotas: config.quotas.clone(),
+        limits: config.limits,
+        auto_timestamp: config.auto_timestamp,
+        eviction,
         audit_log,
+        episode_events: subscribe::event_bus(),
+        audit_events: events::audit_bus(),
+        shutdown: shutdown_rx,
+        replication: Arc::new(replication::Replication::new(&config.replication)),
+        snapshots: snapshots.map(Arc::new),
+        tasks: Arc::new(health::Tasks::default()),
+        embedder: embedder.map(Arc::new),
+        mcp_sessions: mcp::Sessions::default(),
+        openai,
+        write_behind,
+        tenant_settings,
+        retention: Arc::new(retention::Retention::new(config.retention.clone())),
+        archive_dir: config.retention.archive_dir.clone(),
+        webhooks,
+        usage,
     };
+    telemetry.export_metrics(&state.metrics);
+    // Write-behind tenants were restored as saved; stamp only what is stored from now on.
+    for (_, tenant) in state.tenants.write().await.iter_mut() {
+        tenant.backend.set_auto_timestamp(state.fills_timestamps());
+    }
+
+    if state.eviction.enabled() {
+        if state.data_dir.is_none() {
+            tracing::warn!(
+                "Tenant eviction configured without AGENT_MEM_DATA_DIR — in-memory tenants are never evicted"
+            );
+        }
+        let sweep_state = state.clone();
+        state.tasks.spawn("eviction", async move {
+            let mut ticker =
+                tokio::time::interval(Duration::from_secs(config.eviction.interval_secs));
+            loop {
+                ticker.tick().await;
+                evict_tenants(&sweep_state).await;
+            }
+        });
+    }
+    if let Some(write_behind) = state.write_behind.clone() {
+        let save_state = state.clone();
+        let interval = Duration::from_secs(config.write_behind.interval_secs);
+        state.tasks.spawn("write_behind", async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                write_behind.flush(&save_state).await;
+            }
+        });
+    }
+    state.tasks.spawn("usage", usage::run(state.clone()));
+    // Always running: tenants can set their own policies at runtime.
+    let sweep_state = state.clone();
+    state.tasks.spawn("retention", async move {
+        let mut ticker = tokio::time::interval(sweep_state.retention.tick());
+        loop {
+            ticker.tick().await;
+            retention::sweep(&sweep_state).await;
+        }
+    });
 
-    let cors = CorsLayer::permissive();
+    if cli.mcp_stdio {
+        mcp::serve_stdio(state.clone(), config.mcp.tenant.clone()).await;
+        flush_tenants(&state).await;
+        telemetry.shutdown();
+        return;
+    }
+
+    let cors = cors_layer(&config.cors);
+    // Backup archives are already zstd-compressed.
+    let compression = CompressionLayer::new()
+        .gzip(config.http.compression)
+        .br(config.http.compression)
+        .compress_when(
+            DefaultPredicate::new().and(NotForContentType::const_new("application/zstd")),
+        );
     let trace = TraceLayer::new_for_http()
+        .make_span_with(request_id::http_span)
         .on_request(|req: &Request<_>, _: &tracing::Span| {
-            tracing::info!(method = %req.method(), uri = %req.uri(), "request");
+            tracing::info!(method = %req.method(), uri = %request_id::loggable_uri(req.uri()), "request");
         })
         .on_response(|res: &Response, latency: std::time::Duration, _: &tracing::Span| {
             tracing::info!(status = %res.status(), latency_ms = %latency.as_millis(), "response");
         });
 
-    let rate_limit_enabled = state.rate_limit.is_some();
+    let rate_limit_enabled = config.rate_limit.max_requests.is_some();
     let audit_enabled = state.audit_log.is_some();
+    let auth_enabled = state.api_keys.is_some();
 
-    let v1_routes = Router::new()
+    let read_routes = Router::new()
+        .route("/query", post(query_similar))
+        .route("/query/explain", post(query_explain))
+        .route("/query/fused", post(query_fused))
+        .route("/query/contrastive", post(query_contrastive))
+        .route("/query/archive", post(query_archive))
+        .route(
+            "/vectorstore/similarity_search",
+            post(vectorstore::similarity_search),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            concurrency::limit_queries,
+        ))
+        .route("/episodes", get(list_episodes))
+        .route("/config", get(get_config))
+        .route("/stats", get(stats))
+        .route("/stats/windows", get(windowed_stats))
+        .route("/stats/tags", get(tag_stats))
+        .route("/retention", get(retention::get))
+        .route("/tenant/settings", get(tenant_settings::get))
+        .route("/subscribe", get(subscribe::subscribe))
+        .route_layer(axum::middleware::from_fn_with_state(
+            Scope::Read,
+            require_scope,
+        ));
+    let write_routes = Router::new()
         .route("/episodes", post(store_episode))
         .route("/episodes/batch", post(store_episodes))
-        .route("/query", post(query_similar))
-        .route("/save", post(save))
-        .route("/load", post(load))
+        .route("/episodes/:id", put(update_episode))
+        .route("/vectorstore/add_texts", post(vectorstore::add_texts))
+        .route(
+            "/vectorstore/add_embeddings",
+            post(vectorstore::add_embeddings),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            concurrency::limit_stores,
+        ))
+        .route_layer(axum::middleware::from_fn_with_state(
+            Scope::Write,
+            require_scope,
+        ));
+    let prune_routes = Router::new()
         .route("/prune/older-than", post(prune_older_than))
         .route("/prune/keep-newest", post(prune_keep_newest))
         .route(
             "/prune/keep-highest-reward",
             post(prune_keep_highest_reward),
         )
+        .route("/prune/session", post(prune_session))
+        .route("/episodes/delete", post(delete_episodes))
+        .route("/episodes/purge-deleted", post(purge_deleted))
+        .route("/episodes/:id/soft-delete", post(soft_delete_episode))
+        .route("/episodes/:id/restore", post(restore_episode))
+        .route("/retention/run", post(retention::run_now))
+        .route("/vectorstore/delete", post(vectorstore::delete))
+        .route_layer(axum::middleware::from_fn_with_state(
+            Scope::Prune,
+            require_scope,
+        ));
+    // Restore uploads whole archives, so it gets its own body limit.
+    let restore_route = Router::new()
+        .route("/admin/restore", post(backup::restore))
+        .layer(axum::middleware::from_fn_with_state(
+            (config.http.max_restore_bytes, "http.max_restore_bytes"),
+            body_too_large,
+        ))
+        .layer(DefaultBodyLimit::max(config.http.max_restore_bytes));
+    let admin_routes = Router::new()
+        .route("/save", post(save))
+        .route("/load", post(load))
         .route("/checkpoint", post(checkpoint))
+        .route("/vacuum", post(vacuum))
+        .route("/verify", get(verify))
+        .route("/warm-up", post(warm_up))
+        .route("/events", get(events::events))
+        .route("/audit", get(audit::query))
+        .route("/admin/backup", post(backup::backup))
+        .route("/tenant/settings", put(tenant_settings::put))
+        .route("/retention", put(retention::put).delete(retention::delete))
+        .route("/webhooks", get(webhooks::list).post(webhooks::create))
+        .route("/webhooks/:id", delete(webhooks::delete))
+        .merge(restore_route)
+        .route_layer(axum::middleware::from_fn_with_state(
+            Scope::Admin,
+            require_scope,
+        ));
+
+    // Tools check scopes per call.
+    let mcp_routes = Router::new()
+        .route("/mcp/sse", get(mcp::sse))
+        .route("/mcp/messages", post(mcp::message));
+
+    let replication_routes = Router::new()
+        .route("/tenants", get(replication::tenants))
+        .route("/tenants/:tenant_id/log", get(replication::log))
+        .route("/promote", post(replication::promote))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            replication::require_token,
+        ));
+
+    let mut v1_routes = Router::new()
+        .merge(read_routes)
+        .merge(write_routes)
+        .merge(prune_routes)
+        .merge(admin_routes)
+        .merge(mcp_routes);
+    if state.openai.is_some() {
+        v1_routes = v1_routes.merge(openai::routes());
+    }
+    let v1_routes = v1_routes
         .route_layer(axum::middleware::from_fn_with_state(
             state.clone(),
             rate_limit_middleware,
@@ -981,18 +3359,41 @@ async fn main() {
         .with_state(state.clone());
 
     let app = Router::new()
-        .route("/health", get(health))
+        .merge(openapi::routes())
+        // `/health` predates the split probes; kept as a liveness alias.
+        .route("/health", get(health::livez))
+        .route("/livez", get(health::livez))
+        .route("/readyz", get(health::readyz))
         .route("/metrics", get(metrics))
-        .route("/dashboard", get(dashboard))
+        .route("/dashboard", get(dashboard::dashboard))
+        // Authenticates itself: `usage.token` or an admin key.
+        .route("/dashboard/tenants", get(dashboard::tenants))
         .nest("/v1", v1_routes)
+        .nest("/v1/replication", replication_routes)
+        // Authenticates itself: `usage.token` or an admin key.
+        .route("/v1/admin/usage", get(usage::get))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.metrics.clone(),
+            metrics::track_latency,
+        ))
+        .layer(DefaultBodyLimit::max(config.http.max_body_bytes))
+        .layer(axum::middleware::from_fn_with_state(
+            (config.http.max_body_bytes, "http.max_body_bytes"),
+            body_too_large,
+        ))
+        .layer(axum::middleware::from_fn(request_id::annotate_errors))
+        .layer(compression)
         .layer(trace)
+        .layer(request_id::propagate_layer())
+        .layer(request_id::set_layer())
         .layer(cors)
-        .with_state(state);
+        .with_state(state.clone());
 
-    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], 8080));
-    tracing::info!("Listening on http://{}", addr);
-    if api_key.is_none() {
-        tracing::warn!("AGENT_MEM_API_KEY not set — all API keys accepted (dev only)");
+    let addr = config.bind;
+    if !auth_enabled {
+        tracing::warn!(
+            "AGENT_MEM_API_KEY / AGENT_MEM_API_KEYS not set — all API keys accepted (dev only)"
+        );
     }
     if rate_limit_enabled {
         tracing::info!("Rate limiting enabled (AGENT_MEM_RATE_LIMIT)");
@@ -1001,6 +3402,71 @@ async fn main() {
         tracing::info!("Audit logging enabled (AGENT_MEM_AUDIT_LOG)");
     }
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    if state.replication.follower.is_some() {
+        state
+            .tasks
+            .spawn("replication", replication::follow(state.clone()));
+    }
+    if state.snapshots.is_some() {
+        state.tasks.spawn("snapshots", snapshot::run(state.clone()));
+    }
+    if let Some(ref f) = state.keys_file {
+        tracing::info!(keys = f.len(), path = %f.path().display(), "keys file loaded (reloads on SIGHUP)");
+        state
+            .tasks
+            .spawn("keys_file", keys_file::reload_on_sighup(state.clone()));
+    }
+
+    let grpc = config.grpc_bind.map(|addr| {
+        let serve = grpc::serve(addr, state.clone(), config.http.max_body_bytes);
+        state.tasks.spawn("grpc", serve)
+    });
+
+    if let Some(ref tls) = config.tls {
+        serve_tls(addr, tls, app, state.clone()).await;
+    } else {
+        tracing::info!("Listening on http://{}", addr);
+        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        axum::serve(listener, app)
+            .with_graceful_shutdown({
+                let state = state.clone();
+                async move { state.shutdown_requested().await }
+            })
+            .await
+            .unwrap();
+    }
+    if let Some(grpc) = grpc {
+        let _ = grpc.await;
+    }
+    flush_tenants(&state).await;
+    telemetry.shutdown();
+}
+
+#[cfg(feature = "tls")]
+async fn serve_tls(
+    addr: std::net::SocketAddr,
+    tls: &config::TlsConfig,
+    app: Router,
+    state: AppState,
+) {
+    let rustls = axum_server::tls_rustls::RustlsConfig::from_pem_file(&tls.cert, &tls.key)
+        .await
+        .expect("Failed to load TLS certificate/key");
+    let handle = axum_server::Handle::new();
+    let shutdown = handle.clone();
+    tokio::spawn(async move {
+        state.shutdown_requested().await;
+        shutdown.graceful_shutdown(None);
+    });
+    tracing::info!("Listening on https://{}", addr);
+    axum_server::bind_rustls(addr, rustls)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
+
+#[cfg(not(feature = "tls"))]
+async fn serve_tls(_: std::net::SocketAddr, _: &config::TlsConfig, _: Router, _: AppState) {
+    unreachable!("Config::load rejects TLS without the `tls` feature")
 }
@@ -0,0 +1,521 @@
+//! Model Context Protocol (MCP) server, so LLM agents and IDEs can use this store as their
+//! memory directly. Exposes three tools: `store_memory`, `search_memory` and `prune_memory`.
+//!
+//! Two transports share one JSON-RPC handler:
+//! - stdio (`agent-mem-server --mcp-stdio`): newline-delimited JSON-RPC on stdin/stdout,
+//!   acting on the `mcp.tenant` tenant with full access. No HTTP listener is started.
+//! - HTTP+SSE (`GET /v1/mcp/sse`, then `POST /v1/mcp/messages?sessionId=...`): authenticated
+//!   like the rest of `/v1`; tools act on the key's tenant and need the key's scopes.
+//!
+//! Text is embedded with the server-side embedder (`embedding.url`) and kept in
+//! `metadata.text`, so search results can be read back by the model.
+
+use crate::{
+    audit_log, embedding, prune_for_tenant, query_for_tenant, store_for_tenant, ApiError, AppState,
+    Prune, Scope, Scopes,
+};
+use agent_mem_db::{Episode, QueryOptions};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+
+/// Newest protocol revision we speak; older clients get their own version echoed back.
+const PROTOCOL_VERSION: &str = "2025-03-26";
+const SUPPORTED_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26"];
+
+/// Responses buffered per SSE session before `POST /v1/mcp/messages` waits.
+const SESSION_CAPACITY: usize = 64;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const INSTRUCTIONS: &str = "Long-term memory. Call search_memory with a description of the \
+current task before starting work, and store_memory with what was learned afterwards \
+(reward 0-1: how useful or successful it was).";
+
+/// Open HTTP+SSE sessions, keyed by session id.
+pub type Sessions = Arc<Mutex<HashMap<String, Session>>>;
+
+pub struct Session {
+    tenant_id: String,
+    tx: mpsc::Sender<Value>,
+}
+
+fn response(id: Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+/// A `tools/call` result whose content is one text block.
+fn tool_result(text: String, is_error: bool) -> Value {
+    json!({"content": [{"type": "text", "text": text}], "isError": is_error})
+}
+
+fn error_message((_, body): ApiError) -> String {
+    body.0["error"].as_str().unwrap_or("error").to_string()
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "store_memory",
+            "description": "Store a memory (an observation, outcome or lesson) for later recall.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "text": {"type": "string", "description": "What to remember; embedded by the server"},
+                    "embedding": {"type": "array", "items": {"type": "number"}, "description": "Precomputed embedding, instead of text"},
+                    "task_id": {"type": "string", "description": "Task or topic the memory belongs to", "default": "memory"},
+                    "reward": {"type": "number", "description": "How useful or successful this was, 0-1", "default": 1.0},
+                    "tags": {"type": "array", "items": {"type": "string"}},
+                    "metadata": {"type": "object"}
+                }
+            }
+        },
+        {
+            "name": "search_memory",
+            "description": "Find the stored memories most similar to a query.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "text": {"type": "string", "description": "What to look for; embedded by the server"},
+                    "embedding": {"type": "array", "items": {"type": "number"}, "description": "Precomputed query embedding, instead of text"},
+                    "top_k": {"type": "integer", "minimum": 1, "default": 5},
+                    "min_reward": {"type": "number", "default": 0.0},
+                    "max_reward": {"type": "number", "description": "Only memories with reward at most this, e.g. past failures"},
+                    "tags_any": {"type": "array", "items": {"type": "string"}},
+                    "task_id_prefix": {"type": "string"}
+                }
+            }
+        },
+        {
+            "name": "prune_memory",
+            "description": "Delete memories. Pass exactly one of older_than_ms, keep_newest or keep_highest_reward.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "older_than_ms": {"type": "integer", "description": "Delete memories with a timestamp before this (Unix ms)"},
+                    "keep_newest": {"type": "integer", "minimum": 0, "description": "Keep only the n most recent memories"},
+                    "keep_highest_reward": {"type": "integer", "minimum": 0, "description": "Keep only the n highest-reward memories"}
+                }
+            }
+        }
+    ])
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoreArgs {
+    text: Option<String>,
+    embedding: Option<Vec<f32>>,
+    #[serde(default = "default_task_id")]
+    task_id: String,
+    #[serde(default = "default_reward")]
+    reward: f32,
+    tags: Option<Vec<String>>,
+    metadata: Option<Map<String, Value>>,
+}
+
+fn default_task_id() -> String {
+    "memory".to_string()
+}
+
+fn default_reward() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SearchArgs {
+    text: Option<String>,
+    embedding: Option<Vec<f32>>,
+    #[serde(default = "crate::default_top_k")]
+    top_k: usize,
+    #[serde(default)]
+    min_reward: f32,
+    max_reward: Option<f32>,
+    tags_any: Option<Vec<String>>,
+    task_id_prefix: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PruneArgs {
+    older_than_ms: Option<i64>,
+    keep_newest: Option<usize>,
+    keep_highest_reward: Option<usize>,
+}
+
+/// A search hit without its embedding, which is noise to a model.
+#[derive(Serialize)]
+struct Memory {
+    id: String,
+    task_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    reward: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    metadata: Map<String, Value>,
+}
+
+impl From<Episode> for Memory {
+    fn from(ep: Episode) -> Self {
+        let mut metadata = match ep.metadata {
+            Value::Object(m) => m,
+            Value::Null => Map::new(),
+            other => Map::from_iter([("value".to_string(), other)]),
+        };
+        let text = match metadata.remove("text") {
+            Some(Value::String(t)) => Some(t),
+            Some(other) => {
+                metadata.insert("text".to_string(), other);
+                None
+            }
+            None => None,
+        };
+        Self {
+            id: ep.id.to_string(),
+            task_id: ep.task_id,
+            text,
+            reward: ep.reward,
+            tags: ep.tags,
+            timestamp: ep.timestamp,
+            metadata,
+        }
+    }
+}
+
+async fn store_memory(
+    state: &AppState,
+    tenant_id: &str,
+    scopes: &Scopes,
+    args: StoreArgs,
+) -> Result<String, ApiError> {
+    scopes.require(Scope::Write)?;
+    let embedding_model =
+        embedding::model_for(state.embedder.as_deref(), args.text.as_deref(), None);
+    let embedding = embedding::resolve(
+        state.embedder.as_deref(),
+        vec![(args.embedding, args.text.clone())],
+        "embedding",
+    )
+    .await?
+    .remove(0);
+    let mut metadata = args.metadata.unwrap_or_default();
+    if let Some(text) = args.text {
+        metadata.insert("text".to_string(), Value::String(text));
+    }
+    let mut ep = Episode::new(&args.task_id, embedding, args.reward);
+    ep.metadata = Value::Object(metadata);
+    ep.tags = args.tags;
+    ep.embedding_model = embedding_model;
+    ep.timestamp = Some(chrono::Utc::now().timestamp_millis());
+    let id = ep.id.to_string();
+    store_for_tenant(state, tenant_id, vec![ep]).await?;
+    audit_log(
+        state,
+        tenant_id,
+        "store_episode",
+        Some(&args.task_id),
+        Some(1),
+        None,
+    );
+    Ok(json!({"id": id}).to_string())
+}
+
+async fn search_memory(
+    state: &AppState,
+    tenant_id: &str,
+    scopes: &Scopes,
+    args: SearchArgs,
+) -> Result<String, ApiError> {
+    scopes.require(Scope::Read)?;
+    let query = embedding::resolve(
+        state.embedder.as_deref(),
+        vec![(args.embedding, args.text)],
+        "embedding",
+    )
+    .await?
+    .remove(0);
+    let mut opts = QueryOptions::new(args.min_reward, args.top_k);
+    if let Some(max) = args.max_reward {
+        opts = opts.max_reward(max);
+    }
+    if let Some(tags) = args.tags_any.filter(|t| !t.is_empty()) {
+        opts = opts.tags_any(tags);
+    }
+    if let Some(prefix) = args.task_id_prefix {
+        opts = opts.task_id_prefix(prefix);
+    }
+    let episodes = match query_for_tenant(state, tenant_id, &query, opts).await {
+        Ok(results) => results.episodes,
+        // A tenant that has never stored anything simply has no memories yet.
+        Err((StatusCode::NOT_FOUND, _)) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let memories: Vec<Memory> = episodes.into_iter().map(Memory::from).collect();
+    Ok(serde_json::to_string(&memories).unwrap_or_default())
+}
+
+async fn prune_memory(
+    state: &AppState,
+    tenant_id: &str,
+    scopes: &Scopes,
+    args: PruneArgs,
+) -> Result<String, ApiError> {
+    scopes.require(Scope::Prune)?;
+    let prune = match (
+        args.older_than_ms,
+        args.keep_newest,
+        args.keep_highest_reward,
+    ) {
+        (Some(ts), None, None) => Prune::OlderThan(ts),
+        (None, Some(n), None) => Prune::KeepNewest(n),
+        (None, None, Some(n)) => Prune::KeepHighestReward(n),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "Pass exactly one of older_than_ms, keep_newest or keep_highest_reward"
+                })),
+            ))
+        }
+    };
+    let removed = match prune_for_tenant(state, tenant_id, prune).await {
+        Ok(removed) => removed,
+        Err((StatusCode::NOT_FOUND, _)) => 0,
+        Err(e) => return Err(e),
+    };
+    Ok(json!({"removed": removed}).to_string())
+}
+
+fn parse_args<T: serde::de::DeserializeOwned>(arguments: Value) -> Result<T, String> {
+    let arguments = if arguments.is_null() {
+        json!({})
+    } else {
+        arguments
+    };
+    serde_json::from_value(arguments).map_err(|e| format!("Invalid arguments: {e}"))
+}
+
+/// `tools/call`: `Err` is a protocol error (unknown tool, bad arguments); failures while
+/// running the tool are reported in the result with `isError` so the model can react.
+async fn call_tool(
+    state: &AppState,
+    tenant_id: &str,
+    scopes: &Scopes,
+    params: &Value,
+) -> Result<Value, String> {
+    let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
+    let result = match params.get("name").and_then(Value::as_str) {
+        Some("store_memory") => {
+            store_memory(state, tenant_id, scopes, parse_args(arguments)?).await
+        }
+        Some("search_memory") => {
+            search_memory(state, tenant_id, scopes, parse_args(arguments)?).await
+        }
+        Some("prune_memory") => {
+            prune_memory(state, tenant_id, scopes, parse_args(arguments)?).await
+        }
+        Some(name) => return Err(format!("Unknown tool: {name}")),
+        None => return Err("Missing tool name".to_string()),
+    };
+    Ok(match result {
+        Ok(text) => tool_result(text, false),
+        Err(e) => tool_result(error_message(e), true),
+    })
+}
+
+/// Handle one JSON-RPC message. Returns the response, or `None` for notifications.
+#[tracing::instrument(skip_all, fields(method))]
+pub async fn handle(
+    state: &AppState,
+    tenant_id: &str,
+    scopes: &Scopes,
+    message: Value,
+) -> Option<Value> {
+    let id = message.get("id").cloned();
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        // Responses from the client (we send no requests) are ignored.
+        return (message.get("result").is_none() && message.get("error").is_none()).then(|| {
+            error_response(
+                id.unwrap_or(Value::Null),
+                INVALID_REQUEST,
+                "Invalid request",
+            )
+        });
+    };
+    tracing::Span::current().record("method", method);
+    let id = id?;
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+    Some(match method {
+        "initialize" => {
+            let requested = params["protocolVersion"].as_str().unwrap_or_default();
+            let version = if SUPPORTED_VERSIONS.contains(&requested) {
+                requested
+            } else {
+                PROTOCOL_VERSION
+            };
+            response(
+                id,
+                json!({
+                    "protocolVersion": version,
+                    "capabilities": {"tools": {"listChanged": false}},
+                    "serverInfo": {"name": "agent-mem-server", "version": env!("CARGO_PKG_VERSION")},
+                    "instructions": INSTRUCTIONS,
+                }),
+            )
+        }
+        "ping" => response(id, json!({})),
+        "tools/list" => response(id, json!({"tools": tools()})),
+        "tools/call" => match call_tool(state, tenant_id, scopes, &params).await {
+            Ok(result) => response(id, result),
+            Err(e) => error_response(id, INVALID_PARAMS, &e),
+        },
+        _ => error_response(id, METHOD_NOT_FOUND, &format!("Method not found: {method}")),
+    })
+}
+
+/// Serve MCP over stdin/stdout until stdin closes or shutdown begins.
+pub async fn serve_stdio(state: AppState, tenant_id: String) {
+    tracing::info!(tenant_id = %tenant_id, "serving MCP on stdio");
+    let scopes = Scopes::all();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line,
+            _ = state.shutdown_requested() => break,
+        };
+        let line = match line {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::warn!(error = %e, "MCP stdin read failed");
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => handle(&state, &tenant_id, &scopes, message).await,
+            Err(e) => Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+        };
+        if let Some(reply) = reply {
+            let mut out = reply.to_string();
+            out.push('\n');
+            if stdout.write_all(out.as_bytes()).await.is_err() || stdout.flush().await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Removes the session when its SSE stream is dropped (client disconnect or shutdown).
+struct SessionGuard {
+    sessions: Sessions,
+    id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.remove(&self.id);
+        }
+    }
+}
+
+/// Open an MCP session. The first event, `endpoint`, is the URL to POST messages to;
+/// responses arrive as `message` events.
+pub async fn sse(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let (tx, rx) = mpsc::channel(SESSION_CAPACITY);
+    if let Ok(mut sessions) = state.mcp_sessions.lock() {
+        sessions.insert(id.clone(), Session { tenant_id, tx });
+    }
+    let guard = SessionGuard {
+        sessions: state.mcp_sessions.clone(),
+        id: id.clone(),
+    };
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data(format!("/v1/mcp/messages?sessionId={id}"));
+    let stream =
+        tokio_stream::once(endpoint)
+            .chain(ReceiverStream::new(rx).filter_map(|message: Value| {
+                Event::default().event("message").json_data(message).ok()
+            }))
+            .map(move |event| {
+                let _ = &guard;
+                Ok(event)
+            });
+    // End the stream on shutdown so graceful shutdown isn't held open by idle clients.
+    let stream =
+        futures_util::StreamExt::take_until(
+            stream,
+            async move { state.shutdown_requested().await },
+        );
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[derive(Deserialize)]
+pub struct MessageQuery {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+}
+
+/// Deliver a client message to its session; the response is sent on the session's stream.
+pub async fn message(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Extension(scopes): Extension<Scopes>,
+    Query(query): Query<MessageQuery>,
+    Json(message): Json<Value>,
+) -> Result<StatusCode, ApiError> {
+    let tx = state
+        .mcp_sessions
+        .lock()
+        .ok()
+        .and_then(|sessions| {
+            sessions
+                .get(&query.session_id)
+                .filter(|s| s.tenant_id == tenant_id)
+                .map(|s| s.tx.clone())
+        })
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Unknown MCP session"})),
+            )
+        })?;
+    if let Some(reply) = handle(&state, &tenant_id, &scopes, message).await {
+        // Err only means the client has disconnected.
+        let _ = tx.send(reply).await;
+    }
+    Ok(StatusCode::ACCEPTED)
+}
@@ -0,0 +1,303 @@
+//! In-process metrics rendered in Prometheus text format at `/metrics`.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Histogram bucket upper bounds, in seconds.
+const BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0,
+];
+
+/// Fixed-bucket latency histogram.
+pub struct Histogram {
+    /// Per-bucket (non-cumulative) counts; cumulated when rendered.
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|b| secs <= *b) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Run `f`, recording how long it took.
+    pub fn time<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let out = f();
+        self.observe(start.elapsed());
+        out
+    }
+
+    /// Append `name_bucket`/`name_sum`/`name_count` lines. `labels` is `k="v",...` or empty.
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {count}");
+        let braced = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        let _ = writeln!(out, "{name}_sum{braced} {sum}");
+        let _ = writeln!(out, "{name}_count{braced} {count}");
+    }
+}
+
+/// Query latencies kept for the dashboard sparkline.
+const RECENT_QUERIES: usize = 60;
+
+/// Latency histograms keyed by (method, matched route).
+type RouteLatency = HashMap<(String, String), Arc<Histogram>>;
+
+/// Per-tenant request counters (kept after a tenant is evicted, so they stay monotonic).
+#[derive(Default)]
+struct TenantCounters {
+    stored: u64,
+    queries: u64,
+}
+
+/// Simple in-memory metrics for observability (Prometheus-style).
+#[derive(Clone, Default)]
+pub struct Metrics {
+    pub requests_total: Arc<AtomicU64>,
+    pub store_episodes_total: Arc<AtomicU64>,
+    pub query_total: Arc<AtomicU64>,
+    pub tenant_evictions_total: Arc<AtomicU64>,
+    route_latency: Arc<RwLock<RouteLatency>>,
+    tenants: Arc<RwLock<HashMap<String, TenantCounters>>>,
+    /// Time to open a disk-backed tenant (checkpoint load + log replay).
+    pub tenant_open_seconds: Arc<Histogram>,
+    /// Time to write a disk-backed tenant's checkpoint.
+    pub checkpoint_seconds: Arc<Histogram>,
+    /// Scheduled retention runs (one per tenant per run), failed runs and episodes removed.
+    pub retention_runs_total: Arc<AtomicU64>,
+    pub retention_failures_total: Arc<AtomicU64>,
+    pub retention_removed_total: Arc<AtomicU64>,
+    /// Time to apply one tenant's retention policy.
+    pub retention_seconds: Arc<Histogram>,
+    /// Webhooks delivered, retried, and given up on after the last retry.
+    pub webhook_deliveries_total: Arc<AtomicU64>,
+    pub webhook_retries_total: Arc<AtomicU64>,
+    pub webhook_failures_total: Arc<AtomicU64>,
+    /// Latencies of the most recent queries, oldest first.
+    recent_queries: Arc<Mutex<VecDeque<Duration>>>,
+}
+
+impl Metrics {
+    pub fn record_store(&self, tenant_id: &str, episodes: u64) {
+        self.store_episodes_total
+            .fetch_add(episodes, Ordering::Relaxed);
+        if let Ok(mut tenants) = self.tenants.write() {
+            tenants.entry(tenant_id.to_string()).or_default().stored += episodes;
+        }
+    }
+
+    pub fn record_query(&self, tenant_id: &str, elapsed: Duration) {
+        self.query_total.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut tenants) = self.tenants.write() {
+            tenants.entry(tenant_id.to_string()).or_default().queries += 1;
+        }
+        if let Ok(mut recent) = self.recent_queries.lock() {
+            if recent.len() == RECENT_QUERIES {
+                recent.pop_front();
+            }
+            recent.push_back(elapsed);
+        }
+    }
+
+    /// Latencies of the last (up to 60) queries, oldest first.
+    pub fn recent_query_latencies(&self) -> Vec<Duration> {
+        self.recent_queries
+            .lock()
+            .map(|r| r.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// (episodes stored, queries) counted for a tenant since startup.
+    pub fn tenant_counters(&self, tenant_id: &str) -> (u64, u64) {
+        self.tenants
+            .read()
+            .ok()
+            .and_then(|t| t.get(tenant_id).map(|c| (c.stored, c.queries)))
+            .unwrap_or_default()
+    }
+
+    fn observe_route(&self, method: &str, route: &str, elapsed: Duration) {
+        let key = (method.to_string(), route.to_string());
+        let existing = self
+            .route_latency
+            .read()
+            .ok()
+            .and_then(|m| m.get(&key).cloned());
+        let hist = match existing {
+            Some(h) => h,
+            None => match self.route_latency.write() {
+                Ok(mut m) => m.entry(key).or_default().clone(),
+                Err(_) => return,
+            },
+        };
+        hist.observe(elapsed);
+    }
+
+    /// Append route latency, per-tenant counters, disk timing, retention and webhook series.
+    pub fn render_detail(&self, out: &mut String) {
+        out.push_str(
+            "# HELP agent_mem_request_duration_seconds HTTP request latency by route\n\
+             # TYPE agent_mem_request_duration_seconds histogram\n",
+        );
+        if let Ok(routes) = self.route_latency.read() {
+            let mut keys: Vec<_> = routes.keys().collect();
+            keys.sort();
+            for key in keys {
+                let labels = format!(
+                    "method=\"{}\",route=\"{}\"",
+                    escape_label(&key.0),
+                    escape_label(&key.1)
+                );
+                routes[key].render(out, "agent_mem_request_duration_seconds", &labels);
+            }
+        }
+
+        if let Ok(tenants) = self.tenants.read() {
+            let mut ids: Vec<_> = tenants.keys().collect();
+            ids.sort();
+            out.push_str(
+                "# HELP agent_mem_tenant_store_episodes_total Episodes stored per tenant\n\
+                 # TYPE agent_mem_tenant_store_episodes_total counter\n",
+            );
+            for id in &ids {
+                let _ = writeln!(
+                    out,
+                    "agent_mem_tenant_store_episodes_total{{tenant=\"{}\"}} {}",
+                    escape_label(id),
+                    tenants[*id].stored
+                );
+            }
+            out.push_str(
+                "# HELP agent_mem_tenant_query_total Similarity queries per tenant\n\
+                 # TYPE agent_mem_tenant_query_total counter\n",
+            );
+            for id in &ids {
+                let _ = writeln!(
+                    out,
+                    "agent_mem_tenant_query_total{{tenant=\"{}\"}} {}",
+                    escape_label(id),
+                    tenants[*id].queries
+                );
+            }
+        }
+
+        out.push_str(
+            "# HELP agent_mem_tenant_open_seconds Time to open a disk-backed tenant (checkpoint load and log replay)\n\
+             # TYPE agent_mem_tenant_open_seconds histogram\n",
+        );
+        self.tenant_open_seconds
+            .render(out, "agent_mem_tenant_open_seconds", "");
+        out.push_str(
+            "# HELP agent_mem_checkpoint_seconds Time to write a disk-backed tenant checkpoint\n\
+             # TYPE agent_mem_checkpoint_seconds histogram\n",
+        );
+        self.checkpoint_seconds
+            .render(out, "agent_mem_checkpoint_seconds", "");
+
+        let _ = write!(
+            out,
+            "# HELP agent_mem_retention_runs_total Scheduled retention runs (per tenant)\n\
+             # TYPE agent_mem_retention_runs_total counter\n\
+             agent_mem_retention_runs_total {}\n\
+             # HELP agent_mem_retention_failures_total Scheduled retention runs that failed\n\
+             # TYPE agent_mem_retention_failures_total counter\n\
+             agent_mem_retention_failures_total {}\n\
+             # HELP agent_mem_retention_removed_total Episodes removed by scheduled retention\n\
+             # TYPE agent_mem_retention_removed_total counter\n\
+             agent_mem_retention_removed_total {}\n",
+            self.retention_runs_total.load(Ordering::Relaxed),
+            self.retention_failures_total.load(Ordering::Relaxed),
+            self.retention_removed_total.load(Ordering::Relaxed),
+        );
+        out.push_str(
+            "# HELP agent_mem_retention_seconds Time to apply one tenant's retention policy\n\
+             # TYPE agent_mem_retention_seconds histogram\n",
+        );
+        self.retention_seconds
+            .render(out, "agent_mem_retention_seconds", "");
+
+        let _ = write!(
+            out,
+            "# HELP agent_mem_webhook_deliveries_total Webhook deliveries acknowledged with 2xx\n\
+             # TYPE agent_mem_webhook_deliveries_total counter\n\
+             agent_mem_webhook_deliveries_total {}\n\
+             # HELP agent_mem_webhook_retries_total Webhook delivery retries\n\
+             # TYPE agent_mem_webhook_retries_total counter\n\
+             agent_mem_webhook_retries_total {}\n\
+             # HELP agent_mem_webhook_failures_total Webhooks dropped after the last retry\n\
+             # TYPE agent_mem_webhook_failures_total counter\n\
+             agent_mem_webhook_failures_total {}\n",
+            self.webhook_deliveries_total.load(Ordering::Relaxed),
+            self.webhook_retries_total.load(Ordering::Relaxed),
+            self.webhook_failures_total.load(Ordering::Relaxed),
+        );
+    }
+}
+
+/// Escape a Prometheus label value.
+pub fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Middleware recording request latency per matched route (the route template, not the
+/// raw path, to keep label cardinality bounded).
+pub async fn track_latency(
+    State(metrics): State<Metrics>,
+    matched: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = matched
+        .as_ref()
+        .map(MatchedPath::as_str)
+        .unwrap_or("unmatched")
+        .to_string();
+    let method = request.method().to_string();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    metrics.observe_route(&method, &route, start.elapsed());
+    response
+}
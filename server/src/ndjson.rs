@@ -0,0 +1,48 @@
+//! `application/x-ndjson` responses: one JSON value per line, each serialized as the body is
+//! sent, so clients can start on large (embedding-heavy) result sets before the last one is
+//! written.
+
+use axum::{
+    body::{Body, Bytes},
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        HeaderMap,
+    },
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+pub const MEDIA_TYPE: &str = "application/x-ndjson";
+
+/// Whether the `Accept` header lists NDJSON.
+pub fn requested(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|media| {
+            let media = media.split(';').next().unwrap_or_default().trim();
+            media.eq_ignore_ascii_case(MEDIA_TYPE)
+        })
+}
+
+/// Stream `items` one per line. `headers` are added to the response.
+pub fn response<T, I>(items: I, headers: HeaderMap) -> Response
+where
+    T: Serialize,
+    I: IntoIterator<Item = T>,
+    I::IntoIter: Send + 'static,
+{
+    let lines = futures_util::stream::iter(items.into_iter().map(|item| {
+        let mut line = serde_json::to_vec(&item)?;
+        line.push(b'\n');
+        Ok::<_, serde_json::Error>(Bytes::from(line))
+    }));
+    (
+        [(CONTENT_TYPE, MEDIA_TYPE)],
+        headers,
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
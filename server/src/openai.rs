@@ -0,0 +1,871 @@
+//! Optional OpenAI-compatible vector store API (`openai.vector_stores = true`), so tools
+//! built on the OpenAI SDK's `vector_stores` / `files` calls can be pointed at this server by
+//! changing `base_url` to `http://<host>/v1` and the API key.
+//!
+//! A vector store lives in the caller's tenant: every chunk of an attached file is an episode
+//! with `source` = vector store id and `task_id` = file id, the chunk text, filename and file
+//! attributes in `metadata`. Store and file records (names, metadata, attributes) are kept in
+//! `<data_dir>/openai_vector_stores.json`; uploaded file contents stay in memory until
+//! attached or deleted.
+
+use crate::{
+    delete_for_tenant, query_for_tenant, require_scope, store_for_tenant, ApiError, AppState, Scope,
+};
+use agent_mem_db::{Episode, QueryOptions};
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+const REGISTRY_FILE: &str = "openai_vector_stores.json";
+
+/// Default static chunking, in (whitespace-separated) tokens, as in the OpenAI API.
+const DEFAULT_CHUNK_TOKENS: usize = 800;
+const DEFAULT_CHUNK_OVERLAP: usize = 400;
+
+/// Candidates fetched per requested result when attribute filters are applied afterwards.
+const FILTER_CANDIDATE_MULT: usize = 4;
+
+fn error(status: StatusCode, msg: impl Into<String>) -> ApiError {
+    (status, Json(json!({"error": msg.into()})))
+}
+
+fn not_found(what: &str, id: &str) -> ApiError {
+    error(
+        StatusCode::NOT_FOUND,
+        format!("No {what} found with id {id:?}"),
+    )
+}
+
+fn now_secs() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+fn new_id(prefix: &str) -> String {
+    format!("{prefix}{}", uuid::Uuid::new_v4().simple())
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ChunkingStrategy {
+    max_chunk_size_tokens: usize,
+    chunk_overlap_tokens: usize,
+}
+
+impl Default for ChunkingStrategy {
+    fn default() -> Self {
+        Self {
+            max_chunk_size_tokens: DEFAULT_CHUNK_TOKENS,
+            chunk_overlap_tokens: DEFAULT_CHUNK_OVERLAP,
+        }
+    }
+}
+
+impl ChunkingStrategy {
+    /// Parse `{"type": "auto"}` or `{"type": "static", "static": {...}}`.
+    fn parse(value: Option<&Value>) -> Result<Self, ApiError> {
+        let Some(value) = value else {
+            return Ok(Self::default());
+        };
+        match value["type"].as_str() {
+            Some("auto") => Ok(Self::default()),
+            Some("static") => {
+                let strategy: Self =
+                    serde_json::from_value(value["static"].clone()).map_err(|e| {
+                        error(StatusCode::BAD_REQUEST, format!("chunking_strategy: {e}"))
+                    })?;
+                if !(100..=4096).contains(&strategy.max_chunk_size_tokens)
+                    || strategy.chunk_overlap_tokens > strategy.max_chunk_size_tokens / 2
+                {
+                    return Err(error(
+                        StatusCode::BAD_REQUEST,
+                        "chunking_strategy: max_chunk_size_tokens must be 100-4096 and chunk_overlap_tokens at most half of it",
+                    ));
+                }
+                Ok(strategy)
+            }
+            _ => Err(error(
+                StatusCode::BAD_REQUEST,
+                "chunking_strategy.type must be \"auto\" or \"static\"",
+            )),
+        }
+    }
+
+    /// Split on whitespace into overlapping windows of words.
+    fn chunks(&self, text: &str) -> Vec<String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let step = self.max_chunk_size_tokens - self.chunk_overlap_tokens;
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < words.len() {
+            let end = (start + self.max_chunk_size_tokens).min(words.len());
+            chunks.push(words[start..end].join(" "));
+            if end == words.len() {
+                break;
+            }
+            start += step;
+        }
+        chunks
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoreFile {
+    id: String,
+    created_at: i64,
+    usage_bytes: u64,
+    chunking_strategy: ChunkingStrategy,
+    attributes: Map<String, Value>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct VectorStore {
+    id: String,
+    tenant_id: String,
+    name: Option<String>,
+    created_at: i64,
+    last_active_at: i64,
+    metadata: Map<String, Value>,
+    files: Vec<StoreFile>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct File {
+    id: String,
+    tenant_id: String,
+    filename: String,
+    bytes: u64,
+    created_at: i64,
+    purpose: String,
+    /// Not persisted: lost on restart, after which the file must be uploaded again.
+    #[serde(skip)]
+    content: Option<Vec<u8>>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Records {
+    vector_stores: HashMap<String, VectorStore>,
+    files: HashMap<String, File>,
+}
+
+/// Vector store and file records, persisted under `data_dir` when set.
+pub struct Registry {
+    path: Option<PathBuf>,
+    records: Mutex<Records>,
+}
+
+impl Registry {
+    pub fn load(data_dir: Option<&std::path::Path>) -> Result<Self, String> {
+        let path = data_dir.map(|d| d.join(REGISTRY_FILE));
+        let records = match path {
+            Some(ref p) if p.exists() => {
+                let text =
+                    std::fs::read_to_string(p).map_err(|e| format!("{}: {e}", p.display()))?;
+                serde_json::from_str(&text).map_err(|e| format!("{}: {e}", p.display()))?
+            }
+            _ => Records::default(),
+        };
+        Ok(Self {
+            path,
+            records: Mutex::new(records),
+        })
+    }
+
+    /// Write the records (temp file + rename). Called with the lock held so writes are ordered.
+    async fn persist(&self, records: &Records) -> Result<(), ApiError> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        let data = serde_json::to_vec_pretty(records)
+            .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, data)
+            .await
+            .and(tokio::fs::rename(&tmp, path).await)
+            .map_err(|e| {
+                error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("{}: {e}", path.display()),
+                )
+            })
+    }
+}
+
+fn registry(state: &AppState) -> Result<&Registry, ApiError> {
+    state
+        .openai
+        .as_deref()
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "OpenAI compatibility is disabled"))
+}
+
+fn store_object(store: &VectorStore) -> Value {
+    let total = store.files.len();
+    json!({
+        "id": store.id,
+        "object": "vector_store",
+        "created_at": store.created_at,
+        "name": store.name,
+        "usage_bytes": store.files.iter().map(|f| f.usage_bytes).sum::<u64>(),
+        "file_counts": {"in_progress": 0, "completed": total, "failed": 0, "cancelled": 0, "total": total},
+        "status": "completed",
+        "expires_after": null,
+        "expires_at": null,
+        "last_active_at": store.last_active_at,
+        "metadata": store.metadata,
+    })
+}
+
+fn store_file_object(store_id: &str, file: &StoreFile) -> Value {
+    json!({
+        "id": file.id,
+        "object": "vector_store.file",
+        "usage_bytes": file.usage_bytes,
+        "created_at": file.created_at,
+        "vector_store_id": store_id,
+        "status": "completed",
+        "last_error": null,
+        "chunking_strategy": {"type": "static", "static": file.chunking_strategy},
+        "attributes": file.attributes,
+    })
+}
+
+fn file_object(file: &File) -> Value {
+    json!({
+        "id": file.id,
+        "object": "file",
+        "bytes": file.bytes,
+        "created_at": file.created_at,
+        "filename": file.filename,
+        "purpose": file.purpose,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ListParams {
+    #[serde(default = "default_limit")]
+    limit: usize,
+    #[serde(default)]
+    order: Option<String>,
+    #[serde(default)]
+    after: Option<String>,
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+/// An OpenAI list page over `(id, created_at, object)` items.
+fn list_page(mut items: Vec<(String, i64, Value)>, params: &ListParams) -> Value {
+    items.sort_by_key(|(_, created_at, _)| *created_at);
+    if params.order.as_deref() != Some("asc") {
+        items.reverse();
+    }
+    if let Some(ref after) = params.after {
+        if let Some(pos) = items.iter().position(|(id, _, _)| id == after) {
+            items.drain(..=pos);
+        }
+    }
+    let limit = params.limit.clamp(1, 100);
+    let has_more = items.len() > limit;
+    items.truncate(limit);
+    json!({
+        "object": "list",
+        "first_id": items.first().map(|(id, _, _)| id),
+        "last_id": items.last().map(|(id, _, _)| id),
+        "has_more": has_more,
+        "data": items.into_iter().map(|(_, _, v)| v).collect::<Vec<_>>(),
+    })
+}
+
+/// `POST /v1/files` (multipart `file` and `purpose`).
+pub async fn upload_file(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    mut multipart: Multipart,
+) -> Result<Json<Value>, ApiError> {
+    let registry = registry(&state)?;
+    let mut upload = None;
+    let mut purpose = "assistants".to_string();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, e.body_text()))?
+    {
+        match field.name() {
+            Some("file") => {
+                let filename = field.file_name().unwrap_or("upload").to_string();
+                let bytes = field
+                    .bytes()
+                    .await
+                    .map_err(|e| error(StatusCode::BAD_REQUEST, e.body_text()))?;
+                upload = Some((filename, bytes.to_vec()));
+            }
+            Some("purpose") => {
+                purpose = field
+                    .text()
+                    .await
+                    .map_err(|e| error(StatusCode::BAD_REQUEST, e.body_text()))?;
+            }
+            _ => {}
+        }
+    }
+    let (filename, content) =
+        upload.ok_or_else(|| error(StatusCode::BAD_REQUEST, "Missing multipart field `file`"))?;
+    let file = File {
+        id: new_id("file-"),
+        tenant_id,
+        filename,
+        bytes: content.len() as u64,
+        created_at: now_secs(),
+        purpose,
+        content: Some(content),
+    };
+    let object = file_object(&file);
+    let mut records = registry.records.lock().await;
+    records.files.insert(file.id.clone(), file);
+    registry.persist(&records).await?;
+    Ok(Json(object))
+}
+
+/// `GET /v1/files/{file_id}`.
+pub async fn get_file(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Path(file_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let records = registry(&state)?.records.lock().await;
+    records
+        .files
+        .get(&file_id)
+        .filter(|f| f.tenant_id == tenant_id)
+        .map(|f| Json(file_object(f)))
+        .ok_or_else(|| not_found("file", &file_id))
+}
+
+/// `DELETE /v1/files/{file_id}`: drops the upload; chunks already in vector stores remain.
+pub async fn delete_file(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Path(file_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let registry = registry(&state)?;
+    let mut records = registry.records.lock().await;
+    if records
+        .files
+        .get(&file_id)
+        .is_none_or(|f| f.tenant_id != tenant_id)
+    {
+        return Err(not_found("file", &file_id));
+    }
+    records.files.remove(&file_id);
+    registry.persist(&records).await?;
+    Ok(Json(
+        json!({"id": file_id, "object": "file", "deleted": true}),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct CreateStoreRequest {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    file_ids: Vec<String>,
+    #[serde(default)]
+    metadata: Map<String, Value>,
+    #[serde(default)]
+    chunking_strategy: Option<Value>,
+}
+
+/// `POST /v1/vector_stores`.
+pub async fn create_store(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Json(req): Json<CreateStoreRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let registry = registry(&state)?;
+    let chunking = ChunkingStrategy::parse(req.chunking_strategy.as_ref())?;
+    let now = now_secs();
+    let store = VectorStore {
+        id: new_id("vs_"),
+        tenant_id: tenant_id.clone(),
+        name: req.name,
+        created_at: now,
+        last_active_at: now,
+        metadata: req.metadata,
+        files: Vec::new(),
+    };
+    let id = store.id.clone();
+    {
+        let mut records = registry.records.lock().await;
+        records.vector_stores.insert(id.clone(), store);
+        registry.persist(&records).await?;
+    }
+    for file_id in req.file_ids {
+        attach(&state, &tenant_id, &id, &file_id, &chunking, Map::new()).await?;
+    }
+    let records = registry.records.lock().await;
+    Ok(Json(store_object(&records.vector_stores[&id])))
+}
+
+/// `GET /v1/vector_stores`.
+pub async fn list_stores(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Value>, ApiError> {
+    let records = registry(&state)?.records.lock().await;
+    let items = records
+        .vector_stores
+        .values()
+        .filter(|s| s.tenant_id == tenant_id)
+        .map(|s| (s.id.clone(), s.created_at, store_object(s)))
+        .collect();
+    Ok(Json(list_page(items, &params)))
+}
+
+/// `GET /v1/vector_stores/{id}`.
+pub async fn get_store(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Path(store_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let records = registry(&state)?.records.lock().await;
+    records
+        .vector_stores
+        .get(&store_id)
+        .filter(|s| s.tenant_id == tenant_id)
+        .map(|s| Json(store_object(s)))
+        .ok_or_else(|| not_found("vector store", &store_id))
+}
+
+/// Remove a store's (or one of its files') chunks from the tenant.
+async fn delete_chunks(
+    state: &AppState,
+    tenant_id: &str,
+    store_id: &str,
+    file_id: Option<&str>,
+) -> Result<(), ApiError> {
+    let result = delete_for_tenant(state, tenant_id, "openai_vector_store_delete", |ep| {
+        ep.source.as_deref() == Some(store_id) && file_id.is_none_or(|f| ep.task_id == f)
+    })
+    .await;
+    match result {
+        Ok(_) | Err((StatusCode::NOT_FOUND, _)) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// `DELETE /v1/vector_stores/{id}`: deletes the store and all its chunks.
+pub async fn delete_store(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Path(store_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let registry = registry(&state)?;
+    let mut records = registry.records.lock().await;
+    if records
+        .vector_stores
+        .get(&store_id)
+        .is_none_or(|s| s.tenant_id != tenant_id)
+    {
+        return Err(not_found("vector store", &store_id));
+    }
+    delete_chunks(&state, &tenant_id, &store_id, None).await?;
+    records.vector_stores.remove(&store_id);
+    registry.persist(&records).await?;
+    Ok(Json(
+        json!({"id": store_id, "object": "vector_store.deleted", "deleted": true}),
+    ))
+}
+
+/// Chunk, embed and store an uploaded file in a vector store.
+async fn attach(
+    state: &AppState,
+    tenant_id: &str,
+    store_id: &str,
+    file_id: &str,
+    chunking: &ChunkingStrategy,
+    attributes: Map<String, Value>,
+) -> Result<StoreFile, ApiError> {
+    let registry = registry(state)?;
+    let embedder = state.embedder.as_deref().ok_or_else(|| {
+        error(
+            StatusCode::BAD_REQUEST,
+            "Server-side embedding is not configured (embedding.url); vector store files cannot be embedded",
+        )
+    })?;
+    let (filename, text) = {
+        let records = registry.records.lock().await;
+        if records
+            .vector_stores
+            .get(store_id)
+            .is_none_or(|s| s.tenant_id != tenant_id)
+        {
+            return Err(not_found("vector store", store_id));
+        }
+        let file = records
+            .files
+            .get(file_id)
+            .filter(|f| f.tenant_id == tenant_id)
+            .ok_or_else(|| not_found("file", file_id))?;
+        let content = file.content.as_ref().ok_or_else(|| {
+            error(
+                StatusCode::BAD_REQUEST,
+                format!("Contents of {file_id} are no longer available (server restarted); upload it again"),
+            )
+        })?;
+        let text = String::from_utf8(content.clone()).map_err(|_| {
+            error(
+                StatusCode::BAD_REQUEST,
+                format!("{file_id} is not UTF-8 text; only text files are supported"),
+            )
+        })?;
+        (file.filename.clone(), text)
+    };
+
+    let chunks = chunking.chunks(&text);
+    let embeddings = embedder.embed(&chunks).await?;
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let episodes: Vec<Episode> = chunks
+        .into_iter()
+        .zip(embeddings)
+        .enumerate()
+        .map(|(i, (chunk, embedding))| {
+            let mut ep = Episode::new(file_id, embedding, 1.0);
+            ep.source = Some(store_id.to_string());
+            ep.embedding_model = Some(embedder.model().to_string());
+            ep.timestamp = Some(now_ms);
+            ep.metadata = json!({
+                "text": chunk,
+                "filename": filename,
+                "chunk": i,
+                "attributes": attributes,
+            });
+            ep
+        })
+        .collect();
+    let usage_bytes = episodes.iter().map(crate::episode_bytes).sum();
+
+    // Re-attaching a file replaces its chunks.
+    delete_chunks(state, tenant_id, store_id, Some(file_id)).await?;
+    store_for_tenant(state, tenant_id, episodes).await?;
+
+    let file = StoreFile {
+        id: file_id.to_string(),
+        created_at: now_secs(),
+        usage_bytes,
+        chunking_strategy: chunking.clone(),
+        attributes,
+    };
+    let mut records = registry.records.lock().await;
+    let store = records
+        .vector_stores
+        .get_mut(store_id)
+        .ok_or_else(|| not_found("vector store", store_id))?;
+    store.files.retain(|f| f.id != file_id);
+    store.files.push(file.clone());
+    store.last_active_at = now_secs();
+    registry.persist(&records).await?;
+    Ok(file)
+}
+
+#[derive(Deserialize)]
+pub struct AttachRequest {
+    file_id: String,
+    #[serde(default)]
+    attributes: Map<String, Value>,
+    #[serde(default)]
+    chunking_strategy: Option<Value>,
+}
+
+/// `POST /v1/vector_stores/{id}/files`: chunks and embeds synchronously, so the file is
+/// `completed` when this returns.
+pub async fn create_store_file(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Path(store_id): Path<String>,
+    Json(req): Json<AttachRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let chunking = ChunkingStrategy::parse(req.chunking_strategy.as_ref())?;
+    let file = attach(
+        &state,
+        &tenant_id,
+        &store_id,
+        &req.file_id,
+        &chunking,
+        req.attributes,
+    )
+    .await?;
+    Ok(Json(store_file_object(&store_id, &file)))
+}
+
+/// `GET /v1/vector_stores/{id}/files`.
+pub async fn list_store_files(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Path(store_id): Path<String>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Value>, ApiError> {
+    let records = registry(&state)?.records.lock().await;
+    let store = records
+        .vector_stores
+        .get(&store_id)
+        .filter(|s| s.tenant_id == tenant_id)
+        .ok_or_else(|| not_found("vector store", &store_id))?;
+    let items = store
+        .files
+        .iter()
+        .map(|f| (f.id.clone(), f.created_at, store_file_object(&store_id, f)))
+        .collect();
+    Ok(Json(list_page(items, &params)))
+}
+
+/// `DELETE /v1/vector_stores/{id}/files/{file_id}`: removes the file's chunks from the store.
+pub async fn delete_store_file(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Path((store_id, file_id)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    let registry = registry(&state)?;
+    let mut records = registry.records.lock().await;
+    let store = records
+        .vector_stores
+        .get_mut(&store_id)
+        .filter(|s| s.tenant_id == tenant_id)
+        .ok_or_else(|| not_found("vector store", &store_id))?;
+    if !store.files.iter().any(|f| f.id == file_id) {
+        return Err(not_found("file", &file_id));
+    }
+    store.files.retain(|f| f.id != file_id);
+    delete_chunks(&state, &tenant_id, &store_id, Some(&file_id)).await?;
+    registry.persist(&records).await?;
+    Ok(Json(
+        json!({"id": file_id, "object": "vector_store.file.deleted", "deleted": true}),
+    ))
+}
+
+/// Compare an attribute against a filter value: numbers numerically, otherwise equality only.
+fn compare(op: &str, attr: Option<&Value>, value: &Value) -> bool {
+    let Some(attr) = attr else {
+        return op == "ne";
+    };
+    if let (Some(a), Some(b)) = (attr.as_f64(), value.as_f64()) {
+        return match op {
+            "eq" => a == b,
+            "ne" => a != b,
+            "gt" => a > b,
+            "gte" => a >= b,
+            "lt" => a < b,
+            _ => a <= b,
+        };
+    }
+    match op {
+        "eq" => attr == value,
+        "ne" => attr != value,
+        _ => match (attr.as_str(), value.as_str()) {
+            (Some(a), Some(b)) => match op {
+                "gt" => a > b,
+                "gte" => a >= b,
+                "lt" => a < b,
+                _ => a <= b,
+            },
+            _ => false,
+        },
+    }
+}
+
+/// Evaluate an OpenAI attribute filter: `{"type": "eq", "key", "value"}` (also `ne`, `gt`,
+/// `gte`, `lt`, `lte`) or `{"type": "and" | "or", "filters": [...]}`.
+fn filter_matches(filter: &Value, attributes: &Map<String, Value>) -> Result<bool, String> {
+    match filter["type"].as_str() {
+        Some(op @ ("eq" | "ne" | "gt" | "gte" | "lt" | "lte")) => {
+            let key = filter["key"]
+                .as_str()
+                .ok_or("comparison filters need a string `key`")?;
+            let value = filter
+                .get("value")
+                .ok_or("comparison filters need a `value`")?;
+            Ok(compare(op, attributes.get(key), value))
+        }
+        Some(op @ ("and" | "or")) => {
+            let filters = filter["filters"]
+                .as_array()
+                .ok_or("compound filters need a `filters` array")?;
+            let mut results = filters.iter().map(|f| filter_matches(f, attributes));
+            if op == "and" {
+                results.try_fold(true, |acc, r| r.map(|m| acc && m))
+            } else {
+                results.try_fold(false, |acc, r| r.map(|m| acc || m))
+            }
+        }
+        _ => Err("filter type must be eq, ne, gt, gte, lt, lte, and or or".to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SearchRequest {
+    /// A string, or a list of strings that are searched as one query.
+    query: Value,
+    #[serde(default = "default_max_results")]
+    max_num_results: usize,
+    #[serde(default)]
+    filters: Option<Value>,
+    #[serde(default)]
+    ranking_options: Option<Value>,
+    /// Accepted for compatibility; queries are never rewritten.
+    #[serde(default)]
+    #[allow(dead_code)]
+    rewrite_query: bool,
+}
+
+fn default_max_results() -> usize {
+    10
+}
+
+fn l2_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// `POST /v1/vector_stores/{id}/search`. `score` is `1 / (1 + L2 distance)`.
+pub async fn search(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Path(store_id): Path<String>,
+    Json(req): Json<SearchRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let registry = registry(&state)?;
+    {
+        let mut records = registry.records.lock().await;
+        let store = records
+            .vector_stores
+            .get_mut(&store_id)
+            .filter(|s| s.tenant_id == tenant_id)
+            .ok_or_else(|| not_found("vector store", &store_id))?;
+        store.last_active_at = now_secs();
+    }
+    let queries: Vec<String> = match req.query {
+        Value::String(q) => vec![q],
+        Value::Array(ref items) => items
+            .iter()
+            .map(|q| q.as_str().map(String::from))
+            .collect::<Option<_>>()
+            .ok_or_else(|| {
+                error(
+                    StatusCode::BAD_REQUEST,
+                    "query must be a string or a list of strings",
+                )
+            })?,
+        _ => {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                "query must be a string or a list of strings",
+            ))
+        }
+    };
+    if !(1..=50).contains(&req.max_num_results) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "max_num_results must be between 1 and 50",
+        ));
+    }
+    if let Some(ref filter) = req.filters {
+        filter_matches(filter, &Map::new())
+            .map_err(|e| error(StatusCode::BAD_REQUEST, format!("filters: {e}")))?;
+    }
+    let score_threshold = req
+        .ranking_options
+        .as_ref()
+        .and_then(|r| r["score_threshold"].as_f64())
+        .unwrap_or(0.0);
+
+    let embedder = state.embedder.as_deref().ok_or_else(|| {
+        error(
+            StatusCode::BAD_REQUEST,
+            "Server-side embedding is not configured (embedding.url)",
+        )
+    })?;
+    let query = embedder.embed(&[queries.join("\n")]).await?.remove(0);
+    let top_k = if req.filters.is_some() {
+        req.max_num_results * FILTER_CANDIDATE_MULT
+    } else {
+        req.max_num_results
+    };
+    let opts = QueryOptions::new(f32::MIN, top_k).source(store_id.clone());
+    let episodes = match query_for_tenant(&state, &tenant_id, &query, opts).await {
+        Ok(results) => results.episodes,
+        Err((StatusCode::NOT_FOUND, _)) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let data: Vec<Value> = episodes
+        .into_iter()
+        .filter_map(|ep| {
+            let attributes = ep.metadata["attributes"]
+                .as_object()
+                .cloned()
+                .unwrap_or_default();
+            if let Some(ref filter) = req.filters {
+                if !filter_matches(filter, &attributes).unwrap_or(false) {
+                    return None;
+                }
+            }
+            let score = 1.0 / (1.0 + l2_distance(&query, &ep.state_embedding) as f64);
+            (score >= score_threshold).then(|| {
+                json!({
+                    "file_id": ep.task_id,
+                    "filename": ep.metadata["filename"],
+                    "score": score,
+                    "attributes": attributes,
+                    "content": [{"type": "text", "text": ep.metadata["text"]}],
+                })
+            })
+        })
+        .take(req.max_num_results)
+        .collect();
+    Ok(Json(json!({
+        "object": "vector_store.search_results.page",
+        "search_query": queries,
+        "data": data,
+        "has_more": false,
+        "next_page": null,
+    })))
+}
+
+/// The OpenAI-compatible routes, each behind the scope it needs. Nested under `/v1`.
+pub fn routes() -> Router<AppState> {
+    let scoped = |scope: Scope, router: Router<AppState>| {
+        router.route_layer(axum::middleware::from_fn_with_state(scope, require_scope))
+    };
+    let read = Router::new()
+        .route("/files/:file_id", get(get_file))
+        .route("/vector_stores", get(list_stores))
+        .route("/vector_stores/:store_id", get(get_store))
+        .route("/vector_stores/:store_id/files", get(list_store_files))
+        .route("/vector_stores/:store_id/search", post(search));
+    let write = Router::new()
+        .route("/files", post(upload_file))
+        .route("/vector_stores", post(create_store))
+        .route("/vector_stores/:store_id/files", post(create_store_file));
+    let prune = Router::new()
+        .route("/files/:file_id", axum::routing::delete(delete_file))
+        .route(
+            "/vector_stores/:store_id",
+            axum::routing::delete(delete_store),
+        )
+        .route(
+            "/vector_stores/:store_id/files/:file_id",
+            axum::routing::delete(delete_store_file),
+        );
+    scoped(Scope::Read, read)
+        .merge(scoped(Scope::Write, write))
+        .merge(scoped(Scope::Prune, prune))
+}
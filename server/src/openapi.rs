@@ -0,0 +1,146 @@
+//! OpenAPI 3 document for the HTTP API, served at `/openapi.json` with Swagger UI at
+//! `/swagger-ui`. Schemas are derived from the request/response types in main.rs.
+
+use axum::Router;
+use serde_json::Value;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+/// Episode as returned by the API (documentation mirror of `agent_mem_db::Episode`).
+#[allow(dead_code)]
+#[derive(ToSchema)]
+#[schema(as = Episode)]
+pub struct EpisodeSchema {
+    /// UUID v4.
+    id: String,
+    task_id: String,
+    state_embedding: Vec<f32>,
+    reward: f32,
+    /// Arbitrary JSON.
+    metadata: Value,
+    steps: Option<Vec<EpisodeStepSchema>>,
+    /// Unix milliseconds.
+    timestamp: Option<i64>,
+    tags: Option<Vec<String>>,
+    source: Option<String>,
+    user_id: Option<String>,
+    session_id: Option<String>,
+    /// Model that produced `state_embedding`.
+    embedding_model: Option<String>,
+    cost: Option<f32>,
+    duration_ms: Option<i64>,
+    /// Incremented on each update; send it back as `expected_version` to update.
+    version: u64,
+    /// Soft-deleted: excluded from queries until restored or purged.
+    deleted: bool,
+    /// Times a query returned the episode.
+    times_retrieved: u64,
+    /// Unix milliseconds of the last query that returned the episode.
+    last_retrieved_at: Option<i64>,
+}
+
+#[allow(dead_code)]
+#[derive(ToSchema)]
+#[schema(as = EpisodeStep)]
+pub struct EpisodeStepSchema {
+    index: u32,
+    action: String,
+    observation: String,
+    step_reward: f32,
+}
+
+/// Error body returned with every non-2xx status.
+#[allow(dead_code)]
+#[derive(ToSchema)]
+pub struct ErrorBody {
+    error: String,
+    /// Echo of the `X-Request-Id` response header.
+    request_id: Option<String>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Agent Memory DB", description = "Hosted memory API for agent episodes"),
+    paths(
+        crate::list_episodes,
+        crate::store_episode,
+        crate::store_episodes,
+        crate::update_episode,
+        crate::query_similar,
+        crate::query_explain,
+        crate::query_fused,
+        crate::query_contrastive,
+        crate::query_archive,
+        crate::get_config,
+        crate::stats,
+        crate::windowed_stats,
+        crate::tag_stats,
+        crate::prune_older_than,
+        crate::prune_keep_newest,
+        crate::prune_keep_highest_reward,
+        crate::prune_session,
+        crate::delete_episodes,
+        crate::soft_delete_episode,
+        crate::restore_episode,
+        crate::purge_deleted,
+        crate::retention::get,
+        crate::retention::put,
+        crate::retention::delete,
+        crate::retention::run_now,
+        crate::save,
+        crate::load,
+        crate::checkpoint,
+        crate::vacuum,
+        crate::verify,
+        crate::warm_up,
+        crate::subscribe::subscribe,
+        crate::events::events,
+        crate::audit::query,
+        crate::backup::backup,
+        crate::backup::restore,
+        crate::tenant_settings::get,
+        crate::tenant_settings::put,
+        crate::webhooks::list,
+        crate::webhooks::create,
+        crate::webhooks::delete,
+        crate::usage::get,
+        crate::vectorstore::add_texts,
+        crate::vectorstore::add_embeddings,
+        crate::vectorstore::similarity_search,
+        crate::vectorstore::delete,
+    ),
+    components(schemas(EpisodeSchema, EpisodeStepSchema, ErrorBody)),
+    modifiers(&SecurityAddon),
+    tags(
+        (name = "episodes", description = "Store episodes"),
+        (name = "query", description = "Retrieve episodes and usage"),
+        (name = "prune", description = "Retention"),
+        (name = "admin", description = "Persistence and operations"),
+        (name = "vectorstore", description = "LangChain/LlamaIndex-style document API"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+    }
+}
+
+/// `/openapi.json` and `/swagger-ui` (unauthenticated, like `/health`).
+pub fn routes<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    SwaggerUi::new("/swagger-ui")
+        .url("/openapi.json", ApiDoc::openapi())
+        .into()
+}
@@ -0,0 +1,521 @@
+//! Leader-follower replication by log shipping.
+//!
+//! A leader (disk-backed, `replication.token` set) serves each tenant's append-only
+//! episode log under `/v1/replication`. A follower (`replication.leader` set) polls the
+//! tenant list, tails each log from its last offset, and applies new episodes to local
+//! replicas, which serve reads (`/v1/query`, `/v1/stats`, `/v1/subscribe`) while writes are
+//! rejected. `POST /v1/replication/promote` turns a follower into a writable server for
+//! failover.
+//!
+//! Prune, retention and vacuum compact the leader's log in place, so each tenant log carries a
+//! generation that changes whenever it is rewritten (and on leader restart); a follower
+//! that sees a new generation rebuilds that replica from offset 0.
+
+use crate::config::ReplicationConfig;
+use crate::{create_tenant_backend, extract_api_key, subscribe, ApiError, AppState, Tenant};
+use agent_mem_db::{tenant_dir_name, Episode, LogRecord};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The core disk backend's episode log inside a tenant directory.
+pub const EPISODES_LOG: &str = "episodes.jsonl";
+/// Written next to the core files so tenant directories map back to IDs.
+pub use agent_mem_db::TENANT_ID_FILE;
+/// Max log bytes returned per request.
+const MAX_CHUNK: u64 = 4 * 1024 * 1024;
+
+const GENERATION_HEADER: &str = "x-replication-generation";
+const LOG_BYTES_HEADER: &str = "x-replication-log-bytes";
+
+/// Replication state shared by both roles.
+pub struct Replication {
+    token: Option<String>,
+    /// Distinguishes generations across leader restarts.
+    boot: u64,
+    /// Log rewrites per tenant since boot.
+    rewrites: Mutex<HashMap<String, u64>>,
+    pub follower: Option<Follower>,
+}
+
+impl Replication {
+    pub fn new(config: &ReplicationConfig) -> Self {
+        Self {
+            token: config.token.clone(),
+            boot: chrono::Utc::now().timestamp_millis() as u64,
+            rewrites: Mutex::new(HashMap::new()),
+            follower: config.leader.as_ref().map(|leader| Follower {
+                leader: leader.trim_end_matches('/').to_string(),
+                token: config.token.clone().unwrap_or_default(),
+                interval: Duration::from_millis(config.poll_interval_ms),
+                following: AtomicBool::new(true),
+                lag_bytes: AtomicU64::new(0),
+                last_sync_ms: AtomicU64::new(0),
+                client: reqwest::Client::new(),
+            }),
+        }
+    }
+
+    fn generation(&self, tenant_id: &str) -> String {
+        let n = self
+            .rewrites
+            .lock()
+            .ok()
+            .and_then(|r| r.get(tenant_id).copied())
+            .unwrap_or(0);
+        format!("{}-{}", self.boot, n)
+    }
+
+    /// Record that a tenant's log was compacted, so followers resync it.
+    pub fn log_rewritten(&self, tenant_id: &str) {
+        if let Ok(mut r) = self.rewrites.lock() {
+            *r.entry(tenant_id.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Whether this server is currently a read-only follower.
+    pub fn read_only(&self) -> bool {
+        self.follower
+            .as_ref()
+            .is_some_and(|f| f.following.load(Ordering::Relaxed))
+    }
+
+    /// Reject writes while following a leader.
+    pub fn check_writable(&self) -> Result<(), ApiError> {
+        match self.follower {
+            Some(ref f) if f.following.load(Ordering::Relaxed) => Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(serde_json::json!({
+                    "error": format!("Read-only replica; send writes to the leader at {}", f.leader)
+                })),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Follower-side state and progress.
+pub struct Follower {
+    leader: String,
+    token: String,
+    interval: Duration,
+    following: AtomicBool,
+    /// Leader log bytes not yet applied, summed over tenants.
+    lag_bytes: AtomicU64,
+    /// Unix ms of the last complete poll; 0 until the first one.
+    last_sync_ms: AtomicU64,
+    client: reqwest::Client,
+}
+
+impl Follower {
+    /// Append follower series to `/metrics`.
+    pub fn render_metrics(&self, out: &mut String) {
+        out.push_str(&format!(
+            "# HELP agent_mem_replication_lag_bytes Leader log bytes not yet applied\n\
+             # TYPE agent_mem_replication_lag_bytes gauge\n\
+             agent_mem_replication_lag_bytes {}\n\
+             # HELP agent_mem_replication_last_sync_timestamp_seconds Last complete poll of the leader\n\
+             # TYPE agent_mem_replication_last_sync_timestamp_seconds gauge\n\
+             agent_mem_replication_last_sync_timestamp_seconds {}\n\
+             # HELP agent_mem_replication_following 1 while following a leader, 0 once promoted\n\
+             # TYPE agent_mem_replication_following gauge\n\
+             agent_mem_replication_following {}\n",
+            self.lag_bytes.load(Ordering::Relaxed),
+            self.last_sync_ms.load(Ordering::Relaxed) as f64 / 1000.0,
+            u8::from(self.following.load(Ordering::Relaxed)),
+        ));
+    }
+}
+
+fn not_found(msg: &str) -> ApiError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({"error": msg})),
+    )
+}
+
+/// Replication routes authenticate with `replication.token`, not tenant API keys.
+pub async fn require_token(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Response> {
+    let Some(ref token) = state.replication.token else {
+        return Err(not_found("Replication not enabled (replication.token)").into_response());
+    };
+    if extract_api_key(request.headers()).as_deref() != Some(token.as_str()) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({"error": "Invalid replication token"})),
+        )
+            .into_response());
+    }
+    Ok(next.run(request).await)
+}
+
+/// A tenant log available for replication.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplicatedTenant {
+    tenant_id: String,
+    dim: usize,
+    generation: String,
+    log_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TenantsResponse {
+    tenants: Vec<ReplicatedTenant>,
+}
+
+/// Tenant directories under `data_dir`, with their tenant ID and dimension.
+pub fn scan_tenants(data_dir: &std::path::Path) -> Vec<(String, usize, u64)> {
+    let mut out = Vec::new();
+    for entry in std::fs::read_dir(data_dir).into_iter().flatten().flatten() {
+        // `.restore-*` and `.backup-*` staging directories aren't tenants.
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let dir = entry.path();
+        let Ok(meta) = std::fs::read_to_string(dir.join("meta.json")) else {
+            continue;
+        };
+        let Some(dim) = serde_json::from_str::<serde_json::Value>(&meta)
+            .ok()
+            .and_then(|m| m["dim"].as_u64())
+        else {
+            continue;
+        };
+        // Directories created before tenant_id files existed are named after the ID.
+        let tenant_id = std::fs::read_to_string(dir.join(TENANT_ID_FILE))
+            .unwrap_or_else(|_| entry.file_name().to_string_lossy().into_owned());
+        let log_bytes = std::fs::metadata(dir.join(EPISODES_LOG))
+            .map(|m| m.len())
+            .unwrap_or(0);
+        out.push((tenant_id, dim as usize, log_bytes));
+    }
+    out.sort();
+    out
+}
+
+fn require_data_dir(state: &AppState) -> Result<std::path::PathBuf, ApiError> {
+    state.data_dir.clone().ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "Replication requires disk-backed storage (data_dir) on the leader"})),
+        )
+    })
+}
+
+/// `GET /v1/replication/tenants`: every tenant log on the leader.
+pub async fn tenants(State(state): State<AppState>) -> Result<Json<TenantsResponse>, ApiError> {
+    let data_dir = require_data_dir(&state)?;
+    let scanned = tokio::task::spawn_blocking(move || scan_tenants(&data_dir))
+        .await
+        .unwrap_or_default();
+    let tenants = scanned
+        .into_iter()
+        .map(|(tenant_id, dim, log_bytes)| ReplicatedTenant {
+            generation: state.replication.generation(&tenant_id),
+            tenant_id,
+            dim,
+            log_bytes,
+        })
+        .collect();
+    Ok(Json(TenantsResponse { tenants }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogQuery {
+    #[serde(default)]
+    offset: u64,
+    /// Generation the follower has applied; 409 if the log has since been rewritten.
+    generation: Option<String>,
+}
+
+/// Read whole lines from `offset`, up to `MAX_CHUNK` bytes (more if one batch is larger).
+/// Returns (bytes, log length).
+fn read_chunk(path: &std::path::Path, offset: u64) -> std::io::Result<(Vec<u8>, u64)> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    if offset >= len {
+        return Ok((Vec::new(), len));
+    }
+    let mut limit = MAX_CHUNK;
+    loop {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = Vec::new();
+        (&mut file)
+            .take(limit.min(len - offset))
+            .read_to_end(&mut buf)?;
+        // Never hand out a partial trailing line or a batch that isn't committed yet.
+        let end = LogRecord::committed_len(&buf);
+        if end > 0 || offset + buf.len() as u64 >= len {
+            buf.truncate(end);
+            return Ok((buf, len));
+        }
+        limit *= 2;
+    }
+}
+
+/// `GET /v1/replication/tenants/{tenant_id}/log?offset=&generation=`: raw JSONL log bytes
+/// from `offset`, ending on a line boundary.
+pub async fn log(
+    State(state): State<AppState>,
+    Path(tenant_id): Path<String>,
+    Query(query): Query<LogQuery>,
+) -> Result<Response, ApiError> {
+    let data_dir = require_data_dir(&state)?;
+    let path = data_dir
+        .join(tenant_dir_name(&tenant_id))
+        .join(EPISODES_LOG);
+    if !path.exists() {
+        return Err(not_found("No log for this tenant"));
+    }
+    // Prune and retention rewrite the log under the write lock; hold it off while reading.
+    let _tenants = state.tenants.read().await;
+    let generation = state.replication.generation(&tenant_id);
+    let conflict = || {
+        (
+            StatusCode::CONFLICT,
+            Json(
+                serde_json::json!({"error": "Log rewritten; resync from offset 0", "generation": generation}),
+            ),
+        )
+    };
+    if query.generation.as_ref().is_some_and(|g| *g != generation) {
+        return Err(conflict());
+    }
+    let offset = query.offset;
+    let (bytes, len) = tokio::task::spawn_blocking(move || read_chunk(&path, offset))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r.map_err(|e| e.to_string()))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e})),
+            )
+        })?;
+    if offset > len {
+        return Err(conflict());
+    }
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/x-ndjson"),
+    );
+    if let Ok(v) = generation.parse() {
+        headers.insert(GENERATION_HEADER, v);
+    }
+    headers.insert(LOG_BYTES_HEADER, len.into());
+    Ok((headers, bytes).into_response())
+}
+
+/// `POST /v1/replication/promote`: stop following and accept writes.
+pub async fn promote(State(state): State<AppState>) -> Result<Json<serde_json::Value>, ApiError> {
+    let Some(ref follower) = state.replication.follower else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "Not a follower"})),
+        ));
+    };
+    let was_following = follower.following.swap(false, Ordering::Relaxed);
+    if was_following {
+        tracing::warn!(leader = %follower.leader, "promoted: stopped following, accepting writes");
+        // Replicas stored episodes as replicated; writes from now on get `auto_timestamp`.
+        for (_, tenant) in state.tenants.write().await.iter_mut() {
+            tenant.backend.set_auto_timestamp(state.fills_timestamps());
+        }
+    }
+    Ok(Json(
+        serde_json::json!({"ok": true, "was_following": was_following}),
+    ))
+}
+
+/// Follower progress on one tenant log.
+struct Cursor {
+    generation: String,
+    offset: u64,
+}
+
+/// Follower loop: poll the leader until promoted, then idle until shutdown.
+pub async fn follow(state: AppState) {
+    let Some(ref follower) = state.replication.follower else {
+        return;
+    };
+    tracing::info!(leader = %follower.leader, "following leader");
+    let mut cursors: HashMap<String, Cursor> = HashMap::new();
+    let mut ticker = tokio::time::interval(follower.interval);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = state.shutdown_requested() => return,
+        }
+        // Once promoted, idle until shutdown so readiness still sees the task running.
+        if !follower.following.load(Ordering::Relaxed) {
+            continue;
+        }
+        match sync_once(&state, follower, &mut cursors).await {
+            Ok(lag) => {
+                follower.lag_bytes.store(lag, Ordering::Relaxed);
+                follower.last_sync_ms.store(
+                    chrono::Utc::now().timestamp_millis() as u64,
+                    Ordering::Relaxed,
+                );
+            }
+            Err(e) => {
+                tracing::warn!(leader = %follower.leader, error = %e, "replication poll failed")
+            }
+        }
+    }
+}
+
+/// One pass over every leader tenant. Returns the remaining lag in bytes.
+async fn sync_once(
+    state: &AppState,
+    follower: &Follower,
+    cursors: &mut HashMap<String, Cursor>,
+) -> Result<u64, String> {
+    let listing: TenantsResponse = follower
+        .client
+        .get(format!("{}/v1/replication/tenants", follower.leader))
+        .bearer_auth(&follower.token)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut lag = 0;
+    for remote in listing.tenants {
+        // Promotion mid-pass must not apply anything further.
+        if !follower.following.load(Ordering::Relaxed) {
+            break;
+        }
+        let stale = cursors
+            .get(&remote.tenant_id)
+            .is_none_or(|c| c.generation != remote.generation);
+        if stale {
+            reset_replica(state, &remote).await?;
+            cursors.insert(
+                remote.tenant_id.clone(),
+                Cursor {
+                    generation: remote.generation.clone(),
+                    offset: 0,
+                },
+            );
+        }
+        let cursor = cursors.get_mut(&remote.tenant_id).expect("inserted above");
+        while cursor.offset < remote.log_bytes {
+            match fetch_chunk(follower, &remote.tenant_id, cursor).await? {
+                Some(bytes) if !bytes.is_empty() => {
+                    let episodes = parse_log(&bytes)?;
+                    apply(state, &remote.tenant_id, episodes).await?;
+                    cursor.offset += bytes.len() as u64;
+                }
+                Some(_) => break,
+                // Rewritten since listing; the next pass sees the new generation.
+                None => break,
+            }
+        }
+        lag += remote.log_bytes.saturating_sub(cursor.offset);
+    }
+    Ok(lag)
+}
+
+async fn fetch_chunk(
+    follower: &Follower,
+    tenant_id: &str,
+    cursor: &Cursor,
+) -> Result<Option<Vec<u8>>, String> {
+    let mut url = reqwest::Url::parse(&follower.leader).map_err(|e| e.to_string())?;
+    url.path_segments_mut()
+        .map_err(|_| "leader URL cannot be a base".to_string())?
+        .pop_if_empty()
+        .extend(["v1", "replication", "tenants", tenant_id, "log"]);
+    let response = follower
+        .client
+        .get(url)
+        .bearer_auth(&follower.token)
+        .query(&[
+            ("offset", cursor.offset.to_string()),
+            ("generation", cursor.generation.clone()),
+        ])
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status() == reqwest::StatusCode::CONFLICT {
+        return Ok(None);
+    }
+    let response = response.error_for_status().map_err(|e| e.to_string())?;
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    Ok(Some(bytes.to_vec()))
+}
+
+fn parse_log(bytes: &[u8]) -> Result<Vec<Episode>, String> {
+    // Chunks hold whole committed batches, so batch markers can be skipped.
+    bytes
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| match LogRecord::parse(line) {
+            Ok(LogRecord::Episode(ep)) => Some(Ok(ep)),
+            Ok(_) => None,
+            Err(e) => Some(Err(format!("bad log line: {e}"))),
+        })
+        .collect()
+}
+
+/// Drop the local replica so it is rebuilt from the start of the leader's log.
+async fn reset_replica(state: &AppState, remote: &ReplicatedTenant) -> Result<(), String> {
+    let mut tenants = state.tenants.write().await;
+    tenants.remove(&remote.tenant_id);
+    if let Some(ref dir) = state.data_dir {
+        let tenant_dir = dir.join(tenant_dir_name(&remote.tenant_id));
+        if tenant_dir.exists() {
+            std::fs::remove_dir_all(&tenant_dir).map_err(|e| e.to_string())?;
+        }
+    }
+    let settings = state.tenant_settings.resolve(&remote.tenant_id);
+    let backend = create_tenant_backend(
+        state.data_dir.as_ref(),
+        &remote.tenant_id,
+        remote.dim,
+        &settings,
+    )
+    .map_err(|e| e.to_string())?;
+    tenants.insert(remote.tenant_id.clone(), Tenant::new(backend));
+    tracing::info!(tenant_id = %remote.tenant_id, generation = %remote.generation, "resyncing replica");
+    Ok(())
+}
+
+/// Apply replicated episodes, bypassing quotas and write-scope checks.
+async fn apply(state: &AppState, tenant_id: &str, episodes: Vec<Episode>) -> Result<(), String> {
+    let mut tenants = state.tenants.write().await;
+    // Reopens the local replica if it was evicted (disk follower).
+    let tenant = tenants
+        .get_or_insert_with(tenant_id, || crate::open_tenant(state, tenant_id))
+        .map_err(|(_, Json(body))| body.to_string())?;
+    let bytes: u64 = episodes.iter().map(crate::episode_bytes).sum();
+    let published = (state.episode_events.receiver_count() > 0).then(|| episodes.clone());
+    if let Err(e) = tenant.backend.store_episodes(episodes) {
+        tenant.refresh_stored_bytes();
+        return Err(e.to_string());
+    }
+    tenant.stored_bytes += bytes;
+    if let Some(episodes) = published {
+        subscribe::publish(&state.episode_events, tenant_id, episodes);
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! `X-Request-Id` correlation. Each HTTP request and gRPC call keeps the client's
+//! `X-Request-Id` or gets a fresh UUID; the ID is echoed in the response header, recorded
+//! on the request's tracing span (so every log line under it carries it), and added as
+//! `request_id` to JSON error bodies.
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header::CONTENT_LENGTH, header::CONTENT_TYPE, HeaderMap, HeaderName, Uri},
+    middleware::Next,
+    response::Response,
+};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+
+pub const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Error bodies larger than this are passed through untouched.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// Assigns missing request IDs. Must wrap the tracing layer so spans see the ID.
+pub fn set_layer() -> SetRequestIdLayer<MakeRequestUuid> {
+    SetRequestIdLayer::new(HEADER, MakeRequestUuid)
+}
+
+/// Copies the request ID onto the response.
+pub fn propagate_layer() -> PropagateRequestIdLayer {
+    PropagateRequestIdLayer::new(HEADER)
+}
+
+fn id(headers: &HeaderMap) -> &str {
+    headers
+        .get(&HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+}
+
+/// Span for one HTTP request.
+pub fn http_span<B>(request: &axum::http::Request<B>) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %loggable_uri(request.uri()),
+        request_id = %id(request.headers()),
+    )
+}
+
+/// The URI to log and trace, with the value of any `api_key` query parameter (the WebSocket
+/// key fallback) replaced by `REDACTED`. Pairs are decoded the way authentication reads them,
+/// so an encoded name is caught too.
+pub fn loggable_uri(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+    let pairs: Vec<&str> = query
+        .split('&')
+        .map(|pair| {
+            let decoded = format!("/?{pair}").parse::<Uri>().ok().and_then(|uri| {
+                axum::extract::Query::<Vec<(String, String)>>::try_from_uri(&uri).ok()
+            });
+            match decoded {
+                Some(q) if q.0.iter().any(|(k, _)| k == "api_key") => "api_key=REDACTED",
+                _ => pair,
+            }
+        })
+        .collect();
+    format!("{}?{}", uri.path(), pairs.join("&"))
+}
+
+/// Span for one gRPC call.
+pub fn grpc_span<B>(request: &axum::http::Request<B>) -> tracing::Span {
+    tracing::info_span!(
+        "grpc",
+        path = %request.uri().path(),
+        request_id = %id(request.headers()),
+    )
+}
+
+/// Middleware adding `request_id` to JSON error bodies (4xx/5xx with a JSON object body).
+pub async fn annotate_errors(request: Request, next: Next) -> Response {
+    let request_id = id(request.headers()).to_string();
+    let response = next.run(request).await;
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if request_id.is_empty() || !is_json || !(status.is_client_error() || status.is_server_error())
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_ERROR_BODY).await {
+        Ok(b) => b,
+        Err(_) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut obj)) => {
+            obj.insert("request_id".into(), request_id.into());
+            parts.headers.remove(CONTENT_LENGTH);
+            Body::from(serde_json::Value::Object(obj).to_string())
+        }
+        _ => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
@@ -0,0 +1,316 @@
+//! Scheduled retention. Each tenant's policy (its own from `PUT /v1/retention`, else the
+//! operator's `retention.tenants.<id>`, else the default `[retention]` policy) runs every
+//! `interval_secs` in a background sweep, recording an audit entry and metrics, so operators
+//! don't need external cron jobs calling the prune routes.
+//!
+//! The sweep covers loaded tenants plus tenants with a per-tenant policy that are only on disk.
+//! It wakes every `retention.interval_secs` (at most every minute, sooner if a configured
+//! tenant interval is shorter), so shorter tenant intervals are rounded up to that.
+
+use crate::config::{RetentionConfig, RetentionPolicy};
+use crate::{audit_log, existing_tenant_mut, openapi, ApiError, AppState, Tenant};
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Longest time between sweeps, so tenant policies set at runtime are picked up promptly.
+const MAX_TICK: Duration = Duration::from_secs(60);
+
+/// Where a tenant's retention policy comes from.
+#[derive(Clone, Copy, Debug, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicySource {
+    /// Set by the tenant via `PUT /v1/retention`.
+    Tenant,
+    /// `retention.tenants.<id>` in the server config.
+    Operator,
+    /// The server-wide `[retention]` policy.
+    Default,
+}
+
+/// Outcome of the most recent run for a tenant.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct LastRun {
+    /// When the run finished (RFC 3339).
+    at: String,
+    removed: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip)]
+    finished: Option<Instant>,
+}
+
+pub struct Retention {
+    config: RetentionConfig,
+    runs: Mutex<HashMap<String, LastRun>>,
+}
+
+impl Retention {
+    pub fn new(config: RetentionConfig) -> Self {
+        Self {
+            config,
+            runs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Time between sweeps.
+    pub fn tick(&self) -> Duration {
+        self.config
+            .tenants
+            .values()
+            .filter_map(|p| p.interval_secs)
+            .chain([self.config.interval_secs])
+            .map(Duration::from_secs)
+            .min()
+            .unwrap_or(MAX_TICK)
+            .min(MAX_TICK)
+    }
+
+    fn policy(&self, state: &AppState, tenant_id: &str) -> (RetentionPolicy, PolicySource) {
+        if let Some(policy) = state.tenant_settings.retention(tenant_id) {
+            return (policy, PolicySource::Tenant);
+        }
+        match self.config.tenants.get(tenant_id) {
+            Some(policy) => (policy.clone(), PolicySource::Operator),
+            None => (self.config.default_policy(), PolicySource::Default),
+        }
+    }
+
+    fn interval(&self, policy: &RetentionPolicy) -> Duration {
+        Duration::from_secs(policy.interval_secs.unwrap_or(self.config.interval_secs))
+    }
+
+    fn last_run(&self, tenant_id: &str) -> Option<LastRun> {
+        self.runs.lock().ok()?.get(tenant_id).cloned()
+    }
+
+    /// Time until the tenant's policy is next due; zero if it never ran.
+    fn next_run_in(&self, tenant_id: &str, policy: &RetentionPolicy) -> Duration {
+        self.last_run(tenant_id)
+            .and_then(|r| r.finished)
+            .map_or(Duration::ZERO, |at| {
+                self.interval(policy).saturating_sub(at.elapsed())
+            })
+    }
+
+    fn record(&self, tenant_id: &str, result: &Result<usize, String>) {
+        let run = LastRun {
+            at: chrono::Utc::now().to_rfc3339(),
+            removed: *result.as_ref().unwrap_or(&0),
+            error: result.as_ref().err().cloned(),
+            finished: Some(Instant::now()),
+        };
+        if let Ok(mut runs) = self.runs.lock() {
+            runs.insert(tenant_id.to_string(), run);
+        }
+    }
+}
+
+/// Apply a policy to one tenant, recording metrics, the run outcome and (when episodes were
+/// removed) an audit entry and `prune` webhooks.
+fn run(
+    state: &AppState,
+    tenant_id: &str,
+    tenant: &mut Tenant,
+    policy: &RetentionPolicy,
+) -> Result<usize, String> {
+    let start = Instant::now();
+    let mut removed = 0;
+    let mut result = state.attach_archive(tenant_id, &mut tenant.backend);
+    let rules = policy.rules(chrono::Utc::now().timestamp_millis());
+    if result.is_ok() && !rules.is_empty() {
+        result = tenant.backend.prune(&rules).map(|n| removed += n);
+    }
+    let metrics = &state.metrics;
+    metrics.retention_seconds.observe(start.elapsed());
+    metrics.retention_runs_total.fetch_add(1, Ordering::Relaxed);
+    metrics
+        .retention_removed_total
+        .fetch_add(removed as u64, Ordering::Relaxed);
+    if removed > 0 {
+        tenant.refresh_stored_bytes();
+        tenant.dirty = true;
+        state.replication.log_rewritten(tenant_id);
+        audit_log(state, tenant_id, "retention", None, Some(removed), None);
+        let episodes = tenant.backend.len();
+        state
+            .webhooks
+            .pruned(state, tenant_id, "retention", removed, episodes);
+    }
+    let result = result.map(|()| removed).map_err(|e| e.to_string());
+    if let Err(ref e) = result {
+        metrics
+            .retention_failures_total
+            .fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(tenant_id = %tenant_id, error = %e, "retention run failed");
+    }
+    state.retention.record(tenant_id, &result);
+    result
+}
+
+/// Run every policy that is due.
+pub async fn sweep(state: &AppState) {
+    // Replicas follow the leader's retention through its log.
+    if state.replication.read_only() {
+        return;
+    }
+    let retention = &state.retention;
+    let mut tenants = state.tenants.write().await;
+    let mut ids: BTreeSet<String> = tenants.loaded_tenants().map(String::from).collect();
+    ids.extend(retention.config.tenants.keys().cloned());
+    ids.extend(state.tenant_settings.retention_tenants());
+    for id in ids {
+        let (policy, _) = retention.policy(state, &id);
+        if !policy.has_rules() || !retention.next_run_in(&id, &policy).is_zero() {
+            continue;
+        }
+        let tenant = if tenants.is_loaded(&id) {
+            // Not touched, so the sweep doesn't keep idle tenants from being evicted.
+            tenants.loaded_mut(&id)
+        } else {
+            // Only tenants with data on disk; others have nothing to remove yet.
+            existing_tenant_mut(state, &mut tenants, &id).ok()
+        };
+        if let Some(tenant) = tenant {
+            let _ = run(state, &id, tenant, &policy);
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RetentionStatus {
+    /// The policy applied to this tenant.
+    policy: RetentionPolicy,
+    source: PolicySource,
+    /// Effective time between runs.
+    interval_secs: u64,
+    /// Seconds until the next scheduled run (0 = on the next sweep).
+    next_run_in_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_run: Option<LastRun>,
+}
+
+fn status(state: &AppState, tenant_id: &str) -> RetentionStatus {
+    let retention = &state.retention;
+    let (policy, source) = retention.policy(state, tenant_id);
+    RetentionStatus {
+        interval_secs: retention.interval(&policy).as_secs(),
+        next_run_in_secs: retention.next_run_in(tenant_id, &policy).as_secs(),
+        last_run: retention.last_run(tenant_id),
+        policy,
+        source,
+    }
+}
+
+fn internal(e: String) -> ApiError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({"error": e})),
+    )
+}
+
+/// Get the tenant's retention policy and its last scheduled run.
+#[utoipa::path(
+    get,
+    path = "/v1/retention",
+    tag = "prune",
+    responses(
+        (status = 200, body = RetentionStatus),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn get(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+) -> Json<RetentionStatus> {
+    Json(status(&state, &tenant_id))
+}
+
+/// Set the tenant's own retention policy, replacing the operator's. `{}` disables retention.
+#[utoipa::path(
+    put,
+    path = "/v1/retention",
+    tag = "prune",
+    request_body = RetentionPolicy,
+    responses(
+        (status = 200, body = RetentionStatus),
+        (status = 400, description = "Invalid policy", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn put(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Json(policy): Json<RetentionPolicy>,
+) -> Result<Json<RetentionStatus>, ApiError> {
+    policy.validate().map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": e})),
+        )
+    })?;
+    state
+        .tenant_settings
+        .set_retention(&tenant_id, Some(policy))
+        .map_err(internal)?;
+    audit_log(&state, &tenant_id, "retention_policy", None, None, None);
+    Ok(Json(status(&state, &tenant_id)))
+}
+
+/// Remove the tenant's own retention policy, falling back to the operator's.
+#[utoipa::path(
+    delete,
+    path = "/v1/retention",
+    tag = "prune",
+    responses(
+        (status = 200, body = RetentionStatus),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn delete(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+) -> Result<Json<RetentionStatus>, ApiError> {
+    state
+        .tenant_settings
+        .set_retention(&tenant_id, None)
+        .map_err(internal)?;
+    audit_log(&state, &tenant_id, "retention_policy", None, None, None);
+    Ok(Json(status(&state, &tenant_id)))
+}
+
+/// Run the tenant's retention policy now instead of waiting for the schedule.
+#[utoipa::path(
+    post,
+    path = "/v1/retention/run",
+    tag = "prune",
+    responses(
+        (status = 200, body = RetentionStatus),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+        (status = 404, description = "No episodes stored for this tenant yet", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn run_now(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+) -> Result<Json<RetentionStatus>, ApiError> {
+    state.replication.check_writable()?;
+    let (policy, _) = state.retention.policy(&state, &tenant_id);
+    let mut tenants = state.tenants.write().await;
+    let tenant = existing_tenant_mut(&state, &mut tenants, &tenant_id)?;
+    run(&state, &tenant_id, tenant, &policy).map_err(internal)?;
+    drop(tenants);
+    Ok(Json(status(&state, &tenant_id)))
+}
@@ -0,0 +1,179 @@
+//! Scheduled snapshots of every tenant to S3-compatible object storage, for disaster recovery.
+//!
+//! Every `snapshots.interval_secs` each tenant (loaded or on disk) is archived in the
+//! `POST /v1/admin/backup` format and uploaded to
+//! `<bucket>/<prefix>/<tenant>/<tenant>-<timestamp>.tar.zst`. Snapshots are never deleted
+//! here; expire them with a bucket lifecycle rule.
+
+use crate::config::SnapshotConfig;
+use crate::{backup, replication, AppState};
+use agent_mem_db::tenant_dir_name;
+use object_store::{aws::AmazonS3Builder, path::Path as ObjectPath, ObjectStore, PutPayload};
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Uploader plus progress for `/metrics`.
+pub struct Snapshots {
+    store: Box<dyn ObjectStore>,
+    bucket: String,
+    prefix: String,
+    interval: Duration,
+    /// Unix ms of the last run in which every tenant uploaded; 0 until the first one.
+    last_success_ms: AtomicU64,
+    last_duration_ms: AtomicU64,
+    failures_total: AtomicU64,
+    uploaded_bytes_total: AtomicU64,
+}
+
+impl Snapshots {
+    /// `None` when no bucket is configured.
+    pub fn new(config: &SnapshotConfig) -> Result<Option<Self>, String> {
+        let Some(ref bucket) = config.bucket else {
+            return Ok(None);
+        };
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+        if let Some(ref endpoint) = config.endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        if let Some(ref region) = config.region {
+            builder = builder.with_region(region);
+        }
+        let store = builder.build().map_err(|e| e.to_string())?;
+        Ok(Some(Self {
+            store: Box::new(store),
+            bucket: bucket.clone(),
+            prefix: config.prefix.trim_matches('/').to_string(),
+            interval: Duration::from_secs(config.interval_secs),
+            last_success_ms: AtomicU64::new(0),
+            last_duration_ms: AtomicU64::new(0),
+            failures_total: AtomicU64::new(0),
+            uploaded_bytes_total: AtomicU64::new(0),
+        }))
+    }
+
+    /// Append snapshot series to `/metrics`.
+    pub fn render_metrics(&self, out: &mut String) {
+        out.push_str(&format!(
+            "# HELP agent_mem_snapshot_last_success_timestamp_seconds Last snapshot run in which every tenant uploaded\n\
+             # TYPE agent_mem_snapshot_last_success_timestamp_seconds gauge\n\
+             agent_mem_snapshot_last_success_timestamp_seconds {}\n\
+             # HELP agent_mem_snapshot_last_duration_seconds Duration of the last snapshot run\n\
+             # TYPE agent_mem_snapshot_last_duration_seconds gauge\n\
+             agent_mem_snapshot_last_duration_seconds {}\n\
+             # HELP agent_mem_snapshot_failures_total Tenant snapshots that failed to build or upload\n\
+             # TYPE agent_mem_snapshot_failures_total counter\n\
+             agent_mem_snapshot_failures_total {}\n\
+             # HELP agent_mem_snapshot_uploaded_bytes_total Snapshot bytes uploaded\n\
+             # TYPE agent_mem_snapshot_uploaded_bytes_total counter\n\
+             agent_mem_snapshot_uploaded_bytes_total {}\n",
+            self.last_success_ms.load(Ordering::Relaxed) as f64 / 1000.0,
+            self.last_duration_ms.load(Ordering::Relaxed) as f64 / 1000.0,
+            self.failures_total.load(Ordering::Relaxed),
+            self.uploaded_bytes_total.load(Ordering::Relaxed),
+        ));
+    }
+
+    fn key(&self, tenant_id: &str) -> ObjectPath {
+        let safe = tenant_dir_name(tenant_id);
+        let name = backup::archive_name(tenant_id);
+        if self.prefix.is_empty() {
+            ObjectPath::from(format!("{safe}/{name}"))
+        } else {
+            ObjectPath::from(format!("{}/{safe}/{name}", self.prefix))
+        }
+    }
+}
+
+/// Snapshot loop: the first run is one interval after startup.
+pub async fn run(state: AppState) {
+    let Some(ref snapshots) = state.snapshots else {
+        return;
+    };
+    tracing::info!(
+        bucket = %snapshots.bucket,
+        prefix = %snapshots.prefix,
+        interval_secs = snapshots.interval.as_secs(),
+        "scheduled snapshots enabled"
+    );
+    let start = tokio::time::Instant::now() + snapshots.interval;
+    let mut ticker = tokio::time::interval_at(start, snapshots.interval);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = state.shutdown_requested() => return,
+        }
+        snapshot_all(&state, snapshots).await;
+    }
+}
+
+/// Every loaded tenant plus every tenant directory under `data_dir`.
+async fn tenant_ids(state: &AppState) -> BTreeSet<String> {
+    let mut ids: BTreeSet<String> = state
+        .tenants
+        .read()
+        .await
+        .loaded_tenants()
+        .map(String::from)
+        .collect();
+    if let Some(dir) = state.data_dir.clone() {
+        let on_disk = tokio::task::spawn_blocking(move || replication::scan_tenants(&dir))
+            .await
+            .unwrap_or_default();
+        ids.extend(on_disk.into_iter().map(|(id, _, _)| id));
+    }
+    ids
+}
+
+/// One pass over every tenant; a failed tenant is logged and counted, and the rest continue.
+async fn snapshot_all(state: &AppState, snapshots: &Snapshots) {
+    let started = Instant::now();
+    let mut failed = 0;
+    let mut uploaded = 0;
+    for tenant_id in tenant_ids(state).await {
+        let result = match backup::archive(state, &tenant_id).await {
+            Ok(archive) => {
+                let bytes = archive.len() as u64;
+                let key = snapshots.key(&tenant_id);
+                snapshots
+                    .store
+                    .put(&key, PutPayload::from(archive))
+                    .await
+                    .map(|_| bytes)
+                    .map_err(|e| e.to_string())
+            }
+            Err((_, body)) => Err(body.0["error"].as_str().unwrap_or_default().to_string()),
+        };
+        match result {
+            Ok(bytes) => {
+                uploaded += 1;
+                snapshots
+                    .uploaded_bytes_total
+                    .fetch_add(bytes, Ordering::Relaxed);
+            }
+            Err(e) => {
+                failed += 1;
+                snapshots.failures_total.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(tenant_id = %tenant_id, error = %e, "tenant snapshot failed");
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+    snapshots
+        .last_duration_ms
+        .store(elapsed.as_millis() as u64, Ordering::Relaxed);
+    if failed == 0 {
+        snapshots.last_success_ms.store(
+            chrono::Utc::now().timestamp_millis() as u64,
+            Ordering::Relaxed,
+        );
+    }
+    tracing::info!(
+        uploaded,
+        failed,
+        elapsed_ms = elapsed.as_millis() as u64,
+        "snapshot run finished"
+    );
+}
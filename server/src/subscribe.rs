@@ -0,0 +1,137 @@
+//! `GET /v1/subscribe`: WebSocket push of newly stored episodes.
+//!
+//! Stores publish to an in-process broadcast channel; each subscriber forwards its own
+//! tenant's episodes, optionally filtered by tags and user_id, as JSON text frames:
+//! `{"type":"episode","episode":{...}}`. A slow client that falls behind the channel gets
+//! `{"type":"lagged","skipped":n}` instead of the dropped episodes.
+
+use crate::{audit_log, AppState};
+use agent_mem_db::Episode;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::Response,
+    Extension,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Episodes buffered per subscriber before it is reported as lagged.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A stored episode, as seen by subscribers.
+pub struct StoredEvent {
+    pub tenant_id: String,
+    pub episode: Episode,
+}
+
+pub type EventBus = broadcast::Sender<Arc<StoredEvent>>;
+
+pub fn event_bus() -> EventBus {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+/// Publish stored episodes. Callers should skip building events when nobody is subscribed.
+pub fn publish(bus: &EventBus, tenant_id: &str, episodes: Vec<Episode>) {
+    for episode in episodes {
+        // Err only means no receivers; nothing to do.
+        let _ = bus.send(Arc::new(StoredEvent {
+            tenant_id: tenant_id.to_string(),
+            episode,
+        }));
+    }
+}
+
+/// Subscription filter from the query string, e.g. `?tags=a,b&user_id=u1`.
+#[derive(Debug, Default, Deserialize)]
+pub struct SubscribeFilter {
+    /// Comma-separated; an episode matches if it has any of these tags.
+    tags: Option<String>,
+    user_id: Option<String>,
+    session_id: Option<String>,
+}
+
+impl SubscribeFilter {
+    fn matches(&self, ep: &Episode) -> bool {
+        if let Some(ref user_id) = self.user_id {
+            if ep.user_id.as_deref() != Some(user_id.as_str()) {
+                return false;
+            }
+        }
+        if let Some(ref session_id) = self.session_id {
+            if ep.session_id.as_deref() != Some(session_id.as_str()) {
+                return false;
+            }
+        }
+        if let Some(ref tags) = self.tags {
+            let ep_tags = ep.tags.as_deref().unwrap_or_default();
+            let mut wanted = tags.split(',').map(str::trim).filter(|t| !t.is_empty());
+            if !wanted.any(|w| ep_tags.iter().any(|t| t == w)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// WebSocket stream of newly stored episodes.
+#[utoipa::path(
+    get,
+    path = "/v1/subscribe",
+    tag = "query",
+    params(
+        ("tags" = Option<String>, Query, description = "Comma-separated; match any"),
+        ("user_id" = Option<String>, Query, description = "Only this user's episodes"),
+        ("session_id" = Option<String>, Query, description = "Only this session's episodes"),
+        ("api_key" = Option<String>, Query, description = "API key for browser clients"),
+    ),
+    responses(
+        (status = 101, description = "Upgraded; JSON text frames `{\"type\":\"episode\",\"episode\":{...}}`"),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn subscribe(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Query(filter): Query<SubscribeFilter>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let rx = state.episode_events.subscribe();
+    audit_log(&state, &tenant_id, "subscribe", None, None, None);
+    ws.on_upgrade(move |socket| forward(socket, rx, tenant_id, filter))
+}
+
+async fn forward(
+    mut socket: WebSocket,
+    mut rx: broadcast::Receiver<Arc<StoredEvent>>,
+    tenant_id: String,
+    filter: SubscribeFilter,
+) {
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let frame = match event {
+                    Ok(ev) if ev.tenant_id == tenant_id && filter.matches(&ev.episode) => {
+                        serde_json::json!({"type": "episode", "episode": ev.episode})
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        serde_json::json!({"type": "lagged", "skipped": skipped})
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if socket.send(Message::Text(frame.to_string())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                // Pings are answered by axum; other client frames are ignored.
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Tracing setup: log events to stderr (text or JSON) and, when built with the `otel` feature and
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` is set, export spans and counters over OTLP (gRPC).
+//!
+//! Spans cover each HTTP/gRPC request, authentication, tenant lookup (including disk
+//! opens), store/query/prune, and disk checkpoints and saves. The service name defaults to
+//! `agent-mem-server`; override it with `OTEL_SERVICE_NAME`.
+
+use crate::config::LogFormat;
+use crate::metrics::Metrics;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Handle to the installed exporters; call [`Telemetry::shutdown`] before exiting so
+/// buffered spans are flushed.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    otel: Option<otel::Providers>,
+}
+
+/// Install the global tracing subscriber. Must be called from within the Tokio runtime.
+pub fn init(format: LogFormat) -> Telemetry {
+    let filter = tracing_subscriber::EnvFilter::new(
+        std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
+    );
+    let json = format == LogFormat::Json;
+    // Never stdout: `--mcp-stdio` speaks JSON-RPC there.
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with((!json).then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr)))
+        .with(json.then(|| {
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+        }));
+
+    #[cfg(feature = "otel")]
+    {
+        let (providers, error) = match otel::endpoint() {
+            Some(_) => match otel::Providers::new() {
+                Ok(p) => (Some(p), None),
+                Err(e) => (None, Some(e)),
+            },
+            None => (None, None),
+        };
+        let layer = providers.as_ref().map(|p| {
+            use opentelemetry::trace::TracerProvider as _;
+            tracing_opentelemetry::layer().with_tracer(p.tracer.tracer("agent-mem-server"))
+        });
+        registry.with(layer).init();
+        if let Some(e) = error {
+            tracing::error!(error = %e, "OTLP export disabled: failed to create exporter");
+        } else if providers.is_some() {
+            tracing::info!(
+                endpoint = %otel::endpoint().unwrap_or_default(),
+                "OTLP trace and metrics export enabled"
+            );
+        }
+        Telemetry { otel: providers }
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.init();
+        if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_some() {
+            tracing::warn!(
+                "OTEL_EXPORTER_OTLP_ENDPOINT is set but this build has no OTLP support (rebuild with --features otel)"
+            );
+        }
+        Telemetry {}
+    }
+}
+
+impl Telemetry {
+    /// Export the server's request counters as OTLP metrics (no-op unless enabled).
+    pub fn export_metrics(&self, metrics: &Metrics) {
+        #[cfg(feature = "otel")]
+        if let Some(ref p) = self.otel {
+            p.register_counters(metrics);
+        }
+        #[cfg(not(feature = "otel"))]
+        let _ = metrics;
+    }
+
+    /// Flush and stop the exporters.
+    pub fn shutdown(self) {
+        #[cfg(feature = "otel")]
+        if let Some(p) = self.otel {
+            p.shutdown();
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use crate::metrics::Metrics;
+    use opentelemetry::metrics::MeterProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::{metrics::SdkMeterProvider, runtime, trace::TracerProvider, Resource};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    pub fn endpoint() -> Option<String> {
+        std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .filter(|e| !e.trim().is_empty())
+    }
+
+    pub struct Providers {
+        pub tracer: TracerProvider,
+        meter: SdkMeterProvider,
+    }
+
+    impl Providers {
+        /// Build batch exporters for traces and metrics. The tonic exporters read
+        /// `OTEL_EXPORTER_OTLP_ENDPOINT` (and the other standard `OTEL_EXPORTER_OTLP_*` vars).
+        pub fn new() -> Result<Self, String> {
+            let service = std::env::var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|_| "agent-mem-server".to_string());
+            let resource = Resource::new_with_defaults([
+                KeyValue::new("service.name", service),
+                KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+            ]);
+
+            let spans = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .build()
+                .map_err(|e| e.to_string())?;
+            let tracer = TracerProvider::builder()
+                .with_batch_exporter(spans, runtime::Tokio)
+                .with_resource(resource.clone())
+                .build();
+
+            let metrics = opentelemetry_otlp::MetricExporter::builder()
+                .with_tonic()
+                .build()
+                .map_err(|e| e.to_string())?;
+            let reader =
+                opentelemetry_sdk::metrics::PeriodicReader::builder(metrics, runtime::Tokio)
+                    .build();
+            let meter = SdkMeterProvider::builder()
+                .with_reader(reader)
+                .with_resource(resource)
+                .build();
+
+            Ok(Self { tracer, meter })
+        }
+
+        /// Observable counters mirroring the `/metrics` totals.
+        pub fn register_counters(&self, metrics: &Metrics) {
+            let meter = self.meter.meter("agent-mem-server");
+            let counters: [(&'static str, &'static str, &Arc<AtomicU64>); 4] = [
+                (
+                    "agent_mem.requests",
+                    "Authenticated API requests",
+                    &metrics.requests_total,
+                ),
+                (
+                    "agent_mem.store_episodes",
+                    "Episodes stored",
+                    &metrics.store_episodes_total,
+                ),
+                (
+                    "agent_mem.queries",
+                    "Similarity queries",
+                    &metrics.query_total,
+                ),
+                (
+                    "agent_mem.tenant_evictions",
+                    "Tenants evicted from memory",
+                    &metrics.tenant_evictions_total,
+                ),
+            ];
+            for (name, description, value) in counters {
+                let value = value.clone();
+                // The SDK keeps the callback registered after the handle is dropped.
+                meter
+                    .u64_observable_counter(name)
+                    .with_description(description)
+                    .with_callback(move |obs| obs.observe(value.load(Ordering::Relaxed), &[]))
+                    .build();
+            }
+        }
+
+        pub fn shutdown(self) {
+            if let Err(e) = self.tracer.shutdown() {
+                eprintln!("agent-mem-server: OTLP trace shutdown: {e}");
+            }
+            if let Err(e) = self.meter.shutdown() {
+                eprintln!("agent-mem-server: OTLP metrics shutdown: {e}");
+            }
+        }
+    }
+}
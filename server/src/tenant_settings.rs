@@ -0,0 +1,341 @@
+//! Per-tenant backend (memory or disk) and index (exact or HNSW, with HNSW parameters).
+//!
+//! Settings are layered: a tenant's saved settings (`PUT /v1/tenant/settings`), then its
+//! entry in the keys file, then the operator's `tenants.<id>` and `tenant_defaults` config,
+//! then the built-in default (disk + exact with `data_dir`, else memory + HNSW). They apply when a tenant's storage is
+//! created; an existing disk tenant keeps the index recorded in its `meta.json`.
+//!
+//! Saved settings (including the tenant's own retention policy and webhooks, see `retention`
+//! and `webhooks`) are files
+//! under `<data_dir or write_behind.dir>/.tenant_settings/`; without either they last until
+//! restart.
+
+use crate::config::{BackendKind, Config, IndexKind, RetentionPolicy, TenantSettings, Webhook};
+use crate::{openapi, ApiError, AppState};
+use agent_mem_db::{tenant_dir_name, HnswParams};
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use utoipa::ToSchema;
+
+const SETTINGS_DIR: &str = ".tenant_settings";
+const DEFAULT_HNSW_MAX_ELEMENTS: usize = 20_000;
+
+/// Settings after layering, as used to create the tenant's storage.
+#[derive(Clone, Copy, Debug, Serialize, ToSchema)]
+pub struct Resolved {
+    pub backend: BackendKind,
+    pub index: IndexKind,
+    pub hnsw_max_elements: usize,
+    pub hnsw_m: usize,
+    pub hnsw_ef_construction: usize,
+    pub hnsw_ef_search: usize,
+}
+
+impl Resolved {
+    pub fn hnsw_params(&self) -> HnswParams {
+        HnswParams {
+            m: self.hnsw_m,
+            ef_construction: self.hnsw_ef_construction,
+            ef_search: self.hnsw_ef_search,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Saved {
+    #[serde(default)]
+    settings: TenantSettings,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retention: Option<RetentionPolicy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    webhooks: Vec<Webhook>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedFile {
+    tenant_id: String,
+    #[serde(flatten)]
+    saved: Saved,
+}
+
+pub struct Settings {
+    dir: Option<PathBuf>,
+    has_data_dir: bool,
+    defaults: TenantSettings,
+    operator: HashMap<String, TenantSettings>,
+    /// Backend and index from the keys file; replaced when it is reloaded.
+    keys_file: RwLock<HashMap<String, TenantSettings>>,
+    saved: RwLock<HashMap<String, Saved>>,
+}
+
+impl Settings {
+    /// Read the operator config and every saved tenant file.
+    pub fn load(config: &Config) -> Result<Self, String> {
+        let dir = config
+            .data_dir
+            .as_ref()
+            .or(config.write_behind.dir.as_ref())
+            .map(|d| d.join(SETTINGS_DIR));
+        let mut saved = HashMap::new();
+        if let Some(ref dir) = dir {
+            for entry in std::fs::read_dir(dir).into_iter().flatten() {
+                let path = entry.map_err(|e| e.to_string())?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                    continue;
+                }
+                let file: SavedFile = std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+                    .map_err(|e| format!("{}: {e}", path.display()))?;
+                saved.insert(file.tenant_id, file.saved);
+            }
+        }
+        Ok(Self {
+            dir,
+            has_data_dir: config.data_dir.is_some(),
+            defaults: config.tenant_defaults.clone(),
+            operator: config.tenants.clone(),
+            keys_file: RwLock::default(),
+            saved: RwLock::new(saved),
+        })
+    }
+
+    pub fn saved(&self, tenant_id: &str) -> TenantSettings {
+        self.saved
+            .read()
+            .ok()
+            .and_then(|s| s.get(tenant_id).map(|s| s.settings.clone()))
+            .unwrap_or_default()
+    }
+
+    pub fn set_keys_file(&self, settings: HashMap<String, TenantSettings>) {
+        match self.keys_file.write() {
+            Ok(mut current) => *current = settings,
+            Err(e) => *e.into_inner() = settings,
+        }
+    }
+
+    /// The retention policy the tenant set for itself, if any.
+    pub fn retention(&self, tenant_id: &str) -> Option<RetentionPolicy> {
+        self.saved
+            .read()
+            .ok()
+            .and_then(|s| s.get(tenant_id).and_then(|s| s.retention.clone()))
+    }
+
+    /// Tenants that set their own retention policy.
+    pub fn retention_tenants(&self) -> Vec<String> {
+        self.saved
+            .read()
+            .map(|s| {
+                s.iter()
+                    .filter(|(_, s)| s.retention.is_some())
+                    .map(|(id, _)| id.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn set_retention(
+        &self,
+        tenant_id: &str,
+        retention: Option<RetentionPolicy>,
+    ) -> Result<(), String> {
+        self.update(tenant_id, |saved| saved.retention = retention)
+    }
+
+    pub fn resolve(&self, tenant_id: &str) -> Resolved {
+        let empty = TenantSettings::default();
+        let from_file = self
+            .keys_file
+            .read()
+            .ok()
+            .and_then(|f| f.get(tenant_id).cloned())
+            .unwrap_or_default();
+        let layered = self
+            .saved(tenant_id)
+            .or(&from_file)
+            .or(self.operator.get(tenant_id).unwrap_or(&empty))
+            .or(&self.defaults);
+        self.layered(layered)
+    }
+
+    /// Settings of a tenant nothing overrides.
+    pub fn defaults(&self) -> Resolved {
+        self.layered(self.defaults.clone())
+    }
+
+    fn layered(&self, layered: TenantSettings) -> Resolved {
+        let backend = layered.backend.unwrap_or(if self.has_data_dir {
+            BackendKind::Disk
+        } else {
+            BackendKind::Memory
+        });
+        let defaults = HnswParams::default();
+        Resolved {
+            backend,
+            index: layered.index.unwrap_or(match backend {
+                BackendKind::Disk => IndexKind::Exact,
+                BackendKind::Memory => IndexKind::Hnsw,
+            }),
+            hnsw_max_elements: layered
+                .hnsw_max_elements
+                .unwrap_or(DEFAULT_HNSW_MAX_ELEMENTS),
+            hnsw_m: layered.hnsw_m.unwrap_or(defaults.m),
+            hnsw_ef_construction: layered
+                .hnsw_ef_construction
+                .unwrap_or(defaults.ef_construction),
+            hnsw_ef_search: layered.hnsw_ef_search.unwrap_or(defaults.ef_search),
+        }
+    }
+
+    /// Webhooks the tenant registered.
+    pub fn webhooks(&self, tenant_id: &str) -> Vec<Webhook> {
+        self.saved
+            .read()
+            .ok()
+            .and_then(|s| s.get(tenant_id).map(|s| s.webhooks.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Change the tenant's webhooks; `change` returns whether anything changed.
+    pub fn update_webhooks(
+        &self,
+        tenant_id: &str,
+        change: impl FnOnce(&mut Vec<Webhook>) -> bool,
+    ) -> Result<bool, String> {
+        let mut changed = false;
+        self.update(tenant_id, |saved| changed = change(&mut saved.webhooks))?;
+        Ok(changed)
+    }
+
+    /// Change a tenant's saved entry, writing it to disk when a directory is available.
+    fn update(&self, tenant_id: &str, change: impl FnOnce(&mut Saved)) -> Result<(), String> {
+        let mut all = self
+            .saved
+            .write()
+            .map_err(|_| "tenant settings lock poisoned".to_string())?;
+        let mut saved = all.get(tenant_id).cloned().unwrap_or_default();
+        change(&mut saved);
+        if let Some(ref dir) = self.dir {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+            let path = dir.join(format!("{}.json", tenant_dir_name(tenant_id)));
+            let tmp = path.with_extension("json.tmp");
+            let file = SavedFile {
+                tenant_id: tenant_id.to_string(),
+                saved: saved.clone(),
+            };
+            let data = serde_json::to_vec_pretty(&file).map_err(|e| e.to_string())?;
+            std::fs::write(&tmp, data)
+                .and_then(|()| std::fs::rename(&tmp, &path))
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        all.insert(tenant_id.to_string(), saved);
+        Ok(())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TenantSettingsResponse {
+    /// Settings saved for this tenant (unset fields fall through to the server config).
+    settings: TenantSettings,
+    /// What the tenant's storage is (or will be) created with.
+    effective: Resolved,
+}
+
+fn response(state: &AppState, tenant_id: &str) -> TenantSettingsResponse {
+    TenantSettingsResponse {
+        settings: state.tenant_settings.saved(tenant_id),
+        effective: state.tenant_settings.resolve(tenant_id),
+    }
+}
+
+/// Get the tenant's backend and index settings.
+#[utoipa::path(
+    get,
+    path = "/v1/tenant/settings",
+    tag = "admin",
+    responses(
+        (status = 200, body = TenantSettingsResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn get(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+) -> Json<TenantSettingsResponse> {
+    Json(response(&state, &tenant_id))
+}
+
+/// Choose the tenant's backend and index. Only allowed before the tenant stores episodes.
+#[utoipa::path(
+    put,
+    path = "/v1/tenant/settings",
+    tag = "admin",
+    request_body = TenantSettings,
+    responses(
+        (status = 200, body = TenantSettingsResponse),
+        (status = 400, description = "Invalid settings (e.g. disk without data_dir)", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+        (status = 409, description = "Tenant already has storage", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn put(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Json(settings): Json<TenantSettings>,
+) -> Result<Json<TenantSettingsResponse>, ApiError> {
+    settings.validate(state.data_dir.is_some()).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": e})),
+        )
+    })?;
+    let conflict = |msg: &str| {
+        (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({"error": msg})),
+        )
+    };
+
+    // Hold the tenants lock so the tenant can't be created while settings change.
+    let mut tenants = state.tenants.write().await;
+    if let Some(tenant) = tenants.loaded(&tenant_id) {
+        if !tenant.backend.is_empty() {
+            return Err(conflict(
+                "Tenant already stores episodes; settings can only change before the first store",
+            ));
+        }
+    }
+    let on_disk = state.data_dir.as_ref().is_some_and(|dir| {
+        dir.join(tenant_dir_name(&tenant_id))
+            .join("meta.json")
+            .exists()
+    });
+    if on_disk {
+        return Err(conflict(
+            "Tenant storage already exists on disk; settings can only change before the first store",
+        ));
+    }
+    state
+        .tenant_settings
+        .update(&tenant_id, |saved| saved.settings = settings)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e})),
+            )
+        })?;
+    // An empty in-memory tenant is recreated with the new settings on its next write.
+    tenants.remove(&tenant_id);
+    drop(tenants);
+    crate::audit_log(&state, &tenant_id, "tenant_settings", None, None, None);
+    Ok(Json(response(&state, &tenant_id)))
+}
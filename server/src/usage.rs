@@ -0,0 +1,252 @@
+//! Usage accounting for billing: per-tenant episodes stored, queries served, stored bytes and
+//! vector-dimension-seconds (stored vectors × dim, integrated over time, like GB-hours).
+//!
+//! Counters are cumulative across restarts: they are saved every `usage.interval_secs` (and
+//! on shutdown) to `<data_dir or write_behind.dir>/.usage/usage.json` and reloaded on boot.
+//! Without either directory they last until restart. Time the server is down is not billed.
+//!
+//! `GET /v1/admin/usage` returns the caller's tenant for an admin key, or any tenant (all
+//! when `tenant` is omitted) for `usage.token`.
+
+use crate::config::Config;
+use crate::{authenticate, extract_api_key, openapi, ApiError, AppState, Scope, Tenant};
+use agent_mem_db::EpisodeStore;
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use utoipa::{IntoParams, ToSchema};
+
+const USAGE_DIR: &str = ".usage";
+const USAGE_FILE: &str = "usage.json";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct TenantUsage {
+    /// Episodes stored (including replaced ones), all time.
+    pub episodes_stored: u64,
+    /// Queries served, all time.
+    pub queries: u64,
+    /// Stored bytes when last observed.
+    pub stored_bytes: u64,
+    /// Stored episodes when last observed.
+    pub episodes: u64,
+    pub dim: usize,
+    /// `episodes × dim` summed over every second they were stored.
+    pub vector_dimension_seconds: f64,
+    /// Unix ms of the first recorded activity.
+    pub since_ms: i64,
+    /// Unix ms of the last update.
+    pub updated_ms: i64,
+    /// When `vector_dimension_seconds` was last brought up to date; not saved, so downtime
+    /// isn't billed.
+    #[serde(skip)]
+    #[schema(ignore)]
+    accrued_at: Option<Instant>,
+}
+
+impl TenantUsage {
+    /// Add the vector-dimension-seconds since the last accrual at the current size.
+    fn accrue(&mut self, now: Instant) {
+        if let Some(at) = self.accrued_at {
+            let secs = now.saturating_duration_since(at).as_secs_f64();
+            self.vector_dimension_seconds += self.episodes as f64 * self.dim as f64 * secs;
+        }
+        self.accrued_at = Some(now);
+    }
+
+    /// Accrue at the old size, then record the tenant's current size.
+    fn observe(&mut self, tenant: &Tenant, now: Instant) {
+        self.accrue(now);
+        self.episodes = tenant.backend.len() as u64;
+        self.dim = tenant.backend.dim();
+        self.stored_bytes = tenant.stored_bytes;
+        self.touch();
+    }
+
+    fn touch(&mut self) {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        if self.since_ms == 0 {
+            self.since_ms = now_ms;
+        }
+        self.updated_ms = now_ms;
+    }
+}
+
+pub struct Usage {
+    path: Option<PathBuf>,
+    interval: Duration,
+    /// Operator token for any tenant's usage.
+    token: Option<String>,
+    tenants: Mutex<BTreeMap<String, TenantUsage>>,
+}
+
+impl Usage {
+    /// Whether `key` is the operator's `usage.token`.
+    pub fn is_operator(&self, key: Option<&str>) -> bool {
+        self.token.is_some() && key == self.token.as_deref()
+    }
+
+    /// Load saved counters from the usage file, if there is a directory for one.
+    pub fn load(config: &Config) -> Result<Self, String> {
+        let path = config
+            .data_dir
+            .as_ref()
+            .or(config.write_behind.dir.as_ref())
+            .map(|d| d.join(USAGE_DIR).join(USAGE_FILE));
+        let tenants = match path {
+            Some(ref path) if path.exists() => std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+                .map_err(|e| format!("{}: {e}", path.display()))?,
+            _ => BTreeMap::new(),
+        };
+        Ok(Self {
+            path,
+            interval: Duration::from_secs(config.usage.interval_secs),
+            token: config.usage.token.clone(),
+            tenants: Mutex::new(tenants),
+        })
+    }
+
+    fn with_tenant(&self, tenant_id: &str, f: impl FnOnce(&mut TenantUsage)) {
+        let mut tenants = self.tenants.lock().unwrap_or_else(|e| e.into_inner());
+        f(tenants.entry(tenant_id.to_string()).or_default());
+    }
+
+    /// Count a store of `episodes` and the tenant's size after it.
+    pub fn record_store(&self, tenant_id: &str, episodes: u64, tenant: &Tenant) {
+        let now = Instant::now();
+        self.with_tenant(tenant_id, |u| {
+            u.episodes_stored += episodes;
+            u.observe(tenant, now);
+        });
+    }
+
+    pub fn record_query(&self, tenant_id: &str) {
+        self.with_tenant(tenant_id, |u| {
+            u.queries += 1;
+            u.touch();
+        });
+    }
+
+    /// Record the current size of every loaded tenant and accrue the rest (evicted disk
+    /// tenants keep accruing at their last observed size).
+    pub async fn observe_all(&self, state: &AppState) {
+        let tenants = state.tenants.read().await;
+        let now = Instant::now();
+        let mut usage = self.tenants.lock().unwrap_or_else(|e| e.into_inner());
+        for (id, tenant) in tenants.iter() {
+            usage
+                .entry(id.to_string())
+                .or_default()
+                .observe(tenant, now);
+        }
+        for u in usage.values_mut() {
+            u.accrue(now);
+        }
+    }
+
+    /// Write the counters to the usage file (temp file + rename). No-op without one.
+    pub fn save(&self) -> Result<(), String> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        let data = {
+            let tenants = self.tenants.lock().unwrap_or_else(|e| e.into_inner());
+            serde_json::to_vec_pretty(&*tenants).map_err(|e| e.to_string())?
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Bring every tenant up to date and save.
+    pub async fn flush(&self, state: &AppState) {
+        self.observe_all(state).await;
+        if let Err(e) = self.save() {
+            tracing::error!(error = %e, "failed to save usage");
+        }
+    }
+
+    fn snapshot(&self, tenant_id: Option<&str>) -> HashMap<String, TenantUsage> {
+        let tenants = self.tenants.lock().unwrap_or_else(|e| e.into_inner());
+        tenants
+            .iter()
+            .filter(|(id, _)| tenant_id.is_none_or(|t| t == id.as_str()))
+            .map(|(id, u)| (id.clone(), u.clone()))
+            .collect()
+    }
+}
+
+/// Flush usage every `usage.interval_secs`.
+pub async fn run(state: AppState) {
+    let mut ticker = tokio::time::interval(state.usage.interval);
+    loop {
+        ticker.tick().await;
+        state.usage.flush(&state).await;
+    }
+}
+
+/// Query for `GET /v1/admin/usage`.
+#[derive(Deserialize, IntoParams)]
+pub struct UsageQuery {
+    /// Tenant to report. Admin keys may only name their own; with `usage.token`, omit it
+    /// for every tenant.
+    tenant: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UsageResponse {
+    /// Keyed by tenant id.
+    tenants: HashMap<String, TenantUsage>,
+}
+
+/// Billing counters per tenant, brought up to date at the time of the request.
+#[utoipa::path(
+    get,
+    path = "/v1/admin/usage",
+    tag = "admin",
+    params(UsageQuery),
+    responses(
+        (status = 200, body = UsageResponse),
+        (status = 401, description = "Missing or invalid API key or usage token", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope or names another tenant", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn get(
+    State(state): State<AppState>,
+    Query(query): Query<UsageQuery>,
+    headers: HeaderMap,
+) -> Result<Json<UsageResponse>, ApiError> {
+    let key = extract_api_key(&headers);
+    let tenant = if state.usage.is_operator(key.as_deref()) {
+        query.tenant
+    } else {
+        let (tenant_id, scopes) = authenticate(&state, key)?;
+        scopes.require(Scope::Admin)?;
+        if query.tenant.as_ref().is_some_and(|t| *t != tenant_id) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "error": "API keys can only read their own tenant's usage (use usage.token)"
+                })),
+            ));
+        }
+        Some(tenant_id)
+    };
+    state.usage.observe_all(&state).await;
+    Ok(Json(UsageResponse {
+        tenants: state.usage.snapshot(tenant.as_deref()),
+    }))
+}
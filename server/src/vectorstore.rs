@@ -0,0 +1,415 @@
+//! Vector-store compatibility routes under `/v1/vectorstore`, with the document semantics
+//! LangChain and LlamaIndex vector stores expect (add texts or embeddings, similarity search
+//! with a filter dict, delete by ids), so existing Python stacks can use the server through
+//! a thin REST client.
+//!
+//! Documents map onto episodes: the document id is the episode `task_id` (a generated UUID
+//! when the caller gives none), the text is kept in `metadata.text`, and `tags`, `source`,
+//! `user_id`, `session_id`, `embedding_model` and `timestamp` metadata keys are also copied to
+//! the episode fields so they can be filtered on; texts the server embeds are tagged with its
+//! `embedding.model`. Adding a document with an existing id replaces it.
+
+use crate::{
+    audit_log, delete_for_tenant, embedding, openapi, query_for_tenant, store_for_tenant, ApiError,
+    AppState,
+};
+use agent_mem_db::{Episode, QueryOptions};
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Keys accepted in a search `filter`.
+const FILTER_KEYS: &[&str] = &[
+    "tags",
+    "tags_any",
+    "tags_all",
+    "source",
+    "user_id",
+    "session_id",
+    "embedding_model",
+    "time_after",
+    "time_before",
+];
+
+fn bad_request(msg: String) -> ApiError {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({"error": msg})),
+    )
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AddTextsRequest {
+    /// Embedded by the server (`embedding.url`).
+    texts: Vec<String>,
+    #[serde(default)]
+    #[schema(value_type = Option<Vec<Object>>)]
+    metadatas: Option<Vec<Map<String, Value>>>,
+    /// Document ids; generated when omitted.
+    #[serde(default)]
+    ids: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AddEmbeddingsRequest {
+    embeddings: Vec<Vec<f32>>,
+    /// Document text, returned as `page_content`.
+    #[serde(default)]
+    texts: Option<Vec<String>>,
+    #[serde(default)]
+    #[schema(value_type = Option<Vec<Object>>)]
+    metadatas: Option<Vec<Map<String, Value>>>,
+    #[serde(default)]
+    ids: Option<Vec<String>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AddResponse {
+    ids: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SimilaritySearchRequest {
+    /// Query text, embedded by the server; or send `embedding`.
+    #[serde(default)]
+    query: Option<String>,
+    #[serde(default)]
+    embedding: Option<Vec<f32>>,
+    #[serde(default = "default_k")]
+    k: usize,
+    /// `tags`/`tags_any`, `tags_all`, `source`, `user_id`, `session_id`, `embedding_model`,
+    /// `time_after`, `time_before`.
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    filter: Option<Map<String, Value>>,
+}
+
+fn default_k() -> usize {
+    4
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Document {
+    id: String,
+    page_content: String,
+    #[schema(value_type = Object)]
+    metadata: Map<String, Value>,
+    /// L2 distance to the query; lower is more similar.
+    distance: f32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SimilaritySearchResponse {
+    documents: Vec<Document>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeleteRequest {
+    ids: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteResponse {
+    deleted: usize,
+}
+
+/// Build episodes for documents; `texts` and `metadatas` must match `embeddings` in length.
+fn to_episodes(
+    embeddings: Vec<Vec<f32>>,
+    texts: Option<Vec<String>>,
+    metadatas: Option<Vec<Map<String, Value>>>,
+    ids: Option<Vec<String>>,
+) -> Result<Vec<Episode>, ApiError> {
+    let n = embeddings.len();
+    for (name, len) in [
+        ("texts", texts.as_ref().map(Vec::len)),
+        ("metadatas", metadatas.as_ref().map(Vec::len)),
+        ("ids", ids.as_ref().map(Vec::len)),
+    ] {
+        if let Some(len) = len.filter(|&len| len != n) {
+            return Err(bad_request(format!(
+                "{name} has {len} entries but there are {n} documents"
+            )));
+        }
+    }
+    let mut texts = texts.map(Vec::into_iter);
+    let mut metadatas = metadatas.map(Vec::into_iter);
+    let mut ids = ids.map(Vec::into_iter);
+    let now = chrono::Utc::now().timestamp_millis();
+    Ok(embeddings
+        .into_iter()
+        .map(|embedding| {
+            let mut metadata = metadatas
+                .as_mut()
+                .and_then(Iterator::next)
+                .unwrap_or_default();
+            if let Some(text) = texts.as_mut().and_then(Iterator::next) {
+                metadata.insert("text".to_string(), Value::String(text));
+            }
+            let mut ep = Episode::new("", embedding, 1.0);
+            ep.task_id = ids
+                .as_mut()
+                .and_then(Iterator::next)
+                .unwrap_or_else(|| ep.id.to_string());
+            ep.tags = metadata.get("tags").and_then(|t| {
+                t.as_array()?
+                    .iter()
+                    .map(|t| t.as_str().map(String::from))
+                    .collect()
+            });
+            ep.source = metadata
+                .get("source")
+                .and_then(Value::as_str)
+                .map(String::from);
+            ep.user_id = metadata
+                .get("user_id")
+                .and_then(Value::as_str)
+                .map(String::from);
+            ep.session_id = metadata
+                .get("session_id")
+                .and_then(Value::as_str)
+                .map(String::from);
+            ep.embedding_model = metadata
+                .get("embedding_model")
+                .and_then(Value::as_str)
+                .map(String::from);
+            ep.timestamp = Some(
+                metadata
+                    .get("timestamp")
+                    .and_then(Value::as_i64)
+                    .unwrap_or(now),
+            );
+            ep.metadata = Value::Object(metadata);
+            ep
+        })
+        .collect())
+}
+
+/// Store documents. Caller-chosen ids replace any existing documents with the same ids; the
+/// old copies are removed only once the new ones are stored.
+async fn add(
+    state: &AppState,
+    tenant_id: &str,
+    episodes: Vec<Episode>,
+    caller_ids: bool,
+) -> Result<Json<AddResponse>, ApiError> {
+    let ids: Vec<String> = episodes.iter().map(|e| e.task_id.clone()).collect();
+    let stored: HashSet<Uuid> = episodes.iter().map(|e| e.id).collect();
+    let replaced: HashSet<&str> = ids.iter().map(String::as_str).collect();
+    if replaced.len() != ids.len() {
+        return Err(bad_request("ids must be unique".to_string()));
+    }
+    store_for_tenant(state, tenant_id, episodes).await?;
+    if caller_ids {
+        delete_for_tenant(state, tenant_id, "vectorstore_replace", |ep| {
+            replaced.contains(ep.task_id.as_str()) && !stored.contains(&ep.id)
+        })
+        .await?;
+    }
+    audit_log(
+        state,
+        tenant_id,
+        "vectorstore_add",
+        None,
+        Some(ids.len()),
+        None,
+    );
+    Ok(Json(AddResponse { ids }))
+}
+
+/// Add texts, embedded by the server (LangChain `add_texts`).
+#[utoipa::path(
+    post,
+    path = "/v1/vectorstore/add_texts",
+    tag = "vectorstore",
+    request_body = AddTextsRequest,
+    responses(
+        (status = 200, body = AddResponse),
+        (status = 400, description = "Mismatched lengths, or server-side embedding not configured", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `write` scope", body = openapi::ErrorBody),
+        (status = 502, description = "Embedding provider failed", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn add_texts(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Json(req): Json<AddTextsRequest>,
+) -> Result<Json<AddResponse>, ApiError> {
+    let inputs = req.texts.iter().map(|t| (None, Some(t.clone()))).collect();
+    let embeddings = embedding::resolve(state.embedder.as_deref(), inputs, "embeddings").await?;
+    let caller_ids = req.ids.is_some();
+    let mut episodes = to_episodes(embeddings, Some(req.texts), req.metadatas, req.ids)?;
+    let model = state.embedder.as_ref().map(|e| e.model().to_string());
+    for ep in &mut episodes {
+        ep.embedding_model = model.clone();
+    }
+    add(&state, &tenant_id, episodes, caller_ids).await
+}
+
+/// Add documents with precomputed embeddings (LangChain `add_embeddings`, LlamaIndex `add`).
+#[utoipa::path(
+    post,
+    path = "/v1/vectorstore/add_embeddings",
+    tag = "vectorstore",
+    request_body = AddEmbeddingsRequest,
+    responses(
+        (status = 200, body = AddResponse),
+        (status = 400, description = "Mismatched lengths or dimension", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `write` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn add_embeddings(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Json(req): Json<AddEmbeddingsRequest>,
+) -> Result<Json<AddResponse>, ApiError> {
+    let caller_ids = req.ids.is_some();
+    let episodes = to_episodes(req.embeddings, req.texts, req.metadatas, req.ids)?;
+    add(&state, &tenant_id, episodes, caller_ids).await
+}
+
+fn string_list(key: &str, value: &Value) -> Result<Vec<String>, ApiError> {
+    match value {
+        Value::String(s) => Ok(vec![s.clone()]),
+        Value::Array(items) => items
+            .iter()
+            .map(|v| v.as_str().map(String::from))
+            .collect::<Option<_>>()
+            .ok_or_else(|| bad_request(format!("filter.{key} must be a list of strings"))),
+        _ => Err(bad_request(format!(
+            "filter.{key} must be a list of strings"
+        ))),
+    }
+}
+
+fn query_options(k: usize, filter: Option<Map<String, Value>>) -> Result<QueryOptions, ApiError> {
+    let mut opts = QueryOptions::new(f32::MIN, k);
+    for (key, value) in filter.unwrap_or_default() {
+        let timestamp = || {
+            value
+                .as_i64()
+                .ok_or_else(|| bad_request(format!("filter.{key} must be Unix milliseconds")))
+        };
+        let string = || {
+            value
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| bad_request(format!("filter.{key} must be a string")))
+        };
+        opts = match key.as_str() {
+            "tags" | "tags_any" => opts.tags_any(string_list(&key, &value)?),
+            "tags_all" => opts.tags_all(string_list(&key, &value)?),
+            "source" => opts.source(string()?),
+            "user_id" => opts.user_id(string()?),
+            "session_id" => opts.session_id(string()?),
+            "embedding_model" => opts.embedding_model(string()?),
+            "time_after" => opts.time_after(timestamp()?),
+            "time_before" => opts.time_before(timestamp()?),
+            _ => {
+                return Err(bad_request(format!(
+                    "Unsupported filter key {key:?}; supported: {}",
+                    FILTER_KEYS.join(", ")
+                )))
+            }
+        };
+    }
+    Ok(opts)
+}
+
+fn l2_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Nearest documents to a query text or embedding (LangChain `similarity_search_with_score`,
+/// LlamaIndex `query`).
+#[utoipa::path(
+    post,
+    path = "/v1/vectorstore/similarity_search",
+    tag = "vectorstore",
+    request_body = SimilaritySearchRequest,
+    responses(
+        (status = 200, body = SimilaritySearchResponse),
+        (status = 400, description = "Invalid filter, or neither query nor embedding", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `read` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn similarity_search(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Json(req): Json<SimilaritySearchRequest>,
+) -> Result<Json<SimilaritySearchResponse>, ApiError> {
+    let opts = query_options(req.k, req.filter)?;
+    let query = embedding::resolve(
+        state.embedder.as_deref(),
+        vec![(req.embedding, req.query)],
+        "embedding",
+    )
+    .await?
+    .remove(0);
+    let episodes = match query_for_tenant(&state, &tenant_id, &query, opts).await {
+        Ok(results) => results.episodes,
+        Err((StatusCode::NOT_FOUND, _)) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let documents = episodes
+        .into_iter()
+        .map(|ep| {
+            let mut metadata = match ep.metadata {
+                Value::Object(m) => m,
+                _ => Map::new(),
+            };
+            let page_content = match metadata.remove("text") {
+                Some(Value::String(text)) => text,
+                Some(other) => other.to_string(),
+                None => String::new(),
+            };
+            Document {
+                id: ep.task_id,
+                page_content,
+                metadata,
+                distance: l2_distance(&query, &ep.state_embedding),
+            }
+        })
+        .collect();
+    Ok(Json(SimilaritySearchResponse { documents }))
+}
+
+/// Delete documents by id (LangChain `delete`, LlamaIndex `delete_nodes`).
+#[utoipa::path(
+    post,
+    path = "/v1/vectorstore/delete",
+    tag = "vectorstore",
+    request_body = DeleteRequest,
+    responses(
+        (status = 200, body = DeleteResponse),
+        (status = 403, description = "API key lacks the `prune` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn delete(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Json(req): Json<DeleteRequest>,
+) -> Result<Json<DeleteResponse>, ApiError> {
+    let ids: HashSet<&str> = req.ids.iter().map(String::as_str).collect();
+    let deleted = match delete_for_tenant(&state, &tenant_id, "vectorstore_delete", |ep| {
+        ids.contains(ep.task_id.as_str())
+    })
+    .await
+    {
+        Ok(deleted) => deleted,
+        Err((StatusCode::NOT_FOUND, _)) => 0,
+        Err(e) => return Err(e),
+    };
+    Ok(Json(DeleteResponse { deleted }))
+}
@@ -0,0 +1,594 @@
+//! Webhooks on memory events: `episode_count` (a tenant's episode count rises past a
+//! webhook's `episode_threshold`), `prune` (a prune, delete or retention run removed
+//! episodes) and `quota` (usage rises past `quota_warn_ratio` of `max_episodes` or
+//! `max_bytes`). Events fire once per crossing, not on every write above the line.
+//!
+//! Each delivery is a JSON `POST` retried with exponential backoff on network errors and
+//! non-2xx responses. Webhooks come from `webhooks.tenants.<id>` in the config and from the
+//! tenant's own registrations (`/v1/webhooks`, saved with the tenant settings).
+//!
+//! Tenant-registered webhooks can't target the server's own network: registration rejects
+//! hosts that resolve to loopback, private or link-local addresses, and deliveries resolve
+//! through a guard that drops them again (so a name can't be re-pointed inside later) and
+//! don't follow redirects. Hosts in `webhooks.allowed_hosts` are exempt, as are all
+//! operator-configured webhooks.
+
+use crate::config::{Webhook, WebhookEvent, WebhooksConfig};
+use crate::metrics::Metrics;
+use crate::{audit_log, openapi, ApiError, AppState};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::fmt::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Most webhooks a tenant can register through the API.
+const MAX_WEBHOOKS: usize = 20;
+
+/// A memory event, serialized as the payload's `event` and event-specific fields.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    EpisodeCount {
+        episodes: usize,
+        threshold: usize,
+    },
+    Prune {
+        op: String,
+        removed: usize,
+        episodes: usize,
+    },
+    Quota {
+        quota: &'static str,
+        used: u64,
+        limit: u64,
+    },
+}
+
+impl Event {
+    fn kind(&self) -> WebhookEvent {
+        match self {
+            Event::EpisodeCount { .. } => WebhookEvent::EpisodeCount,
+            Event::Prune { .. } => WebhookEvent::Prune,
+            Event::Quota { .. } => WebhookEvent::Quota,
+        }
+    }
+}
+
+/// A tenant's episode count and stored bytes, before and after a write.
+#[derive(Clone, Copy, Debug)]
+pub struct Usage {
+    pub episodes: usize,
+    pub bytes: u64,
+}
+
+/// Who added a webhook: the operator's config or the tenant through the API.
+#[derive(Clone, Copy, PartialEq)]
+enum Owner {
+    Operator,
+    Tenant,
+}
+
+/// Keeps tenant webhooks off loopback, private and link-local addresses, except for hosts
+/// in `webhooks.allowed_hosts`.
+#[derive(Clone)]
+struct Guard {
+    allowed_hosts: Arc<Vec<String>>,
+}
+
+impl Guard {
+    fn allows(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(host))
+    }
+
+    /// Check a webhook URL without DNS: `Ok(Some((host, port)))` for a name still to resolve,
+    /// `Ok(None)` for an allowed host or a public IP literal.
+    fn check_url(&self, url: &str) -> Result<Option<(String, u16)>, String> {
+        let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid url {url:?}: {e}"))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| format!("url {url:?} has no host"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        if self.allows(host) {
+            return Ok(None);
+        }
+        match host.parse::<IpAddr>() {
+            Ok(ip) if is_internal(ip) => Err(internal_target(host)),
+            Ok(_) => Ok(None),
+            Err(_) => Ok(Some((
+                host.to_string(),
+                parsed.port_or_known_default().unwrap_or(80),
+            ))),
+        }
+    }
+
+    /// Resolve `host`, failing if it isn't allowed and any address is internal.
+    async fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| format!("can't resolve {host}: {e}"))?
+            .collect();
+        if !self.allows(host) && addrs.iter().any(|a| is_internal(a.ip())) {
+            return Err(internal_target(host));
+        }
+        Ok(addrs)
+    }
+}
+
+/// The tenant client's resolver, so every delivery re-checks where a name points.
+impl reqwest::dns::Resolve for Guard {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let guard = self.clone();
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = guard.resolve(&host, 0).await?;
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+fn internal_target(host: &str) -> String {
+    format!(
+        "webhook host {host} is a loopback, private or link-local address; ask the operator to add it to webhooks.allowed_hosts"
+    )
+}
+
+/// Loopback, private, link-local, shared (100.64/10) and other non-public addresses.
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_internal(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+pub struct Webhooks {
+    config: WebhooksConfig,
+    client: reqwest::Client,
+    /// Delivers tenant-registered webhooks: resolves through the guard and doesn't follow
+    /// redirects.
+    tenant_client: reqwest::Client,
+    guard: Guard,
+}
+
+impl Webhooks {
+    pub fn new(config: &WebhooksConfig) -> Result<Self, String> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let guard = Guard {
+            allowed_hosts: Arc::new(config.allowed_hosts.clone()),
+        };
+        let tenant_client = reqwest::Client::builder()
+            .timeout(timeout)
+            .dns_resolver(Arc::new(guard.clone()))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            config: config.clone(),
+            client,
+            tenant_client,
+            guard,
+        })
+    }
+
+    /// Operator-configured webhooks followed by the tenant's own.
+    fn hooks(&self, state: &AppState, tenant_id: &str) -> Vec<(Webhook, Owner)> {
+        let operator = self
+            .config
+            .tenants
+            .get(tenant_id)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|hook| (hook, Owner::Operator));
+        let own = state
+            .tenant_settings
+            .webhooks(tenant_id)
+            .into_iter()
+            .map(|hook| (hook, Owner::Tenant));
+        operator.chain(own).collect()
+    }
+
+    /// Reject a URL a tenant registers if it targets the server's own network.
+    async fn check_target(&self, url: &str) -> Result<(), String> {
+        if let Some((host, port)) = self.guard.check_url(url)? {
+            self.guard.resolve(&host, port).await?;
+        }
+        Ok(())
+    }
+
+    /// Fire `episode_count` and `quota` events crossed by a store.
+    pub fn stored(&self, state: &AppState, tenant_id: &str, before: Usage, after: Usage) {
+        let hooks = self.hooks(state, tenant_id);
+        if hooks.is_empty() {
+            return;
+        }
+        let mut quota_events = Vec::new();
+        let ratio = self.config.quota_warn_ratio;
+        let quotas = state.quotas(tenant_id);
+        if let Some(max) = quotas.max_episodes {
+            let warn = (max as f64 * ratio).ceil() as usize;
+            if before.episodes < warn && after.episodes >= warn {
+                quota_events.push(Event::Quota {
+                    quota: "max_episodes",
+                    used: after.episodes as u64,
+                    limit: max as u64,
+                });
+            }
+        }
+        if let Some(max) = quotas.max_bytes {
+            let warn = (max as f64 * ratio).ceil() as u64;
+            if before.bytes < warn && after.bytes >= warn {
+                quota_events.push(Event::Quota {
+                    quota: "max_bytes",
+                    used: after.bytes,
+                    limit: max,
+                });
+            }
+        }
+        for (hook, owner) in &hooks {
+            if let Some(threshold) = hook.episode_threshold {
+                if before.episodes < threshold && after.episodes >= threshold {
+                    self.send(
+                        state,
+                        tenant_id,
+                        hook,
+                        *owner,
+                        &Event::EpisodeCount {
+                            episodes: after.episodes,
+                            threshold,
+                        },
+                    );
+                }
+            }
+            for event in &quota_events {
+                self.send(state, tenant_id, hook, *owner, event);
+            }
+        }
+    }
+
+    /// Fire `prune` for an operation that removed episodes.
+    pub fn pruned(
+        &self,
+        state: &AppState,
+        tenant_id: &str,
+        op: &str,
+        removed: usize,
+        episodes: usize,
+    ) {
+        if removed == 0 {
+            return;
+        }
+        let event = Event::Prune {
+            op: op.to_string(),
+            removed,
+            episodes,
+        };
+        for (hook, owner) in &self.hooks(state, tenant_id) {
+            self.send(state, tenant_id, hook, *owner, &event);
+        }
+    }
+
+    /// Queue a delivery to one webhook, if it subscribes to the event.
+    fn send(&self, state: &AppState, tenant_id: &str, hook: &Webhook, owner: Owner, event: &Event) {
+        if !hook.wants(event.kind()) {
+            return;
+        }
+        let client = match owner {
+            Owner::Operator => &self.client,
+            Owner::Tenant => {
+                // IP literals never reach the resolver, so check them here; registration
+                // rejects them too, but the allow-list may have shrunk since.
+                if let Err(e) = self.guard.check_url(&hook.url) {
+                    state
+                        .metrics
+                        .webhook_failures_total
+                        .fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!(url = %hook.url, error = %e, "webhook not delivered");
+                    return;
+                }
+                &self.tenant_client
+            }
+        };
+        let delivery_id = uuid::Uuid::new_v4().to_string();
+        let mut payload = serde_json::to_value(event).unwrap_or_default();
+        if let Some(fields) = payload.as_object_mut() {
+            fields.insert("tenant_id".into(), tenant_id.into());
+            fields.insert("delivery_id".into(), delivery_id.clone().into());
+            fields.insert("ts".into(), chrono::Utc::now().to_rfc3339().into());
+        }
+        let body = serde_json::to_vec(&payload).unwrap_or_default();
+        let mut request = client
+            .post(&hook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-AgentMem-Event", event_name(event.kind()))
+            .header("X-AgentMem-Delivery", &delivery_id);
+        if let Some(ref secret) = hook.secret {
+            request = request.header("X-AgentMem-Signature", sign(secret, &body));
+        }
+        let request = request.body(body);
+        let retries = self.config.max_retries;
+        let backoff = Duration::from_millis(self.config.initial_backoff_ms);
+        let metrics = state.metrics.clone();
+        let url = hook.url.clone();
+        tokio::spawn(deliver(
+            request,
+            retries,
+            backoff,
+            metrics,
+            url,
+            delivery_id,
+        ));
+    }
+}
+
+/// Send with retries; each retry waits twice as long as the previous one.
+async fn deliver(
+    request: reqwest::RequestBuilder,
+    retries: u32,
+    mut backoff: Duration,
+    metrics: Metrics,
+    url: String,
+    delivery_id: String,
+) {
+    let mut error = String::new();
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            metrics
+                .webhook_retries_total
+                .fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+        // Bodies are plain bytes, so the builder always clones.
+        let Some(request) = request.try_clone() else {
+            break;
+        };
+        match request.send().await {
+            Ok(res) if res.status().is_success() => {
+                metrics
+                    .webhook_deliveries_total
+                    .fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Ok(res) => error = format!("HTTP {}", res.status()),
+            Err(e) => error = e.to_string(),
+        }
+        tracing::debug!(url = %url, delivery_id = %delivery_id, attempt, error = %error, "webhook delivery failed");
+    }
+    metrics
+        .webhook_failures_total
+        .fetch_add(1, Ordering::Relaxed);
+    tracing::warn!(url = %url, delivery_id = %delivery_id, error = %error, "webhook dropped after retries");
+}
+
+fn event_name(event: WebhookEvent) -> &'static str {
+    match event {
+        WebhookEvent::EpisodeCount => "episode_count",
+        WebhookEvent::Prune => "prune",
+        WebhookEvent::Quota => "quota",
+    }
+}
+
+/// `sha256=<hex HMAC-SHA256 of body>`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    let mut out = String::from("sha256=");
+    for byte in mac.finalize().into_bytes() {
+        let _ = write!(out, "{byte:02x}");
+    }
+    out
+}
+
+/// A registered webhook as listed by the API (the secret is never returned).
+#[derive(Serialize, ToSchema)]
+pub struct WebhookView {
+    id: String,
+    url: String,
+    events: Vec<WebhookEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    episode_threshold: Option<usize>,
+    has_secret: bool,
+    /// `tenant` (registered via the API, deletable) or `operator` (server config).
+    source: &'static str,
+}
+
+impl WebhookView {
+    fn new(hook: Webhook, source: &'static str) -> Self {
+        Self {
+            has_secret: hook.secret.is_some(),
+            id: hook.id,
+            url: hook.url,
+            events: hook.events,
+            episode_threshold: hook.episode_threshold,
+            source,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookList {
+    webhooks: Vec<WebhookView>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteWebhookResponse {
+    deleted: String,
+}
+
+fn not_found() -> ApiError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({"error": "No such webhook"})),
+    )
+}
+
+fn internal(e: String) -> ApiError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({"error": e})),
+    )
+}
+
+/// List the tenant's webhooks.
+#[utoipa::path(
+    get,
+    path = "/v1/webhooks",
+    tag = "admin",
+    responses(
+        (status = 200, body = WebhookList),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn list(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+) -> Json<WebhookList> {
+    let operator = state
+        .webhooks
+        .config
+        .tenants
+        .get(&tenant_id)
+        .cloned()
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut hook)| {
+            if hook.id.is_empty() {
+                hook.id = format!("operator-{i}");
+            }
+            WebhookView::new(hook, "operator")
+        });
+    let own = state
+        .tenant_settings
+        .webhooks(&tenant_id)
+        .into_iter()
+        .map(|hook| WebhookView::new(hook, "tenant"));
+    Json(WebhookList {
+        webhooks: operator.chain(own).collect(),
+    })
+}
+
+/// Register a webhook for the tenant. The `id` is assigned by the server.
+#[utoipa::path(
+    post,
+    path = "/v1/webhooks",
+    tag = "admin",
+    request_body = Webhook,
+    responses(
+        (status = 200, body = WebhookView),
+        (status = 400, description = "Invalid webhook or too many webhooks", body = openapi::ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn create(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Json(mut hook): Json<Webhook>,
+) -> Result<Json<WebhookView>, ApiError> {
+    let bad_request = |e: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": e})),
+        )
+    };
+    hook.validate().map_err(bad_request)?;
+    state
+        .webhooks
+        .check_target(&hook.url)
+        .await
+        .map_err(bad_request)?;
+    hook.id = uuid::Uuid::new_v4().to_string();
+    let added = hook.clone();
+    let created = state
+        .tenant_settings
+        .update_webhooks(&tenant_id, |hooks| {
+            if hooks.len() >= MAX_WEBHOOKS {
+                return false;
+            }
+            hooks.push(added);
+            true
+        })
+        .map_err(internal)?;
+    if !created {
+        return Err(bad_request(format!(
+            "A tenant can register at most {MAX_WEBHOOKS} webhooks"
+        )));
+    }
+    audit_log(&state, &tenant_id, "webhook_create", None, None, None);
+    Ok(Json(WebhookView::new(hook, "tenant")))
+}
+
+/// Remove one of the tenant's webhooks. Operator-configured webhooks can't be removed.
+#[utoipa::path(
+    delete,
+    path = "/v1/webhooks/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "Webhook id")),
+    responses(
+        (status = 200, body = DeleteWebhookResponse),
+        (status = 401, description = "Missing or invalid API key", body = openapi::ErrorBody),
+        (status = 403, description = "API key lacks the `admin` scope", body = openapi::ErrorBody),
+        (status = 404, description = "No such webhook", body = openapi::ErrorBody),
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub async fn delete(
+    State(state): State<AppState>,
+    Extension(tenant_id): Extension<String>,
+    Path(id): Path<String>,
+) -> Result<Json<DeleteWebhookResponse>, ApiError> {
+    let deleted = state
+        .tenant_settings
+        .update_webhooks(&tenant_id, |hooks| {
+            let before = hooks.len();
+            hooks.retain(|h| h.id != id);
+            hooks.len() != before
+        })
+        .map_err(internal)?;
+    if !deleted {
+        return Err(not_found());
+    }
+    audit_log(&state, &tenant_id, "webhook_delete", None, None, None);
+    Ok(Json(DeleteWebhookResponse { deleted: id }))
+}
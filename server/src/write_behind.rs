@@ -0,0 +1,125 @@
+//! Write-behind persistence for in-memory tenants (`write_behind.dir`): changed tenants are saved every `interval_secs` and on shutdown, and reloaded
+//! on boot. Writes since the last save are lost on a crash, in exchange for no per-write I/O.
+//!
+//! Each tenant is one `<dir>/<sanitized tenant>.json` file holding its real id, dim and
+//! episodes, replaced atomically (temp file + rename) on every save.
+
+use crate::tenant_settings::Settings;
+use crate::{new_memory_db, AppState, Tenant, TenantStore};
+use agent_mem_db::{tenant_dir_name, AgentMemDB, Episode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+#[derive(Serialize, Deserialize)]
+struct TenantFile {
+    tenant_id: String,
+    dim: usize,
+    episodes: Vec<Episode>,
+}
+
+pub struct WriteBehind {
+    dir: PathBuf,
+    /// Serializes saves, so a shutdown flush can't race a periodic one.
+    saving: Mutex<()>,
+}
+
+impl WriteBehind {
+    pub fn new(dir: PathBuf) -> Result<Self, String> {
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        Ok(Self {
+            dir,
+            saving: Mutex::new(()),
+        })
+    }
+
+    fn tenant_path(&self, tenant_id: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.json", tenant_dir_name(tenant_id)))
+    }
+
+    /// Load every saved tenant, indexed per its current settings.
+    pub fn restore(&self, settings: &Settings) -> Result<HashMap<String, Tenant>, String> {
+        let mut tenants = HashMap::new();
+        let entries =
+            std::fs::read_dir(&self.dir).map_err(|e| format!("{}: {e}", self.dir.display()))?;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let (tenant_id, db) =
+                load(&path, settings).map_err(|e| format!("{}: {e}", path.display()))?;
+            tenants.insert(tenant_id, Tenant::new(TenantStore::Memory(db)));
+        }
+        Ok(tenants)
+    }
+
+    /// Save every in-memory tenant changed since its last save. Episodes are copied under the
+    /// tenants lock and written outside it. Returns the number of tenants saved.
+    pub async fn flush(&self, state: &AppState) -> usize {
+        let _saving = self.saving.lock().await;
+        let pending: Vec<TenantFile> = {
+            let mut tenants = state.tenants.write().await;
+            tenants
+                .iter_mut()
+                .filter(|(_, t)| t.dirty)
+                .filter_map(|(id, t)| match t.backend {
+                    TenantStore::Memory(ref db) => {
+                        t.dirty = false;
+                        Some(TenantFile {
+                            tenant_id: id.to_string(),
+                            dim: db.dim(),
+                            episodes: db.iter().cloned().collect(),
+                        })
+                    }
+                    TenantStore::Disk(_) => None,
+                })
+                .collect()
+        };
+        let mut saved = 0;
+        for file in pending {
+            let path = self.tenant_path(&file.tenant_id);
+            let tenant_id = file.tenant_id.clone();
+            let result = tokio::task::spawn_blocking(move || write_atomic(&path, &file))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|r| r);
+            match result {
+                Ok(()) => saved += 1,
+                Err(e) => {
+                    tracing::error!(tenant_id = %tenant_id, error = %e, "write-behind save failed");
+                    // Retry on the next flush.
+                    if let Some(t) = state.tenants.write().await.loaded_mut(&tenant_id) {
+                        t.dirty = true;
+                    }
+                }
+            }
+        }
+        if saved > 0 {
+            tracing::debug!(tenants = saved, "write-behind saved tenants");
+        }
+        saved
+    }
+}
+
+fn load(path: &Path, settings: &Settings) -> Result<(String, AgentMemDB), String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let saved: TenantFile =
+        serde_json::from_reader(std::io::BufReader::new(file)).map_err(|e| e.to_string())?;
+    let mut db = new_memory_db(&settings.resolve(&saved.tenant_id), saved.dim);
+    db.store_episodes(saved.episodes)
+        .map_err(|e| e.to_string())?;
+    Ok((saved.tenant_id, db))
+}
+
+fn write_atomic(path: &Path, file: &TenantFile) -> Result<(), String> {
+    let tmp = path.with_extension("json.tmp");
+    let mut out = std::io::BufWriter::new(std::fs::File::create(&tmp).map_err(|e| e.to_string())?);
+    serde_json::to_writer(&mut out, file).map_err(|e| e.to_string())?;
+    out.flush().map_err(|e| e.to_string())?;
+    out.get_ref().sync_all().map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
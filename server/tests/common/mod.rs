@@ -0,0 +1,104 @@
+//! Helpers for tests that run the server binary and talk to it over HTTP.
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const KEYS: &str = "k-acme:acme";
+
+/// Kills the server when the test ends, pass or fail.
+pub struct Server {
+    child: Child,
+    pub port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+pub fn spawn(env: &[(&str, &str)]) -> Server {
+    let port = free_port();
+    let child = Command::new(env!("CARGO_BIN_EXE_agent-mem-server"))
+        .env("AGENT_MEM_BIND", format!("127.0.0.1:{port}"))
+        .env("AGENT_MEM_DIM", "2")
+        .env("AGENT_MEM_API_KEYS", KEYS)
+        .envs(env.iter().copied())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let server = Server { child, port };
+    wait_for(|| request(&server, "GET", "/livez", None).is_some_and(|(s, _)| s == 200));
+    server
+}
+
+/// One HTTP/1.0 request (so the body is never chunked) as tenant `acme`; `None` if the server isn't reachable.
+pub fn request(
+    server: &Server,
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+) -> Option<(u16, String)> {
+    request_as(server, "k-acme", method, path, body)
+}
+
+/// [`request`] with another API key.
+pub fn request_as(
+    server: &Server,
+    key: &str,
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+) -> Option<(u16, String)> {
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port)).ok()?;
+    let body = body.unwrap_or("");
+    let head = format!(
+        "{method} {path} HTTP/1.0\r\nHost: localhost\r\nAuthorization: Bearer {key}\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).ok()?;
+    stream.write_all(body.as_bytes()).ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    let status = response.split(' ').nth(1)?.parse().ok()?;
+    let body = response
+        .split_once("\r\n\r\n")
+        .map(|(_, b)| b.to_string())?;
+    Some((status, body))
+}
+
+pub fn json(server: &Server, method: &str, path: &str, body: &str) -> serde_json::Value {
+    let (status, text) = request(server, method, path, Some(body)).unwrap();
+    assert_eq!(status, 200, "{method} {path}: {text}");
+    serde_json::from_str(&text).unwrap()
+}
+
+pub fn wait_for(mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+pub fn store(server: &Server, task: &str) -> String {
+    let body = format!(r#"{{"task_id":"{task}","state_embedding":[1.0,0.0],"reward":1.0}}"#);
+    json(server, "POST", "/v1/episodes", &body)["id"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
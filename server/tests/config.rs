@@ -0,0 +1,60 @@
+use std::process::Command;
+
+/// Runs the server binary with `args` and a config file `name`.toml holding `toml`.
+fn run(name: &str, toml: &str, args: &[&str]) -> std::process::Output {
+    let path = std::env::temp_dir().join(format!("agent_mem_server_config_{name}.toml"));
+    std::fs::write(&path, toml).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_agent-mem-server"))
+        .arg("--config")
+        .arg(&path)
+        .args(args)
+        .env_remove("AGENT_MEM_API_KEYS")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_print_config_redacts_webhook_secrets() {
+    let output = run(
+        "webhook_secrets",
+        r#"
+[[webhooks.tenants.acme]]
+url = "https://hooks.example.com/agent-mem"
+secret = "whsec-do-not-print"
+"#,
+        &["--print-config"],
+    );
+    assert!(output.status.success());
+    let printed = String::from_utf8(output.stdout).unwrap();
+    assert!(printed.contains("hooks.example.com"), "{printed}");
+    assert!(printed.contains("<redacted>"), "{printed}");
+    assert!(!printed.contains("whsec-do-not-print"), "{printed}");
+}
+
+#[test]
+fn test_invalid_configs_rejected() {
+    for (name, toml, error) in [
+        ("zero_dim", "dim = 0", "dim must be greater than 0"),
+        (
+            "disk_without_data_dir",
+            "[tenant_defaults]\nbackend = \"disk\"",
+            "requires data_dir",
+        ),
+        (
+            "bad_scope",
+            "[auth]\napi_keys = [\"k:acme:read+sudo\"]",
+            "auth.api_keys",
+        ),
+        (
+            "keys_mode_without_keys",
+            "[auth]\nmode = \"keys\"",
+            "no API keys are configured",
+        ),
+        ("unknown_field", "dimension = 8", "dimension"),
+    ] {
+        let output = run(name, toml, &[]);
+        assert_eq!(output.status.code(), Some(2), "{name}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(error), "{name}: {stderr}");
+    }
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::{request, request_as, spawn, store};
+
+#[test]
+fn test_dashboard_tenant_table_needs_a_key() {
+    let server = spawn(&[
+        (
+            "AGENT_MEM_API_KEYS",
+            "k-acme:acme,k-globex:globex,k-ro:acme:read",
+        ),
+        ("AGENT_MEM_USAGE_TOKEN", "usage-secret"),
+    ]);
+    store(&server, "a");
+    let body = r#"{"task_id":"g","state_embedding":[1.0,0.0],"reward":1.0}"#;
+    let (status, _) = request_as(&server, "k-globex", "POST", "/v1/episodes", Some(body)).unwrap();
+    assert_eq!(status, 200);
+
+    // The public page shows totals only.
+    let (status, page) = request(&server, "GET", "/dashboard", None).unwrap();
+    assert_eq!(status, 200);
+    assert!(page.contains("<span>Loaded tenants</span><span>2</span>"));
+    assert!(!page.contains("globex") && !page.contains("acme"));
+
+    let tenants = |key: &str| {
+        let (status, text) = request_as(&server, key, "GET", "/dashboard/tenants", None).unwrap();
+        let ids: Vec<String> = serde_json::from_str::<serde_json::Value>(&text)
+            .ok()
+            .and_then(|v| v["tenants"].as_array().cloned())
+            .unwrap_or_default()
+            .iter()
+            .map(|t| t["tenant_id"].as_str().unwrap().to_string())
+            .collect();
+        (status, ids)
+    };
+    assert_eq!(tenants("k-acme"), (200, vec!["acme".to_string()]));
+    assert_eq!(tenants("k-ro").0, 403);
+    assert_eq!(tenants("nope").0, 401);
+    let (status, mut all) = tenants("usage-secret");
+    all.sort();
+    assert_eq!((status, all), (200, vec!["acme".into(), "globex".into()]));
+}
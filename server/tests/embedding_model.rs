@@ -0,0 +1,112 @@
+mod common;
+
+use common::{json, spawn};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+
+/// A stand-in embedding provider that embeds every input as `[1.0, 0.0]`.
+fn fake_provider() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = v.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let data: Vec<_> = (0..request["input"].as_array().unwrap().len())
+                .map(|index| serde_json::json!({"embedding": [1.0, 0.0], "index": index}))
+                .collect();
+            let body = serde_json::json!({ "data": data }).to_string();
+            let _ = write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+    url
+}
+
+fn tasks(response: &serde_json::Value) -> Vec<&str> {
+    response["episodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|ep| ep["task_id"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_store_and_query_by_embedding_model() {
+    let server = spawn(&[]);
+    json(
+        &server,
+        "POST",
+        "/v1/episodes",
+        r#"{"task_id":"old","state_embedding":[1.0,0.0],"reward":1.0,"embedding_model":"v1"}"#,
+    );
+    json(
+        &server,
+        "POST",
+        "/v1/episodes/batch",
+        r#"{"episodes":[
+            {"task_id":"new","state_embedding":[0.9,0.1],"reward":1.0,"embedding_model":"v2"},
+            {"task_id":"untagged","state_embedding":[1.0,0.1],"reward":1.0}
+        ]}"#,
+    );
+
+    let all = json(
+        &server,
+        "POST",
+        "/v1/query",
+        r#"{"query_embedding":[1.0,0.0],"top_k":5}"#,
+    );
+    assert_eq!(tasks(&all).len(), 3);
+    let v2 = json(
+        &server,
+        "POST",
+        "/v1/query",
+        r#"{"query_embedding":[1.0,0.0],"top_k":5,"embedding_model":"v2"}"#,
+    );
+    assert_eq!(tasks(&v2), vec!["new"]);
+    assert_eq!(v2["episodes"][0]["embedding_model"], "v2");
+}
+
+#[test]
+fn test_server_embedded_episodes_tagged_with_model() {
+    let url = fake_provider();
+    let server = spawn(&[
+        ("AGENT_MEM_EMBEDDING_URL", url.as_str()),
+        ("AGENT_MEM_EMBEDDING_MODEL", "fake-v1"),
+    ]);
+    // The server's model wins over one sent alongside text.
+    json(
+        &server,
+        "POST",
+        "/v1/episodes",
+        r#"{"task_id":"text","text":"hello","reward":1.0,"embedding_model":"other"}"#,
+    );
+    json(
+        &server,
+        "POST",
+        "/v1/episodes",
+        r#"{"task_id":"vector","state_embedding":[1.0,0.0],"reward":1.0}"#,
+    );
+    let hits = json(
+        &server,
+        "POST",
+        "/v1/query",
+        r#"{"text":"hi","top_k":5,"embedding_model":"fake-v1"}"#,
+    );
+    assert_eq!(tasks(&hits), vec!["text"]);
+}
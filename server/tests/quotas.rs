@@ -0,0 +1,36 @@
+mod common;
+
+use common::{json, request, spawn, store};
+
+#[test]
+fn test_store_over_max_episodes_is_429() {
+    let server = spawn(&[("AGENT_MEM_QUOTA_MAX_EPISODES", "2")]);
+    store(&server, "a");
+    store(&server, "b");
+    let body = r#"{"task_id":"c","state_embedding":[1.0,0.0],"reward":1.0}"#;
+    let (status, text) = request(&server, "POST", "/v1/episodes", Some(body)).unwrap();
+    assert_eq!(status, 429, "{text}");
+    let error: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(error["quota"], "max_episodes");
+    assert_eq!(error["limit"], 2);
+    assert_eq!(json(&server, "GET", "/v1/stats", "")["episodes"], 2);
+}
+
+#[test]
+fn test_update_checked_against_max_bytes() {
+    let server = spawn(&[("AGENT_MEM_QUOTA_MAX_BYTES", "1000")]);
+    let id = store(&server, "a");
+    let path = format!("/v1/episodes/{id}");
+    let padding = "x".repeat(2000);
+    let body = format!(
+        r#"{{"expected_version":0,"task_id":"a","state_embedding":[1.0,0.0],"reward":1.0,"metadata":{{"notes":"{padding}"}}}}"#
+    );
+    let (status, text) = request(&server, "PUT", &path, Some(&body)).unwrap();
+    assert_eq!(status, 429, "{text}");
+    assert!(text.contains("max_bytes"), "{text}");
+
+    let body = r#"{"expected_version":0,"task_id":"a","state_embedding":[0.0,1.0],"reward":0.5}"#;
+    json(&server, "PUT", &path, body);
+    let stats = json(&server, "GET", "/v1/stats", "");
+    assert!(stats["stored_bytes"].as_u64().unwrap() <= 1000, "{stats}");
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::{json, request, spawn, store, wait_for, Server};
+
+const TOKEN: &str = "repl-secret";
+
+fn episodes(server: &Server) -> Option<u64> {
+    let (status, text) = request(server, "GET", "/v1/stats", None)?;
+    if status != 200 {
+        return None;
+    }
+    serde_json::from_str::<serde_json::Value>(&text).ok()?["episodes"].as_u64()
+}
+
+#[test]
+fn test_follower_resyncs_after_vacuum() {
+    let root = std::env::temp_dir().join("agent_mem_server_replication_vacuum");
+    let _ = std::fs::remove_dir_all(&root);
+    let leader_dir = root.join("leader");
+    let follower_dir = root.join("follower");
+    let leader = spawn(&[
+        ("AGENT_MEM_DATA_DIR", leader_dir.to_str().unwrap()),
+        ("AGENT_MEM_REPLICATION_TOKEN", TOKEN),
+    ]);
+    let leader_url = format!("http://127.0.0.1:{}", leader.port);
+    let follower = spawn(&[
+        ("AGENT_MEM_DATA_DIR", follower_dir.to_str().unwrap()),
+        ("AGENT_MEM_REPLICATION_TOKEN", TOKEN),
+        ("AGENT_MEM_REPLICATION_LEADER", leader_url.as_str()),
+        ("AGENT_MEM_REPLICATION_POLL_MS", "50"),
+    ]);
+
+    // An update leaves a superseded record for vacuum to drop.
+    let id = store(&leader, "first");
+    json(
+        &leader,
+        "PUT",
+        &format!("/v1/episodes/{id}"),
+        r#"{"expected_version":0,"task_id":"first","state_embedding":[0.0,1.0],"reward":0.5}"#,
+    );
+    wait_for(|| episodes(&follower) == Some(1));
+
+    let report = json(&leader, "POST", "/v1/vacuum", "{}");
+    assert_eq!(report["records_removed"], 1);
+    // Grow the shorter log past the follower's old offset.
+    for i in 0..5 {
+        store(&leader, &format!("after-{i}"));
+    }
+    wait_for(|| episodes(&follower) == Some(6));
+
+    drop(follower);
+    drop(leader);
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use common::{json, request_as, spawn, store};
+
+const KEYS: &str = "k-acme:acme,k-read:acme:read";
+
+#[test]
+fn test_read_only_key_cannot_store_or_prune() {
+    let server = spawn(&[("AGENT_MEM_API_KEYS", KEYS)]);
+    store(&server, "a");
+
+    let body = r#"{"task_id":"b","state_embedding":[1.0,0.0],"reward":1.0}"#;
+    let (status, text) = request_as(&server, "k-read", "POST", "/v1/episodes", Some(body)).unwrap();
+    assert_eq!(status, 403, "{text}");
+    for (path, body) in [
+        ("/v1/prune/older-than", r#"{"timestamp":0}"#),
+        ("/v1/prune/keep-newest", r#"{"n":0}"#),
+        ("/v1/prune/keep-highest-reward", r#"{"n":0}"#),
+        ("/v1/prune/session", r#"{"session_id":"s"}"#),
+    ] {
+        let (status, text) = request_as(&server, "k-read", "POST", path, Some(body)).unwrap();
+        assert_eq!(status, 403, "{path}: {text}");
+    }
+
+    // Reads still work, and nothing was removed.
+    let query = r#"{"query_embedding":[1.0,0.0],"top_k":5}"#;
+    let (status, text) = request_as(&server, "k-read", "POST", "/v1/query", Some(query)).unwrap();
+    assert_eq!(status, 200, "{text}");
+    let stats = json(&server, "GET", "/v1/stats", "");
+    assert_eq!(stats["episodes"], 1);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::{json, request, spawn, store, wait_for};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+
+/// A webhook receiver that answers 200 and forwards each payload.
+fn receiver() -> (u16, mpsc::Receiver<serde_json::Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = v.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let _ = reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            let _ = tx.send(serde_json::from_slice(&body).unwrap());
+        }
+    });
+    (port, rx)
+}
+
+#[test]
+fn test_tenant_webhooks_rejected_on_internal_addresses() {
+    let server = spawn(&[]);
+    for url in [
+        "http://127.0.0.1:9/hook",
+        "http://localhost:9/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://10.0.0.1/hook",
+        "http://[::1]:9/hook",
+        "http://[::ffff:192.168.0.1]/hook",
+    ] {
+        let body = format!(r#"{{"url":"{url}"}}"#);
+        let (status, text) = request(&server, "POST", "/v1/webhooks", Some(&body)).unwrap();
+        assert_eq!(status, 400, "{url}: {text}");
+        assert!(text.contains("allowed_hosts"), "{url}: {text}");
+    }
+    let listed = json(&server, "GET", "/v1/webhooks", "");
+    assert!(listed["webhooks"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_allowed_host_receives_tenant_webhook() {
+    let (port, payloads) = receiver();
+    let server = spawn(&[("AGENT_MEM_WEBHOOK_ALLOWED_HOSTS", "localhost")]);
+    let body = format!(r#"{{"url":"http://localhost:{port}/hook","events":["prune"]}}"#);
+    json(&server, "POST", "/v1/webhooks", &body);
+    // Only the named host is exempt, not every loopback address.
+    let body = format!(r#"{{"url":"http://127.0.0.1:{port}/hook"}}"#);
+    let (status, _) = request(&server, "POST", "/v1/webhooks", Some(&body)).unwrap();
+    assert_eq!(status, 400);
+
+    store(&server, "a");
+    store(&server, "b");
+    json(&server, "POST", "/v1/prune/keep-newest", r#"{"n":1}"#);
+    let mut payload = None;
+    wait_for(|| {
+        payload = payloads.try_recv().ok();
+        payload.is_some()
+    });
+    let payload = payload.unwrap();
+    assert_eq!(payload["event"], "prune");
+    assert_eq!(payload["removed"], 1);
+}
@@ -0,0 +1,270 @@
+//! Access statistics: how often and how recently queries return each episode, for
+//! usage-based forgetting and for finding the memories that actually get used.
+//!
+//! Queries only borrow the DB, so they count retrievals beside the stored episodes; the counts
+//! are folded into the episodes' `times_retrieved` and `last_retrieved_at` when the DB is next
+//! modified or saved. Episodes a query returns by copy are always up to date.
+
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode, PruneRule};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// How often and when queries returned an episode (its `times_retrieved` and
+/// `last_retrieved_at`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessStats {
+    pub times_retrieved: u64,
+    /// Unix ms.
+    pub last_retrieved_at: Option<i64>,
+}
+
+impl AccessStats {
+    /// The stats recorded on `episode`.
+    pub fn of(episode: &Episode) -> Self {
+        Self {
+            times_retrieved: episode.times_retrieved,
+            last_retrieved_at: episode.last_retrieved_at,
+        }
+    }
+
+    /// `self` plus the retrievals in `more`.
+    fn plus(self, more: AccessStats) -> Self {
+        Self {
+            times_retrieved: self.times_retrieved.saturating_add(more.times_retrieved),
+            last_retrieved_at: self.last_retrieved_at.max(more.last_retrieved_at),
+        }
+    }
+
+    /// The later of two records of the same retrievals, e.g. a disk DB's log line and its
+    /// `access.json`.
+    pub(crate) fn latest(self, other: AccessStats) -> Self {
+        Self {
+            times_retrieved: self.times_retrieved.max(other.times_retrieved),
+            last_retrieved_at: self.last_retrieved_at.max(other.last_retrieved_at),
+        }
+    }
+
+    pub(crate) fn set_on(self, episode: &mut Episode) {
+        episode.times_retrieved = self.times_retrieved;
+        episode.last_retrieved_at = self.last_retrieved_at;
+    }
+}
+
+/// Access totals over a DB's episodes (see `AgentMemDB::access_summary`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct AccessSummary {
+    /// `times_retrieved` summed over the stored episodes.
+    pub total_retrievals: u64,
+    /// Stored episodes no query has returned.
+    pub never_retrieved: usize,
+}
+
+/// A disk DB's `access.json`: the stats of every retrieved episode. The log only has them as
+/// of each episode's last write.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct AccessFile {
+    #[serde(default)]
+    pub format_version: u32,
+    pub episodes: HashMap<Uuid, AccessStats>,
+}
+
+/// Retrievals counted by queries and not yet folded into the stored episodes.
+#[derive(Debug, Default)]
+pub(crate) struct AccessTracker {
+    pending: Mutex<HashMap<Uuid, AccessStats>>,
+    /// Retrievals were folded in since the last `take_unsaved`.
+    unsaved: bool,
+}
+
+impl AccessTracker {
+    fn lock(&self) -> MutexGuard<'_, HashMap<Uuid, AccessStats>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Count a retrieval of each of `hits` now, and bring the copies' stats up to date.
+    pub(crate) fn record(&self, hits: &mut [Episode]) {
+        let now = now_ms();
+        let mut pending = self.lock();
+        for ep in hits {
+            let stats = pending.entry(ep.id).or_default();
+            stats.times_retrieved += 1;
+            stats.last_retrieved_at = Some(now);
+            AccessStats::of(ep).plus(*stats).set_on(ep);
+        }
+    }
+
+    /// Count a retrieval of each of `hits`, stored episodes that are left as they are.
+    pub(crate) fn record_shared(&self, hits: &[Arc<Episode>]) {
+        let now = now_ms();
+        let mut pending = self.lock();
+        for ep in hits {
+            let stats = pending.entry(ep.id).or_default();
+            stats.times_retrieved += 1;
+            stats.last_retrieved_at = Some(now);
+        }
+    }
+
+    /// Stored episode `episode`'s stats, with retrievals not yet folded in.
+    pub(crate) fn stats(&self, episode: &Episode) -> AccessStats {
+        let stats = AccessStats::of(episode);
+        match self.lock().get(&episode.id) {
+            Some(pending) => stats.plus(*pending),
+            None => stats,
+        }
+    }
+
+    /// Bring copies of stored episodes up to date.
+    pub(crate) fn fill(&self, episodes: &mut [Episode]) {
+        let pending = self.lock();
+        for ep in episodes {
+            if let Some(more) = pending.get(&ep.id) {
+                AccessStats::of(ep).plus(*more).set_on(ep);
+            }
+        }
+    }
+
+    /// Fold pending retrievals into the stored `episodes`; those of episodes no longer stored
+    /// are dropped.
+    pub(crate) fn fold(&mut self, episodes: &mut HashMap<Uuid, Arc<Episode>>) {
+        let pending = self.pending.get_mut().unwrap_or_else(|e| e.into_inner());
+        if pending.is_empty() {
+            return;
+        }
+        for (id, more) in pending.drain() {
+            if let Some(ep) = episodes.get_mut(&id) {
+                let ep = Arc::make_mut(ep);
+                AccessStats::of(ep).plus(more).set_on(ep);
+            }
+        }
+        self.unsaved = true;
+    }
+
+    /// Totals over the stored `episodes`, with retrievals not yet folded in.
+    pub(crate) fn summarize<'a>(
+        &self,
+        episodes: impl Iterator<Item = &'a Episode>,
+    ) -> AccessSummary {
+        let pending = self.lock();
+        episodes.fold(AccessSummary::default(), |mut summary, ep| {
+            let times = ep.times_retrieved + pending.get(&ep.id).map_or(0, |s| s.times_retrieved);
+            summary.total_retrievals = summary.total_retrievals.saturating_add(times);
+            summary.never_retrieved += usize::from(times == 0);
+            summary
+        })
+    }
+
+    /// Whether retrievals were folded in since the last call.
+    pub(crate) fn take_unsaved(&mut self) -> bool {
+        std::mem::take(&mut self.unsaved)
+    }
+
+    /// Undo `take_unsaved` after a failed save.
+    pub(crate) fn mark_unsaved(&mut self) {
+        self.unsaved = true;
+    }
+}
+
+pub(crate) fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// When the episode was last of use: its last retrieval, or when it was stored if never
+/// retrieved.
+pub(crate) fn last_used(episode: &Episode) -> Option<i64> {
+    episode.last_retrieved_at.or(episode.timestamp)
+}
+
+/// Ids of all but the `n` most retrieved episodes. Ties: more recently retrieved first, then
+/// newer (by timestamp); episodes without either sort last.
+pub(crate) fn beyond_most_retrieved<'a>(
+    episodes: impl Iterator<Item = &'a Episode>,
+    n: usize,
+) -> HashSet<Uuid> {
+    let mut ranked: Vec<&Episode> = episodes.collect();
+    ranked.sort_by(|a, b| {
+        b.times_retrieved
+            .cmp(&a.times_retrieved)
+            .then(b.last_retrieved_at.cmp(&a.last_retrieved_at))
+            .then(b.timestamp.cmp(&a.timestamp))
+    });
+    ranked.into_iter().skip(n).map(|ep| ep.id).collect()
+}
+
+impl AgentMemDB {
+    /// How often and when queries returned episode `id`, counting queries made up to now.
+    /// `None` for an unknown id. Only queries on the DB itself count: `explain_query` and
+    /// queries on a `clone_snapshot` don't.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// let ep = Episode::new("t", vec![0.0, 1.0], 1.0);
+    /// db.store_episode(ep.clone()).unwrap();
+    /// let hits = db.query_similar(&[0.0, 1.0], 0.0, 5).unwrap();
+    /// assert_eq!(hits[0].times_retrieved, 1);
+    /// db.query_similar(&[0.0, 1.0], 0.0, 5).unwrap();
+    /// assert_eq!(db.access_stats(ep.id).unwrap().times_retrieved, 2);
+    /// ```
+    pub fn access_stats(&self, id: Uuid) -> Option<AccessStats> {
+        self.episodes.get(&id).map(|ep| self.access.stats(ep))
+    }
+
+    /// Total retrievals and never-retrieved episodes over the DB.
+    pub fn access_summary(&self) -> AccessSummary {
+        self.access.summarize(self.iter())
+    }
+
+    /// Usage-based forgetting: remove episodes not retrieved since `cutoff_ms` (Unix ms).
+    /// Episodes never retrieved go by their timestamp, and are kept if they have none.
+    /// Returns episodes removed.
+    pub fn prune_not_retrieved_since(&mut self, cutoff_ms: i64) -> Result<usize, AgentMemError> {
+        self.prune(&[PruneRule::NotRetrievedSince(cutoff_ms)])
+    }
+
+    /// Keep only the `n` most retrieved episodes; ties keep the more recently retrieved.
+    /// Returns episodes removed.
+    pub fn prune_keep_most_retrieved(&mut self, n: usize) -> Result<usize, AgentMemError> {
+        self.prune(&[PruneRule::KeepMostRetrieved(n)])
+    }
+
+    /// Write retrievals counted by queries into the stored episodes' `times_retrieved` and
+    /// `last_retrieved_at`, so `iter` shows them. Stores, deletes and saves do this first.
+    pub fn fold_access_stats(&mut self) {
+        self.access.fold(&mut self.episodes);
+    }
+}
+
+impl AgentMemDBDisk {
+    /// How often and when queries returned episode `id` (see `AgentMemDB::access_stats`).
+    pub fn access_stats(&self, id: Uuid) -> Option<AccessStats> {
+        self.episodes.get(&id).map(|ep| self.access.stats(ep))
+    }
+
+    /// Total retrievals and never-retrieved episodes over the DB.
+    pub fn access_summary(&self) -> AccessSummary {
+        self.access.summarize(self.iter())
+    }
+
+    /// Remove episodes not retrieved since `cutoff_ms` (see
+    /// `AgentMemDB::prune_not_retrieved_since`). Compacts the log.
+    pub fn prune_not_retrieved_since(&mut self, cutoff_ms: i64) -> Result<usize, AgentMemError> {
+        self.prune(&[PruneRule::NotRetrievedSince(cutoff_ms)])
+    }
+
+    /// Keep only the `n` most retrieved episodes (see
+    /// `AgentMemDB::prune_keep_most_retrieved`). Compacts the log.
+    pub fn prune_keep_most_retrieved(&mut self, n: usize) -> Result<usize, AgentMemError> {
+        self.prune(&[PruneRule::KeepMostRetrieved(n)])
+    }
+
+    /// Write retrievals counted by queries into the stored episodes, as for
+    /// `AgentMemDB::fold_access_stats`; `save_access_stats` also writes them to disk.
+    pub fn fold_access_stats(&mut self) {
+        self.access.fold(&mut self.episodes);
+    }
+}
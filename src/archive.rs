@@ -0,0 +1,250 @@
+//! Archiving pruned episodes: with an archive attached, prunes move the episodes they remove
+//! into cold storage instead of discarding them, and `query_archive` searches that history on
+//! demand.
+//!
+//! Only prunes archive (`prune_older_than`, `prune_keep_newest`, `prune_keep_highest_reward`,
+//! `prune_session`, `prune_not_retrieved_since`, `prune_keep_most_retrieved`). Deletes
+//! (`delete_where`, `purge_deleted`) remove data on request and are not archived.
+
+use crate::access::AccessTracker;
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, QueryOptions};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Cold storage for pruned episodes, e.g. a separate disk directory ([`DiskArchive`]) or an
+/// object store.
+pub trait EpisodeArchive: Send + Sync {
+    /// Embedding dimension of the archived episodes; a DB only accepts an archive whose
+    /// dimension matches its own.
+    fn dim(&self) -> usize;
+
+    /// Keep `episodes`. An episode archived again replaces its earlier copy.
+    fn archive(&self, episodes: Vec<Episode>) -> Result<(), AgentMemError>;
+
+    /// Archived episodes similar to `query_embedding`, as for `query_similar_with_options`.
+    fn query(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError>;
+
+    /// Number of archived episodes.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// An archive in its own `AgentMemDBDisk` directory.
+pub struct DiskArchive {
+    db: Mutex<AgentMemDBDisk>,
+}
+
+impl DiskArchive {
+    /// Open or create an archive at `path`.
+    pub fn open(path: impl AsRef<Path>, opts: DiskOptions) -> Result<Self, AgentMemError> {
+        Ok(Self {
+            db: Mutex::new(AgentMemDBDisk::open_with_options(path, opts)?),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, AgentMemDBDisk> {
+        self.db.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl EpisodeArchive for DiskArchive {
+    fn dim(&self) -> usize {
+        self.lock().dim()
+    }
+
+    fn archive(&self, episodes: Vec<Episode>) -> Result<(), AgentMemError> {
+        self.lock().store_episodes(episodes)
+    }
+
+    fn query(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        self.lock()
+            .query_similar_with_options(query_embedding, opts)
+    }
+
+    fn len(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+/// Fails with `DimensionMismatch` unless `archive` holds episodes of `dim` dimensions.
+fn check_dim(archive: Option<&Arc<dyn EpisodeArchive>>, dim: usize) -> Result<(), AgentMemError> {
+    match archive {
+        Some(archive) if archive.dim() != dim => Err(AgentMemError::DimensionMismatch {
+            expected: dim,
+            got: archive.dim(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Hand the stored `episodes` not in `kept` to `archive`, with their pending retrievals.
+pub(crate) fn archive_removed(
+    archive: &dyn EpisodeArchive,
+    episodes: &HashMap<Uuid, Arc<Episode>>,
+    kept: &HashMap<Uuid, Arc<Episode>>,
+    access: &AccessTracker,
+) -> Result<(), AgentMemError> {
+    let mut removed: Vec<Episode> = episodes
+        .values()
+        .filter(|ep| !kept.contains_key(&ep.id))
+        .map(|ep| (**ep).clone())
+        .collect();
+    access.fill(&mut removed);
+    archive.archive(removed)
+}
+
+fn query_archive(
+    archive: Option<&Arc<dyn EpisodeArchive>>,
+    dim: usize,
+    query_embedding: &[f32],
+    opts: QueryOptions,
+) -> Result<Vec<Episode>, AgentMemError> {
+    if query_embedding.len() != dim {
+        return Err(AgentMemError::DimensionMismatch {
+            expected: dim,
+            got: query_embedding.len(),
+        });
+    }
+    match archive {
+        Some(archive) => archive.query(query_embedding, opts),
+        None => Ok(Vec::new()),
+    }
+}
+
+impl AgentMemDB {
+    /// Move episodes removed by prunes into `archive` instead of discarding them, or discard
+    /// them again with `None`. The archive is shared, so several DBs can prune into one.
+    /// Fails with `DimensionMismatch` unless the archive has the DB's dimension.
+    ///
+    /// With an archive attached, a prune whose archiving fails returns the error and removes
+    /// nothing, so no episode is lost; the next prune tries again.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, DiskArchive, DiskOptions, Episode, QueryOptions};
+    /// use std::sync::Arc;
+    /// let dir = std::env::temp_dir().join("agent_mem_db_archive_doctest");
+    /// let _ = std::fs::remove_dir_all(&dir);
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.set_archive(Some(Arc::new(DiskArchive::open(&dir, DiskOptions::exact(2)).unwrap())))
+    ///     .unwrap();
+    /// db.store_episode(Episode::with_timestamp("old", vec![1.0, 0.0], 1.0, 1_000)).unwrap();
+    /// assert_eq!(db.prune_older_than(2_000).unwrap(), 1);
+    /// assert!(db.is_empty());
+    /// let hits = db.query_archive(&[1.0, 0.0], QueryOptions::new(0.0, 5)).unwrap();
+    /// assert_eq!(hits[0].task_id, "old");
+    /// ```
+    pub fn set_archive(
+        &mut self,
+        archive: Option<Arc<dyn EpisodeArchive>>,
+    ) -> Result<(), AgentMemError> {
+        check_dim(archive.as_ref(), self.dim)?;
+        self.archive = archive;
+        Ok(())
+    }
+
+    /// The attached archive, if any.
+    pub fn archive(&self) -> Option<&Arc<dyn EpisodeArchive>> {
+        self.archive.as_ref()
+    }
+
+    /// Search the archived (pruned) episodes, as `query_similar_with_options` searches the
+    /// stored ones. Empty when no archive is attached.
+    pub fn query_archive(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        query_archive(self.archive.as_ref(), self.dim, query_embedding, opts)
+    }
+
+    /// Keep only `kept` of the stored episodes after a prune, archiving the rest when an
+    /// archive is attached (see `swap_in_pruned`). Returns episodes removed.
+    pub(crate) fn prune_to(&mut self, kept: Vec<Arc<Episode>>) -> Result<usize, AgentMemError> {
+        if kept.len() == self.episodes.len() {
+            return Ok(0);
+        }
+        let rebuilt = self.rebuild(kept);
+        self.swap_in_pruned(rebuilt)
+    }
+
+    /// Prune every episode matching `predicate` (see `prune_to`).
+    pub(crate) fn prune_where(
+        &mut self,
+        predicate: impl Fn(&Episode) -> bool,
+    ) -> Result<usize, AgentMemError> {
+        self.fold_access_stats();
+        let kept = self
+            .episodes
+            .values()
+            .filter(|ep| !predicate(ep))
+            .cloned()
+            .collect();
+        self.prune_to(kept)
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Move episodes removed by prunes into `archive` (see `AgentMemDB::set_archive`). A prune
+    /// whose archiving fails returns the error and removes nothing.
+    pub fn set_archive(
+        &mut self,
+        archive: Option<Arc<dyn EpisodeArchive>>,
+    ) -> Result<(), AgentMemError> {
+        check_dim(archive.as_ref(), self.dim())?;
+        self.archive = archive;
+        Ok(())
+    }
+
+    /// The attached archive, if any.
+    pub fn archive(&self) -> Option<&Arc<dyn EpisodeArchive>> {
+        self.archive.as_ref()
+    }
+
+    /// Search the archived (pruned) episodes (see `AgentMemDB::query_archive`).
+    pub fn query_archive(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        query_archive(self.archive.as_ref(), self.dim(), query_embedding, opts)
+    }
+
+    /// Keep only `kept` of the stored episodes after a prune, archiving the rest when an
+    /// archive is attached, and compact the log (see `swap_in_pruned`). Returns episodes
+    /// removed.
+    pub(crate) fn prune_to(&mut self, kept: Vec<Arc<Episode>>) -> Result<usize, AgentMemError> {
+        if kept.len() == self.episodes.len() {
+            return Ok(0);
+        }
+        let rebuilt = self.rebuild(kept);
+        self.swap_in_pruned(rebuilt)
+    }
+
+    /// Prune every episode matching `predicate` (see `prune_to`).
+    pub(crate) fn prune_where(
+        &mut self,
+        predicate: impl Fn(&Episode) -> bool,
+    ) -> Result<usize, AgentMemError> {
+        self.fold_access_stats();
+        let kept = self
+            .episodes
+            .values()
+            .filter(|ep| !predicate(ep))
+            .cloned()
+            .collect();
+        self.prune_to(kept)
+    }
+}
@@ -3,11 +3,29 @@
 //! Enable with the `async` feature: `agent_mem_db = { version = "0.1", features = ["async"] }`
 //!
 //! The caller must wrap the DB in `Arc<RwLock<AgentMemDB>>` so it can be shared across async tasks.
+//!
+//! Dropping a query future (e.g. the agent moved on, or a `select!` took another branch)
+//! cancels the query: the blocking scan stops instead of running to completion unobserved.
 
-use crate::{AgentMemDB, AgentMemError, Episode, QueryOptions};
+use crate::summarizer::{clusters, finish_summary};
+use crate::{AgentMemDB, AgentMemError, CancelToken, Episode, QueryOptions};
+use std::collections::HashSet;
+use std::future::Future;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::RwLock;
+use uuid::Uuid;
+
+/// A lock poisoned by a writer that panicked; the DB may be half-updated, so fail rather
+/// than read it.
+fn poisoned<T>(_: std::sync::PoisonError<T>) -> AgentMemError {
+    AgentMemError::Locked("DB lock poisoned by a panicked writer".to_string())
+}
+
+/// The blocking task panicked or was cancelled.
+fn join_error(e: tokio::task::JoinError) -> AgentMemError {
+    AgentMemError::io("spawn_blocking")(e.into())
+}
 
 /// Store an episode without blocking the async runtime.
 pub async fn store_episode_async(
@@ -15,25 +33,76 @@ pub async fn store_episode_async(
     ep: Episode,
 ) -> Result<(), AgentMemError> {
     tokio::task::spawn_blocking(move || {
-        let mut guard = db.write().unwrap();
+        let mut guard = db.write().map_err(poisoned)?;
         guard.store_episode(ep)
     })
     .await
-    .map_err(|e| AgentMemError::HnswError(format!("spawn_blocking: {e}")))?
+    .map_err(join_error)?
+}
+
+/// Cancels its token when dropped before `disarm`, i.e. when the future awaiting the blocking
+/// task is dropped.
+struct CancelOnDrop(Option<CancelToken>);
+
+impl CancelOnDrop {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = &self.0 {
+            token.cancel();
+        }
+    }
 }
 
-/// Query similar episodes without blocking the async runtime.
+/// The token in `opts` (so the caller can also cancel explicitly), or a fresh one.
+fn ensure_token(opts: &mut QueryOptions) -> CancelToken {
+    opts.cancel.get_or_insert_with(CancelToken::new).clone()
+}
+
+/// Query similar episodes without blocking the async runtime. Dropping the future, or
+/// cancelling `opts.cancel`, stops the search (see `QueryOptions::cancel_token`).
 pub async fn query_similar_async(
     db: Arc<RwLock<AgentMemDB>>,
     emb: Vec<f32>,
-    opts: QueryOptions,
+    mut opts: QueryOptions,
 ) -> Result<Vec<Episode>, AgentMemError> {
-    tokio::task::spawn_blocking(move || {
-        let guard = db.read().unwrap();
-        guard.query_similar_with_options(&emb, opts)
+    let guard = CancelOnDrop(Some(ensure_token(&mut opts)));
+    let result = tokio::task::spawn_blocking(move || {
+        let db = db.read().map_err(poisoned)?;
+        db.query_similar_with_options(&emb, opts)
+    })
+    .await
+    .map_err(join_error)?;
+    guard.disarm();
+    result
+}
+
+/// Run several queries with the same options on one blocking thread, without blocking the
+/// async runtime. Dropping the future, or cancelling `opts.cancel`, stops the batch: the
+/// current query is interrupted and the rest are not started.
+pub async fn query_similar_batch_async(
+    db: Arc<RwLock<AgentMemDB>>,
+    embs: Vec<Vec<f32>>,
+    mut opts: QueryOptions,
+) -> Result<Vec<Vec<Episode>>, AgentMemError> {
+    let guard = CancelOnDrop(Some(ensure_token(&mut opts)));
+    let result = tokio::task::spawn_blocking(move || {
+        let db = db.read().map_err(poisoned)?;
+        embs.iter()
+            .map(|emb| {
+                opts.check_cancelled()?;
+                db.query_similar_with_options(emb, opts.clone())
+            })
+            .collect()
     })
     .await
-    .map_err(|e| AgentMemError::HnswError(format!("spawn_blocking: {e}")))?
+    .map_err(join_error)?;
+    guard.disarm();
+    result
 }
 
 /// Save DB to file without blocking the async runtime.
@@ -42,17 +111,61 @@ pub async fn save_to_file_async(
     path: PathBuf,
 ) -> Result<(), AgentMemError> {
     tokio::task::spawn_blocking(move || {
-        let guard = db.read().unwrap();
+        let guard = db.read().map_err(poisoned)?;
         guard.save_to_file(&path)
     })
     .await
-    .map_err(|e| AgentMemError::HnswError(format!("spawn_blocking: {e}")))?
+    .map_err(join_error)?
 }
 
 /// Load DB from file without blocking the async runtime.
 pub async fn load_from_file_async(path: PathBuf) -> Result<Arc<RwLock<AgentMemDB>>, AgentMemError> {
     let db = tokio::task::spawn_blocking(move || AgentMemDB::load_from_file(&path))
         .await
-        .map_err(|e| AgentMemError::HnswError(format!("spawn_blocking: {e}")))??;
+        .map_err(join_error)??;
     Ok(Arc::new(RwLock::new(db)))
 }
+
+/// Async counterpart of [`Summarizer`](crate::Summarizer), for summarizers that await a remote
+/// LLM. Implement it with `async fn summarize`.
+pub trait AsyncSummarizer: Send + Sync {
+    /// Summarize `episodes` (at least one) into a single episode; the embedding may be left
+    /// empty (see `Summarizer`).
+    fn summarize(
+        &self,
+        episodes: &[Episode],
+    ) -> impl Future<Output = Result<Episode, AgentMemError>> + Send;
+}
+
+/// `AgentMemDB::merge_clusters` with an async summarizer, without blocking the async runtime.
+/// Groups are selected under the read lock, summarized with no lock held, then replaced under
+/// the write lock, so other tasks keep reading and writing while the summarizer runs. Grouped
+/// episodes removed in the meantime are not restored. Returns the number of groups merged.
+pub async fn merge_clusters_async(
+    db: Arc<RwLock<AgentMemDB>>,
+    threshold: f32,
+    min_size: usize,
+    summarizer: &impl AsyncSummarizer,
+) -> Result<usize, AgentMemError> {
+    let reader = Arc::clone(&db);
+    let (groups, dim) = tokio::task::spawn_blocking(move || {
+        let db = reader.read().map_err(poisoned)?;
+        Ok::<_, AgentMemError>((clusters(db.iter(), threshold, min_size), db.dim()))
+    })
+    .await
+    .map_err(join_error)??;
+    let mut summaries = Vec::with_capacity(groups.len());
+    for group in &groups {
+        let summary = summarizer.summarize(group).await?;
+        summaries.push(finish_summary(summary, group, dim)?);
+    }
+    let merged: HashSet<Uuid> = groups.iter().flatten().map(|ep| ep.id).collect();
+    tokio::task::spawn_blocking(move || {
+        let mut db = db.write().map_err(poisoned)?;
+        db.delete_where(|ep| merged.contains(&ep.id));
+        db.store_episodes(summaries)
+    })
+    .await
+    .map_err(join_error)??;
+    Ok(groups.len())
+}
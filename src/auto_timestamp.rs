@@ -0,0 +1,49 @@
+//! Store-time timestamps: with `auto_timestamp` on, an episode stored without a `timestamp`
+//! gets the current Unix time in milliseconds, so time filters, recency ordering and
+//! age-based prunes apply to it.
+
+use crate::access::now_ms;
+use crate::{AgentMemDB, AgentMemDBDisk, Episode};
+
+/// Fill each unset `timestamp` with the current time.
+pub(crate) fn stamp<'a>(episodes: impl IntoIterator<Item = &'a mut Episode>) {
+    let now = now_ms();
+    for ep in episodes {
+        ep.timestamp.get_or_insert(now);
+    }
+}
+
+impl AgentMemDB {
+    /// When on, `store_episode`, `store_episodes` and `update_episode` set `timestamp` to the
+    /// current Unix ms on episodes that don't have one. Timestamps given by the caller are
+    /// kept, and episodes already stored are unchanged. Off by default; not saved with the DB.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.set_auto_timestamp(true);
+    /// db.store_episode(Episode::new("t", vec![0.0, 1.0], 1.0)).unwrap();
+    /// assert!(db.iter().all(|ep| ep.timestamp.is_some()));
+    /// ```
+    pub fn set_auto_timestamp(&mut self, enabled: bool) {
+        self.auto_timestamp = enabled;
+    }
+
+    /// Whether unset timestamps are filled on store.
+    pub fn auto_timestamp(&self) -> bool {
+        self.auto_timestamp
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Fill unset timestamps on store, as for `AgentMemDB::set_auto_timestamp`. The filled
+    /// timestamp is written to the log, so it survives reopening.
+    pub fn set_auto_timestamp(&mut self, enabled: bool) {
+        self.auto_timestamp = enabled;
+    }
+
+    /// Whether unset timestamps are filled on store.
+    pub fn auto_timestamp(&self) -> bool {
+        self.auto_timestamp
+    }
+}
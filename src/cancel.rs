@@ -0,0 +1,29 @@
+//! Cooperative cancellation for queries whose caller has moved on.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag that stops a query in flight (see `QueryOptions::cancel_token`). Clones share
+/// the flag, so keep one and pass another in the options.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every query holding this token to stop. Exact scans notice within about a
+    /// thousand vectors; an HNSW graph search finishes first.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn flag(&self) -> &AtomicBool {
+        &self.0
+    }
+}
@@ -0,0 +1,156 @@
+//! Side-by-side index comparison: build the same episodes under several index configurations
+//! and measure recall, latency, memory and build time for each.
+
+use crate::index::l2_distance;
+use crate::{AgentMemDB, AgentMemError, Episode, HnswParams, QueryOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// An index configuration to compare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendConfig {
+    /// Exact (brute-force) search.
+    Exact,
+    /// HNSW with the given parameters.
+    Hnsw(HnswParams),
+}
+
+impl fmt::Display for BackendConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendConfig::Exact => write!(f, "exact"),
+            BackendConfig::Hnsw(p) => write!(
+                f,
+                "hnsw(m={}, ef_construction={}, ef_search={})",
+                p.m, p.ef_construction, p.ef_search
+            ),
+        }
+    }
+}
+
+/// Measurements for one configuration.
+#[derive(Debug, Clone)]
+pub struct BackendResult {
+    pub config: BackendConfig,
+    /// Time to insert every episode into an empty DB.
+    pub build_time: Duration,
+    /// Mean fraction of each query's true top-k returned.
+    pub recall_at_k: f64,
+    pub query_p50: Duration,
+    pub query_p95: Duration,
+    pub query_p99: Duration,
+    pub query_max: Duration,
+    /// Estimated index size in bytes (vectors and graph links, not episode payloads, which are
+    /// the same for every configuration).
+    pub index_bytes: usize,
+}
+
+/// Results of [`compare_backends`], one per configuration in the order given.
+#[derive(Debug, Clone)]
+pub struct ComparisonReport {
+    pub episodes: usize,
+    pub queries: usize,
+    pub top_k: usize,
+    pub results: Vec<BackendResult>,
+}
+
+/// Build `episodes` under each of `configs`, run every query for its `top_k` nearest, and
+/// report recall against brute-force ground truth, latency percentiles, index size and build
+/// time per configuration. Ground truth costs O(episodes × queries); keep the query set to
+/// the hundreds.
+///
+/// ```rust
+/// use agent_mem_db::{compare_backends, BackendConfig, Episode, HnswParams};
+/// let episodes: Vec<Episode> = (0..200)
+///     .map(|i| Episode::new("t", vec![(i % 20) as f32, (i / 20) as f32], 1.0))
+///     .collect();
+/// let queries = vec![vec![3.3, 4.1], vec![10.2, 7.7]];
+/// let configs = [BackendConfig::Exact, BackendConfig::Hnsw(HnswParams::default())];
+/// let report = compare_backends(&episodes, &queries, 5, &configs).unwrap();
+/// assert_eq!(report.results.len(), 2);
+/// assert_eq!(report.results[0].recall_at_k, 1.0);
+/// ```
+pub fn compare_backends(
+    episodes: &[Episode],
+    queries: &[Vec<f32>],
+    top_k: usize,
+    configs: &[BackendConfig],
+) -> Result<ComparisonReport, AgentMemError> {
+    let Some(dim) = episodes.first().map(|ep| ep.state_embedding.len()) else {
+        return Err(AgentMemError::InvalidQuery(
+            "compare_backends needs at least one episode".to_string(),
+        ));
+    };
+    let truth: Vec<HashSet<Uuid>> = queries
+        .iter()
+        .map(|q| exact_top_k(episodes, q, top_k))
+        .collect();
+
+    let mut results = Vec::with_capacity(configs.len());
+    for &config in configs {
+        let mut db = match config {
+            BackendConfig::Exact => AgentMemDB::new_exact(dim),
+            BackendConfig::Hnsw(params) => {
+                AgentMemDB::new_hnsw(dim, episodes.len().max(dim * 2), params)
+            }
+        };
+        let started = Instant::now();
+        db.store_episodes(episodes.to_vec())?;
+        let build_time = started.elapsed();
+
+        let mut latencies = Vec::with_capacity(queries.len());
+        let mut recall_total = 0.0;
+        for (q, truth) in queries.iter().zip(&truth) {
+            let started = Instant::now();
+            let found =
+                db.query_similar_with_options(q, QueryOptions::new(f32::NEG_INFINITY, top_k))?;
+            latencies.push(started.elapsed());
+            let hits = found.iter().filter(|ep| truth.contains(&ep.id)).count();
+            recall_total += hits as f64 / truth.len().max(1) as f64;
+        }
+        latencies.sort();
+        results.push(BackendResult {
+            config,
+            build_time,
+            recall_at_k: if queries.is_empty() {
+                1.0
+            } else {
+                recall_total / queries.len() as f64
+            },
+            query_p50: percentile(&latencies, 0.50),
+            query_p95: percentile(&latencies, 0.95),
+            query_p99: percentile(&latencies, 0.99),
+            query_max: latencies.last().copied().unwrap_or_default(),
+            index_bytes: db.index.estimated_bytes(dim),
+        });
+    }
+    Ok(ComparisonReport {
+        episodes: episodes.len(),
+        queries: queries.len(),
+        top_k,
+        results,
+    })
+}
+
+/// Ids of the `k` episodes nearest `query` by brute force.
+fn exact_top_k(episodes: &[Episode], query: &[f32], k: usize) -> HashSet<Uuid> {
+    let mut scored: Vec<(f32, Uuid)> = episodes
+        .iter()
+        .map(|ep| (l2_distance(query, &ep.state_embedding), ep.id))
+        .collect();
+    scored.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    scored.into_iter().take(k).map(|(_, id)| id).collect()
+}
+
+/// Nearest-rank percentile of sorted latencies (zero when there are none).
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
@@ -0,0 +1,104 @@
+//! Contrastive recall: the successes and failures nearest a context, side by side, as
+//! reflection prompts take them.
+
+use crate::federated::EpisodeStore;
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode, QueryOptions};
+
+/// Reward `contrastive_recall` splits at: above it is a success, at or below a failure.
+/// Fits both 0/1 and -1/1 rewards.
+pub const CONTRAST_REWARD_THRESHOLD: f32 = 0.0;
+
+/// Result of `contrastive_recall`: the nearest episodes on each side of the reward threshold,
+/// nearest first.
+#[derive(Debug, Clone, Default)]
+pub struct ContrastiveRecall {
+    /// Episodes with reward above the threshold.
+    pub high_reward: Vec<Episode>,
+    /// Episodes with reward at or below the threshold.
+    pub low_reward: Vec<Episode>,
+}
+
+/// Up to `opts.top_k` episodes nearest `query_embedding` with reward above `reward_threshold`,
+/// and up to `opts.top_k` with reward at or below it. The other filters in `opts` apply to both
+/// lists; its `min_reward` and `max_reward` are replaced by the split.
+pub fn contrastive_recall(
+    store: &dyn EpisodeStore,
+    query_embedding: &[f32],
+    reward_threshold: f32,
+    opts: QueryOptions,
+) -> Result<ContrastiveRecall, AgentMemError> {
+    let mut high = opts.clone();
+    high.min_reward = reward_threshold.next_up();
+    high.max_reward = None;
+    let low = opts.reward_range(f32::NEG_INFINITY, reward_threshold);
+    Ok(ContrastiveRecall {
+        high_reward: store.query_similar_with_options(query_embedding, high)?,
+        low_reward: store.query_similar_with_options(query_embedding, low)?,
+    })
+}
+
+impl AgentMemDB {
+    /// The `k` nearest successes and the `k` nearest failures for `query_embedding` in one
+    /// call, e.g. as contrast pairs for a reflection prompt. Splits at
+    /// `CONTRAST_REWARD_THRESHOLD`; use `contrastive_recall_with_options` for another
+    /// threshold or for filters.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("worked", vec![1.0, 0.0], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("crashed", vec![0.9, 0.1], 0.0)).unwrap();
+    /// let recall = db.contrastive_recall(&[1.0, 0.0], 3).unwrap();
+    /// assert_eq!(recall.high_reward[0].task_id, "worked");
+    /// assert_eq!(recall.low_reward[0].task_id, "crashed");
+    /// ```
+    pub fn contrastive_recall(
+        &self,
+        query_embedding: &[f32],
+        k: usize,
+    ) -> Result<ContrastiveRecall, AgentMemError> {
+        self.contrastive_recall_with_options(
+            query_embedding,
+            CONTRAST_REWARD_THRESHOLD,
+            QueryOptions::new(f32::NEG_INFINITY, k),
+        )
+    }
+
+    /// `contrastive_recall` split at `reward_threshold`, with `opts.top_k` per list and the
+    /// other filters of `opts` (see [`contrastive_recall`](crate::contrastive_recall)).
+    pub fn contrastive_recall_with_options(
+        &self,
+        query_embedding: &[f32],
+        reward_threshold: f32,
+        opts: QueryOptions,
+    ) -> Result<ContrastiveRecall, AgentMemError> {
+        contrastive_recall(self, query_embedding, reward_threshold, opts)
+    }
+}
+
+impl AgentMemDBDisk {
+    /// The `k` nearest successes and the `k` nearest failures for `query_embedding` (see
+    /// `AgentMemDB::contrastive_recall`).
+    pub fn contrastive_recall(
+        &self,
+        query_embedding: &[f32],
+        k: usize,
+    ) -> Result<ContrastiveRecall, AgentMemError> {
+        self.contrastive_recall_with_options(
+            query_embedding,
+            CONTRAST_REWARD_THRESHOLD,
+            QueryOptions::new(f32::NEG_INFINITY, k),
+        )
+    }
+
+    /// `contrastive_recall` with a threshold and filters (see
+    /// `AgentMemDB::contrastive_recall_with_options`).
+    pub fn contrastive_recall_with_options(
+        &self,
+        query_embedding: &[f32],
+        reward_threshold: f32,
+        opts: QueryOptions,
+    ) -> Result<ContrastiveRecall, AgentMemError> {
+        contrastive_recall(self, query_embedding, reward_threshold, opts)
+    }
+}
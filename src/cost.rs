@@ -0,0 +1,69 @@
+//! Cost and latency totals over a DB's episodes, for teams tracking them alongside reward.
+
+use crate::{AgentMemDB, AgentMemDBDisk, Episode};
+use serde::Serialize;
+
+/// Cost and duration totals over a DB's episodes (see `AgentMemDB::cost_summary`). Episodes
+/// without a `cost` or `duration_ms` are left out of that field's totals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CostSummary {
+    /// Episodes with a `cost`.
+    pub with_cost: usize,
+    pub total_cost: f64,
+    /// `None` when no episode has a cost.
+    pub mean_cost: Option<f64>,
+    /// Episodes with a `duration_ms`.
+    pub with_duration: usize,
+    pub total_duration_ms: i64,
+    /// `None` when no episode has a duration.
+    pub mean_duration_ms: Option<f64>,
+}
+
+impl CostSummary {
+    fn of<'a>(episodes: impl Iterator<Item = &'a Episode>) -> Self {
+        let mut summary = episodes.fold(CostSummary::default(), |mut summary, ep| {
+            if let Some(cost) = ep.cost {
+                summary.with_cost += 1;
+                summary.total_cost += f64::from(cost);
+            }
+            if let Some(ms) = ep.duration_ms {
+                summary.with_duration += 1;
+                summary.total_duration_ms = summary.total_duration_ms.saturating_add(ms);
+            }
+            summary
+        });
+        if summary.with_cost > 0 {
+            summary.mean_cost = Some(summary.total_cost / summary.with_cost as f64);
+        }
+        if summary.with_duration > 0 {
+            summary.mean_duration_ms =
+                Some(summary.total_duration_ms as f64 / summary.with_duration as f64);
+        }
+        summary
+    }
+}
+
+impl AgentMemDB {
+    /// Total and mean `cost` and `duration_ms` over the DB's episodes.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::with_cost("a", vec![0.0, 1.0], 1.0, 0.25)).unwrap();
+    /// db.store_episode(Episode::with_cost("b", vec![1.0, 0.0], 0.0, 0.75)).unwrap();
+    /// db.store_episode(Episode::new("c", vec![1.0, 1.0], 1.0)).unwrap();
+    /// let summary = db.cost_summary();
+    /// assert_eq!((summary.with_cost, summary.mean_cost), (2, Some(0.5)));
+    /// assert_eq!(summary.mean_duration_ms, None);
+    /// ```
+    pub fn cost_summary(&self) -> CostSummary {
+        CostSummary::of(self.iter())
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Total and mean `cost` and `duration_ms` over the DB's episodes.
+    pub fn cost_summary(&self) -> CostSummary {
+        CostSummary::of(self.iter())
+    }
+}
@@ -0,0 +1,94 @@
+//! Comparing two databases episode by episode, to verify backups and replicas or find drift
+//! between environments.
+
+use crate::{AgentMemDB, AgentMemDBDisk, Episode};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Differences between two sets of episodes, matched by id. Each list is sorted by id.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DbDiff {
+    /// Episodes whose id is only in the left database (`self`).
+    pub only_in_left: Vec<Episode>,
+    /// Episodes whose id is only in the right database (`other`).
+    pub only_in_right: Vec<Episode>,
+    /// Episodes in both whose contents differ.
+    pub changed: Vec<EpisodeChange>,
+}
+
+/// One episode present on both sides with different contents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodeChange {
+    pub left: Episode,
+    pub right: Episode,
+    /// The differing fields, e.g. `["reward", "tags"]`.
+    pub fields: Vec<&'static str>,
+}
+
+impl DbDiff {
+    /// Compare any two episode collections, e.g. a save file against a disk directory.
+    pub fn between<'a>(
+        left: impl IntoIterator<Item = &'a Episode>,
+        right: impl IntoIterator<Item = &'a Episode>,
+    ) -> Self {
+        let mut right: HashMap<Uuid, &Episode> = right.into_iter().map(|ep| (ep.id, ep)).collect();
+        let mut diff = DbDiff::default();
+        for l in left {
+            match right.remove(&l.id) {
+                None => diff.only_in_left.push(l.clone()),
+                Some(r) if r != l => diff.changed.push(EpisodeChange {
+                    fields: changed_fields(l, r),
+                    left: l.clone(),
+                    right: r.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        diff.only_in_right = right.into_values().cloned().collect();
+        diff.only_in_left.sort_by_key(|ep| ep.id);
+        diff.only_in_right.sort_by_key(|ep| ep.id);
+        diff.changed.sort_by_key(|c| c.left.id);
+        diff
+    }
+
+    /// True when both sides hold exactly the same episodes.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_left.is_empty() && self.only_in_right.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn changed_fields(l: &Episode, r: &Episode) -> Vec<&'static str> {
+    let checks = [
+        ("task_id", l.task_id != r.task_id),
+        ("state_embedding", l.state_embedding != r.state_embedding),
+        ("reward", l.reward != r.reward),
+        ("metadata", l.metadata != r.metadata),
+        ("steps", l.steps != r.steps),
+        ("timestamp", l.timestamp != r.timestamp),
+        ("tags", l.tags != r.tags),
+        ("source", l.source != r.source),
+        ("user_id", l.user_id != r.user_id),
+        ("session_id", l.session_id != r.session_id),
+        ("embedding_model", l.embedding_model != r.embedding_model),
+        ("version", l.version != r.version),
+        ("deleted", l.deleted != r.deleted),
+    ];
+    checks
+        .into_iter()
+        .filter_map(|(field, differs)| differs.then_some(field))
+        .collect()
+}
+
+impl AgentMemDB {
+    /// Episodes only in `self`, only in `other`, and changed between them (matched by id).
+    pub fn diff(&self, other: &AgentMemDB) -> DbDiff {
+        DbDiff::between(self.iter(), other.iter())
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Episodes only in `self`, only in `other`, and changed between them (matched by id).
+    pub fn diff(&self, other: &AgentMemDBDisk) -> DbDiff {
+        DbDiff::between(self.iter(), other.iter())
+    }
+}
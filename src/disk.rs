@@ -1,48 +1,197 @@
 //! Disk-backed agent memory DB. Episodes stored in append-only JSONL log; index in RAM.
 
-use crate::index::{ExactIndex, HnswIndex, IndexBackend};
-use crate::{AgentMemError, Episode, QueryOptions};
+use crate::access::{AccessFile, AccessStats, AccessTracker};
+use crate::auto_timestamp;
+use crate::format::{self, Format};
+use crate::index::{ExactIndex, HnswIndex, HnswParams, IndexBackend, KeySet};
+use crate::outliers::{self, Outlier};
+use crate::prefilter::KeyFilters;
+use crate::rebuild::Rebuilt;
+use crate::{
+    check_finite, search_candidates, AgentMemError, EmbeddingProvider, Episode, EpisodeArchive,
+    EpisodeLimits, ExplainedCandidate, PruneRule, QueryExplanation, QueryLog, QueryOptions,
+    QueryResults, FORMAT_VERSION,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 const EPISODES_LOG: &str = "episodes.jsonl";
 const META_FILE: &str = "meta.json";
 const EXACT_CHECKPOINT_FILE: &str = "exact_checkpoint.json";
+const ACCESS_FILE: &str = "access.json";
+/// Suffix of files written before being renamed into place; any left over are from a crash.
+const TMP_SUFFIX: &str = ".tmp";
 
 /// State loaded from checkpoint or replayed from log.
-type LoadedState = (HashMap<Uuid, Episode>, HashMap<usize, Uuid>, IndexBackend);
+type LoadedState = (
+    HashMap<Uuid, Arc<Episode>>,
+    HashMap<usize, Uuid>,
+    IndexBackend,
+);
 
 #[derive(Serialize, Deserialize)]
 struct DiskMeta {
+    /// Layout of this directory (see `FORMAT_VERSION`); 0 when written before versioning.
+    #[serde(default)]
+    format_version: u32,
     dim: usize,
     index_type: String, // "hnsw" | "exact"
     max_elements: usize,
     #[serde(default)]
     checkpoint_line_count: Option<usize>,
+    /// Absent in metas written before HNSW parameters were configurable (defaults apply).
+    #[serde(default)]
+    hnsw_params: HnswParams,
 }
 
 #[derive(Serialize, Deserialize)]
 struct ExactCheckpoint {
+    #[serde(default)]
+    format_version: u32,
     episodes: Vec<Episode>,
 }
 
+/// Read and migrate the `meta.json` of the DB directory `path`.
+fn read_meta(path: &Path) -> Result<DiskMeta, AgentMemError> {
+    let data = fs::read_to_string(path.join(META_FILE)).map_err(AgentMemError::io("Read meta"))?;
+    format::parse(Format::DiskMeta, &data)
+}
+
+/// One line of a disk DB's log (`episodes.jsonl`).
+///
+/// `store_episodes` writes a batch as `BatchBegin`, the episodes, then `BatchCommit`, in a single
+/// fsync'd write. On replay, episodes of a batch become visible only at its commit; a batch left
+/// uncommitted at the end of the log by a crash is discarded and cut from the log.
+// Records are parsed one line at a time and never stored in bulk, so the size gap between
+// variants costs nothing worth a `Box`.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogRecord {
+    Episode(Episode),
+    /// Start of a batch of `count` episodes.
+    BatchBegin {
+        batch: Uuid,
+        count: usize,
+    },
+    /// End of the batch; its episodes are durable.
+    BatchCommit {
+        batch: Uuid,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchBeginLine {
+    batch_begin: Uuid,
+    count: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchCommitLine {
+    batch_commit: Uuid,
+}
+
+impl LogRecord {
+    /// Parse one log line (without its newline).
+    pub fn parse(line: &[u8]) -> Result<Self, serde_json::Error> {
+        if line.starts_with(b"{\"batch_begin\"") {
+            let m: BatchBeginLine = serde_json::from_slice(line)?;
+            return Ok(LogRecord::BatchBegin {
+                batch: m.batch_begin,
+                count: m.count,
+            });
+        }
+        if line.starts_with(b"{\"batch_commit\"") {
+            let m: BatchCommitLine = serde_json::from_slice(line)?;
+            return Ok(LogRecord::BatchCommit {
+                batch: m.batch_commit,
+            });
+        }
+        serde_json::from_slice(line).map(LogRecord::Episode)
+    }
+
+    /// Serialize as one log line (without its newline).
+    pub fn to_line(&self) -> Result<String, serde_json::Error> {
+        match self {
+            LogRecord::Episode(ep) => serde_json::to_string(ep),
+            LogRecord::BatchBegin { batch, count } => serde_json::to_string(&BatchBeginLine {
+                batch_begin: *batch,
+                count: *count,
+            }),
+            LogRecord::BatchCommit { batch } => serde_json::to_string(&BatchCommitLine {
+                batch_commit: *batch,
+            }),
+        }
+    }
+
+    /// Length of the longest prefix of `log` made of whole lines outside any uncommitted batch,
+    /// i.e. what a reader can safely apply now.
+    pub fn committed_len(log: &[u8]) -> usize {
+        let mut committed = 0;
+        let mut in_batch = false;
+        let mut offset = 0;
+        for line in log.split_inclusive(|b| *b == b'\n') {
+            offset += line.len();
+            if !line.ends_with(b"\n") {
+                break;
+            }
+            match LogRecord::parse(line.trim_ascii()) {
+                Ok(LogRecord::BatchBegin { .. }) => in_batch = true,
+                Ok(LogRecord::BatchCommit { .. }) => in_batch = false,
+                _ => {}
+            }
+            if !in_batch {
+                committed = offset;
+            }
+        }
+        committed
+    }
+}
+
 /// Disk-backed agent memory DB. Episodes stored in append-only log; index in RAM.
 ///
 /// Use for episode sets that exceed RAM or when durability is required.
 /// On open, replays the log to rebuild the index (or loads from checkpoint when valid).
 pub struct AgentMemDBDisk {
     dim: usize,
-    episodes: HashMap<Uuid, Episode>,
-    index: IndexBackend,
+    /// Shared with the results of `query_similar_shared`.
+    pub(crate) episodes: HashMap<Uuid, Arc<Episode>>,
+    pub(crate) index: IndexBackend,
     key_to_uuid: HashMap<usize, Uuid>,
-    #[allow(dead_code)] // Reserved for compaction, retention APIs
+    /// Keys by tag, user_id and source, for filtered index searches.
+    filters: KeyFilters,
     path: PathBuf,
     log_file: File,
     use_checkpoint: bool,
+    /// When to checkpoint on its own (see `DiskOptions::auto_checkpoint`).
+    auto_checkpoint: Option<AutoCheckpoint>,
+    /// Episodes written since the last checkpoint (or open).
+    writes_since_checkpoint: usize,
+    last_checkpoint: Instant,
+    /// Buffer single writes and commit them together (see `DiskOptions::group_commit`).
+    group_commit: Option<GroupCommit>,
+    /// Log lines of single writes not yet in the log, and when the first was buffered.
+    pending: Vec<String>,
+    pending_since: Option<Instant>,
+    /// For `store_text` / `query_text` (see `set_embedder`).
+    pub(crate) embedder: Option<Arc<dyn EmbeddingProvider>>,
+    /// Records queries when set (see `set_query_log`).
+    pub(crate) query_log: Option<Arc<QueryLog>>,
+    /// Size limits checked on store (see `set_limits`).
+    pub(crate) limits: EpisodeLimits,
+    /// Fill unset timestamps on store (see `set_auto_timestamp`).
+    pub(crate) auto_timestamp: bool,
+    /// Retrievals counted by queries (see `access_stats`), saved to `access.json`.
+    pub(crate) access: AccessTracker,
+    /// Where prunes move removed episodes (see `set_archive`).
+    pub(crate) archive: Option<Arc<dyn EpisodeArchive>>,
 }
 
 impl AgentMemDBDisk {
@@ -58,30 +207,28 @@ impl AgentMemDBDisk {
         opts: DiskOptions,
     ) -> Result<Self, AgentMemError> {
         let path = path.as_ref().to_path_buf();
-        fs::create_dir_all(&path)
-            .map_err(|e| AgentMemError::HnswError(format!("Create dir: {e}")))?;
+        fs::create_dir_all(&path).map_err(AgentMemError::io("Create dir"))?;
 
         let meta_path = path.join(META_FILE);
         let log_path = path.join(EPISODES_LOG);
 
         let (dim, index, episodes, key_to_uuid) = if meta_path.exists() {
             // Load existing
-            let meta: DiskMeta = serde_json::from_str(
-                &fs::read_to_string(&meta_path)
-                    .map_err(|e| AgentMemError::HnswError(format!("Read meta: {e}")))?,
-            )
-            .map_err(|e| AgentMemError::HnswError(format!("Parse meta: {e}")))?;
+            let meta = read_meta(&path)?;
 
             if meta.dim != opts.dim {
-                return Err(AgentMemError::HnswError(format!(
-                    "Dimension mismatch: meta has {}, requested {}",
-                    meta.dim, opts.dim
-                )));
+                return Err(AgentMemError::DimensionMismatch {
+                    expected: meta.dim,
+                    got: opts.dim,
+                });
             }
 
             let index: IndexBackend = match meta.index_type.as_str() {
                 "exact" => IndexBackend::Exact(ExactIndex::new()),
-                _ => IndexBackend::Hnsw(Box::new(HnswIndex::new(meta.max_elements))),
+                _ => IndexBackend::Hnsw(Box::new(HnswIndex::with_params(
+                    meta.max_elements,
+                    meta.hnsw_params,
+                ))),
             };
 
             let (episodes, key_to_uuid, index) = if log_path.exists() {
@@ -94,10 +241,10 @@ impl AgentMemDBDisk {
                     if meta.checkpoint_line_count == Some(line_count) {
                         Self::load_from_checkpoint(&checkpoint_path, meta.dim)?
                     } else {
-                        Self::replay_log(&log_path, meta.dim, meta.max_elements, &meta.index_type)?
+                        Self::replay_log(&log_path, meta.dim, index)?
                     }
                 } else {
-                    Self::replay_log(&log_path, meta.dim, meta.max_elements, &meta.index_type)?
+                    Self::replay_log(&log_path, meta.dim, index)?
                 }
             } else {
                 (HashMap::new(), HashMap::new(), index)
@@ -108,19 +255,23 @@ impl AgentMemDBDisk {
             // Create new
             let index = match opts.index_type.as_deref() {
                 Some("exact") => IndexBackend::Exact(ExactIndex::new()),
-                _ => IndexBackend::Hnsw(Box::new(HnswIndex::new(opts.max_elements))),
+                _ => IndexBackend::Hnsw(Box::new(HnswIndex::with_params(
+                    opts.max_elements,
+                    opts.hnsw_params,
+                ))),
             };
 
             let meta = DiskMeta {
+                format_version: FORMAT_VERSION,
                 dim: opts.dim,
                 index_type: opts.index_type.unwrap_or_else(|| "hnsw".to_string()),
                 max_elements: opts.max_elements,
                 checkpoint_line_count: None,
+                hnsw_params: opts.hnsw_params,
             };
             let meta_json = serde_json::to_string_pretty(&meta)
-                .map_err(|e| AgentMemError::HnswError(format!("Serialize meta: {e}")))?;
-            fs::write(&meta_path, meta_json)
-                .map_err(|e| AgentMemError::HnswError(format!("Write meta: {e}")))?;
+                .map_err(AgentMemError::serde("Serialize meta"))?;
+            fs::write(&meta_path, meta_json).map_err(AgentMemError::io("Write meta"))?;
 
             (opts.dim, index, HashMap::new(), HashMap::new())
         };
@@ -129,22 +280,84 @@ impl AgentMemDBDisk {
             .create(true)
             .append(true)
             .open(&log_path)
-            .map_err(|e| AgentMemError::HnswError(format!("Open log: {e}")))?;
+            .map_err(AgentMemError::io("Open log"))?;
 
+        let mut episodes = episodes;
+        Self::load_access_stats(&path, &mut episodes)?;
+        let filters = KeyFilters::build(
+            key_to_uuid
+                .iter()
+                .filter_map(|(&key, id)| Some((key, episodes.get(id)?.as_ref()))),
+        );
         Ok(Self {
             dim,
             episodes,
             index,
             key_to_uuid,
+            filters,
             path,
             log_file,
             use_checkpoint: opts.use_checkpoint,
+            auto_checkpoint: opts.auto_checkpoint.filter(|_| opts.use_checkpoint),
+            writes_since_checkpoint: 0,
+            last_checkpoint: Instant::now(),
+            group_commit: opts.group_commit,
+            pending: Vec::new(),
+            pending_since: None,
+            embedder: None,
+            query_log: None,
+            limits: EpisodeLimits::default(),
+            auto_timestamp: false,
+            access: AccessTracker::default(),
+            archive: None,
         })
     }
 
+    /// Whether `path` holds a DB (its `meta.json`), e.g. to tell an existing tenant from a new
+    /// one without creating it.
+    pub fn exists(path: impl AsRef<Path>) -> bool {
+        path.as_ref().join(META_FILE).exists()
+    }
+
+    /// Open an existing DB, taking the dimension and index from its `meta.json`.
+    /// Errors if the directory holds no DB. Uses the checkpoint when it is valid.
+    pub fn open_existing(path: impl AsRef<Path>) -> Result<Self, AgentMemError> {
+        let path = path.as_ref();
+        let meta = read_meta(path)?;
+        let opts = DiskOptions {
+            use_checkpoint: true,
+            ..DiskOptions::exact(meta.dim)
+        };
+        Self::open_with_options(path, opts)
+    }
+
+    /// Return the embedding dimension.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Number of stored episodes.
+    pub fn len(&self) -> usize {
+        self.episodes.len()
+    }
+
+    /// True when no episodes are stored.
+    pub fn is_empty(&self) -> bool {
+        self.episodes.is_empty()
+    }
+
+    /// Iterate over all stored episodes (arbitrary order).
+    pub fn iter(&self) -> impl Iterator<Item = &Episode> {
+        self.episodes.values().map(|ep| &**ep)
+    }
+
+    /// The stored episode with this id, if any.
+    pub fn get(&self, id: Uuid) -> Option<&Episode> {
+        self.episodes.get(&id).map(|ep| &**ep)
+    }
+
     fn count_log_lines(log_path: &Path) -> Result<usize, AgentMemError> {
-        let file = File::open(log_path)
-            .map_err(|e| AgentMemError::HnswError(format!("Open log for count: {e}")))?;
+        let file = File::open(log_path).map_err(AgentMemError::io("Open log for count"))?;
         let reader = BufReader::new(file);
         let count = reader
             .lines()
@@ -158,10 +371,9 @@ impl AgentMemDBDisk {
         checkpoint_path: &Path,
         dim: usize,
     ) -> Result<LoadedState, AgentMemError> {
-        let data = fs::read_to_string(checkpoint_path)
-            .map_err(|e| AgentMemError::HnswError(format!("Read checkpoint: {e}")))?;
-        let cp: ExactCheckpoint = serde_json::from_str(&data)
-            .map_err(|e| AgentMemError::HnswError(format!("Deserialize checkpoint: {e}")))?;
+        let data =
+            fs::read_to_string(checkpoint_path).map_err(AgentMemError::io("Read checkpoint"))?;
+        let cp: ExactCheckpoint = format::parse(Format::Checkpoint, &data)?;
 
         let mut episodes = HashMap::new();
         let mut key_to_uuid = HashMap::new();
@@ -182,63 +394,138 @@ impl AgentMemDBDisk {
 
         for (i, ep) in cp.episodes.into_iter().enumerate() {
             key_to_uuid.insert(i, ep.id);
-            episodes.insert(ep.id, ep);
+            episodes.insert(ep.id, Arc::new(ep));
         }
 
         let index = IndexBackend::Exact(ExactIndex::from_vectors(vectors));
         Ok((episodes, key_to_uuid, index))
     }
 
+    /// Rebuild state from the log. A batch left uncommitted at the end (a crash mid-write) is
+    /// discarded and truncated from the log, so later appends don't follow a torn line.
     fn replay_log(
         log_path: &Path,
         dim: usize,
-        max_elements: usize,
-        index_type: &str,
+        mut index: IndexBackend,
     ) -> Result<LoadedState, AgentMemError> {
-        let file = File::open(log_path)
-            .map_err(|e| AgentMemError::HnswError(format!("Open log for replay: {e}")))?;
-        let reader = BufReader::new(file);
+        let file = File::open(log_path).map_err(AgentMemError::io("Open log for replay"))?;
+        let mut reader = BufReader::new(file);
         let mut episodes = HashMap::new();
         let mut key_to_uuid = HashMap::new();
-
-        let mut index: IndexBackend = match index_type {
-            "exact" => IndexBackend::Exact(ExactIndex::new()),
-            _ => IndexBackend::Hnsw(Box::new(HnswIndex::new(max_elements))),
+        // A later line for the same id (`update_episode`) replaces the earlier one.
+        let mut keys: HashMap<Uuid, usize> = HashMap::new();
+        let mut apply = |ep: Episode, index: &mut IndexBackend| {
+            let id = ep.id;
+            let key = index.insert(&ep.state_embedding);
+            if let Some(old) = keys.insert(id, key) {
+                key_to_uuid.remove(&old);
+            }
+            key_to_uuid.insert(key, id);
+            episodes.insert(id, Arc::new(ep));
         };
-
-        for line in reader.lines() {
-            let line = line.map_err(|e| AgentMemError::HnswError(format!("Read line: {e}")))?;
-            let line = line.trim();
+        // (batch id, expected count, log offset of the begin marker, episodes so far)
+        let mut pending: Option<(Uuid, usize, u64, Vec<Episode>)> = None;
+        let mut torn = false;
+        let mut buf = Vec::new();
+        let mut offset = 0u64;
+        let mut line_no = 0;
+        loop {
+            buf.clear();
+            let n = reader
+                .read_until(b'\n', &mut buf)
+                .map_err(AgentMemError::io("Read line"))?;
+            if n == 0 {
+                break;
+            }
+            let start = offset;
+            offset += n as u64;
+            line_no += 1;
+            let line = buf.trim_ascii();
             if line.is_empty() {
                 continue;
             }
-            let ep: Episode = serde_json::from_str(line)
-                .map_err(|e| AgentMemError::HnswError(format!("Parse episode: {e}")))?;
-            if ep.state_embedding.len() != dim {
-                return Err(AgentMemError::DimensionMismatch {
-                    expected: dim,
-                    got: ep.state_embedding.len(),
+            if torn {
+                return Err(AgentMemError::Corruption {
+                    line: line_no,
+                    reason: "follows an unreadable line inside a batch".to_string(),
                 });
             }
-            let id = ep.id;
-            let key = index.insert(&ep.state_embedding);
-            key_to_uuid.insert(key, id);
-            episodes.insert(id, ep);
+            let record = match LogRecord::parse(line) {
+                Ok(record) => record,
+                // Part of a batch still being written when the process stopped.
+                Err(_) if pending.is_some() => {
+                    torn = true;
+                    continue;
+                }
+                Err(e) => {
+                    return Err(AgentMemError::Corruption {
+                        line: line_no,
+                        reason: e.to_string(),
+                    })
+                }
+            };
+            match record {
+                LogRecord::Episode(ep) => {
+                    if ep.state_embedding.len() != dim {
+                        return Err(AgentMemError::DimensionMismatch {
+                            expected: dim,
+                            got: ep.state_embedding.len(),
+                        });
+                    }
+                    match pending {
+                        Some((.., ref mut batch)) => batch.push(ep),
+                        None => apply(ep, &mut index),
+                    }
+                }
+                // An earlier uncommitted batch (if any) never becomes visible.
+                LogRecord::BatchBegin { batch, count } => {
+                    pending = Some((batch, count, start, Vec::with_capacity(count)))
+                }
+                LogRecord::BatchCommit { batch } => match pending.take() {
+                    Some((id, count, _, eps)) if id == batch && eps.len() == count => {
+                        for ep in eps {
+                            apply(ep, &mut index);
+                        }
+                    }
+                    _ => {
+                        return Err(AgentMemError::Corruption {
+                            line: line_no,
+                            reason: format!("commit for unknown or incomplete batch {batch}"),
+                        })
+                    }
+                },
+            }
+        }
+
+        if let Some((_, _, begin, _)) = pending {
+            let file = OpenOptions::new()
+                .write(true)
+                .open(log_path)
+                .map_err(AgentMemError::io("Open log for truncate"))?;
+            file.set_len(begin)
+                .and_then(|()| file.sync_all())
+                .map_err(AgentMemError::io("Truncate log"))?;
         }
 
         Ok((episodes, key_to_uuid, index))
     }
 
-    /// Persist ExactIndex checkpoint for fast restart. No-op for HNSW or when checkpoint disabled.
-    /// Call after storing episodes to avoid full replay on next open.
+    /// Persist ExactIndex checkpoint for fast restart, after saving access statistics (see
+    /// `save_access_stats`). The checkpoint itself is skipped for HNSW or when checkpoint
+    /// disabled. Call after storing episodes to avoid full replay on next open, or let
+    /// `DiskOptions::auto_checkpoint` do it.
     pub fn checkpoint(&mut self) -> Result<(), AgentMemError> {
+        self.save_access_stats()?;
         if !self.use_checkpoint {
             return Ok(());
         }
         let IndexBackend::Exact(_) = &self.index else {
             return Ok(());
         };
+        self.flush()?;
 
+        // Every write is whole in the log before it is applied here, so the loaded episodes are
+        // exactly what replaying these lines gives, whatever updates and batch markers they hold.
         let line_count = Self::count_log_lines(&self.path.join(EPISODES_LOG))?;
         let episodes: Vec<Episode> = (0..self.index.len())
             .filter_map(|key| {
@@ -246,59 +533,281 @@ impl AgentMemDBDisk {
                     .get(&key)
                     .and_then(|id| self.episodes.get(id))
             })
-            .cloned()
+            .map(|ep| Episode::clone(ep))
             .collect();
 
-        if episodes.len() != line_count {
-            return Ok(());
-        }
-
-        let cp = ExactCheckpoint { episodes };
-        let data = serde_json::to_string(&cp)
-            .map_err(|e| AgentMemError::HnswError(format!("Serialize checkpoint: {e}")))?;
+        let cp = ExactCheckpoint {
+            format_version: FORMAT_VERSION,
+            episodes,
+        };
+        let data =
+            serde_json::to_string(&cp).map_err(AgentMemError::serde("Serialize checkpoint"))?;
         let checkpoint_path = self.path.join(EXACT_CHECKPOINT_FILE);
-        fs::write(&checkpoint_path, data)
-            .map_err(|e| AgentMemError::HnswError(format!("Write checkpoint: {e}")))?;
+        fs::write(&checkpoint_path, data).map_err(AgentMemError::io("Write checkpoint"))?;
 
         let meta_path = self.path.join(META_FILE);
-        let meta: DiskMeta = serde_json::from_str(
-            &fs::read_to_string(&meta_path)
-                .map_err(|e| AgentMemError::HnswError(format!("Read meta: {e}")))?,
-        )
-        .map_err(|e| AgentMemError::HnswError(format!("Parse meta: {e}")))?;
+        let meta = read_meta(&self.path)?;
 
         let updated = DiskMeta {
             checkpoint_line_count: Some(line_count),
             ..meta
         };
         let meta_json = serde_json::to_string_pretty(&updated)
-            .map_err(|e| AgentMemError::HnswError(format!("Serialize meta: {e}")))?;
-        fs::write(&meta_path, meta_json)
-            .map_err(|e| AgentMemError::HnswError(format!("Write meta: {e}")))?;
+            .map_err(AgentMemError::serde("Serialize meta"))?;
+        fs::write(&meta_path, meta_json).map_err(AgentMemError::io("Write meta"))?;
 
+        self.writes_since_checkpoint = 0;
+        self.last_checkpoint = Instant::now();
         Ok(())
     }
 
-    /// Store an episode: append to log and insert into index.
-    pub fn store_episode(&mut self, episode: Episode) -> Result<(), AgentMemError> {
+    /// Append one log line: written and fsync'd now, or buffered under group commit until the
+    /// group is full or its window has passed. If that flush fails the line is dropped again,
+    /// since the caller reports the write as failed; earlier pending writes stay buffered.
+    fn append(&mut self, line: String) -> Result<(), AgentMemError> {
+        let Some(group) = self.group_commit else {
+            return self.write_log(&format!("{line}\n"));
+        };
+        self.pending.push(line);
+        let since = *self.pending_since.get_or_insert_with(Instant::now);
+        if self.pending.len() >= group.max_records.max(1)
+            || since.elapsed() >= Duration::from_millis(group.window_ms)
+        {
+            if let Err(e) = self.flush() {
+                self.pending.pop();
+                if self.pending.is_empty() {
+                    self.pending_since = None;
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write and fsync single writes buffered by group commit, as one batch so a crash
+    /// mid-write drops the whole group rather than leaving a torn line. No-op without group
+    /// commit or when nothing is pending. On error the writes stay buffered, to be retried.
+    pub fn flush(&mut self) -> Result<(), AgentMemError> {
+        let data = match self.pending.len() {
+            0 => return Ok(()),
+            1 => format!("{}\n", self.pending[0]),
+            count => {
+                let batch = Uuid::new_v4();
+                let serialize =
+                    |r: &LogRecord| r.to_line().map_err(AgentMemError::serde("Serialize"));
+                let mut data = serialize(&LogRecord::BatchBegin { batch, count })?;
+                data.push('\n');
+                for line in &self.pending {
+                    data.push_str(line);
+                    data.push('\n');
+                }
+                data.push_str(&serialize(&LogRecord::BatchCommit { batch })?);
+                data.push('\n');
+                data
+            }
+        };
+        self.write_log(&data)?;
+        self.discard_pending();
+        Ok(())
+    }
+
+    /// Forget buffered writes after the log was rewritten from the loaded episodes, which
+    /// include them.
+    fn discard_pending(&mut self) {
+        self.pending.clear();
+        self.pending_since = None;
+    }
+
+    /// Append `data` (whole lines) to the log and fsync. On failure the log is cut back, so
+    /// later appends don't follow a partial write.
+    fn write_log(&mut self, data: &str) -> Result<(), AgentMemError> {
+        let before = self
+            .log_file
+            .metadata()
+            .map_err(AgentMemError::io("Write log"))?
+            .len();
+        if let Err(e) = self
+            .log_file
+            .write_all(data.as_bytes())
+            .and_then(|()| self.log_file.sync_all())
+        {
+            let _ = self.log_file.set_len(before);
+            return Err(AgentMemError::io("Write log")(e));
+        }
+        Ok(())
+    }
+
+    /// Count `n` episodes written and checkpoint if `auto_checkpoint` says it's due. The
+    /// episodes are already in the log, so a failed checkpoint is not an error for the write:
+    /// it only leaves the next open to replay, and is retried on the next write.
+    fn wrote(&mut self, n: usize) {
+        self.writes_since_checkpoint += n;
+        let Some(auto) = self.auto_checkpoint else {
+            return;
+        };
+        let due = (auto.every_n > 0 && self.writes_since_checkpoint >= auto.every_n)
+            || (auto.every_ms > 0
+                && self.last_checkpoint.elapsed() >= Duration::from_millis(auto.every_ms));
+        if due {
+            let _ = self.checkpoint();
+        }
+    }
+
+    /// Store an episode: append to log and insert into index. An episode with the same id is
+    /// replaced (the later log line wins on replay). Fails without writing if the episode is
+    /// over a size limit (see `set_limits`).
+    pub fn store_episode(&mut self, mut episode: Episode) -> Result<(), AgentMemError> {
         if episode.state_embedding.len() != self.dim {
             return Err(AgentMemError::DimensionMismatch {
                 expected: self.dim,
                 got: episode.state_embedding.len(),
             });
         }
-        let line = serde_json::to_string(&episode)
-            .map_err(|e| AgentMemError::HnswError(format!("Serialize: {e}")))?;
-        writeln!(self.log_file, "{}", line)
-            .map_err(|e| AgentMemError::HnswError(format!("Write log: {e}")))?;
-        self.log_file
-            .sync_all()
-            .map_err(|e| AgentMemError::HnswError(format!("Sync log: {e}")))?;
+        check_finite(&episode.state_embedding)?;
+        self.limits.check(&episode)?;
+        self.index.check_room(1)?;
+        if self.auto_timestamp {
+            auto_timestamp::stamp([&mut episode]);
+        }
+        let line = serde_json::to_string(&episode).map_err(AgentMemError::serde("Serialize"))?;
+        self.append(line)?;
+
+        self.insert_indexed(episode);
+        self.wrote(1);
+        Ok(())
+    }
+
+    /// Replace the stored episode with the same id, if its version is still `expected_version`
+    /// (optimistic concurrency). The new version, `expected_version + 1`, is set on the episode,
+    /// appended to the log and returned. Access statistics carry over from the stored episode. Fails with `NotFound` for an unknown id and `Conflict`
+    /// when another writer updated the episode first.
+    pub fn update_episode(
+        &mut self,
+        mut episode: Episode,
+        expected_version: u64,
+    ) -> Result<u64, AgentMemError> {
+        let current = self
+            .episodes
+            .get(&episode.id)
+            .ok_or(AgentMemError::NotFound)?;
+        if current.version != expected_version {
+            return Err(AgentMemError::Conflict {
+                expected: expected_version,
+                actual: current.version,
+            });
+        }
+        self.access.stats(current).set_on(&mut episode);
+        episode.version = expected_version + 1;
+        let version = episode.version;
+        self.store_episode(episode)?;
+        Ok(version)
+    }
+
+    /// Soft-delete an episode: it stays in the log but queries skip it until `restore`, and
+    /// `purge_deleted` removes it for good. Appends the episode with the version incremented.
+    /// No-op if already deleted.
+    pub fn soft_delete(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        self.set_deleted(id, true)
+    }
+
+    /// Undo `soft_delete`. No-op if the episode isn't deleted.
+    pub fn restore(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        self.set_deleted(id, false)
+    }
+
+    /// Permanently remove every soft-deleted episode, compacting the log. Returns episodes
+    /// removed.
+    pub fn purge_deleted(&mut self) -> Result<usize, AgentMemError> {
+        self.delete_where(|ep| ep.deleted)
+    }
+
+    fn set_deleted(&mut self, id: Uuid, deleted: bool) -> Result<(), AgentMemError> {
+        let current = self.episodes.get(&id).ok_or(AgentMemError::NotFound)?;
+        if current.deleted == deleted {
+            return Ok(());
+        }
+        let mut episode = Episode::clone(current);
+        episode.deleted = deleted;
+        episode.version += 1;
+        let line = serde_json::to_string(&episode).map_err(AgentMemError::serde("Serialize"))?;
+        self.append(line)?;
+        // The embedding is unchanged, so the index entry stays.
+        self.episodes.insert(id, Arc::new(episode));
+        self.wrote(1);
+        Ok(())
+    }
 
+    fn insert_indexed(&mut self, episode: Episode) {
+        // Retrievals of a replaced episode count for the old one, not the replacement.
+        self.fold_access_stats();
         let id = episode.id;
+        if let Some(old) = self.episodes.get(&id) {
+            // Replacing: drop the old index entry so queries don't return the episode twice.
+            let filters = &mut self.filters;
+            self.key_to_uuid.retain(|&key, uuid| {
+                let stale = *uuid == id;
+                if stale {
+                    filters.remove(key, old);
+                }
+                !stale
+            });
+        }
         let key = self.index.insert(&episode.state_embedding);
         self.key_to_uuid.insert(key, id);
-        self.episodes.insert(id, episode);
+        self.filters.insert(key, &episode);
+        self.episodes.insert(id, Arc::new(episode));
+    }
+
+    /// Store a batch all-or-nothing. Every dimension and size limit is checked before anything
+    /// is written; the batch then goes to the log between begin/commit markers in one fsync'd
+    /// write, so a crash mid-batch leaves none of it visible on replay. A single episode is stored as by
+    /// `store_episode`.
+    pub fn store_episodes(&mut self, mut episodes: Vec<Episode>) -> Result<(), AgentMemError> {
+        if let Some(ep) = episodes
+            .iter()
+            .find(|ep| ep.state_embedding.len() != self.dim)
+        {
+            return Err(AgentMemError::DimensionMismatch {
+                expected: self.dim,
+                got: ep.state_embedding.len(),
+            });
+        }
+        episodes
+            .iter()
+            .try_for_each(|ep| check_finite(&ep.state_embedding))?;
+        self.limits.check_all(&episodes)?;
+        self.index.check_room(episodes.len())?;
+        if self.auto_timestamp {
+            auto_timestamp::stamp(&mut episodes);
+        }
+        if episodes.len() <= 1 {
+            return episodes
+                .into_iter()
+                .try_for_each(|ep| self.store_episode(ep));
+        }
+        let batch = Uuid::new_v4();
+        let serialize = |r: &LogRecord| r.to_line().map_err(AgentMemError::serde("Serialize"));
+        let mut data = serialize(&LogRecord::BatchBegin {
+            batch,
+            count: episodes.len(),
+        })?;
+        data.push('\n');
+        for ep in &episodes {
+            data.push_str(&serde_json::to_string(ep).map_err(AgentMemError::serde("Serialize"))?);
+            data.push('\n');
+        }
+        data.push_str(&serialize(&LogRecord::BatchCommit { batch })?);
+        data.push('\n');
+
+        // Buffered single writes come first, as they were made first.
+        self.flush()?;
+        self.write_log(&data)?;
+
+        let count = episodes.len();
+        for ep in episodes {
+            self.insert_indexed(ep);
+        }
+        self.wrote(count);
         Ok(())
     }
 
@@ -318,235 +827,648 @@ impl AgentMemDBDisk {
         query_embedding: &[f32],
         opts: QueryOptions,
     ) -> Result<Vec<Episode>, AgentMemError> {
+        self.query_similar_timed(query_embedding, opts)
+            .map(|results| results.episodes)
+    }
+
+    /// Like `query_similar_with_options`, and also report whether `opts.timeout_ms` cut the
+    /// search short (see `AgentMemDB::query_similar_timed`).
+    pub fn query_similar_timed(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<QueryResults, AgentMemError> {
+        let started = Instant::now();
+        let (hits, truncated) = self.ranked_hits(query_embedding, &opts)?;
+        let mut episodes: Vec<Episode> = hits.iter().map(|ep| opts.project_ref(ep)).collect();
+        self.access.record(&mut episodes);
+        if let Some(ref log) = self.query_log {
+            log.record(query_embedding, &opts, &episodes, started.elapsed());
+        }
+        Ok(QueryResults {
+            episodes,
+            truncated,
+        })
+    }
+
+    /// Like `query_similar_with_options`, but return the stored episodes themselves, shared
+    /// (see `AgentMemDB::query_similar_shared`).
+    pub fn query_similar_shared(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Arc<Episode>>, AgentMemError> {
+        let started = Instant::now();
+        let (hits, _) = self.ranked_hits(query_embedding, &opts)?;
+        self.access.record_shared(&hits);
+        if let Some(ref log) = self.query_log {
+            log.record(query_embedding, &opts, &hits, started.elapsed());
+        }
+        Ok(hits)
+    }
+
+    /// The stored episodes a query returns, best first, and whether the search was cut short.
+    pub(crate) fn ranked_hits(
+        &self,
+        query_embedding: &[f32],
+        opts: &QueryOptions,
+    ) -> Result<(Vec<Arc<Episode>>, bool), AgentMemError> {
         if query_embedding.len() != self.dim {
             return Err(AgentMemError::DimensionMismatch {
                 expected: self.dim,
                 got: query_embedding.len(),
             });
         }
-        let candidate_mult =
-            if opts.tags_any.is_some() || opts.time_after.is_some() || opts.time_before.is_some() {
-                4
-            } else {
-                2
-            };
-        let results = self
-            .index
-            .search(query_embedding, opts.top_k * candidate_mult);
-        let episodes: Vec<Episode> = results
+        opts.check_avoid_dim(self.dim)?;
+        let allowed = self.filters.allowed(opts);
+        let (results, _, truncated) = search_candidates(
+            &self.index,
+            allowed.as_ref(),
+            |key| self.vector(key),
+            query_embedding,
+            opts,
+            |key| self.key_matches(key, opts),
+        );
+        opts.check_cancelled()?;
+        let mut ranked: Vec<(f32, &Arc<Episode>)> = results
             .into_iter()
-            .filter_map(|(key, _)| {
+            .filter_map(|(key, distance)| {
                 self.key_to_uuid
                     .get(&key)
                     .and_then(|uuid| self.episodes.get(uuid))
+                    .map(|ep| (distance, ep))
+            })
+            .filter(|(_, ep)| opts.matches(ep))
+            .collect();
+        opts.apply_avoid(&mut ranked, |ep| ep.state_embedding.as_slice());
+        opts.apply_recency_boost(&mut ranked, |ep| ep.timestamp);
+        if opts.deterministic_order || opts.reranks() {
+            ranked.sort_by(|a, b| opts.rank(&(a.0, a.1.as_ref()), &(b.0, b.1.as_ref())));
+        }
+        opts.take_results(&mut ranked, |ep| ep.task_id.clone());
+        let hits = ranked.into_iter().map(|(_, ep)| Arc::clone(ep)).collect();
+        Ok((hits, truncated))
+    }
+
+    /// Whether the episode at index `key` passes `opts`.
+    fn key_matches(&self, key: usize, opts: &QueryOptions) -> bool {
+        self.key_to_uuid
+            .get(&key)
+            .and_then(|uuid| self.episodes.get(uuid))
+            .is_some_and(|ep| opts.matches(ep))
+    }
+
+    /// Embedding of the episode at index `key`.
+    fn vector(&self, key: usize) -> Option<&[f32]> {
+        self.key_to_uuid
+            .get(&key)
+            .and_then(|uuid| self.episodes.get(uuid))
+            .map(|ep| ep.state_embedding.as_slice())
+    }
+
+    /// Episodes whose mean distance to their `k` nearest neighbours is more than `threshold`
+    /// standard deviations above the average (see `AgentMemDB::find_outliers`).
+    pub fn find_outliers(&self, k: usize, threshold: f32) -> Vec<Outlier> {
+        outliers::find_outliers(
+            &self.index,
+            |key| {
+                self.key_to_uuid
+                    .get(&key)
+                    .and_then(|uuid| self.episodes.get(uuid))
+                    .map(|ep| ep.as_ref())
+            },
+            k,
+            threshold,
+        )
+    }
+
+    /// Run a query like `query_similar_with_options` and report every index candidate,
+    /// which filter (if any) eliminated it, and where the time went.
+    pub fn explain_query(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<QueryExplanation, AgentMemError> {
+        if query_embedding.len() != self.dim {
+            return Err(AgentMemError::DimensionMismatch {
+                expected: self.dim,
+                got: query_embedding.len(),
+            });
+        }
+        opts.check_avoid_dim(self.dim)?;
+        let candidate_multiplier = opts.candidate_multiplier();
+        let start = Instant::now();
+        let allowed = self.filters.allowed(&opts);
+        let (results, candidates_requested, truncated) = search_candidates(
+            &self.index,
+            allowed.as_ref(),
+            |key| self.vector(key),
+            query_embedding,
+            &opts,
+            |key| self.key_matches(key, &opts),
+        );
+        let search_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut candidates: Vec<ExplainedCandidate> = results
+            .into_iter()
+            .filter_map(|(key, distance)| {
+                let ep = self
+                    .key_to_uuid
+                    .get(&key)
+                    .and_then(|u| self.episodes.get(u))?;
+                Some(ExplainedCandidate {
+                    episode: Episode::clone(ep),
+                    distance,
+                    rejected_by: opts.rejected_by(ep),
+                    returned: false,
+                })
+            })
+            .collect();
+        let mut ranked: Vec<(f32, usize)> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.rejected_by.is_none())
+            .map(|(i, c)| (c.distance, i))
+            .collect();
+        for (distance, i) in ranked.iter_mut() {
+            *distance += opts.avoid_penalty(&candidates[*i].episode.state_embedding);
+        }
+        opts.apply_recency_boost(&mut ranked, |&i| candidates[i].episode.timestamp);
+        if opts.deterministic_order || opts.reranks() {
+            ranked.sort_by(|a, b| {
+                opts.rank(
+                    &(a.0, &candidates[a.1].episode),
+                    &(b.0, &candidates[b.1].episode),
+                )
+            });
+        }
+        opts.take_results(&mut ranked, |&i| candidates[i].episode.task_id.clone());
+        let results = ranked
+            .iter()
+            .map(|&(_, i)| {
+                candidates[i].returned = true;
+                opts.project(candidates[i].episode.clone())
             })
-            .filter(|ep| opts.matches(ep))
-            .take(opts.top_k)
-            .cloned()
             .collect();
-        Ok(episodes)
+        Ok(QueryExplanation {
+            candidate_multiplier,
+            allowed_keys: allowed.as_ref().map(KeySet::len),
+            tag_index: None,
+            user_partition: false,
+            candidates_requested,
+            truncated,
+            candidates,
+            results,
+            search_time,
+            filter_time: start.elapsed(),
+        })
     }
 
     /// Prune episodes with timestamp older than cutoff (Unix ms).
     /// Episodes without timestamp are kept. Compacts the log file. Returns episodes removed.
     pub fn prune_older_than(&mut self, timestamp_cutoff_ms: i64) -> Result<usize, AgentMemError> {
-        let kept: Vec<Episode> = self
+        self.prune(&[PruneRule::OlderThan(timestamp_cutoff_ms)])
+    }
+
+    /// Prune to keep only the n most recent episodes (by timestamp). Compacts the log.
+    /// Episodes without timestamp are treated as oldest. Returns episodes removed.
+    pub fn prune_keep_newest(&mut self, n: usize) -> Result<usize, AgentMemError> {
+        self.prune(&[PruneRule::KeepNewest(n)])
+    }
+
+    /// Prune to keep only the n episodes with highest reward. Compacts the log.
+    pub fn prune_keep_highest_reward(&mut self, n: usize) -> Result<usize, AgentMemError> {
+        self.prune(&[PruneRule::KeepHighestReward(n)])
+    }
+
+    /// Remove every episode matching `predicate`, e.g. by id or user. Compacts the log.
+    /// Returns episodes removed.
+    pub fn delete_where(
+        &mut self,
+        predicate: impl Fn(&Episode) -> bool,
+    ) -> Result<usize, AgentMemError> {
+        self.fold_access_stats();
+        let kept: Vec<Arc<Episode>> = self
             .episodes
             .values()
-            .filter(|ep| {
-                ep.timestamp
-                    .map(|t| t >= timestamp_cutoff_ms)
-                    .unwrap_or(true)
-            })
+            .filter(|ep| !predicate(ep))
             .cloned()
             .collect();
         let removed = self.episodes.len() - kept.len();
         if removed == 0 {
             return Ok(0);
         }
+        self.compact(kept)?;
+        self.remove_checkpoint_if_exists()?;
+        Ok(removed)
+    }
 
-        self.episodes.clear();
-        self.key_to_uuid.clear();
-        let was_exact = matches!(&self.index, IndexBackend::Exact(_));
-        self.index = if was_exact {
-            IndexBackend::Exact(ExactIndex::new())
-        } else {
-            IndexBackend::Hnsw(Box::new(HnswIndex::new(
-                kept.len().max(20_000).max(self.dim * 2),
-            )))
-        };
+    /// Remove every episode of the session `session_id`. Compacts the log.
+    /// Returns episodes removed.
+    pub fn prune_session(&mut self, session_id: &str) -> Result<usize, AgentMemError> {
+        self.prune_where(|ep| ep.session_id.as_deref() == Some(session_id))
+    }
 
-        for ep in &kept {
-            let id = ep.id;
-            let key = self.index.insert(&ep.state_embedding);
-            self.key_to_uuid.insert(key, id);
-            self.episodes.insert(id, ep.clone());
-        }
+    /// Reclaim disk space: rewrite the log with one line per stored episode (dropping
+    /// superseded versions, batch markers and purged episodes), rewrite the checkpoint when
+    /// checkpoints are enabled, and remove files nothing reads any more (a checkpoint that
+    /// can't be used, temporary files left by a crash). Soft-deleted episodes are kept.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDBDisk, DiskOptions, Episode};
+    /// let dir = std::env::temp_dir().join("agent_mem_db_vacuum_doctest");
+    /// let _ = std::fs::remove_dir_all(&dir);
+    /// let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    /// let ep = Episode::new("t", vec![0.0, 1.0], 1.0);
+    /// db.store_episode(ep.clone()).unwrap();
+    /// db.store_episode(ep).unwrap();
+    /// let report = db.vacuum().unwrap();
+    /// assert_eq!(report.records_removed, 1);
+    /// assert!(report.bytes_after < report.bytes_before);
+    /// ```
+    pub fn vacuum(&mut self) -> Result<VacuumReport, AgentMemError> {
+        let bytes_before = dir_size(&self.path)?;
+        let records_before = Self::count_log_lines(&self.path.join(EPISODES_LOG))?;
+        // Keep store order, so the rewritten log replays to the same index order.
+        let mut keys: Vec<(&usize, &Uuid)> = self.key_to_uuid.iter().collect();
+        keys.sort_unstable_by_key(|&(key, _)| *key);
+        let kept: Vec<Arc<Episode>> = keys
+            .into_iter()
+            .filter_map(|(_, id)| self.episodes.get(id).cloned())
+            .collect();
+        let records_after = kept.len();
+        self.compact(kept)?;
 
-        let log_path = self.path.join(EPISODES_LOG);
-        drop(std::mem::replace(&mut self.log_file, {
-            let mut f = File::create(&log_path)
-                .map_err(|e| AgentMemError::HnswError(format!("Create log for compaction: {e}")))?;
-            for ep in &kept {
-                let line = serde_json::to_string(ep)
-                    .map_err(|e| AgentMemError::HnswError(format!("Serialize: {e}")))?;
-                writeln!(f, "{}", line)
-                    .map_err(|e| AgentMemError::HnswError(format!("Write log: {e}")))?;
+        let checkpointed = self.use_checkpoint && matches!(self.index, IndexBackend::Exact(_));
+        if checkpointed {
+            self.checkpoint()?;
+        } else {
+            self.remove_checkpoint_if_exists()?;
+        }
+        for entry in fs::read_dir(&self.path).map_err(AgentMemError::io("Read dir"))? {
+            let path = entry.map_err(AgentMemError::io("Read dir"))?.path();
+            if path.to_string_lossy().ends_with(TMP_SUFFIX) {
+                fs::remove_file(&path).map_err(AgentMemError::io("Remove orphan"))?;
             }
-            f.sync_all()
-                .map_err(|e| AgentMemError::HnswError(format!("Sync log: {e}")))?;
-            drop(f);
-            OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&log_path)
-                .map_err(|e| AgentMemError::HnswError(format!("Reopen log: {e}")))?
-        }));
+        }
 
-        self.remove_checkpoint_if_exists()?;
-        self.log_file
-            .sync_all()
-            .map_err(|e| AgentMemError::HnswError(format!("Sync log: {e}")))?;
-        Ok(removed)
+        Ok(VacuumReport {
+            bytes_before,
+            bytes_after: dir_size(&self.path)?,
+            records_removed: records_before.saturating_sub(records_after),
+        })
     }
 
-    /// Prune to keep only the n most recent episodes (by timestamp). Compacts the log.
-    /// Episodes without timestamp are treated as oldest. Returns episodes removed.
-    pub fn prune_keep_newest(&mut self, n: usize) -> Result<usize, AgentMemError> {
-        if self.episodes.len() <= n {
-            return Ok(0);
+    /// Rebuild the index from `kept` and replace the log with one line per episode (see
+    /// `replace_with`). Callers deal with the checkpoint, which no longer matches.
+    pub(crate) fn compact(&mut self, kept: Vec<Arc<Episode>>) -> Result<(), AgentMemError> {
+        let rebuilt = self.rebuild(kept);
+        self.replace_with(rebuilt)
+    }
+
+    /// Replace the log with one line per episode of `rebuilt`, then install its episodes and
+    /// index. The new log is written beside the old one and renamed over it, so a crash leaves
+    /// either log intact and an open handle on the old log (see `begin_snapshot`) keeps reading
+    /// it. If writing fails, the DB is left as it was.
+    pub(crate) fn replace_with(&mut self, rebuilt: Rebuilt) -> Result<(), AgentMemError> {
+        let log_path = self.path.join(EPISODES_LOG);
+        let tmp_path = self.path.join(format!("{EPISODES_LOG}{TMP_SUFFIX}"));
+        let mut f =
+            File::create(&tmp_path).map_err(AgentMemError::io("Create log for compaction"))?;
+        for ep in rebuilt.in_key_order() {
+            let line = serde_json::to_string(&**ep).map_err(AgentMemError::serde("Serialize"))?;
+            writeln!(f, "{}", line).map_err(AgentMemError::io("Write log"))?;
         }
-        let mut episodes: Vec<Episode> = self.episodes.drain().map(|(_, ep)| ep).collect();
-        let original = episodes.len();
-        episodes.sort_by(|a, b| {
-            let ts_a = a.timestamp.unwrap_or(i64::MIN);
-            let ts_b = b.timestamp.unwrap_or(i64::MIN);
-            ts_b.cmp(&ts_a)
-        });
-        let kept: Vec<Episode> = episodes.into_iter().take(n).collect();
-        let removed = original - kept.len();
+        f.sync_all().map_err(AgentMemError::io("Sync log"))?;
+        drop(f);
+        fs::rename(&tmp_path, &log_path).map_err(AgentMemError::io("Replace log"))?;
+        self.log_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .map_err(AgentMemError::io("Reopen log"))?;
+        self.discard_pending();
+
+        self.episodes = rebuilt.episodes;
+        self.index = rebuilt.index;
+        self.key_to_uuid = rebuilt.key_to_uuid;
+        self.filters = rebuilt.filters;
+        Ok(())
+    }
 
-        self.key_to_uuid.clear();
-        let was_exact = matches!(&self.index, IndexBackend::Exact(_));
-        self.index = if was_exact {
-            IndexBackend::Exact(ExactIndex::new())
+    /// Copy the DB to a new directory at `path` (see `begin_snapshot`), as of this call.
+    pub fn snapshot_to(&mut self, path: impl AsRef<Path>) -> Result<(), AgentMemError> {
+        self.begin_snapshot()?.write_to(path)
+    }
+
+    /// Mark a consistent snapshot without copying anything yet: pending group-commit writes
+    /// are committed and the log's length is recorded. The returned [`DiskSnapshot`] copies
+    /// the log up to that point whenever `write_to` is called, without borrowing the DB, so
+    /// a server can release its lock and keep accepting stores while a backup is written.
+    /// Later appends land past the marker, and compaction replaces the log rather than
+    /// rewriting it, so the copy is unaffected by either.
+    pub fn begin_snapshot(&mut self) -> Result<DiskSnapshot, AgentMemError> {
+        self.flush()?;
+        self.save_access_stats()?;
+        let access_path = self.path.join(ACCESS_FILE);
+        let access = if access_path.exists() {
+            Some(fs::read_to_string(&access_path).map_err(AgentMemError::io("Read access stats"))?)
         } else {
-            IndexBackend::Hnsw(Box::new(HnswIndex::new(
-                kept.len().max(20_000).max(self.dim * 2),
-            )))
+            None
         };
+        let log = File::open(self.path.join(EPISODES_LOG))
+            .map_err(AgentMemError::io("Open log for snapshot"))?;
+        let log_len = log
+            .metadata()
+            .map_err(AgentMemError::io("Open log for snapshot"))?
+            .len();
+        let mut meta = read_meta(&self.path)?;
+        // The copy gets its own checkpoint, if any.
+        meta.checkpoint_line_count = None;
+        Ok(DiskSnapshot {
+            log,
+            log_len,
+            meta,
+            access,
+            episodes: self.episodes.len(),
+        })
+    }
 
-        for ep in &kept {
-            let id = ep.id;
-            let key = self.index.insert(&ep.state_embedding);
-            self.key_to_uuid.insert(key, id);
-            self.episodes.insert(id, ep.clone());
+    /// Check the open DB against its files: every log line parses and has the DB's dimension,
+    /// the log holds exactly the stored episodes, every index key maps to a stored episode (and
+    /// every episode to one key below the index length), and a checkpoint that would be loaded
+    /// on open holds as many episodes as the log. Problems are reported, not fixed; an error
+    /// is returned only when the files can't be read.
+    pub fn verify(&self) -> Result<VerifyReport, AgentMemError> {
+        let mut problems = Vec::new();
+        let log_path = self.path.join(EPISODES_LOG);
+        let file = File::open(&log_path).map_err(AgentMemError::io("Open log for verify"))?;
+        let mut log_records = 0;
+        let mut log_ids = std::collections::HashSet::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(AgentMemError::io("Read line"))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            log_records += 1;
+            match LogRecord::parse(line.trim().as_bytes()) {
+                Ok(LogRecord::Episode(ep)) if ep.state_embedding.len() != self.dim => problems
+                    .push(format!(
+                        "log line {}: episode {} has dimension {}, expected {}",
+                        i + 1,
+                        ep.id,
+                        ep.state_embedding.len(),
+                        self.dim
+                    )),
+                Ok(LogRecord::Episode(ep)) => {
+                    log_ids.insert(ep.id);
+                }
+                Ok(_) => {}
+                Err(e) => problems.push(format!("log line {}: {e}", i + 1)),
+            }
+        }
+        // Buffered by group commit: loaded, but not in the file until `flush`.
+        for line in &self.pending {
+            if let Ok(LogRecord::Episode(ep)) = LogRecord::parse(line.as_bytes()) {
+                log_ids.insert(ep.id);
+            }
+        }
+        if log_ids.len() != self.episodes.len()
+            || self.episodes.keys().any(|id| !log_ids.contains(id))
+        {
+            problems.push(format!(
+                "log holds {} episodes but {} are loaded",
+                log_ids.len(),
+                self.episodes.len()
+            ));
         }
 
-        let log_path = self.path.join(EPISODES_LOG);
-        drop(std::mem::replace(&mut self.log_file, {
-            let mut f = File::create(&log_path)
-                .map_err(|e| AgentMemError::HnswError(format!("Create log for compaction: {e}")))?;
-            for ep in &kept {
-                let line = serde_json::to_string(ep)
-                    .map_err(|e| AgentMemError::HnswError(format!("Serialize: {e}")))?;
-                writeln!(f, "{}", line)
-                    .map_err(|e| AgentMemError::HnswError(format!("Write log: {e}")))?;
+        let index_len = self.index.len();
+        let mut mapped = std::collections::HashSet::new();
+        for (&key, id) in &self.key_to_uuid {
+            if key >= index_len {
+                problems.push(format!("key {key} is beyond the index length {index_len}"));
+            }
+            if !self.episodes.contains_key(id) {
+                problems.push(format!("key {key} maps to missing episode {id}"));
+            }
+            if !mapped.insert(*id) {
+                problems.push(format!("episode {id} has more than one index key"));
             }
-            f.sync_all()
-                .map_err(|e| AgentMemError::HnswError(format!("Sync log: {e}")))?;
-            drop(f);
-            OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&log_path)
-                .map_err(|e| AgentMemError::HnswError(format!("Reopen log: {e}")))?
-        }));
+        }
+        if let Some(id) = self.episodes.keys().find(|id| !mapped.contains(*id)) {
+            problems.push(format!("episode {id} has no index key"));
+        }
 
-        self.remove_checkpoint_if_exists()?;
-        self.log_file
-            .sync_all()
-            .map_err(|e| AgentMemError::HnswError(format!("Sync log: {e}")))?;
-        Ok(removed)
+        let checkpoint_path = self.path.join(EXACT_CHECKPOINT_FILE);
+        if matches!(self.index, IndexBackend::Exact(_)) && checkpoint_path.exists() {
+            let meta = read_meta(&self.path)?;
+            // A checkpoint for another log length is ignored on open, so it can't be wrong.
+            if meta.checkpoint_line_count == Some(log_records) {
+                match fs::read_to_string(&checkpoint_path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| {
+                        format::parse::<ExactCheckpoint>(Format::Checkpoint, &data)
+                            .map_err(|e| e.to_string())
+                    }) {
+                    Ok(cp) if cp.episodes.len() != log_ids.len() => problems.push(format!(
+                        "checkpoint holds {} episodes but the log has {}",
+                        cp.episodes.len(),
+                        log_ids.len()
+                    )),
+                    Ok(_) => {}
+                    Err(e) => problems.push(format!("checkpoint: {e}")),
+                }
+            }
+        }
+
+        Ok(VerifyReport {
+            log_records,
+            episodes: self.episodes.len(),
+            index_len,
+            problems,
+        })
     }
 
-    /// Prune to keep only the n episodes with highest reward. Compacts the log.
-    pub fn prune_keep_highest_reward(&mut self, n: usize) -> Result<usize, AgentMemError> {
-        if self.episodes.len() <= n {
-            return Ok(0);
+    /// Save access statistics to `access.json`, as queries don't write to the log. A no-op
+    /// when no query returned anything since the last save. `checkpoint` and dropping the DB
+    /// save them too; on open they are applied over the log's.
+    pub fn save_access_stats(&mut self) -> Result<(), AgentMemError> {
+        self.fold_access_stats();
+        if !self.access.take_unsaved() {
+            return Ok(());
         }
-        let mut episodes: Vec<Episode> = self.episodes.drain().map(|(_, ep)| ep).collect();
-        let original = episodes.len();
-        episodes.sort_by(|a, b| {
-            let reward_cmp = b
-                .reward
-                .partial_cmp(&a.reward)
-                .unwrap_or(std::cmp::Ordering::Equal);
-            if reward_cmp != std::cmp::Ordering::Equal {
-                return reward_cmp;
-            }
-            let ts_a = a.timestamp.unwrap_or(i64::MIN);
-            let ts_b = b.timestamp.unwrap_or(i64::MIN);
-            ts_b.cmp(&ts_a)
-        });
-        let kept: Vec<Episode> = episodes.into_iter().take(n).collect();
-        let removed = original - kept.len();
-
-        self.key_to_uuid.clear();
-        let was_exact = matches!(&self.index, IndexBackend::Exact(_));
-        self.index = if was_exact {
-            IndexBackend::Exact(ExactIndex::new())
-        } else {
-            IndexBackend::Hnsw(Box::new(HnswIndex::new(
-                kept.len().max(20_000).max(self.dim * 2),
-            )))
+        let file = AccessFile {
+            format_version: FORMAT_VERSION,
+            episodes: self
+                .episodes
+                .values()
+                .filter(|ep| ep.times_retrieved > 0)
+                .map(|ep| (ep.id, AccessStats::of(ep)))
+                .collect(),
         };
-
-        for ep in &kept {
-            let id = ep.id;
-            let key = self.index.insert(&ep.state_embedding);
-            self.key_to_uuid.insert(key, id);
-            self.episodes.insert(id, ep.clone());
+        let result = serde_json::to_string(&file)
+            .map_err(AgentMemError::serde("Serialize access stats"))
+            .and_then(|data| {
+                let tmp_path = self.path.join(format!("{ACCESS_FILE}{TMP_SUFFIX}"));
+                fs::write(&tmp_path, data)
+                    .and_then(|()| fs::rename(&tmp_path, self.path.join(ACCESS_FILE)))
+                    .map_err(AgentMemError::io("Write access stats"))
+            });
+        if result.is_err() {
+            // Retry on the next save.
+            self.access.mark_unsaved();
         }
+        result
+    }
 
-        let log_path = self.path.join(EPISODES_LOG);
-        drop(std::mem::replace(&mut self.log_file, {
-            let mut f = File::create(&log_path)
-                .map_err(|e| AgentMemError::HnswError(format!("Create log for compaction: {e}")))?;
-            for ep in &kept {
-                let line = serde_json::to_string(ep)
-                    .map_err(|e| AgentMemError::HnswError(format!("Serialize: {e}")))?;
-                writeln!(f, "{}", line)
-                    .map_err(|e| AgentMemError::HnswError(format!("Write log: {e}")))?;
+    /// Apply `access.json`, if any, over the stats the log has for `episodes`.
+    fn load_access_stats(
+        path: &Path,
+        episodes: &mut HashMap<Uuid, Arc<Episode>>,
+    ) -> Result<(), AgentMemError> {
+        let access_path = path.join(ACCESS_FILE);
+        if !access_path.exists() {
+            return Ok(());
+        }
+        let data =
+            fs::read_to_string(&access_path).map_err(AgentMemError::io("Read access stats"))?;
+        let file: AccessFile = format::parse(Format::AccessStats, &data)?;
+        for (id, saved) in file.episodes {
+            if let Some(ep) = episodes.get_mut(&id) {
+                let ep = Arc::make_mut(ep);
+                AccessStats::of(ep).latest(saved).set_on(ep);
             }
-            f.sync_all()
-                .map_err(|e| AgentMemError::HnswError(format!("Sync log: {e}")))?;
-            drop(f);
-            OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&log_path)
-                .map_err(|e| AgentMemError::HnswError(format!("Reopen log: {e}")))?
-        }));
-
-        self.remove_checkpoint_if_exists()?;
-        self.log_file
-            .sync_all()
-            .map_err(|e| AgentMemError::HnswError(format!("Sync log: {e}")))?;
-        Ok(removed)
+        }
+        Ok(())
     }
 
-    fn remove_checkpoint_if_exists(&self) -> Result<(), AgentMemError> {
+    pub(crate) fn remove_checkpoint_if_exists(&self) -> Result<(), AgentMemError> {
         let p = self.path.join(EXACT_CHECKPOINT_FILE);
         if p.exists() {
-            fs::remove_file(&p)
-                .map_err(|e| AgentMemError::HnswError(format!("Remove checkpoint: {e}")))?;
+            fs::remove_file(&p).map_err(AgentMemError::io("Remove checkpoint"))?;
         }
         Ok(())
     }
 }
 
+/// A consistent point in a disk DB's log, from `AgentMemDBDisk::begin_snapshot`.
+pub struct DiskSnapshot {
+    /// Handle on the log as of the snapshot; still readable after the DB replaces its log.
+    log: File,
+    log_len: u64,
+    meta: DiskMeta,
+    /// `access.json` as of the snapshot, if any.
+    access: Option<String>,
+    episodes: usize,
+}
+
+impl DiskSnapshot {
+    /// Episodes in the DB when the snapshot was taken.
+    pub fn len(&self) -> usize {
+        self.episodes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.episodes == 0
+    }
+
+    /// Write the snapshot as a new DB directory at `path` (which must not hold a DB): its
+    /// `meta.json`, the log up to the snapshot, its access statistics, and for an exact index
+    /// a fresh checkpoint so the copy opens without replay.
+    pub fn write_to(self, path: impl AsRef<Path>) -> Result<(), AgentMemError> {
+        let path = path.as_ref();
+        if path.join(META_FILE).exists() {
+            return Err(AgentMemError::AlreadyExists(format!(
+                "DB at snapshot target {}",
+                path.display()
+            )));
+        }
+        fs::create_dir_all(path).map_err(AgentMemError::io("Create dir"))?;
+        let mut out =
+            File::create(path.join(EPISODES_LOG)).map_err(AgentMemError::io("Create log"))?;
+        let copied = std::io::copy(&mut (&self.log).take(self.log_len), &mut out)
+            .and_then(|n| out.sync_all().map(|()| n))
+            .map_err(AgentMemError::io("Copy log"))?;
+        if copied != self.log_len {
+            return Err(AgentMemError::io("Copy log")(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("expected {} bytes, read {copied}", self.log_len),
+            )));
+        }
+        let meta_json = serde_json::to_string_pretty(&self.meta)
+            .map_err(AgentMemError::serde("Serialize meta"))?;
+        fs::write(path.join(META_FILE), meta_json).map_err(AgentMemError::io("Write meta"))?;
+        if let Some(access) = &self.access {
+            fs::write(path.join(ACCESS_FILE), access)
+                .map_err(AgentMemError::io("Write access stats"))?;
+        }
+        if self.meta.index_type == "exact" {
+            AgentMemDBDisk::open_with_options(
+                path,
+                DiskOptions::exact_with_checkpoint(self.meta.dim),
+            )?
+            .checkpoint()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AgentMemDBDisk {
+    /// Commit writes still buffered by group commit and save access statistics. Errors can't
+    /// be reported here; call `flush` and `save_access_stats` first to see them.
+    fn drop(&mut self) {
+        let _ = self.flush();
+        let _ = self.save_access_stats();
+    }
+}
+
+/// What `AgentMemDBDisk::vacuum` reclaimed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct VacuumReport {
+    /// Total size of the DB directory's files before vacuuming.
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    /// Log lines dropped: superseded versions, batch markers and purged episodes.
+    pub records_removed: usize,
+}
+
+/// What `AgentMemDBDisk::verify` found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    /// Non-empty log lines, batch markers included.
+    pub log_records: usize,
+    /// Episodes loaded.
+    pub episodes: usize,
+    /// Vectors in the index, including ones superseded by updates.
+    pub index_len: usize,
+    /// One message per inconsistency; empty when the DB is sound.
+    pub problems: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Total size of the regular files directly in `dir`.
+fn dir_size(dir: &Path) -> Result<u64, AgentMemError> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir).map_err(AgentMemError::io("Read dir"))? {
+        let meta = entry
+            .and_then(|entry| entry.metadata())
+            .map_err(AgentMemError::io("Read dir"))?;
+        if meta.is_file() {
+            total += meta.len();
+        }
+    }
+    Ok(total)
+}
+
 /// Options for opening a disk-backed DB.
+#[derive(Debug, Clone)]
 pub struct DiskOptions {
     pub dim: usize,
     pub index_type: Option<String>,
@@ -554,6 +1476,34 @@ pub struct DiskOptions {
     /// If true and index is ExactIndex, enables checkpoint for fast restart.
     /// Call `checkpoint()` to persist; on next open, replay is skipped when checkpoint is valid.
     pub use_checkpoint: bool,
+    /// HNSW graph parameters for a new DB; an existing DB keeps the ones it was created with.
+    pub hnsw_params: HnswParams,
+    /// Checkpoint automatically after writes (see `auto_checkpoint`). Needs `use_checkpoint`.
+    pub auto_checkpoint: Option<AutoCheckpoint>,
+    /// Commit single writes in groups (see `group_commit`). `None` fsyncs every write.
+    pub group_commit: Option<GroupCommit>,
+}
+
+/// Group commit for single writes (`store_episode`, `update_episode`, `soft_delete`,
+/// `restore`): they are buffered and written with one fsync once `max_records` are pending,
+/// or on the first write `window_ms` milliseconds after the oldest pending one.
+///
+/// There is no background timer: `window_ms` is only checked when the next write arrives, so
+/// once writes stop, acknowledged writes stay buffered (and are lost in a crash) until the
+/// next write, `flush()`, checkpoint or drop. Call `flush()` periodically to bound that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupCommit {
+    pub window_ms: u64,
+    pub max_records: usize,
+}
+
+/// When a disk DB with checkpoints checkpoints on its own: once `every_n` episodes have been
+/// written since the last checkpoint, or on the first write `every_ms` milliseconds after it.
+/// Zero disables that trigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoCheckpoint {
+    pub every_n: usize,
+    pub every_ms: u64,
 }
 
 impl DiskOptions {
@@ -563,6 +1513,9 @@ impl DiskOptions {
             index_type: Some("hnsw".to_string()),
             max_elements,
             use_checkpoint: false,
+            hnsw_params: HnswParams::default(),
+            auto_checkpoint: None,
+            group_commit: None,
         }
     }
 
@@ -572,9 +1525,57 @@ impl DiskOptions {
             index_type: Some("exact".to_string()),
             max_elements: 0, // unused for exact
             use_checkpoint: false,
+            hnsw_params: HnswParams::default(),
+            auto_checkpoint: None,
+            group_commit: None,
         }
     }
 
+    /// Use custom HNSW parameters (`params.m` must be at least 2).
+    pub fn with_hnsw_params(mut self, params: HnswParams) -> Self {
+        self.hnsw_params = params;
+        self
+    }
+
+    /// Checkpoint after every `every_n` episodes written, or on the first write `every_ms`
+    /// milliseconds after the last checkpoint (zero disables either trigger), so restart time
+    /// stays bounded without callers remembering to call `checkpoint()`. Only applies with
+    /// checkpoints enabled (`exact_with_checkpoint`).
+    ///
+    /// ```rust
+    /// use agent_mem_db::DiskOptions;
+    /// let opts = DiskOptions::exact_with_checkpoint(16).auto_checkpoint(1_000, 60_000);
+    /// assert_eq!(opts.auto_checkpoint.unwrap().every_n, 1_000);
+    /// ```
+    pub fn auto_checkpoint(mut self, every_n: usize, every_ms: u64) -> Self {
+        self.auto_checkpoint = Some(AutoCheckpoint { every_n, every_ms });
+        self
+    }
+
+    /// Commit single writes in groups of up to `max_records`, or `window_ms` apart, with one
+    /// fsync each, instead of one fsync per write. Sustained single-episode ingest is much
+    /// faster, but a write is only durable once its group is committed: a crash loses the
+    /// pending group (never part of it), and the window is only checked on writes, so call
+    /// `flush` at quiet points. Checkpoints, compaction and dropping the DB also flush. Batch
+    /// writes (`store_episodes`) are committed at once, as before.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDBDisk, DiskOptions, Episode};
+    /// let dir = std::env::temp_dir().join("agent_mem_db_group_commit_doctest");
+    /// let _ = std::fs::remove_dir_all(&dir);
+    /// let opts = DiskOptions::exact(2).group_commit(10, 64);
+    /// let mut db = AgentMemDBDisk::open_with_options(&dir, opts).unwrap();
+    /// db.store_episode(Episode::new("t", vec![0.0, 1.0], 1.0)).unwrap();
+    /// db.flush().unwrap();
+    /// ```
+    pub fn group_commit(mut self, window_ms: u64, max_records: usize) -> Self {
+        self.group_commit = Some(GroupCommit {
+            window_ms,
+            max_records,
+        });
+        self
+    }
+
     /// Exact index with checkpoint enabled for fast restart.
     pub fn exact_with_checkpoint(dim: usize) -> Self {
         Self {
@@ -582,6 +1583,9 @@ impl DiskOptions {
             index_type: Some("exact".to_string()),
             max_elements: 0,
             use_checkpoint: true,
+            hnsw_params: HnswParams::default(),
+            auto_checkpoint: None,
+            group_commit: None,
         }
     }
 }
@@ -0,0 +1,139 @@
+//! Embedding drift detection: comparing the distribution of recent embeddings against older
+//! ones, to catch an upstream embedding model change that silently degrades retrieval.
+
+use crate::{AgentMemDB, AgentMemDBDisk, Episode};
+
+/// How far the embeddings stored within a recent window have moved from the older ones.
+/// Covariance is compared per dimension (the diagonal), so the report costs O(n·dim).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftReport {
+    /// Episodes with a timestamp inside the window.
+    pub recent_count: usize,
+    /// Episodes with an older timestamp.
+    pub historical_count: usize,
+    /// L2 distance between the recent and historical centroids.
+    pub centroid_shift: f32,
+    /// Root-mean-square distance of historical embeddings from their centroid; the scale
+    /// `centroid_shift` is measured against.
+    pub historical_spread: f32,
+    /// Relative change in per-dimension variance: `‖var_recent − var_hist‖ / ‖var_hist‖`.
+    pub variance_shift: f32,
+    /// `centroid_shift / historical_spread + variance_shift`. Near 0 when recent embeddings
+    /// look like older ones; around 1 or more means the centroid moved by a whole spread or
+    /// the variances changed by their own size, as after a model swap.
+    pub score: f32,
+}
+
+impl DriftReport {
+    /// Compare embeddings timestamped within `window_ms` of the newest timestamp against all
+    /// older ones. Undated and soft-deleted episodes are ignored. `None` when either side is
+    /// empty.
+    pub fn between<'a>(
+        episodes: impl IntoIterator<Item = &'a Episode>,
+        window_ms: i64,
+    ) -> Option<Self> {
+        let dated: Vec<(i64, &[f32])> = episodes
+            .into_iter()
+            .filter(|ep| !ep.deleted)
+            .filter_map(|ep| Some((ep.timestamp?, ep.state_embedding.as_slice())))
+            .collect();
+        let newest = dated.iter().map(|&(ts, _)| ts).max()?;
+        let cutoff = newest.saturating_sub(window_ms);
+        let (recent, historical): (Vec<_>, Vec<_>) =
+            dated.into_iter().partition(|&(ts, _)| ts > cutoff);
+        if recent.is_empty() || historical.is_empty() {
+            return None;
+        }
+        let recent = Moments::of(recent.iter().map(|&(_, v)| v));
+        let hist = Moments::of(historical.iter().map(|&(_, v)| v));
+
+        let centroid_shift = norm(recent.mean.iter().zip(&hist.mean).map(|(a, b)| a - b));
+        let historical_spread = hist.var.iter().sum::<f64>().sqrt();
+        let variance_diff = norm(recent.var.iter().zip(&hist.var).map(|(a, b)| a - b));
+        let variance_norm = norm(hist.var.iter().copied());
+        let variance_shift = relative(variance_diff, variance_norm);
+        let score = relative(centroid_shift, historical_spread) + variance_shift;
+        Some(Self {
+            recent_count: recent.count,
+            historical_count: hist.count,
+            centroid_shift: centroid_shift as f32,
+            historical_spread: historical_spread as f32,
+            variance_shift: variance_shift as f32,
+            score: score as f32,
+        })
+    }
+}
+
+/// Per-dimension mean and variance of a set of embeddings.
+struct Moments {
+    count: usize,
+    mean: Vec<f64>,
+    var: Vec<f64>,
+}
+
+impl Moments {
+    fn of<'a>(vecs: impl Iterator<Item = &'a [f32]> + Clone) -> Self {
+        let count = vecs.clone().count();
+        let dim = vecs.clone().map(<[f32]>::len).max().unwrap_or(0);
+        let mut mean = vec![0.0f64; dim];
+        for v in vecs.clone() {
+            for (m, &x) in mean.iter_mut().zip(v) {
+                *m += x as f64;
+            }
+        }
+        mean.iter_mut().for_each(|m| *m /= count as f64);
+        let mut var = vec![0.0f64; dim];
+        for v in vecs {
+            for ((s, &x), m) in var.iter_mut().zip(v).zip(&mean) {
+                *s += (x as f64 - m).powi(2);
+            }
+        }
+        var.iter_mut().for_each(|s| *s /= count as f64);
+        Self { count, mean, var }
+    }
+}
+
+fn norm(xs: impl Iterator<Item = f64>) -> f64 {
+    xs.map(|x| x * x).sum::<f64>().sqrt()
+}
+
+/// `value / scale`, where a zero scale (identical historical embeddings) counts any change as
+/// a full unit of drift.
+fn relative(value: f64, scale: f64) -> f64 {
+    if scale > f64::EPSILON {
+        value / scale
+    } else if value > f64::EPSILON {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl AgentMemDB {
+    /// Compare embeddings stored within `window_ms` of the newest episode against older ones
+    /// (see `DriftReport::between`). A rising score after a deploy suggests the embedding model
+    /// changed and old and new vectors are no longer comparable.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// for ts in 0..10 {
+    ///     let v = if ts < 5 { vec![ts as f32 * 0.1, 0.0] } else { vec![5.0, ts as f32 * 0.1] };
+    ///     db.store_episode(Episode::with_timestamp("t", v, 1.0, ts)).unwrap();
+    /// }
+    /// let report = db.drift_report(5).unwrap();
+    /// assert_eq!((report.recent_count, report.historical_count), (5, 5));
+    /// assert!(report.score > 1.0);
+    /// ```
+    pub fn drift_report(&self, window_ms: i64) -> Option<DriftReport> {
+        DriftReport::between(self.iter(), window_ms)
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Compare embeddings stored within `window_ms` of the newest episode against older ones
+    /// (see `DriftReport::between`).
+    pub fn drift_report(&self, window_ms: i64) -> Option<DriftReport> {
+        DriftReport::between(self.iter(), window_ms)
+    }
+}
@@ -0,0 +1,493 @@
+//! Text embedding providers, attachable to a DB so episodes can be stored and queried by text.
+//!
+//! Built-in providers are behind features: `openai` (any OpenAI-compatible `/embeddings` API,
+//! including Ollama, vLLM and text-embeddings-inference) and `onnx` (a local sentence-embedding
+//! model run with ONNX Runtime).
+
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode, QueryOptions};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Turns text into embeddings of a fixed dimension.
+pub trait EmbeddingProvider: Send + Sync {
+    /// Output dimension; a DB only accepts a provider whose dimension matches its own.
+    fn dim(&self) -> usize;
+
+    /// Embed one text.
+    fn embed(&self, text: &str) -> Result<Vec<f32>, AgentMemError>;
+
+    /// Embed several texts, in order. The default calls `embed` once per text; providers
+    /// override it to batch requests.
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AgentMemError> {
+        texts.iter().map(|text| self.embed(text)).collect()
+    }
+
+    /// Model name/version, recorded as `embedding_model` on the episodes this provider embeds
+    /// for `store_text`, `store_episode_with_text` and `reembed_all`. The default `None` leaves
+    /// the tag to the caller.
+    fn model(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Check a provider's dimension against the DB's before attaching it.
+fn check_provider(provider: &Arc<dyn EmbeddingProvider>, dim: usize) -> Result<(), AgentMemError> {
+    if provider.dim() != dim {
+        return Err(AgentMemError::DimensionMismatch {
+            expected: dim,
+            got: provider.dim(),
+        });
+    }
+    Ok(())
+}
+
+/// Tag `episode` with the attached provider's model, if it names one.
+fn tag_model(provider: Option<&Arc<dyn EmbeddingProvider>>, episode: &mut Episode) {
+    if let Some(model) = provider.and_then(|p| p.model()) {
+        episode.embedding_model = Some(model.to_string());
+    }
+}
+
+/// Embed with the attached provider, checking what it returns against the DB dimension.
+fn embed_with(
+    provider: Option<&Arc<dyn EmbeddingProvider>>,
+    dim: usize,
+    texts: &[&str],
+) -> Result<Vec<Vec<f32>>, AgentMemError> {
+    let provider = provider.ok_or_else(|| {
+        AgentMemError::Embedding("no embedding provider attached (set_embedder)".to_string())
+    })?;
+    embed_checked(provider.as_ref(), dim, texts)
+}
+
+/// Embed with `provider`, checking that it returns one `dim`-long embedding per text.
+pub(crate) fn embed_checked(
+    provider: &dyn EmbeddingProvider,
+    dim: usize,
+    texts: &[&str],
+) -> Result<Vec<Vec<f32>>, AgentMemError> {
+    let embeddings = provider.embed_batch(texts)?;
+    if embeddings.len() != texts.len() {
+        return Err(AgentMemError::Embedding(format!(
+            "provider returned {} embeddings for {} texts",
+            embeddings.len(),
+            texts.len()
+        )));
+    }
+    if let Some(e) = embeddings.iter().find(|e| e.len() != dim) {
+        return Err(AgentMemError::DimensionMismatch {
+            expected: dim,
+            got: e.len(),
+        });
+    }
+    Ok(embeddings)
+}
+
+impl AgentMemDB {
+    /// Attach an embedding provider for `store_text` and `query_text`. Fails with
+    /// `DimensionMismatch` if the provider's dimension differs from the DB's.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, AgentMemError, EmbeddingProvider};
+    /// use std::sync::Arc;
+    ///
+    /// struct Lengths;
+    /// impl EmbeddingProvider for Lengths {
+    ///     fn dim(&self) -> usize {
+    ///         2
+    ///     }
+    ///     fn embed(&self, text: &str) -> Result<Vec<f32>, AgentMemError> {
+    ///         Ok(vec![text.len() as f32, 0.0])
+    ///     }
+    /// }
+    ///
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.set_embedder(Arc::new(Lengths)).unwrap();
+    /// db.store_text("greet", "hello", 1.0).unwrap();
+    /// let hits = db.query_text("hullo", 0.0, 1).unwrap();
+    /// assert_eq!(hits[0].task_id, "greet");
+    /// ```
+    pub fn set_embedder(
+        &mut self,
+        provider: Arc<dyn EmbeddingProvider>,
+    ) -> Result<(), AgentMemError> {
+        check_provider(&provider, self.dim)?;
+        self.embedder = Some(provider);
+        Ok(())
+    }
+
+    /// Embed texts with the attached provider.
+    pub fn embed_texts(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AgentMemError> {
+        embed_with(self.embedder.as_ref(), self.dim, texts)
+    }
+
+    /// Embed `text` and store it as a new episode (the text itself is not stored; put it in
+    /// `metadata` if needed). Returns the episode id.
+    pub fn store_text(
+        &mut self,
+        task_id: impl Into<String>,
+        text: &str,
+        reward: f32,
+    ) -> Result<Uuid, AgentMemError> {
+        self.store_episode_with_text(Episode::new(task_id, Vec::new(), reward), text)
+    }
+
+    /// Store `episode` (tags, metadata, timestamp, ...) with its `state_embedding` replaced by
+    /// the embedding of `text`, tagged with the provider's `model` if it has one. Returns the
+    /// episode id.
+    pub fn store_episode_with_text(
+        &mut self,
+        mut episode: Episode,
+        text: &str,
+    ) -> Result<Uuid, AgentMemError> {
+        episode.state_embedding = self.embed_texts(&[text])?.remove(0);
+        tag_model(self.embedder.as_ref(), &mut episode);
+        let id = episode.id;
+        self.store_episode(episode)?;
+        Ok(id)
+    }
+
+    /// Query by text: embed it, then `query_similar`.
+    pub fn query_text(
+        &self,
+        text: &str,
+        min_reward: f32,
+        top_k: usize,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        self.query_text_with_options(text, QueryOptions::new(min_reward, top_k))
+    }
+
+    /// Query by text with filters.
+    pub fn query_text_with_options(
+        &self,
+        text: &str,
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        let embedding = self.embed_texts(&[text])?.remove(0);
+        self.query_similar_with_options(&embedding, opts)
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Attach an embedding provider for `store_text` and `query_text`. Fails with
+    /// `DimensionMismatch` if the provider's dimension differs from the DB's.
+    pub fn set_embedder(
+        &mut self,
+        provider: Arc<dyn EmbeddingProvider>,
+    ) -> Result<(), AgentMemError> {
+        check_provider(&provider, self.dim())?;
+        self.embedder = Some(provider);
+        Ok(())
+    }
+
+    /// Embed texts with the attached provider.
+    pub fn embed_texts(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AgentMemError> {
+        embed_with(self.embedder.as_ref(), self.dim(), texts)
+    }
+
+    /// Embed `text` and store it as a new episode. Returns the episode id.
+    pub fn store_text(
+        &mut self,
+        task_id: impl Into<String>,
+        text: &str,
+        reward: f32,
+    ) -> Result<Uuid, AgentMemError> {
+        self.store_episode_with_text(Episode::new(task_id, Vec::new(), reward), text)
+    }
+
+    /// Store `episode` with its `state_embedding` replaced by the embedding of `text`.
+    /// Returns the episode id.
+    pub fn store_episode_with_text(
+        &mut self,
+        mut episode: Episode,
+        text: &str,
+    ) -> Result<Uuid, AgentMemError> {
+        episode.state_embedding = self.embed_texts(&[text])?.remove(0);
+        tag_model(self.embedder.as_ref(), &mut episode);
+        let id = episode.id;
+        self.store_episode(episode)?;
+        Ok(id)
+    }
+
+    /// Query by text: embed it, then `query_similar`.
+    pub fn query_text(
+        &self,
+        text: &str,
+        min_reward: f32,
+        top_k: usize,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        self.query_text_with_options(text, QueryOptions::new(min_reward, top_k))
+    }
+
+    /// Query by text with filters.
+    pub fn query_text_with_options(
+        &self,
+        text: &str,
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        let embedding = self.embed_texts(&[text])?.remove(0);
+        self.query_similar_with_options(&embedding, opts)
+    }
+}
+
+#[cfg(feature = "openai")]
+pub use openai::OpenAiEmbeddings;
+
+#[cfg(feature = "openai")]
+mod openai {
+    use super::EmbeddingProvider;
+    use crate::AgentMemError;
+    use serde::Deserialize;
+    use std::time::Duration;
+
+    /// Inputs sent per request.
+    const MAX_BATCH: usize = 256;
+
+    /// An OpenAI-compatible embeddings API (`POST <base_url>/embeddings`): OpenAI itself, or a
+    /// local model served by Ollama (`http://localhost:11434/v1`), vLLM or
+    /// text-embeddings-inference.
+    ///
+    /// Uses a blocking HTTP client; from async code, call it via `spawn_blocking`.
+    pub struct OpenAiEmbeddings {
+        endpoint: String,
+        model: String,
+        api_key: Option<String>,
+        dim: usize,
+        client: reqwest::blocking::Client,
+    }
+
+    #[derive(Deserialize)]
+    struct EmbeddingsResponse {
+        data: Vec<EmbeddingData>,
+    }
+
+    #[derive(Deserialize)]
+    struct EmbeddingData {
+        embedding: Vec<f32>,
+        #[serde(default)]
+        index: usize,
+    }
+
+    impl OpenAiEmbeddings {
+        /// `dim` is the model's output dimension (e.g. 1536 for `text-embedding-3-small`).
+        pub fn new(
+            base_url: &str,
+            model: impl Into<String>,
+            dim: usize,
+        ) -> Result<Self, AgentMemError> {
+            let client = reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .map_err(|e| AgentMemError::Embedding(e.to_string()))?;
+            Ok(Self {
+                endpoint: format!("{}/embeddings", base_url.trim_end_matches('/')),
+                model: model.into(),
+                api_key: None,
+                dim,
+                client,
+            })
+        }
+
+        /// Sent as `Authorization: Bearer`.
+        pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+            self.api_key = Some(api_key.into());
+            self
+        }
+    }
+
+    impl EmbeddingProvider for OpenAiEmbeddings {
+        fn dim(&self) -> usize {
+            self.dim
+        }
+
+        fn embed(&self, text: &str) -> Result<Vec<f32>, AgentMemError> {
+            Ok(self.embed_batch(&[text])?.remove(0))
+        }
+
+        fn model(&self) -> Option<&str> {
+            Some(&self.model)
+        }
+
+        fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AgentMemError> {
+            let err = |msg: String| AgentMemError::Embedding(format!("{}: {msg}", self.endpoint));
+            let mut out = Vec::with_capacity(texts.len());
+            for chunk in texts.chunks(MAX_BATCH) {
+                let mut request = self.client.post(&self.endpoint).json(&serde_json::json!({
+                    "model": self.model,
+                    "input": chunk,
+                }));
+                if let Some(ref key) = self.api_key {
+                    request = request.bearer_auth(key);
+                }
+                let response = request.send().map_err(|e| err(e.to_string()))?;
+                let status = response.status();
+                if !status.is_success() {
+                    let body: String = response
+                        .text()
+                        .unwrap_or_default()
+                        .chars()
+                        .take(500)
+                        .collect();
+                    return Err(err(format!("HTTP {status}: {body}")));
+                }
+                let mut parsed: EmbeddingsResponse = response
+                    .json()
+                    .map_err(|e| err(format!("invalid response: {e}")))?;
+                if parsed.data.len() != chunk.len() {
+                    return Err(err(format!(
+                        "returned {} embeddings for {} inputs",
+                        parsed.data.len(),
+                        chunk.len()
+                    )));
+                }
+                parsed.data.sort_by_key(|d| d.index);
+                out.extend(parsed.data.into_iter().map(|d| d.embedding));
+            }
+            Ok(out)
+        }
+    }
+}
+
+#[cfg(feature = "onnx")]
+pub use onnx::OnnxEmbeddings;
+
+#[cfg(feature = "onnx")]
+mod onnx {
+    use super::EmbeddingProvider;
+    use crate::AgentMemError;
+    use ort::session::Session;
+    use ort::value::Tensor;
+    use std::path::Path;
+    use std::sync::Mutex;
+    use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
+
+    /// A local sentence-embedding model (e.g. all-MiniLM-L6-v2 exported to ONNX) run with
+    /// ONNX Runtime, which is loaded dynamically: set `ORT_DYLIB_PATH` to `libonnxruntime`.
+    ///
+    /// Token embeddings are mean-pooled over the attention mask and L2-normalized; a model
+    /// whose first output is already pooled (`[batch, dim]`) is only normalized.
+    pub struct OnnxEmbeddings {
+        session: Mutex<Session>,
+        tokenizer: Tokenizer,
+        dim: usize,
+    }
+
+    fn onnx_err(e: impl std::fmt::Display) -> AgentMemError {
+        AgentMemError::Embedding(format!("onnx: {e}"))
+    }
+
+    impl OnnxEmbeddings {
+        /// Load `model.onnx` and its Hugging Face `tokenizer.json`. Inputs are truncated to
+        /// `max_tokens` (512 for BERT-style models). The dimension is read by embedding a probe
+        /// text.
+        pub fn new(
+            model_path: impl AsRef<Path>,
+            tokenizer_path: impl AsRef<Path>,
+            max_tokens: usize,
+        ) -> Result<Self, AgentMemError> {
+            let session = Session::builder()
+                .and_then(|b| b.commit_from_file(model_path))
+                .map_err(onnx_err)?;
+            let mut tokenizer = Tokenizer::from_file(tokenizer_path).map_err(onnx_err)?;
+            tokenizer.with_padding(Some(PaddingParams::default()));
+            tokenizer
+                .with_truncation(Some(TruncationParams {
+                    max_length: max_tokens,
+                    ..Default::default()
+                }))
+                .map_err(onnx_err)?;
+            let mut provider = Self {
+                session: Mutex::new(session),
+                tokenizer,
+                dim: 0,
+            };
+            provider.dim = provider.run(&["dimension probe"])?.remove(0).len();
+            Ok(provider)
+        }
+
+        fn run(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AgentMemError> {
+            let encodings = self
+                .tokenizer
+                .encode_batch(texts.to_vec(), true)
+                .map_err(onnx_err)?;
+            let batch = encodings.len();
+            let seq = encodings.first().map_or(0, |e| e.len());
+            let column = |f: fn(&tokenizers::Encoding) -> &[u32]| -> Vec<i64> {
+                encodings
+                    .iter()
+                    .flat_map(|e| f(e).iter().map(|&v| v as i64))
+                    .collect()
+            };
+            let mask = column(tokenizers::Encoding::get_attention_mask);
+            let shape = [batch, seq];
+
+            let mut session = self.session.lock().map_err(onnx_err)?;
+            let mut inputs = Vec::new();
+            for input in &session.inputs {
+                let values = match input.name.as_str() {
+                    "input_ids" => column(tokenizers::Encoding::get_ids),
+                    "attention_mask" => mask.clone(),
+                    "token_type_ids" => column(tokenizers::Encoding::get_type_ids),
+                    other => return Err(onnx_err(format!("unsupported model input {other:?}"))),
+                };
+                let tensor = Tensor::from_array((shape, values)).map_err(onnx_err)?;
+                inputs.push((input.name.clone(), tensor));
+            }
+            let outputs = session.run(inputs).map_err(onnx_err)?;
+            let (out_shape, data) = outputs[0].try_extract_tensor::<f32>().map_err(onnx_err)?;
+
+            let mut embeddings = match **out_shape {
+                // Token embeddings: mean-pool over the attention mask.
+                [b, s, h] if b as usize == batch && s as usize == seq => {
+                    let h = h as usize;
+                    (0..batch)
+                        .map(|i| {
+                            let mut sum = vec![0.0f32; h];
+                            let mut count = 0.0f32;
+                            for t in 0..seq {
+                                if mask[i * seq + t] == 0 {
+                                    continue;
+                                }
+                                let row = &data[(i * seq + t) * h..(i * seq + t + 1) * h];
+                                sum.iter_mut().zip(row).for_each(|(s, v)| *s += v);
+                                count += 1.0;
+                            }
+                            sum.iter_mut().for_each(|s| *s /= count.max(1.0));
+                            sum
+                        })
+                        .collect::<Vec<_>>()
+                }
+                [b, h] if b as usize == batch => {
+                    data.chunks(h as usize).map(<[f32]>::to_vec).collect()
+                }
+                ref other => {
+                    return Err(onnx_err(format!("unexpected output shape {other:?}")));
+                }
+            };
+            for e in &mut embeddings {
+                let norm = e.iter().map(|v| v * v).sum::<f32>().sqrt();
+                if norm > 0.0 {
+                    e.iter_mut().for_each(|v| *v /= norm);
+                }
+            }
+            Ok(embeddings)
+        }
+    }
+
+    impl EmbeddingProvider for OnnxEmbeddings {
+        fn dim(&self) -> usize {
+            self.dim
+        }
+
+        fn embed(&self, text: &str) -> Result<Vec<f32>, AgentMemError> {
+            Ok(self.run(&[text])?.remove(0))
+        }
+
+        fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AgentMemError> {
+            if texts.is_empty() {
+                return Ok(Vec::new());
+            }
+            self.run(texts)
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Querying several stores at once (e.g. episodic + semantic memory, or hot + archive) and
+//! merging their results by distance.
+
+use crate::index::l2_distance;
+use crate::{
+    AgentMemDB, AgentMemDBDisk, AgentMemDBTiered, AgentMemDBTimePartitioned, AgentMemError,
+    AgentMemSnapshot, Episode, QueryOptions,
+};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// A queryable episode store, so different backends can be searched together with
+/// [`query_federated`].
+pub trait EpisodeStore {
+    /// Embedding dimension.
+    fn dim(&self) -> usize;
+
+    /// Up to `opts.top_k` episodes passing `opts`, nearest first.
+    fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError>;
+}
+
+impl EpisodeStore for AgentMemDB {
+    fn dim(&self) -> usize {
+        AgentMemDB::dim(self)
+    }
+
+    fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        AgentMemDB::query_similar_with_options(self, query_embedding, opts)
+    }
+}
+
+impl EpisodeStore for AgentMemDBDisk {
+    fn dim(&self) -> usize {
+        AgentMemDBDisk::dim(self)
+    }
+
+    fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        AgentMemDBDisk::query_similar_with_options(self, query_embedding, opts)
+    }
+}
+
+impl EpisodeStore for AgentMemSnapshot {
+    fn dim(&self) -> usize {
+        AgentMemSnapshot::dim(self)
+    }
+
+    fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        AgentMemSnapshot::query_similar_with_options(self, query_embedding, opts)
+    }
+}
+
+impl EpisodeStore for AgentMemDBTiered {
+    fn dim(&self) -> usize {
+        AgentMemDBTiered::dim(self)
+    }
+
+    fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        AgentMemDBTiered::query_similar_with_options(self, query_embedding, opts)
+    }
+}
+
+impl EpisodeStore for AgentMemDBTimePartitioned {
+    fn dim(&self) -> usize {
+        AgentMemDBTimePartitioned::dim(self)
+    }
+
+    fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        AgentMemDBTimePartitioned::query_similar_with_options(self, query_embedding, opts)
+    }
+}
+
+/// One result of [`query_federated`].
+#[derive(Debug, Clone)]
+pub struct FederatedHit {
+    pub episode: Episode,
+    /// L2 distance to the query embedding.
+    pub distance: f32,
+    /// Index in `stores` of the store the episode came from.
+    pub source: usize,
+}
+
+/// Query every store with `opts` and merge into one `top_k` list, nearest first (ties broken
+/// by recency, like a single store). An episode id found in several stores is returned once,
+/// attributed to the first store listing it.
+///
+/// ```rust
+/// use agent_mem_db::{query_federated, AgentMemDB, Episode, EpisodeStore, QueryOptions};
+/// let mut episodic = AgentMemDB::new_exact(2);
+/// episodic.store_episode(Episode::new("chat", vec![0.0, 1.0], 1.0)).unwrap();
+/// let mut semantic = AgentMemDB::new_exact(2);
+/// semantic.store_episode(Episode::new("fact", vec![0.0, 0.9], 1.0)).unwrap();
+///
+/// let stores: [&dyn EpisodeStore; 2] = [&episodic, &semantic];
+/// let hits = query_federated(&stores, &[0.0, 0.8], QueryOptions::new(0.0, 2)).unwrap();
+/// assert_eq!(hits[0].episode.task_id, "fact");
+/// assert_eq!(hits[0].source, 1);
+/// ```
+pub fn query_federated(
+    stores: &[&dyn EpisodeStore],
+    query_embedding: &[f32],
+    opts: QueryOptions,
+) -> Result<Vec<FederatedHit>, AgentMemError> {
+    // Distances are recomputed from the embeddings, which are dropped at the end if not wanted.
+    let store_opts = opts.clone().include_embeddings(true);
+    let mut by_id: HashMap<Uuid, FederatedHit> = HashMap::new();
+    for (source, store) in stores.iter().enumerate() {
+        for episode in store.query_similar_with_options(query_embedding, store_opts.clone())? {
+            by_id.entry(episode.id).or_insert_with(|| FederatedHit {
+                distance: l2_distance(query_embedding, &episode.state_embedding),
+                episode,
+                source,
+            });
+        }
+    }
+    let mut ranked: Vec<(f32, FederatedHit)> =
+        by_id.into_values().map(|hit| (hit.distance, hit)).collect();
+    opts.apply_avoid(&mut ranked, |hit| hit.episode.state_embedding.as_slice());
+    opts.apply_recency_boost(&mut ranked, |hit| hit.episode.timestamp);
+    ranked.sort_by(|a, b| {
+        opts.rank(&(a.0, &a.1.episode), &(b.0, &b.1.episode))
+            .then(a.1.source.cmp(&b.1.source))
+    });
+    opts.take_results(&mut ranked, |hit| hit.episode.task_id.clone());
+    Ok(ranked
+        .into_iter()
+        .map(|(_, hit)| FederatedHit {
+            episode: opts.project(hit.episode),
+            ..hit
+        })
+        .collect())
+}
@@ -0,0 +1,88 @@
+//! Versions of the persisted formats (`save_to_file` files, a disk DB's `meta.json`, its
+//! exact-index checkpoint and `access.json`) and the migrations that read older layouts.
+//!
+//! Each file carries a `format_version`; files written before versioning have none and are
+//! read as version 0. Reading runs the file through its migrations up to [`FORMAT_VERSION`]
+//! before deserializing, and a file from a newer build fails with
+//! `AgentMemError::UnsupportedVersion` instead of being misread. The log (`episodes.jsonl`) is
+//! covered by the version in `meta.json`.
+
+use crate::AgentMemError;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Format version written by this build, and the newest it reads.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A persisted file kind.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Format {
+    SaveFile,
+    DiskMeta,
+    Checkpoint,
+    AccessStats,
+}
+
+/// Upgrades a file from version `n` to `n + 1`, in place.
+type Migration = fn(&mut Value) -> Result<(), AgentMemError>;
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::SaveFile => "save file",
+            Format::DiskMeta => "meta",
+            Format::Checkpoint => "checkpoint",
+            Format::AccessStats => "access stats",
+        }
+    }
+
+    /// `migrations()[n]` upgrades version `n`; there is one per version below
+    /// `FORMAT_VERSION`.
+    fn migrations(self) -> &'static [Migration] {
+        match self {
+            Format::SaveFile | Format::DiskMeta | Format::Checkpoint | Format::AccessStats => {
+                &[v0_to_v1]
+            }
+        }
+    }
+}
+
+/// Version 0 (unversioned) to 1: nothing moves. Fields added to `Episode` and `DiskMeta`
+/// before versioning all have defaults, so a version 0 file already reads as version 1.
+fn v0_to_v1(_value: &mut Value) -> Result<(), AgentMemError> {
+    Ok(())
+}
+
+/// Just the version of a file; the rest is skipped.
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default)]
+    format_version: u64,
+}
+
+/// Parse `data` as a `format` file of any supported version, migrating older layouts to the
+/// current one first.
+pub(crate) fn parse<T: DeserializeOwned>(format: Format, data: &str) -> Result<T, AgentMemError> {
+    let parse_error = |e| AgentMemError::serde(format!("Parse {}", format.name()))(e);
+    let header: Header = serde_json::from_str(data).map_err(parse_error)?;
+    let version = u32::try_from(header.format_version).unwrap_or(u32::MAX);
+    if version > FORMAT_VERSION {
+        return Err(AgentMemError::UnsupportedVersion {
+            file: format.name(),
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    if version == FORMAT_VERSION {
+        return serde_json::from_str(data).map_err(parse_error);
+    }
+    let mut value: Value = serde_json::from_str(data).map_err(parse_error)?;
+    for migrate in &format.migrations()[version as usize..] {
+        migrate(&mut value)?;
+    }
+    if let Some(fields) = value.as_object_mut() {
+        fields.insert("format_version".into(), FORMAT_VERSION.into());
+    }
+    serde_json::from_value(value).map_err(parse_error)
+}
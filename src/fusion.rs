@@ -0,0 +1,106 @@
+//! Multi-vector queries: search with several embeddings at once (e.g. the current state and
+//! the goal) and fuse the result lists by weighted reciprocal rank.
+
+use crate::federated::EpisodeStore;
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode, QueryOptions};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The `k` of reciprocal rank fusion: an episode at rank `r` (1-based) of a list contributes
+/// `weight / (RRF_K + r)`. The usual value; it damps the lead of the very top ranks.
+pub const RRF_K: f32 = 60.0;
+
+/// One result of `query_fused`.
+#[derive(Debug, Clone)]
+pub struct FusedHit {
+    pub episode: Episode,
+    /// Weighted reciprocal-rank score summed over the lists the episode appears in; higher is
+    /// better.
+    pub score: f32,
+}
+
+/// Run one query per embedding in `queries` with `opts` (each returns up to `opts.top_k`
+/// episodes) and fuse the lists by weighted reciprocal rank, `weights[i]` applying to
+/// `queries[i]`. Returns up to `top_k` episodes (at most `max_per_task` per task_id), best
+/// score first; ties keep the order the episodes were first found in. Fails with `InvalidQuery` unless there is one finite,
+/// non-negative weight per query.
+pub fn query_fused(
+    store: &dyn EpisodeStore,
+    queries: &[Vec<f32>],
+    weights: &[f32],
+    opts: QueryOptions,
+) -> Result<Vec<FusedHit>, AgentMemError> {
+    if weights.len() != queries.len() {
+        return Err(AgentMemError::InvalidQuery(format!(
+            "{} weights for {} query embeddings",
+            weights.len(),
+            queries.len()
+        )));
+    }
+    if let Some(w) = weights.iter().find(|w| !w.is_finite() || **w < 0.0) {
+        return Err(AgentMemError::InvalidQuery(format!(
+            "weight {w} is not a finite non-negative number"
+        )));
+    }
+    let mut hits: Vec<FusedHit> = Vec::new();
+    let mut position: HashMap<Uuid, usize> = HashMap::new();
+    for (query, &weight) in queries.iter().zip(weights) {
+        let results = store.query_similar_with_options(query, opts.clone())?;
+        for (rank, episode) in results.into_iter().enumerate() {
+            let score = weight / (RRF_K + rank as f32 + 1.0);
+            match position.get(&episode.id) {
+                Some(&i) => hits[i].score += score,
+                None => {
+                    position.insert(episode.id, hits.len());
+                    hits.push(FusedHit { episode, score });
+                }
+            }
+        }
+    }
+    // Stable, so equal scores keep discovery order.
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut ranked: Vec<(f32, FusedHit)> = hits.into_iter().map(|hit| (hit.score, hit)).collect();
+    opts.take_results(&mut ranked, |hit| hit.episode.task_id.clone());
+    Ok(ranked.into_iter().map(|(_, hit)| hit).collect())
+}
+
+impl AgentMemDB {
+    /// Search with several query embeddings (e.g. the current state and the goal) and fuse
+    /// the results by weighted reciprocal rank, in one call instead of one query per
+    /// embedding and a manual merge. See [`query_fused`](crate::query_fused).
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, QueryOptions};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("like state", vec![1.0, 0.0], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("like goal", vec![0.0, 1.0], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("both", vec![0.6, 0.6], 1.0)).unwrap();
+    /// let state = vec![1.0, 0.0];
+    /// let goal = vec![0.0, 1.0];
+    /// let hits = db
+    ///     .query_fused(&[state, goal], &[1.0, 1.0], QueryOptions::new(0.0, 2))
+    ///     .unwrap();
+    /// assert_eq!(hits[0].episode.task_id, "both");
+    /// ```
+    pub fn query_fused(
+        &self,
+        queries: &[Vec<f32>],
+        weights: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<FusedHit>, AgentMemError> {
+        query_fused(self, queries, weights, opts)
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Search with several query embeddings and fuse the results by weighted reciprocal rank
+    /// (see `AgentMemDB::query_fused`).
+    pub fn query_fused(
+        &self,
+        queries: &[Vec<f32>],
+        weights: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<FusedHit>, AgentMemError> {
+        query_fused(self, queries, weights, opts)
+    }
+}
@@ -0,0 +1,205 @@
+//! Similarity-graph export: episodes as nodes, joined when their embeddings are close, written
+//! as GraphML or DOT for Gephi, yEd, Graphviz and similar tools.
+
+use crate::index::l2_distance;
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// File format of a similarity graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT (`graph { ... }`).
+    Dot,
+    /// GraphML (XML), with episode fields as node attributes.
+    GraphMl,
+}
+
+impl GraphFormat {
+    /// GraphML for a `.graphml` or `.xml` extension, DOT otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("graphml") || ext.eq_ignore_ascii_case("xml") => {
+                GraphFormat::GraphMl
+            }
+            _ => GraphFormat::Dot,
+        }
+    }
+}
+
+impl AgentMemDB {
+    /// Write the similarity graph of the stored episodes to `path`: one node per episode
+    /// (soft-deleted ones excluded) and an edge, weighted by L2 distance, between every pair
+    /// closer than `threshold`. The format follows the extension (see
+    /// [`GraphFormat::from_path`]). Dense clusters point at redundant memories. Compares every
+    /// pair, so cost is O(n²). Returns the number of edges written.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("a", vec![0.0, 0.0], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("b", vec![0.0, 0.1], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("c", vec![5.0, 5.0], 1.0)).unwrap();
+    /// let path = std::env::temp_dir().join("agent_mem_db_graph_doctest.dot");
+    /// assert_eq!(db.export_similarity_graph(0.5, &path).unwrap(), 1);
+    /// ```
+    pub fn export_similarity_graph(
+        &self,
+        threshold: f32,
+        path: impl AsRef<Path>,
+    ) -> Result<usize, AgentMemError> {
+        export_to_file(self.iter(), threshold, path.as_ref())
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Write the similarity graph of the stored episodes (see
+    /// `AgentMemDB::export_similarity_graph`). Returns the number of edges written.
+    pub fn export_similarity_graph(
+        &self,
+        threshold: f32,
+        path: impl AsRef<Path>,
+    ) -> Result<usize, AgentMemError> {
+        export_to_file(self.iter(), threshold, path.as_ref())
+    }
+}
+
+fn export_to_file<'a>(
+    episodes: impl Iterator<Item = &'a Episode>,
+    threshold: f32,
+    path: &Path,
+) -> Result<usize, AgentMemError> {
+    let file = File::create(path).map_err(AgentMemError::io("File create"))?;
+    let mut writer = BufWriter::new(file);
+    let edges = write_similarity_graph(
+        episodes,
+        threshold,
+        GraphFormat::from_path(path),
+        &mut writer,
+    )
+    .and_then(|edges| writer.flush().map(|()| edges))
+    .map_err(AgentMemError::io("Write graph"))?;
+    Ok(edges)
+}
+
+/// Write the similarity graph of `episodes` (see `AgentMemDB::export_similarity_graph`) to
+/// `out`. Nodes are ordered by id, so the same episodes always give the same file. Returns the
+/// number of edges written.
+pub fn write_similarity_graph<'a>(
+    episodes: impl Iterator<Item = &'a Episode>,
+    threshold: f32,
+    format: GraphFormat,
+    mut out: impl Write,
+) -> io::Result<usize> {
+    let mut nodes: Vec<&Episode> = episodes.filter(|ep| !ep.deleted).collect();
+    nodes.sort_by_key(|ep| ep.id);
+    let mut edges: Vec<(usize, usize, f32)> = Vec::new();
+    for (i, a) in nodes.iter().enumerate() {
+        for (j, b) in nodes.iter().enumerate().skip(i + 1) {
+            let distance = l2_distance(&a.state_embedding, &b.state_embedding);
+            if distance < threshold {
+                edges.push((i, j, distance));
+            }
+        }
+    }
+
+    match format {
+        GraphFormat::Dot => {
+            writeln!(out, "graph memory {{")?;
+            for ep in &nodes {
+                writeln!(
+                    out,
+                    "  \"{}\" [label=\"{}\", reward={}{}{}];",
+                    ep.id,
+                    dot_escape(&ep.task_id),
+                    ep.reward,
+                    ep.timestamp
+                        .map(|ts| format!(", timestamp={ts}"))
+                        .unwrap_or_default(),
+                    ep.tags
+                        .as_ref()
+                        .map(|tags| format!(", tags=\"{}\"", dot_escape(&tags.join(","))))
+                        .unwrap_or_default(),
+                )?;
+            }
+            for &(i, j, distance) in &edges {
+                writeln!(
+                    out,
+                    "  \"{}\" -- \"{}\" [weight={distance}];",
+                    nodes[i].id, nodes[j].id
+                )?;
+            }
+            writeln!(out, "}}")?;
+        }
+        GraphFormat::GraphMl => {
+            writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(
+                out,
+                r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+            )?;
+            for (key, name, ty, domain) in [
+                ("task_id", "task_id", "string", "node"),
+                ("reward", "reward", "double", "node"),
+                ("timestamp", "timestamp", "long", "node"),
+                ("tags", "tags", "string", "node"),
+                ("user_id", "user_id", "string", "node"),
+                ("distance", "distance", "double", "edge"),
+            ] {
+                writeln!(
+                    out,
+                    r#"  <key id="{key}" for="{domain}" attr.name="{name}" attr.type="{ty}"/>"#
+                )?;
+            }
+            writeln!(out, r#"  <graph id="memory" edgedefault="undirected">"#)?;
+            for ep in &nodes {
+                writeln!(out, r#"    <node id="{}">"#, ep.id)?;
+                writeln!(
+                    out,
+                    r#"      <data key="task_id">{}</data>"#,
+                    xml_escape(&ep.task_id)
+                )?;
+                writeln!(out, r#"      <data key="reward">{}</data>"#, ep.reward)?;
+                if let Some(ts) = ep.timestamp {
+                    writeln!(out, r#"      <data key="timestamp">{ts}</data>"#)?;
+                }
+                if let Some(ref tags) = ep.tags {
+                    writeln!(
+                        out,
+                        r#"      <data key="tags">{}</data>"#,
+                        xml_escape(&tags.join(","))
+                    )?;
+                }
+                if let Some(ref user) = ep.user_id {
+                    writeln!(
+                        out,
+                        r#"      <data key="user_id">{}</data>"#,
+                        xml_escape(user)
+                    )?;
+                }
+                writeln!(out, "    </node>")?;
+            }
+            for &(i, j, distance) in &edges {
+                writeln!(
+                    out,
+                    r#"    <edge source="{}" target="{}"><data key="distance">{distance}</data></edge>"#,
+                    nodes[i].id, nodes[j].id
+                )?;
+            }
+            writeln!(out, "  </graph>")?;
+            writeln!(out, "</graphml>")?;
+        }
+    }
+    Ok(edges.len())
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,9 +1,13 @@
 //! Pluggable vector index backends for episode similarity search.
 
+use crate::AgentMemError;
 use hnswx::{EuclideanDistance, HnswConfig, HNSW};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 /// Euclidean L2 distance between two vectors.
-fn l2_distance(a: &[f32], b: &[f32]) -> f32 {
+pub(crate) fn l2_distance(a: &[f32], b: &[f32]) -> f32 {
     a.iter()
         .zip(b.iter())
         .map(|(x, y)| (x - y) * (x - y))
@@ -11,6 +15,112 @@ fn l2_distance(a: &[f32], b: &[f32]) -> f32 {
         .sqrt()
 }
 
+/// When a search gives up early: at a deadline (`QueryOptions::timeout_ms`), once a cancel
+/// flag is set (`QueryOptions::cancel_token`), or never (the default).
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Budget<'a> {
+    pub deadline: Option<Instant>,
+    pub cancelled: Option<&'a AtomicBool>,
+}
+
+impl Budget<'_> {
+    pub fn spent(&self) -> bool {
+        self.cancelled.is_some_and(|c| c.load(Ordering::Relaxed))
+            || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+/// Hits scored between budget checks in `nearest_until`.
+const BUDGET_CHECK_INTERVAL: usize = 1024;
+
+/// Top-k of `(key, distance)` pairs, sorted by distance. Stops consuming `hits` once `budget`
+/// is spent, returning the top-k of those scored so far and `true`.
+fn nearest_until(
+    hits: impl Iterator<Item = (usize, f32)>,
+    k: usize,
+    budget: Budget,
+) -> (Vec<(usize, f32)>, bool) {
+    let mut results: Vec<(usize, f32)> = Vec::new();
+    let mut truncated = false;
+    for (i, hit) in hits.enumerate() {
+        if i > 0 && i % BUDGET_CHECK_INTERVAL == 0 && budget.spent() {
+            truncated = true;
+            break;
+        }
+        results.push(hit);
+    }
+    results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    results.truncate(k);
+    (results, truncated)
+}
+
+/// A set of index keys, stored as a bitset. Used as the allow-list of a filtered search.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct KeySet {
+    words: Vec<u64>,
+}
+
+impl KeySet {
+    pub fn insert(&mut self, key: usize) {
+        let word = key / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (key % 64);
+    }
+
+    pub fn remove(&mut self, key: usize) {
+        if let Some(word) = self.words.get_mut(key / 64) {
+            *word &= !(1 << (key % 64));
+        }
+    }
+
+    pub fn contains(&self, key: usize) -> bool {
+        self.words
+            .get(key / 64)
+            .is_some_and(|word| word & (1 << (key % 64)) != 0)
+    }
+
+    /// Number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    pub fn union_with(&mut self, other: &KeySet) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    pub fn intersect_with(&mut self, other: &KeySet) {
+        self.words.truncate(other.words.len());
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= other;
+        }
+    }
+
+    /// Keys in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+/// An HNSW allow-list this small, or this small a fraction of the index, is searched by
+/// scoring each allowed key instead of walking the graph.
+const EXACT_SCAN_MAX_KEYS: usize = 1024;
+const EXACT_SCAN_MAX_FRACTION: usize = 8;
+
 /// Exact (brute-force) vector index. O(n) per query; use for small episode sets or correctness-critical use.
 #[derive(Default)]
 pub struct ExactIndex {
@@ -44,39 +154,95 @@ impl ExactIndex {
 
     /// Search for top-k nearest neighbors by L2 distance. Returns (key, distance) pairs sorted by distance.
     pub fn search(&self, query: &[f32], k: usize) -> Vec<(usize, f32)> {
-        let mut results: Vec<(usize, f32)> = self
-            .vectors
-            .iter()
-            .enumerate()
-            .map(|(i, v)| (i, l2_distance(query, v)))
-            .collect();
-        results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
-        results.truncate(k);
-        results
+        self.search_until(query, k, Budget::default()).0
+    }
+
+    /// Like `search`, but the scan stops when `budget` is spent (see `nearest_until`).
+    pub(crate) fn search_until(
+        &self,
+        query: &[f32],
+        k: usize,
+        budget: Budget,
+    ) -> (Vec<(usize, f32)>, bool) {
+        nearest_until(
+            self.vectors
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (i, l2_distance(query, v))),
+            k,
+            budget,
+        )
+    }
+
+    /// Like `search_until`, but only the keys in `allowed` are scored.
+    pub(crate) fn search_allowed(
+        &self,
+        query: &[f32],
+        k: usize,
+        allowed: &KeySet,
+        budget: Budget,
+    ) -> (Vec<(usize, f32)>, bool) {
+        nearest_until(
+            allowed
+                .iter()
+                .filter_map(|key| Some((key, l2_distance(query, self.vectors.get(key)?)))),
+            k,
+            budget,
+        )
+    }
+}
+
+/// HNSW graph parameters. Larger `m` and `ef_construction` give better recall at the cost of
+/// memory and insert time; larger `ef_search` gives better recall at the cost of query time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HnswParams {
+    /// Max neighbours per node.
+    pub m: usize,
+    /// Candidate list size while inserting.
+    pub ef_construction: usize,
+    /// Candidate list size while searching.
+    pub ef_search: usize,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 32,
+        }
     }
 }
 
 /// HNSW approximate nearest-neighbor index. Fast for large episode sets.
 pub struct HnswIndex {
     hnsw: HNSW<EuclideanDistance>,
+    params: HnswParams,
+    max_elements: usize,
 }
 
 impl HnswIndex {
     pub fn new(max_elements: usize) -> Self {
+        Self::with_params(max_elements, HnswParams::default())
+    }
+
+    pub fn with_params(max_elements: usize, params: HnswParams) -> Self {
         let config = HnswConfig {
             max_elements,
-            m: 16,
-            m_max: 16,
-            m_max_0: 16,
-            ef_construction: 200,
-            level_multiplier: 1.0 / (16.0f64.ln()),
+            m: params.m,
+            m_max: params.m,
+            m_max_0: params.m,
+            ef_construction: params.ef_construction,
+            level_multiplier: 1.0 / (params.m as f64).ln(),
             allow_replace_deleted: false,
             batch_size: 64,
-            ef_search: 32,
+            ef_search: params.ef_search,
             num_threads: 1,
         };
         Self {
             hnsw: HNSW::new(config, EuclideanDistance::new()),
+            params,
+            max_elements,
         }
     }
 
@@ -102,6 +268,64 @@ pub enum IndexBackend {
 }
 
 impl IndexBackend {
+    /// An empty index of the same kind (and HNSW parameters), for rebuilds.
+    pub fn empty_like(&self, max_elements: usize) -> Self {
+        match self {
+            IndexBackend::Hnsw(idx) => {
+                IndexBackend::Hnsw(Box::new(HnswIndex::with_params(max_elements, idx.params)))
+            }
+            IndexBackend::Exact(_) => IndexBackend::Exact(ExactIndex::new()),
+        }
+    }
+
+    /// Rough heap size of the index holding `dim`-dimensional vectors: the vectors, plus for
+    /// HNSW one list of `m` neighbour ids per node (upper layers add about `1/m` of that).
+    pub fn estimated_bytes(&self, dim: usize) -> usize {
+        let vectors = self.len() * dim * std::mem::size_of::<f32>();
+        match self {
+            IndexBackend::Hnsw(idx) => {
+                vectors + self.len() * (idx.params.m + 1) * std::mem::size_of::<usize>()
+            }
+            IndexBackend::Exact(_) => vectors,
+        }
+    }
+
+    /// HNSW parameters, or `None` for an exact index.
+    pub fn hnsw_params(&self) -> Option<HnswParams> {
+        match self {
+            IndexBackend::Hnsw(idx) => Some(idx.params),
+            IndexBackend::Exact(_) => None,
+        }
+    }
+
+    /// An HNSW index holding this exact index's vectors under the same keys. An HNSW index is
+    /// returned as is.
+    pub fn into_hnsw(self, max_elements: usize, params: HnswParams) -> Self {
+        match self {
+            IndexBackend::Hnsw(_) => self,
+            IndexBackend::Exact(idx) => {
+                let mut hnsw = HnswIndex::with_params(max_elements, params);
+                for vec in &idx.vectors {
+                    hnsw.insert(vec);
+                }
+                IndexBackend::Hnsw(Box::new(hnsw))
+            }
+        }
+    }
+
+    /// Fails with `IndexFull` unless `additional` more vectors fit. Only HNSW has a capacity;
+    /// inserting past it panics inside the graph.
+    pub fn check_room(&self, additional: usize) -> Result<(), AgentMemError> {
+        match self {
+            IndexBackend::Hnsw(idx) if idx.hnsw.len() + additional > idx.max_elements => {
+                Err(AgentMemError::IndexFull {
+                    max_elements: idx.max_elements,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn insert(&mut self, vec: &[f32]) -> usize {
         match self {
             IndexBackend::Hnsw(idx) => idx.insert(vec),
@@ -116,10 +340,91 @@ impl IndexBackend {
         }
     }
 
+    /// Read every vector the index holds its own copy of, for `warm_up`; returns how many.
+    /// HNSW keeps its vectors inside the graph, which warm-up queries walk instead.
+    pub(crate) fn touch_vectors(&self) -> usize {
+        match self {
+            IndexBackend::Hnsw(_) => 0,
+            IndexBackend::Exact(idx) => {
+                for vec in &idx.vectors {
+                    std::hint::black_box(vec.iter().sum::<f32>());
+                }
+                idx.len()
+            }
+        }
+    }
+
     pub fn search(&self, query: &[f32], k: usize) -> Vec<(usize, f32)> {
         match self {
             IndexBackend::Hnsw(idx) => idx.search(query, k),
             IndexBackend::Exact(idx) => idx.search(query, k),
         }
     }
+
+    /// Like `search`, but an exact scan stops when `budget` is spent, returning the nearest of
+    /// the vectors scored so far and `true`. An HNSW search is bounded by `ef_search` and
+    /// always runs to completion.
+    pub(crate) fn search_until(
+        &self,
+        query: &[f32],
+        k: usize,
+        budget: Budget,
+    ) -> (Vec<(usize, f32)>, bool) {
+        match self {
+            IndexBackend::Hnsw(idx) => (idx.search(query, k), false),
+            IndexBackend::Exact(idx) => idx.search_until(query, k, budget),
+        }
+    }
+
+    /// Top-k nearest neighbours among the keys in `allowed`, so filters are applied during the
+    /// search instead of by discarding hits afterwards. Exact scores only the allowed keys. HNSW
+    /// does the same for a selective allow-list (reading vectors through `vector_of`), and
+    /// otherwise widens the graph search until `k` allowed keys are found or the index is
+    /// exhausted. Scans and widening stop when `budget` is spent; the flag reports whether
+    /// they did.
+    pub(crate) fn search_allowed<'a>(
+        &self,
+        query: &[f32],
+        k: usize,
+        allowed: &KeySet,
+        vector_of: impl Fn(usize) -> Option<&'a [f32]>,
+        budget: Budget,
+    ) -> (Vec<(usize, f32)>, bool) {
+        let idx = match self {
+            IndexBackend::Exact(idx) => return idx.search_allowed(query, k, allowed, budget),
+            IndexBackend::Hnsw(idx) => idx,
+        };
+        let total = idx.hnsw.len();
+        let count = allowed.len();
+        if k == 0 {
+            return (Vec::new(), false);
+        }
+        if count <= EXACT_SCAN_MAX_KEYS || count.saturating_mul(EXACT_SCAN_MAX_FRACTION) <= total {
+            return nearest_until(
+                allowed
+                    .iter()
+                    .filter_map(|key| Some((key, l2_distance(query, vector_of(key)?)))),
+                k,
+                budget,
+            );
+        }
+        // Start from the fetch that would hold `k` allowed keys if they were spread evenly.
+        let mut fetch = k.saturating_mul(total / count).max(k).min(total);
+        loop {
+            let hits = idx.search(query, fetch);
+            let exhausted = hits.len() < fetch || fetch >= total;
+            let mut hits: Vec<(usize, f32)> = hits
+                .into_iter()
+                .filter(|(key, _)| allowed.contains(*key))
+                .collect();
+            if hits.len() >= k || exhausted {
+                hits.truncate(k);
+                return (hits, false);
+            }
+            if budget.spent() {
+                return (hits, true);
+            }
+            fetch = fetch.saturating_mul(2).min(total);
+        }
+    }
 }
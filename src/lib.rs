@@ -11,7 +11,7 @@
 /// use agent_mem_db::EpisodeStep;
 /// let step = EpisodeStep { index: 0, action: "move".into(), observation: "obs".into(), step_reward: 0.1 };
 /// ```
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EpisodeStep {
     /// Step index (0-based)
     pub index: u32,
@@ -23,24 +23,107 @@ pub struct EpisodeStep {
     pub step_reward: f32,
 }
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, Read};
 use std::path::Path;
 #[derive(Serialize, Deserialize)]
 struct PersistedDB {
+    #[serde(default)]
+    format_version: u32,
     dim: usize,
     episodes: Vec<Episode>,
 }
 
+mod access;
+mod archive;
+mod auto_timestamp;
+mod cancel;
+mod compare;
+mod contrastive;
+mod cost;
+mod diff;
 mod disk;
+mod drift;
+mod embedding;
+mod federated;
+mod format;
+mod fusion;
+mod graph_export;
 mod index;
-pub use disk::{AgentMemDBDisk, DiskOptions};
+mod limits;
+mod memory_system;
+mod multi_tenant;
+mod outliers;
+mod prefilter;
+mod query_log;
+mod rebuild;
+mod reembed;
+mod reward_norm;
+mod snapshot;
+mod store_policy;
+mod sub_index;
+mod summarizer;
+mod tag_index;
+mod tag_tree;
+mod task_pattern;
+mod tiered;
+mod time_partitioned;
+mod user_partition;
+mod warm_up;
+mod windowed;
+pub use access::{AccessStats, AccessSummary};
+pub use archive::{DiskArchive, EpisodeArchive};
+pub use cancel::CancelToken;
+pub use compare::{compare_backends, BackendConfig, BackendResult, ComparisonReport};
+pub use contrastive::{contrastive_recall, ContrastiveRecall, CONTRAST_REWARD_THRESHOLD};
+pub use cost::CostSummary;
+pub use diff::{DbDiff, EpisodeChange};
+pub use disk::{
+    AgentMemDBDisk, AutoCheckpoint, DiskOptions, DiskSnapshot, GroupCommit, LogRecord,
+    VacuumReport, VerifyReport,
+};
+pub use drift::DriftReport;
+pub use embedding::EmbeddingProvider;
+#[cfg(feature = "onnx")]
+pub use embedding::OnnxEmbeddings;
+#[cfg(feature = "openai")]
+pub use embedding::OpenAiEmbeddings;
+pub use federated::{query_federated, EpisodeStore, FederatedHit};
+pub use format::FORMAT_VERSION;
+pub use fusion::{query_fused, FusedHit, RRF_K};
+pub use graph_export::{write_similarity_graph, GraphFormat};
+pub use index::HnswParams;
+pub use limits::EpisodeLimits;
+pub use memory_system::{MemoryKind, MemorySystem, Recalled};
+pub use multi_tenant::{
+    tenant_dir_name, MultiTenantMemDB, TenantEntry, TenantOptions, TenantStore, TENANT_ID_FILE,
+};
+pub use outliers::Outlier;
+pub use query_log::{
+    embedding_hash, replay_queries, QueryLog, QueryLogEntry, ReplayReport, ReplayedQuery,
+};
+pub use rebuild::{PreparedPrune, PruneRule};
+pub use reembed::{ReembedProgress, ReembedReport, REEMBED_TEXT_KEY};
+pub use reward_norm::RewardNormalization;
+pub use snapshot::AgentMemSnapshot;
+pub use store_policy::{DuplicateAction, StorePolicy};
+pub use summarizer::Summarizer;
+pub use tag_tree::{tag_has_prefix, TagSubtree, TAG_SEPARATOR};
+pub use tiered::{AgentMemDBTiered, ColdSearch, TierPolicy, TieredOptions};
+pub use time_partitioned::{AgentMemDBTimePartitioned, TimePartitionOptions};
+pub use warm_up::{WarmUpReport, WARM_UP_QUERIES};
+pub use windowed::{WindowStats, MAX_WINDOWS};
 
 #[cfg(feature = "async")]
 pub mod async_api;
-use index::{ExactIndex, HnswIndex, IndexBackend};
+use access::AccessTracker;
+use index::{Budget, ExactIndex, HnswIndex, IndexBackend, KeySet};
+use prefilter::KeyFilters;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sub_index::SubIndex;
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -57,7 +140,7 @@ use uuid::Uuid;
 /// use agent_mem_db::Episode;
 /// let ep = Episode::new("task_x", vec![0.0f32; 16], 1.0);
 /// ```
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Episode {
     /// Unique episode id (UUID v4)
     pub id: Uuid,
@@ -83,6 +166,32 @@ pub struct Episode {
     /// Optional user id for multi-tenant isolation
     #[serde(default)]
     pub user_id: Option<String>,
+    /// Optional session (conversation) id, the natural memory boundary for chat agents
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Optional cost of the episode (e.g. tokens or dollars), for cost-aware retrieval
+    #[serde(default)]
+    pub cost: Option<f32>,
+    /// Optional wall-clock duration of the episode in milliseconds
+    #[serde(default)]
+    pub duration_ms: Option<i64>,
+    /// Optional name/version of the model that produced `state_embedding` (e.g.
+    /// `"text-embedding-3-small"`), so vectors from different embedders aren't compared
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    /// Incremented by each `update_episode`; 0 for a newly created episode
+    #[serde(default)]
+    pub version: u64,
+    /// Set by `soft_delete`: kept and persisted, but excluded from queries until restored
+    #[serde(default)]
+    pub deleted: bool,
+    /// Times a query returned the episode; maintained by the DB, and seen by `iter` as of the
+    /// last write (see `AgentMemDB::fold_access_stats`)
+    #[serde(default)]
+    pub times_retrieved: u64,
+    /// When a query last returned the episode (Unix ms); maintained by the DB
+    #[serde(default)]
+    pub last_retrieved_at: Option<i64>,
 }
 impl Episode {
     /// Create a new episode with a random UUID and empty metadata.
@@ -98,6 +207,14 @@ impl Episode {
             tags: None,
             source: None,
             user_id: None,
+            session_id: None,
+            cost: None,
+            duration_ms: None,
+            embedding_model: None,
+            version: 0,
+            deleted: false,
+            times_retrieved: 0,
+            last_retrieved_at: None,
         }
     }
 
@@ -148,21 +265,97 @@ impl Episode {
         ep.user_id = Some(user_id.into());
         ep
     }
+
+    /// Create an episode with session_id.
+    pub fn with_session_id(
+        task_id: impl Into<String>,
+        state_embedding: Vec<f32>,
+        reward: f32,
+        session_id: impl Into<String>,
+    ) -> Self {
+        let mut ep = Self::new(task_id, state_embedding, reward);
+        ep.session_id = Some(session_id.into());
+        ep
+    }
+
+    /// Create an episode with a cost.
+    pub fn with_cost(
+        task_id: impl Into<String>,
+        state_embedding: Vec<f32>,
+        reward: f32,
+        cost: f32,
+    ) -> Self {
+        let mut ep = Self::new(task_id, state_embedding, reward);
+        ep.cost = Some(cost);
+        ep
+    }
+
+    /// Create an episode with a duration (ms).
+    pub fn with_duration_ms(
+        task_id: impl Into<String>,
+        state_embedding: Vec<f32>,
+        reward: f32,
+        duration_ms: i64,
+    ) -> Self {
+        let mut ep = Self::new(task_id, state_embedding, reward);
+        ep.duration_ms = Some(duration_ms);
+        ep
+    }
+
+    /// Create an episode tagged with the model that produced its embedding.
+    pub fn with_embedding_model(
+        task_id: impl Into<String>,
+        state_embedding: Vec<f32>,
+        reward: f32,
+        embedding_model: impl Into<String>,
+    ) -> Self {
+        let mut ep = Self::new(task_id, state_embedding, reward);
+        ep.embedding_model = Some(embedding_model.into());
+        ep
+    }
+}
+
+/// Numeric range on a top-level metadata value (see [`QueryOptions::metadata_range`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataRange {
+    pub key: String,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// An embedding whose neighbours a query down-ranks (see [`QueryOptions::avoid_embedding`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AvoidEmbedding {
+    pub embedding: Vec<f32>,
+    pub penalty: f32,
 }
 
 /// Query options for similarity search with optional filters.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct QueryOptions {
     /// Minimum episode reward to include
     pub min_reward: f32,
+    /// Maximum episode reward to include, e.g. to retrieve past failures
+    pub max_reward: Option<f32>,
     /// Maximum number of episodes to return
     pub top_k: usize,
     /// Include only episodes that have any of these tags
     pub tags_any: Option<Vec<String>>,
     /// Include only episodes that have all of these tags
     pub tags_all: Option<Vec<String>>,
+    /// Include only episodes with a tag in any of these subtrees of the tag hierarchy, e.g.
+    /// `tool/browser` for `tool/browser/click` (see [`tag_has_prefix`])
+    pub tag_prefix_any: Option<Vec<String>>,
     /// Include only episodes with task_id starting with this prefix
     pub task_id_prefix: Option<String>,
+    /// Include only episodes whose whole task_id matches this glob (`*` and `?`)
+    pub task_id_glob: Option<String>,
+    /// Include only episodes whose task_id matches this regex (unanchored; use `^…$` for a
+    /// full match). Serialized as the pattern string.
+    #[cfg(feature = "regex")]
+    #[serde(with = "task_pattern::serde_regex")]
+    pub task_id_regex: Option<regex::Regex>,
     /// Include only episodes with timestamp >= (Unix ms)
     pub time_after: Option<i64>,
     /// Include only episodes with timestamp <= (Unix ms)
@@ -171,6 +364,95 @@ pub struct QueryOptions {
     pub source: Option<String>,
     /// Include only episodes with this user_id (exact match)
     pub user_id: Option<String>,
+    /// Include only episodes with this session_id (exact match)
+    pub session_id: Option<String>,
+    /// Include only episodes embedded by this model (exact match)
+    pub embedding_model: Option<String>,
+    /// Include only episodes with (`true`) or without (`false`) a non-empty trajectory
+    pub has_steps: Option<bool>,
+    /// Include only episodes with at least this many steps
+    pub min_steps: Option<usize>,
+    /// Include only episodes with at most this many steps
+    pub max_steps: Option<usize>,
+    /// Include only episodes with a cost of at most this
+    pub max_cost: Option<f32>,
+    /// Include only episodes with a duration of at most this many ms
+    pub max_duration_ms: Option<i64>,
+    /// Include only episodes whose metadata has all of these top-level keys
+    pub metadata_has_keys: Vec<String>,
+    /// Include only episodes whose metadata has a number within each of these ranges
+    pub metadata_ranges: Vec<MetadataRange>,
+    /// Also match soft-deleted episodes (excluded by default)
+    pub include_deleted: bool,
+    /// Index candidates fetched per requested result; `None` uses the default (see
+    /// `candidate_multiplier`)
+    pub over_fetch: Option<usize>,
+    /// Keep doubling the candidates fetched until `top_k` episodes pass the filters or the
+    /// index is exhausted, for selective filters (e.g. one user_id among thousands)
+    pub adaptive: bool,
+    /// Order results totally: distance, then timestamp (recent first), then id, so equal
+    /// distances always come back in the same order, whatever the index returned them in
+    pub deterministic_order: bool,
+    /// Return at most this many episodes per task_id, so one repeated task can't fill every
+    /// result slot (see [`QueryOptions::group_by_task`])
+    pub max_per_task: Option<usize>,
+    /// Recency boost λ (per second): each candidate's similarity `1 / (1 + distance)` is
+    /// multiplied by `exp(-λ · age)`, where age is measured back from the newest candidate
+    pub recency_boost: Option<f32>,
+    /// Down-rank candidates near this embedding: `penalty / (1 + d)` is added to the distance
+    /// of a candidate at distance `d` from it
+    pub avoid_embedding: Option<AvoidEmbedding>,
+    /// Time budget in milliseconds for the index search. Exact scans and adaptive widening
+    /// stop when it runs out and the query returns what was found so far (see
+    /// `AgentMemDB::query_similar_timed` for the `truncated` flag)
+    pub timeout_ms: Option<u64>,
+    /// Return each episode's `state_embedding` (default). When false, results come back with
+    /// an empty embedding, which saves copying and sending vectors most callers never read
+    pub include_embeddings: bool,
+    /// Stops the query early when cancelled; it then fails with `AgentMemError::Cancelled`.
+    /// Not serialized
+    #[serde(skip)]
+    pub cancel: Option<CancelToken>,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            min_reward: 0.0,
+            max_reward: None,
+            top_k: 0,
+            tags_any: None,
+            tags_all: None,
+            tag_prefix_any: None,
+            task_id_prefix: None,
+            task_id_glob: None,
+            #[cfg(feature = "regex")]
+            task_id_regex: None,
+            time_after: None,
+            time_before: None,
+            source: None,
+            user_id: None,
+            session_id: None,
+            embedding_model: None,
+            has_steps: None,
+            min_steps: None,
+            max_steps: None,
+            max_cost: None,
+            max_duration_ms: None,
+            metadata_has_keys: Vec::new(),
+            metadata_ranges: Vec::new(),
+            include_deleted: false,
+            over_fetch: None,
+            adaptive: false,
+            deterministic_order: false,
+            max_per_task: None,
+            recency_boost: None,
+            avoid_embedding: None,
+            timeout_ms: None,
+            include_embeddings: true,
+            cancel: None,
+        }
+    }
 }
 
 impl QueryOptions {
@@ -183,6 +465,20 @@ impl QueryOptions {
         }
     }
 
+    /// Add max_reward filter (reward <=), e.g. `QueryOptions::new(f32::NEG_INFINITY, 5)
+    /// .max_reward(0.0)` for failed attempts at a similar state.
+    pub fn max_reward(mut self, max: f32) -> Self {
+        self.max_reward = Some(max);
+        self
+    }
+
+    /// Keep rewards within `min..=max` (sets both `min_reward` and `max_reward`).
+    pub fn reward_range(mut self, min: f32, max: f32) -> Self {
+        self.min_reward = min;
+        self.max_reward = Some(max);
+        self
+    }
+
     /// Add tags_any filter.
     pub fn tags_any(mut self, tags: Vec<String>) -> Self {
         self.tags_any = Some(tags);
@@ -195,12 +491,33 @@ impl QueryOptions {
         self
     }
 
+    /// Add tag_prefix_any filter for hierarchical tags: keep episodes with a tag equal to or
+    /// under any of `prefixes`, e.g. `tool/browser` matches `tool/browser/click`.
+    pub fn tag_prefix_any(mut self, prefixes: Vec<String>) -> Self {
+        self.tag_prefix_any = Some(prefixes);
+        self
+    }
+
     /// Add task_id_prefix filter.
     pub fn task_id_prefix(mut self, prefix: impl Into<String>) -> Self {
         self.task_id_prefix = Some(prefix.into());
         self
     }
 
+    /// Add task_id_glob filter, e.g. `deploy-*-prod`: `*` matches any run of characters
+    /// (separators included), `?` any single one, and the glob must match the whole task_id.
+    pub fn task_id_glob(mut self, glob: impl Into<String>) -> Self {
+        self.task_id_glob = Some(glob.into());
+        self
+    }
+
+    /// Add task_id_regex filter (requires the `regex` feature).
+    #[cfg(feature = "regex")]
+    pub fn task_id_regex(mut self, re: regex::Regex) -> Self {
+        self.task_id_regex = Some(re);
+        self
+    }
+
     /// Add time_after filter (timestamp >=).
     pub fn time_after(mut self, ts: i64) -> Self {
         self.time_after = Some(ts);
@@ -225,56 +542,565 @@ impl QueryOptions {
         self
     }
 
-    pub(crate) fn matches(&self, ep: &Episode) -> bool {
+    /// Add session_id filter (exact match).
+    pub fn session_id(mut self, s: impl Into<String>) -> Self {
+        self.session_id = Some(s.into());
+        self
+    }
+
+    /// Add embedding_model filter (exact match), so a store holding vectors from several
+    /// embedders only compares the query against compatible ones. Untagged episodes are excluded.
+    pub fn embedding_model(mut self, m: impl Into<String>) -> Self {
+        self.embedding_model = Some(m.into());
+        self
+    }
+
+    /// Add has_steps filter: `true` for episodes carrying a trajectory (e.g. for imitation),
+    /// `false` for summaries only. An empty step list counts as no trajectory.
+    pub fn has_steps(mut self, has: bool) -> Self {
+        self.has_steps = Some(has);
+        self
+    }
+
+    /// Add min_steps filter (step count >=; episodes without steps count 0).
+    pub fn min_steps(mut self, n: usize) -> Self {
+        self.min_steps = Some(n);
+        self
+    }
+
+    /// Add max_steps filter (step count <=; episodes without steps count 0).
+    pub fn max_steps(mut self, n: usize) -> Self {
+        self.max_steps = Some(n);
+        self
+    }
+
+    /// Add max_cost filter (cost <=), e.g. `QueryOptions::new(1.0, 5).max_cost(0.05)` for
+    /// cheap successes. Episodes without a cost are excluded.
+    pub fn max_cost(mut self, max: f32) -> Self {
+        self.max_cost = Some(max);
+        self
+    }
+
+    /// Add max_duration_ms filter (duration <=). Episodes without a duration are excluded.
+    pub fn max_duration_ms(mut self, max: i64) -> Self {
+        self.max_duration_ms = Some(max);
+        self
+    }
+
+    /// Require a top-level metadata key (any value, including null). Repeatable.
+    pub fn metadata_has_key(mut self, key: impl Into<String>) -> Self {
+        self.metadata_has_keys.push(key.into());
+        self
+    }
+
+    /// Require the top-level metadata value at `key` to be a number in `min..=max`, e.g.
+    /// `metadata_range("cost_usd", 0.0, 0.05)`. Episodes without the key, or with a
+    /// non-numeric value there, are excluded. Repeatable.
+    pub fn metadata_range(mut self, key: impl Into<String>, min: f64, max: f64) -> Self {
+        self.metadata_ranges.push(MetadataRange {
+            key: key.into(),
+            min,
+            max,
+        });
+        self
+    }
+
+    /// Match soft-deleted episodes too, e.g. to hard-delete a user's episodes.
+    pub fn include_deleted(mut self, include: bool) -> Self {
+        self.include_deleted = include;
+        self
+    }
+
+    /// Fetch `multiplier` index candidates per requested result (at least 1).
+    pub fn over_fetch(mut self, multiplier: usize) -> Self {
+        self.over_fetch = Some(multiplier.max(1));
+        self
+    }
+
+    /// Widen the index search until `top_k` matches are found (see [`QueryOptions::adaptive`]).
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Order results by distance, then timestamp, then id, for snapshot tests and caches
+    /// that need stable output.
+    pub fn deterministic_order(mut self, deterministic: bool) -> Self {
+        self.deterministic_order = deterministic;
+        self
+    }
+
+    /// Group results by task: keep at most `max_per_task` (at least 1) of each task_id's
+    /// episodes, the best ranked, and fill the rest of `top_k` from other tasks. Setting this
+    /// raises the default over-fetch to 4 (see `candidate_multiplier`); raise `over_fetch`
+    /// further when a few tasks dominate the store.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, QueryOptions};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("deploy", vec![1.0, 0.0], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("deploy", vec![1.0, 0.1], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("rollback", vec![0.5, 0.5], 1.0)).unwrap();
+    /// let opts = QueryOptions::new(0.0, 2).group_by_task(1);
+    /// let hits = db.query_similar_with_options(&[1.0, 0.0], opts).unwrap();
+    /// let tasks: Vec<&str> = hits.iter().map(|ep| ep.task_id.as_str()).collect();
+    /// assert_eq!(tasks, vec!["deploy", "rollback"]);
+    /// ```
+    pub fn group_by_task(mut self, max_per_task: usize) -> Self {
+        self.max_per_task = Some(max_per_task.max(1));
+        self
+    }
+
+    /// Bound the index search to `ms` milliseconds so a pathological query (a huge exact
+    /// scan, a selective filter under `adaptive`) can't stall the caller. HNSW graph searches
+    /// are bounded by `ef_search` and are not interrupted.
+    pub fn timeout_ms(mut self, ms: u64) -> Self {
+        self.timeout_ms = Some(ms);
+        self
+    }
+
+    /// Return results with (`true`, the default) or without their embeddings.
+    pub fn include_embeddings(mut self, include: bool) -> Self {
+        self.include_embeddings = include;
+        self
+    }
+
+    /// Let the caller abandon the query: once `token` is cancelled, exact scans and adaptive
+    /// widening stop and the query returns `AgentMemError::Cancelled`.
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// `Err(Cancelled)` once the query's cancel token has been cancelled.
+    pub(crate) fn check_cancelled(&self) -> Result<(), AgentMemError> {
+        match &self.cancel {
+            Some(token) if token.is_cancelled() => Err(AgentMemError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// `ep` as a query returns it: with its embedding emptied unless `include_embeddings`.
+    pub(crate) fn project(&self, mut ep: Episode) -> Episode {
+        if !self.include_embeddings {
+            ep.state_embedding = Vec::new();
+        }
+        ep
+    }
+
+    /// A copy of stored episode `ep` as a query returns it (see `project`). An embedding that
+    /// would be emptied isn't copied.
+    pub(crate) fn project_ref(&self, ep: &Episode) -> Episode {
+        if self.include_embeddings {
+            return ep.clone();
+        }
+        Episode {
+            id: ep.id,
+            task_id: ep.task_id.clone(),
+            state_embedding: Vec::new(),
+            reward: ep.reward,
+            metadata: ep.metadata.clone(),
+            steps: ep.steps.clone(),
+            timestamp: ep.timestamp,
+            tags: ep.tags.clone(),
+            source: ep.source.clone(),
+            user_id: ep.user_id.clone(),
+            session_id: ep.session_id.clone(),
+            cost: ep.cost,
+            duration_ms: ep.duration_ms,
+            embedding_model: ep.embedding_model.clone(),
+            version: ep.version,
+            deleted: ep.deleted,
+            times_retrieved: ep.times_retrieved,
+            last_retrieved_at: ep.last_retrieved_at,
+        }
+    }
+
+    /// Favour recent episodes over slightly closer old ones: similarity decays by
+    /// `exp(-lambda · age_secs)` (e.g. `lambda = ln 2 / half_life_secs`). Only candidates the
+    /// index returned are re-ranked, so raise `over_fetch` to let recent episodes from further
+    /// away compete.
+    pub fn recency_boost(mut self, lambda: f32) -> Self {
+        self.recency_boost = Some(lambda);
+        self
+    }
+
+    /// Down-rank results similar to `embedding` (e.g. an approach that just failed): a
+    /// candidate at L2 distance `d` from it has `penalty / (1 + d)` added to its distance to
+    /// the query, so an exact match is pushed back by `penalty`. Only candidates the index
+    /// returned are re-ranked; setting this raises the default over-fetch to 4 (see
+    /// `candidate_multiplier`). The embedding must have the DB's dimension.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, QueryOptions};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// let failed = Episode::new("retry with sudo", vec![1.0, 0.0], 1.0);
+    /// db.store_episode(failed.clone()).unwrap();
+    /// db.store_episode(Episode::new("free disk space", vec![0.8, 0.5], 1.0)).unwrap();
+    /// let opts = QueryOptions::new(0.0, 1).avoid_embedding(failed.state_embedding, 2.0);
+    /// let hits = db.query_similar_with_options(&[1.0, 0.0], opts).unwrap();
+    /// assert_eq!(hits[0].task_id, "free disk space");
+    /// ```
+    pub fn avoid_embedding(mut self, embedding: Vec<f32>, penalty: f32) -> Self {
+        self.avoid_embedding = Some(AvoidEmbedding { embedding, penalty });
+        self
+    }
+
+    /// Whether candidates are re-ranked after the search (`recency_boost` or
+    /// `avoid_embedding`), so index order isn't final.
+    pub(crate) fn reranks(&self) -> bool {
+        self.recency_boost.is_some() || self.avoid_embedding.is_some()
+    }
+
+    /// `DimensionMismatch` unless the avoid embedding, if any, has dimension `dim`.
+    pub(crate) fn check_avoid_dim(&self, dim: usize) -> Result<(), AgentMemError> {
+        match &self.avoid_embedding {
+            Some(avoid) if avoid.embedding.len() != dim => Err(AgentMemError::DimensionMismatch {
+                expected: dim,
+                got: avoid.embedding.len(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The `avoid_embedding` penalty for a candidate with this embedding (0 when unset).
+    pub(crate) fn avoid_penalty(&self, embedding: &[f32]) -> f32 {
+        self.avoid_embedding.as_ref().map_or(0.0, |avoid| {
+            avoid.penalty / (1.0 + index::l2_distance(&avoid.embedding, embedding))
+        })
+    }
+
+    /// Add the `avoid_embedding` penalty to each candidate distance.
+    pub(crate) fn apply_avoid<E>(&self, ranked: &mut [(f32, E)], embedding: impl Fn(&E) -> &[f32]) {
+        if self.avoid_embedding.is_none() {
+            return;
+        }
+        for (distance, e) in ranked.iter_mut() {
+            *distance += self.avoid_penalty(embedding(e));
+        }
+    }
+
+    /// Replace each candidate distance with the one its recency-boosted similarity
+    /// corresponds to, so ranking by distance ranks by boosted score. Ages are measured from
+    /// the newest candidate (a common reference point does not change the order); undated
+    /// candidates count as old as the oldest dated one.
+    pub(crate) fn apply_recency_boost<E>(
+        &self,
+        ranked: &mut [(f32, E)],
+        timestamp: impl Fn(&E) -> Option<i64>,
+    ) {
+        let Some(lambda) = self.recency_boost.filter(|l| *l > 0.0) else {
+            return;
+        };
+        let Some((oldest, newest)) = ranked.iter().filter_map(|(_, e)| timestamp(e)).fold(
+            None,
+            |range: Option<(i64, i64)>, ts| match range {
+                Some((lo, hi)) => Some((lo.min(ts), hi.max(ts))),
+                None => Some((ts, ts)),
+            },
+        ) else {
+            return;
+        };
+        for (distance, e) in ranked.iter_mut() {
+            let age_secs = newest.saturating_sub(timestamp(e).unwrap_or(oldest)) as f64 / 1000.0;
+            let decay = (lambda as f64 * age_secs).exp();
+            *distance = ((1.0 + *distance as f64) * decay - 1.0) as f32;
+        }
+    }
+
+    /// Cut ranked candidates to the query's results: the first `top_k`, skipping any beyond
+    /// `max_per_task` for their task_id.
+    pub(crate) fn take_results<E>(
+        &self,
+        ranked: &mut Vec<(f32, E)>,
+        task_id: impl Fn(&E) -> String,
+    ) {
+        if let Some(max) = self.max_per_task {
+            let mut per_task: HashMap<String, usize> = HashMap::new();
+            ranked.retain(|(_, e)| {
+                let count = per_task.entry(task_id(e)).or_default();
+                *count += 1;
+                *count <= max
+            });
+        }
+        ranked.truncate(self.top_k);
+    }
+
+    /// Result order for this query: `rank_order`, made total (by id) with
+    /// `deterministic_order`.
+    pub(crate) fn rank<E: std::borrow::Borrow<Episode>>(
+        &self,
+        a: &(f32, E),
+        b: &(f32, E),
+    ) -> std::cmp::Ordering {
+        if !self.deterministic_order {
+            return rank_order(a, b);
+        }
+        a.0.total_cmp(&b.0)
+            .then_with(|| rank_order(a, b))
+            .then_with(|| a.1.borrow().id.cmp(&b.1.borrow().id))
+    }
+
+    /// Whether `ep` passes `min_reward` and every filter (`top_k` is not considered).
+    pub fn matches(&self, ep: &Episode) -> bool {
+        self.rejected_by(ep).is_none()
+    }
+
+    /// The first filter `ep` fails (`"deleted"`, `"min_reward"`, `"max_reward"`, `"tags_any"`,
+    /// `"tags_all"`, `"tag_prefix_any"`, `"task_id_prefix"`, `"task_id_glob"`, `"task_id_regex"`, `"time_after"`,
+    /// `"time_before"`, `"source"`, `"user_id"`, `"session_id"`, `"embedding_model"`,
+    /// `"has_steps"`, `"min_steps"`, `"max_steps"`, `"max_cost"`, `"max_duration_ms"`,
+    /// `"metadata_has_key"` or `"metadata_range"`), or `None` if it passes all of them.
+    pub fn rejected_by(&self, ep: &Episode) -> Option<&'static str> {
+        if ep.deleted && !self.include_deleted {
+            return Some("deleted");
+        }
         if ep.reward < self.min_reward {
-            return false;
+            return Some("min_reward");
+        }
+        if self.max_reward.is_some_and(|max| ep.reward > max) {
+            return Some("max_reward");
         }
         if let Some(ref tags) = self.tags_any {
             let ep_tags = ep.tags.as_deref().unwrap_or(&[]);
             if !tags.iter().any(|t| ep_tags.contains(t)) {
-                return false;
+                return Some("tags_any");
             }
         }
         if let Some(ref tags) = self.tags_all {
             let ep_tags = ep.tags.as_deref().unwrap_or(&[]);
             if !tags.iter().all(|t| ep_tags.contains(t)) {
-                return false;
+                return Some("tags_all");
+            }
+        }
+        if let Some(ref prefixes) = self.tag_prefix_any {
+            let ep_tags = ep.tags.as_deref().unwrap_or(&[]);
+            if !prefixes
+                .iter()
+                .any(|prefix| ep_tags.iter().any(|t| tag_has_prefix(t, prefix)))
+            {
+                return Some("tag_prefix_any");
             }
         }
         if let Some(ref prefix) = self.task_id_prefix {
             if !ep.task_id.starts_with(prefix) {
-                return false;
+                return Some("task_id_prefix");
+            }
+        }
+        if let Some(ref glob) = self.task_id_glob {
+            if !task_pattern::glob_match(glob, &ep.task_id) {
+                return Some("task_id_glob");
+            }
+        }
+        #[cfg(feature = "regex")]
+        if let Some(ref re) = self.task_id_regex {
+            if !re.is_match(&ep.task_id) {
+                return Some("task_id_regex");
             }
         }
         if let Some(ts) = self.time_after {
-            if let Some(ep_ts) = ep.timestamp {
-                if ep_ts < ts {
-                    return false;
-                }
-            } else {
-                return false;
+            if ep.timestamp.is_none_or(|ep_ts| ep_ts < ts) {
+                return Some("time_after");
             }
         }
         if let Some(ts) = self.time_before {
-            if let Some(ep_ts) = ep.timestamp {
-                if ep_ts > ts {
-                    return false;
-                }
-            } else {
-                return false;
+            if ep.timestamp.is_none_or(|ep_ts| ep_ts > ts) {
+                return Some("time_before");
             }
         }
         if let Some(ref s) = self.source {
             if ep.source.as_deref() != Some(s.as_str()) {
-                return false;
+                return Some("source");
             }
         }
         if let Some(ref u) = self.user_id {
             if ep.user_id.as_deref() != Some(u.as_str()) {
-                return false;
+                return Some("user_id");
+            }
+        }
+        if let Some(ref s) = self.session_id {
+            if ep.session_id.as_deref() != Some(s.as_str()) {
+                return Some("session_id");
+            }
+        }
+        if let Some(ref m) = self.embedding_model {
+            if ep.embedding_model.as_deref() != Some(m.as_str()) {
+                return Some("embedding_model");
+            }
+        }
+        let steps = ep.steps.as_ref().map_or(0, Vec::len);
+        if self.has_steps.is_some_and(|has| has != (steps > 0)) {
+            return Some("has_steps");
+        }
+        if self.min_steps.is_some_and(|min| steps < min) {
+            return Some("min_steps");
+        }
+        if self.max_steps.is_some_and(|max| steps > max) {
+            return Some("max_steps");
+        }
+        if let Some(max) = self.max_cost {
+            if ep.cost.is_none_or(|cost| cost > max) {
+                return Some("max_cost");
             }
         }
-        true
+        if let Some(max) = self.max_duration_ms {
+            if ep.duration_ms.is_none_or(|ms| ms > max) {
+                return Some("max_duration_ms");
+            }
+        }
+        if self
+            .metadata_has_keys
+            .iter()
+            .any(|key| ep.metadata.get(key).is_none())
+        {
+            return Some("metadata_has_key");
+        }
+        if !self.metadata_ranges.iter().all(|range| {
+            ep.metadata
+                .get(&range.key)
+                .and_then(Value::as_f64)
+                .is_some_and(|v| v >= range.min && v <= range.max)
+        }) {
+            return Some("metadata_range");
+        }
+        None
+    }
+
+    /// How many index candidates are fetched per requested result: `over_fetch` if set,
+    /// else 4 when a task_id pattern, a time range, a step, cost or duration filter, a
+    /// metadata filter or `max_per_task` is set, else 2.
+    /// Adaptive queries start here. Tag, source, user_id, session_id and embedding_model filters
+    /// don't count: they are applied inside the index search, which only returns matching episodes.
+    pub fn candidate_multiplier(&self) -> usize {
+        #[cfg(feature = "regex")]
+        let task_id_regex = self.task_id_regex.is_some();
+        #[cfg(not(feature = "regex"))]
+        let task_id_regex = false;
+        if let Some(multiplier) = self.over_fetch {
+            multiplier.max(1)
+        } else if self.task_id_prefix.is_some()
+            || self.task_id_glob.is_some()
+            || task_id_regex
+            || self.time_after.is_some()
+            || self.time_before.is_some()
+            || self.has_steps.is_some()
+            || self.min_steps.is_some()
+            || self.max_steps.is_some()
+            || self.max_cost.is_some()
+            || self.max_duration_ms.is_some()
+            || !self.metadata_has_keys.is_empty()
+            || !self.metadata_ranges.is_empty()
+            || self.avoid_embedding.is_some()
+            || self.max_per_task.is_some()
+        {
+            4
+        } else {
+            2
+        }
+    }
+}
+
+/// Results of a query run with a time budget (see [`QueryOptions::timeout_ms`]).
+#[derive(Debug, Clone, Default)]
+pub struct QueryResults {
+    pub episodes: Vec<Episode>,
+    /// Whether the budget ran out before the search finished, so nearer episodes may have
+    /// been missed.
+    pub truncated: bool,
+}
+
+/// One index candidate considered by a query, as reported by `explain_query`.
+#[derive(Debug, Clone)]
+pub struct ExplainedCandidate {
+    pub episode: Episode,
+    /// L2 distance to the query embedding.
+    pub distance: f32,
+    /// Filter that eliminated the candidate (see [`QueryOptions::rejected_by`]).
+    pub rejected_by: Option<&'static str>,
+    /// Whether the candidate is among the returned results (passed every filter and made
+    /// the `top_k` cut).
+    pub returned: bool,
+}
+
+/// How a query arrived at its results: the candidates fetched from the index, in index
+/// order, and why each was kept or dropped.
+#[derive(Debug, Clone)]
+pub struct QueryExplanation {
+    pub candidate_multiplier: usize,
+    /// Size of the allow-list the tag, source, user_id, session_id and embedding_model filters were
+    /// pushed into the index search as, or `None` when the query sets none of them.
+    pub allowed_keys: Option<usize>,
+    /// Tag whose sub-index was searched instead of the main index (see
+    /// `AgentMemDB::add_tag_index`).
+    pub tag_index: Option<String>,
+    /// Whether only the query user's partition was searched (see
+    /// `AgentMemDB::set_user_partitions`).
+    pub user_partition: bool,
+    /// Candidates requested from the index (`top_k * candidate_multiplier`, or more after
+    /// adaptive widening).
+    pub candidates_requested: usize,
+    /// Whether the search stopped early on `timeout_ms`.
+    pub truncated: bool,
+    pub candidates: Vec<ExplainedCandidate>,
+    /// The query's results, as `query_similar_with_options` returns them.
+    pub results: Vec<Episode>,
+    /// Time spent in the index search.
+    pub search_time: Duration,
+    /// Time spent filtering and ranking candidates.
+    pub filter_time: Duration,
+}
+
+/// Result order: distance ascending, ties broken by recency (recent first); episodes
+/// without a timestamp sort last.
+fn rank_order<E: std::borrow::Borrow<Episode>>(a: &(f32, E), b: &(f32, E)) -> std::cmp::Ordering {
+    let dist_cmp = a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal);
+    if dist_cmp != std::cmp::Ordering::Equal {
+        return dist_cmp;
+    }
+    let ts_a = a.1.borrow().timestamp.unwrap_or(i64::MIN);
+    let ts_b = b.1.borrow().timestamp.unwrap_or(i64::MIN);
+    ts_b.cmp(&ts_a)
+}
+
+/// Fetch index candidates for `opts`: `top_k * candidate_multiplier()` of them, doubled while
+/// `opts.adaptive` is set and fewer than `top_k` pass `matches`, until the index is exhausted.
+/// With an `allowed` key set only those keys are searched (`vector_of` reads their
+/// embeddings). The search stops early once `opts.timeout_ms` has elapsed or `opts.cancel` is
+/// cancelled. Returns the hits, the number of candidates requested and whether the search was
+/// cut short.
+fn search_candidates<'a>(
+    index: &IndexBackend,
+    allowed: Option<&KeySet>,
+    vector_of: impl Fn(usize) -> Option<&'a [f32]>,
+    query_embedding: &[f32],
+    opts: &QueryOptions,
+    matches: impl Fn(usize) -> bool,
+) -> (Vec<(usize, f32)>, usize, bool) {
+    let budget = Budget {
+        deadline: opts
+            .timeout_ms
+            .map(|ms| Instant::now() + Duration::from_millis(ms)),
+        cancelled: opts.cancel.as_ref().map(CancelToken::flag),
+    };
+    let limit = allowed.map_or(index.len(), KeySet::len);
+    let mut k = opts.top_k.saturating_mul(opts.candidate_multiplier());
+    loop {
+        let (results, truncated) = match allowed {
+            Some(allowed) => index.search_allowed(query_embedding, k, allowed, &vector_of, budget),
+            None => index.search_until(query_embedding, k, budget),
+        };
+        let exhausted = results.len() < k || k >= limit;
+        if truncated
+            || !opts.adaptive
+            || exhausted
+            || results.iter().filter(|(key, _)| matches(*key)).count() >= opts.top_k
+        {
+            return (results, k, truncated);
+        }
+        if budget.spent() {
+            return (results, k, true);
+        }
+        k = k.saturating_mul(2).min(limit);
     }
 }
 
@@ -295,20 +1121,129 @@ impl QueryOptions {
 /// ```
 pub struct AgentMemDB {
     dim: usize,
-    episodes: HashMap<Uuid, Episode>,
+    /// Shared with snapshots from `clone_snapshot`.
+    episodes: HashMap<Uuid, Arc<Episode>>,
     index: IndexBackend,
     key_to_uuid: HashMap<usize, Uuid>,
+    /// Keys by tag, user_id and source, for filtered index searches.
+    filters: KeyFilters,
+    /// Opt-in per-tag indexes (see `add_tag_index`).
+    tag_indexes: HashMap<String, SubIndex>,
+    /// Per-user indexes, when partitioned (see `set_user_partitions`).
+    user_partitions: Option<HashMap<String, SubIndex>>,
+    /// For `store_text` / `query_text` (see `set_embedder`).
+    embedder: Option<Arc<dyn EmbeddingProvider>>,
+    /// Records queries when set (see `set_query_log`).
+    query_log: Option<Arc<QueryLog>>,
+    /// Near-duplicate handling on store (see `set_store_policy`).
+    store_policy: Option<StorePolicy>,
+    /// Size limits checked on store (see `set_limits`).
+    limits: EpisodeLimits,
+    /// Fill unset timestamps on store (see `set_auto_timestamp`).
+    auto_timestamp: bool,
+    /// Retrievals counted by queries (see `access_stats`).
+    access: AccessTracker,
+    /// Where prunes move removed episodes (see `set_archive`).
+    archive: Option<Arc<dyn EpisodeArchive>>,
 }
 
 #[derive(Error, Debug)]
 pub enum AgentMemError {
     #[error("Embedding dimension mismatch: expected {expected}, got {got}")]
     DimensionMismatch { expected: usize, got: usize },
+    /// A file or directory operation failed; `context` names it (e.g. "Write log"). The
+    /// message keeps the `HNSW or IO error:` prefix these failures have always had.
+    #[error("HNSW or IO error: {context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// Serializing, or parsing a persisted file, failed.
+    #[error("HNSW or IO error: {context}: {source}")]
+    Serde {
+        context: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The HNSW index already holds `max_elements` vectors, counting those of replaced
+    /// episodes until the next rebuild. Nothing was stored.
+    #[error("HNSW or IO error: Index full: max_elements {max_elements} reached")]
+    IndexFull { max_elements: usize },
+    /// The episode log has an unreadable or inconsistent record at `line` (1-based).
+    #[error("HNSW or IO error: Parse episode: line {line}: {reason}")]
+    Corruption { line: usize, reason: String },
+    /// A lock around a shared DB was poisoned by a writer that panicked.
     #[error("HNSW or IO error: {0}")]
-    HnswError(String),
-    // Add bincode to dependencies
+    Locked(String),
+    /// What was to be created is already there: a tenant, or a DB at a snapshot target.
+    #[error("Already exists: {0}")]
+    AlreadyExists(String),
+    /// An embedding with a NaN or infinite component, which no distance can rank.
+    #[error("Invalid embedding: {0}")]
+    InvalidEmbedding(String),
     #[error("Episode not found")]
     NotFound,
+    /// `update_episode` was given a version other than the stored one: someone else updated
+    /// the episode since it was read.
+    #[error("Version conflict: expected {expected}, found {actual}")]
+    Conflict { expected: u64, actual: u64 },
+    /// An embedding provider failed, or none is attached.
+    #[error("Embedding error: {0}")]
+    Embedding(String),
+    /// The store policy rejected a near-duplicate of a stored episode (see `StorePolicy`).
+    #[error("Duplicate of episode {existing}")]
+    Duplicate { existing: Uuid },
+    /// The query's cancel token was cancelled before it finished (see `CancelToken`).
+    #[error("Query cancelled")]
+    Cancelled,
+    /// A persisted file was written by a newer build, in a format version this one can't read
+    /// (see `FORMAT_VERSION`).
+    #[error("Unsupported {file} format version {found} (this build reads up to {supported})")]
+    UnsupportedVersion {
+        file: &'static str,
+        found: u32,
+        supported: u32,
+    },
+    /// A `Summarizer` failed to summarize episodes.
+    #[error("Summarizer error: {0}")]
+    Summarizer(String),
+    /// An episode is over one of the DB's size limits (see `EpisodeLimits`).
+    #[error("Episode exceeds {limit}: {actual} > {max}")]
+    LimitExceeded {
+        limit: &'static str,
+        max: usize,
+        actual: usize,
+    },
+    /// A query's arguments don't fit together, e.g. `query_fused` weights that don't match
+    /// its query embeddings.
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+}
+
+impl AgentMemError {
+    /// `map_err` adapter wrapping an I/O error as `Io` with `context`.
+    pub fn io(context: impl Into<String>) -> impl FnOnce(std::io::Error) -> Self {
+        let context = context.into();
+        move |source| AgentMemError::Io { context, source }
+    }
+
+    /// `map_err` adapter wrapping a JSON error as `Serde` with `context`.
+    pub fn serde(context: impl Into<String>) -> impl FnOnce(serde_json::Error) -> Self {
+        let context = context.into();
+        move |source| AgentMemError::Serde { context, source }
+    }
+}
+
+/// Reject embeddings with a NaN or infinite component.
+pub(crate) fn check_finite(embedding: &[f32]) -> Result<(), AgentMemError> {
+    match embedding.iter().position(|x| !x.is_finite()) {
+        Some(i) => Err(AgentMemError::InvalidEmbedding(format!(
+            "component {i} is {}",
+            embedding[i]
+        ))),
+        None => Ok(()),
+    }
 }
 
 impl AgentMemDB {
@@ -324,6 +1259,36 @@ impl AgentMemDB {
             episodes: HashMap::new(),
             index: IndexBackend::Hnsw(Box::new(HnswIndex::new(max_elements))),
             key_to_uuid: HashMap::new(),
+            filters: KeyFilters::default(),
+            tag_indexes: HashMap::new(),
+            user_partitions: None,
+            embedder: None,
+            query_log: None,
+            store_policy: None,
+            limits: EpisodeLimits::default(),
+            auto_timestamp: false,
+            access: AccessTracker::default(),
+            archive: None,
+        }
+    }
+
+    /// Create a new empty AgentMemDB with custom HNSW parameters (`params.m` must be at least 2).
+    pub fn new_hnsw(dim: usize, max_elements: usize, params: HnswParams) -> Self {
+        Self {
+            dim,
+            episodes: HashMap::new(),
+            index: IndexBackend::Hnsw(Box::new(HnswIndex::with_params(max_elements, params))),
+            key_to_uuid: HashMap::new(),
+            filters: KeyFilters::default(),
+            tag_indexes: HashMap::new(),
+            user_partitions: None,
+            embedder: None,
+            query_log: None,
+            store_policy: None,
+            limits: EpisodeLimits::default(),
+            auto_timestamp: false,
+            access: AccessTracker::default(),
+            archive: None,
         }
     }
 
@@ -335,6 +1300,16 @@ impl AgentMemDB {
             episodes: HashMap::new(),
             index: IndexBackend::Exact(ExactIndex::new()),
             key_to_uuid: HashMap::new(),
+            filters: KeyFilters::default(),
+            tag_indexes: HashMap::new(),
+            user_partitions: None,
+            embedder: None,
+            query_log: None,
+            store_policy: None,
+            limits: EpisodeLimits::default(),
+            auto_timestamp: false,
+            access: AccessTracker::default(),
+            archive: None,
         }
     }
 
@@ -343,8 +1318,30 @@ impl AgentMemDB {
         self.dim
     }
 
-    /// Store an episode in memory and update the HNSW index.
-    /// Returns an error if the embedding dimension does not match.
+    /// Number of stored episodes.
+    pub fn len(&self) -> usize {
+        self.episodes.len()
+    }
+
+    /// True when no episodes are stored.
+    pub fn is_empty(&self) -> bool {
+        self.episodes.is_empty()
+    }
+
+    /// Iterate over all stored episodes (arbitrary order).
+    pub fn iter(&self) -> impl Iterator<Item = &Episode> {
+        self.episodes.values().map(|ep| &**ep)
+    }
+
+    /// The stored episode with this id, if any.
+    pub fn get(&self, id: Uuid) -> Option<&Episode> {
+        self.episodes.get(&id).map(|ep| &**ep)
+    }
+
+    /// Store an episode in memory and update the HNSW index. An episode with the same id is
+    /// replaced. Returns an error if the embedding dimension does not match or the episode is
+    /// over a size limit (see `set_limits`). Near-duplicates are handled per the store policy,
+    /// if one is set (see `set_store_policy`).
     ///
     /// Example:
     ///
@@ -354,17 +1351,120 @@ impl AgentMemDB {
     /// let ep = Episode::new("t", vec![0.0f32; 16], 0.5);
     /// db.store_episode(ep).unwrap();
     /// ```
-    pub fn store_episode(&mut self, episode: Episode) -> Result<(), AgentMemError> {
+    pub fn store_episode(&mut self, mut episode: Episode) -> Result<(), AgentMemError> {
         if episode.state_embedding.len() != self.dim {
             return Err(AgentMemError::DimensionMismatch {
                 expected: self.dim,
                 got: episode.state_embedding.len(),
             });
         }
+        check_finite(&episode.state_embedding)?;
+        self.limits.check(&episode)?;
+        self.index.check_room(1)?;
+        if self.auto_timestamp {
+            auto_timestamp::stamp([&mut episode]);
+        }
+        self.fold_access_stats();
+        let episode = self.apply_store_policy(episode)?;
         let id = episode.id;
+        // Replacing: drop the old index entry so queries don't return the episode twice.
+        self.unindex(id);
         let key = self.index.insert(&episode.state_embedding);
         self.key_to_uuid.insert(key, id);
-        self.episodes.insert(id, episode);
+        self.filters.insert(key, &episode);
+        self.insert_sub_indexes(key, &episode);
+        self.episodes.insert(id, Arc::new(episode));
+        Ok(())
+    }
+
+    /// Drop the index keys of stored episode `id`. Its vector stays in the graph, unreachable.
+    fn unindex(&mut self, id: Uuid) {
+        let Some(old) = self.episodes.get(&id) else {
+            return;
+        };
+        let filters = &mut self.filters;
+        self.key_to_uuid.retain(|&key, uuid| {
+            let stale = *uuid == id;
+            if stale {
+                filters.remove(key, old);
+            }
+            !stale
+        });
+    }
+
+    /// Replace the stored episode with the same id, if its version is still `expected_version`
+    /// (optimistic concurrency). Returns the new version, `expected_version + 1`, which is also
+    /// set on the stored episode. Access statistics carry over from the stored episode, as
+    /// they aren't the caller's to set. Fails with `NotFound` for an unknown id and `Conflict`
+    /// when another writer updated the episode first; the DB is unchanged on error.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, AgentMemError, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// let mut ep = Episode::new("t", vec![0.0, 1.0], 0.5);
+    /// db.store_episode(ep.clone()).unwrap();
+    /// ep.reward = 1.0;
+    /// assert_eq!(db.update_episode(ep.clone(), 0).unwrap(), 1);
+    /// assert!(matches!(db.update_episode(ep, 0), Err(AgentMemError::Conflict { actual: 1, .. })));
+    /// ```
+    pub fn update_episode(
+        &mut self,
+        mut episode: Episode,
+        expected_version: u64,
+    ) -> Result<u64, AgentMemError> {
+        let current = self
+            .episodes
+            .get(&episode.id)
+            .ok_or(AgentMemError::NotFound)?;
+        if current.version != expected_version {
+            return Err(AgentMemError::Conflict {
+                expected: expected_version,
+                actual: current.version,
+            });
+        }
+        self.access.stats(current).set_on(&mut episode);
+        episode.version = expected_version + 1;
+        let version = episode.version;
+        self.store_episode(episode)?;
+        Ok(version)
+    }
+
+    /// Soft-delete an episode: it stays stored (and saved) but queries skip it until
+    /// `restore`, and `purge_deleted` removes it for good. Counts as an update (the version is
+    /// incremented). No-op if already deleted.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// let ep = Episode::new("t", vec![0.0, 1.0], 0.5);
+    /// db.store_episode(ep.clone()).unwrap();
+    /// db.soft_delete(ep.id).unwrap();
+    /// assert!(db.query_similar(&[0.0, 1.0], 0.0, 5).unwrap().is_empty());
+    /// db.restore(ep.id).unwrap();
+    /// assert_eq!(db.query_similar(&[0.0, 1.0], 0.0, 5).unwrap().len(), 1);
+    /// ```
+    pub fn soft_delete(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        self.set_deleted(id, true)
+    }
+
+    /// Undo `soft_delete`. No-op if the episode isn't deleted.
+    pub fn restore(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        self.set_deleted(id, false)
+    }
+
+    /// Permanently remove every soft-deleted episode. Returns episodes removed.
+    pub fn purge_deleted(&mut self) -> usize {
+        self.delete_where(|ep| ep.deleted)
+    }
+
+    fn set_deleted(&mut self, id: Uuid, deleted: bool) -> Result<(), AgentMemError> {
+        let ep = self.episodes.get_mut(&id).ok_or(AgentMemError::NotFound)?;
+        if ep.deleted != deleted {
+            // The embedding is unchanged, so the index entry stays.
+            let ep = Arc::make_mut(ep);
+            ep.deleted = deleted;
+            ep.version += 1;
+        }
         Ok(())
     }
 
@@ -400,62 +1500,269 @@ impl AgentMemDB {
         query_embedding: &[f32],
         opts: QueryOptions,
     ) -> Result<Vec<Episode>, AgentMemError> {
+        self.query_similar_timed(query_embedding, opts)
+            .map(|results| results.episodes)
+    }
+
+    /// Like `query_similar_with_options`, and also report whether `opts.timeout_ms` cut the
+    /// search short, in which case the results are the best found in the time allowed.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, QueryOptions};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("t", vec![0.0, 1.0], 1.0)).unwrap();
+    /// let opts = QueryOptions::new(0.0, 5).timeout_ms(50);
+    /// let results = db.query_similar_timed(&[0.0, 1.0], opts).unwrap();
+    /// assert_eq!(results.episodes.len(), 1);
+    /// assert!(!results.truncated);
+    /// ```
+    pub fn query_similar_timed(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<QueryResults, AgentMemError> {
+        let started = Instant::now();
+        let (hits, truncated) = self.ranked_hits(query_embedding, &opts)?;
+        let mut episodes: Vec<Episode> = hits.iter().map(|ep| opts.project_ref(ep)).collect();
+        self.access.record(&mut episodes);
+        if let Some(ref log) = self.query_log {
+            log.record(query_embedding, &opts, &episodes, started.elapsed());
+        }
+        Ok(QueryResults {
+            episodes,
+            truncated,
+        })
+    }
+
+    /// Like `query_similar_with_options`, but return the stored episodes themselves, shared,
+    /// instead of copying each hit (embedding, steps and metadata included). Embeddings are
+    /// always included (`include_embeddings` is ignored). A returned episode is a snapshot:
+    /// later updates replace the stored episode and don't change it.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, QueryOptions};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("t", vec![0.0, 1.0], 1.0)).unwrap();
+    /// let hits = db
+    ///     .query_similar_shared(&[0.0, 1.0], QueryOptions::new(0.0, 5))
+    ///     .unwrap();
+    /// assert_eq!(hits[0].task_id, "t");
+    /// ```
+    pub fn query_similar_shared(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Arc<Episode>>, AgentMemError> {
+        let started = Instant::now();
+        let (hits, _) = self.ranked_hits(query_embedding, &opts)?;
+        self.access.record_shared(&hits);
+        if let Some(ref log) = self.query_log {
+            log.record(query_embedding, &opts, &hits, started.elapsed());
+        }
+        Ok(hits)
+    }
+
+    /// The stored episodes a query returns, best first, and whether the search was cut short.
+    /// Only reference counts are copied.
+    fn ranked_hits(
+        &self,
+        query_embedding: &[f32],
+        opts: &QueryOptions,
+    ) -> Result<(Vec<Arc<Episode>>, bool), AgentMemError> {
         if query_embedding.len() != self.dim {
             return Err(AgentMemError::DimensionMismatch {
                 expected: self.dim,
                 got: query_embedding.len(),
             });
         }
-        let candidate_mult = if opts.tags_any.is_some()
-            || opts.tags_all.is_some()
-            || opts.task_id_prefix.is_some()
-            || opts.time_after.is_some()
-            || opts.time_before.is_some()
-            || opts.source.is_some()
-            || opts.user_id.is_some()
-        {
-            4
-        } else {
-            2
-        };
-        let results = self
-            .index
-            .search(query_embedding, opts.top_k * candidate_mult);
-        let mut candidates: Vec<(f32, Episode)> = results
+        opts.check_avoid_dim(self.dim)?;
+        let allowed = self.filters.allowed(opts);
+        let (results, _, truncated) = self.search_index(query_embedding, opts, allowed.as_ref());
+        opts.check_cancelled()?;
+        let mut candidates: Vec<(f32, Arc<Episode>)> = results
             .into_iter()
             .filter_map(|(key, dist)| {
                 self.key_to_uuid
                     .get(&key)
                     .and_then(|uuid| self.episodes.get(uuid))
                     .filter(|ep| opts.matches(ep))
-                    .map(|ep| (dist, ep.clone()))
+                    .map(|ep| (dist, Arc::clone(ep)))
             })
             .collect();
-        // Sort by distance asc; tie-break by recency (recent first). Episodes without timestamp sort last.
-        candidates.sort_by(|a, b| {
-            let dist_cmp = a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal);
-            if dist_cmp != std::cmp::Ordering::Equal {
-                return dist_cmp;
-            }
-            let ts_a = a.1.timestamp.unwrap_or(i64::MIN);
-            let ts_b = b.1.timestamp.unwrap_or(i64::MIN);
-            ts_b.cmp(&ts_a)
-        });
-        let episodes: Vec<Episode> = candidates
+        opts.apply_avoid(&mut candidates, |ep| ep.state_embedding.as_slice());
+        opts.apply_recency_boost(&mut candidates, |ep| ep.timestamp);
+        candidates.sort_by(|a, b| opts.rank(a, b));
+        opts.take_results(&mut candidates, |ep| ep.task_id.clone());
+        Ok((
+            candidates.into_iter().map(|(_, ep)| ep).collect(),
+            truncated,
+        ))
+    }
+
+    /// Whether the episode at index `key` passes `opts`.
+    fn key_matches(&self, key: usize, opts: &QueryOptions) -> bool {
+        self.key_to_uuid
+            .get(&key)
+            .and_then(|uuid| self.episodes.get(uuid))
+            .is_some_and(|ep| opts.matches(ep))
+    }
+
+    /// Embedding of the episode at index `key`.
+    fn vector(&self, key: usize) -> Option<&[f32]> {
+        self.key_to_uuid
+            .get(&key)
+            .and_then(|uuid| self.episodes.get(uuid))
+            .map(|ep| ep.state_embedding.as_slice())
+    }
+
+    /// `search_candidates` over the query's user partition when the index is partitioned (see
+    /// `set_user_partitions`), else its tag sub-index if it has one (see `add_tag_index`), else
+    /// the main index. Hits are main-index keys.
+    fn search_index(
+        &self,
+        query_embedding: &[f32],
+        opts: &QueryOptions,
+        allowed: Option<&KeySet>,
+    ) -> (Vec<(usize, f32)>, usize, bool) {
+        let sub = match self.user_partition(opts) {
+            Some(Some(partition)) => partition,
+            // The user has no episodes: there is nothing of theirs to search.
+            Some(None) => return (Vec::new(), 0, false),
+            None => match self.scoped_tag_index(opts) {
+                Some((_, sub)) => sub,
+                None => {
+                    return search_candidates(
+                        &self.index,
+                        allowed,
+                        |key| self.vector(key),
+                        query_embedding,
+                        opts,
+                        |key| self.key_matches(key, opts),
+                    )
+                }
+            },
+        };
+        let allowed = allowed.map(|keys| sub.translate(keys));
+        let (results, requested, truncated) = search_candidates(
+            &sub.index,
+            allowed.as_ref(),
+            |key| self.vector(sub.main_key(key)?),
+            query_embedding,
+            opts,
+            |key| sub.main_key(key).is_some_and(|k| self.key_matches(k, opts)),
+        );
+        let results = results
             .into_iter()
-            .take(opts.top_k)
-            .map(|(_, ep)| ep)
+            .filter_map(|(key, dist)| Some((sub.main_key(key)?, dist)))
             .collect();
-        Ok(episodes)
+        (results, requested, truncated)
     }
 
-    /// Store multiple episodes in memory and update the HNSW index for each.
-    ///
-    /// This is a convenience batch API that calls `store_episode` for each entry.
-    /// If you need higher performance for very large batches, consider a bulk
-    /// construction API (not implemented here) or increase `max_elements` in the
-    /// HNSW configuration used at construction time.
-    pub fn store_episodes(&mut self, episodes: Vec<Episode>) -> Result<(), AgentMemError> {
+    /// Run a query like `query_similar_with_options` and report every index candidate,
+    /// which filter (if any) eliminated it, and where the time went.
+    pub fn explain_query(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<QueryExplanation, AgentMemError> {
+        if query_embedding.len() != self.dim {
+            return Err(AgentMemError::DimensionMismatch {
+                expected: self.dim,
+                got: query_embedding.len(),
+            });
+        }
+        opts.check_avoid_dim(self.dim)?;
+        let candidate_multiplier = opts.candidate_multiplier();
+        let start = Instant::now();
+        let allowed = self.filters.allowed(&opts);
+        let (results, candidates_requested, truncated) =
+            self.search_index(query_embedding, &opts, allowed.as_ref());
+        let search_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut candidates: Vec<ExplainedCandidate> = results
+            .into_iter()
+            .filter_map(|(key, distance)| {
+                let ep = self
+                    .key_to_uuid
+                    .get(&key)
+                    .and_then(|u| self.episodes.get(u))?;
+                Some(ExplainedCandidate {
+                    episode: Episode::clone(ep),
+                    distance,
+                    rejected_by: opts.rejected_by(ep),
+                    returned: false,
+                })
+            })
+            .collect();
+        let mut ranked: Vec<(f32, usize)> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.rejected_by.is_none())
+            .map(|(i, c)| (c.distance, i))
+            .collect();
+        for (distance, i) in ranked.iter_mut() {
+            *distance += opts.avoid_penalty(&candidates[*i].episode.state_embedding);
+        }
+        opts.apply_recency_boost(&mut ranked, |&i| candidates[i].episode.timestamp);
+        ranked.sort_by(|a, b| {
+            opts.rank(
+                &(a.0, &candidates[a.1].episode),
+                &(b.0, &candidates[b.1].episode),
+            )
+        });
+        opts.take_results(&mut ranked, |&i| candidates[i].episode.task_id.clone());
+        let user_partition = self.user_partition(&opts).is_some();
+        let tag_index = match self.scoped_tag_index(&opts) {
+            Some((tag, _)) if !user_partition => Some(tag.to_string()),
+            _ => None,
+        };
+        let results = ranked
+            .iter()
+            .map(|&(_, i)| {
+                candidates[i].returned = true;
+                opts.project_ref(&candidates[i].episode)
+            })
+            .collect();
+        Ok(QueryExplanation {
+            candidate_multiplier,
+            allowed_keys: allowed.as_ref().map(KeySet::len),
+            tag_index,
+            user_partition,
+            candidates_requested,
+            truncated,
+            candidates,
+            results,
+            search_time,
+            filter_time: start.elapsed(),
+        })
+    }
+
+    /// Store multiple episodes, all-or-nothing: every dimension and size limit (and, under a
+    /// rejecting store policy, every duplicate check) is checked before any episode is
+    /// inserted, so a bad entry leaves the DB unchanged.
+    pub fn store_episodes(&mut self, mut episodes: Vec<Episode>) -> Result<(), AgentMemError> {
+        if let Some(ep) = episodes
+            .iter()
+            .find(|ep| ep.state_embedding.len() != self.dim)
+        {
+            return Err(AgentMemError::DimensionMismatch {
+                expected: self.dim,
+                got: ep.state_embedding.len(),
+            });
+        }
+        episodes
+            .iter()
+            .try_for_each(|ep| check_finite(&ep.state_embedding))?;
+        self.limits.check_all(&episodes)?;
+        self.index.check_room(episodes.len())?;
+        if let Some(err) = self.first_rejected(&episodes) {
+            return Err(err);
+        }
+        // One time for the whole batch.
+        if self.auto_timestamp {
+            auto_timestamp::stamp(&mut episodes);
+        }
         for ep in episodes {
             self.store_episode(ep)?;
         }
@@ -481,15 +1788,16 @@ impl AgentMemDB {
 
     /// Save all episodes to a JSON file. On load, the HNSW index is rebuilt.
     pub fn save_to_file(&self, path: &Path) -> Result<(), AgentMemError> {
-        let file = File::create(path)
-            .map_err(|e| AgentMemError::HnswError(format!("File create: {e}")))?;
+        let file = File::create(path).map_err(AgentMemError::io("File create"))?;
         let writer = BufWriter::new(file);
+        let mut episodes: Vec<Episode> = self.iter().cloned().collect();
+        self.access.fill(&mut episodes);
         let persisted = PersistedDB {
+            format_version: FORMAT_VERSION,
             dim: self.dim,
-            episodes: self.episodes.values().cloned().collect(),
+            episodes,
         };
-        serde_json::to_writer(writer, &persisted)
-            .map_err(|e| AgentMemError::HnswError(format!("Serialize: {e}")))?;
+        serde_json::to_writer(writer, &persisted).map_err(AgentMemError::serde("Serialize"))?;
         Ok(())
     }
 
@@ -506,124 +1814,68 @@ impl AgentMemDB {
     /// Prune episodes with timestamp older than cutoff (Unix ms).
     /// Episodes without timestamp are kept. Returns the number of episodes removed.
     /// Rebuilds the index internally (HNSW/Exact do not support in-place removal).
-    pub fn prune_older_than(&mut self, timestamp_cutoff_ms: i64) -> usize {
-        let kept: Vec<Episode> = self
-            .episodes
-            .values()
-            .filter(|ep| {
-                ep.timestamp
-                    .map(|t| t >= timestamp_cutoff_ms)
-                    .unwrap_or(true)
-            })
-            .cloned()
-            .collect();
-        let removed = self.episodes.len() - kept.len();
-        self.episodes.clear();
-        self.key_to_uuid.clear();
-        let was_exact = matches!(&self.index, IndexBackend::Exact(_));
-        self.index = if was_exact {
-            IndexBackend::Exact(ExactIndex::new())
-        } else {
-            IndexBackend::Hnsw(Box::new(HnswIndex::new(
-                kept.len().max(20_000).max(self.dim * 2),
-            )))
-        };
-        for ep in kept {
-            let id = ep.id;
-            let key = self.index.insert(&ep.state_embedding);
-            self.key_to_uuid.insert(key, id);
-            self.episodes.insert(id, ep);
-        }
-        removed
+    pub fn prune_older_than(&mut self, timestamp_cutoff_ms: i64) -> Result<usize, AgentMemError> {
+        self.prune(&[PruneRule::OlderThan(timestamp_cutoff_ms)])
     }
 
     /// Prune to keep only the n most recent episodes (by timestamp).
     /// Episodes without timestamp are treated as oldest and pruned first. Returns episodes removed.
-    pub fn prune_keep_newest(&mut self, n: usize) -> usize {
-        if self.episodes.len() <= n {
-            return 0;
-        }
-        let mut episodes: Vec<Episode> = self.episodes.drain().map(|(_, ep)| ep).collect();
-        let original = episodes.len();
-        episodes.sort_by(|a, b| {
-            let ts_a = a.timestamp.unwrap_or(i64::MIN);
-            let ts_b = b.timestamp.unwrap_or(i64::MIN);
-            ts_b.cmp(&ts_a)
-        });
-        let kept: Vec<Episode> = episodes.into_iter().take(n).collect();
-        let removed = original - kept.len();
-        self.key_to_uuid.clear();
-        let was_exact = matches!(&self.index, IndexBackend::Exact(_));
-        self.index = if was_exact {
-            IndexBackend::Exact(ExactIndex::new())
-        } else {
-            IndexBackend::Hnsw(Box::new(HnswIndex::new(
-                kept.len().max(20_000).max(self.dim * 2),
-            )))
-        };
-        for ep in kept {
-            let id = ep.id;
-            let key = self.index.insert(&ep.state_embedding);
-            self.key_to_uuid.insert(key, id);
-            self.episodes.insert(id, ep);
-        }
-        removed
+    pub fn prune_keep_newest(&mut self, n: usize) -> Result<usize, AgentMemError> {
+        self.prune(&[PruneRule::KeepNewest(n)])
     }
 
     /// Prune to keep only the n episodes with highest reward.
     /// Ties: prefer more recent (higher timestamp); episodes without timestamp sort last. Returns episodes removed.
-    pub fn prune_keep_highest_reward(&mut self, n: usize) -> usize {
-        if self.episodes.len() <= n {
+    pub fn prune_keep_highest_reward(&mut self, n: usize) -> Result<usize, AgentMemError> {
+        self.prune(&[PruneRule::KeepHighestReward(n)])
+    }
+
+    /// Remove every episode matching `predicate`, e.g. by id or user. Returns episodes removed.
+    /// Rebuilds the index when anything was removed.
+    pub fn delete_where(&mut self, predicate: impl Fn(&Episode) -> bool) -> usize {
+        self.fold_access_stats();
+        let kept: Vec<Arc<Episode>> = self
+            .episodes
+            .values()
+            .filter(|ep| !predicate(ep))
+            .cloned()
+            .collect();
+        let removed = self.episodes.len() - kept.len();
+        if removed == 0 {
             return 0;
         }
-        let mut episodes: Vec<Episode> = self.episodes.drain().map(|(_, ep)| ep).collect();
-        let original = episodes.len();
-        episodes.sort_by(|a, b| {
-            let reward_cmp = b
-                .reward
-                .partial_cmp(&a.reward)
-                .unwrap_or(std::cmp::Ordering::Equal);
-            if reward_cmp != std::cmp::Ordering::Equal {
-                return reward_cmp;
-            }
-            let ts_a = a.timestamp.unwrap_or(i64::MIN);
-            let ts_b = b.timestamp.unwrap_or(i64::MIN);
-            ts_b.cmp(&ts_a)
-        });
-        let kept: Vec<Episode> = episodes.into_iter().take(n).collect();
-        let removed = original - kept.len();
-        self.key_to_uuid.clear();
-        let was_exact = matches!(&self.index, IndexBackend::Exact(_));
-        self.index = if was_exact {
-            IndexBackend::Exact(ExactIndex::new())
-        } else {
-            IndexBackend::Hnsw(Box::new(HnswIndex::new(
-                kept.len().max(20_000).max(self.dim * 2),
-            )))
-        };
-        for ep in kept {
-            let id = ep.id;
-            let key = self.index.insert(&ep.state_embedding);
-            self.key_to_uuid.insert(key, id);
-            self.episodes.insert(id, ep);
-        }
+        self.reindex(kept);
         removed
     }
 
+    /// Remove every episode of the session `session_id`, e.g. when a conversation ends.
+    /// Returns episodes removed.
+    pub fn prune_session(&mut self, session_id: &str) -> Result<usize, AgentMemError> {
+        self.prune_where(|ep| ep.session_id.as_deref() == Some(session_id))
+    }
+
+    /// Replace the stored episodes with `kept` and rebuild the index (and sub-indexes)
+    /// over them; HNSW/Exact do not support in-place removal. The new index is built off to
+    /// the side and swapped in once complete (see `rebuild`).
+    fn reindex(&mut self, kept: Vec<Arc<Episode>>) {
+        let rebuilt = self.rebuild(kept);
+        self.install(rebuilt);
+    }
+
     fn load_from_file_with_index(path: &Path, use_exact: bool) -> Result<Self, AgentMemError> {
-        let file =
-            File::open(path).map_err(|e| AgentMemError::HnswError(format!("File open: {e}")))?;
-        let reader = BufReader::new(file);
-        let persisted: PersistedDB = serde_json::from_reader(reader)
-            .map_err(|e| AgentMemError::HnswError(format!("Deserialize: {e}")))?;
+        let file = File::open(path).map_err(AgentMemError::io("File open"))?;
+        let mut data = String::new();
+        BufReader::new(file)
+            .read_to_string(&mut data)
+            .map_err(AgentMemError::io("File read"))?;
+        let persisted: PersistedDB = format::parse(format::Format::SaveFile, &data)?;
         let mut db = if use_exact {
             AgentMemDB::new_exact(persisted.dim)
         } else {
             AgentMemDB::new(persisted.dim)
         };
         for ep in persisted.episodes {
-            db.store_episode(ep)
-                .map_err(|e| AgentMemError::HnswError(format!("Reinsert: {e}")))?;
+            db.store_episode(ep)?;
         }
         Ok(db)
     }
@@ -0,0 +1,149 @@
+//! Store-time size limits on episodes, so one misbehaving agent can't write multi-megabyte
+//! observations into every episode and blow up memory, save files and query payloads.
+
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode};
+use serde::{Deserialize, Serialize};
+
+/// Size limits checked on store, set with `AgentMemDB::set_limits` (or
+/// `AgentMemDBDisk::set_limits`). `None` means unlimited; the default has no limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EpisodeLimits {
+    /// Max size of `metadata` as serialized JSON, in bytes. Null metadata counts as 0.
+    pub max_metadata_bytes: Option<usize>,
+    /// Max number of `steps`.
+    pub max_steps: Option<usize>,
+    /// Max number of `tags`.
+    pub max_tags: Option<usize>,
+    /// Max length of `task_id`, in bytes.
+    pub max_task_id_len: Option<usize>,
+}
+
+impl EpisodeLimits {
+    /// No limits (same as `default()`).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_metadata_bytes(mut self, n: usize) -> Self {
+        self.max_metadata_bytes = Some(n);
+        self
+    }
+
+    pub fn max_steps(mut self, n: usize) -> Self {
+        self.max_steps = Some(n);
+        self
+    }
+
+    pub fn max_tags(mut self, n: usize) -> Self {
+        self.max_tags = Some(n);
+        self
+    }
+
+    pub fn max_task_id_len(mut self, n: usize) -> Self {
+        self.max_task_id_len = Some(n);
+        self
+    }
+
+    /// True when no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check `episode` against every limit set. Fails with `AgentMemError::LimitExceeded`
+    /// naming the first limit it is over.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemError, Episode, EpisodeLimits};
+    /// let limits = EpisodeLimits::new().max_tags(1);
+    /// let mut ep = Episode::new("t", vec![0.0], 1.0);
+    /// ep.tags = Some(vec!["a".into(), "b".into()]);
+    /// assert!(matches!(
+    ///     limits.check(&ep),
+    ///     Err(AgentMemError::LimitExceeded { limit: "max_tags", max: 1, actual: 2 })
+    /// ));
+    /// ```
+    pub fn check(&self, episode: &Episode) -> Result<(), AgentMemError> {
+        let over = |limit, max: Option<usize>, actual: usize| match max {
+            Some(max) if actual > max => Err(AgentMemError::LimitExceeded { limit, max, actual }),
+            _ => Ok(()),
+        };
+        over(
+            "max_task_id_len",
+            self.max_task_id_len,
+            episode.task_id.len(),
+        )?;
+        over(
+            "max_tags",
+            self.max_tags,
+            episode.tags.as_ref().map_or(0, Vec::len),
+        )?;
+        over(
+            "max_steps",
+            self.max_steps,
+            episode.steps.as_ref().map_or(0, Vec::len),
+        )?;
+        if self.max_metadata_bytes.is_some() {
+            over(
+                "max_metadata_bytes",
+                self.max_metadata_bytes,
+                metadata_bytes(episode),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// `check` each episode of a batch, so it can fail before anything is stored.
+    pub(crate) fn check_all(&self, episodes: &[Episode]) -> Result<(), AgentMemError> {
+        if self.is_unlimited() {
+            return Ok(());
+        }
+        episodes.iter().try_for_each(|ep| self.check(ep))
+    }
+}
+
+/// Serialized size of the episode's metadata; null (no metadata) is 0.
+fn metadata_bytes(episode: &Episode) -> usize {
+    if episode.metadata.is_null() {
+        return 0;
+    }
+    serde_json::to_vec(&episode.metadata).map_or(0, |v| v.len())
+}
+
+impl AgentMemDB {
+    /// Set the size limits checked by `store_episode`, `store_episodes` and `update_episode`
+    /// (see `EpisodeLimits`). An episode over a limit fails with `LimitExceeded` and nothing
+    /// is stored; a batch with one such episode is rejected whole. Episodes already stored
+    /// are not checked. Limits aren't saved with the DB.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, AgentMemError, Episode, EpisodeLimits};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.set_limits(EpisodeLimits::new().max_metadata_bytes(64));
+    /// let mut ep = Episode::new("t", vec![0.0, 1.0], 1.0);
+    /// ep.metadata = serde_json::json!({"observation": "x".repeat(1_000)});
+    /// assert!(matches!(db.store_episode(ep), Err(AgentMemError::LimitExceeded { .. })));
+    /// assert!(db.is_empty());
+    /// ```
+    pub fn set_limits(&mut self, limits: EpisodeLimits) {
+        self.limits = limits;
+    }
+
+    /// The size limits checked on store.
+    pub fn limits(&self) -> EpisodeLimits {
+        self.limits
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Set the size limits checked on store, as for `AgentMemDB::set_limits`. An episode
+    /// over a limit is rejected before it is written to the log.
+    pub fn set_limits(&mut self, limits: EpisodeLimits) {
+        self.limits = limits;
+    }
+
+    /// The size limits checked on store.
+    pub fn limits(&self) -> EpisodeLimits {
+        self.limits
+    }
+}
@@ -0,0 +1,157 @@
+//! Two-tier agent memory: raw episodes plus the facts and summaries consolidated from them,
+//! recalled together.
+
+use crate::federated::{query_federated, EpisodeStore};
+use crate::{AgentMemDB, AgentMemError, Episode, QueryOptions};
+use serde::Serialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Which store of a [`MemorySystem`] a recalled memory came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryKind {
+    /// A raw episode, as the agent experienced it.
+    Episodic,
+    /// A fact or summary consolidated from episodes.
+    Semantic,
+}
+
+/// One result of [`MemorySystem::recall`].
+#[derive(Debug, Clone)]
+pub struct Recalled {
+    pub episode: Episode,
+    /// L2 distance to the query embedding.
+    pub distance: f32,
+    pub kind: MemoryKind,
+}
+
+/// An episodic store (raw episodes) and a semantic store (facts and summaries) of the same
+/// dimension, with one `recall` over both.
+///
+/// New experience goes to the episodic store with `remember`. `consolidate` replaces a group
+/// of episodes with a summary in the semantic store; facts that come from elsewhere go there
+/// directly with `learn`. Each store is a plain `AgentMemDB`, reachable through
+/// `episodic_mut`/`semantic_mut` for pruning, saving or anything else the facade doesn't wrap.
+///
+/// ```rust
+/// use agent_mem_db::{Episode, MemoryKind, MemorySystem, QueryOptions};
+/// let mut memory = MemorySystem::new_exact(2);
+/// let a = Episode::new("deploy failed: disk full", vec![1.0, 0.0], -1.0);
+/// let b = Episode::new("deploy failed: disk full again", vec![1.0, 0.1], -1.0);
+/// let ids = [a.id, b.id];
+/// memory.remember(a).unwrap();
+/// memory.remember(b).unwrap();
+/// memory.remember(Episode::new("deploy ok", vec![0.0, 1.0], 1.0)).unwrap();
+///
+/// let summary = Episode::new("deploys fail when the disk is full", vec![1.0, 0.05], -1.0);
+/// assert_eq!(memory.consolidate(&ids, summary).unwrap(), 2);
+///
+/// let hits = memory.recall(&[1.0, 0.0], QueryOptions::new(-1.0, 2)).unwrap();
+/// assert_eq!(hits[0].kind, MemoryKind::Semantic);
+/// assert_eq!(hits[1].kind, MemoryKind::Episodic);
+/// ```
+pub struct MemorySystem {
+    episodic: AgentMemDB,
+    semantic: AgentMemDB,
+}
+
+impl MemorySystem {
+    /// Two empty HNSW-backed stores of dimension `dim`.
+    pub fn new(dim: usize) -> Self {
+        Self {
+            episodic: AgentMemDB::new(dim),
+            semantic: AgentMemDB::new(dim),
+        }
+    }
+
+    /// Two empty exact-search stores of dimension `dim`.
+    pub fn new_exact(dim: usize) -> Self {
+        Self {
+            episodic: AgentMemDB::new_exact(dim),
+            semantic: AgentMemDB::new_exact(dim),
+        }
+    }
+
+    /// Wrap existing stores, e.g. loaded from files. Their dimensions must match.
+    pub fn from_stores(episodic: AgentMemDB, semantic: AgentMemDB) -> Result<Self, AgentMemError> {
+        if episodic.dim() != semantic.dim() {
+            return Err(AgentMemError::DimensionMismatch {
+                expected: episodic.dim(),
+                got: semantic.dim(),
+            });
+        }
+        Ok(Self { episodic, semantic })
+    }
+
+    /// The episodic and semantic stores.
+    pub fn into_stores(self) -> (AgentMemDB, AgentMemDB) {
+        (self.episodic, self.semantic)
+    }
+
+    pub fn dim(&self) -> usize {
+        self.episodic.dim()
+    }
+
+    pub fn episodic(&self) -> &AgentMemDB {
+        &self.episodic
+    }
+
+    pub fn episodic_mut(&mut self) -> &mut AgentMemDB {
+        &mut self.episodic
+    }
+
+    pub fn semantic(&self) -> &AgentMemDB {
+        &self.semantic
+    }
+
+    pub fn semantic_mut(&mut self) -> &mut AgentMemDB {
+        &mut self.semantic
+    }
+
+    /// Store a raw episode in the episodic store.
+    pub fn remember(&mut self, episode: Episode) -> Result<(), AgentMemError> {
+        self.episodic.store_episode(episode)
+    }
+
+    /// Store a fact or summary in the semantic store.
+    pub fn learn(&mut self, fact: Episode) -> Result<(), AgentMemError> {
+        self.semantic.store_episode(fact)
+    }
+
+    /// Replace the episodes `episode_ids` with `summary`: the summary is stored in the
+    /// semantic store, then the episodes are removed from the episodic store. Returns the
+    /// number of episodes removed; ids not in the episodic store are ignored. If the summary
+    /// can't be stored, nothing is removed.
+    pub fn consolidate(
+        &mut self,
+        episode_ids: &[Uuid],
+        summary: Episode,
+    ) -> Result<usize, AgentMemError> {
+        self.semantic.store_episode(summary)?;
+        let ids: HashSet<Uuid> = episode_ids.iter().copied().collect();
+        Ok(self.episodic.delete_where(|ep| ids.contains(&ep.id)))
+    }
+
+    /// Query both stores with `opts` and merge into one `top_k` list, nearest first, each
+    /// result labelled with the store it came from.
+    pub fn recall(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Recalled>, AgentMemError> {
+        let stores: [&dyn EpisodeStore; 2] = [&self.episodic, &self.semantic];
+        Ok(query_federated(&stores, query_embedding, opts)?
+            .into_iter()
+            .map(|hit| Recalled {
+                episode: hit.episode,
+                distance: hit.distance,
+                kind: if hit.source == 0 {
+                    MemoryKind::Episodic
+                } else {
+                    MemoryKind::Semantic
+                },
+            })
+            .collect())
+    }
+}
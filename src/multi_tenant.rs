@@ -0,0 +1,741 @@
+//! Multi-tenant mode: one store per tenant behind one handle, created on first use from
+//! per-tenant options, reopened from disk after eviction.
+
+use crate::{
+    AccessSummary, AgentMemDB, AgentMemDBDisk, AgentMemError, ContrastiveRecall, CostSummary,
+    DiskOptions, Episode, EpisodeArchive, EpisodeStore, FusedHit, HnswParams, PruneRule,
+    QueryExplanation, QueryOptions, QueryResults, TagSubtree, VacuumReport, VerifyReport,
+    WarmUpReport, WindowStats,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// File in a disk tenant's directory holding its real id; the directory name is lossy (see
+/// `tenant_dir_name`).
+pub const TENANT_ID_FILE: &str = "tenant_id";
+
+/// Directory name for `tenant_id` under a multi-tenant root: ASCII letters, digits, `-` and
+/// `_` are kept, anything else becomes `_`.
+pub fn tenant_dir_name(tenant_id: &str) -> String {
+    tenant_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// How a tenant's store is created.
+#[derive(Debug, Clone)]
+pub enum TenantOptions {
+    /// An in-memory `AgentMemDB`; its episodes are gone once the tenant is evicted.
+    Memory {
+        dim: usize,
+        /// Exact (brute-force) search instead of HNSW.
+        exact: bool,
+        /// HNSW capacity; ignored when `exact`.
+        max_elements: usize,
+        /// HNSW graph parameters; ignored when `exact`.
+        hnsw_params: HnswParams,
+    },
+    /// An `AgentMemDBDisk` in the tenant's directory under the root.
+    Disk(DiskOptions),
+}
+
+impl TenantOptions {
+    /// In memory, HNSW with `AgentMemDB::new`'s capacity.
+    pub fn memory(dim: usize) -> Self {
+        Self::memory_hnsw(dim, 20_000, HnswParams::default())
+    }
+
+    /// In memory, exact search.
+    pub fn memory_exact(dim: usize) -> Self {
+        TenantOptions::Memory {
+            dim,
+            exact: true,
+            max_elements: 20_000,
+            hnsw_params: HnswParams::default(),
+        }
+    }
+
+    /// In memory, HNSW with the given capacity and graph parameters.
+    pub fn memory_hnsw(dim: usize, max_elements: usize, hnsw_params: HnswParams) -> Self {
+        TenantOptions::Memory {
+            dim,
+            exact: false,
+            max_elements,
+            hnsw_params,
+        }
+    }
+
+    /// On disk, opened with `options`.
+    pub fn disk(options: DiskOptions) -> Self {
+        TenantOptions::Disk(options)
+    }
+
+    pub fn dim(&self) -> usize {
+        match self {
+            TenantOptions::Memory { dim, .. } => *dim,
+            TenantOptions::Disk(options) => options.dim,
+        }
+    }
+}
+
+/// One tenant's store.
+pub enum TenantStore {
+    Memory(AgentMemDB),
+    Disk(AgentMemDBDisk),
+}
+
+impl TenantStore {
+    /// Create `tenant_id`'s store, or reopen it if it is on disk under `root` (see
+    /// `TenantOptions`). Disk tenants live in `root/<tenant_dir_name>` with their id in
+    /// `TENANT_ID_FILE`, and need a root. Fails with `AlreadyExists` if the directory belongs
+    /// to another tenant whose id maps to the same name (`a.b` and `a_b`).
+    pub fn open(
+        root: Option<&Path>,
+        tenant_id: &str,
+        options: &TenantOptions,
+    ) -> Result<Self, AgentMemError> {
+        match (options, root) {
+            (
+                TenantOptions::Memory {
+                    dim,
+                    exact,
+                    max_elements,
+                    hnsw_params,
+                },
+                _,
+            ) => Ok(TenantStore::Memory(if *exact {
+                AgentMemDB::new_exact(*dim)
+            } else {
+                AgentMemDB::new_hnsw(*dim, *max_elements, *hnsw_params)
+            })),
+            (TenantOptions::Disk(options), Some(root)) => {
+                let dir = root.join(tenant_dir_name(tenant_id));
+                let id_file = dir.join(TENANT_ID_FILE);
+                let owner = match fs::read_to_string(&id_file) {
+                    Ok(owner) => Some(owner),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(AgentMemError::io("Read tenant_id")(e)),
+                };
+                if let Some(owner) = owner.as_ref().filter(|owner| *owner != tenant_id) {
+                    return Err(AgentMemError::AlreadyExists(format!(
+                        "tenant directory {} holds tenant {owner:?}, not {tenant_id:?}",
+                        dir.display()
+                    )));
+                }
+                let db = AgentMemDBDisk::open_with_options(&dir, options.clone())?;
+                if owner.is_none() {
+                    fs::write(&id_file, tenant_id).map_err(AgentMemError::io("Write tenant_id"))?;
+                }
+                Ok(TenantStore::Disk(db))
+            }
+            (TenantOptions::Disk(_), None) => {
+                Err(AgentMemError::io("Open tenant")(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "disk tenants require a root directory",
+                )))
+            }
+        }
+    }
+
+    /// Whether `tenant_id` has a disk store under `root`, loaded or not.
+    pub fn exists_on_disk(root: &Path, tenant_id: &str) -> bool {
+        AgentMemDBDisk::exists(root.join(tenant_dir_name(tenant_id)))
+    }
+
+    pub fn store_episode(&mut self, episode: Episode) -> Result<(), AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.store_episode(episode),
+            TenantStore::Disk(db) => db.store_episode(episode),
+        }
+    }
+
+    pub fn store_episodes(&mut self, episodes: Vec<Episode>) -> Result<(), AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.store_episodes(episodes),
+            TenantStore::Disk(db) => db.store_episodes(episodes),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            TenantStore::Memory(db) => db.len(),
+            TenantStore::Disk(db) => db.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over all stored episodes (arbitrary order).
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &Episode> + '_> {
+        match self {
+            TenantStore::Memory(db) => Box::new(db.iter()),
+            TenantStore::Disk(db) => Box::new(db.iter()),
+        }
+    }
+
+    /// Make a disk store's state durable (see `AgentMemDBDisk::checkpoint`); no-op in memory.
+    pub fn checkpoint(&mut self) -> Result<(), AgentMemError> {
+        match self {
+            TenantStore::Memory(_) => Ok(()),
+            TenantStore::Disk(db) => db.checkpoint(),
+        }
+    }
+
+    pub fn is_disk(&self) -> bool {
+        matches!(self, TenantStore::Disk(_))
+    }
+
+    pub fn update_episode(
+        &mut self,
+        episode: Episode,
+        expected_version: u64,
+    ) -> Result<u64, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.update_episode(episode, expected_version),
+            TenantStore::Disk(db) => db.update_episode(episode, expected_version),
+        }
+    }
+
+    pub fn soft_delete(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.soft_delete(id),
+            TenantStore::Disk(db) => db.soft_delete(id),
+        }
+    }
+
+    pub fn restore(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.restore(id),
+            TenantStore::Disk(db) => db.restore(id),
+        }
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&Episode> {
+        match self {
+            TenantStore::Memory(db) => db.get(id),
+            TenantStore::Disk(db) => db.get(id),
+        }
+    }
+
+    pub fn set_auto_timestamp(&mut self, enabled: bool) {
+        match self {
+            TenantStore::Memory(db) => db.set_auto_timestamp(enabled),
+            TenantStore::Disk(db) => db.set_auto_timestamp(enabled),
+        }
+    }
+
+    pub fn query_similar_timed(
+        &self,
+        embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<QueryResults, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.query_similar_timed(embedding, opts),
+            TenantStore::Disk(db) => db.query_similar_timed(embedding, opts),
+        }
+    }
+
+    pub fn query_fused(
+        &self,
+        queries: &[Vec<f32>],
+        weights: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<FusedHit>, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.query_fused(queries, weights, opts),
+            TenantStore::Disk(db) => db.query_fused(queries, weights, opts),
+        }
+    }
+
+    pub fn contrastive_recall_with_options(
+        &self,
+        query_embedding: &[f32],
+        reward_threshold: f32,
+        opts: QueryOptions,
+    ) -> Result<ContrastiveRecall, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => {
+                db.contrastive_recall_with_options(query_embedding, reward_threshold, opts)
+            }
+            TenantStore::Disk(db) => {
+                db.contrastive_recall_with_options(query_embedding, reward_threshold, opts)
+            }
+        }
+    }
+
+    pub fn explain_query(
+        &self,
+        embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<QueryExplanation, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.explain_query(embedding, opts),
+            TenantStore::Disk(db) => db.explain_query(embedding, opts),
+        }
+    }
+
+    pub fn prune(&mut self, policy: &[PruneRule]) -> Result<usize, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.prune(policy),
+            TenantStore::Disk(db) => db.prune(policy),
+        }
+    }
+
+    pub fn prune_older_than(&mut self, ts: i64) -> Result<usize, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.prune_older_than(ts),
+            TenantStore::Disk(db) => db.prune_older_than(ts),
+        }
+    }
+
+    pub fn prune_keep_newest(&mut self, n: usize) -> Result<usize, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.prune_keep_newest(n),
+            TenantStore::Disk(db) => db.prune_keep_newest(n),
+        }
+    }
+
+    pub fn prune_keep_highest_reward(&mut self, n: usize) -> Result<usize, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.prune_keep_highest_reward(n),
+            TenantStore::Disk(db) => db.prune_keep_highest_reward(n),
+        }
+    }
+
+    pub fn access_summary(&self) -> AccessSummary {
+        match self {
+            TenantStore::Memory(db) => db.access_summary(),
+            TenantStore::Disk(db) => db.access_summary(),
+        }
+    }
+
+    pub fn cost_summary(&self) -> CostSummary {
+        match self {
+            TenantStore::Memory(db) => db.cost_summary(),
+            TenantStore::Disk(db) => db.cost_summary(),
+        }
+    }
+
+    pub fn windowed_stats(
+        &self,
+        window_ms: i64,
+        step_ms: i64,
+    ) -> Result<Vec<WindowStats>, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.windowed_stats(window_ms, step_ms),
+            TenantStore::Disk(db) => db.windowed_stats(window_ms, step_ms),
+        }
+    }
+
+    pub fn tag_subtree_stats(&self, root: &str) -> Vec<TagSubtree> {
+        match self {
+            TenantStore::Memory(db) => db.tag_subtree_stats(root),
+            TenantStore::Disk(db) => db.tag_subtree_stats(root),
+        }
+    }
+
+    /// Delete matching episodes; returns how many were removed.
+    pub fn delete_where(
+        &mut self,
+        predicate: impl Fn(&Episode) -> bool,
+    ) -> Result<usize, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => Ok(db.delete_where(predicate)),
+            TenantStore::Disk(db) => db.delete_where(predicate),
+        }
+    }
+
+    /// The attached archive, if any (see `set_archive`).
+    pub fn archive(&self) -> Option<&Arc<dyn EpisodeArchive>> {
+        match self {
+            TenantStore::Memory(db) => db.archive(),
+            TenantStore::Disk(db) => db.archive(),
+        }
+    }
+
+    pub fn set_archive(
+        &mut self,
+        archive: Option<Arc<dyn EpisodeArchive>>,
+    ) -> Result<(), AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.set_archive(archive),
+            TenantStore::Disk(db) => db.set_archive(archive),
+        }
+    }
+
+    pub fn query_archive(
+        &self,
+        embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.query_archive(embedding, opts),
+            TenantStore::Disk(db) => db.query_archive(embedding, opts),
+        }
+    }
+
+    /// Save an in-memory store to `path` (see `AgentMemDB::save_to_file`); no-op on disk,
+    /// where every write is already persisted.
+    pub fn save_to_file(&self, path: &Path) -> Result<(), AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.save_to_file(path),
+            TenantStore::Disk(_) => Ok(()),
+        }
+    }
+
+    /// Compact a disk store (see `AgentMemDBDisk::vacuum`); `None` in memory, where there
+    /// are no files to compact.
+    pub fn vacuum(&mut self) -> Result<Option<VacuumReport>, AgentMemError> {
+        match self {
+            TenantStore::Memory(_) => Ok(None),
+            TenantStore::Disk(db) => db.vacuum().map(Some),
+        }
+    }
+
+    pub fn warm_up(
+        &self,
+        sample_queries: Option<&[Vec<f32>]>,
+    ) -> Result<WarmUpReport, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.warm_up(sample_queries),
+            TenantStore::Disk(db) => db.warm_up(sample_queries),
+        }
+    }
+
+    /// Check a disk store's files (see `AgentMemDBDisk::verify`); `None` in memory, where
+    /// there are none.
+    pub fn verify(&self) -> Result<Option<VerifyReport>, AgentMemError> {
+        match self {
+            TenantStore::Memory(_) => Ok(None),
+            TenantStore::Disk(db) => db.verify().map(Some),
+        }
+    }
+
+    /// `"memory"` or `"disk"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TenantStore::Memory(_) => "memory",
+            TenantStore::Disk(_) => "disk",
+        }
+    }
+}
+
+impl EpisodeStore for TenantStore {
+    fn dim(&self) -> usize {
+        match self {
+            TenantStore::Memory(db) => db.dim(),
+            TenantStore::Disk(db) => db.dim(),
+        }
+    }
+
+    fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        match self {
+            TenantStore::Memory(db) => db.query_similar_with_options(query_embedding, opts),
+            TenantStore::Disk(db) => db.query_similar_with_options(query_embedding, opts),
+        }
+    }
+}
+
+/// What `MultiTenantMemDB` keeps for each loaded tenant: its `TenantStore`, or a host's own
+/// type holding the store next to per-tenant bookkeeping (the server keeps stored bytes and
+/// write-behind state this way).
+pub trait TenantEntry {
+    fn store(&self) -> &TenantStore;
+    fn store_mut(&mut self) -> &mut TenantStore;
+}
+
+impl TenantEntry for TenantStore {
+    fn store(&self) -> &TenantStore {
+        self
+    }
+
+    fn store_mut(&mut self) -> &mut TenantStore {
+        self
+    }
+}
+
+struct LoadedTenant<T> {
+    entry: T,
+    last_access: Instant,
+}
+
+/// Stores for many tenants behind one handle, as the server keeps one per API key. A tenant's
+/// store is created from its options (`set_tenant_options`, else the defaults) the first time
+/// it is written to, and disk tenants are reopened from `root` when used after eviction.
+///
+/// Hosts that open stores themselves or keep more per tenant use `MultiTenantMemDB<T>` with
+/// their own `TenantEntry`, and `get_or_insert_with`, `insert` and `loaded_mut` in place of
+/// `get_or_create` and `get`; access tracking and eviction work the same.
+///
+/// ```rust
+/// use agent_mem_db::{DiskOptions, Episode, MultiTenantMemDB, QueryOptions, TenantOptions};
+/// let dir = std::env::temp_dir().join("agent_mem_db_multi_tenant_doctest");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// let mut db = MultiTenantMemDB::with_root(&dir, TenantOptions::disk(DiskOptions::exact(2)))
+///     .unwrap();
+/// db.set_tenant_options("scratch", TenantOptions::memory_exact(2));
+/// db.get_or_create("acme").unwrap()
+///     .store_episode(Episode::new("deploy", vec![1.0, 0.0], 1.0)).unwrap();
+/// assert!(db.get_or_create("scratch").unwrap().is_empty());
+///
+/// // Evicted disk tenants are reopened on their next use.
+/// db.evict("acme").unwrap();
+/// assert_eq!(db.loaded_tenants().count(), 1);
+/// let hits = db.query("acme", &[1.0, 0.0], QueryOptions::new(0.0, 5)).unwrap();
+/// assert_eq!(hits[0].task_id, "deploy");
+/// ```
+pub struct MultiTenantMemDB<T = TenantStore> {
+    root: Option<PathBuf>,
+    default_options: TenantOptions,
+    tenant_options: HashMap<String, TenantOptions>,
+    tenants: HashMap<String, LoadedTenant<T>>,
+}
+
+impl<T: TenantEntry> MultiTenantMemDB<T> {
+    /// Tenants kept in memory only; `TenantOptions::Disk` tenants can't be created.
+    pub fn new(default_options: TenantOptions) -> Self {
+        Self {
+            root: None,
+            default_options,
+            tenant_options: HashMap::new(),
+            tenants: HashMap::new(),
+        }
+    }
+
+    /// Disk tenants live in a directory each under `root`, which is created if missing.
+    pub fn with_root(
+        root: impl AsRef<Path>,
+        default_options: TenantOptions,
+    ) -> Result<Self, AgentMemError> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).map_err(AgentMemError::io("Create dir"))?;
+        Ok(Self {
+            root: Some(root),
+            ..Self::new(default_options)
+        })
+    }
+
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Options for creating (or reopening) `tenant_id`'s store from now on, instead of the
+    /// defaults. A loaded store keeps the options it was opened with until evicted.
+    pub fn set_tenant_options(&mut self, tenant_id: impl Into<String>, options: TenantOptions) {
+        self.tenant_options.insert(tenant_id.into(), options);
+    }
+
+    /// The options `tenant_id`'s store is created with.
+    pub fn tenant_options(&self, tenant_id: &str) -> &TenantOptions {
+        self.tenant_options
+            .get(tenant_id)
+            .unwrap_or(&self.default_options)
+    }
+
+    /// `tenant_id`'s entry, loading it with `open` if it isn't loaded. Counts as a use for
+    /// eviction.
+    pub fn get_or_insert_with<E>(
+        &mut self,
+        tenant_id: &str,
+        open: impl FnOnce() -> Result<T, E>,
+    ) -> Result<&mut T, E> {
+        if !self.tenants.contains_key(tenant_id) {
+            let entry = open()?;
+            self.insert(tenant_id, entry);
+        }
+        let tenant = self.tenants.get_mut(tenant_id).expect("tenant just loaded");
+        tenant.last_access = Instant::now();
+        Ok(&mut tenant.entry)
+    }
+
+    /// Load `entry` as `tenant_id`'s, replacing (and returning) a loaded one.
+    pub fn insert(&mut self, tenant_id: impl Into<String>, entry: T) -> Option<T> {
+        let loaded = LoadedTenant {
+            entry,
+            last_access: Instant::now(),
+        };
+        self.tenants
+            .insert(tenant_id.into(), loaded)
+            .map(|old| old.entry)
+    }
+
+    /// Drop `tenant_id` from memory without checkpointing it (see `evict`).
+    pub fn remove(&mut self, tenant_id: &str) -> Option<T> {
+        self.tenants.remove(tenant_id).map(|tenant| tenant.entry)
+    }
+
+    /// A loaded tenant's entry; doesn't load, and doesn't count as a use.
+    pub fn loaded(&self, tenant_id: &str) -> Option<&T> {
+        self.tenants.get(tenant_id).map(|tenant| &tenant.entry)
+    }
+
+    /// Mutable `loaded`.
+    pub fn loaded_mut(&mut self, tenant_id: &str) -> Option<&mut T> {
+        self.tenants
+            .get_mut(tenant_id)
+            .map(|tenant| &mut tenant.entry)
+    }
+
+    pub fn is_loaded(&self, tenant_id: &str) -> bool {
+        self.tenants.contains_key(tenant_id)
+    }
+
+    /// When a loaded tenant was last used (loaded, or returned by `get_or_insert_with`).
+    pub fn last_access(&self, tenant_id: &str) -> Option<Instant> {
+        self.tenants.get(tenant_id).map(|tenant| tenant.last_access)
+    }
+
+    /// Loaded tenants and their entries (arbitrary order).
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.tenants
+            .iter()
+            .map(|(id, tenant)| (id.as_str(), &tenant.entry))
+    }
+
+    /// Mutable `iter`.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut T)> {
+        self.tenants
+            .iter_mut()
+            .map(|(id, tenant)| (id.as_str(), &mut tenant.entry))
+    }
+
+    /// Drop `tenant_id`'s store from memory, checkpointing a disk store first so it reopens
+    /// quickly; it stays loaded if that fails. Returns the store, so the caller can save an
+    /// in-memory one, or `None` if the tenant wasn't loaded.
+    pub fn evict(&mut self, tenant_id: &str) -> Result<Option<T>, AgentMemError> {
+        let Some(tenant) = self.tenants.get_mut(tenant_id) else {
+            return Ok(None);
+        };
+        tenant.entry.store_mut().checkpoint()?;
+        Ok(self.remove(tenant_id))
+    }
+
+    /// Evict disk tenants not used for `idle_timeout`, then the least recently used ones while
+    /// more than `max_loaded` tenants are loaded. In-memory tenants are never evicted here, and
+    /// a tenant whose checkpoint fails stays loaded. Returns the evicted tenant ids.
+    pub fn evict_idle(
+        &mut self,
+        idle_timeout: Option<Duration>,
+        max_loaded: Option<usize>,
+    ) -> Vec<String> {
+        self.evict_idle_with(idle_timeout, max_loaded, |_, store| store.checkpoint())
+    }
+
+    /// `evict_idle`, checkpointing each tenant with `checkpoint` (e.g. to time or log it).
+    pub fn evict_idle_with(
+        &mut self,
+        idle_timeout: Option<Duration>,
+        max_loaded: Option<usize>,
+        mut checkpoint: impl FnMut(&str, &mut TenantStore) -> Result<(), AgentMemError>,
+    ) -> Vec<String> {
+        let now = Instant::now();
+        let mut candidates: Vec<(&String, Instant)> = self
+            .tenants
+            .iter()
+            .filter(|(_, t)| t.entry.store().is_disk())
+            .map(|(id, t)| (id, t.last_access))
+            .collect();
+        candidates.sort_by_key(|&(_, last)| last);
+
+        let excess = max_loaded.map_or(0, |max| self.tenants.len().saturating_sub(max));
+        let to_evict: Vec<String> = candidates
+            .iter()
+            .enumerate()
+            .filter(|&(i, &(_, last))| {
+                i < excess || idle_timeout.is_some_and(|idle| now.duration_since(last) >= idle)
+            })
+            .map(|(_, &(id, _))| id.clone())
+            .collect();
+        to_evict
+            .into_iter()
+            .filter(|id| {
+                let tenant = self.tenants.get_mut(id).expect("candidate is loaded");
+                if checkpoint(id, tenant.entry.store_mut()).is_err() {
+                    return false;
+                }
+                self.tenants.remove(id);
+                true
+            })
+            .collect()
+    }
+
+    /// Ids of the tenants currently loaded (arbitrary order).
+    pub fn loaded_tenants(&self) -> impl Iterator<Item = &str> {
+        self.tenants.keys().map(String::as_str)
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        self.tenants.len()
+    }
+
+    /// Checkpoint every loaded disk store (see `TenantStore::checkpoint`), e.g. before exit.
+    pub fn checkpoint_all(&mut self) -> Result<(), AgentMemError> {
+        self.tenants
+            .values_mut()
+            .try_for_each(|tenant| tenant.entry.store_mut().checkpoint())
+    }
+
+    fn on_disk(&self, tenant_id: &str) -> bool {
+        self.root
+            .as_deref()
+            .is_some_and(|root| TenantStore::exists_on_disk(root, tenant_id))
+    }
+}
+
+impl MultiTenantMemDB {
+    /// Create `tenant_id`'s store. Fails if the tenant is loaded or already on disk.
+    pub fn create_tenant(&mut self, tenant_id: &str) -> Result<&mut TenantStore, AgentMemError> {
+        if self.tenants.contains_key(tenant_id) || self.on_disk(tenant_id) {
+            return Err(AgentMemError::AlreadyExists(format!("tenant {tenant_id}")));
+        }
+        self.get_or_create(tenant_id)
+    }
+
+    /// `tenant_id`'s store, loading it from disk if it was evicted. `None` for a tenant that
+    /// was never created.
+    pub fn get(&mut self, tenant_id: &str) -> Result<Option<&mut TenantStore>, AgentMemError> {
+        if !self.tenants.contains_key(tenant_id) && !self.on_disk(tenant_id) {
+            return Ok(None);
+        }
+        self.get_or_create(tenant_id).map(Some)
+    }
+
+    /// `tenant_id`'s store, loading it from disk or creating it as needed.
+    pub fn get_or_create(&mut self, tenant_id: &str) -> Result<&mut TenantStore, AgentMemError> {
+        if !self.is_loaded(tenant_id) {
+            let store = TenantStore::open(self.root(), tenant_id, self.tenant_options(tenant_id))?;
+            self.insert(tenant_id, store);
+        }
+        self.get_or_insert_with(tenant_id, || unreachable!("tenant just loaded"))
+    }
+
+    /// Query `tenant_id`'s store; empty for a tenant that was never created.
+    pub fn query(
+        &mut self,
+        tenant_id: &str,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        match self.get(tenant_id)? {
+            Some(store) => store.query_similar_with_options(query_embedding, opts),
+            None => Ok(Vec::new()),
+        }
+    }
+}
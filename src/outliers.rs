@@ -0,0 +1,120 @@
+//! Outlier detection: episodes unusually far from their nearest neighbours, often corrupted
+//! embeddings or off-task memories that crowd out useful results.
+
+use crate::index::IndexBackend;
+use crate::{AgentMemDB, Episode};
+use uuid::Uuid;
+
+/// An episode whose neighbours are anomalously far away (see `AgentMemDB::find_outliers`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outlier {
+    pub id: Uuid,
+    pub task_id: String,
+    /// Mean L2 distance to the episode's k nearest neighbours.
+    pub mean_distance: f32,
+    /// Standard deviations `mean_distance` lies above the average over all episodes.
+    pub z_score: f32,
+}
+
+/// Mean distance from each live episode to its `k` nearest live neighbours in `index`, then
+/// those more than `threshold` standard deviations above the average, most anomalous first.
+/// `episode_at` resolves an index key to its current episode (stale keys give `None`).
+pub(crate) fn find_outliers<'a>(
+    index: &IndexBackend,
+    episode_at: impl Fn(usize) -> Option<&'a Episode>,
+    k: usize,
+    threshold: f32,
+) -> Vec<Outlier> {
+    if k == 0 {
+        return Vec::new();
+    }
+    let mut scored: Vec<(&Episode, f32)> = Vec::new();
+    for key in 0..index.len() {
+        let Some(ep) = episode_at(key).filter(|ep| !ep.deleted) else {
+            continue;
+        };
+        // Widen the search past stale and deleted keys until k live neighbours are found.
+        let mut fetch = k + 1;
+        let neighbours = loop {
+            let found: Vec<f32> = index
+                .search(&ep.state_embedding, fetch)
+                .into_iter()
+                .filter(|&(hit, _)| hit != key && episode_at(hit).is_some_and(|n| !n.deleted))
+                .map(|(_, distance)| distance)
+                .take(k)
+                .collect();
+            if found.len() == k || fetch >= index.len() {
+                break found;
+            }
+            fetch = (fetch * 2).min(index.len());
+        };
+        if !neighbours.is_empty() {
+            let mean = neighbours.iter().sum::<f32>() / neighbours.len() as f32;
+            scored.push((ep, mean));
+        }
+    }
+    if scored.len() < 2 {
+        return Vec::new();
+    }
+
+    let n = scored.len() as f32;
+    let average = scored.iter().map(|&(_, d)| d).sum::<f32>() / n;
+    let std_dev = (scored
+        .iter()
+        .map(|&(_, d)| (d - average).powi(2))
+        .sum::<f32>()
+        / n)
+        .sqrt();
+    if std_dev <= f32::EPSILON {
+        return Vec::new();
+    }
+    let mut outliers: Vec<Outlier> = scored
+        .into_iter()
+        .map(|(ep, mean_distance)| Outlier {
+            id: ep.id,
+            task_id: ep.task_id.clone(),
+            mean_distance,
+            z_score: (mean_distance - average) / std_dev,
+        })
+        .filter(|o| o.z_score > threshold)
+        .collect();
+    outliers.sort_by(|a, b| {
+        b.z_score
+            .partial_cmp(&a.z_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    outliers
+}
+
+impl AgentMemDB {
+    /// Episodes whose mean distance to their `k` nearest neighbours is more than `threshold`
+    /// standard deviations above the average, most anomalous first. Neighbours come from the
+    /// index (approximate under HNSW); soft-deleted episodes are skipped. `threshold` of 2 to 3
+    /// is a reasonable start; a few extreme outliers widen the deviation and can hide milder
+    /// ones until they are removed. One index search per episode.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// for i in 0..20 {
+    ///     db.store_episode(Episode::new("ok", vec![i as f32 * 0.1, 0.0], 1.0)).unwrap();
+    /// }
+    /// db.store_episode(Episode::new("corrupt", vec![50.0, 50.0], 1.0)).unwrap();
+    /// let outliers = db.find_outliers(3, 2.0);
+    /// assert_eq!(outliers.len(), 1);
+    /// assert_eq!(outliers[0].task_id, "corrupt");
+    /// ```
+    pub fn find_outliers(&self, k: usize, threshold: f32) -> Vec<Outlier> {
+        find_outliers(
+            &self.index,
+            |key| {
+                self.key_to_uuid
+                    .get(&key)
+                    .and_then(|uuid| self.episodes.get(uuid))
+                    .map(|ep| ep.as_ref())
+            },
+            k,
+            threshold,
+        )
+    }
+}
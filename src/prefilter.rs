@@ -0,0 +1,129 @@
+//! Per-value key bitsets for pushing tag, user_id, session_id, embedding_model and source filters
+//! into the index search.
+
+use crate::index::KeySet;
+use crate::{tag_has_prefix, Episode, QueryOptions};
+use std::collections::HashMap;
+
+/// Index keys by tag, user_id, session_id, embedding_model and source, kept in step with a DB's
+/// `key_to_uuid`.
+///
+/// A query's tag, user_id, session_id, embedding_model and source filters become an allow-list for
+/// `IndexBackend::search_allowed`, so a selective filter (one tenant's user_id among
+/// thousands) no longer depends on over-fetching enough candidates to survive filtering.
+#[derive(Default)]
+pub(crate) struct KeyFilters {
+    tags: HashMap<String, KeySet>,
+    user_ids: HashMap<String, KeySet>,
+    session_ids: HashMap<String, KeySet>,
+    embedding_models: HashMap<String, KeySet>,
+    sources: HashMap<String, KeySet>,
+}
+
+impl KeyFilters {
+    /// Bitsets for already indexed `(key, episode)` pairs, e.g. after a log replay.
+    pub fn build<'a>(entries: impl IntoIterator<Item = (usize, &'a Episode)>) -> Self {
+        let mut filters = Self::default();
+        for (key, ep) in entries {
+            filters.insert(key, ep);
+        }
+        filters
+    }
+
+    /// Record the tags, user_id, session_id, embedding_model and source of the episode indexed at `key`.
+    pub fn insert(&mut self, key: usize, ep: &Episode) {
+        for tag in ep.tags.iter().flatten() {
+            self.tags.entry(tag.clone()).or_default().insert(key);
+        }
+        if let Some(ref u) = ep.user_id {
+            self.user_ids.entry(u.clone()).or_default().insert(key);
+        }
+        if let Some(ref s) = ep.session_id {
+            self.session_ids.entry(s.clone()).or_default().insert(key);
+        }
+        if let Some(ref m) = ep.embedding_model {
+            self.embedding_models
+                .entry(m.clone())
+                .or_default()
+                .insert(key);
+        }
+        if let Some(ref s) = ep.source {
+            self.sources.entry(s.clone()).or_default().insert(key);
+        }
+    }
+
+    /// Forget `key`, whose episode was `ep` (the episode was replaced or removed).
+    pub fn remove(&mut self, key: usize, ep: &Episode) {
+        fn remove_from(sets: &mut HashMap<String, KeySet>, value: &str, key: usize) {
+            if let Some(set) = sets.get_mut(value) {
+                set.remove(key);
+                if set.is_empty() {
+                    sets.remove(value);
+                }
+            }
+        }
+        for tag in ep.tags.iter().flatten() {
+            remove_from(&mut self.tags, tag, key);
+        }
+        if let Some(ref u) = ep.user_id {
+            remove_from(&mut self.user_ids, u, key);
+        }
+        if let Some(ref s) = ep.session_id {
+            remove_from(&mut self.session_ids, s, key);
+        }
+        if let Some(ref m) = ep.embedding_model {
+            remove_from(&mut self.embedding_models, m, key);
+        }
+        if let Some(ref s) = ep.source {
+            remove_from(&mut self.sources, s, key);
+        }
+    }
+
+    /// The keys that pass the `tags_any`, `tags_all`, `tag_prefix_any`, `source`, `user_id`,
+    /// `session_id` and `embedding_model` filters of `opts`, or `None` when none of them is set.
+    /// The remaining filters are still applied to the search results.
+    pub fn allowed(&self, opts: &QueryOptions) -> Option<KeySet> {
+        let empty = KeySet::default();
+        let mut allowed: Option<KeySet> = None;
+        let mut restrict = |set: &KeySet| match allowed {
+            Some(ref mut keys) => keys.intersect_with(set),
+            None => allowed = Some(set.clone()),
+        };
+        if let Some(ref tags) = opts.tags_any {
+            let mut any = KeySet::default();
+            for set in tags.iter().filter_map(|t| self.tags.get(t)) {
+                any.union_with(set);
+            }
+            restrict(&any);
+        }
+        if let Some(ref tags) = opts.tags_all {
+            for tag in tags {
+                restrict(self.tags.get(tag).unwrap_or(&empty));
+            }
+        }
+        if let Some(ref prefixes) = opts.tag_prefix_any {
+            let mut any = KeySet::default();
+            for (_, set) in self
+                .tags
+                .iter()
+                .filter(|(tag, _)| prefixes.iter().any(|p| tag_has_prefix(tag, p)))
+            {
+                any.union_with(set);
+            }
+            restrict(&any);
+        }
+        if let Some(ref s) = opts.source {
+            restrict(self.sources.get(s).unwrap_or(&empty));
+        }
+        if let Some(ref u) = opts.user_id {
+            restrict(self.user_ids.get(u).unwrap_or(&empty));
+        }
+        if let Some(ref s) = opts.session_id {
+            restrict(self.session_ids.get(s).unwrap_or(&empty));
+        }
+        if let Some(ref m) = opts.embedding_model {
+            restrict(self.embedding_models.get(m).unwrap_or(&empty));
+        }
+        allowed
+    }
+}
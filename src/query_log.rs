@@ -0,0 +1,252 @@
+//! Query logging and offline replay: record production queries, then re-run them against a
+//! differently configured store (other HNSW parameters, index kind, options) and measure how
+//! much the results change.
+
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode, EpisodeStore, QueryOptions};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// One logged query.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryLogEntry {
+    /// Stable hash of the embedding (see [`embedding_hash`]), to group repeated queries.
+    pub embedding_hash: u64,
+    /// The query embedding, kept so the query can be replayed.
+    pub embedding: Vec<f32>,
+    pub options: QueryOptions,
+    /// Ids of the returned episodes, in result order.
+    pub result_ids: Vec<Uuid>,
+    /// Time the query took, in microseconds.
+    pub latency_us: u64,
+}
+
+/// A bounded in-memory log of queries, shared with the DBs it is attached to (see
+/// `AgentMemDB::set_query_log`). Once full, the oldest entries are dropped.
+#[derive(Debug)]
+pub struct QueryLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<QueryLogEntry>>,
+}
+
+impl QueryLog {
+    /// A log keeping the most recent `capacity` queries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Append a query, dropping the oldest entry when full.
+    pub fn record<E: Borrow<Episode>>(
+        &self,
+        embedding: &[f32],
+        options: &QueryOptions,
+        results: &[E],
+        latency: Duration,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let entry = QueryLogEntry {
+            embedding_hash: embedding_hash(embedding),
+            embedding: embedding.to_vec(),
+            options: options.clone(),
+            result_ids: results.iter().map(|ep| ep.borrow().id).collect(),
+            latency_us: latency.as_micros() as u64,
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// The logged queries, oldest first.
+    pub fn entries(&self) -> Vec<QueryLogEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Write the logged queries to `path` as JSON lines, for replay elsewhere.
+    pub fn save_jsonl(&self, path: &Path) -> Result<(), AgentMemError> {
+        let file = File::create(path).map_err(AgentMemError::io("File create"))?;
+        let mut writer = BufWriter::new(file);
+        for entry in self.entries() {
+            serde_json::to_writer(&mut writer, &entry)
+                .map_err(AgentMemError::serde("Serialize"))?;
+            writer
+                .write_all(b"\n")
+                .map_err(AgentMemError::io("Write"))?;
+        }
+        writer.flush().map_err(AgentMemError::io("Write"))
+    }
+
+    /// Read entries written by `save_jsonl`.
+    pub fn load_jsonl(path: &Path) -> Result<Vec<QueryLogEntry>, AgentMemError> {
+        let file = File::open(path).map_err(AgentMemError::io("File open"))?;
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(AgentMemError::io("Read"))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line).map_err(AgentMemError::serde("Deserialize"))?);
+        }
+        Ok(entries)
+    }
+}
+
+/// FNV-1a over the embedding's bit patterns: the same vector hashes the same across runs and
+/// builds.
+pub fn embedding_hash(embedding: &[f32]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in embedding.iter().flat_map(|x| x.to_bits().to_le_bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// One query as logged and as replayed.
+#[derive(Debug, Clone)]
+pub struct ReplayedQuery {
+    pub embedding_hash: u64,
+    pub logged_ids: Vec<Uuid>,
+    pub replayed_ids: Vec<Uuid>,
+    /// Shared ids over the longer of the two result lists; 1.0 when both are empty.
+    pub overlap: f32,
+    pub logged_latency: Duration,
+    pub replay_latency: Duration,
+}
+
+/// How replayed results compare with the logged ones (see [`replay_queries`]).
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    pub queries: usize,
+    /// Mean of the per-query overlaps.
+    pub mean_overlap: f32,
+    /// Queries that returned exactly the logged ids, in the same order.
+    pub identical: usize,
+    pub mean_logged_latency: Duration,
+    pub mean_replay_latency: Duration,
+    pub per_query: Vec<ReplayedQuery>,
+}
+
+/// Re-run logged queries against `store` and compare the results with the logged ones. To
+/// try different query options, adjust each entry's `options` before replaying; to try a
+/// different index, load the same episodes into a store built with it.
+///
+/// ```rust
+/// use agent_mem_db::{replay_queries, AgentMemDB, Episode, HnswParams, QueryLog};
+/// use std::sync::Arc;
+/// let episodes: Vec<Episode> =
+///     (0..50).map(|i| Episode::new("t", vec![i as f32, 0.0], 1.0)).collect();
+/// let mut prod = AgentMemDB::new_exact(2);
+/// prod.store_episodes(episodes.clone()).unwrap();
+/// let log = Arc::new(QueryLog::new(100));
+/// prod.set_query_log(Some(log.clone()));
+/// prod.query_similar(&[10.2, 0.0], 0.0, 5).unwrap();
+///
+/// let mut candidate = AgentMemDB::new_hnsw(2, 100, HnswParams::default());
+/// candidate.store_episodes(episodes).unwrap();
+/// let report = replay_queries(&log.entries(), &candidate).unwrap();
+/// assert_eq!(report.queries, 1);
+/// println!("overlap with exact results: {:.2}", report.mean_overlap);
+/// ```
+pub fn replay_queries(
+    log: &[QueryLogEntry],
+    store: &dyn EpisodeStore,
+) -> Result<ReplayReport, AgentMemError> {
+    let mut report = ReplayReport::default();
+    let mut logged_total = Duration::ZERO;
+    let mut replay_total = Duration::ZERO;
+    let mut overlap_total = 0.0f32;
+    for entry in log {
+        let started = Instant::now();
+        let results = store.query_similar_with_options(&entry.embedding, entry.options.clone())?;
+        let replay_latency = started.elapsed();
+        let replayed_ids: Vec<Uuid> = results.iter().map(|ep| ep.id).collect();
+        let overlap = overlap(&entry.result_ids, &replayed_ids);
+        let logged_latency = Duration::from_micros(entry.latency_us);
+
+        report.identical += usize::from(replayed_ids == entry.result_ids);
+        overlap_total += overlap;
+        logged_total += logged_latency;
+        replay_total += replay_latency;
+        report.per_query.push(ReplayedQuery {
+            embedding_hash: entry.embedding_hash,
+            logged_ids: entry.result_ids.clone(),
+            replayed_ids,
+            overlap,
+            logged_latency,
+            replay_latency,
+        });
+    }
+    report.queries = log.len();
+    if report.queries > 0 {
+        let n = report.queries as u32;
+        report.mean_overlap = overlap_total / report.queries as f32;
+        report.mean_logged_latency = logged_total / n;
+        report.mean_replay_latency = replay_total / n;
+    }
+    Ok(report)
+}
+
+fn overlap(logged: &[Uuid], replayed: &[Uuid]) -> f32 {
+    let longest = logged.len().max(replayed.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let logged: HashSet<&Uuid> = logged.iter().collect();
+    let shared = replayed.iter().filter(|id| logged.contains(id)).count();
+    shared as f32 / longest as f32
+}
+
+impl AgentMemDB {
+    /// Record every `query_similar*` call (embedding, options, returned ids and latency) in
+    /// `log`, or stop logging with `None`. The log is shared, so the caller keeps a handle to
+    /// read or save it; see [`replay_queries`].
+    pub fn set_query_log(&mut self, log: Option<Arc<QueryLog>>) {
+        self.query_log = log;
+    }
+
+    /// The attached query log, if any.
+    pub fn query_log(&self) -> Option<&Arc<QueryLog>> {
+        self.query_log.as_ref()
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Record every `query_similar*` call in `log` (see `AgentMemDB::set_query_log`).
+    pub fn set_query_log(&mut self, log: Option<Arc<QueryLog>>) {
+        self.query_log = log;
+    }
+
+    /// The attached query log, if any.
+    pub fn query_log(&self) -> Option<&Arc<QueryLog>> {
+        self.query_log.as_ref()
+    }
+}
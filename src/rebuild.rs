@@ -0,0 +1,389 @@
+//! Prunes that never expose a half-rebuilt index. The replacement episode map, index, key map,
+//! filter bitsets and sub-indexes are built off to the side and swapped in whole, and
+//! `prepare_prune` / `commit_prune` split a prune so a host can build under a read lock and
+//! hold its write lock only for the swap.
+
+use crate::access::{beyond_most_retrieved, last_used};
+use crate::archive::archive_removed;
+use crate::index::IndexBackend;
+use crate::prefilter::KeyFilters;
+use crate::sub_index::{self, SubIndex};
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Which episodes a prune removes (see `AgentMemDB::prepare_prune`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum PruneRule {
+    /// Episodes timestamped before this (Unix ms); undated ones are kept. As `prune_older_than`.
+    OlderThan(i64),
+    /// All but the n newest; undated episodes go first. As `prune_keep_newest`.
+    KeepNewest(usize),
+    /// All but the n with the highest reward, ties keeping the newer. As
+    /// `prune_keep_highest_reward`.
+    KeepHighestReward(usize),
+    /// Every episode of this session. As `prune_session`.
+    Session(String),
+    /// The oldest episodes (undated first) until the rest serialize to at most this many
+    /// bytes of JSON, the size a server quota counts. A prune with this rule fails with `Serde`
+    /// if an episode can't be serialized, rather than guess its size.
+    MaxBytes(u64),
+    /// Episodes no query has returned since this (Unix ms); never-retrieved ones go by their
+    /// timestamp, and undated ones are kept. As `prune_not_retrieved_since`.
+    NotRetrievedSince(i64),
+    /// All but the n most retrieved, ties keeping the more recently retrieved. As
+    /// `prune_keep_most_retrieved`.
+    KeepMostRetrieved(usize),
+}
+
+impl PruneRule {
+    /// Whether the rule goes by access statistics, which queries count beside the stored
+    /// episodes until they are folded in.
+    fn uses_access(&self) -> bool {
+        matches!(
+            self,
+            PruneRule::NotRetrievedSince(_) | PruneRule::KeepMostRetrieved(_)
+        )
+    }
+
+    /// The episodes of `episodes` the rule keeps.
+    fn kept(&self, mut episodes: Vec<Arc<Episode>>) -> Result<Vec<Arc<Episode>>, AgentMemError> {
+        let newest_first = |a: &Arc<Episode>, b: &Arc<Episode>| {
+            b.timestamp
+                .unwrap_or(i64::MIN)
+                .cmp(&a.timestamp.unwrap_or(i64::MIN))
+        };
+        match self {
+            PruneRule::OlderThan(cutoff) => {
+                episodes.retain(|ep| ep.timestamp.is_none_or(|t| t >= *cutoff));
+            }
+            PruneRule::Session(session_id) => {
+                episodes.retain(|ep| ep.session_id.as_deref() != Some(session_id.as_str()));
+            }
+            PruneRule::KeepNewest(n)
+            | PruneRule::KeepHighestReward(n)
+            | PruneRule::KeepMostRetrieved(n)
+                if episodes.len() <= *n => {}
+            PruneRule::KeepNewest(n) => {
+                episodes.sort_by(newest_first);
+                episodes.truncate(*n);
+            }
+            PruneRule::KeepHighestReward(n) => {
+                episodes.sort_by(|a, b| {
+                    b.reward
+                        .partial_cmp(&a.reward)
+                        .unwrap_or(Ordering::Equal)
+                        .then_with(|| newest_first(a, b))
+                });
+                episodes.truncate(*n);
+            }
+            PruneRule::MaxBytes(max) => {
+                episodes.sort_by(newest_first);
+                let (mut total, mut fit) = (0u64, 0);
+                for ep in &episodes {
+                    let json =
+                        serde_json::to_vec(&**ep).map_err(AgentMemError::serde("Serialize"))?;
+                    total += json.len() as u64;
+                    if total > *max {
+                        break;
+                    }
+                    fit += 1;
+                }
+                episodes.truncate(fit);
+            }
+            PruneRule::NotRetrievedSince(cutoff) => {
+                episodes.retain(|ep| last_used(ep).is_none_or(|t| t >= *cutoff));
+            }
+            PruneRule::KeepMostRetrieved(n) => {
+                let removed = beyond_most_retrieved(episodes.iter().map(|ep| &**ep), *n);
+                episodes.retain(|ep| !removed.contains(&ep.id));
+            }
+        }
+        Ok(episodes)
+    }
+
+    /// The stored `episodes` that every rule keeps, applied in order (so `KeepNewest` after
+    /// `OlderThan` counts only the episodes `OlderThan` kept).
+    pub(crate) fn kept_by_all(
+        rules: &[PruneRule],
+        episodes: &HashMap<Uuid, Arc<Episode>>,
+    ) -> Result<Vec<Arc<Episode>>, AgentMemError> {
+        rules
+            .iter()
+            .try_fold(episodes.values().cloned().collect(), |kept, rule| {
+                rule.kept(kept)
+            })
+    }
+}
+
+/// Episodes and the structures indexing them, built without touching a DB's live ones.
+pub(crate) struct Rebuilt {
+    pub(crate) episodes: HashMap<Uuid, Arc<Episode>>,
+    pub(crate) index: IndexBackend,
+    pub(crate) key_to_uuid: HashMap<usize, Uuid>,
+    pub(crate) filters: KeyFilters,
+    /// Per-tag sub-indexes (in-memory DBs only).
+    pub(crate) tag_indexes: HashMap<String, SubIndex>,
+    /// Per-user partitions, when partitioned (in-memory DBs only).
+    pub(crate) user_partitions: Option<HashMap<String, SubIndex>>,
+}
+
+impl Rebuilt {
+    /// Index `kept` in a new, empty index like `like`, with a sub-index for each of
+    /// `sub_index_tags` and, when `partitioned`, a partition per user.
+    pub(crate) fn new(
+        like: &IndexBackend,
+        dim: usize,
+        kept: Vec<Arc<Episode>>,
+        sub_index_tags: impl Iterator<Item = String>,
+        partitioned: bool,
+    ) -> Self {
+        let max_elements = kept.len().max(20_000).max(dim * 2);
+        let mut rebuilt = Self {
+            episodes: HashMap::with_capacity(kept.len()),
+            index: like.empty_like(max_elements),
+            key_to_uuid: HashMap::with_capacity(kept.len()),
+            filters: KeyFilters::default(),
+            tag_indexes: sub_index_tags
+                .map(|tag| (tag, SubIndex::new(like, max_elements)))
+                .collect(),
+            user_partitions: partitioned.then(HashMap::new),
+        };
+        for ep in kept {
+            let key = rebuilt.index.insert(&ep.state_embedding);
+            rebuilt.key_to_uuid.insert(key, ep.id);
+            rebuilt.filters.insert(key, &ep);
+            sub_index::insert_into(
+                &mut rebuilt.tag_indexes,
+                rebuilt.user_partitions.as_mut(),
+                like,
+                max_elements,
+                key,
+                &ep,
+            );
+            rebuilt.episodes.insert(ep.id, ep);
+        }
+        rebuilt
+    }
+
+    /// The episodes in index-key (insertion) order.
+    pub(crate) fn in_key_order(&self) -> impl Iterator<Item = &Arc<Episode>> {
+        (0..self.index.len()).filter_map(|key| {
+            self.key_to_uuid
+                .get(&key)
+                .and_then(|id| self.episodes.get(id))
+        })
+    }
+}
+
+/// A prune built with `prepare_prune` without modifying the DB, applied with `commit_prune`.
+pub struct PreparedPrune {
+    policy: Vec<PruneRule>,
+    /// The DB's episodes when prepared, to tell at commit whether it has changed since.
+    seen: HashMap<Uuid, Arc<Episode>>,
+    /// `None` when the rule removes nothing.
+    rebuilt: Option<Rebuilt>,
+}
+
+impl PreparedPrune {
+    fn new(
+        policy: &[PruneRule],
+        seen: &HashMap<Uuid, Arc<Episode>>,
+        build: impl FnOnce(Vec<Arc<Episode>>) -> Rebuilt,
+    ) -> Result<Self, AgentMemError> {
+        let kept = PruneRule::kept_by_all(policy, seen)?;
+        let rebuilt = (kept.len() < seen.len()).then(|| build(kept));
+        Ok(Self {
+            policy: policy.to_vec(),
+            seen: seen.clone(),
+            rebuilt,
+        })
+    }
+
+    /// The rules the prune applies, in order.
+    pub fn policy(&self) -> &[PruneRule] {
+        &self.policy
+    }
+
+    fn uses_access(&self) -> bool {
+        self.policy.iter().any(PruneRule::uses_access)
+    }
+
+    /// Episodes the prune removes if the DB is unchanged at commit.
+    pub fn removed(&self) -> usize {
+        self.rebuilt
+            .as_ref()
+            .map_or(0, |rebuilt| self.seen.len() - rebuilt.episodes.len())
+    }
+
+    /// Whether `episodes` are still exactly the ones prepared against. Stored episodes are
+    /// replaced, never modified in place while shared, so pointer equality suffices.
+    fn is_current(&self, episodes: &HashMap<Uuid, Arc<Episode>>) -> bool {
+        episodes.len() == self.seen.len()
+            && episodes
+                .iter()
+                .all(|(id, ep)| self.seen.get(id).is_some_and(|seen| Arc::ptr_eq(seen, ep)))
+    }
+}
+
+impl AgentMemDB {
+    /// Remove the episodes any of `policy`'s rules selects (see [`PruneRule`]), archiving them
+    /// when an archive is attached. The rules apply in order, as successive prune calls would,
+    /// but the index is rebuilt once. Returns episodes removed; if archiving fails, the error,
+    /// with nothing removed.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, PruneRule};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// for (ts, reward) in [(1_000, 1.0), (2_000, 0.0), (3_000, 0.5), (4_000, 0.9)] {
+    ///     db.store_episode(Episode::with_timestamp("t", vec![1.0, 0.0], reward, ts)).unwrap();
+    /// }
+    /// let removed = db.prune(&[
+    ///     PruneRule::OlderThan(1_500),
+    ///     PruneRule::KeepNewest(2),
+    ///     PruneRule::KeepHighestReward(1),
+    /// ]);
+    /// assert_eq!(removed.unwrap(), 3);
+    /// assert_eq!(db.iter().next().unwrap().timestamp, Some(4_000));
+    /// ```
+    pub fn prune(&mut self, policy: &[PruneRule]) -> Result<usize, AgentMemError> {
+        self.fold_access_stats();
+        self.prune_to(PruneRule::kept_by_all(policy, &self.episodes)?)
+    }
+
+    /// Compute a prune and build the index that results, without modifying the DB, so a host
+    /// can do the expensive part under a read lock (concurrent queries keep seeing the whole,
+    /// old index) and take its write lock only for `commit_prune`. Costs a pointer copy per
+    /// episode, as `clone_snapshot` does, on top of the rebuild. Fails only if a `MaxBytes`
+    /// rule can't size an episode.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, PruneRule};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::with_timestamp("old", vec![1.0, 0.0], 1.0, 1_000)).unwrap();
+    /// db.store_episode(Episode::with_timestamp("new", vec![0.0, 1.0], 1.0, 5_000)).unwrap();
+    /// let prepared = db.prepare_prune(&[PruneRule::OlderThan(2_000)]).unwrap();
+    /// assert_eq!((prepared.removed(), db.len()), (1, 2));
+    /// assert_eq!(db.commit_prune(prepared).unwrap(), 1);
+    /// assert_eq!(db.len(), 1);
+    /// ```
+    pub fn prepare_prune(&self, policy: &[PruneRule]) -> Result<PreparedPrune, AgentMemError> {
+        PreparedPrune::new(policy, &self.episodes, |kept| self.rebuild(kept))
+    }
+
+    /// Swap in a prune built by `prepare_prune`: the episode map, index and sub-indexes are
+    /// replaced together, so readers see the DB either before or after the prune. If the DB
+    /// changed since it was prepared (a store, update, delete or new sub-index), the prune is
+    /// recomputed here instead, as `prune` would; so is one by access statistics if queries
+    /// have returned episodes since. Returns episodes removed.
+    pub fn commit_prune(&mut self, prepared: PreparedPrune) -> Result<usize, AgentMemError> {
+        let same_layout = prepared.rebuilt.as_ref().is_none_or(|rebuilt| {
+            rebuilt.user_partitions.is_some() == self.user_partitions.is_some()
+                && rebuilt.tag_indexes.len() == self.tag_indexes.len()
+                && rebuilt
+                    .tag_indexes
+                    .keys()
+                    .all(|tag| self.tag_indexes.contains_key(tag))
+        });
+        if prepared.uses_access() {
+            // Stale if queries counted retrievals since it was prepared.
+            self.fold_access_stats();
+        }
+        if !same_layout || !prepared.is_current(&self.episodes) {
+            return self.prune(&prepared.policy);
+        }
+        match prepared.rebuilt {
+            Some(rebuilt) => self.swap_in_pruned(rebuilt),
+            None => Ok(0),
+        }
+    }
+
+    /// Index `kept` off to the side, with this DB's kind of index and sub-indexes.
+    pub(crate) fn rebuild(&self, kept: Vec<Arc<Episode>>) -> Rebuilt {
+        Rebuilt::new(
+            &self.index,
+            self.dim,
+            kept,
+            self.tag_indexes.keys().cloned(),
+            self.user_partitions.is_some(),
+        )
+    }
+
+    /// Replace the episodes and everything indexing them with `rebuilt`.
+    pub(crate) fn install(&mut self, rebuilt: Rebuilt) {
+        self.episodes = rebuilt.episodes;
+        self.index = rebuilt.index;
+        self.key_to_uuid = rebuilt.key_to_uuid;
+        self.filters = rebuilt.filters;
+        self.tag_indexes = rebuilt.tag_indexes;
+        self.user_partitions = rebuilt.user_partitions;
+    }
+
+    /// Archive the stored episodes `rebuilt` leaves out, when an archive is attached, then
+    /// install it. Nothing changes if archiving fails. Returns episodes removed.
+    pub(crate) fn swap_in_pruned(&mut self, rebuilt: Rebuilt) -> Result<usize, AgentMemError> {
+        let removed = self.episodes.len() - rebuilt.episodes.len();
+        if let Some(archive) = &self.archive {
+            archive_removed(&**archive, &self.episodes, &rebuilt.episodes, &self.access)?;
+        }
+        self.install(rebuilt);
+        Ok(removed)
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Remove the episodes any of `policy`'s rules selects, with one index rebuild and one
+    /// log compaction (see `AgentMemDB::prune`).
+    pub fn prune(&mut self, policy: &[PruneRule]) -> Result<usize, AgentMemError> {
+        self.fold_access_stats();
+        self.prune_to(PruneRule::kept_by_all(policy, &self.episodes)?)
+    }
+
+    /// Compute a prune and build the resulting index without modifying the DB (see
+    /// `AgentMemDB::prepare_prune`).
+    pub fn prepare_prune(&self, policy: &[PruneRule]) -> Result<PreparedPrune, AgentMemError> {
+        PreparedPrune::new(policy, &self.episodes, |kept| self.rebuild(kept))
+    }
+
+    /// Write the compacted log for a prune built by `prepare_prune` and swap the new index in
+    /// (see `AgentMemDB::commit_prune`). A prune prepared before the DB changed is recomputed.
+    pub fn commit_prune(&mut self, prepared: PreparedPrune) -> Result<usize, AgentMemError> {
+        let from_disk = prepared.rebuilt.as_ref().is_none_or(|rebuilt| {
+            rebuilt.tag_indexes.is_empty() && rebuilt.user_partitions.is_none()
+        });
+        if prepared.uses_access() {
+            // Stale if queries counted retrievals since it was prepared.
+            self.fold_access_stats();
+        }
+        if !from_disk || !prepared.is_current(&self.episodes) {
+            return self.prune(&prepared.policy);
+        }
+        match prepared.rebuilt {
+            Some(rebuilt) => self.swap_in_pruned(rebuilt),
+            None => Ok(0),
+        }
+    }
+
+    /// Index `kept` off to the side, with this DB's kind of index.
+    pub(crate) fn rebuild(&self, kept: Vec<Arc<Episode>>) -> Rebuilt {
+        Rebuilt::new(&self.index, self.dim(), kept, std::iter::empty(), false)
+    }
+
+    /// Archive the stored episodes `rebuilt` leaves out, when an archive is attached, then
+    /// rewrite the log with the rest and install `rebuilt`. Nothing changes if archiving or
+    /// the log rewrite fails. Returns episodes removed.
+    pub(crate) fn swap_in_pruned(&mut self, rebuilt: Rebuilt) -> Result<usize, AgentMemError> {
+        let removed = self.episodes.len() - rebuilt.episodes.len();
+        if removed == 0 {
+            return Ok(0);
+        }
+        if let Some(archive) = &self.archive {
+            archive_removed(&**archive, &self.episodes, &rebuilt.episodes, &self.access)?;
+        }
+        self.replace_with(rebuilt)?;
+        self.remove_checkpoint_if_exists()?;
+        Ok(removed)
+    }
+}
@@ -0,0 +1,230 @@
+//! Bulk re-embedding: recompute every episode's embedding from its stored text with a new
+//! embedding provider, the standard operation after upgrading the embedding model.
+//!
+//! The text is the episode's `metadata[REEMBED_TEXT_KEY]`, where the server keeps the text of
+//! episodes it embedded; embeddings aren't invertible, so episodes without it can't be
+//! re-embedded and are reported as skipped. Re-embedded episodes are tagged with the
+//! provider's `model`, and `needs_reembedding` lists those a migration hasn't reached yet.
+
+use crate::embedding::{embed_checked, EmbeddingProvider};
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Metadata key holding an episode's text for re-embedding.
+pub const REEMBED_TEXT_KEY: &str = "text";
+
+/// Progress of a re-embedding run, passed to its callback after each batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReembedProgress {
+    /// Episodes re-embedded so far.
+    pub done: usize,
+    /// Episodes with text to re-embed.
+    pub total: usize,
+}
+
+/// What a re-embedding run did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReembedReport {
+    pub reembedded: usize,
+    /// Episodes without text under `REEMBED_TEXT_KEY`: kept as they are by an in-place run,
+    /// not copied by one into a new store.
+    pub skipped: usize,
+}
+
+/// The text an episode is re-embedded from.
+fn text_of(episode: &Episode) -> Option<&str> {
+    episode
+        .metadata
+        .get(REEMBED_TEXT_KEY)
+        .and_then(|text| text.as_str())
+        .filter(|text| !text.is_empty())
+}
+
+/// Re-embed the `episodes` with text, `batch_size` per provider call, handing each batch of
+/// copies to `emit` and then reporting progress.
+fn reembed<'a>(
+    episodes: impl Iterator<Item = &'a Episode>,
+    provider: &dyn EmbeddingProvider,
+    batch_size: usize,
+    mut progress: impl FnMut(ReembedProgress),
+    mut emit: impl FnMut(Vec<Episode>) -> Result<(), AgentMemError>,
+) -> Result<ReembedReport, AgentMemError> {
+    let (with_text, without): (Vec<&Episode>, Vec<&Episode>) =
+        episodes.partition(|ep| text_of(ep).is_some());
+    let total = with_text.len();
+    let mut done = 0;
+    for batch in with_text.chunks(batch_size.max(1)) {
+        let texts: Vec<&str> = batch.iter().filter_map(|ep| text_of(ep)).collect();
+        let embeddings = embed_checked(provider, provider.dim(), &texts)?;
+        let copies = batch
+            .iter()
+            .zip(embeddings)
+            .map(|(ep, embedding)| Episode {
+                state_embedding: embedding,
+                embedding_model: provider.model().map(str::to_string),
+                ..(*ep).clone()
+            })
+            .collect();
+        emit(copies)?;
+        done += batch.len();
+        progress(ReembedProgress { done, total });
+    }
+    Ok(ReembedReport {
+        reembedded: total,
+        skipped: without.len(),
+    })
+}
+
+/// Ids of the `episodes` not embedded by `model`.
+fn not_embedded_by<'a>(episodes: impl Iterator<Item = &'a Episode>, model: &str) -> Vec<Uuid> {
+    episodes
+        .filter(|ep| ep.embedding_model.as_deref() != Some(model))
+        .map(|ep| ep.id)
+        .collect()
+}
+
+/// Fails with `DimensionMismatch` unless `provider` embeds into `dim` dimensions.
+fn check_dim(provider: &dyn EmbeddingProvider, dim: usize) -> Result<(), AgentMemError> {
+    if provider.dim() != dim {
+        return Err(AgentMemError::DimensionMismatch {
+            expected: dim,
+            got: provider.dim(),
+        });
+    }
+    Ok(())
+}
+
+impl AgentMemDB {
+    /// Recompute every episode's embedding from its text (`metadata["text"]`) with
+    /// `provider`, `batch_size` texts per call, and rebuild the index. `progress` is called
+    /// after each batch. All or nothing: if the provider fails, the DB is left as it was.
+    /// Episodes keep their id and version. The provider must have the DB's dimension; use
+    /// `reembed_into` for a model of another dimension.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, AgentMemError, EmbeddingProvider, Episode};
+    ///
+    /// struct Lengths;
+    /// impl EmbeddingProvider for Lengths {
+    ///     fn dim(&self) -> usize {
+    ///         2
+    ///     }
+    ///     fn embed(&self, text: &str) -> Result<Vec<f32>, AgentMemError> {
+    ///         Ok(vec![text.len() as f32, 0.0])
+    ///     }
+    /// }
+    ///
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// let mut ep = Episode::new("greet", vec![0.0, 1.0], 1.0);
+    /// ep.metadata = serde_json::json!({"text": "hello"});
+    /// db.store_episode(ep.clone()).unwrap();
+    /// let report = db.reembed_all(&Lengths, 64, |_| {}).unwrap();
+    /// assert_eq!(report.reembedded, 1);
+    /// assert_eq!(db.iter().next().unwrap().state_embedding, vec![5.0, 0.0]);
+    /// ```
+    pub fn reembed_all(
+        &mut self,
+        provider: &dyn EmbeddingProvider,
+        batch_size: usize,
+        progress: impl FnMut(ReembedProgress),
+    ) -> Result<ReembedReport, AgentMemError> {
+        check_dim(provider, self.dim)?;
+        self.fold_access_stats();
+        let mut reembedded: Vec<Arc<Episode>> = Vec::new();
+        let report = reembed(self.iter(), provider, batch_size, progress, |batch| {
+            reembedded.extend(batch.into_iter().map(Arc::new));
+            Ok(())
+        })?;
+        for ep in reembedded {
+            self.episodes.insert(ep.id, ep);
+        }
+        let all: Vec<Arc<Episode>> = self.episodes.drain().map(|(_, ep)| ep).collect();
+        self.reindex(all);
+        Ok(report)
+    }
+
+    /// Ids of the episodes whose `embedding_model` isn't `model`, untagged ones included: what
+    /// a migration to `model` still has to re-embed.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::with_embedding_model("a", vec![0.0, 1.0], 1.0, "v2")).unwrap();
+    /// let old = Episode::with_embedding_model("b", vec![1.0, 0.0], 1.0, "v1");
+    /// let old_id = old.id;
+    /// db.store_episode(old).unwrap();
+    /// assert_eq!(db.needs_reembedding("v2"), vec![old_id]);
+    /// ```
+    pub fn needs_reembedding(&self, model: &str) -> Vec<Uuid> {
+        not_embedded_by(self.iter(), model)
+    }
+
+    /// Store copies of the episodes with text in `target`, embedded from it by `provider`
+    /// (see `reembed_all`), `batch_size` at a time. `target` may have another dimension, e.g.
+    /// for a new model; it must match the provider's. Stops at the first failure, with the
+    /// batches before it stored.
+    pub fn reembed_into(
+        &self,
+        target: &mut AgentMemDB,
+        provider: &dyn EmbeddingProvider,
+        batch_size: usize,
+        progress: impl FnMut(ReembedProgress),
+    ) -> Result<ReembedReport, AgentMemError> {
+        check_dim(provider, target.dim())?;
+        reembed(self.iter(), provider, batch_size, progress, |mut batch| {
+            self.access.fill(&mut batch);
+            target.store_episodes(batch)
+        })
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Recompute every episode's embedding from its text with `provider` and rewrite the
+    /// log and index (see `AgentMemDB::reembed_all`). All or nothing: the log is only
+    /// rewritten once every batch is embedded.
+    pub fn reembed_all(
+        &mut self,
+        provider: &dyn EmbeddingProvider,
+        batch_size: usize,
+        progress: impl FnMut(ReembedProgress),
+    ) -> Result<ReembedReport, AgentMemError> {
+        check_dim(provider, self.dim())?;
+        self.flush()?;
+        self.fold_access_stats();
+        let mut reembedded: Vec<Arc<Episode>> = Vec::new();
+        let report = reembed(self.iter(), provider, batch_size, progress, |batch| {
+            reembedded.extend(batch.into_iter().map(Arc::new));
+            Ok(())
+        })?;
+        let mut all = self.episodes.clone();
+        for ep in reembedded {
+            all.insert(ep.id, ep);
+        }
+        self.compact(all.into_values().collect())?;
+        self.remove_checkpoint_if_exists()?;
+        Ok(report)
+    }
+
+    /// Ids of the episodes whose `embedding_model` isn't `model` (see
+    /// `AgentMemDB::needs_reembedding`).
+    pub fn needs_reembedding(&self, model: &str) -> Vec<Uuid> {
+        not_embedded_by(self.iter(), model)
+    }
+
+    /// Store copies of the episodes with text in `target`, embedded from it by `provider`
+    /// (see `AgentMemDB::reembed_into`).
+    pub fn reembed_into(
+        &self,
+        target: &mut AgentMemDBDisk,
+        provider: &dyn EmbeddingProvider,
+        batch_size: usize,
+        progress: impl FnMut(ReembedProgress),
+    ) -> Result<ReembedReport, AgentMemError> {
+        check_dim(provider, target.dim())?;
+        reembed(self.iter(), provider, batch_size, progress, |mut batch| {
+            self.access.fill(&mut batch);
+            target.store_episodes(batch)
+        })
+    }
+}
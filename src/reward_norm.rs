@@ -0,0 +1,114 @@
+//! Per-task reward normalization, so one `min_reward` threshold means the same thing for tasks
+//! that score on different scales (e.g. 0/1 success next to a 0–100 score).
+
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// How `normalize_rewards_per_task` rescales the rewards of each task_id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewardNormalization {
+    /// Zero mean, unit variance. A task whose rewards are all equal gets 0.
+    ZScore,
+    /// Lowest reward 0, highest 1. A task whose rewards are all equal gets 0.5.
+    MinMax,
+}
+
+/// New reward for every live episode whose reward changes under `method`, grouped by task_id.
+fn normalized<'a>(
+    episodes: impl Iterator<Item = &'a Episode>,
+    method: RewardNormalization,
+) -> Vec<(Uuid, f32)> {
+    let mut by_task: HashMap<&str, Vec<&Episode>> = HashMap::new();
+    for ep in episodes.filter(|ep| !ep.deleted) {
+        by_task.entry(ep.task_id.as_str()).or_default().push(ep);
+    }
+    let mut changed = Vec::new();
+    for task in by_task.values() {
+        let rewards = task.iter().map(|ep| ep.reward as f64);
+        // reward ↦ (reward − shift) / divisor, or `flat` when the task's rewards are all equal.
+        let (shift, divisor, flat) = match method {
+            RewardNormalization::ZScore => {
+                let n = task.len() as f64;
+                let mean = rewards.clone().sum::<f64>() / n;
+                let variance = rewards.map(|r| (r - mean).powi(2)).sum::<f64>() / n;
+                (mean, variance.sqrt(), 0.0)
+            }
+            RewardNormalization::MinMax => {
+                let min = rewards.clone().fold(f64::INFINITY, f64::min);
+                let max = rewards.fold(f64::NEG_INFINITY, f64::max);
+                (min, max - min, 0.5)
+            }
+        };
+        for ep in task {
+            let reward = if divisor > 0.0 {
+                ((ep.reward as f64 - shift) / divisor) as f32
+            } else {
+                flat
+            };
+            if reward != ep.reward {
+                changed.push((ep.id, reward));
+            }
+        }
+    }
+    changed
+}
+
+impl AgentMemDB {
+    /// Rescale stored rewards within each task_id (see [`RewardNormalization`]), so a single
+    /// `min_reward` selects comparably good episodes across tasks. Soft-deleted episodes are
+    /// left out of the statistics and unchanged. Each changed episode counts as an update (its
+    /// version is incremented). Returns the number of episodes changed.
+    ///
+    /// This rewrites the stored rewards; episodes stored afterwards are not rescaled, so run
+    /// it again after adding to a task.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, RewardNormalization};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("binary", vec![0.0, 1.0], 0.0)).unwrap();
+    /// db.store_episode(Episode::new("binary", vec![0.0, 1.0], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("score", vec![0.0, 1.0], 20.0)).unwrap();
+    /// db.store_episode(Episode::new("score", vec![0.0, 1.0], 80.0)).unwrap();
+    /// db.normalize_rewards_per_task(RewardNormalization::MinMax);
+    /// assert_eq!(db.query_similar(&[0.0, 1.0], 0.9, 4).unwrap().len(), 2);
+    /// ```
+    pub fn normalize_rewards_per_task(&mut self, method: RewardNormalization) -> usize {
+        let changed = normalized(self.iter(), method);
+        for &(id, reward) in &changed {
+            if let Some(ep) = self.episodes.get_mut(&id) {
+                // The embedding is unchanged, so the index entry stays.
+                let ep = std::sync::Arc::make_mut(ep);
+                ep.reward = reward;
+                ep.version += 1;
+            }
+        }
+        changed.len()
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Rescale stored rewards within each task_id (see
+    /// `AgentMemDB::normalize_rewards_per_task`). Changed episodes are appended to the log
+    /// with their version incremented. Returns the number of episodes changed.
+    pub fn normalize_rewards_per_task(
+        &mut self,
+        method: RewardNormalization,
+    ) -> Result<usize, AgentMemError> {
+        let by_id: HashMap<Uuid, &Episode> = self.iter().map(|ep| (ep.id, ep)).collect();
+        let updated: Vec<Episode> = normalized(self.iter(), method)
+            .into_iter()
+            .map(|(id, reward)| {
+                let mut ep = by_id[&id].clone();
+                ep.reward = reward;
+                ep.version += 1;
+                ep
+            })
+            .collect();
+        let count = updated.len();
+        if count > 0 {
+            self.store_episodes(updated)?;
+        }
+        Ok(count)
+    }
+}
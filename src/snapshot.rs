@@ -0,0 +1,124 @@
+//! Frozen, cheaply cloned read views of an `AgentMemDB`.
+
+use crate::index::l2_distance;
+use crate::{AgentMemDB, AgentMemError, Episode, QueryOptions};
+use std::sync::Arc;
+
+/// An immutable copy of an `AgentMemDB`, taken with [`AgentMemDB::clone_snapshot`].
+///
+/// Episodes are shared with the primary through `Arc` (embeddings and metadata are not copied),
+/// and later writes, prunes and deletes on the primary do not affect the snapshot. Cloning a
+/// snapshot is O(1), so one can be handed to every request handler while a writer keeps
+/// updating the primary.
+///
+/// Queries are exact: every episode's embedding is compared (O(n) per query), filters are
+/// applied before `top_k` is taken, and ties are broken like `AgentMemDB` (newer first).
+#[derive(Clone)]
+pub struct AgentMemSnapshot {
+    dim: usize,
+    episodes: Arc<[Arc<Episode>]>,
+}
+
+impl AgentMemDB {
+    /// Take an immutable snapshot for concurrent readers. Costs one pointer copy per episode;
+    /// no episode data is duplicated.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("a", vec![0.0, 1.0], 1.0)).unwrap();
+    /// let snapshot = db.clone_snapshot();
+    /// db.store_episode(Episode::new("b", vec![1.0, 0.0], 1.0)).unwrap();
+    /// assert_eq!(snapshot.len(), 1);
+    /// ```
+    pub fn clone_snapshot(&self) -> AgentMemSnapshot {
+        AgentMemSnapshot {
+            dim: self.dim,
+            episodes: self.episodes.values().cloned().collect(),
+        }
+    }
+}
+
+impl AgentMemSnapshot {
+    /// Return the embedding dimension.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Number of episodes in the snapshot.
+    pub fn len(&self) -> usize {
+        self.episodes.len()
+    }
+
+    /// True when the snapshot holds no episodes.
+    pub fn is_empty(&self) -> bool {
+        self.episodes.is_empty()
+    }
+
+    /// Iterate over all episodes (arbitrary order).
+    pub fn iter(&self) -> impl Iterator<Item = &Episode> {
+        self.episodes.iter().map(|ep| &**ep)
+    }
+
+    /// Query for the top_k most similar episodes with reward >= min_reward.
+    pub fn query_similar(
+        &self,
+        query_embedding: &[f32],
+        min_reward: f32,
+        top_k: usize,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        self.query_similar_with_options(query_embedding, QueryOptions::new(min_reward, top_k))
+    }
+
+    /// Query with filters (tags, time range, task prefix, source, user).
+    pub fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        Ok(self
+            .ranked_hits(query_embedding, &opts)?
+            .iter()
+            .map(|ep| opts.project_ref(ep))
+            .collect())
+    }
+
+    /// Like `query_similar_with_options`, but return the snapshot's episodes themselves,
+    /// shared, instead of copying each hit (see `AgentMemDB::query_similar_shared`).
+    pub fn query_similar_shared(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Arc<Episode>>, AgentMemError> {
+        self.ranked_hits(query_embedding, &opts)
+    }
+
+    /// The episodes a query returns, best first.
+    fn ranked_hits(
+        &self,
+        query_embedding: &[f32],
+        opts: &QueryOptions,
+    ) -> Result<Vec<Arc<Episode>>, AgentMemError> {
+        if query_embedding.len() != self.dim {
+            return Err(AgentMemError::DimensionMismatch {
+                expected: self.dim,
+                got: query_embedding.len(),
+            });
+        }
+        opts.check_avoid_dim(self.dim)?;
+        let mut candidates: Vec<(f32, &Arc<Episode>)> = self
+            .episodes
+            .iter()
+            .filter(|ep| opts.matches(ep))
+            .map(|ep| (l2_distance(query_embedding, &ep.state_embedding), ep))
+            .collect();
+        opts.apply_avoid(&mut candidates, |ep| ep.state_embedding.as_slice());
+        opts.apply_recency_boost(&mut candidates, |ep| ep.timestamp);
+        candidates.sort_by(|a, b| opts.rank(&(a.0, a.1.as_ref()), &(b.0, b.1.as_ref())));
+        opts.take_results(&mut candidates, |ep| ep.task_id.clone());
+        Ok(candidates
+            .into_iter()
+            .map(|(_, ep)| Arc::clone(ep))
+            .collect())
+    }
+}
@@ -0,0 +1,181 @@
+//! Store-time duplicate handling: what to do when an agent stores an episode that is already
+//! in memory (same task, embedding within epsilon), so looping agents don't grow the DB without
+//! bound.
+
+use crate::index::l2_distance;
+use crate::{AgentMemDB, AgentMemError, Episode};
+use uuid::Uuid;
+
+/// What `store_episode` does with a near-duplicate of a stored episode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateAction {
+    /// Fail with `AgentMemError::Duplicate`; the DB is unchanged.
+    Reject,
+    /// Remove the stored episode and store the new one.
+    Replace,
+    /// Fold the new episode into the stored one (see [`StorePolicy::merge`]).
+    Merge,
+}
+
+/// Duplicate policy set with `AgentMemDB::set_store_policy`. An incoming episode is a
+/// duplicate when a live stored episode has the same `task_id` and an embedding within
+/// `epsilon` (L2) of it. Storing under an id that is already stored is an explicit
+/// replacement and bypasses the policy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StorePolicy {
+    pub epsilon: f32,
+    pub on_duplicate: DuplicateAction,
+}
+
+impl StorePolicy {
+    /// Reject near-duplicates.
+    pub fn reject(epsilon: f32) -> Self {
+        Self {
+            epsilon,
+            on_duplicate: DuplicateAction::Reject,
+        }
+    }
+
+    /// Replace the stored episode with the newer one.
+    pub fn replace(epsilon: f32) -> Self {
+        Self {
+            epsilon,
+            on_duplicate: DuplicateAction::Replace,
+        }
+    }
+
+    /// Keep the stored episode (id, embedding, metadata) and fold the new one in: the reward
+    /// becomes the mean of the two, tags are unioned, the timestamp is the newer one and a
+    /// missing source, user_id or session_id is taken from the new episode.
+    pub fn merge(epsilon: f32) -> Self {
+        Self {
+            epsilon,
+            on_duplicate: DuplicateAction::Merge,
+        }
+    }
+}
+
+/// `existing` with `incoming` folded in (see `StorePolicy::merge`).
+fn merged(existing: &Episode, incoming: Episode) -> Episode {
+    let mut ep = existing.clone();
+    ep.reward = (existing.reward + incoming.reward) / 2.0;
+    if let Some(tags) = incoming.tags {
+        let merged_tags = ep.tags.get_or_insert_with(Vec::new);
+        for tag in tags {
+            if !merged_tags.contains(&tag) {
+                merged_tags.push(tag);
+            }
+        }
+    }
+    ep.timestamp = ep.timestamp.max(incoming.timestamp);
+    ep.source = ep.source.or(incoming.source);
+    ep.user_id = ep.user_id.or(incoming.user_id);
+    ep.session_id = ep.session_id.or(incoming.session_id);
+    ep.version += 1;
+    ep
+}
+
+impl AgentMemDB {
+    /// Handle near-duplicates on store (see [`StorePolicy`]), or store everything as given
+    /// with `None` (the default).
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, StorePolicy};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.set_store_policy(Some(StorePolicy::merge(0.01)));
+    /// db.store_episode(Episode::new("loop", vec![1.0, 0.0], 0.0)).unwrap();
+    /// db.store_episode(Episode::new("loop", vec![1.0, 0.001], 1.0)).unwrap();
+    /// assert_eq!(db.len(), 1);
+    /// assert_eq!(db.iter().next().unwrap().reward, 0.5);
+    /// ```
+    pub fn set_store_policy(&mut self, policy: Option<StorePolicy>) {
+        self.store_policy = policy;
+    }
+
+    /// The duplicate policy, if any.
+    pub fn store_policy(&self) -> Option<StorePolicy> {
+        self.store_policy
+    }
+
+    /// The episode `store_episode` should store in place of `episode` under the store policy:
+    /// `episode` itself, or a merge into the duplicate it was found to repeat. A replaced
+    /// duplicate is removed here.
+    pub(crate) fn apply_store_policy(
+        &mut self,
+        episode: Episode,
+    ) -> Result<Episode, AgentMemError> {
+        let Some(policy) = self.store_policy else {
+            return Ok(episode);
+        };
+        if self.episodes.contains_key(&episode.id) {
+            return Ok(episode);
+        }
+        let Some(existing) = self.find_duplicate(&episode, policy.epsilon) else {
+            return Ok(episode);
+        };
+        match policy.on_duplicate {
+            DuplicateAction::Reject => Err(AgentMemError::Duplicate { existing }),
+            DuplicateAction::Replace => {
+                self.unindex(existing);
+                self.episodes.remove(&existing);
+                Ok(episode)
+            }
+            DuplicateAction::Merge => Ok(merged(&self.episodes[&existing], episode)),
+        }
+    }
+
+    /// For `store_episodes` under a rejecting policy: the first episode of the batch that
+    /// duplicates a stored one or an earlier one in the batch, so the batch can fail before
+    /// anything is inserted.
+    pub(crate) fn first_rejected(&self, batch: &[Episode]) -> Option<AgentMemError> {
+        let policy = self
+            .store_policy
+            .filter(|p| p.on_duplicate == DuplicateAction::Reject)?;
+        for (i, ep) in batch.iter().enumerate() {
+            if self.episodes.contains_key(&ep.id) {
+                continue;
+            }
+            let in_batch = batch[..i].iter().find(|earlier| {
+                earlier.task_id == ep.task_id
+                    && l2_distance(&earlier.state_embedding, &ep.state_embedding) <= policy.epsilon
+            });
+            if let Some(existing) = self
+                .find_duplicate(ep, policy.epsilon)
+                .or(in_batch.map(|earlier| earlier.id))
+            {
+                return Some(AgentMemError::Duplicate { existing });
+            }
+        }
+        None
+    }
+
+    /// A live stored episode with `ep`'s task_id within `epsilon` of its embedding. Widens the
+    /// index search while every hit is within `epsilon`.
+    fn find_duplicate(&self, ep: &Episode, epsilon: f32) -> Option<Uuid> {
+        let total = self.index.len();
+        let mut fetch = total.min(8);
+        while fetch > 0 {
+            let mut beyond = false;
+            for (key, distance) in self.index.search(&ep.state_embedding, fetch) {
+                if distance > epsilon {
+                    beyond = true;
+                    continue;
+                }
+                let stored = self
+                    .key_to_uuid
+                    .get(&key)
+                    .and_then(|uuid| self.episodes.get(uuid));
+                if let Some(stored) = stored {
+                    if stored.task_id == ep.task_id && !stored.deleted {
+                        return Some(stored.id);
+                    }
+                }
+            }
+            if beyond || fetch == total {
+                break;
+            }
+            fetch = (fetch * 2).min(total);
+        }
+        None
+    }
+}
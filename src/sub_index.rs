@@ -0,0 +1,118 @@
+//! Secondary indexes over a subset of the episodes: per-tag sub-indexes and per-user
+//! partitions.
+
+use crate::index::{ExactIndex, HnswParams, IndexBackend, KeySet};
+use crate::{AgentMemDB, Episode};
+use std::collections::HashMap;
+
+/// Partitions that start exact switch to HNSW past this many vectors.
+const EXACT_PARTITION_MAX: usize = 1024;
+
+/// An index over some of the episodes, alongside the main index. Its keys are its own; `keys`
+/// maps each back to the main-index key, which `key_to_uuid` resolves as usual.
+pub(crate) struct SubIndex {
+    pub(crate) index: IndexBackend,
+    keys: Vec<usize>,
+    /// Set for a partition that starts exact: the HNSW parameters (and capacity) to switch to
+    /// once it outgrows exact search.
+    grow_into: Option<(HnswParams, usize)>,
+}
+
+impl SubIndex {
+    /// An empty sub-index of the same kind (and HNSW parameters) as `main`.
+    pub fn new(main: &IndexBackend, max_elements: usize) -> Self {
+        Self {
+            index: main.empty_like(max_elements),
+            keys: Vec::new(),
+            grow_into: None,
+        }
+    }
+
+    /// An empty sub-index that searches exactly until it holds `EXACT_PARTITION_MAX` vectors,
+    /// then becomes an index like `main`. Many small partitions stay cheap this way: an empty
+    /// HNSW graph preallocates room for a thousand vectors.
+    pub fn growing(main: &IndexBackend, max_elements: usize) -> Self {
+        Self {
+            index: IndexBackend::Exact(ExactIndex::new()),
+            keys: Vec::new(),
+            grow_into: main.hnsw_params().map(|params| (params, max_elements)),
+        }
+    }
+
+    /// Number of vectors in the sub-index.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Add the episode stored at `main_key` in the main index.
+    pub fn insert(&mut self, main_key: usize, vec: &[f32]) {
+        let key = self.index.insert(vec);
+        if key >= self.keys.len() {
+            self.keys.resize(key + 1, usize::MAX);
+        }
+        self.keys[key] = main_key;
+        if let Some((params, max_elements)) = self.grow_into {
+            if self.index.len() > EXACT_PARTITION_MAX {
+                // Keys are insertion order in both kinds of index, so `keys` stays valid.
+                let exact =
+                    std::mem::replace(&mut self.index, IndexBackend::Exact(ExactIndex::new()));
+                self.index = exact.into_hnsw(max_elements, params);
+                self.grow_into = None;
+            }
+        }
+    }
+
+    /// Main-index key of sub-index key `key`.
+    pub fn main_key(&self, key: usize) -> Option<usize> {
+        self.keys.get(key).copied().filter(|&k| k != usize::MAX)
+    }
+
+    /// Sub-index keys whose main-index key is in `allowed`.
+    pub fn translate(&self, allowed: &KeySet) -> KeySet {
+        let mut keys = KeySet::default();
+        for (key, &main_key) in self.keys.iter().enumerate() {
+            if main_key != usize::MAX && allowed.contains(main_key) {
+                keys.insert(key);
+            }
+        }
+        keys
+    }
+}
+
+/// Add the episode indexed at main-index `key` to its tags' sub-indexes in `tag_indexes` and,
+/// when `user_partitions` is set, its user's partition (a new partition is like `main`).
+pub(crate) fn insert_into(
+    tag_indexes: &mut HashMap<String, SubIndex>,
+    user_partitions: Option<&mut HashMap<String, SubIndex>>,
+    main: &IndexBackend,
+    max_elements: usize,
+    key: usize,
+    ep: &Episode,
+) {
+    for tag in ep.tags.iter().flatten() {
+        if let Some(sub) = tag_indexes.get_mut(tag) {
+            sub.insert(key, &ep.state_embedding);
+        }
+    }
+    if let (Some(partitions), Some(user)) = (user_partitions, &ep.user_id) {
+        partitions
+            .entry(user.clone())
+            .or_insert_with(|| SubIndex::growing(main, max_elements))
+            .insert(key, &ep.state_embedding);
+    }
+}
+
+impl AgentMemDB {
+    /// Add the episode just indexed at `key` to its tags' sub-indexes and its user's partition.
+    pub(crate) fn insert_sub_indexes(&mut self, key: usize, ep: &Episode) {
+        let max_elements = self.episodes.len().max(20_000).max(self.dim * 2);
+        insert_into(
+            &mut self.tag_indexes,
+            self.user_partitions.as_mut(),
+            &self.index,
+            max_elements,
+            key,
+            ep,
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Summarization hooks for consolidation: the crate picks which episodes to merge and replaces
+//! them; a [`Summarizer`] (typically an LLM call) writes the summary.
+
+use crate::index::l2_distance;
+use crate::{AgentMemDB, AgentMemError, Episode, MemorySystem};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Writes one summary episode for a group of episodes, e.g. by asking an LLM to describe what
+/// they have in common.
+///
+/// The summary may leave `state_embedding` empty: it is then given the centroid of the
+/// summarized episodes' embeddings. Otherwise its dimension must match the DB's.
+pub trait Summarizer: Send + Sync {
+    /// Summarize `episodes` (at least one) into a single episode.
+    fn summarize(&self, episodes: &[Episode]) -> Result<Episode, AgentMemError>;
+}
+
+/// Groups of near-duplicate episodes: greedily, each episode not yet grouped (in id order)
+/// gathers the remaining ones closer than `threshold` to it. Only groups of at least
+/// `min_size` (and at least 2) are returned. Soft-deleted episodes are skipped. Compares every
+/// pair, so cost is O(n²).
+pub(crate) fn clusters<'a>(
+    episodes: impl Iterator<Item = &'a Episode>,
+    threshold: f32,
+    min_size: usize,
+) -> Vec<Vec<Episode>> {
+    let mut nodes: Vec<&Episode> = episodes.filter(|ep| !ep.deleted).collect();
+    nodes.sort_by_key(|ep| ep.id);
+    let mut grouped = vec![false; nodes.len()];
+    let mut out = Vec::new();
+    for i in 0..nodes.len() {
+        if grouped[i] {
+            continue;
+        }
+        let mut members = vec![i];
+        for j in i + 1..nodes.len() {
+            if !grouped[j]
+                && l2_distance(&nodes[i].state_embedding, &nodes[j].state_embedding) < threshold
+            {
+                members.push(j);
+            }
+        }
+        if members.len() >= min_size.max(2) {
+            for &m in &members {
+                grouped[m] = true;
+            }
+            out.push(members.into_iter().map(|m| nodes[m].clone()).collect());
+        }
+    }
+    out
+}
+
+/// Give `summary` the centroid of `sources` if it has no embedding, and check its dimension.
+pub(crate) fn finish_summary(
+    mut summary: Episode,
+    sources: &[Episode],
+    dim: usize,
+) -> Result<Episode, AgentMemError> {
+    if summary.state_embedding.is_empty() && !sources.is_empty() {
+        let mut centroid = vec![0.0f32; dim];
+        for ep in sources {
+            for (c, x) in centroid.iter_mut().zip(&ep.state_embedding) {
+                *c += x;
+            }
+        }
+        let n = sources.len() as f32;
+        centroid.iter_mut().for_each(|c| *c /= n);
+        summary.state_embedding = centroid;
+    }
+    if summary.state_embedding.len() != dim {
+        return Err(AgentMemError::DimensionMismatch {
+            expected: dim,
+            got: summary.state_embedding.len(),
+        });
+    }
+    Ok(summary)
+}
+
+impl AgentMemDB {
+    /// Replace each group of near-duplicate episodes (see below) with one summary written by
+    /// `summarizer`. Groups are formed greedily in id order: an episode gathers the remaining
+    /// episodes closer than `threshold` to it, and groups smaller than `min_size` (at least 2)
+    /// are left alone. Compares every pair, so cost is O(n²). Every summary is produced before
+    /// anything is removed, so a failing summarizer leaves the DB unchanged. Returns the number
+    /// of groups merged.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, AgentMemError, Episode, Summarizer};
+    /// struct Join;
+    /// impl Summarizer for Join {
+    ///     fn summarize(&self, episodes: &[Episode]) -> Result<Episode, AgentMemError> {
+    ///         let tasks: Vec<&str> = episodes.iter().map(|e| e.task_id.as_str()).collect();
+    ///         Ok(Episode::new(tasks.join("+"), Vec::new(), 1.0))
+    ///     }
+    /// }
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("a", vec![0.0, 0.0], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("b", vec![0.0, 0.1], 1.0)).unwrap();
+    /// db.store_episode(Episode::new("c", vec![5.0, 5.0], 1.0)).unwrap();
+    /// assert_eq!(db.merge_clusters(0.5, 2, &Join).unwrap(), 1);
+    /// assert_eq!(db.len(), 2);
+    /// ```
+    pub fn merge_clusters(
+        &mut self,
+        threshold: f32,
+        min_size: usize,
+        summarizer: &dyn Summarizer,
+    ) -> Result<usize, AgentMemError> {
+        let groups = clusters(self.iter(), threshold, min_size);
+        let mut summaries = Vec::with_capacity(groups.len());
+        for group in &groups {
+            let summary = summarizer.summarize(group)?;
+            summaries.push(finish_summary(summary, group, self.dim())?);
+        }
+        let merged: HashSet<Uuid> = groups.iter().flatten().map(|ep| ep.id).collect();
+        self.delete_where(|ep| merged.contains(&ep.id));
+        self.store_episodes(summaries)?;
+        Ok(groups.len())
+    }
+}
+
+impl MemorySystem {
+    /// Like `consolidate`, with the summary written by `summarizer` from the episodes
+    /// `episode_ids` (ids not in the episodic store are ignored). Does nothing and returns 0 if
+    /// none of them is there.
+    pub fn consolidate_with(
+        &mut self,
+        episode_ids: &[Uuid],
+        summarizer: &dyn Summarizer,
+    ) -> Result<usize, AgentMemError> {
+        let wanted: HashSet<Uuid> = episode_ids.iter().copied().collect();
+        let mut stored: HashMap<Uuid, &Episode> = self
+            .episodic()
+            .iter()
+            .filter(|ep| wanted.contains(&ep.id))
+            .map(|ep| (ep.id, ep))
+            .collect();
+        // In the caller's order, each episode once.
+        let sources: Vec<Episode> = episode_ids
+            .iter()
+            .filter_map(|id| stored.remove(id).cloned())
+            .collect();
+        if sources.is_empty() {
+            return Ok(0);
+        }
+        let summary = finish_summary(summarizer.summarize(&sources)?, &sources, self.dim())?;
+        let ids: Vec<Uuid> = sources.iter().map(|ep| ep.id).collect();
+        self.consolidate(&ids, summary)
+    }
+
+    /// Move each group of near-duplicate episodes (grouped as in `AgentMemDB::merge_clusters`)
+    /// out of the episodic store, as one summary per group in the semantic store. Returns the
+    /// number of groups consolidated.
+    pub fn consolidate_clusters(
+        &mut self,
+        threshold: f32,
+        min_size: usize,
+        summarizer: &dyn Summarizer,
+    ) -> Result<usize, AgentMemError> {
+        let groups = clusters(self.episodic().iter(), threshold, min_size);
+        let mut summaries = Vec::with_capacity(groups.len());
+        for group in &groups {
+            let summary = summarizer.summarize(group)?;
+            summaries.push(finish_summary(summary, group, self.dim())?);
+        }
+        self.semantic_mut().store_episodes(summaries)?;
+        let merged: HashSet<Uuid> = groups.iter().flatten().map(|ep| ep.id).collect();
+        self.episodic_mut()
+            .delete_where(|ep| merged.contains(&ep.id));
+        Ok(groups.len())
+    }
+}
@@ -0,0 +1,74 @@
+//! Opt-in per-tag sub-indexes: a separate graph over the episodes carrying one tag.
+
+use crate::sub_index::SubIndex;
+use crate::{AgentMemDB, Episode, QueryOptions};
+
+impl AgentMemDB {
+    /// Keep a separate index for episodes tagged `tag`, so queries scoped to it (`tags_any` of
+    /// just `tag`, or `tags_all` including it) search a graph of those episodes only instead of
+    /// filtering the whole index. Meant for a handful of tags that most queries are scoped to
+    /// (e.g. `"plan"`, `"tool_result"`, `"reflection"`). Built from the episodes already stored;
+    /// no-op if `tag` already has one. Sub-indexes live in memory and are not saved.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, QueryOptions};
+    /// let mut db = AgentMemDB::new(2);
+    /// db.add_tag_index("plan");
+    /// db.store_episode(Episode::with_tags("t", vec![0.0, 1.0], 1.0, vec!["plan".into()]))
+    ///     .unwrap();
+    /// let opts = QueryOptions::new(0.0, 5).tags_any(vec!["plan".into()]);
+    /// assert_eq!(db.explain_query(&[0.0, 1.0], opts).unwrap().tag_index.as_deref(), Some("plan"));
+    /// ```
+    pub fn add_tag_index(&mut self, tag: impl Into<String>) {
+        let tag = tag.into();
+        if self.tag_indexes.contains_key(&tag) {
+            return;
+        }
+        let mut sub = SubIndex::new(&self.index, self.tag_index_capacity());
+        let mut keys: Vec<(usize, &Episode)> = self
+            .key_to_uuid
+            .iter()
+            .filter_map(|(&key, id)| Some((key, &**self.episodes.get(id)?)))
+            .filter(|(_, ep)| has_tag(ep, &tag))
+            .collect();
+        keys.sort_by_key(|&(key, _)| key);
+        for (key, ep) in keys {
+            sub.insert(key, &ep.state_embedding);
+        }
+        self.tag_indexes.insert(tag, sub);
+    }
+
+    /// Drop the sub-index for `tag`. Returns whether there was one.
+    pub fn remove_tag_index(&mut self, tag: &str) -> bool {
+        self.tag_indexes.remove(tag).is_some()
+    }
+
+    /// Tags with a sub-index (arbitrary order).
+    pub fn tag_indexes(&self) -> impl Iterator<Item = &str> {
+        self.tag_indexes.keys().map(String::as_str)
+    }
+
+    /// The sub-index a query should search: the one for its only `tags_any` tag, else the
+    /// smallest one among its `tags_all` tags.
+    pub(crate) fn scoped_tag_index(&self, opts: &QueryOptions) -> Option<(&str, &SubIndex)> {
+        if let Some([tag]) = opts.tags_any.as_deref() {
+            if let Some((tag, sub)) = self.tag_indexes.get_key_value(tag) {
+                return Some((tag, sub));
+            }
+        }
+        opts.tags_all
+            .iter()
+            .flatten()
+            .filter_map(|tag| self.tag_indexes.get_key_value(tag))
+            .min_by_key(|(_, sub)| sub.len())
+            .map(|(tag, sub)| (tag.as_str(), sub))
+    }
+
+    fn tag_index_capacity(&self) -> usize {
+        self.episodes.len().max(20_000).max(self.dim * 2)
+    }
+}
+
+fn has_tag(ep: &Episode, tag: &str) -> bool {
+    ep.tags.iter().flatten().any(|t| t == tag)
+}
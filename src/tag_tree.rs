@@ -0,0 +1,99 @@
+//! Hierarchical tags: `/`-separated paths like `tool/browser/click`, matched by subtree
+//! (`QueryOptions::tag_prefix_any`) and aggregated per subtree (`tag_subtree_stats`).
+
+use crate::{AgentMemDB, AgentMemDBDisk, Episode};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Separates the levels of a hierarchical tag.
+pub const TAG_SEPARATOR: char = '/';
+
+/// Whether `tag` is `prefix` or lies under it: `tool/browser` covers `tool/browser` and
+/// `tool/browser/click`, but not `tool/browsers`. A trailing `/` on `prefix` is ignored, and
+/// an empty prefix covers every tag.
+pub fn tag_has_prefix(tag: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches(TAG_SEPARATOR);
+    prefix.is_empty()
+        || tag
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(TAG_SEPARATOR))
+}
+
+/// Episodes tagged within one subtree of the tag hierarchy (see `tag_subtree_stats`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TagSubtree {
+    /// Path of the subtree's root, e.g. `tool/browser`.
+    pub tag: String,
+    /// Episodes with at least one tag in the subtree; each counts once.
+    pub count: usize,
+    pub mean_reward: f32,
+}
+
+/// The tag itself and each of its ancestors (`a/b/c` → `a`, `a/b`, `a/b/c`).
+fn tag_paths(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices(TAG_SEPARATOR)
+        .map(|(i, _)| &tag[..i])
+        .chain(std::iter::once(tag))
+        .filter(|path| !path.is_empty())
+}
+
+/// One `TagSubtree` per tag path under `root` (root included), ordered by path. Ancestors
+/// count as paths of their own, so `tool/browser/click` adds to `tool` and `tool/browser`.
+/// Soft-deleted episodes are left out.
+fn tag_subtree_stats<'a>(
+    episodes: impl Iterator<Item = &'a Episode>,
+    root: &str,
+) -> Vec<TagSubtree> {
+    let mut totals: BTreeMap<&str, (usize, f64)> = BTreeMap::new();
+    for ep in episodes.filter(|ep| !ep.deleted) {
+        let paths: BTreeSet<&str> = ep
+            .tags
+            .iter()
+            .flatten()
+            .flat_map(|tag| tag_paths(tag))
+            .filter(|path| tag_has_prefix(path, root))
+            .collect();
+        for path in paths {
+            let (count, sum) = totals.entry(path).or_default();
+            *count += 1;
+            *sum += f64::from(ep.reward);
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(tag, (count, sum))| TagSubtree {
+            tag: tag.to_string(),
+            count,
+            mean_reward: (sum / count as f64) as f32,
+        })
+        .collect()
+}
+
+impl AgentMemDB {
+    /// Episode count and mean reward for every tag subtree under `root` (`""` for the whole
+    /// hierarchy), ordered by path. An episode counts once per subtree however many of its
+    /// tags fall in it; soft-deleted episodes are left out.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// for (tag, reward) in [("tool/browser/click", 1.0), ("tool/browser/type", 0.0)] {
+    ///     let ep = Episode::with_tags("t", vec![0.0, 1.0], reward, vec![tag.into()]);
+    ///     db.store_episode(ep).unwrap();
+    /// }
+    /// let stats = db.tag_subtree_stats("tool/browser");
+    /// let tags: Vec<&str> = stats.iter().map(|s| s.tag.as_str()).collect();
+    /// assert_eq!(tags, ["tool/browser", "tool/browser/click", "tool/browser/type"]);
+    /// assert_eq!((stats[0].count, stats[0].mean_reward), (2, 0.5));
+    /// ```
+    pub fn tag_subtree_stats(&self, root: &str) -> Vec<TagSubtree> {
+        tag_subtree_stats(self.iter(), root)
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Episode count and mean reward per tag subtree (see `AgentMemDB::tag_subtree_stats`).
+    pub fn tag_subtree_stats(&self, root: &str) -> Vec<TagSubtree> {
+        tag_subtree_stats(self.iter(), root)
+    }
+}
@@ -0,0 +1,293 @@
+//! Hot/cold tiering: a small RAM index over the episodes that matter most, in front of a disk
+//! DB holding everything.
+
+use crate::index::l2_distance;
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, QueryOptions};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Which episodes stay in the hot tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TierPolicy {
+    /// The most recent by timestamp; episodes without a timestamp are demoted first.
+    #[default]
+    Newest,
+    /// The highest reward; ties prefer more recent.
+    HighestReward,
+}
+
+/// When a query also searches the cold tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColdSearch {
+    /// Hot tier only: lowest latency, older or lower-priority episodes are never returned.
+    Never,
+    /// Fall back to the cold tier when the hot tier returns fewer than `top_k` results.
+    #[default]
+    IfShort,
+    /// Always search both tiers and merge.
+    Always,
+}
+
+/// Options for opening an `AgentMemDBTiered`.
+pub struct TieredOptions {
+    /// The cold tier. Its index type (exact or HNSW parameters) is used for the hot tier too.
+    pub disk: DiskOptions,
+    /// Episodes kept in the hot tier.
+    pub hot_capacity: usize,
+    pub policy: TierPolicy,
+    /// Default for `query_similar` and `query_similar_with_options`.
+    pub cold_search: ColdSearch,
+}
+
+impl TieredOptions {
+    pub fn new(disk: DiskOptions, hot_capacity: usize) -> Self {
+        Self {
+            disk,
+            hot_capacity,
+            policy: TierPolicy::default(),
+            cold_search: ColdSearch::default(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: TierPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_cold_search(mut self, cold_search: ColdSearch) -> Self {
+        self.cold_search = cold_search;
+        self
+    }
+}
+
+/// An in-memory hot tier of the top `hot_capacity` episodes (by [`TierPolicy`]) in front of an
+/// `AgentMemDBDisk` cold tier.
+///
+/// Every episode is written to the cold tier, which is the durable copy; the hot tier is a
+/// subset rebuilt from it on open. Queries search the small hot index first and, per
+/// [`ColdSearch`], fan out to the cold tier, so steady-state queries over recent (or
+/// high-reward) memory stay fast as the corpus grows.
+///
+/// The hot tier may hold up to 10% more than `hot_capacity`: overflow is demoted in one step,
+/// so the hot index is rebuilt once per spill rather than on every insert.
+///
+/// ```rust
+/// use agent_mem_db::{
+///     AgentMemDBTiered, ColdSearch, DiskOptions, Episode, QueryOptions, TieredOptions,
+/// };
+/// let dir = std::env::temp_dir().join("agent_mem_db_tiered_doctest");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// let opts = TieredOptions::new(DiskOptions::exact(2), 10);
+/// let mut db = AgentMemDBTiered::open(&dir, opts).unwrap();
+/// for i in 0..100 {
+///     db.store_episode(Episode::with_timestamp("t", vec![i as f32, 0.0], 1.0, i)).unwrap();
+/// }
+/// assert_eq!(db.len(), 100);
+/// assert!(db.hot_len() <= 11);
+/// // The oldest episode was demoted; only the cold tier finds it.
+/// let opts = QueryOptions::new(0.0, 1);
+/// let hot = db.query_tiers(&[0.0, 0.0], opts.clone(), ColdSearch::Never).unwrap();
+/// assert_ne!(hot[0].timestamp, Some(0));
+/// let all = db.query_tiers(&[0.0, 0.0], opts, ColdSearch::Always).unwrap();
+/// assert_eq!(all[0].timestamp, Some(0));
+/// ```
+pub struct AgentMemDBTiered {
+    hot: AgentMemDB,
+    cold: AgentMemDBDisk,
+    hot_capacity: usize,
+    policy: TierPolicy,
+    cold_search: ColdSearch,
+}
+
+impl AgentMemDBTiered {
+    /// Open or create the cold tier at `path` and fill the hot tier from it.
+    pub fn open(path: impl AsRef<Path>, opts: TieredOptions) -> Result<Self, AgentMemError> {
+        let dim = opts.disk.dim;
+        let hnsw_params = opts.disk.hnsw_params;
+        let hot = match opts.disk.index_type.as_deref() {
+            Some("exact") => AgentMemDB::new_exact(dim),
+            _ => AgentMemDB::new_hnsw(dim, spill_limit(opts.hot_capacity).max(20_000), hnsw_params),
+        };
+        let cold = AgentMemDBDisk::open_with_options(path, opts.disk)?;
+        let mut db = Self {
+            hot,
+            cold,
+            hot_capacity: opts.hot_capacity,
+            policy: opts.policy,
+            cold_search: opts.cold_search,
+        };
+        let mut ranked: Vec<&Episode> = db.cold.iter().collect();
+        ranked.sort_by(|a, b| priority(db.policy, a, b));
+        let hot: Vec<Episode> = ranked.into_iter().take(db.hot_capacity).cloned().collect();
+        db.hot.store_episodes(hot)?;
+        Ok(db)
+    }
+
+    /// Return the embedding dimension.
+    pub fn dim(&self) -> usize {
+        self.cold.dim()
+    }
+
+    /// Number of stored episodes (both tiers).
+    pub fn len(&self) -> usize {
+        self.cold.len()
+    }
+
+    /// True when no episodes are stored.
+    pub fn is_empty(&self) -> bool {
+        self.cold.is_empty()
+    }
+
+    /// Episodes currently in the hot tier.
+    pub fn hot_len(&self) -> usize {
+        self.hot.len()
+    }
+
+    /// Iterate over all stored episodes (arbitrary order).
+    pub fn iter(&self) -> impl Iterator<Item = &Episode> {
+        self.cold.iter()
+    }
+
+    /// Store an episode in both tiers, demoting hot overflow.
+    pub fn store_episode(&mut self, episode: Episode) -> Result<(), AgentMemError> {
+        self.store_episodes(vec![episode])
+    }
+
+    /// Store a batch all-or-nothing (see `AgentMemDBDisk::store_episodes`), demoting hot
+    /// overflow.
+    pub fn store_episodes(&mut self, episodes: Vec<Episode>) -> Result<(), AgentMemError> {
+        self.cold.store_episodes(episodes.clone())?;
+        self.hot.store_episodes(episodes)?;
+        if self.hot.len() > spill_limit(self.hot_capacity) {
+            match self.policy {
+                TierPolicy::Newest => self.hot.prune_keep_newest(self.hot_capacity),
+                TierPolicy::HighestReward => self.hot.prune_keep_highest_reward(self.hot_capacity),
+            }?;
+        }
+        Ok(())
+    }
+
+    /// Replace an episode if its version is still `expected_version` (see
+    /// `AgentMemDB::update_episode`). Returns the new version.
+    pub fn update_episode(
+        &mut self,
+        episode: Episode,
+        expected_version: u64,
+    ) -> Result<u64, AgentMemError> {
+        let version = self
+            .cold
+            .update_episode(episode.clone(), expected_version)?;
+        ignore_not_found(self.hot.update_episode(episode, expected_version))?;
+        Ok(version)
+    }
+
+    /// Soft-delete an episode in both tiers (see `AgentMemDB::soft_delete`).
+    pub fn soft_delete(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        self.cold.soft_delete(id)?;
+        ignore_not_found(self.hot.soft_delete(id))
+    }
+
+    /// Undo `soft_delete`.
+    pub fn restore(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        self.cold.restore(id)?;
+        ignore_not_found(self.hot.restore(id))
+    }
+
+    /// Remove every episode matching `predicate` from both tiers. Returns episodes removed.
+    pub fn delete_where(
+        &mut self,
+        predicate: impl Fn(&Episode) -> bool,
+    ) -> Result<usize, AgentMemError> {
+        let removed = self.cold.delete_where(&predicate)?;
+        self.hot.delete_where(predicate);
+        Ok(removed)
+    }
+
+    /// Query for the top_k most similar episodes with reward >= min_reward.
+    pub fn query_similar(
+        &self,
+        query_embedding: &[f32],
+        min_reward: f32,
+        top_k: usize,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        self.query_similar_with_options(query_embedding, QueryOptions::new(min_reward, top_k))
+    }
+
+    /// Query with filters, searching the cold tier per the configured `ColdSearch`.
+    pub fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        self.query_tiers(query_embedding, opts, self.cold_search)
+    }
+
+    /// Query with filters, choosing per call whether to search the cold tier.
+    pub fn query_tiers(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+        cold_search: ColdSearch,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        // Merging needs the embeddings; they are dropped at the end if not wanted.
+        let tier_opts = opts.clone().include_embeddings(true);
+        let hot = self
+            .hot
+            .query_similar_with_options(query_embedding, tier_opts.clone())?;
+        let fan_out = match cold_search {
+            ColdSearch::Never => false,
+            ColdSearch::IfShort => hot.len() < opts.top_k,
+            ColdSearch::Always => true,
+        };
+        if !fan_out {
+            return Ok(hot.into_iter().map(|ep| opts.project(ep)).collect());
+        }
+        let cold = self
+            .cold
+            .query_similar_with_options(query_embedding, tier_opts)?;
+        // The cold tier holds the hot episodes too; keep one copy of each.
+        let merged: HashMap<Uuid, Episode> =
+            hot.into_iter().chain(cold).map(|ep| (ep.id, ep)).collect();
+        let mut ranked: Vec<(f32, Episode)> = merged
+            .into_values()
+            .map(|ep| (l2_distance(query_embedding, &ep.state_embedding), ep))
+            .collect();
+        opts.apply_avoid(&mut ranked, |ep| ep.state_embedding.as_slice());
+        opts.apply_recency_boost(&mut ranked, |ep| ep.timestamp);
+        ranked.sort_by(|a, b| opts.rank(a, b));
+        opts.take_results(&mut ranked, |ep| ep.task_id.clone());
+        Ok(ranked.into_iter().map(|(_, ep)| opts.project(ep)).collect())
+    }
+}
+
+/// Hot tier size that triggers demotion back down to `hot_capacity`.
+fn spill_limit(hot_capacity: usize) -> usize {
+    hot_capacity + (hot_capacity / 10).max(1)
+}
+
+/// Hot-tier order, best first; matches `prune_keep_newest` / `prune_keep_highest_reward`.
+fn priority(policy: TierPolicy, a: &Episode, b: &Episode) -> Ordering {
+    let newer = || {
+        b.timestamp
+            .unwrap_or(i64::MIN)
+            .cmp(&a.timestamp.unwrap_or(i64::MIN))
+    };
+    match policy {
+        TierPolicy::Newest => newer(),
+        TierPolicy::HighestReward => b
+            .reward
+            .partial_cmp(&a.reward)
+            .unwrap_or(Ordering::Equal)
+            .then_with(newer),
+    }
+}
+
+/// Hot-tier mirror of a cold-tier change: the episode may have been demoted.
+fn ignore_not_found<T>(result: Result<T, AgentMemError>) -> Result<(), AgentMemError> {
+    match result {
+        Ok(_) | Err(AgentMemError::NotFound) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,410 @@
+//! Time partitioning: one disk DB per time bucket, so time-range queries skip whole buckets and
+//! age-based pruning deletes bucket directories instead of rewriting a log.
+
+use crate::{
+    query_federated, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, EpisodeStore,
+    QueryOptions,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const PARTITIONS_FILE: &str = "partitions.json";
+const UNDATED_DIR: &str = "undated";
+const BUCKET_DIR_PREFIX: &str = "bucket-";
+
+/// One day in milliseconds.
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Serialize, Deserialize)]
+struct PartitionsMeta {
+    bucket_ms: i64,
+}
+
+/// Options for opening an `AgentMemDBTimePartitioned`.
+#[derive(Debug, Clone)]
+pub struct TimePartitionOptions {
+    /// Options for every partition's disk DB.
+    pub disk: DiskOptions,
+    /// Bucket width in milliseconds; an existing directory keeps the one it was created with.
+    pub bucket_ms: i64,
+}
+
+impl TimePartitionOptions {
+    /// Daily buckets.
+    pub fn daily(disk: DiskOptions) -> Self {
+        Self::new(disk, DAY_MS)
+    }
+
+    /// Buckets `bucket_ms` wide (at least 1).
+    pub fn new(disk: DiskOptions, bucket_ms: i64) -> Self {
+        Self {
+            disk,
+            bucket_ms: bucket_ms.max(1),
+        }
+    }
+}
+
+/// Episodes split by timestamp into fixed-width buckets, each an `AgentMemDBDisk` in its own
+/// directory (`bucket-<start ms>`); episodes without a timestamp go to `undated`.
+///
+/// A query with `time_after` / `time_before` only searches the buckets overlapping that range
+/// (and skips `undated`, whose episodes those filters reject), then merges their results by
+/// distance. `prune_older_than` deletes the directories of buckets entirely before the cutoff
+/// and only rewrites the log of the bucket the cutoff falls in.
+///
+/// ```rust
+/// use agent_mem_db::{AgentMemDBTimePartitioned, DiskOptions, Episode, TimePartitionOptions};
+/// let dir = std::env::temp_dir().join("agent_mem_db_time_partitioned_doctest");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// let opts = TimePartitionOptions::new(DiskOptions::exact(2), 10);
+/// let mut db = AgentMemDBTimePartitioned::open(&dir, opts).unwrap();
+/// for ts in 0..30 {
+///     db.store_episode(Episode::with_timestamp("t", vec![ts as f32, 0.0], 1.0, ts)).unwrap();
+/// }
+/// assert_eq!(db.buckets(), vec![0, 10, 20]);
+/// assert_eq!(db.prune_older_than(20).unwrap(), 20);
+/// assert_eq!(db.buckets(), vec![20]);
+/// ```
+pub struct AgentMemDBTimePartitioned {
+    path: PathBuf,
+    disk: DiskOptions,
+    bucket_ms: i64,
+    buckets: BTreeMap<i64, AgentMemDBDisk>,
+    undated: AgentMemDBDisk,
+    /// Bucket of each stored episode (`None` for undated).
+    locations: HashMap<Uuid, Option<i64>>,
+}
+
+impl AgentMemDBTimePartitioned {
+    /// Open or create a partitioned DB at `path`, opening every bucket found there.
+    pub fn open(path: impl AsRef<Path>, opts: TimePartitionOptions) -> Result<Self, AgentMemError> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path).map_err(AgentMemError::io("Create dir"))?;
+        let meta_path = path.join(PARTITIONS_FILE);
+        let bucket_ms = if meta_path.exists() {
+            let meta: PartitionsMeta = serde_json::from_str(
+                &fs::read_to_string(&meta_path).map_err(AgentMemError::io("Read partitions"))?,
+            )
+            .map_err(AgentMemError::serde("Parse partitions"))?;
+            meta.bucket_ms
+        } else {
+            let meta = serde_json::to_string_pretty(&PartitionsMeta {
+                bucket_ms: opts.bucket_ms,
+            })
+            .map_err(AgentMemError::serde("Serialize partitions"))?;
+            fs::write(&meta_path, meta).map_err(AgentMemError::io("Write partitions"))?;
+            opts.bucket_ms
+        };
+
+        let mut buckets = BTreeMap::new();
+        let entries = fs::read_dir(&path).map_err(AgentMemError::io("Read dir"))?;
+        for entry in entries {
+            let entry = entry.map_err(AgentMemError::io("Read dir"))?;
+            let name = entry.file_name();
+            let Some(start) = name
+                .to_str()
+                .and_then(|n| n.strip_prefix(BUCKET_DIR_PREFIX))
+                .and_then(|n| n.parse::<i64>().ok())
+            else {
+                continue;
+            };
+            buckets.insert(
+                start,
+                AgentMemDBDisk::open_with_options(entry.path(), opts.disk.clone())?,
+            );
+        }
+        let undated = AgentMemDBDisk::open_with_options(path.join(UNDATED_DIR), opts.disk.clone())?;
+
+        let mut locations: HashMap<Uuid, Option<i64>> =
+            undated.iter().map(|ep| (ep.id, None)).collect();
+        for (&start, db) in &buckets {
+            locations.extend(db.iter().map(|ep| (ep.id, Some(start))));
+        }
+        Ok(Self {
+            path,
+            disk: opts.disk,
+            bucket_ms,
+            buckets,
+            undated,
+            locations,
+        })
+    }
+
+    /// Return the embedding dimension.
+    pub fn dim(&self) -> usize {
+        self.undated.dim()
+    }
+
+    /// Number of stored episodes across all buckets.
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// True when no episodes are stored.
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// Bucket width in milliseconds.
+    pub fn bucket_ms(&self) -> i64 {
+        self.bucket_ms
+    }
+
+    /// Start (Unix ms) of every non-undated bucket, oldest first.
+    pub fn buckets(&self) -> Vec<i64> {
+        self.buckets.keys().copied().collect()
+    }
+
+    /// Iterate over all stored episodes (arbitrary order).
+    pub fn iter(&self) -> impl Iterator<Item = &Episode> {
+        self.buckets
+            .values()
+            .chain(std::iter::once(&self.undated))
+            .flat_map(|db| db.iter())
+    }
+
+    /// Start of the bucket holding `timestamp`.
+    fn bucket_of(&self, timestamp: i64) -> i64 {
+        timestamp.div_euclid(self.bucket_ms) * self.bucket_ms
+    }
+
+    /// The partition for `bucket`, opening (creating) it if needed.
+    fn partition_mut(&mut self, bucket: Option<i64>) -> Result<&mut AgentMemDBDisk, AgentMemError> {
+        let Some(start) = bucket else {
+            return Ok(&mut self.undated);
+        };
+        if !self.buckets.contains_key(&start) {
+            let dir = self.path.join(format!("{BUCKET_DIR_PREFIX}{start}"));
+            let db = AgentMemDBDisk::open_with_options(dir, self.disk.clone())?;
+            self.buckets.insert(start, db);
+        }
+        Ok(self.buckets.get_mut(&start).expect("bucket just opened"))
+    }
+
+    fn partition(&self, bucket: Option<i64>) -> Option<&AgentMemDBDisk> {
+        match bucket {
+            Some(start) => self.buckets.get(&start),
+            None => Some(&self.undated),
+        }
+    }
+
+    /// Store an episode in its bucket. An episode with the same id is replaced, moving it if its
+    /// timestamp now falls in another bucket.
+    pub fn store_episode(&mut self, episode: Episode) -> Result<(), AgentMemError> {
+        self.store_episodes(vec![episode])
+    }
+
+    /// Store a batch. Dimensions are checked before anything is written; each bucket's share is
+    /// then stored all-or-nothing (see `AgentMemDBDisk::store_episodes`), but a failure in one
+    /// bucket does not undo the buckets already written.
+    pub fn store_episodes(&mut self, episodes: Vec<Episode>) -> Result<(), AgentMemError> {
+        let dim = self.dim();
+        if let Some(ep) = episodes.iter().find(|ep| ep.state_embedding.len() != dim) {
+            return Err(AgentMemError::DimensionMismatch {
+                expected: dim,
+                got: ep.state_embedding.len(),
+            });
+        }
+        let mut by_bucket: BTreeMap<Option<i64>, Vec<Episode>> = BTreeMap::new();
+        for ep in episodes {
+            by_bucket
+                .entry(ep.timestamp.map(|ts| self.bucket_of(ts)))
+                .or_default()
+                .push(ep);
+        }
+        for (bucket, episodes) in by_bucket {
+            let ids: Vec<Uuid> = episodes.iter().map(|ep| ep.id).collect();
+            self.partition_mut(bucket)?.store_episodes(episodes)?;
+            for id in ids {
+                self.relocate(id, bucket)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record that `id` now lives in `bucket`, removing the copy from its previous bucket.
+    fn relocate(&mut self, id: Uuid, bucket: Option<i64>) -> Result<(), AgentMemError> {
+        match self.locations.insert(id, bucket) {
+            Some(old) if old != bucket => {
+                self.partition_mut(old)?.delete_where(|ep| ep.id == id)?;
+                self.drop_if_empty(old)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Replace an episode if its version is still `expected_version` (see
+    /// `AgentMemDB::update_episode`). Returns the new version.
+    pub fn update_episode(
+        &mut self,
+        mut episode: Episode,
+        expected_version: u64,
+    ) -> Result<u64, AgentMemError> {
+        let current = self
+            .locations
+            .get(&episode.id)
+            .and_then(|&bucket| self.partition(bucket))
+            .and_then(|db| db.iter().find(|ep| ep.id == episode.id))
+            .ok_or(AgentMemError::NotFound)?;
+        if current.version != expected_version {
+            return Err(AgentMemError::Conflict {
+                expected: expected_version,
+                actual: current.version,
+            });
+        }
+        episode.version = expected_version + 1;
+        let version = episode.version;
+        self.store_episode(episode)?;
+        Ok(version)
+    }
+
+    /// Soft-delete an episode (see `AgentMemDB::soft_delete`).
+    pub fn soft_delete(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        let bucket = *self.locations.get(&id).ok_or(AgentMemError::NotFound)?;
+        self.partition_mut(bucket)?.soft_delete(id)
+    }
+
+    /// Undo `soft_delete`.
+    pub fn restore(&mut self, id: Uuid) -> Result<(), AgentMemError> {
+        let bucket = *self.locations.get(&id).ok_or(AgentMemError::NotFound)?;
+        self.partition_mut(bucket)?.restore(id)
+    }
+
+    /// Remove every episode matching `predicate`, compacting the logs of the buckets it touched.
+    /// Returns episodes removed.
+    pub fn delete_where(
+        &mut self,
+        predicate: impl Fn(&Episode) -> bool,
+    ) -> Result<usize, AgentMemError> {
+        let mut removed = 0;
+        let starts = self.buckets();
+        for bucket in starts.into_iter().map(Some).chain([None]) {
+            let db = self.partition_mut(bucket)?;
+            let ids: Vec<Uuid> = db
+                .iter()
+                .filter(|ep| predicate(ep))
+                .map(|ep| ep.id)
+                .collect();
+            if ids.is_empty() {
+                continue;
+            }
+            removed += db.delete_where(&predicate)?;
+            for id in ids {
+                self.locations.remove(&id);
+            }
+            self.drop_if_empty(bucket)?;
+        }
+        Ok(removed)
+    }
+
+    /// Remove episodes with a timestamp before `timestamp_cutoff_ms` (undated ones are kept).
+    /// Buckets entirely before the cutoff are deleted as directories; only the bucket containing
+    /// the cutoff has its log rewritten. Returns episodes removed.
+    pub fn prune_older_than(&mut self, timestamp_cutoff_ms: i64) -> Result<usize, AgentMemError> {
+        let mut removed = 0;
+        let cutoff_bucket = self.bucket_of(timestamp_cutoff_ms);
+        let expired: Vec<i64> = self
+            .buckets
+            .range(..cutoff_bucket)
+            .map(|(&s, _)| s)
+            .collect();
+        for start in expired {
+            if let Some(db) = self.buckets.remove(&start) {
+                removed += db.len();
+                for ep in db.iter() {
+                    self.locations.remove(&ep.id);
+                }
+                drop(db);
+                self.remove_bucket_dir(start)?;
+            }
+        }
+        if let Some(db) = self.buckets.get_mut(&cutoff_bucket) {
+            let ids: Vec<Uuid> = db
+                .iter()
+                .filter(|ep| ep.timestamp.is_some_and(|t| t < timestamp_cutoff_ms))
+                .map(|ep| ep.id)
+                .collect();
+            removed += db.prune_older_than(timestamp_cutoff_ms)?;
+            for id in ids {
+                self.locations.remove(&id);
+            }
+            self.drop_if_empty(Some(cutoff_bucket))?;
+        }
+        Ok(removed)
+    }
+
+    /// Delete a bucket left without episodes (never `undated`).
+    fn drop_if_empty(&mut self, bucket: Option<i64>) -> Result<(), AgentMemError> {
+        let Some(start) = bucket else {
+            return Ok(());
+        };
+        if self.buckets.get(&start).is_some_and(|db| db.is_empty()) {
+            self.buckets.remove(&start);
+            self.remove_bucket_dir(start)?;
+        }
+        Ok(())
+    }
+
+    fn remove_bucket_dir(&self, start: i64) -> Result<(), AgentMemError> {
+        fs::remove_dir_all(self.path.join(format!("{BUCKET_DIR_PREFIX}{start}")))
+            .map_err(AgentMemError::io(format!("Remove bucket {start}")))
+    }
+
+    /// The partitions a query has to search: the buckets overlapping its time range, plus
+    /// `undated` unless a time filter is set.
+    fn partitions_for(&self, opts: &QueryOptions) -> Vec<&AgentMemDBDisk> {
+        let first = opts.time_after.map_or(i64::MIN, |ts| self.bucket_of(ts));
+        let last = opts.time_before.map_or(i64::MAX, |ts| self.bucket_of(ts));
+        let mut partitions: Vec<&AgentMemDBDisk> = if first > last {
+            Vec::new()
+        } else {
+            self.buckets.range(first..=last).map(|(_, db)| db).collect()
+        };
+        if opts.time_after.is_none() && opts.time_before.is_none() {
+            partitions.push(&self.undated);
+        }
+        partitions
+    }
+
+    /// Number of partitions (buckets, plus `undated`) a query with `opts` searches.
+    pub fn partitions_searched(&self, opts: &QueryOptions) -> usize {
+        self.partitions_for(opts).len()
+    }
+
+    /// Query for the top_k most similar episodes with reward >= min_reward.
+    pub fn query_similar(
+        &self,
+        query_embedding: &[f32],
+        min_reward: f32,
+        top_k: usize,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        self.query_similar_with_options(query_embedding, QueryOptions::new(min_reward, top_k))
+    }
+
+    /// Query with filters, searching only the buckets the time filters allow.
+    pub fn query_similar_with_options(
+        &self,
+        query_embedding: &[f32],
+        opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        if query_embedding.len() != self.dim() {
+            return Err(AgentMemError::DimensionMismatch {
+                expected: self.dim(),
+                got: query_embedding.len(),
+            });
+        }
+        let partitions = self.partitions_for(&opts);
+        let stores: Vec<&dyn EpisodeStore> = partitions
+            .into_iter()
+            .map(|db| db as &dyn EpisodeStore)
+            .collect();
+        Ok(query_federated(&stores, query_embedding, opts)?
+            .into_iter()
+            .map(|hit| hit.episode)
+            .collect())
+    }
+}
@@ -0,0 +1,68 @@
+//! Per-user index partitions: a user-scoped query searches only that user's vectors.
+
+use crate::sub_index::SubIndex;
+use crate::{AgentMemDB, QueryOptions};
+use std::collections::HashMap;
+
+impl AgentMemDB {
+    /// Partition the index by `user_id`. Each user's episodes also go into an index of their
+    /// own, and a query with `QueryOptions::user_id` searches only that partition, so it never
+    /// visits another user's vectors (filtering the shared index can't promise that). A
+    /// partition starts exact and switches to HNSW, with this DB's parameters, once it outgrows
+    /// exact search. Episodes without a user_id are only in the main index. Enabling builds the
+    /// partitions from the stored episodes; disabling drops them.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode, QueryOptions};
+    /// let mut db = AgentMemDB::new(2);
+    /// db.set_user_partitions(true);
+    /// db.store_episode(Episode::with_user_id("t", vec![0.0, 1.0], 1.0, "alice")).unwrap();
+    /// db.store_episode(Episode::with_user_id("t", vec![0.0, 1.0], 1.0, "bob")).unwrap();
+    /// let opts = QueryOptions::new(0.0, 5).user_id("alice");
+    /// let explained = db.explain_query(&[0.0, 1.0], opts).unwrap();
+    /// assert!(explained.user_partition);
+    /// assert_eq!(explained.candidates.len(), 1);
+    /// ```
+    pub fn set_user_partitions(&mut self, enabled: bool) {
+        if !enabled {
+            self.user_partitions = None;
+            return;
+        }
+        if self.user_partitions.is_some() {
+            return;
+        }
+        let max_elements = self.episodes.len().max(20_000).max(self.dim * 2);
+        let mut partitions: HashMap<String, SubIndex> = HashMap::new();
+        let mut keys: Vec<usize> = self.key_to_uuid.keys().copied().collect();
+        keys.sort_unstable();
+        for key in keys {
+            let Some(ep) = self
+                .key_to_uuid
+                .get(&key)
+                .and_then(|id| self.episodes.get(id))
+            else {
+                continue;
+            };
+            if let Some(ref user) = ep.user_id {
+                partitions
+                    .entry(user.clone())
+                    .or_insert_with(|| SubIndex::growing(&self.index, max_elements))
+                    .insert(key, &ep.state_embedding);
+            }
+        }
+        self.user_partitions = Some(partitions);
+    }
+
+    /// Whether the index is partitioned by user_id (see `set_user_partitions`).
+    pub fn is_user_partitioned(&self) -> bool {
+        self.user_partitions.is_some()
+    }
+
+    /// The partition a query must search, when the index is partitioned and the query is
+    /// scoped to a user: `Some(None)` if that user has no episodes.
+    pub(crate) fn user_partition(&self, opts: &QueryOptions) -> Option<Option<&SubIndex>> {
+        let partitions = self.user_partitions.as_ref()?;
+        let user = opts.user_id.as_ref()?;
+        Some(partitions.get(user))
+    }
+}
@@ -0,0 +1,131 @@
+//! Warming a freshly loaded DB so its first queries don't pay for cold caches: the embeddings
+//! and index vectors are read once, and a few queries walk the index.
+//!
+//! Everything is loaded into memory at open (there are no memory-mapped files), but nothing
+//! has been read since: the first queries after `load_from_file` or `open` fault in the pages
+//! and fill the CPU caches, and can take hundreds of milliseconds on a large DB.
+
+use crate::index::IndexBackend;
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode, QueryOptions};
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Stored embeddings queried by `warm_up` when no sample queries are given.
+pub const WARM_UP_QUERIES: usize = 16;
+
+/// Results per warm-up query.
+const WARM_UP_TOP_K: usize = 10;
+
+/// What `AgentMemDB::warm_up` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarmUpReport {
+    /// Embeddings read: the stored episodes', plus the exact index's own copies.
+    pub vectors_touched: usize,
+    pub queries_run: usize,
+    pub elapsed: Duration,
+}
+
+/// Read every vector in `embeddings` and `index`; returns how many.
+fn touch<'a>(embeddings: impl Iterator<Item = &'a Episode>, index: &IndexBackend) -> usize {
+    let mut touched = 0;
+    for ep in embeddings {
+        black_box(ep.state_embedding.iter().sum::<f32>());
+        touched += 1;
+    }
+    touched + index.touch_vectors()
+}
+
+/// Up to `WARM_UP_QUERIES` stored embeddings, spread over `episodes`.
+fn default_queries<'a>(episodes: impl ExactSizeIterator<Item = &'a Episode>) -> Vec<Vec<f32>> {
+    let step = episodes.len().div_ceil(WARM_UP_QUERIES).max(1);
+    episodes
+        .step_by(step)
+        .map(|ep| ep.state_embedding.clone())
+        .collect()
+}
+
+/// Touch the vectors, then run `sample_queries` (or stored embeddings) through `search`.
+fn warm_up<'a>(
+    dim: usize,
+    episodes: impl ExactSizeIterator<Item = &'a Episode> + Clone,
+    index: &IndexBackend,
+    sample_queries: Option<&[Vec<f32>]>,
+    search: impl Fn(&[f32], &QueryOptions) -> Result<(Vec<Arc<Episode>>, bool), AgentMemError>,
+) -> Result<WarmUpReport, AgentMemError> {
+    if let Some(query) = sample_queries
+        .into_iter()
+        .flatten()
+        .find(|q| q.len() != dim)
+    {
+        return Err(AgentMemError::DimensionMismatch {
+            expected: dim,
+            got: query.len(),
+        });
+    }
+    let started = Instant::now();
+    let vectors_touched = touch(episodes.clone(), index);
+    let defaults;
+    let queries = match sample_queries {
+        Some(queries) => queries,
+        None => {
+            defaults = default_queries(episodes);
+            &defaults
+        }
+    };
+    let opts = QueryOptions::new(f32::NEG_INFINITY, WARM_UP_TOP_K);
+    for query in queries {
+        black_box(search(query, &opts)?);
+    }
+    Ok(WarmUpReport {
+        vectors_touched,
+        queries_run: queries.len(),
+        elapsed: started.elapsed(),
+    })
+}
+
+impl AgentMemDB {
+    /// Read every stored embedding and index vector and run a few queries, so the first real
+    /// queries after `load_from_file` don't pay the cold-start cost. `sample_queries` should
+    /// look like the queries to come; without them, up to `WARM_UP_QUERIES` stored embeddings
+    /// are queried. Warm-up queries aren't counted in access statistics or the query log.
+    /// Fails with `DimensionMismatch` if a sample query has the wrong length.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// db.store_episode(Episode::new("t", vec![0.0, 1.0], 1.0)).unwrap();
+    /// let report = db.warm_up(None).unwrap();
+    /// assert_eq!(report.queries_run, 1);
+    /// db.warm_up(Some(&[vec![1.0, 0.0], vec![0.5, 0.5]])).unwrap();
+    /// ```
+    pub fn warm_up(
+        &self,
+        sample_queries: Option<&[Vec<f32>]>,
+    ) -> Result<WarmUpReport, AgentMemError> {
+        warm_up(
+            self.dim,
+            self.episodes.values().map(|ep| &**ep),
+            &self.index,
+            sample_queries,
+            |query, opts| self.ranked_hits(query, opts),
+        )
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Read every stored embedding and index vector and run a few queries, so the first real
+    /// queries after `open` don't pay the cold-start cost (see `AgentMemDB::warm_up`).
+    pub fn warm_up(
+        &self,
+        sample_queries: Option<&[Vec<f32>]>,
+    ) -> Result<WarmUpReport, AgentMemError> {
+        warm_up(
+            self.dim(),
+            self.episodes.values().map(|ep| &**ep),
+            &self.index,
+            sample_queries,
+            |query, opts| self.ranked_hits(query, opts),
+        )
+    }
+}
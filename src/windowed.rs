@@ -0,0 +1,114 @@
+//! Rolling time-window statistics: episode counts and mean reward over time, for "is my agent
+//! improving" charts straight from the memory store.
+
+use crate::{AgentMemDB, AgentMemDBDisk, AgentMemError, Episode};
+use serde::Serialize;
+
+/// Most windows `windowed_stats` returns; a smaller range or a larger step stays under it.
+pub const MAX_WINDOWS: usize = 10_000;
+
+/// Episodes timestamped within one window of `windowed_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct WindowStats {
+    /// Window start, Unix ms (inclusive).
+    pub start: i64,
+    /// Window end, Unix ms (exclusive).
+    pub end: i64,
+    pub count: usize,
+    /// `None` for an empty window.
+    pub mean_reward: Option<f32>,
+}
+
+/// Windows `window_ms` long, one starting every `step_ms` (at multiples of `step_ms`, so
+/// windows line up across calls), from the one holding the oldest timestamp to the one holding
+/// the newest. Windows overlap when `step_ms < window_ms`, and empty windows are kept so
+/// charts show the gaps. Undated and soft-deleted episodes are left out.
+fn windowed_stats<'a>(
+    episodes: impl Iterator<Item = &'a Episode>,
+    window_ms: i64,
+    step_ms: i64,
+) -> Result<Vec<WindowStats>, AgentMemError> {
+    if window_ms <= 0 || step_ms <= 0 {
+        return Err(AgentMemError::InvalidQuery(format!(
+            "window_ms ({window_ms}) and step_ms ({step_ms}) must be positive"
+        )));
+    }
+    let mut dated: Vec<(i64, f32)> = episodes
+        .filter(|ep| !ep.deleted)
+        .filter_map(|ep| Some((ep.timestamp?, ep.reward)))
+        .collect();
+    dated.sort_unstable_by_key(|&(ts, _)| ts);
+    let (Some(&(oldest, _)), Some(&(newest, _))) = (dated.first(), dated.last()) else {
+        return Ok(Vec::new());
+    };
+    // The first window that reaches the oldest episode (the first start after
+    // `oldest - window_ms`), and the last that starts by the newest.
+    let after = oldest.saturating_sub(window_ms) + 1;
+    let first = -(-after).div_euclid(step_ms) * step_ms;
+    let last = newest.div_euclid(step_ms) * step_ms;
+    let windows = (last as i128 - first as i128) / step_ms as i128 + 1;
+    if windows > MAX_WINDOWS as i128 {
+        return Err(AgentMemError::InvalidQuery(format!(
+            "{windows} windows of step_ms {step_ms} over the stored time range; at most {MAX_WINDOWS}"
+        )));
+    }
+    // Reward prefix sums, so each window is two binary searches.
+    let mut prefix = Vec::with_capacity(dated.len() + 1);
+    prefix.push(0.0f64);
+    for &(_, reward) in &dated {
+        prefix.push(prefix.last().unwrap() + f64::from(reward));
+    }
+    Ok((0..windows as i64)
+        .map(|i| {
+            let start = first + i * step_ms;
+            let end = start.saturating_add(window_ms);
+            let from = dated.partition_point(|&(ts, _)| ts < start);
+            let to = dated.partition_point(|&(ts, _)| ts < end);
+            let count = to - from;
+            WindowStats {
+                start,
+                end,
+                count,
+                mean_reward: (count > 0)
+                    .then(|| ((prefix[to] - prefix[from]) / count as f64) as f32),
+            }
+        })
+        .collect())
+}
+
+impl AgentMemDB {
+    /// Episode count and mean reward per time window: windows `window_ms` long, one starting
+    /// every `step_ms` (a multiple of it), covering the stored timestamps. Empty windows are
+    /// kept; undated and soft-deleted episodes are left out. Fails with `InvalidQuery` unless
+    /// both are positive, or when the range needs more than `MAX_WINDOWS` windows.
+    ///
+    /// ```rust
+    /// use agent_mem_db::{AgentMemDB, Episode};
+    /// let mut db = AgentMemDB::new_exact(2);
+    /// for (ts, reward) in [(0, 0.0), (500, 0.5), (1_200, 1.0)] {
+    ///     db.store_episode(Episode::with_timestamp("t", vec![0.0, 1.0], reward, ts)).unwrap();
+    /// }
+    /// let windows = db.windowed_stats(1_000, 1_000).unwrap();
+    /// assert_eq!(windows.len(), 2);
+    /// assert_eq!((windows[0].count, windows[0].mean_reward), (2, Some(0.25)));
+    /// assert_eq!((windows[1].start, windows[1].mean_reward), (1_000, Some(1.0)));
+    /// ```
+    pub fn windowed_stats(
+        &self,
+        window_ms: i64,
+        step_ms: i64,
+    ) -> Result<Vec<WindowStats>, AgentMemError> {
+        windowed_stats(self.iter(), window_ms, step_ms)
+    }
+}
+
+impl AgentMemDBDisk {
+    /// Episode count and mean reward per time window (see `AgentMemDB::windowed_stats`).
+    pub fn windowed_stats(
+        &self,
+        window_ms: i64,
+        step_ms: i64,
+    ) -> Result<Vec<WindowStats>, AgentMemError> {
+        windowed_stats(self.iter(), window_ms, step_ms)
+    }
+}
@@ -0,0 +1,134 @@
+use agent_mem_db::{
+    AccessSummary, AgentMemDB, AgentMemDBDisk, DiskOptions, Episode, PruneRule, QueryOptions,
+};
+use std::fs;
+
+#[test]
+fn test_access_stats_counted_on_query() {
+    let mut db = AgentMemDB::new_exact(2);
+    let hit = Episode::new("hit", vec![1.0, 0.0], 1.0);
+    let miss = Episode::new("miss", vec![0.0, 5.0], 1.0);
+    db.store_episodes(vec![hit.clone(), miss.clone()]).unwrap();
+
+    let first = db.query_similar(&[1.0, 0.0], 0.0, 1).unwrap();
+    assert_eq!((first[0].id, first[0].times_retrieved), (hit.id, 1));
+    let retrieved_at = first[0].last_retrieved_at.unwrap();
+    db.query_similar_shared(&[1.0, 0.0], QueryOptions::new(0.0, 1))
+        .unwrap();
+    // Explaining a query doesn't count as retrieving.
+    db.explain_query(&[1.0, 0.0], QueryOptions::new(0.0, 1))
+        .unwrap();
+    let stats = db.access_stats(hit.id).unwrap();
+    assert_eq!(stats.times_retrieved, 2);
+    assert!(stats.last_retrieved_at.unwrap() >= retrieved_at);
+    assert_eq!(db.access_stats(miss.id).unwrap().times_retrieved, 0);
+    assert_eq!(
+        db.access_summary(),
+        AccessSummary {
+            total_retrievals: 2,
+            never_retrieved: 1
+        }
+    );
+
+    // Stored episodes catch up when folded; an update keeps the stats, whatever it sends.
+    db.fold_access_stats();
+    let stored = db.iter().find(|ep| ep.id == hit.id).unwrap();
+    assert_eq!(stored.times_retrieved, 2);
+    db.query_similar(&[1.0, 0.0], 0.0, 1).unwrap();
+    let mut update = hit.clone();
+    update.reward = 0.5;
+    db.update_episode(update, 0).unwrap();
+    assert_eq!(db.access_stats(hit.id).unwrap().times_retrieved, 3);
+
+    let path = std::env::temp_dir().join("agent_mem_db_access_stats_test.json");
+    db.query_similar(&[1.0, 0.0], 0.0, 1).unwrap();
+    db.save_to_file(&path).unwrap();
+    let loaded = AgentMemDB::load_from_file_exact(&path).unwrap();
+    assert_eq!(loaded.access_stats(hit.id).unwrap().times_retrieved, 4);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn test_prune_by_access() {
+    let mut db = AgentMemDB::new_exact(2);
+    let popular = Episode::with_timestamp("popular", vec![1.0, 0.0], 1.0, 0);
+    let once = Episode::with_timestamp("once", vec![0.0, 1.0], 1.0, 0);
+    let recent = Episode::with_timestamp("recent", vec![-1.0, 0.0], 1.0, i64::MAX);
+    let undated = Episode::new("undated", vec![0.0, -1.0], 1.0);
+    db.store_episodes(vec![
+        popular.clone(),
+        once.clone(),
+        recent.clone(),
+        undated.clone(),
+    ])
+    .unwrap();
+    db.query_similar(&[1.0, 0.0], 0.0, 1).unwrap();
+    db.query_similar(&[1.0, 0.0], 0.0, 1).unwrap();
+    db.query_similar(&[0.0, 1.0], 0.0, 1).unwrap();
+
+    // Retrieved just now, stored "in the future", or undated (and never retrieved): all kept.
+    assert_eq!(db.prune_not_retrieved_since(1).unwrap(), 0);
+    assert_eq!(db.len(), 4);
+
+    assert_eq!(db.prune_keep_most_retrieved(2).unwrap(), 2);
+    let mut kept: Vec<&str> = db.iter().map(|ep| ep.task_id.as_str()).collect();
+    kept.sort_unstable();
+    assert_eq!(kept, vec!["once", "popular"]);
+    assert_eq!(db.prune_keep_most_retrieved(5).unwrap(), 0);
+
+    assert_eq!(db.prune_not_retrieved_since(i64::MAX).unwrap(), 2);
+    assert!(db.is_empty());
+}
+
+#[test]
+fn test_prepared_prune_counts_later_retrievals() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(vec![
+        Episode::with_timestamp("old", vec![1.0, 1.0], 1.0, 0),
+        Episode::with_timestamp("a", vec![1.0, 0.0], 1.0, 10),
+        Episode::with_timestamp("b", vec![0.0, 1.0], 1.0, 10),
+        Episode::with_timestamp("c", vec![-1.0, 0.0], 1.0, 10),
+    ])
+    .unwrap();
+    db.query_similar(&[1.0, 0.0], 0.0, 1).unwrap();
+    db.fold_access_stats();
+    let prepared = db
+        .prepare_prune(&[PruneRule::OlderThan(5), PruneRule::KeepMostRetrieved(1)])
+        .unwrap();
+
+    // Retrieved more than `a` after the prune was prepared: the commit must keep it instead.
+    db.query_similar(&[-1.0, 0.0], 0.0, 1).unwrap();
+    db.query_similar(&[-1.0, 0.0], 0.0, 1).unwrap();
+    assert_eq!(db.commit_prune(prepared).unwrap(), 3);
+    let kept: Vec<&str> = db.iter().map(|ep| ep.task_id.as_str()).collect();
+    assert_eq!(kept, vec!["c"]);
+}
+
+#[test]
+fn test_disk_access_stats_persisted() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_access_test");
+    let _ = fs::remove_dir_all(&dir);
+    let hit = Episode::new("hit", vec![1.0, 0.0], 1.0);
+    let miss = Episode::new("miss", vec![0.0, 5.0], 1.0);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.store_episode(hit.clone()).unwrap();
+        db.store_episode(miss.clone()).unwrap();
+        let hits = db.query_similar(&[1.0, 0.0], 0.0, 1).unwrap();
+        assert_eq!(hits[0].times_retrieved, 1);
+        db.query_similar(&[1.0, 0.0], 0.0, 1).unwrap();
+        // Dropping the DB saves the stats beside the log.
+    }
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        assert_eq!(db.access_stats(hit.id).unwrap().times_retrieved, 2);
+        db.query_similar(&[1.0, 0.0], 0.0, 1).unwrap();
+        db.save_access_stats().unwrap();
+        assert_eq!(db.access_summary().never_retrieved, 1);
+        assert_eq!(db.prune_keep_most_retrieved(1).unwrap(), 1);
+    }
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    assert_eq!(db.len(), 1);
+    assert_eq!(db.access_stats(hit.id).unwrap().times_retrieved, 3);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,151 @@
+use agent_mem_db::{
+    AgentMemDB, AgentMemDBDisk, AgentMemError, DiskArchive, DiskOptions, Episode, EpisodeArchive,
+    PruneRule, QueryOptions,
+};
+use std::fs;
+use std::sync::Arc;
+
+/// An archive that refuses every write.
+struct Unavailable;
+
+impl EpisodeArchive for Unavailable {
+    fn dim(&self) -> usize {
+        2
+    }
+
+    fn archive(&self, _episodes: Vec<Episode>) -> Result<(), AgentMemError> {
+        Err(AgentMemError::io("Archive")(std::io::Error::other(
+            "archive unavailable",
+        )))
+    }
+
+    fn query(
+        &self,
+        _query_embedding: &[f32],
+        _opts: QueryOptions,
+    ) -> Result<Vec<Episode>, AgentMemError> {
+        Ok(Vec::new())
+    }
+
+    fn len(&self) -> usize {
+        0
+    }
+}
+
+fn episodes() -> Vec<Episode> {
+    vec![
+        Episode::with_timestamp("old", vec![1.0, 0.0], 1.0, 1_000),
+        Episode::with_timestamp("older", vec![0.0, 1.0], 0.0, 500),
+        Episode::with_timestamp("new", vec![1.0, 0.1], 1.0, 5_000),
+    ]
+}
+
+#[test]
+fn test_prune_into_archive() {
+    let dir = std::env::temp_dir().join("agent_mem_db_archive_test");
+    let _ = fs::remove_dir_all(&dir);
+    let archive = Arc::new(DiskArchive::open(&dir, DiskOptions::exact(2)).unwrap());
+    let mut db = AgentMemDB::new_exact(2);
+    assert!(db
+        .query_archive(&[1.0, 0.0], QueryOptions::new(0.0, 5))
+        .unwrap()
+        .is_empty());
+    db.set_archive(Some(archive.clone())).unwrap();
+    db.store_episodes(episodes()).unwrap();
+    db.query_similar(&[0.0, 1.0], 0.0, 1).unwrap();
+
+    assert_eq!(db.prune_older_than(2_000).unwrap(), 2);
+    assert_eq!((db.len(), archive.len()), (1, 2));
+
+    // Cold history is searched on demand, with the usual options and retrievals kept.
+    let hits = db
+        .query_archive(&[1.0, 0.0], QueryOptions::new(0.5, 5))
+        .unwrap();
+    let tasks: Vec<&str> = hits.iter().map(|ep| ep.task_id.as_str()).collect();
+    assert_eq!(tasks, vec!["old"]);
+    // One retrieval before the prune, one by this query on the archive.
+    let hits = db
+        .query_archive(&[0.0, 1.0], QueryOptions::new(0.0, 1))
+        .unwrap();
+    assert_eq!(
+        (hits[0].task_id.as_str(), hits[0].times_retrieved),
+        ("older", 2)
+    );
+
+    // Explicit deletes are not archived.
+    db.delete_where(|_| true);
+    assert_eq!((db.len(), archive.len()), (0, 2));
+
+    assert!(matches!(
+        db.query_archive(&[1.0], QueryOptions::new(0.0, 1)),
+        Err(AgentMemError::DimensionMismatch { .. })
+    ));
+    assert!(matches!(
+        AgentMemDB::new_exact(3).set_archive(Some(archive)),
+        Err(AgentMemError::DimensionMismatch {
+            expected: 3,
+            got: 2
+        })
+    ));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_failed_archive_keeps_episodes() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.set_archive(Some(Arc::new(Unavailable))).unwrap();
+    db.store_episodes(episodes()).unwrap();
+    assert!(matches!(
+        db.prune_keep_newest(1),
+        Err(AgentMemError::Io { .. })
+    ));
+    let prepared = db.prepare_prune(&[PruneRule::KeepNewest(1)]).unwrap();
+    assert!(matches!(
+        db.commit_prune(prepared),
+        Err(AgentMemError::Io { .. })
+    ));
+    assert_eq!(db.len(), 3);
+
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_archive_failed_test");
+    let _ = fs::remove_dir_all(&dir);
+    let mut disk = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    disk.set_archive(Some(Arc::new(Unavailable))).unwrap();
+    disk.store_episodes(episodes()).unwrap();
+    assert!(matches!(
+        disk.prune_keep_highest_reward(1),
+        Err(AgentMemError::Io { .. })
+    ));
+    assert_eq!(disk.len(), 3);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_disk_prune_into_archive() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_archive_test");
+    let archive_dir = std::env::temp_dir().join("agent_mem_db_disk_archive_cold_test");
+    let _ = fs::remove_dir_all(&dir);
+    let _ = fs::remove_dir_all(&archive_dir);
+    {
+        let archive = DiskArchive::open(&archive_dir, DiskOptions::exact(2)).unwrap();
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.set_archive(Some(Arc::new(archive))).unwrap();
+        let mut in_session = Episode::new("chat", vec![0.5, 0.5], 1.0);
+        in_session.session_id = Some("s1".into());
+        db.store_episodes(episodes()).unwrap();
+        db.store_episode(in_session).unwrap();
+        assert_eq!(db.prune_session("s1").unwrap(), 1);
+        assert_eq!(db.prune_keep_newest(2).unwrap(), 1);
+        assert_eq!(db.len(), 2);
+    }
+    // The archive is an ordinary disk DB and outlives the store that pruned into it.
+    let archive = DiskArchive::open(&archive_dir, DiskOptions::exact(2)).unwrap();
+    assert_eq!(archive.len(), 2);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    db.set_archive(Some(Arc::new(archive))).unwrap();
+    let hits = db
+        .query_archive(&[0.5, 0.5], QueryOptions::new(0.0, 1))
+        .unwrap();
+    assert_eq!(hits[0].session_id.as_deref(), Some("s1"));
+    let _ = fs::remove_dir_all(&dir);
+    let _ = fs::remove_dir_all(&archive_dir);
+}
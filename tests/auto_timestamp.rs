@@ -0,0 +1,73 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, DiskOptions, Episode, QueryOptions};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+#[test]
+fn test_auto_timestamp_fills_only_unset() {
+    let mut db = AgentMemDB::new_exact(2);
+    assert!(!db.auto_timestamp());
+    db.store_episode(Episode::new("off", vec![0.0, 1.0], 1.0))
+        .unwrap();
+
+    db.set_auto_timestamp(true);
+    let before = now_ms();
+    db.store_episode(Episode::new("on", vec![1.0, 0.0], 1.0))
+        .unwrap();
+    db.store_episode(Episode::with_timestamp("given", vec![1.0, 1.0], 1.0, 42))
+        .unwrap();
+    db.store_episodes(vec![
+        Episode::new("batch", vec![0.5, 0.5], 1.0),
+        Episode::new("batch", vec![0.5, 0.0], 1.0),
+    ])
+    .unwrap();
+    let after = now_ms();
+
+    let ts = |task: &str| -> Vec<Option<i64>> {
+        db.iter()
+            .filter(|ep| ep.task_id == task)
+            .map(|ep| ep.timestamp)
+            .collect()
+    };
+    assert_eq!(ts("off"), vec![None]);
+    assert_eq!(ts("given"), vec![Some(42)]);
+    let on = ts("on")[0].unwrap();
+    assert!((before..=after).contains(&on));
+    let batch = ts("batch");
+    assert!(batch[0].is_some());
+    assert_eq!(batch[0], batch[1]);
+
+    // Time filters now see the stamped episodes.
+    let mut opts = QueryOptions::new(0.0, 10);
+    opts.time_after = Some(before);
+    let results = db.query_similar_with_options(&[1.0, 0.0], opts).unwrap();
+    assert_eq!(results.len(), 3);
+}
+
+#[test]
+fn test_disk_auto_timestamp_is_logged() {
+    let dir = std::env::temp_dir().join("agent_mem_db_auto_timestamp_disk");
+    let _ = fs::remove_dir_all(&dir);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.set_auto_timestamp(true);
+        db.store_episode(Episode::new("t", vec![0.0, 1.0], 1.0))
+            .unwrap();
+        db.store_episodes(vec![
+            Episode::new("t", vec![1.0, 0.0], 1.0),
+            Episode::new("t", vec![1.0, 1.0], 1.0),
+        ])
+        .unwrap();
+    }
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    assert!(!db.auto_timestamp());
+    assert_eq!(db.len(), 3);
+    assert!(db.iter().all(|ep| ep.timestamp.is_some()));
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -1,5 +1,9 @@
-use agent_mem_db::{AgentMemDB, AgentMemError, Episode, QueryOptions};
+use agent_mem_db::{
+    AgentMemDB, AgentMemError, CancelToken, Episode, EpisodeLimits, EpisodeStep, HnswParams,
+    QueryOptions, StorePolicy,
+};
 use serde_json::json;
+use std::sync::Arc;
 use uuid::Uuid;
 
 fn make_episode(dim: usize, reward: f32) -> Episode {
@@ -14,6 +18,14 @@ fn make_episode(dim: usize, reward: f32) -> Episode {
         tags: None,
         source: None,
         user_id: None,
+        session_id: None,
+        version: 0,
+        deleted: false,
+        times_retrieved: 0,
+        last_retrieved_at: None,
+        cost: None,
+        duration_ms: None,
+        embedding_model: None,
     }
 }
 
@@ -43,6 +55,314 @@ fn test_min_reward_filter() {
     assert_eq!(results[0].id, ep2.id);
 }
 
+#[test]
+fn test_max_reward_filter() {
+    let dim = 8;
+    let mut db = AgentMemDB::new_exact(dim);
+    let failed = make_episode(dim, -1.0);
+    let partial = make_episode(dim, 0.4);
+    let solved = make_episode(dim, 1.0);
+    db.store_episodes(vec![failed.clone(), partial.clone(), solved.clone()])
+        .unwrap();
+    let query = vec![0.1; dim];
+
+    let opts = QueryOptions::new(f32::NEG_INFINITY, 3).max_reward(0.0);
+    let results = db.query_similar_with_options(&query, opts).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, failed.id);
+
+    let opts = QueryOptions::new(0.0, 3).reward_range(0.0, 0.5);
+    let results = db.query_similar_with_options(&query, opts.clone()).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, partial.id);
+    assert_eq!(opts.rejected_by(&solved), Some("max_reward"));
+    assert_eq!(opts.rejected_by(&failed), Some("min_reward"));
+}
+
+#[test]
+fn test_metadata_filters() {
+    let dim = 8;
+    let mut db = AgentMemDB::new_exact(dim);
+    let mut cheap = make_episode(dim, 1.0);
+    cheap.metadata = json!({"cost": 0.01, "latency_ms": 120});
+    let mut pricey = make_episode(dim, 1.0);
+    pricey.metadata = json!({"cost": 0.40, "latency_ms": 80});
+    let mut unpriced = make_episode(dim, 1.0);
+    unpriced.metadata = json!({"cost": "n/a", "error": null});
+    let bare = make_episode(dim, 1.0);
+    db.store_episodes(vec![
+        cheap.clone(),
+        pricey.clone(),
+        unpriced.clone(),
+        bare.clone(),
+    ])
+    .unwrap();
+    let query = vec![0.1; dim];
+    let ids = |opts: QueryOptions| -> Vec<Uuid> {
+        let mut ids: Vec<Uuid> = db
+            .query_similar_with_options(&query, opts)
+            .unwrap()
+            .iter()
+            .map(|ep| ep.id)
+            .collect();
+        ids.sort();
+        ids
+    };
+    let sorted = |mut v: Vec<Uuid>| {
+        v.sort();
+        v
+    };
+
+    let has_cost = QueryOptions::new(0.0, 10).metadata_has_key("cost");
+    assert_eq!(
+        ids(has_cost),
+        sorted(vec![cheap.id, pricey.id, unpriced.id])
+    );
+    // A null value still counts as present.
+    let has_error = QueryOptions::new(0.0, 10).metadata_has_key("error");
+    assert_eq!(ids(has_error), vec![unpriced.id]);
+
+    // Non-numeric and missing values never match a range.
+    let cheap_only = QueryOptions::new(0.0, 10).metadata_range("cost", 0.0, 0.05);
+    assert_eq!(ids(cheap_only.clone()), vec![cheap.id]);
+    assert_eq!(cheap_only.rejected_by(&unpriced), Some("metadata_range"));
+    assert_eq!(cheap_only.rejected_by(&bare), Some("metadata_range"));
+
+    // Ranges combine.
+    let fast_and_cheap = QueryOptions::new(0.0, 10)
+        .metadata_range("cost", 0.0, 1.0)
+        .metadata_range("latency_ms", 0.0, 100.0);
+    assert_eq!(ids(fast_and_cheap), vec![pricey.id]);
+}
+
+#[test]
+fn test_step_filters() {
+    let dim = 8;
+    let step = |index| EpisodeStep {
+        index,
+        action: "act".into(),
+        observation: "obs".into(),
+        step_reward: 0.0,
+    };
+    let mut db = AgentMemDB::new_exact(dim);
+    let summary = make_episode(dim, 1.0);
+    let mut empty = make_episode(dim, 1.0);
+    empty.steps = Some(Vec::new());
+    let mut short = make_episode(dim, 1.0);
+    short.steps = Some((0..2).map(step).collect());
+    let mut long = make_episode(dim, 1.0);
+    long.steps = Some((0..10).map(step).collect());
+    db.store_episodes(vec![
+        summary.clone(),
+        empty.clone(),
+        short.clone(),
+        long.clone(),
+    ])
+    .unwrap();
+    let query = vec![0.1; dim];
+    let ids = |opts: QueryOptions| -> Vec<Uuid> {
+        let mut ids: Vec<Uuid> = db
+            .query_similar_with_options(&query, opts)
+            .unwrap()
+            .iter()
+            .map(|ep| ep.id)
+            .collect();
+        ids.sort();
+        ids
+    };
+    let sorted = |mut v: Vec<Uuid>| {
+        v.sort();
+        v
+    };
+
+    let trajectories = QueryOptions::new(0.0, 10).has_steps(true);
+    assert_eq!(ids(trajectories), sorted(vec![short.id, long.id]));
+    // An empty step list is a summary.
+    let summaries = QueryOptions::new(0.0, 10).has_steps(false);
+    assert_eq!(ids(summaries.clone()), sorted(vec![summary.id, empty.id]));
+    assert_eq!(summaries.rejected_by(&long), Some("has_steps"));
+
+    let opts = QueryOptions::new(0.0, 10).min_steps(3);
+    assert_eq!(ids(opts), vec![long.id]);
+    let opts = QueryOptions::new(0.0, 10).min_steps(1).max_steps(5);
+    assert_eq!(ids(opts.clone()), vec![short.id]);
+    assert_eq!(opts.rejected_by(&long), Some("max_steps"));
+    assert_eq!(opts.rejected_by(&summary), Some("min_steps"));
+}
+
+#[test]
+fn test_include_embeddings() {
+    let dim = 8;
+    let mut db = AgentMemDB::new_exact(dim);
+    db.store_episode(make_episode(dim, 1.0)).unwrap();
+    let query = vec![0.1; dim];
+
+    // On by default, including for options deserialized without the field.
+    let results = db
+        .query_similar_with_options(&query, QueryOptions::new(0.0, 1))
+        .unwrap();
+    assert_eq!(results[0].state_embedding, query);
+    let opts: QueryOptions = serde_json::from_value(json!({"top_k": 1})).unwrap();
+    assert!(opts.include_embeddings);
+
+    let opts = QueryOptions::new(0.0, 1).include_embeddings(false);
+    let results = db.query_similar_with_options(&query, opts.clone()).unwrap();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].state_embedding.is_empty());
+    let explained = db.explain_query(&query, opts.clone()).unwrap();
+    assert!(explained.results[0].state_embedding.is_empty());
+    let snapshot = db.clone_snapshot();
+    let results = snapshot.query_similar_with_options(&query, opts).unwrap();
+    assert!(results[0].state_embedding.is_empty());
+    // The stored episode keeps its embedding.
+    assert_eq!(db.iter().next().unwrap().state_embedding, query);
+}
+
+#[test]
+fn test_query_timeout() {
+    let dim = 4;
+    let mut db = AgentMemDB::new_exact(dim);
+    let episodes: Vec<Episode> = (0..5000)
+        .map(|i| Episode::new("t", vec![i as f32; dim], 1.0))
+        .collect();
+    db.store_episodes(episodes).unwrap();
+    let query = vec![4999.0; dim];
+
+    let full = db
+        .query_similar_timed(&query, QueryOptions::new(0.0, 3).timeout_ms(60_000))
+        .unwrap();
+    assert!(!full.truncated);
+    assert_eq!(full.episodes[0].state_embedding, query);
+
+    // An exhausted budget stops the exact scan early, with the best of what was scored.
+    let partial = db
+        .query_similar_timed(&query, QueryOptions::new(0.0, 3).timeout_ms(0))
+        .unwrap();
+    assert!(partial.truncated);
+    assert_eq!(partial.episodes.len(), 3);
+    assert_ne!(partial.episodes[0].state_embedding, query);
+    let explained = db
+        .explain_query(&query, QueryOptions::new(0.0, 3).timeout_ms(0))
+        .unwrap();
+    assert!(explained.truncated);
+
+    // Adaptive widening stops too: the few high-reward episodes can't all be reached.
+    let mut hnsw = AgentMemDB::new(dim);
+    for i in 0..200 {
+        let reward = if i % 50 == 0 { 1.0 } else { 0.0 };
+        hnsw.store_episode(Episode::new("t", vec![i as f32; dim], reward))
+            .unwrap();
+    }
+    let opts = QueryOptions::new(0.5, 4).adaptive(true).timeout_ms(0);
+    let results = hnsw.query_similar_timed(&[0.0; 4], opts).unwrap();
+    assert!(results.truncated);
+}
+
+#[test]
+fn test_cancel_token() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episode(Episode::new("t", vec![0.0, 1.0], 1.0))
+        .unwrap();
+    let token = CancelToken::new();
+    let opts = QueryOptions::new(0.0, 5).cancel_token(token.clone());
+    assert_eq!(
+        db.query_similar_with_options(&[0.0, 1.0], opts.clone())
+            .unwrap()
+            .len(),
+        1
+    );
+
+    token.cancel();
+    assert!(opts.cancel.as_ref().unwrap().is_cancelled());
+    let err = db
+        .query_similar_with_options(&[0.0, 1.0], opts)
+        .unwrap_err();
+    assert!(matches!(err, AgentMemError::Cancelled));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_merge_clusters() {
+    use agent_mem_db::async_api::{merge_clusters_async, AsyncSummarizer};
+    use std::sync::{Arc, RwLock};
+
+    struct Count;
+    impl AsyncSummarizer for Count {
+        async fn summarize(&self, episodes: &[Episode]) -> Result<Episode, AgentMemError> {
+            tokio::task::yield_now().await;
+            Ok(Episode::new(
+                format!("{} merged", episodes.len()),
+                Vec::new(),
+                1.0,
+            ))
+        }
+    }
+
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(vec![
+        Episode::new("a", vec![0.0, 0.0], 1.0),
+        Episode::new("b", vec![0.0, 0.1], 1.0),
+        Episode::new("c", vec![0.1, 0.0], 1.0),
+        Episode::new("far", vec![9.0, 9.0], 1.0),
+    ])
+    .unwrap();
+    let db = Arc::new(RwLock::new(db));
+    assert_eq!(
+        merge_clusters_async(db.clone(), 0.5, 2, &Count)
+            .await
+            .unwrap(),
+        1
+    );
+    let db = db.read().unwrap();
+    let mut tasks: Vec<&str> = db.iter().map(|e| e.task_id.as_str()).collect();
+    tasks.sort();
+    assert_eq!(tasks, ["3 merged", "far"]);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_query_cancelled_on_drop() {
+    use agent_mem_db::async_api::{query_similar_async, query_similar_batch_async};
+    use std::sync::{Arc, RwLock};
+
+    let dim = 4;
+    let mut db = AgentMemDB::new_exact(dim);
+    let episodes: Vec<Episode> = (0..20_000)
+        .map(|i| Episode::new("t", vec![i as f32; dim], 1.0))
+        .collect();
+    db.store_episodes(episodes).unwrap();
+    let db = Arc::new(RwLock::new(db));
+
+    let hits = query_similar_async(db.clone(), vec![0.0; dim], QueryOptions::new(0.0, 2))
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 2);
+
+    // Abandoning the future cancels the scan it started.
+    let token = CancelToken::new();
+    let opts = QueryOptions::new(0.0, 2).cancel_token(token.clone());
+    tokio::select! {
+        biased;
+        _ = query_similar_async(db.clone(), vec![0.0; dim], opts) => panic!("query finished first"),
+        _ = async {} => {}
+    }
+    assert!(token.is_cancelled());
+
+    // A cancelled batch stops before running its queries.
+    let token = CancelToken::new();
+    token.cancel();
+    let opts = QueryOptions::new(0.0, 2).cancel_token(token);
+    let err = query_similar_batch_async(db.clone(), vec![vec![0.0; dim]; 3], opts)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AgentMemError::Cancelled));
+
+    let batch = query_similar_batch_async(db, vec![vec![0.0; dim]; 3], QueryOptions::new(0.0, 2))
+        .await
+        .unwrap();
+    assert_eq!(batch.len(), 3);
+}
+
 #[test]
 fn test_exact_backend() {
     let dim = 8;
@@ -57,6 +377,28 @@ fn test_exact_backend() {
     assert_eq!(results[0].id, ep1.id);
 }
 
+#[test]
+fn test_hnsw_params() {
+    let dim = 8;
+    let params = HnswParams {
+        m: 4,
+        ef_construction: 32,
+        ef_search: 16,
+    };
+    let mut db = AgentMemDB::new_hnsw(dim, 1000, params);
+    db.store_episode(Episode::with_timestamp("old", vec![0.1; dim], 0.9, 1000))
+        .unwrap();
+    db.store_episode(Episode::with_timestamp("new", vec![0.2; dim], 0.9, 2000))
+        .unwrap();
+    assert_eq!(db.query_similar(&vec![0.1; dim], 0.0, 5).unwrap().len(), 2);
+
+    // Rebuilding the index on prune keeps working with the custom parameters.
+    assert_eq!(db.prune_older_than(1500).unwrap(), 1);
+    let results = db.query_similar(&vec![0.1; dim], 0.0, 5).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].task_id, "new");
+}
+
 #[test]
 fn test_query_filters_tags_and_time() {
     let dim = 8;
@@ -116,6 +458,80 @@ fn test_query_filters_tags_and_time() {
     assert_eq!(results[0].task_id, "t4");
 }
 
+#[test]
+fn test_task_id_glob() {
+    let mut db = AgentMemDB::new_exact(2);
+    for task in [
+        "deploy-api-prod",
+        "deploy-web-prod",
+        "deploy-api-staging",
+        "deploy-prod",
+        "rollback-api-prod",
+    ] {
+        db.store_episode(Episode::new(task, vec![0.0, 1.0], 1.0))
+            .unwrap();
+    }
+    let tasks = |glob: &str| -> Vec<String> {
+        let opts = QueryOptions::new(0.0, 10).task_id_glob(glob);
+        let mut tasks: Vec<String> = db
+            .query_similar_with_options(&[0.0, 1.0], opts)
+            .unwrap()
+            .into_iter()
+            .map(|ep| ep.task_id)
+            .collect();
+        tasks.sort();
+        tasks
+    };
+    assert_eq!(
+        tasks("deploy-*-prod"),
+        ["deploy-api-prod", "deploy-web-prod"]
+    );
+    assert_eq!(
+        tasks("*-api-*"),
+        ["deploy-api-prod", "deploy-api-staging", "rollback-api-prod"]
+    );
+    assert_eq!(
+        tasks("deploy-???-prod"),
+        ["deploy-api-prod", "deploy-web-prod"]
+    );
+    assert_eq!(tasks("deploy*prod").len(), 3);
+    // The glob must match the whole task_id.
+    assert!(tasks("deploy").is_empty());
+    let opts = QueryOptions::new(0.0, 10).task_id_glob("deploy-*-prod");
+    let rollback = Episode::new("rollback-api-prod", vec![0.0, 1.0], 1.0);
+    assert_eq!(opts.rejected_by(&rollback), Some("task_id_glob"));
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_task_id_regex() {
+    let mut db = AgentMemDB::new_exact(2);
+    for task in [
+        "deploy-api-prod",
+        "deploy-api-v2-prod",
+        "deploy-api-staging",
+    ] {
+        db.store_episode(Episode::new(task, vec![0.0, 1.0], 1.0))
+            .unwrap();
+    }
+    let re = regex::Regex::new(r"^deploy-[a-z]+-prod$").unwrap();
+    let opts = QueryOptions::new(0.0, 10).task_id_regex(re);
+    let results = db
+        .query_similar_with_options(&[0.0, 1.0], opts.clone())
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].task_id, "deploy-api-prod");
+
+    // Options round-trip through JSON as the pattern string.
+    let json = serde_json::to_value(&opts).unwrap();
+    assert_eq!(json["task_id_regex"], r"^deploy-[a-z]+-prod$");
+    let back: QueryOptions = serde_json::from_value(json).unwrap();
+    assert_eq!(
+        back.task_id_regex.unwrap().as_str(),
+        r"^deploy-[a-z]+-prod$"
+    );
+}
+
 #[test]
 fn test_recency_tie_breaker() {
     // When two episodes have identical embeddings (same distance), prefer the more recent one.
@@ -134,6 +550,114 @@ fn test_recency_tie_breaker() {
     assert_eq!(results[1].task_id, "old");
 }
 
+#[test]
+fn test_deterministic_order() {
+    // Equal distances and no timestamps: only the id separates them.
+    let dim = 4;
+    let emb = vec![0.5; dim];
+    let mut episodes: Vec<Episode> = (0..6)
+        .map(|i| Episode::new(format!("t{i}"), emb.clone(), 1.0))
+        .collect();
+    episodes.push(Episode::with_timestamp("recent", emb.clone(), 1.0, 50));
+    let mut expected: Vec<Uuid> = episodes[..6].iter().map(|ep| ep.id).collect();
+    expected.sort();
+    expected.insert(0, episodes[6].id);
+
+    let opts = QueryOptions::new(0.0, 7).deterministic_order(true);
+    let mut forward = AgentMemDB::new(dim);
+    forward.store_episodes(episodes.clone()).unwrap();
+    let mut backward = AgentMemDB::new_exact(dim);
+    backward
+        .store_episodes(episodes.into_iter().rev().collect())
+        .unwrap();
+    for db in [&forward, &backward] {
+        let ids: Vec<Uuid> = db
+            .query_similar_with_options(&emb, opts.clone())
+            .unwrap()
+            .iter()
+            .map(|ep| ep.id)
+            .collect();
+        assert_eq!(ids, expected);
+    }
+    let snapshot = backward.clone_snapshot();
+    let ids: Vec<Uuid> = snapshot
+        .query_similar_with_options(&emb, opts)
+        .unwrap()
+        .iter()
+        .map(|ep| ep.id)
+        .collect();
+    assert_eq!(ids, expected);
+}
+
+#[test]
+fn test_recency_boost() {
+    let mut db = AgentMemDB::new_exact(2);
+    let old = Episode::with_timestamp("old", vec![1.0, 0.0], 1.0, 0);
+    let recent = Episode::with_timestamp("recent", vec![1.1, 0.0], 1.0, 3_600_000);
+    db.store_episodes(vec![old.clone(), recent.clone()])
+        .unwrap();
+    let query = [1.0, 0.0];
+
+    let plain = db
+        .query_similar_with_options(&query, QueryOptions::new(0.0, 2))
+        .unwrap();
+    assert_eq!(plain[0].id, old.id);
+
+    // An hour's age halves the similarity: 1.0 for the old episode vs 1/1.1 for the recent one.
+    let half_life = std::f32::consts::LN_2 / 3600.0;
+    let opts = QueryOptions::new(0.0, 2).recency_boost(half_life);
+    let boosted = db.query_similar_with_options(&query, opts.clone()).unwrap();
+    assert_eq!(boosted[0].id, recent.id);
+    assert_eq!(boosted[1].id, old.id);
+    let explained = db.explain_query(&query, opts.clone()).unwrap();
+    assert_eq!(explained.results[0].id, recent.id);
+    let snapshot = db.clone_snapshot();
+    let snapshot_hits = snapshot.query_similar_with_options(&query, opts).unwrap();
+    assert_eq!(snapshot_hits[0].id, recent.id);
+
+    // A weak boost leaves the closer episode first.
+    let weak = QueryOptions::new(0.0, 2).recency_boost(1e-9);
+    let hits = db.query_similar_with_options(&query, weak).unwrap();
+    assert_eq!(hits[0].id, old.id);
+}
+
+#[test]
+fn test_avoid_embedding() {
+    let mut db = AgentMemDB::new_exact(2);
+    let failed = Episode::new("failed", vec![1.0, 0.0], 1.0);
+    let other = Episode::new("other", vec![0.8, 0.5], 1.0);
+    db.store_episodes(vec![failed.clone(), other.clone()])
+        .unwrap();
+    let query = [1.0, 0.0];
+
+    // At distance 0.54, `other` takes a 2/1.54 penalty; the exact match takes the full 2.
+    let opts = QueryOptions::new(0.0, 2)
+        .avoid_embedding(failed.state_embedding.clone(), 2.0)
+        .include_embeddings(false);
+    let hits = db.query_similar_with_options(&query, opts.clone()).unwrap();
+    assert_eq!(hits[0].id, other.id);
+    assert_eq!(hits[1].id, failed.id);
+    let explained = db.explain_query(&query, opts.clone()).unwrap();
+    assert_eq!(explained.results[0].id, other.id);
+    let snapshot = db.clone_snapshot();
+    let snapshot_hits = snapshot.query_similar_with_options(&query, opts).unwrap();
+    assert_eq!(snapshot_hits[0].id, other.id);
+
+    // A small penalty doesn't outweigh the better match.
+    let mild = QueryOptions::new(0.0, 2).avoid_embedding(vec![1.0, 0.0], 0.5);
+    let hits = db.query_similar_with_options(&query, mild).unwrap();
+    assert_eq!(hits[0].id, failed.id);
+
+    let wrong_dim = QueryOptions::new(0.0, 2).avoid_embedding(vec![1.0; 3], 1.0);
+    assert!(matches!(
+        db.query_similar_with_options(&query, wrong_dim),
+        Err(AgentMemError::DimensionMismatch {
+            expected: 2,
+            got: 3
+        })
+    ));
+}
+
 #[test]
 fn test_prune_older_than() {
     let dim = 8;
@@ -145,7 +669,7 @@ fn test_prune_older_than() {
     db.store_episode(ep_new).unwrap();
     db.store_episode(ep_no_ts).unwrap();
 
-    let removed = db.prune_older_than(2000);
+    let removed = db.prune_older_than(2000).unwrap();
     assert_eq!(removed, 1);
     let query = vec![0.1; dim];
     let results = db.query_similar(&query, 0.0, 5).unwrap();
@@ -169,7 +693,7 @@ fn test_prune_keep_newest() {
     db.store_episode(Episode::with_timestamp("d", vec![0.1; dim], 0.6, 4000))
         .unwrap();
 
-    let removed = db.prune_keep_newest(2);
+    let removed = db.prune_keep_newest(2).unwrap();
     assert_eq!(removed, 2);
     let query = vec![0.1; dim];
     let results = db.query_similar(&query, 0.0, 5).unwrap();
@@ -181,6 +705,423 @@ fn test_prune_keep_newest() {
     assert!(!task_ids.contains(&"b"));
 }
 
+#[test]
+fn test_store_episodes_all_or_nothing() {
+    let mut db = AgentMemDB::new_exact(4);
+    let batch = vec![
+        Episode::new("a", vec![0.1; 4], 0.5),
+        Episode::new("b", vec![0.1; 3], 0.5),
+        Episode::new("c", vec![0.1; 4], 0.5),
+    ];
+    assert!(matches!(
+        db.store_episodes(batch),
+        Err(AgentMemError::DimensionMismatch {
+            expected: 4,
+            got: 3
+        })
+    ));
+    assert!(db.is_empty());
+}
+
+#[test]
+fn test_update_episode_checks_version() {
+    let mut db = AgentMemDB::new_exact(2);
+    let ep = Episode::new("a", vec![0.0, 0.0], 0.5);
+    db.store_episode(ep.clone()).unwrap();
+
+    // Two writers read version 0; the first update wins, the second gets a conflict.
+    let mut first = ep.clone();
+    first.reward = 0.9;
+    first.state_embedding = vec![1.0, 0.0];
+    let mut second = ep.clone();
+    second.reward = 0.1;
+    assert_eq!(db.update_episode(first, 0).unwrap(), 1);
+    assert!(matches!(
+        db.update_episode(second.clone(), 0),
+        Err(AgentMemError::Conflict {
+            expected: 0,
+            actual: 1
+        })
+    ));
+    assert_eq!(db.update_episode(second, 1).unwrap(), 2);
+
+    assert_eq!(db.len(), 1);
+    let stored = db.iter().next().unwrap();
+    assert_eq!((stored.reward, stored.version), (0.1, 2));
+    // The replaced embedding no longer matches; the episode is returned once.
+    let hits = db.query_similar(&[0.0, 0.0], 0.0, 5).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].version, 2);
+
+    let unknown = Episode::new("b", vec![0.0, 0.0], 0.5);
+    assert!(matches!(
+        db.update_episode(unknown, 0),
+        Err(AgentMemError::NotFound)
+    ));
+}
+
+#[test]
+fn test_delete_where() {
+    let dim = 8;
+    let mut db = AgentMemDB::new(dim);
+    let keep = Episode::new("keep", vec![0.1; dim], 0.9);
+    let drop_a = Episode::with_user_id("drop", vec![0.1; dim], 0.8, "u1");
+    let drop_b = Episode::new("other", vec![0.1; dim], 0.7);
+    let drop_b_id = drop_b.id;
+    db.store_episodes(vec![keep, drop_a, drop_b]).unwrap();
+
+    let removed = db.delete_where(|ep| ep.user_id.as_deref() == Some("u1") || ep.id == drop_b_id);
+    assert_eq!(removed, 2);
+    assert_eq!(db.delete_where(|ep| ep.task_id == "missing"), 0);
+    let results = db.query_similar(&vec![0.1; dim], 0.0, 5).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].task_id, "keep");
+}
+
+#[test]
+fn test_delete_where_query_options() {
+    let dim = 8;
+    let mut db = AgentMemDB::new(dim);
+    db.store_episode(Episode::with_user_id("a", vec![0.1; dim], 0.9, "u1"))
+        .unwrap();
+    db.store_episode(Episode::with_user_id("b", vec![0.1; dim], -0.5, "u1"))
+        .unwrap();
+    db.store_episode(Episode::with_user_id("c", vec![0.1; dim], 0.9, "u2"))
+        .unwrap();
+
+    let opts = QueryOptions::new(f32::NEG_INFINITY, 0).user_id("u1");
+    assert_eq!(db.delete_where(|ep| opts.matches(ep)), 2);
+    let results = db.query_similar(&vec![0.1; dim], -1.0, 5).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].task_id, "c");
+}
+
+#[test]
+fn test_session_id_filter_and_prune() {
+    let dim = 4;
+    let mut db = AgentMemDB::new_exact(dim);
+    db.store_episode(Episode::with_session_id("a", vec![0.0; dim], 0.9, "s1"))
+        .unwrap();
+    db.store_episode(Episode::with_session_id("b", vec![0.1; dim], 0.9, "s2"))
+        .unwrap();
+    db.store_episode(Episode::with_session_id("c", vec![0.2; dim], 0.9, "s1"))
+        .unwrap();
+    db.store_episode(Episode::new("d", vec![0.0; dim], 0.9))
+        .unwrap();
+
+    let opts = QueryOptions::new(0.0, 5).session_id("s1");
+    let results = db
+        .query_similar_with_options(&vec![0.0; dim], opts.clone())
+        .unwrap();
+    let tasks: Vec<&str> = results.iter().map(|e| e.task_id.as_str()).collect();
+    assert_eq!(tasks, vec!["a", "c"]);
+    let explained = db.explain_query(&vec![0.0; dim], opts).unwrap();
+    assert_eq!(explained.allowed_keys, Some(2));
+    let other = Episode::with_session_id("x", vec![0.0; dim], 0.9, "s2");
+    assert_eq!(
+        QueryOptions::new(0.0, 5)
+            .session_id("s1")
+            .rejected_by(&other),
+        Some("session_id")
+    );
+
+    assert_eq!(db.prune_session("s1").unwrap(), 2);
+    assert_eq!(db.prune_session("s1").unwrap(), 0);
+    let left = db.query_similar(&vec![0.0; dim], 0.0, 5).unwrap();
+    let mut tasks: Vec<&str> = left.iter().map(|e| e.task_id.as_str()).collect();
+    tasks.sort();
+    assert_eq!(tasks, vec!["b", "d"]);
+}
+
+#[test]
+fn test_explain_query() {
+    let dim = 4;
+    let mut db = AgentMemDB::new_exact(dim);
+    let mut near = Episode::new("near", vec![0.0; dim], 0.9);
+    near.tags = Some(vec!["keep".into()]);
+    let mut low = Episode::new("low", vec![0.1; dim], 0.1);
+    low.tags = Some(vec!["keep".into()]);
+    let untagged = Episode::new("untagged", vec![0.2; dim], 0.9);
+    let mut far = Episode::new("far", vec![0.3; dim], 0.9);
+    far.tags = Some(vec!["keep".into()]);
+    db.store_episodes(vec![near, low, untagged, far]).unwrap();
+
+    let opts = QueryOptions::new(0.5, 1).tags_any(vec!["keep".into()]);
+    let explained = db.explain_query(&vec![0.0; dim], opts.clone()).unwrap();
+    // The tag filter is applied inside the index search: "untagged" is never a candidate.
+    assert_eq!(explained.candidate_multiplier, 2);
+    assert_eq!(explained.allowed_keys, Some(3));
+    assert_eq!(explained.candidates_requested, 2);
+    let status: Vec<(&str, Option<&str>, bool)> = explained
+        .candidates
+        .iter()
+        .map(|c| (c.episode.task_id.as_str(), c.rejected_by, c.returned))
+        .collect();
+    assert_eq!(
+        status,
+        vec![("near", None, true), ("low", Some("min_reward"), false)]
+    );
+    let results = db
+        .query_similar_with_options(&vec![0.0; dim], opts)
+        .unwrap();
+    assert_eq!(explained.results.len(), 1);
+    assert_eq!(explained.results[0].id, results[0].id);
+}
+
+#[test]
+fn test_adaptive_over_fetch_finds_selective_matches() {
+    let mut db = AgentMemDB::new_exact(2);
+    // 100 other tasks' episodes sit nearer the query than the two we want.
+    for i in 0..100 {
+        db.store_episode(Episode::new("other", vec![i as f32 * 0.01, 0.0], 1.0))
+            .unwrap();
+    }
+    for i in 0..2 {
+        db.store_episode(Episode::new("mine", vec![5.0 + i as f32, 0.0], 1.0))
+            .unwrap();
+    }
+    let query = [0.0, 0.0];
+    let opts = QueryOptions::new(0.0, 2).task_id_prefix("mine");
+    assert!(db
+        .query_similar_with_options(&query, opts.clone())
+        .unwrap()
+        .is_empty());
+
+    // A larger fixed multiplier reaches them...
+    let wide = opts.clone().over_fetch(60);
+    assert_eq!(
+        db.explain_query(&query, wide.clone())
+            .unwrap()
+            .candidate_multiplier,
+        60
+    );
+    assert_eq!(
+        db.query_similar_with_options(&query, wide).unwrap().len(),
+        2
+    );
+
+    // ...and adaptive mode doubles until it does.
+    let adaptive = opts.adaptive(true);
+    let explained = db.explain_query(&query, adaptive.clone()).unwrap();
+    assert_eq!(explained.candidate_multiplier, 4);
+    assert_eq!(explained.candidates_requested, 102);
+    assert_eq!(explained.results.len(), 2);
+    assert_eq!(
+        db.query_similar_with_options(&query, adaptive)
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // Exhausting the index stops the search short of top_k.
+    let missing = QueryOptions::new(0.0, 2)
+        .task_id_prefix("nobody")
+        .adaptive(true);
+    assert!(db
+        .query_similar_with_options(&query, missing)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_filters_pushed_into_index_search() {
+    for mut db in [AgentMemDB::new_exact(2), AgentMemDB::new(2)] {
+        // 2000 other users' episodes sit nearer the query than the two we want.
+        for i in 0..2000 {
+            let mut ep = Episode::with_user_id("t", vec![i as f32 * 0.001, 0.0], 1.0, "other");
+            ep.tags = Some(vec!["common".into()]);
+            db.store_episode(ep).unwrap();
+        }
+        for i in 0..2 {
+            let mut ep = Episode::with_user_id("t", vec![5.0 + i as f32, 0.0], 1.0, "me");
+            ep.tags = Some(vec!["common".into(), "rare".into()]);
+            db.store_episode(ep).unwrap();
+        }
+        let query = [0.0, 0.0];
+
+        let opts = QueryOptions::new(0.0, 2).user_id("me");
+        let explained = db.explain_query(&query, opts.clone()).unwrap();
+        assert_eq!(explained.allowed_keys, Some(2));
+        assert!(explained.candidates.iter().all(|c| c.rejected_by.is_none()));
+        let results = db.query_similar_with_options(&query, opts).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|ep| ep.user_id.as_deref() == Some("me")));
+
+        let opts = QueryOptions::new(0.0, 5).tags_all(vec!["common".into(), "rare".into()]);
+        assert_eq!(
+            db.query_similar_with_options(&query, opts).unwrap().len(),
+            2
+        );
+
+        // A broad allow-list goes through the graph search.
+        let opts = QueryOptions::new(0.0, 5).tags_any(vec!["common".into()]);
+        let results = db.query_similar_with_options(&query, opts).unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(results
+            .iter()
+            .all(|ep| ep.user_id.as_deref() == Some("other")));
+
+        let opts = QueryOptions::new(0.0, 5).source("nowhere");
+        assert!(db
+            .query_similar_with_options(&query, opts)
+            .unwrap()
+            .is_empty());
+    }
+}
+
+#[test]
+fn test_filter_bitsets_follow_updates_and_prunes() {
+    let mut db = AgentMemDB::new_exact(2);
+    let ep = Episode::with_user_id("t", vec![0.0, 0.0], 1.0, "u1");
+    db.store_episode(ep.clone()).unwrap();
+    db.store_episode(Episode::with_user_id("t", vec![1.0, 0.0], 1.0, "u2"))
+        .unwrap();
+
+    let mut moved = ep.clone();
+    moved.user_id = Some("u2".into());
+    db.update_episode(moved, 0).unwrap();
+    let by_user = |db: &AgentMemDB, user: &str| {
+        db.query_similar_with_options(&[0.0, 0.0], QueryOptions::new(0.0, 5).user_id(user))
+            .unwrap()
+            .len()
+    };
+    assert_eq!(by_user(&db, "u1"), 0);
+    assert_eq!(by_user(&db, "u2"), 2);
+
+    db.delete_where(|e| e.id == ep.id);
+    assert_eq!(by_user(&db, "u2"), 1);
+}
+
+#[test]
+fn test_tag_sub_index() {
+    let mut db = AgentMemDB::new(2);
+    for i in 0..300 {
+        let tag = if i % 3 == 0 { "plan" } else { "tool_result" };
+        let ep = Episode::with_tags("t", vec![i as f32, 0.0], 1.0, vec![tag.into()]);
+        db.store_episode(ep).unwrap();
+    }
+    // Built from the episodes already stored, then kept up to date.
+    db.add_tag_index("plan");
+    let mut late = Episode::with_tags("late", vec![0.5, 0.0], 1.0, vec!["plan".into()]);
+    late.timestamp = Some(1);
+    db.store_episode(late.clone()).unwrap();
+    assert_eq!(db.tag_indexes().collect::<Vec<_>>(), vec!["plan"]);
+
+    let query = [0.0, 0.0];
+    let plan = QueryOptions::new(0.0, 3).tags_any(vec!["plan".into()]);
+    let explained = db.explain_query(&query, plan.clone()).unwrap();
+    assert_eq!(explained.tag_index.as_deref(), Some("plan"));
+    let xs: Vec<f32> = db
+        .query_similar_with_options(&query, plan.clone())
+        .unwrap()
+        .iter()
+        .map(|ep| ep.state_embedding[0])
+        .collect();
+    assert_eq!(xs, vec![0.0, 0.5, 3.0]);
+
+    // Other filters still apply within the sub-index.
+    let recent = plan.clone().time_after(0);
+    let results = db.query_similar_with_options(&query, recent).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, late.id);
+
+    // Queries not scoped to one indexed tag use the main index.
+    let both = QueryOptions::new(0.0, 3).tags_any(vec!["plan".into(), "tool_result".into()]);
+    assert_eq!(db.explain_query(&query, both).unwrap().tag_index, None);
+    let all = QueryOptions::new(0.0, 3).tags_all(vec!["tool_result".into(), "plan".into()]);
+    assert_eq!(
+        db.explain_query(&query, all).unwrap().tag_index.as_deref(),
+        Some("plan")
+    );
+
+    // Untagging an episode removes it from scoped results; rebuilds keep the sub-index.
+    let mut untagged = late.clone();
+    untagged.tags = None;
+    db.update_episode(untagged, 0).unwrap();
+    db.prune_keep_newest(200).unwrap();
+    let results = db.query_similar_with_options(&query, plan.clone()).unwrap();
+    assert!(results.iter().all(|ep| ep.id != late.id));
+    assert!(!results.is_empty());
+    assert!(results
+        .iter()
+        .all(|ep| ep.tags == Some(vec!["plan".to_string()])));
+
+    assert!(db.remove_tag_index("plan"));
+    assert_eq!(db.explain_query(&query, plan).unwrap().tag_index, None);
+}
+
+#[test]
+fn test_user_partitions() {
+    let mut db = AgentMemDB::new(2);
+    for i in 0..500 {
+        db.store_episode(Episode::with_user_id(
+            "t",
+            vec![i as f32 * 0.001, 0.0],
+            1.0,
+            "bob",
+        ))
+        .unwrap();
+    }
+    db.set_user_partitions(true);
+    assert!(db.is_user_partitioned());
+    // Enough episodes for alice's partition to outgrow exact search.
+    for i in 0..1100 {
+        db.store_episode(Episode::with_user_id(
+            "t",
+            vec![10.0 + i as f32, 0.0],
+            1.0,
+            "alice",
+        ))
+        .unwrap();
+    }
+
+    let query = [0.0, 0.0];
+    for user in ["alice", "bob"] {
+        let opts = QueryOptions::new(0.0, 5).user_id(user);
+        let explained = db.explain_query(&query, opts.clone()).unwrap();
+        assert!(explained.user_partition);
+        // Only the user's own vectors are ever candidates.
+        assert!(explained
+            .candidates
+            .iter()
+            .all(|c| c.episode.user_id.as_deref() == Some(user)));
+        assert_eq!(
+            db.query_similar_with_options(&query, opts).unwrap().len(),
+            5
+        );
+    }
+    let nobody = QueryOptions::new(0.0, 5).user_id("carol");
+    let explained = db.explain_query(&query, nobody).unwrap();
+    assert_eq!(explained.candidates_requested, 0);
+    assert!(explained.results.is_empty());
+
+    // A moved episode leaves its old partition, also across rebuilds.
+    let mut moved = db
+        .query_similar_with_options(&query, QueryOptions::new(0.0, 1).user_id("bob"))
+        .unwrap()
+        .remove(0);
+    moved.user_id = Some("alice".into());
+    let version = moved.version;
+    db.update_episode(moved.clone(), version).unwrap();
+    let alice = QueryOptions::new(0.0, 1).user_id("alice");
+    assert_eq!(
+        db.query_similar_with_options(&query, alice.clone())
+            .unwrap()[0]
+            .id,
+        moved.id
+    );
+    db.prune_keep_newest(1000).unwrap();
+    let bob = QueryOptions::new(0.0, 1000).user_id("bob");
+    assert!(db
+        .query_similar_with_options(&query, bob)
+        .unwrap()
+        .iter()
+        .all(|ep| ep.id != moved.id));
+
+    db.set_user_partitions(false);
+    assert!(!db.explain_query(&query, alice).unwrap().user_partition);
+}
+
 #[test]
 fn test_prune_keep_highest_reward() {
     let dim = 8;
@@ -189,7 +1130,7 @@ fn test_prune_keep_highest_reward() {
     db.store_episode(make_episode(dim, 0.9)).unwrap();
     db.store_episode(make_episode(dim, 0.5)).unwrap();
 
-    let removed = db.prune_keep_highest_reward(2);
+    let removed = db.prune_keep_highest_reward(2).unwrap();
     assert_eq!(removed, 1);
     let query = vec![0.1; dim];
     let results = db.query_similar(&query, 0.0, 5).unwrap();
@@ -223,3 +1164,226 @@ fn test_dimension_mismatch() {
         _ => panic!("Expected DimensionMismatch error"),
     }
 }
+
+#[test]
+fn test_len_and_iter() {
+    let dim = 8;
+    let mut db = AgentMemDB::new(dim);
+    assert!(db.is_empty());
+    db.store_episode(make_episode(dim, 0.1)).unwrap();
+    db.store_episode(make_episode(dim, 0.2)).unwrap();
+    assert_eq!(db.len(), 2);
+    let mut rewards: Vec<f32> = db.iter().map(|e| e.reward).collect();
+    rewards.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(rewards, vec![0.1, 0.2]);
+}
+
+#[test]
+fn test_soft_delete_restore_purge() {
+    let mut db = AgentMemDB::new_exact(2);
+    let a = Episode::new("a", vec![0.0, 0.0], 0.5);
+    let b = Episode::new("b", vec![1.0, 0.0], 0.5);
+    db.store_episodes(vec![a.clone(), b.clone()]).unwrap();
+
+    db.soft_delete(a.id).unwrap();
+    let hits = db.query_similar(&[0.0, 0.0], 0.0, 5).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, b.id);
+    let with_deleted = QueryOptions::new(0.0, 5).include_deleted(true);
+    assert_eq!(
+        db.query_similar_with_options(&[0.0, 0.0], with_deleted)
+            .unwrap()
+            .len(),
+        2
+    );
+    let explained = db
+        .explain_query(&[0.0, 0.0], QueryOptions::new(0.0, 5))
+        .unwrap();
+    assert_eq!(explained.candidates[0].rejected_by, Some("deleted"));
+    assert_eq!(db.len(), 2);
+
+    // Kept across save/load.
+    let path = std::env::temp_dir().join("agent_mem_db_soft_delete_test.json");
+    db.save_to_file(&path).unwrap();
+    let mut db = AgentMemDB::load_from_file_exact(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(db.query_similar(&[0.0, 0.0], 0.0, 5).unwrap().len(), 1);
+
+    db.restore(a.id).unwrap();
+    assert_eq!(db.query_similar(&[0.0, 0.0], 0.0, 5).unwrap().len(), 2);
+    assert_eq!(db.iter().find(|e| e.id == a.id).unwrap().version, 2);
+
+    db.soft_delete(b.id).unwrap();
+    assert_eq!(db.purge_deleted(), 1);
+    assert_eq!(db.len(), 1);
+    assert!(matches!(db.restore(b.id), Err(AgentMemError::NotFound)));
+}
+
+#[test]
+fn test_store_policy() {
+    let base = Episode::with_tags("loop", vec![1.0, 0.0], 0.2, vec!["a".to_string()]);
+    let mut near = Episode::with_tags("loop", vec![1.0, 0.005], 0.8, vec!["b".to_string()]);
+    near.timestamp = Some(500);
+    let other_task = Episode::new("other", vec![1.0, 0.0], 0.5);
+    let far = Episode::new("loop", vec![0.0, 1.0], 0.5);
+
+    // Reject: the DB is unchanged, including for batches.
+    let mut db = AgentMemDB::new_exact(2);
+    db.set_store_policy(Some(StorePolicy::reject(0.01)));
+    db.store_episodes(vec![base.clone(), other_task.clone(), far.clone()])
+        .unwrap();
+    assert!(matches!(
+        db.store_episode(near.clone()),
+        Err(AgentMemError::Duplicate { existing }) if existing == base.id
+    ));
+    let batch = vec![Episode::new("new", vec![5.0, 5.0], 1.0), near.clone()];
+    assert!(db.store_episodes(batch).is_err());
+    let in_batch = vec![
+        Episode::new("pair", vec![9.0, 9.0], 1.0),
+        Episode::new("pair", vec![9.0, 9.0], 1.0),
+    ];
+    assert!(db.store_episodes(in_batch).is_err());
+    assert_eq!(db.len(), 3);
+    // Re-storing the same id is an explicit replacement.
+    db.store_episode(base.clone()).unwrap();
+    assert_eq!(db.len(), 3);
+
+    // Replace: the older episode goes away.
+    let mut db = AgentMemDB::new(2);
+    db.set_store_policy(Some(StorePolicy::replace(0.01)));
+    db.store_episodes(vec![base.clone(), other_task.clone()])
+        .unwrap();
+    db.store_episode(near.clone()).unwrap();
+    assert_eq!(db.len(), 2);
+    let hits = db.query_similar(&[1.0, 0.0], 0.0, 5).unwrap();
+    let ids: Vec<Uuid> = hits.iter().map(|e| e.id).collect();
+    assert!(ids.contains(&near.id) && !ids.contains(&base.id));
+    assert_eq!(hits.len(), 2);
+
+    // Merge: the stored episode absorbs the new one.
+    let mut db = AgentMemDB::new_exact(2);
+    db.set_store_policy(Some(StorePolicy::merge(0.01)));
+    db.store_episode(base.clone()).unwrap();
+    db.store_episode(near).unwrap();
+    assert_eq!(db.len(), 1);
+    let merged = db.iter().next().unwrap();
+    assert_eq!(merged.id, base.id);
+    assert_eq!(merged.reward, 0.5);
+    assert_eq!(merged.tags, Some(vec!["a".to_string(), "b".to_string()]));
+    assert_eq!(merged.timestamp, Some(500));
+    assert_eq!(merged.version, 1);
+    let tagged = QueryOptions::new(0.0, 5).tags_any(vec!["b".to_string()]);
+    assert_eq!(
+        db.query_similar_with_options(&[1.0, 0.0], tagged)
+            .unwrap()
+            .len(),
+        1
+    );
+    db.store_episode(far).unwrap();
+    assert_eq!(db.len(), 2);
+}
+
+#[test]
+fn test_episode_limits() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.set_limits(
+        EpisodeLimits::new()
+            .max_metadata_bytes(32)
+            .max_steps(2)
+            .max_tags(2)
+            .max_task_id_len(8),
+    );
+    assert_eq!(db.limits().max_steps, Some(2));
+
+    let ok = Episode::with_tags("task", vec![0.0, 1.0], 1.0, vec!["a".to_string()]);
+    db.store_episode(ok.clone()).unwrap();
+
+    let long_task = Episode::new("a-very-long-task-id", vec![0.0, 1.0], 1.0);
+    assert!(matches!(
+        db.store_episode(long_task),
+        Err(AgentMemError::LimitExceeded {
+            limit: "max_task_id_len",
+            max: 8,
+            actual: 19
+        })
+    ));
+    let tags = (0..3).map(|i| i.to_string()).collect();
+    assert!(matches!(
+        db.store_episode(Episode::with_tags("t", vec![0.0, 1.0], 1.0, tags)),
+        Err(AgentMemError::LimitExceeded {
+            limit: "max_tags",
+            actual: 3,
+            ..
+        })
+    ));
+    let step = EpisodeStep {
+        index: 0,
+        action: "a".to_string(),
+        observation: "o".to_string(),
+        step_reward: 0.0,
+    };
+    let mut steps = Episode::new("t", vec![0.0, 1.0], 1.0);
+    steps.steps = Some(vec![step; 3]);
+    assert!(matches!(
+        db.store_episode(steps),
+        Err(AgentMemError::LimitExceeded {
+            limit: "max_steps",
+            ..
+        })
+    ));
+    let mut big = Episode::new("t", vec![0.0, 1.0], 1.0);
+    big.metadata = json!({"observation": "x".repeat(100)});
+    let err = db.store_episode(big.clone()).unwrap_err();
+    assert!(err.to_string().contains("max_metadata_bytes"));
+
+    // A batch with one episode over a limit is rejected whole, and updates are checked too.
+    let batch = vec![Episode::new("t", vec![1.0, 0.0], 1.0), big.clone()];
+    assert!(db.store_episodes(batch).is_err());
+    big.id = ok.id;
+    assert!(db.update_episode(big, 0).is_err());
+    assert_eq!(db.len(), 1);
+    assert_eq!(db.iter().next().unwrap().version, 0);
+
+    db.set_limits(EpisodeLimits::default());
+    let mut big = Episode::new("t", vec![0.0, 1.0], 1.0);
+    big.metadata = json!({"observation": "x".repeat(100)});
+    db.store_episode(big).unwrap();
+    assert_eq!(db.len(), 2);
+}
+
+#[test]
+fn test_query_similar_shared() {
+    let mut db = AgentMemDB::new_exact(2);
+    let mut near = Episode::new("near", vec![0.0, 1.0], 1.0);
+    near.metadata = json!({"observation": "large"});
+    db.store_episode(near.clone()).unwrap();
+    db.store_episode(Episode::new("far", vec![5.0, 5.0], 1.0))
+        .unwrap();
+    db.store_episode(Episode::new("low", vec![0.0, 1.1], -1.0))
+        .unwrap();
+
+    let opts = QueryOptions::new(0.0, 5).include_embeddings(false);
+    let copied = db
+        .query_similar_with_options(&[0.0, 1.0], opts.clone())
+        .unwrap();
+    let shared = db.query_similar_shared(&[0.0, 1.0], opts.clone()).unwrap();
+    let ids: Vec<Uuid> = shared.iter().map(|ep| ep.id).collect();
+    assert_eq!(ids, copied.iter().map(|ep| ep.id).collect::<Vec<_>>());
+    assert_eq!(ids, vec![near.id, shared[1].id]);
+    // Copies drop the embedding as asked; shared episodes are the stored ones, whole.
+    assert!(copied[0].state_embedding.is_empty());
+    assert_eq!(shared[0].state_embedding, vec![0.0, 1.0]);
+    let again = db.query_similar_shared(&[0.0, 1.0], opts.clone()).unwrap();
+    assert!(Arc::ptr_eq(&shared[0], &again[0]));
+
+    // An update replaces the stored episode; results already returned keep the old one.
+    near.reward = 0.5;
+    db.update_episode(near, 0).unwrap();
+    assert_eq!(shared[0].reward, 1.0);
+    let fresh = db.query_similar_shared(&[0.0, 1.0], opts.clone()).unwrap();
+    assert_eq!((fresh[0].reward, fresh[0].version), (0.5, 1));
+
+    let snapshot = db.clone_snapshot();
+    let from_snapshot = snapshot.query_similar_shared(&[0.0, 1.0], opts).unwrap();
+    assert!(Arc::ptr_eq(&from_snapshot[0], &fresh[0]));
+}
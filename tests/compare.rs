@@ -0,0 +1,62 @@
+use agent_mem_db::{compare_backends, BackendConfig, Episode, HnswParams};
+
+fn synthetic(n: usize, dim: usize) -> Vec<Episode> {
+    (0..n)
+        .map(|i| {
+            let v = (0..dim)
+                .map(|d| ((i * 7919 + d * 104_729) % 1_009) as f32 / 1_009.0)
+                .collect();
+            Episode::new(format!("t{i}"), v, 1.0)
+        })
+        .collect()
+}
+
+#[test]
+fn test_compare_backends_reports_each_config() {
+    let episodes = synthetic(300, 8);
+    let queries: Vec<Vec<f32>> = synthetic(20, 8)
+        .into_iter()
+        .map(|ep| ep.state_embedding.iter().map(|x| x + 0.013).collect())
+        .collect();
+    let small = HnswParams {
+        m: 4,
+        ef_construction: 16,
+        ef_search: 8,
+    };
+    let configs = [
+        BackendConfig::Exact,
+        BackendConfig::Hnsw(HnswParams::default()),
+        BackendConfig::Hnsw(small),
+    ];
+    let report = compare_backends(&episodes, &queries, 5, &configs).unwrap();
+    assert_eq!(
+        (report.episodes, report.queries, report.top_k),
+        (300, 20, 5)
+    );
+    assert_eq!(report.results.len(), 3);
+
+    let exact = &report.results[0];
+    assert_eq!(exact.config, BackendConfig::Exact);
+    assert_eq!(exact.recall_at_k, 1.0);
+    assert_eq!(exact.index_bytes, 300 * 8 * 4);
+    assert!(exact.query_p50 <= exact.query_p99 && exact.query_p99 <= exact.query_max);
+
+    for result in &report.results[1..] {
+        assert!((0.0..=1.0).contains(&result.recall_at_k));
+        assert!(result.index_bytes > exact.index_bytes);
+    }
+    assert!(report.results[1].index_bytes > report.results[2].index_bytes);
+    assert_eq!(
+        report.results[2].config.to_string(),
+        "hnsw(m=4, ef_construction=16, ef_search=8)"
+    );
+}
+
+#[test]
+fn test_compare_backends_rejects_empty_input() {
+    assert!(compare_backends(&[], &[vec![0.0]], 5, &[BackendConfig::Exact]).is_err());
+    let report = compare_backends(&synthetic(3, 2), &[], 5, &[BackendConfig::Exact]).unwrap();
+    assert_eq!(report.results[0].recall_at_k, 1.0);
+    // A query of the wrong dimension is an error, not a zero-recall result.
+    assert!(compare_backends(&synthetic(3, 2), &[vec![0.0]], 5, &[BackendConfig::Exact]).is_err());
+}
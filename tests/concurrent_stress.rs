@@ -32,6 +32,14 @@ fn make_episode(seed: u64, reward: f32) -> Episode {
         tags: None,
         source: None,
         user_id: None,
+        session_id: None,
+        version: 0,
+        deleted: false,
+        times_retrieved: 0,
+        last_retrieved_at: None,
+        cost: None,
+        duration_ms: None,
+        embedding_model: None,
     }
 }
 
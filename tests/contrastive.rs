@@ -0,0 +1,57 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, QueryOptions};
+use std::fs;
+
+fn task_ids(episodes: &[Episode]) -> Vec<&str> {
+    episodes.iter().map(|ep| ep.task_id.as_str()).collect()
+}
+
+#[test]
+fn test_contrastive_recall_splits_by_reward() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(vec![
+        Episode::new("win near", vec![1.0, 0.0], 1.0),
+        Episode::new("win far", vec![0.0, 1.0], 0.5),
+        Episode::new("zero near", vec![0.9, 0.0], 0.0),
+        Episode::new("loss far", vec![0.0, 2.0], -1.0),
+        Episode::new("win other", vec![5.0, 5.0], 1.0),
+    ])
+    .unwrap();
+
+    // Zero counts as a failure; negative rewards aren't filtered out.
+    let recall = db.contrastive_recall(&[1.0, 0.0], 2).unwrap();
+    assert_eq!(task_ids(&recall.high_reward), vec!["win near", "win far"]);
+    assert_eq!(task_ids(&recall.low_reward), vec!["zero near", "loss far"]);
+
+    // Another threshold; filters apply to both lists, reward bounds are replaced.
+    let opts = QueryOptions::new(100.0, 5).max_reward(-5.0);
+    let recall = db
+        .contrastive_recall_with_options(&[1.0, 0.0], 0.5, opts.clone())
+        .unwrap();
+    assert_eq!(task_ids(&recall.high_reward), vec!["win near", "win other"]);
+    assert_eq!(recall.low_reward.len(), 3);
+    let wins = opts.task_id_prefix("win");
+    let recall = db
+        .contrastive_recall_with_options(&[1.0, 0.0], 0.5, wins)
+        .unwrap();
+    assert_eq!(task_ids(&recall.low_reward), vec!["win far"]);
+
+    assert!(matches!(
+        db.contrastive_recall(&[1.0; 3], 2),
+        Err(AgentMemError::DimensionMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_disk_contrastive_recall() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_contrastive_test");
+    let _ = fs::remove_dir_all(&dir);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    db.store_episode(Episode::new("win", vec![1.0, 0.0], 1.0))
+        .unwrap();
+    db.store_episode(Episode::new("loss", vec![1.0, 0.1], 0.0))
+        .unwrap();
+    let recall = db.contrastive_recall(&[1.0, 0.0], 5).unwrap();
+    assert_eq!(task_ids(&recall.high_reward), vec!["win"]);
+    assert_eq!(task_ids(&recall.low_reward), vec!["loss"]);
+    let _ = fs::remove_dir_all(&dir);
+}
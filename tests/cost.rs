@@ -0,0 +1,68 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, CostSummary, DiskOptions, Episode, QueryOptions};
+use std::fs;
+
+fn episodes() -> Vec<Episode> {
+    let mut slow = Episode::with_cost("cheap slow", vec![1.0, 0.0], 1.0, 0.01);
+    slow.duration_ms = Some(9_000);
+    let mut fast = Episode::with_cost("pricey fast", vec![0.9, 0.0], 1.0, 2.0);
+    fast.duration_ms = Some(500);
+    vec![
+        slow,
+        fast,
+        Episode::with_cost("cheap failure", vec![1.0, 0.1], 0.0, 0.01),
+        Episode::with_duration_ms("untracked cost", vec![1.0, 0.0], 1.0, 100),
+    ]
+}
+
+#[test]
+fn test_cost_and_duration_filters() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+
+    // Cheap successful episodes similar to this state; unknown costs don't count as cheap.
+    let hits = db
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(1.0, 5).max_cost(0.5))
+        .unwrap();
+    let tasks: Vec<&str> = hits.iter().map(|ep| ep.task_id.as_str()).collect();
+    assert_eq!(tasks, vec!["cheap slow"]);
+    assert_eq!(hits[0].cost, Some(0.01));
+
+    let hits = db
+        .query_similar_with_options(
+            &[1.0, 0.0],
+            QueryOptions::new(1.0, 5).max_duration_ms(1_000),
+        )
+        .unwrap();
+    let mut tasks: Vec<&str> = hits.iter().map(|ep| ep.task_id.as_str()).collect();
+    tasks.sort_unstable();
+    assert_eq!(tasks, vec!["pricey fast", "untracked cost"]);
+
+    let explained = db
+        .explain_query(&[1.0, 0.0], QueryOptions::new(0.0, 5).max_cost(0.5))
+        .unwrap();
+    let rejected = explained
+        .candidates
+        .iter()
+        .find(|c| c.episode.task_id == "untracked cost")
+        .unwrap();
+    assert_eq!(rejected.rejected_by, Some("max_cost"));
+}
+
+#[test]
+fn test_cost_summary_and_persistence() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_cost_test");
+    let _ = fs::remove_dir_all(&dir);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        assert_eq!(db.cost_summary(), CostSummary::default());
+        db.store_episodes(episodes()).unwrap();
+    }
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    let summary = db.cost_summary();
+    assert_eq!((summary.with_cost, summary.with_duration), (3, 3));
+    assert!((summary.total_cost - 2.02).abs() < 1e-6);
+    assert!((summary.mean_cost.unwrap() - 2.02 / 3.0).abs() < 1e-6);
+    assert_eq!(summary.total_duration_ms, 9_600);
+    assert_eq!(summary.mean_duration_ms, Some(3_200.0));
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,62 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, DiskOptions, Episode};
+use std::fs;
+
+#[test]
+fn test_diff_in_memory() {
+    let mut left = AgentMemDB::new_exact(2);
+    let same = Episode::new("same", vec![0.0, 1.0], 0.5);
+    let mut changed = Episode::new("changed", vec![1.0, 0.0], 0.5);
+    let gone = Episode::new("gone", vec![1.0, 1.0], 0.5);
+    left.store_episodes(vec![same.clone(), changed.clone(), gone.clone()])
+        .unwrap();
+
+    let mut right = AgentMemDB::new_exact(2);
+    right.store_episode(same).unwrap();
+    changed.reward = 0.9;
+    changed.tags = Some(vec!["fixed".to_string()]);
+    right.store_episode(changed.clone()).unwrap();
+    let added = Episode::new("added", vec![0.5, 0.5], 0.1);
+    right.store_episode(added.clone()).unwrap();
+
+    let diff = left.diff(&right);
+    assert!(!diff.is_empty());
+    assert_eq!(diff.only_in_left.len(), 1);
+    assert_eq!(diff.only_in_left[0].id, gone.id);
+    assert_eq!(diff.only_in_right.len(), 1);
+    assert_eq!(diff.only_in_right[0].id, added.id);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].left.reward, 0.5);
+    assert_eq!(diff.changed[0].right.id, changed.id);
+    assert_eq!(diff.changed[0].fields, ["reward", "tags"]);
+
+    assert!(left.diff(&left).is_empty());
+    let reverse = right.diff(&left);
+    assert_eq!(reverse.only_in_left[0].id, added.id);
+}
+
+#[test]
+fn test_diff_disk_against_file() {
+    let dir = std::env::temp_dir().join("agent_mem_diff_disk");
+    let _ = fs::remove_dir_all(&dir);
+    let mut mem = AgentMemDB::new_exact(3);
+    let mut disk = AgentMemDBDisk::open_with_options(dir.join("a"), DiskOptions::exact(3)).unwrap();
+    for i in 0..10 {
+        let ep = Episode::with_timestamp(format!("t{i}"), vec![i as f32; 3], 0.5, i);
+        disk.store_episode(ep.clone()).unwrap();
+        mem.store_episode(ep).unwrap();
+    }
+    let path = dir.join("backup.json");
+    mem.save_to_file(&path).unwrap();
+    let backup = AgentMemDB::load_from_file(&path).unwrap();
+    assert!(agent_mem_db::DbDiff::between(disk.iter(), backup.iter()).is_empty());
+
+    let mut replica =
+        AgentMemDBDisk::open_with_options(dir.join("b"), DiskOptions::exact(3)).unwrap();
+    for ep in disk.iter().filter(|ep| ep.timestamp >= Some(5)) {
+        replica.store_episode(ep.clone()).unwrap();
+    }
+    let diff = disk.diff(&replica);
+    assert_eq!(diff.only_in_left.len(), 5);
+    assert!(diff.only_in_right.is_empty() && diff.changed.is_empty());
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -1,4 +1,7 @@
-use agent_mem_db::{AgentMemDBDisk, DiskOptions, Episode};
+use agent_mem_db::{
+    AgentMemDBDisk, AgentMemError, DiskOptions, Episode, EpisodeLimits, HnswParams, QueryOptions,
+    FORMAT_VERSION,
+};
 use serde_json::json;
 use std::fs;
 use uuid::Uuid;
@@ -15,6 +18,14 @@ fn make_episode(dim: usize, reward: f32) -> Episode {
         tags: None,
         source: None,
         user_id: None,
+        session_id: None,
+        version: 0,
+        deleted: false,
+        times_retrieved: 0,
+        last_retrieved_at: None,
+        cost: None,
+        duration_ms: None,
+        embedding_model: None,
     }
 }
 
@@ -128,6 +139,125 @@ fn test_disk_prune_keep_highest_reward() {
     assert!(!rewards.contains(&0.3));
 }
 
+#[test]
+fn test_disk_delete_where() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_delete_where_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 8;
+
+    {
+        let mut db = AgentMemDBDisk::open(&dir, dim).unwrap();
+        db.store_episode(Episode::new("a", vec![0.1; dim], 0.3))
+            .unwrap();
+        db.store_episode(Episode::new("b", vec![0.1; dim], 0.9))
+            .unwrap();
+        db.store_episode(Episode::new("c", vec![0.1; dim], 0.5))
+            .unwrap();
+        let removed = db.delete_where(|ep| ep.task_id != "b").unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(db.delete_where(|ep| ep.task_id == "a").unwrap(), 0);
+    }
+
+    let db2 = AgentMemDBDisk::open(&dir, dim).unwrap();
+    let results = db2.query_similar(&vec![0.1; dim], 0.0, 5).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].task_id, "b");
+}
+
+#[test]
+fn test_disk_explain_query() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_explain_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+
+    let mut db = AgentMemDBDisk::open(&dir, dim).unwrap();
+    db.store_episode(Episode::with_user_id("a", vec![0.0; dim], 0.9, "u1"))
+        .unwrap();
+    db.store_episode(Episode::with_user_id("b", vec![0.1; dim], 0.9, "u2"))
+        .unwrap();
+    db.store_episode(Episode::with_user_id("c", vec![0.2; dim], 0.9, "u1"))
+        .unwrap();
+
+    let opts = QueryOptions::new(0.0, 2).task_id_prefix("a").user_id("u1");
+    let explained = db.explain_query(&vec![0.0; dim], opts.clone()).unwrap();
+    assert_eq!(explained.candidate_multiplier, 4);
+    // The user_id filter is applied inside the index search; "b" is never a candidate.
+    assert_eq!(explained.allowed_keys, Some(2));
+    assert_eq!(explained.candidates.len(), 2);
+    let rejected: Vec<Option<&str>> = explained.candidates.iter().map(|c| c.rejected_by).collect();
+    assert_eq!(rejected, vec![None, Some("task_id_prefix")]);
+    let results = db
+        .query_similar_with_options(&vec![0.0; dim], opts)
+        .unwrap();
+    let explained_ids: Vec<_> = explained.results.iter().map(|e| e.id).collect();
+    let ids: Vec<_> = results.iter().map(|e| e.id).collect();
+    assert_eq!(explained_ids, ids);
+
+    // The filter bitsets are rebuilt when the log is replayed.
+    drop(db);
+    let db = AgentMemDBDisk::open(&dir, dim).unwrap();
+    let opts = QueryOptions::new(0.0, 5).user_id("u1");
+    let explained = db.explain_query(&vec![0.0; dim], opts).unwrap();
+    assert_eq!(explained.allowed_keys, Some(2));
+    assert_eq!(explained.results.len(), 2);
+}
+
+#[test]
+fn test_disk_deterministic_order() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_deterministic_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    let mut ids = Vec::new();
+    for _ in 0..5 {
+        let ep = make_episode(dim, 0.5);
+        ids.push(ep.id);
+        db.store_episode(ep).unwrap();
+    }
+    ids.sort();
+
+    let opts = QueryOptions::new(0.0, 3).deterministic_order(true);
+    let results = db
+        .query_similar_with_options(&vec![0.1; dim], opts.clone())
+        .unwrap();
+    let got: Vec<Uuid> = results.iter().map(|e| e.id).collect();
+    assert_eq!(got, ids[..3]);
+    let explained = db.explain_query(&vec![0.1; dim], opts).unwrap();
+    let explained_ids: Vec<Uuid> = explained.results.iter().map(|e| e.id).collect();
+    assert_eq!(explained_ids, got);
+    assert_eq!(
+        explained.candidates.iter().filter(|c| c.returned).count(),
+        3
+    );
+}
+
+#[test]
+fn test_disk_hnsw_params_persisted() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_hnsw_params_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+    let params = HnswParams {
+        m: 8,
+        ef_construction: 64,
+        ef_search: 48,
+    };
+
+    {
+        let opts = DiskOptions::hnsw(dim, 1000).with_hnsw_params(params);
+        let mut db = AgentMemDBDisk::open_with_options(&dir, opts).unwrap();
+        db.store_episode(make_episode(dim, 0.5)).unwrap();
+    }
+
+    let meta: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("meta.json")).unwrap()).unwrap();
+    assert_eq!(meta["hnsw_params"]["m"], 8);
+    assert_eq!(meta["hnsw_params"]["ef_search"], 48);
+
+    // Reopening with default options keeps the stored parameters and data.
+    let db = AgentMemDBDisk::open(&dir, dim).unwrap();
+    assert_eq!(db.query_similar(&vec![0.1; dim], 0.0, 5).unwrap().len(), 1);
+}
+
 #[test]
 fn test_disk_checkpoint_fast_restart() {
     let dir = std::env::temp_dir().join("agent_mem_db_disk_checkpoint_test");
@@ -152,3 +282,468 @@ fn test_disk_checkpoint_fast_restart() {
     assert!(dir.join("exact_checkpoint.json").exists());
     assert!(dir.join("meta.json").exists());
 }
+
+#[test]
+fn test_disk_open_existing() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_open_existing_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+
+    assert!(AgentMemDBDisk::open_existing(&dir).is_err());
+    assert!(!dir.exists());
+
+    {
+        let mut db = AgentMemDBDisk::open(&dir, dim).unwrap();
+        db.store_episode(make_episode(dim, 0.9)).unwrap();
+    }
+    let db = AgentMemDBDisk::open_existing(&dir).unwrap();
+    assert_eq!(db.dim(), dim);
+    assert_eq!(db.len(), 1);
+}
+
+#[test]
+fn test_disk_store_episodes_batch_is_atomic() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_batch_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+    let log = dir.join("episodes.jsonl");
+
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    let bad = vec![make_episode(dim, 0.5), make_episode(dim + 1, 0.5)];
+    assert!(db.store_episodes(bad).is_err());
+    assert!(db.is_empty());
+    assert_eq!(fs::read_to_string(&log).unwrap(), "");
+
+    let batch: Vec<Episode> = (0..3).map(|_| make_episode(dim, 0.5)).collect();
+    db.store_episodes(batch).unwrap();
+    assert_eq!(db.len(), 3);
+    // Begin marker, three episodes, commit marker.
+    let text = fs::read_to_string(&log).unwrap();
+    assert_eq!(text.lines().count(), 5);
+    assert!(text.lines().next().unwrap().starts_with("{\"batch_begin\""));
+    drop(db);
+
+    // A crash mid-batch: begin marker, one episode, and a torn line.
+    let committed_len = text.len() as u64;
+    let torn = text.lines().take(2).collect::<Vec<_>>().join("\n") + "\n{\"id\":\"3f2a";
+    fs::write(&log, text.clone() + &torn).unwrap();
+
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    assert_eq!(db.len(), 3);
+    assert_eq!(fs::metadata(&log).unwrap().len(), committed_len);
+    db.store_episode(make_episode(dim, 0.9)).unwrap();
+    drop(db);
+
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    assert_eq!(db.len(), 4);
+}
+
+#[test]
+fn test_disk_update_episode_survives_reopen() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_update_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    let ep = make_episode(dim, 0.5);
+    db.store_episode(ep.clone()).unwrap();
+    db.store_episode(make_episode(dim, 0.5)).unwrap();
+    let mut updated = ep.clone();
+    updated.reward = 0.8;
+    updated.state_embedding = vec![0.9; dim];
+    assert_eq!(db.update_episode(updated.clone(), 0).unwrap(), 1);
+    assert!(matches!(
+        db.update_episode(updated, 0),
+        Err(agent_mem_db::AgentMemError::Conflict { actual: 1, .. })
+    ));
+    drop(db);
+
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    assert_eq!(db.len(), 2);
+    let stored = db.iter().find(|e| e.id == ep.id).unwrap();
+    assert_eq!((stored.reward, stored.version), (0.8, 1));
+    let hits = db.query_similar(&[0.9; 4], 0.0, 5).unwrap();
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].id, ep.id);
+}
+
+#[test]
+fn test_disk_soft_delete_survives_reopen() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_soft_delete_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    let ep = make_episode(dim, 0.5);
+    db.store_episode(ep.clone()).unwrap();
+    db.store_episode(make_episode(dim, 0.5)).unwrap();
+    db.soft_delete(ep.id).unwrap();
+    assert_eq!(db.query_similar(&[0.1; 4], 0.0, 5).unwrap().len(), 1);
+    drop(db);
+
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    assert_eq!(db.len(), 2);
+    assert_eq!(db.query_similar(&[0.1; 4], 0.0, 5).unwrap().len(), 1);
+    assert_eq!(db.purge_deleted().unwrap(), 1);
+    drop(db);
+
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    assert_eq!(db.len(), 1);
+    assert!(db.iter().all(|e| e.id != ep.id));
+}
+
+#[test]
+fn test_disk_vacuum() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_vacuum_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+
+    let mut db =
+        AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact_with_checkpoint(dim)).unwrap();
+    let ep = make_episode(dim, 0.5);
+    db.store_episode(ep.clone()).unwrap();
+    db.store_episodes(vec![make_episode(dim, 0.6), make_episode(dim, 0.7)])
+        .unwrap();
+    db.update_episode(ep.clone(), 0).unwrap();
+    db.soft_delete(ep.id).unwrap();
+    fs::write(dir.join("episodes.jsonl.tmp"), "left by a crash").unwrap();
+
+    // 3 versions of `ep` + 2 batch markers + 2 batch episodes, down to 3 lines.
+    let report = db.vacuum().unwrap();
+    assert_eq!(report.records_removed, 4);
+    let log = fs::read_to_string(dir.join("episodes.jsonl")).unwrap();
+    assert_eq!(log.lines().count(), 3);
+    assert!(!dir.join("episodes.jsonl.tmp").exists());
+    assert!(dir.join("exact_checkpoint.json").exists());
+    assert_eq!(db.len(), 3);
+    assert_eq!(db.query_similar(&[0.1; 4], 0.0, 5).unwrap().len(), 2);
+
+    db.store_episode(make_episode(dim, 0.8)).unwrap();
+    drop(db);
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    assert_eq!(db.len(), 4);
+    let stored = db.iter().find(|e| e.id == ep.id).unwrap();
+    assert!(stored.deleted);
+    assert_eq!(stored.version, 2);
+
+    // Nothing left to reclaim.
+    let mut db = db;
+    assert_eq!(db.vacuum().unwrap().records_removed, 0);
+    assert!(!dir.join("exact_checkpoint.json").exists());
+}
+
+#[test]
+fn test_disk_verify() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_verify_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+
+    let mut db =
+        AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact_with_checkpoint(dim)).unwrap();
+    let ep = make_episode(dim, 0.5);
+    db.store_episode(ep.clone()).unwrap();
+    db.store_episode(make_episode(dim, 0.6)).unwrap();
+    db.update_episode(ep, 0).unwrap();
+    let report = db.verify().unwrap();
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(
+        (report.log_records, report.episodes, report.index_len),
+        (3, 2, 3)
+    );
+
+    db.vacuum().unwrap();
+    assert!(db.verify().unwrap().is_ok());
+    drop(db);
+
+    // A checkpoint that lost an episode is loaded on open, and verify catches it.
+    let cp_path = dir.join("exact_checkpoint.json");
+    let mut cp: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&cp_path).unwrap()).unwrap();
+    cp["episodes"].as_array_mut().unwrap().pop();
+    fs::write(&cp_path, cp.to_string()).unwrap();
+    let db = AgentMemDBDisk::open_existing(&dir).unwrap();
+    let report = db.verify().unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.episodes, 1);
+    assert!(report
+        .problems
+        .iter()
+        .any(|p| p.contains("checkpoint holds 1")));
+}
+
+#[test]
+fn test_disk_auto_checkpoint() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_auto_checkpoint_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+    let cp_path = dir.join("exact_checkpoint.json");
+
+    let opts = DiskOptions::exact_with_checkpoint(dim).auto_checkpoint(3, 0);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, opts.clone()).unwrap();
+    let ep = make_episode(dim, 0.5);
+    db.store_episode(ep.clone()).unwrap();
+    db.update_episode(ep, 0).unwrap();
+    assert!(!cp_path.exists());
+    // The batch brings the count to 4, and its markers don't stop the checkpoint.
+    db.store_episodes(vec![make_episode(dim, 0.6), make_episode(dim, 0.7)])
+        .unwrap();
+    assert!(cp_path.exists());
+    drop(db);
+
+    let db = AgentMemDBDisk::open_with_options(&dir, opts).unwrap();
+    assert_eq!(db.len(), 3);
+    assert!(db.verify().unwrap().is_ok());
+    drop(db);
+
+    // Time-based: the first write after `every_ms` checkpoints.
+    let opts = DiskOptions::exact_with_checkpoint(dim).auto_checkpoint(0, 1);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, opts).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    db.store_episode(make_episode(dim, 0.8)).unwrap();
+    let data = fs::read_to_string(&cp_path).unwrap();
+    assert_eq!(data.matches("test_task").count(), 4);
+}
+
+#[test]
+fn test_disk_group_commit() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_group_commit_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+    let log_path = dir.join("episodes.jsonl");
+    let log_lines = || fs::read_to_string(&log_path).unwrap().lines().count();
+
+    let opts = DiskOptions::exact(dim).group_commit(60_000, 3);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, opts.clone()).unwrap();
+    let ep = make_episode(dim, 0.5);
+    db.store_episode(ep.clone()).unwrap();
+    db.update_episode(ep.clone(), 0).unwrap();
+    assert_eq!(log_lines(), 0);
+    // Buffered writes are visible and accounted for.
+    assert_eq!(db.query_similar(&[0.1; 4], 0.0, 5).unwrap().len(), 1);
+    assert!(db.verify().unwrap().is_ok());
+
+    // The third write fills the group: one batch, begin and commit markers included.
+    db.store_episode(make_episode(dim, 0.6)).unwrap();
+    assert_eq!(log_lines(), 5);
+
+    // A batch write commits what is pending first; dropping commits the rest.
+    db.soft_delete(ep.id).unwrap();
+    db.store_episodes(vec![make_episode(dim, 0.7), make_episode(dim, 0.8)])
+        .unwrap();
+    assert_eq!(log_lines(), 10);
+    db.store_episode(make_episode(dim, 0.9)).unwrap();
+    drop(db);
+    assert_eq!(log_lines(), 11);
+
+    let mut db = AgentMemDBDisk::open_with_options(&dir, opts).unwrap();
+    assert_eq!(db.len(), 5);
+    assert!(db.iter().find(|e| e.id == ep.id).unwrap().deleted);
+
+    // A group torn by a crash is dropped whole on replay.
+    db.store_episode(make_episode(dim, 1.0)).unwrap();
+    db.store_episode(make_episode(dim, 1.0)).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let log = fs::read_to_string(&log_path).unwrap();
+    let torn = &log[..log.trim_end().rfind('\n').unwrap() + 1];
+    fs::write(&log_path, torn).unwrap();
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    assert_eq!(db.len(), 5);
+}
+
+#[test]
+fn test_disk_snapshot_while_writing() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_snapshot_src");
+    let copy = std::env::temp_dir().join("agent_mem_db_disk_snapshot_copy");
+    let _ = fs::remove_dir_all(&dir);
+    let _ = fs::remove_dir_all(&copy);
+    let dim = 4;
+
+    let opts = DiskOptions::exact(dim).group_commit(60_000, 100);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, opts).unwrap();
+    let mut first = make_episode(dim, 0.5);
+    first.timestamp = Some(1_000);
+    db.store_episode(first.clone()).unwrap();
+    db.store_episodes(vec![make_episode(dim, 0.6), make_episode(dim, 0.7)])
+        .unwrap();
+    let snapshot = db.begin_snapshot().unwrap();
+    assert_eq!(snapshot.len(), 3);
+
+    // Writes and a compaction after the marker don't reach the copy.
+    db.store_episode(make_episode(dim, 0.8)).unwrap();
+    assert_eq!(db.prune_older_than(2_000).unwrap(), 1);
+    db.store_episode(make_episode(dim, 0.9)).unwrap();
+    snapshot.write_to(&copy).unwrap();
+
+    assert!(copy.join("exact_checkpoint.json").exists());
+    let restored = AgentMemDBDisk::open_existing(&copy).unwrap();
+    assert_eq!(restored.len(), 3);
+    assert!(restored.iter().any(|e| e.id == first.id));
+    assert!(restored.verify().unwrap().is_ok());
+    assert_eq!(db.len(), 4);
+
+    // One-shot form; an existing DB is not overwritten.
+    assert!(matches!(
+        db.snapshot_to(&copy),
+        Err(AgentMemError::AlreadyExists(_))
+    ));
+    let _ = fs::remove_dir_all(&copy);
+    db.snapshot_to(&copy).unwrap();
+    assert_eq!(AgentMemDBDisk::open_existing(&copy).unwrap().len(), 4);
+}
+
+#[test]
+fn test_disk_prune_session() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_prune_session_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+
+    let mut db = AgentMemDBDisk::open(&dir, dim).unwrap();
+    db.store_episode(Episode::with_session_id("a", vec![0.0; dim], 0.9, "s1"))
+        .unwrap();
+    db.store_episode(Episode::with_session_id("b", vec![0.1; dim], 0.9, "s2"))
+        .unwrap();
+    db.store_episode(Episode::with_session_id("c", vec![0.2; dim], 0.9, "s1"))
+        .unwrap();
+    assert_eq!(db.prune_session("s1").unwrap(), 2);
+    drop(db);
+
+    let db = AgentMemDBDisk::open(&dir, dim).unwrap();
+    let opts = QueryOptions::new(0.0, 5).session_id("s2");
+    let results = db
+        .query_similar_with_options(&vec![0.0; dim], opts)
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].session_id.as_deref(), Some("s2"));
+    assert!(db
+        .query_similar_with_options(&vec![0.0; dim], QueryOptions::new(0.0, 5).session_id("s1"))
+        .unwrap()
+        .is_empty());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_disk_format_versions() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_format_version_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 2;
+
+    let mut db =
+        AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact_with_checkpoint(dim)).unwrap();
+    db.store_episode(Episode::new("a", vec![0.0; dim], 0.9))
+        .unwrap();
+    db.checkpoint().unwrap();
+    drop(db);
+    let read = |file: &str| -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(dir.join(file)).unwrap()).unwrap()
+    };
+    assert_eq!(read("meta.json")["format_version"], FORMAT_VERSION);
+    assert_eq!(
+        read("exact_checkpoint.json")["format_version"],
+        FORMAT_VERSION
+    );
+
+    // A directory written before versioning still opens, checkpoint included.
+    for file in ["meta.json", "exact_checkpoint.json"] {
+        let mut value = read(file);
+        value.as_object_mut().unwrap().remove("format_version");
+        fs::write(dir.join(file), value.to_string()).unwrap();
+    }
+    let db = AgentMemDBDisk::open_existing(&dir).unwrap();
+    assert_eq!(db.len(), 1);
+    assert!(db.verify().unwrap().is_ok());
+    drop(db);
+
+    let mut meta = read("meta.json");
+    meta["format_version"] = (FORMAT_VERSION + 1).into();
+    fs::write(dir.join("meta.json"), meta.to_string()).unwrap();
+    assert!(matches!(
+        AgentMemDBDisk::open_existing(&dir),
+        Err(AgentMemError::UnsupportedVersion { file: "meta", .. })
+    ));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_disk_episode_limits() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_limits_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    db.set_limits(EpisodeLimits::new().max_metadata_bytes(64));
+    db.store_episode(make_episode(dim, 0.5)).unwrap();
+    let mut big = make_episode(dim, 0.5);
+    big.metadata = json!({"observation": "x".repeat(1_000)});
+    assert!(matches!(
+        db.store_episode(big.clone()),
+        Err(AgentMemError::LimitExceeded {
+            limit: "max_metadata_bytes",
+            max: 64,
+            ..
+        })
+    ));
+    assert!(db
+        .store_episodes(vec![make_episode(dim, 0.5), big])
+        .is_err());
+    assert_eq!(db.len(), 1);
+    drop(db);
+
+    // Nothing over the limit reached the log.
+    let db = AgentMemDBDisk::open_existing(&dir).unwrap();
+    assert_eq!(db.len(), 1);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_disk_query_similar_shared() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_shared_test");
+    let _ = fs::remove_dir_all(&dir);
+    let dim = 4;
+
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(dim)).unwrap();
+    let near = make_episode(dim, 0.9);
+    db.store_episode(near.clone()).unwrap();
+    let mut far = make_episode(dim, 0.9);
+    far.state_embedding = vec![1.0; dim];
+    db.store_episode(far.clone()).unwrap();
+
+    let opts = QueryOptions::new(0.0, 1).include_embeddings(false);
+    let shared = db.query_similar_shared(&[0.1; 4], opts.clone()).unwrap();
+    assert_eq!(shared.len(), 1);
+    assert_eq!(shared[0].id, near.id);
+    assert_eq!(shared[0].state_embedding, vec![0.1; dim]);
+    let copied = db.query_similar_with_options(&[0.1; 4], opts).unwrap();
+    assert_eq!(copied[0].id, near.id);
+    assert!(copied[0].state_embedding.is_empty());
+
+    // Compaction rebuilds the store; returned episodes are unaffected.
+    db.delete_where(|ep| ep.id == near.id).unwrap();
+    assert_eq!(shared[0].id, near.id);
+    assert_eq!(db.len(), 1);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_disk_avoid_embedding() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_avoid_test");
+    let _ = fs::remove_dir_all(&dir);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    let failed = Episode::new("failed", vec![1.0, 0.0], 1.0);
+    let other = Episode::new("other", vec![0.8, 0.5], 1.0);
+    db.store_episode(failed.clone()).unwrap();
+    db.store_episode(other.clone()).unwrap();
+
+    let opts = QueryOptions::new(0.0, 2).avoid_embedding(failed.state_embedding.clone(), 2.0);
+    let hits = db
+        .query_similar_with_options(&[1.0, 0.0], opts.clone())
+        .unwrap();
+    assert_eq!(hits[0].id, other.id);
+    let explained = db.explain_query(&[1.0, 0.0], opts).unwrap();
+    assert_eq!(explained.results[0].id, other.id);
+    let plain = db
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(0.0, 2))
+        .unwrap();
+    assert_eq!(plain[0].id, failed.id);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,61 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, DiskOptions, DriftReport, Episode};
+use std::fs;
+
+fn ep(ts: i64, v: Vec<f32>) -> Episode {
+    Episode::with_timestamp(format!("task-{ts}"), v, 1.0, ts)
+}
+
+#[test]
+fn test_drift_report_stable_vs_shifted() {
+    // Same distribution throughout: little drift.
+    let mut stable = AgentMemDB::new_exact(2);
+    for ts in 0..100 {
+        let x = (ts % 10) as f32 * 0.1;
+        stable.store_episode(ep(ts, vec![x, 1.0 - x])).unwrap();
+    }
+    let report = stable.drift_report(50).unwrap();
+    assert_eq!((report.recent_count, report.historical_count), (50, 50));
+    assert!(report.score < 0.1, "{report:?}");
+
+    // The last 20 episodes come from a "new model" in another region of the space.
+    let mut shifted = AgentMemDB::new_exact(2);
+    for ts in 0..100 {
+        let x = (ts % 10) as f32 * 0.1;
+        let v = if ts < 80 {
+            vec![x, 1.0 - x]
+        } else {
+            vec![-3.0 + x, 4.0]
+        };
+        shifted.store_episode(ep(ts, v)).unwrap();
+    }
+    let report = shifted.drift_report(20).unwrap();
+    assert_eq!(report.recent_count, 20);
+    assert!(report.centroid_shift > 3.0);
+    assert!(report.score > 1.0, "{report:?}");
+
+    // Undated episodes are ignored; an empty side gives no report.
+    shifted
+        .store_episode(Episode::new("undated", vec![100.0, 100.0], 1.0))
+        .unwrap();
+    assert_eq!(shifted.drift_report(20).unwrap(), report);
+    assert!(shifted.drift_report(1_000).is_none());
+    assert!(DriftReport::between(std::iter::empty(), 10).is_none());
+}
+
+#[test]
+fn test_drift_report_on_disk() {
+    let dir = std::env::temp_dir().join("agent_mem_db_drift_disk");
+    let _ = fs::remove_dir_all(&dir);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    for ts in 0..10 {
+        let v = if ts < 5 {
+            vec![0.0, ts as f32]
+        } else {
+            vec![10.0, ts as f32]
+        };
+        db.store_episode(ep(ts, v)).unwrap();
+    }
+    let report = db.drift_report(5).unwrap();
+    assert_eq!((report.recent_count, report.historical_count), (5, 5));
+    assert!((report.centroid_shift - 11.18).abs() < 0.01, "{report:?}");
+}
@@ -0,0 +1,82 @@
+use agent_mem_db::{
+    AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, EmbeddingProvider, Episode,
+    QueryOptions,
+};
+use std::fs;
+use std::sync::Arc;
+
+/// Embeds a text as (length, count of 'a').
+struct Counts {
+    dim: usize,
+}
+
+impl EmbeddingProvider for Counts {
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, AgentMemError> {
+        let mut e = vec![0.0; self.dim];
+        e[0] = text.len() as f32;
+        e[1] = text.matches('a').count() as f32;
+        Ok(e)
+    }
+}
+
+#[test]
+fn test_set_embedder_checks_dim() {
+    let mut db = AgentMemDB::new_exact(2);
+    let err = db.set_embedder(Arc::new(Counts { dim: 3 })).unwrap_err();
+    assert!(matches!(
+        err,
+        AgentMemError::DimensionMismatch {
+            expected: 2,
+            got: 3
+        }
+    ));
+    assert!(matches!(
+        db.store_text("t", "hello", 1.0),
+        Err(AgentMemError::Embedding(_))
+    ));
+}
+
+#[test]
+fn test_store_and_query_text() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.set_embedder(Arc::new(Counts { dim: 2 })).unwrap();
+    let id = db.store_text("banana", "banana", 1.0).unwrap();
+    db.store_text("kiwi", "kiwi", 1.0).unwrap();
+    assert_eq!(db.len(), 2);
+
+    let hits = db.query_text("papaya", 0.0, 1).unwrap();
+    assert_eq!(hits[0].id, id);
+    assert_eq!(hits[0].state_embedding, vec![6.0, 3.0]);
+}
+
+#[test]
+fn test_disk_store_text() {
+    let dir = std::env::temp_dir().join("agent_mem_db_embedding_disk");
+    let _ = fs::remove_dir_all(&dir);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    db.set_embedder(Arc::new(Counts { dim: 2 })).unwrap();
+    db.store_text("kiwi", "kiwi", 1.0).unwrap();
+    drop(db);
+
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    assert_eq!(db.iter().next().unwrap().state_embedding, vec![4.0, 0.0]);
+}
+
+#[test]
+fn test_store_episode_with_text_keeps_fields() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.set_embedder(Arc::new(Counts { dim: 2 })).unwrap();
+    let mut episode = Episode::new("t", vec![9.0, 9.0], 0.5);
+    episode.tags = Some(vec!["fruit".into()]);
+    let id = db.store_episode_with_text(episode, "apple").unwrap();
+
+    let opts = QueryOptions::new(0.0, 5).tags_any(vec!["fruit".into()]);
+    let hits = db.query_text_with_options("apple", opts).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, id);
+    assert_eq!(hits[0].state_embedding, vec![5.0, 1.0]);
+}
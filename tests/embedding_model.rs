@@ -0,0 +1,103 @@
+use agent_mem_db::{
+    AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, EmbeddingProvider, Episode,
+    QueryOptions,
+};
+use std::fs;
+use std::sync::Arc;
+
+/// Embeds a text as its length, then zeros, under the model name "len-v2".
+struct LengthsV2;
+
+impl EmbeddingProvider for LengthsV2 {
+    fn dim(&self) -> usize {
+        2
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, AgentMemError> {
+        Ok(vec![text.len() as f32, 0.0])
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some("len-v2")
+    }
+}
+
+#[test]
+fn test_query_scoped_to_embedding_model() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episode(Episode::with_embedding_model(
+        "old",
+        vec![1.0, 0.0],
+        1.0,
+        "v1",
+    ))
+    .unwrap();
+    db.store_episode(Episode::with_embedding_model(
+        "new",
+        vec![0.0, 1.0],
+        1.0,
+        "v2",
+    ))
+    .unwrap();
+    db.store_episode(Episode::new("untagged", vec![1.0, 0.1], 1.0))
+        .unwrap();
+
+    let opts = QueryOptions::new(0.0, 5).embedding_model("v2");
+    let results = db.query_similar_with_options(&[1.0, 0.0], opts).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].task_id, "new");
+
+    let explained = db
+        .explain_query(&[1.0, 0.0], QueryOptions::new(0.0, 5).embedding_model("v1"))
+        .unwrap();
+    assert_eq!(explained.allowed_keys, Some(1));
+    assert!(QueryOptions::new(0.0, 5)
+        .embedding_model("v3")
+        .rejected_by(&results[0])
+        .is_some_and(|f| f == "embedding_model"));
+}
+
+#[test]
+fn test_disk_embedding_model_persists() {
+    let dir = std::env::temp_dir().join("agent_mem_db_embedding_model_disk");
+    let _ = fs::remove_dir_all(&dir);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.store_episode(Episode::with_embedding_model(
+            "a",
+            vec![0.0, 1.0],
+            1.0,
+            "v1",
+        ))
+        .unwrap();
+        db.store_episode(Episode::new("b", vec![1.0, 0.0], 1.0))
+            .unwrap();
+    }
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    let results = db
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(0.0, 5).embedding_model("v1"))
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].task_id, "a");
+    assert_eq!(db.needs_reembedding("v1").len(), 1);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_provider_model_tags_stored_and_reembedded_episodes() {
+    let mut db = AgentMemDB::new_exact(2);
+    let mut old = Episode::with_embedding_model("old", vec![0.0, 9.0], 1.0, "len-v1");
+    old.metadata = serde_json::json!({ "text": "hello" });
+    let old_id = old.id;
+    db.store_episode(old).unwrap();
+    db.set_embedder(Arc::new(LengthsV2)).unwrap();
+    db.store_text("new", "hi", 1.0).unwrap();
+    assert_eq!(db.needs_reembedding("len-v2"), vec![old_id]);
+
+    let report = db.reembed_all(&LengthsV2, 16, |_| {}).unwrap();
+    assert_eq!(report.reembedded, 1);
+    assert!(db.needs_reembedding("len-v2").is_empty());
+    assert!(db
+        .iter()
+        .all(|ep| ep.embedding_model.as_deref() == Some("len-v2")));
+}
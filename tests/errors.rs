@@ -0,0 +1,112 @@
+use agent_mem_db::{
+    AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, MultiTenantMemDB,
+    TenantOptions,
+};
+use std::error::Error;
+use std::fs;
+
+#[test]
+fn test_missing_file_is_io_with_source() {
+    let path = std::env::temp_dir().join("agent_mem_db_errors_missing.json");
+    let _ = fs::remove_file(&path);
+    let err = AgentMemDB::load_from_file(&path).err().unwrap();
+    match err {
+        AgentMemError::Io { ref source, .. } => {
+            assert_eq!(source.kind(), std::io::ErrorKind::NotFound)
+        }
+        ref other => panic!("expected Io, got {other:?}"),
+    }
+    assert!(err.source().is_some());
+    // Same message the bindings have always shown.
+    assert!(err.to_string().starts_with("HNSW or IO error: File open: "));
+}
+
+#[test]
+fn test_unparseable_save_file_is_serde() {
+    let path = std::env::temp_dir().join("agent_mem_db_errors_garbage.json");
+    fs::write(&path, "not json").unwrap();
+    let err = AgentMemDB::load_from_file(&path).err().unwrap();
+    assert!(matches!(err, AgentMemError::Serde { .. }), "{err:?}");
+    assert!(err.to_string().starts_with("HNSW or IO error: Parse "));
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn test_corrupt_log_reports_line() {
+    let dir = std::env::temp_dir().join("agent_mem_db_errors_corrupt_log");
+    let _ = fs::remove_dir_all(&dir);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.store_episode(Episode::new("a", vec![0.0, 1.0], 1.0))
+            .unwrap();
+        db.store_episode(Episode::new("b", vec![1.0, 0.0], 1.0))
+            .unwrap();
+    }
+    let log = dir.join("episodes.jsonl");
+    let text = fs::read_to_string(&log).unwrap();
+    fs::write(&log, text + "{\"id\": garbage}\n").unwrap();
+    let err = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2))
+        .err()
+        .unwrap();
+    assert!(
+        matches!(err, AgentMemError::Corruption { line: 3, .. }),
+        "{err:?}"
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_full_hnsw_index_is_an_error() {
+    let mut db = AgentMemDB::new_with_max_elements(2, 2);
+    db.store_episode(Episode::new("a", vec![0.0, 1.0], 1.0))
+        .unwrap();
+    let batch = vec![
+        Episode::new("b", vec![1.0, 0.0], 1.0),
+        Episode::new("c", vec![1.0, 1.0], 1.0),
+    ];
+    assert!(matches!(
+        db.store_episodes(batch),
+        Err(AgentMemError::IndexFull { max_elements: 2 })
+    ));
+    assert_eq!(db.len(), 1);
+    db.store_episode(Episode::new("b", vec![1.0, 0.0], 1.0))
+        .unwrap();
+    assert!(matches!(
+        db.store_episode(Episode::new("c", vec![1.0, 1.0], 1.0)),
+        Err(AgentMemError::IndexFull { .. })
+    ));
+    assert_eq!(db.len(), 2);
+}
+
+#[test]
+fn test_non_finite_embedding_is_rejected() {
+    let mut db = AgentMemDB::new_exact(2);
+    assert!(matches!(
+        db.store_episode(Episode::new("a", vec![0.0, f32::NAN], 1.0)),
+        Err(AgentMemError::InvalidEmbedding(_))
+    ));
+
+    let dir = std::env::temp_dir().join("agent_mem_db_errors_non_finite");
+    let _ = fs::remove_dir_all(&dir);
+    let mut disk = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    let batch = vec![
+        Episode::new("a", vec![0.0, 1.0], 1.0),
+        Episode::new("b", vec![f32::INFINITY, 1.0], 1.0),
+    ];
+    assert!(matches!(
+        disk.store_episodes(batch),
+        Err(AgentMemError::InvalidEmbedding(_))
+    ));
+    assert!(disk.is_empty());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_existing_tenant_already_exists() {
+    let mut tenants = MultiTenantMemDB::new(TenantOptions::memory_exact(2));
+    tenants.create_tenant("acme").unwrap();
+    assert!(matches!(
+        tenants.create_tenant("acme"),
+        Err(AgentMemError::AlreadyExists(_))
+    ));
+}
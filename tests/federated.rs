@@ -0,0 +1,59 @@
+use agent_mem_db::{
+    query_federated, AgentMemDB, AgentMemDBDisk, DiskOptions, Episode, EpisodeStore, QueryOptions,
+};
+use std::fs;
+
+#[test]
+fn test_federated_merges_by_distance_with_sources() {
+    let dir = std::env::temp_dir().join("agent_mem_db_federated_test");
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut episodic = AgentMemDB::new_exact(2);
+    let shared = Episode::new("shared", vec![0.5, 0.0], 1.0);
+    episodic
+        .store_episodes(vec![
+            Episode::new("e-near", vec![0.1, 0.0], 1.0),
+            Episode::new("e-far", vec![5.0, 0.0], 1.0),
+            shared.clone(),
+        ])
+        .unwrap();
+    let mut archive = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    archive
+        .store_episodes(vec![
+            Episode::new("a-nearest", vec![0.0, 0.0], 1.0),
+            Episode::new("a-low", vec![0.2, 0.0], -1.0),
+            shared.clone(),
+        ])
+        .unwrap();
+    let snapshot = episodic.clone_snapshot();
+
+    let stores: [&dyn EpisodeStore; 2] = [&episodic, &archive];
+    let hits = query_federated(&stores, &[0.0, 0.0], QueryOptions::new(0.0, 3)).unwrap();
+    let got: Vec<(&str, usize)> = hits
+        .iter()
+        .map(|h| (h.episode.task_id.as_str(), h.source))
+        .collect();
+    assert_eq!(got, [("a-nearest", 1), ("e-near", 0), ("shared", 0)]);
+    assert!((hits[1].distance - 0.1).abs() < 1e-6);
+
+    // Without embeddings the merge still ranks by true distance.
+    let opts = QueryOptions::new(0.0, 3).include_embeddings(false);
+    let stripped = query_federated(&stores, &[0.0, 0.0], opts).unwrap();
+    let stripped_ids: Vec<_> = stripped.iter().map(|h| h.episode.id).collect();
+    let ids: Vec<_> = hits.iter().map(|h| h.episode.id).collect();
+    assert_eq!(stripped_ids, ids);
+    assert!((stripped[1].distance - 0.1).abs() < 1e-6);
+    assert!(stripped
+        .iter()
+        .all(|h| h.episode.state_embedding.is_empty()));
+
+    // The same episode in two stores is returned once.
+    let stores: [&dyn EpisodeStore; 2] = [&snapshot, &episodic];
+    let hits = query_federated(&stores, &[0.0, 0.0], QueryOptions::new(0.0, 10)).unwrap();
+    assert_eq!(hits.len(), 3);
+    assert!(hits.iter().all(|h| h.source == 0));
+
+    let stores: [&dyn EpisodeStore; 1] = [&archive];
+    assert!(query_federated(&stores, &[0.0], QueryOptions::new(0.0, 1)).is_err());
+    assert_eq!(stores[0].dim(), 2);
+}
@@ -0,0 +1,82 @@
+use agent_mem_db::{
+    query_fused, AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, QueryOptions,
+    RRF_K,
+};
+use std::fs;
+
+fn store(db: &mut AgentMemDB, task: &str, embedding: Vec<f32>) {
+    db.store_episode(Episode::new(task, embedding, 1.0))
+        .unwrap();
+}
+
+#[test]
+fn test_query_fused_weighted_rrf() {
+    let mut db = AgentMemDB::new_exact(2);
+    store(&mut db, "state", vec![1.0, 0.0]);
+    store(&mut db, "goal", vec![0.0, 1.0]);
+    store(&mut db, "between", vec![0.6, 0.5]);
+    let queries = [vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    // Equal weights: the episode ranked second in both lists beats either list's winner.
+    let hits = db
+        .query_fused(&queries, &[1.0, 1.0], QueryOptions::new(0.0, 2))
+        .unwrap();
+    let tasks: Vec<&str> = hits.iter().map(|h| h.episode.task_id.as_str()).collect();
+    assert_eq!(tasks[0], "between");
+    assert!((hits[0].score - 2.0 / (RRF_K + 2.0)).abs() < 1e-6);
+    assert_eq!(tasks.len(), 2);
+
+    // Between two list winners, the heavier weight wins.
+    let hits = db
+        .query_fused(&queries, &[1.0, 5.0], QueryOptions::new(0.0, 1))
+        .unwrap();
+    assert_eq!(hits[0].episode.task_id, "goal");
+    assert!((hits[0].score - 5.0 / (RRF_K + 1.0)).abs() < 1e-6);
+    assert_eq!(hits.len(), 1);
+
+    // Filters apply to every list; a zero weight contributes nothing.
+    let hits = db
+        .query_fused(&queries, &[0.0, 1.0], QueryOptions::new(0.0, 1))
+        .unwrap();
+    assert_eq!(hits[0].episode.task_id, "goal");
+
+    assert!(db
+        .query_fused(&[], &[], QueryOptions::new(0.0, 5))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_query_fused_errors_and_disk() {
+    let mut db = AgentMemDB::new_exact(2);
+    store(&mut db, "a", vec![1.0, 0.0]);
+    let queries = [vec![1.0, 0.0], vec![0.0, 1.0]];
+    assert!(matches!(
+        db.query_fused(&queries, &[1.0], QueryOptions::new(0.0, 5)),
+        Err(AgentMemError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        db.query_fused(&queries, &[1.0, f32::NAN], QueryOptions::new(0.0, 5)),
+        Err(AgentMemError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        db.query_fused(&[vec![1.0; 3]], &[1.0], QueryOptions::new(0.0, 5)),
+        Err(AgentMemError::DimensionMismatch { .. })
+    ));
+
+    let dir = std::env::temp_dir().join("agent_mem_db_fusion_disk_test");
+    let _ = fs::remove_dir_all(&dir);
+    let mut disk = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    for ep in db.iter() {
+        disk.store_episode(ep.clone()).unwrap();
+    }
+    disk.store_episode(Episode::new("b", vec![0.0, 1.0], 1.0))
+        .unwrap();
+    let from_disk = disk
+        .query_fused(&queries, &[1.0, 2.0], QueryOptions::new(0.0, 2))
+        .unwrap();
+    assert_eq!(from_disk[0].episode.task_id, "b");
+    let generic = query_fused(&disk, &queries, &[1.0, 2.0], QueryOptions::new(0.0, 2)).unwrap();
+    assert_eq!(generic[1].episode.id, from_disk[1].episode.id);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -51,6 +51,7 @@ fn episode_strategy(dim: usize) -> impl Strategy<Value = Episode> {
         prop::option::of(prop::collection::vec(any::<String>(), 0..5)),
         prop::option::of(any::<String>()),
         prop::option::of(any::<String>()),
+        prop::option::of(any::<String>()),
     )
         .prop_map(
             |(
@@ -63,6 +64,7 @@ fn episode_strategy(dim: usize) -> impl Strategy<Value = Episode> {
                 tags,
                 source,
                 user_id,
+                session_id,
             )| Episode {
                 id: Uuid::new_v4(),
                 task_id,
@@ -74,6 +76,14 @@ fn episode_strategy(dim: usize) -> impl Strategy<Value = Episode> {
                 tags,
                 source,
                 user_id,
+                session_id,
+                version: 0,
+                deleted: false,
+                times_retrieved: 0,
+                last_retrieved_at: None,
+                cost: None,
+                duration_ms: None,
+                embedding_model: None,
             },
         )
 }
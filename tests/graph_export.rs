@@ -0,0 +1,72 @@
+use agent_mem_db::{
+    write_similarity_graph, AgentMemDB, AgentMemDBDisk, DiskOptions, Episode, GraphFormat,
+};
+use std::fs;
+
+fn cluster_db() -> (AgentMemDB, Episode, Episode) {
+    let mut db = AgentMemDB::new_exact(2);
+    let a = Episode::with_tags("a&b", vec![0.0, 0.0], 1.0, vec!["x".to_string()]);
+    let b = Episode::new("say \"hi\"", vec![0.0, 0.2], 0.5);
+    db.store_episodes(vec![
+        a.clone(),
+        b.clone(),
+        Episode::new("far", vec![9.0, 9.0], 0.5),
+    ])
+    .unwrap();
+    (db, a, b)
+}
+
+#[test]
+fn test_export_dot_and_graphml() {
+    let (db, a, b) = cluster_db();
+    let dir = std::env::temp_dir().join("agent_mem_db_graph_export");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let dot = dir.join("memory.dot");
+    assert_eq!(db.export_similarity_graph(0.5, &dot).unwrap(), 1);
+    let text = fs::read_to_string(&dot).unwrap();
+    assert!(text.starts_with("graph memory {"));
+    assert_eq!(text.matches(" -- ").count(), 1);
+    assert!(text.contains(r#"label="say \"hi\"""#));
+    let (first, second) = if a.id < b.id {
+        (a.id, b.id)
+    } else {
+        (b.id, a.id)
+    };
+    assert!(text.contains(&format!("\"{first}\" -- \"{second}\"")));
+
+    let graphml = dir.join("memory.graphml");
+    assert_eq!(db.export_similarity_graph(100.0, &graphml).unwrap(), 3);
+    let text = fs::read_to_string(&graphml).unwrap();
+    assert!(text.contains("<graphml"));
+    assert_eq!(text.matches("<node ").count(), 3);
+    assert_eq!(text.matches("<edge ").count(), 3);
+    assert!(text.contains(r#"<data key="task_id">a&amp;b</data>"#));
+    assert!(text.contains(r#"<data key="tags">x</data>"#));
+}
+
+#[test]
+fn test_export_skips_deleted_and_works_on_disk() {
+    let (mut db, a, _) = cluster_db();
+    db.soft_delete(a.id).unwrap();
+    let mut out = Vec::new();
+    let edges = write_similarity_graph(db.iter(), 0.5, GraphFormat::Dot, &mut out).unwrap();
+    assert_eq!(edges, 0);
+    assert!(!String::from_utf8(out).unwrap().contains(&a.id.to_string()));
+
+    let dir = std::env::temp_dir().join("agent_mem_db_graph_export_disk");
+    let _ = fs::remove_dir_all(&dir);
+    let mut disk = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    disk.store_episode(Episode::new("a", vec![0.0, 0.0], 1.0))
+        .unwrap();
+    disk.store_episode(Episode::new("b", vec![0.1, 0.0], 1.0))
+        .unwrap();
+    let path = dir.join("graph.xml");
+    assert_eq!(disk.export_similarity_graph(0.5, &path).unwrap(), 1);
+    assert_eq!(
+        GraphFormat::from_path(&path),
+        GraphFormat::GraphMl,
+        "xml extension selects GraphML"
+    );
+}
@@ -0,0 +1,89 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, DiskOptions, Episode, QueryOptions};
+use std::fs;
+
+/// Three near-identical "deploy" episodes closest to the query, then two other tasks.
+fn episodes() -> Vec<Episode> {
+    vec![
+        Episode::with_timestamp("deploy", vec![1.0, 0.0], 1.0, 3_000),
+        Episode::with_timestamp("deploy", vec![1.0, 0.05], 1.0, 2_000),
+        Episode::with_timestamp("deploy", vec![1.0, 0.1], 1.0, 1_000),
+        Episode::with_timestamp("rollback", vec![0.8, 0.4], 1.0, 1_000),
+        Episode::with_timestamp("scale up", vec![0.5, 0.8], 1.0, 1_000),
+    ]
+}
+
+fn tasks(hits: &[Episode]) -> Vec<&str> {
+    hits.iter().map(|ep| ep.task_id.as_str()).collect()
+}
+
+#[test]
+fn test_group_by_task() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+
+    let hits = db
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(0.0, 3))
+        .unwrap();
+    assert_eq!(tasks(&hits), vec!["deploy", "deploy", "deploy"]);
+
+    let hits = db
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(0.0, 3).group_by_task(1))
+        .unwrap();
+    assert_eq!(tasks(&hits), vec!["deploy", "rollback", "scale up"]);
+    // The task keeps its best-ranked episode.
+    assert_eq!(hits[0].timestamp, Some(3_000));
+
+    let hits = db
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(0.0, 5).group_by_task(2))
+        .unwrap();
+    assert_eq!(
+        tasks(&hits),
+        vec!["deploy", "deploy", "rollback", "scale up"]
+    );
+
+    // 0 is treated as 1.
+    let opts = QueryOptions::new(0.0, 5).group_by_task(0);
+    assert_eq!(opts.max_per_task, Some(1));
+    assert_eq!(opts.candidate_multiplier(), 4);
+
+    // Candidates dropped by the grouping are reported as not returned.
+    let explained = db
+        .explain_query(&[1.0, 0.0], QueryOptions::new(0.0, 3).group_by_task(1))
+        .unwrap();
+    assert_eq!(
+        tasks(&explained.results),
+        vec!["deploy", "rollback", "scale up"]
+    );
+    let returned_deploys = explained
+        .candidates
+        .iter()
+        .filter(|c| c.episode.task_id == "deploy" && c.returned)
+        .count();
+    assert_eq!(returned_deploys, 1);
+}
+
+#[test]
+fn test_group_by_task_fused_and_disk() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+    let hits = db
+        .query_fused(
+            &[vec![1.0, 0.0], vec![1.0, 0.1]],
+            &[1.0, 1.0],
+            QueryOptions::new(0.0, 2).group_by_task(1),
+        )
+        .unwrap();
+    // Each query's best deploy is a different episode; the fused list still keeps one.
+    let fused: Vec<&str> = hits.iter().map(|h| h.episode.task_id.as_str()).collect();
+    assert_eq!(fused, vec!["rollback", "deploy"]);
+
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_group_by_task_test");
+    let _ = fs::remove_dir_all(&dir);
+    let mut disk = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    disk.store_episodes(episodes()).unwrap();
+    let hits = disk
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(0.0, 3).group_by_task(1))
+        .unwrap();
+    assert_eq!(tasks(&hits), vec!["deploy", "rollback", "scale up"]);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,141 @@
+use agent_mem_db::{
+    AgentMemDB, AgentMemError, Episode, MemoryKind, MemorySystem, QueryOptions, Summarizer,
+};
+
+#[test]
+fn test_memory_system_recall_and_consolidate() {
+    let mut memory = MemorySystem::new_exact(2);
+    let a = Episode::new("a", vec![1.0, 0.0], 1.0);
+    let b = Episode::new("b", vec![1.2, 0.0], 1.0);
+    let ids = [a.id, b.id];
+    memory.remember(a).unwrap();
+    memory.remember(b).unwrap();
+    memory
+        .remember(Episode::new("far", vec![9.0, 0.0], 1.0))
+        .unwrap();
+    memory
+        .learn(Episode::new("fact", vec![0.0, 0.5], 1.0))
+        .unwrap();
+
+    let hits = memory
+        .recall(&[0.0, 0.0], QueryOptions::new(0.0, 3))
+        .unwrap();
+    let got: Vec<(&str, MemoryKind)> = hits
+        .iter()
+        .map(|h| (h.episode.task_id.as_str(), h.kind))
+        .collect();
+    assert_eq!(
+        got,
+        [
+            ("fact", MemoryKind::Semantic),
+            ("a", MemoryKind::Episodic),
+            ("b", MemoryKind::Episodic),
+        ]
+    );
+    assert!((hits[0].distance - 0.5).abs() < 1e-6);
+
+    let summary = Episode::new("summary", vec![1.1, 0.0], 1.0);
+    assert_eq!(memory.consolidate(&ids, summary).unwrap(), 2);
+    assert_eq!(memory.episodic().len(), 1);
+    assert_eq!(memory.semantic().len(), 2);
+    let hits = memory
+        .recall(&[1.0, 0.0], QueryOptions::new(0.0, 1))
+        .unwrap();
+    assert_eq!(hits[0].episode.task_id, "summary");
+    assert_eq!(hits[0].kind, MemoryKind::Semantic);
+
+    // A summary of the wrong dimension is rejected and the episodes are kept.
+    let far_id = memory
+        .recall(&[9.0, 0.0], QueryOptions::new(0.0, 1))
+        .unwrap()[0]
+        .episode
+        .id;
+    let bad = Episode::new("bad", vec![0.0; 3], 1.0);
+    assert!(memory.consolidate(&[far_id], bad).is_err());
+    assert_eq!(memory.episodic().len(), 1);
+
+    assert!(matches!(
+        MemorySystem::from_stores(AgentMemDB::new_exact(2), AgentMemDB::new_exact(3)),
+        Err(AgentMemError::DimensionMismatch {
+            expected: 2,
+            got: 3
+        })
+    ));
+}
+
+/// Joins task_ids; leaves the embedding to the caller (the centroid).
+struct JoinTasks;
+
+impl Summarizer for JoinTasks {
+    fn summarize(&self, episodes: &[Episode]) -> Result<Episode, AgentMemError> {
+        let mut tasks: Vec<&str> = episodes.iter().map(|e| e.task_id.as_str()).collect();
+        tasks.sort();
+        Ok(Episode::new(tasks.join("+"), Vec::new(), 1.0))
+    }
+}
+
+struct Failing;
+
+impl Summarizer for Failing {
+    fn summarize(&self, _episodes: &[Episode]) -> Result<Episode, AgentMemError> {
+        Err(AgentMemError::Summarizer("model unavailable".into()))
+    }
+}
+
+#[test]
+fn test_summarizer_merge_clusters() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(vec![
+        Episode::new("a", vec![0.0, 0.0], 1.0),
+        Episode::new("b", vec![0.0, 0.2], 1.0),
+        Episode::new("c", vec![5.0, 5.0], 1.0),
+        Episode::new("d", vec![5.0, 5.2], 1.0),
+        Episode::new("e", vec![9.0, 0.0], 1.0),
+    ])
+    .unwrap();
+
+    assert!(matches!(
+        db.merge_clusters(0.5, 2, &Failing),
+        Err(AgentMemError::Summarizer(_))
+    ));
+    assert_eq!(db.len(), 5);
+    // No group reaches the minimum size.
+    assert_eq!(db.merge_clusters(0.5, 3, &JoinTasks).unwrap(), 0);
+
+    assert_eq!(db.merge_clusters(0.5, 2, &JoinTasks).unwrap(), 2);
+    let mut tasks: Vec<&str> = db.iter().map(|e| e.task_id.as_str()).collect();
+    tasks.sort();
+    assert_eq!(tasks, ["a+b", "c+d", "e"]);
+    let ab = db.iter().find(|e| e.task_id == "a+b").unwrap();
+    assert_eq!(ab.state_embedding, vec![0.0, 0.1]);
+    let hits = db.query_similar(&[5.0, 5.1], 0.0, 1).unwrap();
+    assert_eq!(hits[0].task_id, "c+d");
+}
+
+#[test]
+fn test_summarizer_consolidates_memory_system() {
+    let mut memory = MemorySystem::new_exact(2);
+    let a = Episode::new("a", vec![0.0, 0.0], 1.0);
+    let b = Episode::new("b", vec![0.0, 0.2], 1.0);
+    let ids = [a.id, b.id];
+    memory.remember(a).unwrap();
+    memory.remember(b).unwrap();
+    memory
+        .remember(Episode::new("c", vec![5.0, 5.0], 1.0))
+        .unwrap();
+    memory
+        .remember(Episode::new("d", vec![5.0, 5.2], 1.0))
+        .unwrap();
+
+    assert_eq!(memory.consolidate_with(&ids, &JoinTasks).unwrap(), 2);
+    assert_eq!(memory.consolidate_with(&ids, &JoinTasks).unwrap(), 0);
+    assert_eq!(memory.consolidate_clusters(0.5, 2, &JoinTasks).unwrap(), 1);
+    assert!(memory.episodic().is_empty());
+    let mut facts: Vec<&str> = memory
+        .semantic()
+        .iter()
+        .map(|e| e.task_id.as_str())
+        .collect();
+    facts.sort();
+    assert_eq!(facts, ["a+b", "c+d"]);
+}
@@ -0,0 +1,136 @@
+use agent_mem_db::{
+    tenant_dir_name, AgentMemError, DiskOptions, Episode, EpisodeStore, MultiTenantMemDB,
+    QueryOptions, TenantOptions, TenantStore, TENANT_ID_FILE,
+};
+use std::fs;
+use std::time::Duration;
+
+fn temp_root(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn test_tenants_are_isolated() {
+    let mut db = MultiTenantMemDB::new(TenantOptions::memory_exact(2));
+    db.get_or_create("a")
+        .unwrap()
+        .store_episode(Episode::new("a-task", vec![1.0, 0.0], 1.0))
+        .unwrap();
+    db.get_or_create("b")
+        .unwrap()
+        .store_episode(Episode::new("b-task", vec![1.0, 0.0], 1.0))
+        .unwrap();
+
+    let hits = db
+        .query("a", &[1.0, 0.0], QueryOptions::new(0.0, 10))
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].task_id, "a-task");
+    assert!(db
+        .query("missing", &[1.0, 0.0], QueryOptions::new(0.0, 10))
+        .unwrap()
+        .is_empty());
+    assert!(db.get("missing").unwrap().is_none());
+    let mut loaded: Vec<&str> = db.loaded_tenants().collect();
+    loaded.sort_unstable();
+    assert_eq!(loaded, vec!["a", "b"]);
+
+    assert!(db.create_tenant("a").is_err());
+    assert!(db.create_tenant("c").unwrap().is_empty());
+
+    // Evicting an in-memory tenant hands its store back; the next use starts empty.
+    let evicted = db.evict("a").unwrap().unwrap();
+    assert_eq!(evicted.len(), 1);
+    assert!(db.get("a").unwrap().is_none());
+    assert!(db.evict("a").unwrap().is_none());
+}
+
+#[test]
+fn test_per_tenant_options() {
+    let root = temp_root("agent_mem_db_multi_tenant_options_test");
+    let mut db = MultiTenantMemDB::with_root(&root, TenantOptions::memory(2)).unwrap();
+    db.set_tenant_options("wide", TenantOptions::memory_exact(4));
+    db.set_tenant_options("durable", TenantOptions::disk(DiskOptions::exact(2)));
+    assert_eq!(db.tenant_options("wide").dim(), 4);
+    assert_eq!(db.tenant_options("other").dim(), 2);
+
+    assert_eq!(db.get_or_create("wide").unwrap().dim(), 4);
+    assert!(!db.get_or_create("other").unwrap().is_disk());
+    let durable = db.get_or_create("durable").unwrap();
+    assert!(durable.is_disk());
+    assert!(durable
+        .store_episode(Episode::new("t", vec![1.0], 1.0))
+        .is_err());
+
+    // Disk tenants need a root.
+    let mut no_root = MultiTenantMemDB::new(TenantOptions::disk(DiskOptions::exact(2)));
+    assert!(no_root.get_or_create("t").is_err());
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_disk_tenants_reopen_after_eviction() {
+    let root = temp_root("agent_mem_db_multi_tenant_disk_test");
+    let options = TenantOptions::disk(DiskOptions::exact_with_checkpoint(2));
+    {
+        let mut db = MultiTenantMemDB::with_root(&root, options.clone()).unwrap();
+        db.get_or_create("acme/prod")
+            .unwrap()
+            .store_episode(Episode::new("deploy", vec![1.0, 0.0], 1.0))
+            .unwrap();
+        db.get_or_create("other").unwrap();
+
+        assert!(db.evict("acme/prod").unwrap().unwrap().is_disk());
+        assert_eq!(db.loaded_tenants().collect::<Vec<_>>(), vec!["other"]);
+        // Reloaded from its directory on next use.
+        assert_eq!(db.get("acme/prod").unwrap().unwrap().len(), 1);
+        assert!(db.create_tenant("acme/prod").is_err());
+    }
+
+    let dir = root.join(tenant_dir_name("acme/prod"));
+    assert_eq!(tenant_dir_name("acme/prod"), "acme_prod");
+    assert_eq!(
+        fs::read_to_string(dir.join(TENANT_ID_FILE)).unwrap(),
+        "acme/prod"
+    );
+    assert!(TenantStore::exists_on_disk(&root, "acme/prod"));
+    // Another id with the same directory name can't open it.
+    assert!(matches!(
+        TenantStore::open(Some(&root), "acme.prod", &options),
+        Err(AgentMemError::AlreadyExists(_))
+    ));
+
+    // A new handle on the same root finds tenants created by the last one.
+    let mut db = MultiTenantMemDB::with_root(&root, options).unwrap();
+    assert_eq!(db.loaded_tenants().count(), 0);
+    let hits = db
+        .query("acme/prod", &[1.0, 0.0], QueryOptions::new(0.0, 5))
+        .unwrap();
+    assert_eq!(hits[0].task_id, "deploy");
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_evict_idle() {
+    let root = temp_root("agent_mem_db_multi_tenant_evict_test");
+    let mut db =
+        MultiTenantMemDB::with_root(&root, TenantOptions::disk(DiskOptions::exact(2))).unwrap();
+    db.set_tenant_options("memory", TenantOptions::memory_exact(2));
+    for tenant in ["first", "second", "third", "memory"] {
+        db.get_or_create(tenant).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+    }
+
+    // Over the cap: least recently used disk tenants go first; in-memory ones stay.
+    let evicted = db.evict_idle(None, Some(2));
+    assert_eq!(evicted, vec!["first", "second"]);
+    assert_eq!(db.evict_idle(None, Some(2)), Vec::<String>::new());
+
+    let evicted = db.evict_idle(Some(Duration::ZERO), None);
+    assert_eq!(evicted, vec!["third"]);
+    assert_eq!(db.loaded_tenants().collect::<Vec<_>>(), vec!["memory"]);
+    db.checkpoint_all().unwrap();
+    let _ = fs::remove_dir_all(&root);
+}
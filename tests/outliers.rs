@@ -0,0 +1,55 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, DiskOptions, Episode};
+use std::fs;
+
+fn grid() -> Vec<Episode> {
+    (0..50)
+        .map(|i| {
+            let v = vec![(i % 10) as f32 * 0.1, (i / 10) as f32 * 0.1, 0.0];
+            Episode::new(format!("ok-{i}"), v, 1.0)
+        })
+        .collect()
+}
+
+#[test]
+fn test_find_outliers_flags_far_episodes() {
+    let mut db = AgentMemDB::new(3);
+    db.store_episodes(grid()).unwrap();
+    let corrupt = Episode::new("corrupt", vec![0.0, 0.0, -40.0], 1.0);
+    let off_task = Episode::new("off-task", vec![0.5, 0.2, 20.0], 1.0);
+    db.store_episodes(vec![corrupt.clone(), off_task.clone()])
+        .unwrap();
+
+    let outliers = db.find_outliers(5, 2.0);
+    let ids: Vec<_> = outliers.iter().map(|o| o.id).collect();
+    assert_eq!(ids, [corrupt.id, off_task.id]);
+    assert!(outliers[0].z_score > outliers[1].z_score);
+    assert!(outliers[1].mean_distance > 10.0);
+
+    // Deleted episodes are neither reported nor counted as neighbours.
+    db.soft_delete(corrupt.id).unwrap();
+    let ids: Vec<_> = db.find_outliers(5, 2.0).iter().map(|o| o.id).collect();
+    assert_eq!(ids, [off_task.id]);
+
+    assert!(db.find_outliers(0, 2.0).is_empty());
+    assert!(AgentMemDB::new(3).find_outliers(5, 2.0).is_empty());
+}
+
+#[test]
+fn test_find_outliers_on_disk_after_update() {
+    let dir = std::env::temp_dir().join("agent_mem_db_outliers_disk");
+    let _ = fs::remove_dir_all(&dir);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(3)).unwrap();
+    for ep in grid() {
+        db.store_episode(ep).unwrap();
+    }
+    let mut moved = Episode::new("moved", vec![0.3, 0.3, 0.0], 1.0);
+    db.store_episode(moved.clone()).unwrap();
+    assert!(db.find_outliers(3, 3.0).is_empty());
+
+    // Re-storing under the same id replaces the old vector.
+    moved.state_embedding = vec![0.3, 0.3, 30.0];
+    db.store_episode(moved.clone()).unwrap();
+    let outliers = db.find_outliers(3, 3.0);
+    assert_eq!(outliers.len(), 1);
+    assert_eq!(outliers[0].task_id, "moved");
+}
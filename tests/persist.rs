@@ -1,4 +1,4 @@
-use agent_mem_db::{AgentMemDB, Episode};
+use agent_mem_db::{AgentMemDB, AgentMemError, Episode, FORMAT_VERSION};
 use serde_json::json;
 use std::collections::HashSet;
 use std::fs;
@@ -17,6 +17,14 @@ fn make_episode(dim: usize, reward: f32) -> Episode {
         tags: None,
         source: None,
         user_id: None,
+        session_id: None,
+        version: 0,
+        deleted: false,
+        times_retrieved: 0,
+        last_retrieved_at: None,
+        cost: None,
+        duration_ms: None,
+        embedding_model: None,
     }
 }
 
@@ -56,3 +64,43 @@ fn test_load_corrupted_file() {
     assert!(res.is_err());
     fs::remove_file(&path).unwrap();
 }
+
+#[test]
+fn test_load_format_versions() {
+    let path = std::env::temp_dir().join("agent_mem_db_format_version.json");
+    let id = Uuid::new_v4();
+    // Written before versioning: no format_version, and none of the later episode fields.
+    let old = json!({
+        "dim": 2,
+        "episodes": [{
+            "id": id,
+            "task_id": "old",
+            "state_embedding": [0.0, 1.0],
+            "reward": 0.5,
+            "metadata": null,
+            "steps": null
+        }]
+    });
+    fs::write(&path, old.to_string()).unwrap();
+    let db = AgentMemDB::load_from_file_exact(&path).unwrap();
+    let ep = db.iter().next().unwrap();
+    assert_eq!((ep.id, ep.session_id.as_deref()), (id, None));
+
+    // Saving writes the current version.
+    db.save_to_file(&path).unwrap();
+    let saved: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved["format_version"], FORMAT_VERSION);
+
+    let newer = json!({"format_version": FORMAT_VERSION + 1, "dim": 2, "episodes": []});
+    fs::write(&path, newer.to_string()).unwrap();
+    match AgentMemDB::load_from_file(&path) {
+        Err(AgentMemError::UnsupportedVersion {
+            found, supported, ..
+        }) => {
+            assert_eq!((found, supported), (FORMAT_VERSION + 1, FORMAT_VERSION));
+        }
+        other => panic!("expected UnsupportedVersion, got {:?}", other.err()),
+    }
+    fs::remove_file(&path).unwrap();
+}
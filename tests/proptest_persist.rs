@@ -47,6 +47,14 @@ proptest! {
                 tags: None,
                 source: None,
                 user_id: None,
+                session_id: None,
+                version: 0,
+                deleted: false,
+                times_retrieved: 0,
+                last_retrieved_at: None,
+                cost: None,
+                duration_ms: None,
+                embedding_model: None,
             };
             db.store_episode(ep).unwrap();
         }
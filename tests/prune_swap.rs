@@ -0,0 +1,272 @@
+use agent_mem_db::{
+    AgentMemDB, AgentMemDBDisk, AgentMemError, DiskArchive, DiskOptions, Episode, EpisodeArchive,
+    PruneRule, QueryOptions,
+};
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn episodes() -> Vec<Episode> {
+    vec![
+        Episode::with_timestamp("old", vec![1.0, 0.0], 0.2, 1_000),
+        Episode::with_timestamp("older", vec![0.9, 0.1], 0.9, 500),
+        Episode::with_timestamp("new", vec![0.0, 1.0], 0.5, 5_000),
+        Episode::with_timestamp("newest", vec![0.1, 0.9], 1.0, 9_000),
+    ]
+}
+
+fn tasks(hits: &[Episode]) -> Vec<&str> {
+    hits.iter().map(|ep| ep.task_id.as_str()).collect()
+}
+
+#[test]
+fn test_prepare_then_commit_prune() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+
+    let prepared = db.prepare_prune(&[PruneRule::OlderThan(2_000)]).unwrap();
+    assert_eq!(prepared.policy(), [PruneRule::OlderThan(2_000)]);
+    assert_eq!(prepared.removed(), 2);
+    // Preparing leaves the DB untouched; queries still see every episode.
+    assert_eq!(db.len(), 4);
+    let hits = db
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(0.0, 1))
+        .unwrap();
+    assert_eq!(tasks(&hits), vec!["old"]);
+
+    assert_eq!(db.commit_prune(prepared).unwrap(), 2);
+    let hits = db
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(0.0, 10))
+        .unwrap();
+    assert_eq!(tasks(&hits), vec!["newest", "new"]);
+
+    // A prune that removes nothing.
+    let prepared = db.prepare_prune(&[PruneRule::KeepNewest(5)]).unwrap();
+    assert_eq!(prepared.removed(), 0);
+    assert_eq!(db.commit_prune(prepared).unwrap(), 0);
+    assert_eq!(db.len(), 2);
+}
+
+#[test]
+fn test_prune_rules() {
+    let cases = [
+        (PruneRule::KeepNewest(1), vec!["newest"]),
+        (PruneRule::KeepHighestReward(2), vec!["older", "newest"]),
+        (PruneRule::OlderThan(600), vec!["old", "new", "newest"]),
+    ];
+    for (rule, expected) in cases {
+        let mut db = AgentMemDB::new_exact(2);
+        db.store_episodes(episodes()).unwrap();
+        db.prune(std::slice::from_ref(&rule)).unwrap();
+        let mut kept: Vec<&str> = db.iter().map(|ep| ep.task_id.as_str()).collect();
+        kept.sort_by_key(|task| expected.iter().position(|t| t == task));
+        assert_eq!(kept, expected, "{rule:?}");
+    }
+
+    let mut db = AgentMemDB::new_exact(2);
+    let mut eps = episodes();
+    eps[0].session_id = Some("s1".into());
+    eps[2].session_id = Some("s1".into());
+    db.store_episodes(eps).unwrap();
+    assert_eq!(db.prune(&[PruneRule::Session("s1".into())]).unwrap(), 2);
+    assert_eq!(db.len(), 2);
+}
+
+#[test]
+fn test_commit_recomputes_stale_prune() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+    let prepared = db
+        .prepare_prune(&[PruneRule::KeepNewest(2), PruneRule::KeepHighestReward(1)])
+        .unwrap();
+
+    // Stored after the prune was prepared: the commit must not drop it.
+    db.store_episode(Episode::with_timestamp(
+        "latest",
+        vec![0.5, 0.5],
+        1.0,
+        10_000,
+    ))
+    .unwrap();
+    // Recomputed with the whole policy, not just its first rule.
+    assert_eq!(db.commit_prune(prepared).unwrap(), 4);
+    let kept: Vec<&str> = db.iter().map(|ep| ep.task_id.as_str()).collect();
+    assert_eq!(kept, vec!["latest"]);
+}
+
+#[test]
+fn test_prune_rebuilds_sub_indexes() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.add_tag_index("plan");
+    db.set_user_partitions(true);
+    let mut eps = episodes();
+    for ep in &mut eps {
+        ep.tags = Some(vec!["plan".into()]);
+        ep.user_id = Some("alice".into());
+    }
+    db.store_episodes(eps).unwrap();
+
+    let prepared = db.prepare_prune(&[PruneRule::OlderThan(2_000)]).unwrap();
+    assert_eq!(db.commit_prune(prepared).unwrap(), 2);
+
+    let opts = QueryOptions::new(0.0, 10).tags_any(vec!["plan".into()]);
+    let explained = db.explain_query(&[1.0, 0.0], opts).unwrap();
+    assert_eq!(explained.tag_index.as_deref(), Some("plan"));
+    assert_eq!(tasks(&explained.results), vec!["newest", "new"]);
+    let opts = QueryOptions::new(0.0, 10).user_id("alice");
+    let hits = db.query_similar_with_options(&[1.0, 0.0], opts).unwrap();
+    assert_eq!(tasks(&hits), vec!["newest", "new"]);
+
+    // A sub-index added after preparing is rebuilt by the commit too.
+    let prepared = db.prepare_prune(&[PruneRule::KeepNewest(1)]).unwrap();
+    db.add_tag_index("other");
+    assert_eq!(db.commit_prune(prepared).unwrap(), 1);
+    assert_eq!(db.tag_indexes().count(), 2);
+    let opts = QueryOptions::new(0.0, 10).tags_any(vec!["plan".into()]);
+    let hits = db.query_similar_with_options(&[1.0, 0.0], opts).unwrap();
+    assert_eq!(tasks(&hits), vec!["newest"]);
+}
+
+#[test]
+fn test_disk_prepare_commit_prune() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_prune_swap_test");
+    let archive_dir = std::env::temp_dir().join("agent_mem_db_disk_prune_swap_archive");
+    let _ = fs::remove_dir_all(&dir);
+    let _ = fs::remove_dir_all(&archive_dir);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.set_archive(Some(Arc::new(
+            DiskArchive::open(&archive_dir, DiskOptions::exact(2)).unwrap(),
+        )))
+        .unwrap();
+        db.store_episodes(episodes()).unwrap();
+
+        let prepared = db.prepare_prune(&[PruneRule::OlderThan(2_000)]).unwrap();
+        assert_eq!(db.len(), 4);
+        assert_eq!(db.commit_prune(prepared).unwrap(), 2);
+        assert_eq!(db.len(), 2);
+        let archived = db
+            .query_archive(&[1.0, 0.0], QueryOptions::new(0.0, 10))
+            .unwrap();
+        assert_eq!(tasks(&archived), vec!["old", "older"]);
+
+        // Stale: the episode stored since is kept.
+        let prepared = db.prepare_prune(&[PruneRule::KeepNewest(1)]).unwrap();
+        db.store_episode(Episode::with_timestamp(
+            "latest",
+            vec![0.5, 0.5],
+            1.0,
+            10_000,
+        ))
+        .unwrap();
+        assert_eq!(db.commit_prune(prepared).unwrap(), 2);
+    }
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    let hits = db
+        .query_similar_with_options(&[1.0, 0.0], QueryOptions::new(0.0, 10))
+        .unwrap();
+    assert_eq!(tasks(&hits), vec!["latest"]);
+    let _ = fs::remove_dir_all(&dir);
+    let _ = fs::remove_dir_all(&archive_dir);
+}
+
+#[test]
+fn test_prune_policy_matches_successive_prunes() {
+    let policy = [
+        PruneRule::OlderThan(600),
+        PruneRule::KeepNewest(2),
+        PruneRule::KeepHighestReward(1),
+    ];
+    let mut combined = AgentMemDB::new_exact(2);
+    combined.store_episodes(episodes()).unwrap();
+    assert_eq!(combined.prune(&policy).unwrap(), 3);
+
+    let mut successive = AgentMemDB::new_exact(2);
+    successive.store_episodes(episodes()).unwrap();
+    for rule in &policy {
+        successive.prune(std::slice::from_ref(rule)).unwrap();
+    }
+    assert_eq!(
+        tasks(&combined.iter().cloned().collect::<Vec<_>>()),
+        vec!["newest"]
+    );
+    assert_eq!(
+        tasks(&successive.iter().cloned().collect::<Vec<_>>()),
+        vec!["newest"]
+    );
+    assert_eq!(combined.prune(&[]).unwrap(), 0);
+}
+
+/// Counts `archive` calls; each prune that removes episodes archives them in one call.
+#[derive(Default)]
+struct CountingArchive {
+    calls: AtomicUsize,
+    archived: AtomicUsize,
+}
+
+impl EpisodeArchive for CountingArchive {
+    fn dim(&self) -> usize {
+        2
+    }
+
+    fn archive(&self, episodes: Vec<Episode>) -> Result<(), AgentMemError> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.archived.fetch_add(episodes.len(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn query(&self, _: &[f32], _: QueryOptions) -> Result<Vec<Episode>, AgentMemError> {
+        Ok(Vec::new())
+    }
+
+    fn len(&self) -> usize {
+        self.archived.load(Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn test_disk_prune_policy_compacts_once() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_prune_policy_once");
+    let _ = fs::remove_dir_all(&dir);
+    let archive = Arc::new(CountingArchive::default());
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    db.set_archive(Some(archive.clone())).unwrap();
+    db.store_episodes(episodes()).unwrap();
+    let policy = [
+        PruneRule::OlderThan(600),
+        PruneRule::KeepNewest(2),
+        PruneRule::KeepHighestReward(1),
+    ];
+    assert_eq!(db.prune(&policy).unwrap(), 3);
+
+    // One swap: the removed episodes are archived together and the log rewritten once, to
+    // exactly the kept episodes.
+    assert_eq!(archive.calls.load(Ordering::Relaxed), 1);
+    assert_eq!(archive.len(), 3);
+    let log = fs::read_to_string(dir.join("episodes.jsonl")).unwrap();
+    assert_eq!(log.lines().count(), db.len());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_prune_max_bytes_drops_oldest() {
+    let eps = episodes();
+    let size = |ep: &Episode| serde_json::to_vec(ep).unwrap().len() as u64;
+    let budget = size(&eps[2]) + size(&eps[3]);
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_prune_max_bytes");
+    let _ = fs::remove_dir_all(&dir);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.store_episodes(eps).unwrap();
+        let removed = db
+            .prune(&[PruneRule::MaxBytes(budget), PruneRule::KeepNewest(3)])
+            .unwrap();
+        assert_eq!(removed, 2);
+    }
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    let hits = db
+        .query_similar_with_options(&[0.0, 1.0], QueryOptions::new(0.0, 10))
+        .unwrap();
+    assert_eq!(tasks(&hits), vec!["new", "newest"]);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,83 @@
+use agent_mem_db::{
+    embedding_hash, replay_queries, AgentMemDB, AgentMemDBDisk, DiskOptions, Episode, QueryLog,
+    QueryOptions,
+};
+use std::fs;
+use std::sync::Arc;
+
+fn episodes() -> Vec<Episode> {
+    (0..200)
+        .map(|i| {
+            let tag = if i % 2 == 0 { "even" } else { "odd" };
+            let v = vec![
+                (i * 7919 % 997) as f32 / 50.0,
+                (i * 104_729 % 991) as f32 / 50.0,
+            ];
+            Episode::with_tags(format!("t{i}"), v, 1.0, vec![tag.to_string()])
+        })
+        .collect()
+}
+
+#[test]
+fn test_query_log_records_and_bounds() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+    let log = Arc::new(QueryLog::new(2));
+    db.set_query_log(Some(log.clone()));
+
+    db.query_similar(&[0.0, 0.0], 0.0, 3).unwrap();
+    let tagged = QueryOptions::new(0.0, 4).tags_any(vec!["odd".to_string()]);
+    let results = db.query_similar_with_options(&[5.0, 5.0], tagged).unwrap();
+    db.query_similar(&[9.0, 9.0], 0.0, 1).unwrap();
+
+    let entries = log.entries();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].embedding_hash, embedding_hash(&[5.0, 5.0]));
+    assert_ne!(entries[0].embedding_hash, entries[1].embedding_hash);
+    assert_eq!(entries[0].options.tags_any, Some(vec!["odd".to_string()]));
+    let ids: Vec<_> = results.iter().map(|ep| ep.id).collect();
+    assert_eq!(entries[0].result_ids, ids);
+
+    db.set_query_log(None);
+    db.query_similar(&[1.0, 1.0], 0.0, 1).unwrap();
+    assert_eq!(log.len(), 2);
+}
+
+#[test]
+fn test_replay_against_other_config() {
+    let dir = std::env::temp_dir().join("agent_mem_db_query_log");
+    let _ = fs::remove_dir_all(&dir);
+    let mut prod =
+        AgentMemDBDisk::open_with_options(dir.join("db"), DiskOptions::exact(2)).unwrap();
+    for ep in episodes() {
+        prod.store_episode(ep).unwrap();
+    }
+    let log = Arc::new(QueryLog::new(100));
+    prod.set_query_log(Some(log.clone()));
+    for i in 0..10 {
+        prod.query_similar(&[i as f32 * 1.53 + 0.01, i as f32 * 0.97 + 0.02], 0.0, 5)
+            .unwrap();
+    }
+    let path = dir.join("queries.jsonl");
+    log.save_jsonl(&path).unwrap();
+    let mut entries = QueryLog::load_jsonl(&path).unwrap();
+    assert_eq!(entries.len(), 10);
+
+    // The same data under exact search reproduces every result.
+    let mut exact = AgentMemDB::new_exact(2);
+    exact
+        .store_episodes(prod.iter().cloned().collect())
+        .unwrap();
+    let report = replay_queries(&entries, &exact).unwrap();
+    assert_eq!(report.queries, 10);
+    assert_eq!(report.identical, 10);
+    assert_eq!(report.mean_overlap, 1.0);
+
+    // Changed options show up as lost overlap.
+    for entry in &mut entries {
+        entry.options = entry.options.clone().tags_all(vec!["even".to_string()]);
+    }
+    let report = replay_queries(&entries, &exact).unwrap();
+    assert!(report.mean_overlap < 1.0);
+    assert_eq!(report.per_query.len(), 10);
+}
@@ -0,0 +1,157 @@
+use agent_mem_db::{
+    AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, EmbeddingProvider, Episode,
+    ReembedProgress, ReembedReport,
+};
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Embeds a text as its length, then zeros; counts provider calls.
+struct Lengths {
+    dim: usize,
+    calls: AtomicUsize,
+}
+
+impl Lengths {
+    fn new(dim: usize) -> Self {
+        Self {
+            dim,
+            calls: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl EmbeddingProvider for Lengths {
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, AgentMemError> {
+        if text == "fail" {
+            return Err(AgentMemError::Embedding("unreachable model".into()));
+        }
+        let mut v = vec![0.0; self.dim];
+        v[0] = text.len() as f32;
+        Ok(v)
+    }
+
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AgentMemError> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        texts.iter().map(|text| self.embed(text)).collect()
+    }
+}
+
+fn with_text(task: &str, text: &str) -> Episode {
+    let mut ep = Episode::new(task, vec![0.0, 9.0], 1.0);
+    ep.metadata = serde_json::json!({ "text": text });
+    ep
+}
+
+#[test]
+fn test_reembed_all_in_place() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(vec![
+        with_text("a", "x"),
+        with_text("b", "xxx"),
+        with_text("c", "xxxxx"),
+        Episode::new("no text", vec![0.0, 9.0], 1.0),
+    ])
+    .unwrap();
+    let provider = Lengths::new(2);
+    let mut seen = Vec::new();
+    let report = db
+        .reembed_all(&provider, 2, |p| seen.push((p.done, p.total)))
+        .unwrap();
+    assert_eq!(
+        report,
+        ReembedReport {
+            reembedded: 3,
+            skipped: 1
+        }
+    );
+    assert_eq!(seen, vec![(2, 3), (3, 3)]);
+    assert_eq!(provider.calls.load(Ordering::Relaxed), 2);
+
+    // The index was rebuilt over the new embeddings; the untexted episode kept its own.
+    let hits = db.query_similar(&[3.0, 0.0], 0.0, 1).unwrap();
+    assert_eq!((hits[0].task_id.as_str(), hits[0].version), ("b", 0));
+    let untexted = db.iter().find(|ep| ep.task_id == "no text").unwrap();
+    assert_eq!(untexted.state_embedding, vec![0.0, 9.0]);
+
+    // A failing batch leaves the DB as it was.
+    db.store_episode(with_text("d", "fail")).unwrap();
+    let before: Vec<Vec<f32>> = db.iter().map(|ep| ep.state_embedding.clone()).collect();
+    assert!(matches!(
+        db.reembed_all(&Lengths::new(2), 10, |_| {}),
+        Err(AgentMemError::Embedding(_))
+    ));
+    let after: Vec<Vec<f32>> = db.iter().map(|ep| ep.state_embedding.clone()).collect();
+    assert_eq!(before, after);
+
+    assert!(matches!(
+        db.reembed_all(&Lengths::new(3), 10, |_| {}),
+        Err(AgentMemError::DimensionMismatch {
+            expected: 2,
+            got: 3
+        })
+    ));
+}
+
+#[test]
+fn test_reembed_into_new_dimension() {
+    let mut db = AgentMemDB::new_exact(2);
+    let a = with_text("a", "xx");
+    db.store_episodes(vec![
+        a.clone(),
+        Episode::new("no text", vec![0.0, 9.0], 1.0),
+    ])
+    .unwrap();
+    let mut target = AgentMemDB::new_exact(3);
+    let mut last = None;
+    let report = db
+        .reembed_into(&mut target, &Lengths::new(3), 8, |p: ReembedProgress| {
+            last = Some(p)
+        })
+        .unwrap();
+    assert_eq!((report.reembedded, report.skipped), (1, 1));
+    assert_eq!(last, Some(ReembedProgress { done: 1, total: 1 }));
+    assert_eq!(target.len(), 1);
+    let copy = target.iter().next().unwrap();
+    assert_eq!(
+        (copy.id, &copy.state_embedding),
+        (a.id, &vec![2.0, 0.0, 0.0])
+    );
+    // The source is untouched.
+    assert_eq!(
+        db.iter().find(|ep| ep.id == a.id).unwrap().state_embedding,
+        a.state_embedding
+    );
+}
+
+#[test]
+fn test_disk_reembed_all() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_reembed_test");
+    let _ = fs::remove_dir_all(&dir);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.store_episodes(vec![with_text("a", "x"), with_text("b", "xxxx")])
+            .unwrap();
+        let report = db.reembed_all(&Lengths::new(2), 1, |_| {}).unwrap();
+        assert_eq!(report.reembedded, 2);
+    }
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    assert_eq!(db.len(), 2);
+    let hits = db.query_similar(&[4.0, 0.0], 0.0, 1).unwrap();
+    assert_eq!(
+        (hits[0].task_id.as_str(), &hits[0].state_embedding),
+        ("b", &vec![4.0, 0.0])
+    );
+
+    let new_dir = std::env::temp_dir().join("agent_mem_db_disk_reembed_target_test");
+    let _ = fs::remove_dir_all(&new_dir);
+    let mut target = AgentMemDBDisk::open_with_options(&new_dir, DiskOptions::exact(3)).unwrap();
+    db.reembed_into(&mut target, &Lengths::new(3), 1, |_| {})
+        .unwrap();
+    assert_eq!(target.len(), 2);
+    let _ = fs::remove_dir_all(&dir);
+    let _ = fs::remove_dir_all(&new_dir);
+}
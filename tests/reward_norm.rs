@@ -0,0 +1,89 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, DiskOptions, Episode, RewardNormalization};
+use std::collections::HashMap;
+use std::fs;
+
+fn episodes() -> Vec<Episode> {
+    let mut eps: Vec<Episode> = [0.0, 1.0, 1.0, 0.0]
+        .into_iter()
+        .map(|r| Episode::new("binary", vec![0.0, 1.0], r))
+        .collect();
+    eps.extend(
+        [10.0, 40.0, 70.0, 100.0]
+            .into_iter()
+            .map(|r| Episode::new("score", vec![1.0, 0.0], r)),
+    );
+    eps.push(Episode::new("constant", vec![1.0, 1.0], 3.0));
+    eps
+}
+
+fn rewards<'a>(eps: impl Iterator<Item = &'a Episode>) -> HashMap<String, Vec<f32>> {
+    let mut by_task: HashMap<String, Vec<f32>> = HashMap::new();
+    for ep in eps {
+        by_task
+            .entry(ep.task_id.clone())
+            .or_default()
+            .push(ep.reward);
+    }
+    for rewards in by_task.values_mut() {
+        rewards.sort_by(f32::total_cmp);
+    }
+    by_task
+}
+
+#[test]
+fn test_normalize_rewards_per_task() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+    // The 0/1 rewards are already in range; the other five change.
+    assert_eq!(
+        db.normalize_rewards_per_task(RewardNormalization::MinMax),
+        5
+    );
+    let by_task = rewards(db.iter());
+    assert_eq!(by_task["binary"], [0.0, 0.0, 1.0, 1.0]);
+    assert_eq!(by_task["score"], [0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0]);
+    assert_eq!(by_task["constant"], [0.5]);
+    assert!(db
+        .iter()
+        .filter(|ep| ep.task_id == "score")
+        .all(|ep| ep.version == 1));
+    // Already normalized: nothing changes.
+    assert_eq!(
+        db.normalize_rewards_per_task(RewardNormalization::MinMax),
+        0
+    );
+
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+    db.normalize_rewards_per_task(RewardNormalization::ZScore);
+    let by_task = rewards(db.iter());
+    assert_eq!(by_task["binary"], [-1.0, -1.0, 1.0, 1.0]);
+    assert_eq!(by_task["constant"], [0.0]);
+    let score = &by_task["score"];
+    let mean: f32 = score.iter().sum::<f32>() / 4.0;
+    let variance: f32 = score.iter().map(|r| (r - mean).powi(2)).sum::<f32>() / 4.0;
+    assert!(mean.abs() < 1e-6 && (variance - 1.0).abs() < 1e-5);
+    // One threshold now picks the better half of each task.
+    let above_mean = db.query_similar(&[0.0, 1.0], 0.1, 10).unwrap();
+    assert_eq!(above_mean.len(), 4);
+    assert_eq!(rewards(above_mean.iter())["score"].len(), 2);
+}
+
+#[test]
+fn test_disk_normalize_rewards_per_task() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_reward_norm_test");
+    let _ = fs::remove_dir_all(&dir);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.store_episodes(episodes()).unwrap();
+        let changed = db
+            .normalize_rewards_per_task(RewardNormalization::MinMax)
+            .unwrap();
+        assert_eq!(changed, 5);
+    }
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    assert_eq!(db.len(), 9);
+    let by_task = rewards(db.iter());
+    assert_eq!(by_task["score"], [0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0]);
+    assert_eq!(by_task["constant"], [0.5]);
+}
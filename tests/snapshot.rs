@@ -0,0 +1,96 @@
+use agent_mem_db::{AgentMemDB, AgentMemSnapshot, Episode, QueryOptions};
+use std::thread;
+
+fn ep(task: &str, embedding: Vec<f32>, reward: f32, ts: i64) -> Episode {
+    Episode::with_timestamp(task, embedding, reward, ts)
+}
+
+#[test]
+fn test_snapshot_is_frozen() {
+    let mut db = AgentMemDB::new(2);
+    db.store_episode(ep("a", vec![0.0, 0.0], 0.1, 1)).unwrap();
+    db.store_episode(ep("b", vec![1.0, 0.0], 0.9, 2)).unwrap();
+    let snapshot = db.clone_snapshot();
+
+    db.store_episode(ep("c", vec![0.1, 0.0], 1.0, 3)).unwrap();
+    assert_eq!(db.prune_keep_newest(1).unwrap(), 2);
+
+    assert_eq!(db.len(), 1);
+    assert_eq!(snapshot.len(), 2);
+    assert_eq!(snapshot.dim(), 2);
+    let results = snapshot.query_similar(&[0.0, 0.0], 0.0, 5).unwrap();
+    let tasks: Vec<&str> = results.iter().map(|e| e.task_id.as_str()).collect();
+    assert_eq!(tasks, ["a", "b"]);
+    assert!(matches!(
+        snapshot.query_similar(&[0.0], 0.0, 1),
+        Err(agent_mem_db::AgentMemError::DimensionMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_snapshot_query_is_exact_with_filters() {
+    let mut db = AgentMemDB::new_exact(3);
+    for i in 0..50 {
+        let x = i as f32;
+        let mut e = ep(
+            &format!("task-{}", i % 5),
+            vec![x, (x * 0.7).sin(), 1.0],
+            (i % 10) as f32 / 10.0,
+            i,
+        );
+        e.tags = Some(vec![if i % 2 == 0 { "even" } else { "odd" }.to_string()]);
+        db.store_episode(e).unwrap();
+    }
+    let snapshot = db.clone_snapshot();
+    let opts = || {
+        QueryOptions::new(0.1, 4)
+            .tags_any(vec!["even".to_string()])
+            .task_id_prefix("task-2")
+    };
+    let got = snapshot
+        .query_similar_with_options(&[20.0, 0.0, 1.0], opts())
+        .unwrap();
+    // Filters apply before top_k, so this is the exact answer over every matching episode.
+    let mut expected: Vec<Episode> = db.iter().filter(|e| opts().matches(e)).cloned().collect();
+    let dist = |e: &Episode| {
+        let d = &e.state_embedding;
+        (d[0] - 20.0).powi(2) + d[1].powi(2) + (d[2] - 1.0).powi(2)
+    };
+    expected.sort_by(|a, b| dist(a).total_cmp(&dist(b)));
+    expected.truncate(4);
+    assert_eq!(got.len(), 4);
+    let ids = |v: &[Episode]| v.iter().map(|e| e.id).collect::<Vec<_>>();
+    assert_eq!(ids(&got), ids(&expected));
+}
+
+#[test]
+fn test_snapshot_read_while_writing() {
+    let mut db = AgentMemDB::new_exact(2);
+    for i in 0..100 {
+        db.store_episode(ep("seed", vec![i as f32, 0.0], 0.5, i))
+            .unwrap();
+    }
+    let snapshot = db.clone_snapshot();
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let snapshot: AgentMemSnapshot = snapshot.clone();
+            thread::spawn(move || {
+                for i in 0..100 {
+                    let r = snapshot.query_similar(&[i as f32, 0.0], 0.0, 1).unwrap();
+                    assert_eq!(r[0].state_embedding[0], i as f32);
+                    assert_eq!(snapshot.len(), 100);
+                }
+            })
+        })
+        .collect();
+    for i in 100..200 {
+        db.store_episode(ep("new", vec![i as f32, 0.0], 0.5, i))
+            .unwrap();
+    }
+    db.delete_where(|e| e.task_id == "seed");
+    for r in readers {
+        r.join().unwrap();
+    }
+    assert_eq!(db.len(), 100);
+    assert_eq!(snapshot.iter().filter(|e| e.task_id == "seed").count(), 100);
+}
@@ -0,0 +1,120 @@
+use agent_mem_db::{
+    tag_has_prefix, AgentMemDB, AgentMemDBDisk, DiskOptions, Episode, QueryOptions, TagSubtree,
+};
+use std::fs;
+
+fn tagged(task: &str, embedding: Vec<f32>, reward: f32, tags: &[&str]) -> Episode {
+    Episode::with_tags(
+        task,
+        embedding,
+        reward,
+        tags.iter().map(|t| t.to_string()).collect(),
+    )
+}
+
+fn episodes() -> Vec<Episode> {
+    vec![
+        tagged("click", vec![1.0, 0.0], 1.0, &["tool/browser/click"]),
+        tagged("type", vec![0.9, 0.1], 0.0, &["tool/browser/type", "ui"]),
+        tagged("plural", vec![0.8, 0.2], 1.0, &["tool/browsers"]),
+        tagged("shell", vec![0.7, 0.3], 0.5, &["tool/shell"]),
+        tagged("untagged", vec![1.0, 0.0], 1.0, &[]),
+    ]
+}
+
+fn tasks(hits: &[Episode]) -> Vec<&str> {
+    hits.iter().map(|ep| ep.task_id.as_str()).collect()
+}
+
+#[test]
+fn test_tag_has_prefix() {
+    assert!(tag_has_prefix("tool/browser/click", "tool/browser"));
+    assert!(tag_has_prefix("tool/browser", "tool/browser"));
+    assert!(tag_has_prefix("tool/browser", "tool/browser/"));
+    assert!(tag_has_prefix("tool/browser", ""));
+    assert!(!tag_has_prefix("tool/browsers", "tool/browser"));
+    assert!(!tag_has_prefix("tool", "tool/browser"));
+}
+
+#[test]
+fn test_tag_prefix_any_filter() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+
+    let opts = QueryOptions::new(0.0, 10).tag_prefix_any(vec!["tool/browser".into()]);
+    let hits = db.query_similar_with_options(&[1.0, 0.0], opts).unwrap();
+    assert_eq!(tasks(&hits), vec!["click", "type"]);
+
+    let opts = QueryOptions::new(0.0, 10).tag_prefix_any(vec!["tool/shell".into(), "ui".into()]);
+    let hits = db.query_similar_with_options(&[1.0, 0.0], opts).unwrap();
+    assert_eq!(tasks(&hits), vec!["type", "shell"]);
+
+    // Pushed into the index search like the other tag filters.
+    let explained = db
+        .explain_query(
+            &[1.0, 0.0],
+            QueryOptions::new(0.0, 10).tag_prefix_any(vec!["tool".into()]),
+        )
+        .unwrap();
+    assert_eq!(explained.allowed_keys, Some(4));
+    let untagged = Episode::new("t", vec![0.0, 1.0], 1.0);
+    let opts = QueryOptions::new(0.0, 1).tag_prefix_any(vec!["tool".into()]);
+    assert_eq!(opts.rejected_by(&untagged), Some("tag_prefix_any"));
+}
+
+#[test]
+fn test_tag_subtree_stats() {
+    let mut db = AgentMemDB::new_exact(2);
+    db.store_episodes(episodes()).unwrap();
+    // Two tags in one subtree still count the episode once.
+    db.store_episode(tagged(
+        "both",
+        vec![0.0, 1.0],
+        1.0,
+        &["tool/shell/ls", "tool/shell/cd"],
+    ))
+    .unwrap();
+
+    let stats = db.tag_subtree_stats("tool");
+    let summary: Vec<(&str, usize)> = stats.iter().map(|s| (s.tag.as_str(), s.count)).collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("tool", 5),
+            ("tool/browser", 2),
+            ("tool/browser/click", 1),
+            ("tool/browser/type", 1),
+            ("tool/browsers", 1),
+            ("tool/shell", 2),
+            ("tool/shell/cd", 1),
+            ("tool/shell/ls", 1),
+        ]
+    );
+    assert_eq!(stats[1].mean_reward, 0.5);
+    assert_eq!(stats[5].mean_reward, 0.75);
+
+    let all = db.tag_subtree_stats("");
+    assert_eq!(all.len(), 9);
+    assert!(all.iter().any(|s| s.tag == "ui"));
+    assert!(db.tag_subtree_stats("missing").is_empty());
+}
+
+#[test]
+fn test_disk_tag_prefix_and_subtree_stats() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_tag_tree_test");
+    let _ = fs::remove_dir_all(&dir);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    db.store_episodes(episodes()).unwrap();
+    let opts = QueryOptions::new(0.0, 10).tag_prefix_any(vec!["tool/browser/".into()]);
+    let hits = db.query_similar_with_options(&[1.0, 0.0], opts).unwrap();
+    assert_eq!(tasks(&hits), vec!["click", "type"]);
+    assert_eq!(
+        db.tag_subtree_stats("tool/shell"),
+        vec![TagSubtree {
+            tag: "tool/shell".into(),
+            count: 1,
+            mean_reward: 0.5,
+        }]
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,76 @@
+use agent_mem_db::{
+    AgentMemDBTiered, ColdSearch, DiskOptions, Episode, QueryOptions, TierPolicy, TieredOptions,
+};
+use std::fs;
+use std::path::PathBuf;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("agent_mem_db_tiered_{name}"));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn ep(i: i64, reward: f32) -> Episode {
+    Episode::with_timestamp(format!("task-{i}"), vec![i as f32, 0.0], reward, i)
+}
+
+#[test]
+fn test_tiered_spills_and_fans_out() {
+    let dir = scratch("spill");
+    let opts = TieredOptions::new(DiskOptions::exact(2), 20);
+    let mut db = AgentMemDBTiered::open(&dir, opts).unwrap();
+    for i in 0..100 {
+        db.store_episode(ep(i, 1.0)).unwrap();
+    }
+    assert_eq!(db.len(), 100);
+    assert!((20..=22).contains(&db.hot_len()), "{}", db.hot_len());
+
+    // Episode 0 is long demoted: the hot tier alone can't see it.
+    let opts = QueryOptions::new(0.0, 3);
+    let hot = db
+        .query_tiers(&[0.0, 0.0], opts.clone(), ColdSearch::Never)
+        .unwrap();
+    assert_eq!(hot.len(), 3);
+    assert!(hot.iter().all(|e| e.timestamp.unwrap() >= 78));
+    let all = db
+        .query_tiers(&[0.0, 0.0], opts.clone(), ColdSearch::Always)
+        .unwrap();
+    let tasks: Vec<&str> = all.iter().map(|e| e.task_id.as_str()).collect();
+    assert_eq!(tasks, ["task-0", "task-1", "task-2"]);
+
+    // IfShort fans out only when filters leave the hot tier short.
+    let old = opts.clone().time_before(10);
+    assert_eq!(
+        db.query_similar_with_options(&[0.0, 0.0], old)
+            .unwrap()
+            .len(),
+        3
+    );
+    let recent = db.query_similar_with_options(&[0.0, 0.0], opts).unwrap();
+    assert!(recent.iter().all(|e| e.timestamp.unwrap() >= 78));
+}
+
+#[test]
+fn test_tiered_reopen_rebuilds_hot_by_policy() {
+    let dir = scratch("reopen");
+    let opts = || {
+        TieredOptions::new(DiskOptions::exact(2), 5)
+            .with_policy(TierPolicy::HighestReward)
+            .with_cold_search(ColdSearch::Never)
+    };
+    let mut db = AgentMemDBTiered::open(&dir, opts()).unwrap();
+    let episodes: Vec<Episode> = (0..30).map(|i| ep(i, (i % 10) as f32)).collect();
+    db.store_episodes(episodes).unwrap();
+    let first = db.iter().next().unwrap().clone();
+    db.soft_delete(first.id).unwrap();
+    drop(db);
+
+    let db = AgentMemDBTiered::open(&dir, opts()).unwrap();
+    assert_eq!(db.len(), 30);
+    assert_eq!(db.hot_len(), 5);
+    let hot = db
+        .query_similar(&[0.0, 0.0], f32::NEG_INFINITY, 10)
+        .unwrap();
+    assert!(hot.iter().all(|e| e.reward >= 8.0), "{hot:?}");
+    assert!(hot.iter().all(|e| e.id != first.id));
+}
@@ -0,0 +1,104 @@
+use agent_mem_db::{
+    AgentMemDBTimePartitioned, DiskOptions, Episode, QueryOptions, TimePartitionOptions,
+};
+use std::fs;
+use std::path::PathBuf;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("agent_mem_db_time_partitioned_{name}"));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn ep(ts: i64) -> Episode {
+    Episode::with_timestamp(format!("task-{ts}"), vec![ts as f32, 0.0], 1.0, ts)
+}
+
+#[test]
+fn test_time_range_queries_skip_buckets() {
+    let dir = scratch("query");
+    let opts = TimePartitionOptions::new(DiskOptions::exact(2), 100);
+    let mut db = AgentMemDBTimePartitioned::open(&dir, opts).unwrap();
+    db.store_episodes((0..500).step_by(10).map(ep).collect())
+        .unwrap();
+    db.store_episode(Episode::new("undated", vec![0.0, 0.0], 1.0))
+        .unwrap();
+    assert_eq!(db.len(), 51);
+    assert_eq!(db.buckets(), vec![0, 100, 200, 300, 400]);
+
+    let all = QueryOptions::new(0.0, 3);
+    assert_eq!(db.partitions_searched(&all), 6);
+    let tasks: Vec<String> = db
+        .query_similar_with_options(&[0.0, 0.0], all)
+        .unwrap()
+        .into_iter()
+        .map(|e| e.task_id)
+        .collect();
+    assert_eq!(tasks, ["task-0", "undated", "task-10"]);
+
+    let range = QueryOptions::new(0.0, 3).time_after(250).time_before(320);
+    assert_eq!(db.partitions_searched(&range), 2);
+    let ts: Vec<i64> = db
+        .query_similar_with_options(&[0.0, 0.0], range)
+        .unwrap()
+        .iter()
+        .map(|e| e.timestamp.unwrap())
+        .collect();
+    assert_eq!(ts, [250, 260, 270]);
+
+    let empty = QueryOptions::new(0.0, 3).time_after(900);
+    assert_eq!(db.partitions_searched(&empty), 0);
+}
+
+#[test]
+fn test_prune_drops_bucket_directories() {
+    let dir = scratch("prune");
+    let opts = TimePartitionOptions::new(DiskOptions::exact(2), 100);
+    let mut db = AgentMemDBTimePartitioned::open(&dir, opts.clone()).unwrap();
+    db.store_episodes((0..300).step_by(10).map(ep).collect())
+        .unwrap();
+    db.store_episode(Episode::new("undated", vec![0.0, 0.0], 1.0))
+        .unwrap();
+
+    assert_eq!(db.prune_older_than(150).unwrap(), 15);
+    assert_eq!(db.buckets(), vec![100, 200]);
+    assert!(!dir.join("bucket-0").exists());
+    assert_eq!(db.len(), 16);
+
+    // Reopening keeps the bucket width it was created with.
+    drop(db);
+    let reopened = TimePartitionOptions::new(DiskOptions::exact(2), 7);
+    let db = AgentMemDBTimePartitioned::open(&dir, reopened).unwrap();
+    assert_eq!(db.bucket_ms(), 100);
+    assert_eq!(db.buckets(), vec![100, 200]);
+    assert_eq!(db.len(), 16);
+}
+
+#[test]
+fn test_updates_move_between_buckets() {
+    let dir = scratch("update");
+    let opts = TimePartitionOptions::new(DiskOptions::exact(2), 100);
+    let mut db = AgentMemDBTimePartitioned::open(&dir, opts).unwrap();
+    let mut moved = ep(50);
+    db.store_episode(moved.clone()).unwrap();
+    db.store_episode(ep(60)).unwrap();
+
+    moved.timestamp = Some(150);
+    assert_eq!(db.update_episode(moved.clone(), 0).unwrap(), 1);
+    assert_eq!(db.buckets(), vec![0, 100]);
+    assert_eq!(db.len(), 2);
+    let recent = QueryOptions::new(0.0, 5).time_after(100);
+    let results = db.query_similar_with_options(&[0.0, 0.0], recent).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, moved.id);
+
+    db.soft_delete(moved.id).unwrap();
+    assert_eq!(db.query_similar(&[150.0, 0.0], 0.0, 5).unwrap().len(), 1);
+    db.restore(moved.id).unwrap();
+    assert_eq!(db.query_similar(&[150.0, 0.0], 0.0, 5).unwrap().len(), 2);
+
+    // Emptied buckets are removed.
+    assert_eq!(db.delete_where(|e| e.timestamp == Some(60)).unwrap(), 1);
+    assert_eq!(db.buckets(), vec![100]);
+    assert!(!dir.join("bucket-0").exists());
+}
@@ -0,0 +1,54 @@
+use agent_mem_db::{
+    AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, WARM_UP_QUERIES,
+};
+use std::fs;
+
+#[test]
+fn test_warm_up_after_load() {
+    let mut db = AgentMemDB::new_exact(2);
+    for i in 0..40 {
+        db.store_episode(Episode::new("t", vec![i as f32, 1.0], 1.0))
+            .unwrap();
+    }
+    let path = std::env::temp_dir().join("agent_mem_db_warm_up_test.json");
+    db.save_to_file(&path).unwrap();
+    let loaded = AgentMemDB::load_from_file_exact(&path).unwrap();
+
+    let report = loaded.warm_up(None).unwrap();
+    // The episodes' embeddings plus the exact index's copies.
+    assert_eq!(report.vectors_touched, 80);
+    assert!(report.queries_run > 0 && report.queries_run <= WARM_UP_QUERIES);
+    let report = loaded
+        .warm_up(Some(&[vec![0.0, 1.0], vec![3.0, 1.0]]))
+        .unwrap();
+    assert_eq!(report.queries_run, 2);
+    // Warm-up queries aren't retrievals.
+    assert_eq!(loaded.access_summary().total_retrievals, 0);
+
+    assert!(matches!(
+        loaded.warm_up(Some(&[vec![0.0, 1.0], vec![1.0; 3]])),
+        Err(AgentMemError::DimensionMismatch {
+            expected: 2,
+            got: 3
+        })
+    ));
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn test_disk_warm_up() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_warm_up_test");
+    let _ = fs::remove_dir_all(&dir);
+    {
+        let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+        db.store_episode(Episode::new("a", vec![1.0, 0.0], 1.0))
+            .unwrap();
+        db.store_episode(Episode::new("b", vec![0.0, 1.0], 1.0))
+            .unwrap();
+    }
+    let db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    let report = db.warm_up(None).unwrap();
+    assert_eq!((report.vectors_touched, report.queries_run), (4, 2));
+    assert_eq!(db.access_summary().never_retrieved, 2);
+    let _ = fs::remove_dir_all(&dir);
+}
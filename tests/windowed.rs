@@ -0,0 +1,78 @@
+use agent_mem_db::{AgentMemDB, AgentMemDBDisk, AgentMemError, DiskOptions, Episode, MAX_WINDOWS};
+use std::fs;
+
+fn store(db: &mut AgentMemDB, ts: i64, reward: f32) {
+    db.store_episode(Episode::with_timestamp("t", vec![1.0, 0.0], reward, ts))
+        .unwrap();
+}
+
+#[test]
+fn test_windowed_stats() {
+    let mut db = AgentMemDB::new_exact(2);
+    assert!(db.windowed_stats(1_000, 1_000).unwrap().is_empty());
+    store(&mut db, 1_100, 0.0);
+    store(&mut db, 1_900, 0.5);
+    store(&mut db, 4_000, 1.0);
+    db.store_episode(Episode::new("undated", vec![0.0, 1.0], -5.0))
+        .unwrap();
+
+    // Side by side, aligned to multiples of the step; the empty windows are kept.
+    let windows = db.windowed_stats(1_000, 1_000).unwrap();
+    let summary: Vec<(i64, i64, usize, Option<f32>)> = windows
+        .iter()
+        .map(|w| (w.start, w.end, w.count, w.mean_reward))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (1_000, 2_000, 2, Some(0.25)),
+            (2_000, 3_000, 0, None),
+            (3_000, 4_000, 0, None),
+            (4_000, 5_000, 1, Some(1.0)),
+        ]
+    );
+
+    // Overlapping: every window reaching a timestamp is included.
+    let windows = db.windowed_stats(2_000, 1_000).unwrap();
+    assert_eq!(windows.first().unwrap().start, 0);
+    assert_eq!(windows.last().unwrap().start, 4_000);
+    let counts: Vec<usize> = windows.iter().map(|w| w.count).collect();
+    assert_eq!(counts, vec![2, 2, 0, 1, 1]);
+
+    // Soft-deleted episodes don't count.
+    let last = db.iter().find(|ep| ep.timestamp == Some(4_000)).unwrap().id;
+    db.soft_delete(last).unwrap();
+    assert_eq!(db.windowed_stats(1_000, 1_000).unwrap().len(), 1);
+
+    assert!(matches!(
+        db.windowed_stats(0, 1_000),
+        Err(AgentMemError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        db.windowed_stats(1_000, -1),
+        Err(AgentMemError::InvalidQuery(_))
+    ));
+    store(&mut db, 1_100 + MAX_WINDOWS as i64, 1.0);
+    assert!(matches!(
+        db.windowed_stats(1, 1),
+        Err(AgentMemError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn test_disk_windowed_stats() {
+    let dir = std::env::temp_dir().join("agent_mem_db_disk_windowed_test");
+    let _ = fs::remove_dir_all(&dir);
+    let mut db = AgentMemDBDisk::open_with_options(&dir, DiskOptions::exact(2)).unwrap();
+    for (ts, reward) in [(-1_500, 0.0), (-500, 1.0), (500, 1.0)] {
+        db.store_episode(Episode::with_timestamp("t", vec![1.0, 0.0], reward, ts))
+            .unwrap();
+    }
+    let windows = db.windowed_stats(1_000, 1_000).unwrap();
+    let means: Vec<(i64, Option<f32>)> = windows.iter().map(|w| (w.start, w.mean_reward)).collect();
+    assert_eq!(
+        means,
+        vec![(-2_000, Some(0.0)), (-1_000, Some(1.0)), (0, Some(1.0))]
+    );
+    let _ = fs::remove_dir_all(&dir);
+}